@@ -0,0 +1,10 @@
+//! The `bvh` Python module. The classes are defined in the `python` module of the `bvh`
+//! crate, build this crate with `maturin develop --release`.
+
+use pyo3::prelude::*;
+
+#[pymodule]
+#[pyo3(name = "bvh")]
+fn init(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    bvh::python::register(module)
+}
@@ -2,6 +2,9 @@
 
 use crate::aabb::Bounded;
 use crate::aabb::AABB;
+use crate::ray::{Intersection, IntersectionRay, Ray};
+use crate::{Point3, Real, Vector3};
+use std::cell::Cell;
 
 /// Describes a shape as referenced by a [`BoundingHierarchy`] leaf node.
 /// Knows the index of the node in the [`BoundingHierarchy`] it is in.
@@ -23,6 +26,37 @@ pub trait BHShape: Bounded + Sync + Send {
     fn bh_node_index(&self) -> usize;
 }
 
+impl<T: ?Sized> BHShape for &mut T
+where
+    T: BHShape,
+{
+    fn set_bh_node_index(&mut self, index: usize) {
+        (**self).set_bh_node_index(index);
+    }
+
+    fn bh_node_index(&self) -> usize {
+        (**self).bh_node_index()
+    }
+}
+
+/// Allows building hierarchies over boxed trait objects, e.g. a `Vec<Box<dyn Shape>>`
+/// where `Shape` extends [`BHShape`].
+///
+/// [`BHShape`]: trait.BHShape.html
+///
+impl<T: ?Sized> BHShape for Box<T>
+where
+    T: BHShape,
+{
+    fn set_bh_node_index(&mut self, index: usize) {
+        (**self).set_bh_node_index(index);
+    }
+
+    fn bh_node_index(&self) -> usize {
+        (**self).bh_node_index()
+    }
+}
+
 /// This trait defines an acceleration structure with space partitioning.
 /// This structure is used to efficiently compute ray-scene intersections.
 pub trait BoundingHierarchy {
@@ -171,6 +205,175 @@ pub trait BoundingHierarchy {
         shapes: &'a [Shape],
     ) -> Vec<&Shape>;
 
+    /// Traverses the [`BoundingHierarchy`] and calls `visit` with every shape whose [`AABB`]
+    /// was hit by `test`. The default implementation collects the result of
+    /// [`traverse`] first. Implementations which call `visit` during the traversal allow
+    /// `test` to depend on the shapes visited so far, which the nearest hit queries use to
+    /// prune the traversal.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BoundingHierarchy`]: trait.BoundingHierarchy.html
+    /// [`traverse`]: trait.BoundingHierarchy.html#tymethod.traverse
+    ///
+    fn traverse_with<'a, Shape: BHShape>(
+        &'a self,
+        test: &impl IntersectionAABB,
+        shapes: &'a [Shape],
+        mut visit: impl FnMut(&'a Shape),
+    ) {
+        for shape in self.traverse(test, shapes) {
+            visit(shape);
+        }
+    }
+
+    /// Finds the `n` closest intersections of `ray` with the shapes between `t_min` and
+    /// `t_max`. The hits are returned sorted by their distance.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::{BHShape, BoundingHierarchy};
+    /// use bvh::flat_bvh::FlatBVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::sphere::Sphere;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Ball { sphere: Sphere, node_index: usize }
+    /// # impl Bounded for Ball {
+    /// #     fn aabb(&self) -> AABB { self.sphere.aabb() }
+    /// # }
+    /// # impl BHShape for Ball {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// # impl bvh::ray::IntersectionRay for Ball {
+    /// #     fn intersects_ray(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<bvh::ray::Intersection> {
+    /// #         self.sphere.intersects_ray(ray, t_min, t_max)
+    /// #     }
+    /// # }
+    /// let mut balls: Vec<Ball> = (1..10)
+    ///     .map(|x| Ball { sphere: Sphere::new(Point3::new(x as f32 * 3.0, 0.0, 0.0), 1.0), node_index: 0 })
+    ///     .collect();
+    /// let flat_bvh = FlatBVH::build(&mut balls);
+    ///
+    /// let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+    /// let hits = flat_bvh.traverse_n_nearest(&ray, 3, 0.0, f32::INFINITY, &balls);
+    /// let distances: Vec<f32> = hits.iter().map(|(_, hit)| hit.distance).collect();
+    /// assert_eq!(distances, vec![2.0, 5.0, 8.0]);
+    /// ```
+    ///
+    fn traverse_n_nearest<'a, Shape: BHShape + IntersectionRay>(
+        &'a self,
+        ray: &Ray,
+        n: usize,
+        t_min: Real,
+        t_max: Real,
+        shapes: &'a [Shape],
+    ) -> Vec<(&'a Shape, Intersection)> {
+        let mut hits: Vec<(&'a Shape, Intersection)> = Vec::new();
+        if n == 0 {
+            return hits;
+        }
+
+        // Everything beyond the current `n`-th hit can not contribute anymore.
+        let limit = Cell::new(t_max);
+        let test = RaySegmentTest {
+            ray,
+            t_min,
+            limit: &limit,
+        };
+        self.traverse_with(&test, shapes, |shape| {
+            if let Some(hit) = shape.intersects_ray(ray, t_min, limit.get()) {
+                let position = hits
+                    .iter()
+                    .position(|(_, other)| hit.distance < other.distance)
+                    .unwrap_or(hits.len());
+                if position < n {
+                    hits.insert(position, (shape, hit));
+                    hits.truncate(n);
+                    if hits.len() == n {
+                        limit.set(hits[n - 1].1.distance);
+                    }
+                }
+            }
+        });
+        hits
+    }
+
+    /// Finds the closest intersection of `ray` with the shapes between `t_min` and `t_max`,
+    /// like [`traverse_n_nearest`] with `n = 1`.
+    ///
+    /// [`traverse_n_nearest`]: trait.BoundingHierarchy.html#method.traverse_n_nearest
+    ///
+    fn traverse_nearest<'a, Shape: BHShape + IntersectionRay>(
+        &'a self,
+        ray: &Ray,
+        t_min: Real,
+        t_max: Real,
+        shapes: &'a [Shape],
+    ) -> Option<(&'a Shape, Intersection)> {
+        self.traverse_n_nearest(ray, 1, t_min, t_max, shapes).pop()
+    }
+
+    /// Finds the shape closest to `point` within `max_distance`. The exact distance of a
+    /// shape to `point` is computed by `distance`, which must not be smaller than the
+    /// distance of `point` to the [`AABB`] of the shape.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::{BHShape, BoundingHierarchy};
+    /// use bvh::flat_bvh::FlatBVH;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..10)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let flat_bvh = FlatBVH::build(&mut cubes);
+    ///
+    /// let point = Point3::new(7.2, 0.0, 0.0);
+    /// let (cube, distance) = flat_bvh
+    ///     .nearest_neighbor(point, f32::INFINITY, &cubes, |cube| cube.pos.distance(point))
+    ///     .unwrap();
+    /// assert_eq!(cube.pos, Point3::new(8.0, 0.0, 0.0));
+    /// assert!((distance - 0.8).abs() < 1e-5);
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    fn nearest_neighbor<'a, Shape: BHShape>(
+        &'a self,
+        point: Point3,
+        max_distance: Real,
+        shapes: &'a [Shape],
+        mut distance: impl FnMut(&Shape) -> Real,
+    ) -> Option<(&'a Shape, Real)> {
+        let mut nearest = None;
+        let limit = Cell::new(max_distance);
+        let test = PointDistanceTest {
+            point,
+            limit: &limit,
+        };
+        self.traverse_with(&test, shapes, |shape| {
+            let shape_distance = distance(shape);
+            if shape_distance <= limit.get() {
+                limit.set(shape_distance);
+                nearest = Some((shape, shape_distance));
+            }
+        });
+        nearest
+    }
+
     /// Prints the [`BoundingHierarchy`] in a tree-like visualization.
     ///
     /// [`BoundingHierarchy`]: trait.BoundingHierarchy.html
@@ -178,6 +381,41 @@ pub trait BoundingHierarchy {
     fn pretty_print(&self) {}
 }
 
+/// Accepts the [`AABB`]s which a [`Ray`] hits between `t_min` and the current `limit`.
+///
+/// [`AABB`]: ../aabb/struct.AABB.html
+/// [`Ray`]: ../ray/struct.Ray.html
+///
+struct RaySegmentTest<'r> {
+    ray: &'r Ray,
+    t_min: Real,
+    limit: &'r Cell<Real>,
+}
+
+impl IntersectionAABB for RaySegmentTest<'_> {
+    fn intersects_aabb(&self, aabb: &AABB) -> bool {
+        self.ray
+            .intersects_aabb_interval(aabb)
+            .is_some_and(|(entry, exit)| entry <= self.limit.get() && exit >= self.t_min)
+    }
+}
+
+/// Accepts the [`AABB`]s which are not farther away from `point` than the current `limit`.
+///
+/// [`AABB`]: ../aabb/struct.AABB.html
+///
+struct PointDistanceTest<'l> {
+    point: Point3,
+    limit: &'l Cell<Real>,
+}
+
+impl IntersectionAABB for PointDistanceTest<'_> {
+    fn intersects_aabb(&self, aabb: &AABB) -> bool {
+        let limit = self.limit.get();
+        aabb.distance_squared(&self.point) <= limit * limit
+    }
+}
+
 /// This trait can be implemented on anything that can intersect with an `AABB`
 /// Used to traverse the `BVH`
 ///
@@ -189,3 +427,95 @@ pub trait IntersectionAABB {
     /// Returns true if there is an intersection with the given `AABB`
     fn intersects_aabb(&self, aabb: &AABB) -> bool;
 }
+
+/// This trait can be implemented on shapes which can test whether they overlap a shape of
+/// type `Other`. It is implemented for all pairs of [`AABB`], [`OBB`], [`Sphere`] and
+/// [`Capsule`].
+///
+/// # Examples
+/// ```
+/// use bvh::aabb::AABB;
+/// use bvh::bounding_hierarchy::IntersectionShape;
+/// use bvh::capsule::Capsule;
+/// use bvh::sphere::Sphere;
+/// use bvh::Point3;
+///
+/// let capsule = Capsule::new(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 4.0, 0.0), 1.0);
+/// let sphere = Sphere::new(Point3::new(2.5, 2.0, 0.0), 1.0);
+/// assert!(!capsule.intersects_shape(&sphere));
+/// assert!(capsule.intersects_shape(&Sphere::new(Point3::new(1.5, 5.0, 0.0), 1.0)));
+///
+/// let aabb = AABB::with_bounds(Point3::new(1.5, 1.0, -1.0), Point3::new(3.0, 3.0, 1.0));
+/// assert!(aabb.intersects_shape(&sphere));
+/// assert!(sphere.intersects_shape(&aabb));
+/// ```
+///
+/// [`AABB`]: ../aabb/struct.AABB.html
+/// [`Capsule`]: ../capsule/struct.Capsule.html
+/// [`OBB`]: ../obb/struct.OBB.html
+/// [`Sphere`]: ../sphere/struct.Sphere.html
+///
+pub trait IntersectionShape<Other: ?Sized = Self> {
+    /// Returns true if the shape overlaps `other`, including shapes which only touch.
+    fn intersects_shape(&self, other: &Other) -> bool;
+}
+
+/// The shortest translation which moves a shape out of another one, as computed by
+/// [`PenetrationDepth`].
+///
+/// [`PenetrationDepth`]: trait.PenetrationDepth.html
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct Penetration {
+    /// The unit direction in which the penetrating shape has to move.
+    pub normal: Vector3,
+    /// The distance the penetrating shape has to move along the normal.
+    pub depth: Real,
+}
+
+impl Penetration {
+    /// Returns the translation which separates the shapes, `normal * depth`.
+    pub fn translation(&self) -> Vector3 {
+        self.normal * self.depth
+    }
+}
+
+/// This trait can be implemented on shapes which can compute how deep a shape of type
+/// `Other` penetrates them. It is implemented for [`Triangle`]s and [`Sphere`]s with
+/// [`Sphere`]s and [`Capsule`]s, which are the usual shapes of character controllers.
+/// [`BVH::penetrations`] and [`BVH::depenetrate`] use it to move a shape out of a scene.
+///
+/// # Examples
+/// ```
+/// use bvh::bounding_hierarchy::PenetrationDepth;
+/// use bvh::capsule::Capsule;
+/// use bvh::triangle::Triangle;
+/// use bvh::{Point3, Vector3};
+///
+/// let floor = Triangle::new(
+///     Point3::new(-10.0, 0.0, 10.0),
+///     Point3::new(10.0, 0.0, 10.0),
+///     Point3::new(0.0, 0.0, -10.0),
+/// );
+/// // A capsule standing on the floor, sunk in by a quarter.
+/// let capsule = Capsule::new(Point3::new(0.0, 0.25, 0.0), Point3::new(0.0, 2.0, 0.0), 0.5);
+/// let penetration = floor.penetration(&capsule).unwrap();
+/// assert_eq!(penetration.normal, Vector3::new(0.0, 1.0, 0.0));
+/// assert_eq!(penetration.depth, 0.25);
+///
+/// let above = Capsule::new(Point3::new(0.0, 1.0, 0.0), Point3::new(0.0, 2.0, 0.0), 0.5);
+/// assert!(floor.penetration(&above).is_none());
+/// ```
+///
+/// [`BVH::depenetrate`]: ../bvh/struct.BVH.html#method.depenetrate
+/// [`BVH::penetrations`]: ../bvh/struct.BVH.html#method.penetrations
+/// [`Capsule`]: ../capsule/struct.Capsule.html
+/// [`Sphere`]: ../sphere/struct.Sphere.html
+/// [`Triangle`]: ../triangle/struct.Triangle.html
+///
+pub trait PenetrationDepth<Other: ?Sized = Self> {
+    /// Returns the shortest translation which moves `other` out of the shape, or `None` if
+    /// they do not overlap. Shapes which only touch do not penetrate each other.
+    fn penetration(&self, other: &Other) -> Option<Penetration>;
+}
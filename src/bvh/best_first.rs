@@ -64,6 +64,9 @@ impl BVH {
         heap: &mut BinaryHeap<BvhTraversalRes>,
     ) -> Option<Res> {
         heap.clear();
+        if self.nodes.is_empty() {
+            return None;
+        }
         heap.push(BvhTraversalRes::new(0, 0.));
 
         let mut result = None;
@@ -79,7 +82,7 @@ impl BVH {
                 BVHNode::Leaf { shape_index, .. } => {
                     if let Some((dist, res)) = test_shape(shape_index) {
                         let dist_squared = dist * dist;
-                        if dist_squared < curr_min && dist > t_min && dist < t_max {
+                        if dist_squared < curr_min && dist >= t_min && dist <= t_max {
                             curr_min = dist_squared;
                             result = Some(res);
                         }
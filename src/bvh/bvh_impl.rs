@@ -7,8 +7,10 @@
 use crate::aabb::{Bounded, AABB};
 use crate::axis::Axis;
 use crate::bounding_hierarchy::{BHShape, BoundingHierarchy, IntersectionAABB};
-use crate::bvh::iter::BVHTraverseIterator;
-use crate::utils::{joint_aabb_of_shapes, Bucket};
+use crate::bvh::iter::{BVHIndexIterator, BVHTraverseIterator};
+use crate::bvh::{BuildError, BuildLimits};
+use crate::ray::{Intersection, IntersectionRay, Ray};
+use crate::utils::{join, joint_aabb_of_shapes, Bucket};
 use crate::EPSILON;
 use crate::{Point3, Real};
 
@@ -34,6 +36,10 @@ thread_local! {
 ///
 #[derive(Debug, Copy, Clone)]
 #[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)
+)]
 #[allow(clippy::upper_case_acronyms)]
 pub enum BVHNode {
     /// Leaf node.
@@ -257,6 +263,39 @@ impl BVHNode {
         aabb_bounds: AABB,
         centroid_bounds: AABB,
     ) {
+        // Without limits the build can not fail.
+        let _ = BVHNode::build_limited(
+            shapes,
+            indices,
+            nodes,
+            parent_index,
+            depth,
+            node_index,
+            aabb_bounds,
+            centroid_bounds,
+            &BuildLimits::NONE,
+        );
+    }
+
+    /// Builds a [`BVHNode`] recursively like [`BVHNode::build`], but checks the `limits`
+    /// before every node. On error, some of the `nodes` are left uninitialized.
+    ///
+    /// [`BVHNode`]: enum.BVHNode.html
+    /// [`BVHNode::build`]: enum.BVHNode.html#method.build
+    ///
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn build_limited<T: BHShape>(
+        shapes: &mut [T],
+        indices: &mut [usize],
+        nodes: &mut [MaybeUninit<BVHNode>],
+        parent_index: usize,
+        depth: u32,
+        node_index: usize,
+        aabb_bounds: AABB,
+        centroid_bounds: AABB,
+        limits: &BuildLimits,
+    ) -> Result<(), BuildError> {
+        limits.check(depth)?;
         // If there is only one element left, don't split anymore
         if indices.len() == 1 {
             let shape_index = indices[0];
@@ -266,12 +305,10 @@ impl BVHNode {
             });
             // Let the shape know the index of the node that represents it.
             shapes[shape_index].set_bh_node_index(node_index);
-            return;
-        }
-        let mut parallel_recurse = false;
-        if indices.len() > 64 {
-            parallel_recurse = true;
+            return Ok(());
         }
+        // Without the `rayon` feature, e.g. on wasm32, everything is built on this thread.
+        let parallel_recurse = cfg!(feature = "rayon") && indices.len() > 64;
 
         // Find the axis along which the shapes are spread the most.
         let split_axis = centroid_bounds.largest_axis();
@@ -301,9 +338,10 @@ impl BVHNode {
                     let shapes_b = slice::from_raw_parts_mut(ptr, len);
                     (shapes_a, shapes_b)
                 };
-                rayon::join(
+                let (mut l_result, mut r_result) = (Ok(()), Ok(()));
+                join(
                     || {
-                        BVHNode::build(
+                        l_result = BVHNode::build_limited(
                             shapes_a,
                             child_l_indices,
                             l_nodes,
@@ -312,10 +350,11 @@ impl BVHNode {
                             child_l_index,
                             child_l_aabb,
                             child_l_centroid,
+                            limits,
                         )
                     },
                     || {
-                        BVHNode::build(
+                        r_result = BVHNode::build_limited(
                             shapes_b,
                             child_r_indices,
                             r_nodes,
@@ -324,11 +363,14 @@ impl BVHNode {
                             child_r_index,
                             child_r_aabb,
                             child_r_centroid,
+                            limits,
                         )
                     },
                 );
+                l_result?;
+                r_result?;
             } else {
-                BVHNode::build(
+                BVHNode::build_limited(
                     shapes,
                     child_l_indices,
                     l_nodes,
@@ -337,8 +379,9 @@ impl BVHNode {
                     child_l_index,
                     child_l_aabb,
                     child_l_centroid,
-                );
-                BVHNode::build(
+                    limits,
+                )?;
+                BVHNode::build_limited(
                     shapes,
                     child_r_indices,
                     r_nodes,
@@ -347,7 +390,8 @@ impl BVHNode {
                     child_r_index,
                     child_r_aabb,
                     child_r_centroid,
-                );
+                    limits,
+                )?;
             }
             (child_l_index, child_l_aabb, child_r_index, child_r_aabb)
         } else {
@@ -378,9 +422,10 @@ impl BVHNode {
                     let shapes_b = slice::from_raw_parts_mut(ptr, len);
                     (shapes_a, shapes_b)
                 };
-                rayon::join(
+                let (mut l_result, mut r_result) = (Ok(()), Ok(()));
+                join(
                     || {
-                        BVHNode::build(
+                        l_result = BVHNode::build_limited(
                             shapes_a,
                             child_l_indices,
                             l_nodes,
@@ -389,10 +434,11 @@ impl BVHNode {
                             child_l_index,
                             child_l_aabb,
                             child_l_centroid,
+                            limits,
                         )
                     },
                     || {
-                        BVHNode::build(
+                        r_result = BVHNode::build_limited(
                             shapes_b,
                             child_r_indices,
                             r_nodes,
@@ -401,11 +447,14 @@ impl BVHNode {
                             child_r_index,
                             child_r_aabb,
                             child_r_centroid,
+                            limits,
                         )
                     },
                 );
+                l_result?;
+                r_result?;
             } else {
-                BVHNode::build(
+                BVHNode::build_limited(
                     shapes,
                     child_l_indices,
                     l_nodes,
@@ -414,8 +463,9 @@ impl BVHNode {
                     child_l_index,
                     child_l_aabb,
                     child_l_centroid,
-                );
-                BVHNode::build(
+                    limits,
+                )?;
+                BVHNode::build_limited(
                     shapes,
                     child_r_indices,
                     r_nodes,
@@ -424,7 +474,8 @@ impl BVHNode {
                     child_r_index,
                     child_r_aabb,
                     child_r_centroid,
-                );
+                    limits,
+                )?;
             }
             (child_l_index, child_l_aabb, child_r_index, child_r_aabb)
         };
@@ -439,6 +490,24 @@ impl BVHNode {
             child_r_aabb,
             child_r_index,
         });
+        Ok(())
+    }
+
+    /// Returns the measure of a child's bounds in the SAH cost of splitting the node with the
+    /// bounds `parent`. This is the surface area, unless `parent` has none because all of
+    /// its shapes lie on a line or in a point. Then no child has a surface area either, so
+    /// every configuration would cost nothing, and the sum of the side lengths tells them
+    /// apart instead. The measure depends only on the parent, so all configurations of a
+    /// node are compared with the same one.
+    fn sah_measure(parent: &AABB) -> fn(&AABB) -> Real {
+        if parent.surface_area() > 0.0 {
+            AABB::surface_area
+        } else {
+            |aabb| {
+                let size = aabb.size();
+                size.x + size.y + size.z
+            }
+        }
     }
 
     #[allow(clippy::type_complexity)]
@@ -480,7 +549,11 @@ impl BVHNode {
                 bucket_assignments[bucket_num].push(*idx);
             }
 
+            let measure = BVHNode::sah_measure(aabb_bounds);
+
             // Compute the costs for each configuration and select the best configuration.
+            // The costs are not divided by the surface area of the parent, which is the same
+            // for every configuration.
             let mut min_bucket = 0;
             let mut min_cost = Real::INFINITY;
             let mut child_l_aabb = AABB::empty();
@@ -491,10 +564,13 @@ impl BVHNode {
                 let (l_buckets, r_buckets) = buckets.split_at(i + 1);
                 let child_l = l_buckets.iter().fold(Bucket::empty(), Bucket::join_bucket);
                 let child_r = r_buckets.iter().fold(Bucket::empty(), Bucket::join_bucket);
+                // Both children need shapes, and the size of an empty `AABB` is negative.
+                if child_l.size == 0 || child_r.size == 0 {
+                    continue;
+                }
 
-                let cost = (child_l.size as Real * child_l.aabb.surface_area()
-                    + child_r.size as Real * child_r.aabb.surface_area())
-                    / aabb_bounds.surface_area();
+                let cost = child_l.size as Real * measure(&child_l.aabb)
+                    + child_r.size as Real * measure(&child_r.aabb);
                 if cost < min_cost {
                     min_bucket = i;
                     min_cost = cost;
@@ -504,6 +580,20 @@ impl BVHNode {
                     child_r_centroid = child_r.centroid;
                 }
             }
+            // The centroids can still fall into a single bucket if their extent overflows to
+            // infinity. As no plane separates them, split the list of shapes in half instead.
+            if min_cost == Real::INFINITY {
+                let (child_l_indices, child_r_indices) = indices.split_at_mut(indices.len() / 2);
+                let (child_l_aabb, child_l_centroid) =
+                    joint_aabb_of_shapes(child_l_indices, shapes);
+                let (child_r_aabb, child_r_centroid) =
+                    joint_aabb_of_shapes(child_r_indices, shapes);
+                return (
+                    (child_l_aabb, child_l_centroid, child_l_indices),
+                    (child_r_aabb, child_r_centroid, child_r_indices),
+                );
+            }
+
             // Join together all index buckets.
             // split input indices, loop over assignments and assign
             let (l_assignments, r_assignments) = bucket_assignments.split_at_mut(min_bucket + 1);
@@ -578,6 +668,10 @@ impl BVHNode {
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Clone)]
 #[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)
+)]
 pub struct BVH {
     /// The list of nodes of the [`BVH`].
     ///
@@ -587,32 +681,62 @@ pub struct BVH {
 }
 
 impl BVH {
-    /// Creates a new [`BVH`] from the `shapes` slice.
+    /// Creates a new [`BVH`] from the `shapes` slice. The [`AABB`]s of the shapes must be
+    /// finite and not inverted, use [`BVH::try_build`] for shapes which can not be trusted.
+    ///
+    /// Every number of shapes results in a valid tree:
+    /// - no shapes result in a [`BVH`] without nodes, which every query treats as empty,
+    /// - a single shape results in a single [`BVHNode::Leaf`] as the root. Its [`AABB`] is
+    ///   not stored, so the traversals which are given the shapes test the [`AABB`] of the
+    ///   shape instead. Only [`BVH::traverse_indices`] and the other traversals without
+    ///   shapes have no bounds to test, and always return the shape,
+    /// - two or more shapes result in a [`BVHNode::Node`] as the root, and in
+    ///   `2 * shapes.len() - 1` nodes in total.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::{BVHNode, BVH};
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let ray = Ray::new(Point3::new(0.0, 0.0, -5.0), Vector3::Z);
+    ///
+    /// let mut none: Vec<Cube> = Vec::new();
+    /// let bvh = BVH::build(&mut none);
+    /// assert!(bvh.nodes.is_empty());
+    /// assert!(bvh.traverse(&ray, &none).is_empty());
     ///
+    /// let mut one = vec![Cube { pos: Point3::ZERO, node_index: 0 }];
+    /// let bvh = BVH::build(&mut one);
+    /// assert!(matches!(bvh.nodes[..], [BVHNode::Leaf { shape_index: 0, .. }]));
+    /// assert_eq!(bvh.traverse(&ray, &one).len(), 1);
+    /// let elsewhere = Ray::new(Point3::new(5.0, 0.0, -5.0), Vector3::Z);
+    /// assert!(bvh.traverse(&elsewhere, &one).is_empty());
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
     /// [`BVH`]: struct.BVH.html
+    /// [`BVH::traverse_indices`]: struct.BVH.html#method.traverse_indices
+    /// [`BVH::try_build`]: struct.BVH.html#method.try_build
+    /// [`BVHNode::Leaf`]: enum.BVHNode.html#variant.Leaf
+    /// [`BVHNode::Node`]: enum.BVHNode.html#variant.Node
     ///
     pub fn build<Shape: BHShape>(shapes: &mut [Shape]) -> BVH {
-        if shapes.is_empty() {
-            return BVH { nodes: Vec::new() };
-        }
-
-        let mut indices = (0..shapes.len()).collect::<Vec<usize>>();
-        let expected_node_count = shapes.len() * 2 - 1;
-        let mut nodes = Vec::with_capacity(expected_node_count);
-
-        let uninit_slice = unsafe {
-            slice::from_raw_parts_mut(
-                nodes.as_mut_ptr() as *mut MaybeUninit<BVHNode>,
-                expected_node_count,
-            )
-        };
-        let (aabb, centroid) = joint_aabb_of_shapes(&indices, shapes);
-        BVHNode::build(shapes, &mut indices, uninit_slice, 0, 0, 0, aabb, centroid);
-
-        unsafe {
-            nodes.set_len(expected_node_count);
-        }
-        BVH { nodes }
+        let mut bvh = BVH { nodes: Vec::new() };
+        bvh.rebuild(shapes);
+        bvh
     }
 
     /// Rebuilds a [`BVH`] from the `shapes` slice. Reuses the existing allocated space
@@ -620,9 +744,27 @@ impl BVH {
     /// [`BVH`]: struct.BVH.html
     ///
     pub fn rebuild<Shape: BHShape>(&mut self, shapes: &mut [Shape]) {
+        // Without limits the build can not fail.
+        let _ = self.rebuild_limited(shapes, &BuildLimits::NONE);
+    }
+
+    /// Rebuilds a [`BVH`] like [`BVH::rebuild`], but checks the `limits` before every node.
+    /// On error, the [`BVH`] is left without nodes.
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::rebuild`]: struct.BVH.html#method.rebuild
+    ///
+    pub(crate) fn rebuild_limited<Shape: BHShape>(
+        &mut self,
+        shapes: &mut [Shape],
+        limits: &BuildLimits,
+    ) -> Result<(), BuildError> {
+        self.nodes.clear();
+        if shapes.is_empty() {
+            return Ok(());
+        }
         let mut indices = (0..shapes.len()).collect::<Vec<usize>>();
         let expected_node_count = shapes.len() * 2 - 1;
-        self.nodes.clear();
         self.nodes.reserve(expected_node_count);
         let ptr = self.nodes.as_mut_ptr();
 
@@ -630,10 +772,42 @@ impl BVH {
             slice::from_raw_parts_mut(ptr as *mut MaybeUninit<BVHNode>, expected_node_count)
         };
         let (aabb, centroid) = joint_aabb_of_shapes(&indices, shapes);
-        BVHNode::build(shapes, &mut indices, uninit_slice, 0, 0, 0, aabb, centroid);
+        BVHNode::build_limited(
+            shapes,
+            &mut indices,
+            uninit_slice,
+            0,
+            0,
+            0,
+            aabb,
+            centroid,
+            limits,
+        )?;
         unsafe {
             self.nodes.set_len(expected_node_count);
         }
+        Ok(())
+    }
+
+    /// Returns false if the [`BVH`] has no nodes, or if its root is the leaf of a single shape
+    /// whose [`AABB`] fails `test`. Such a root stores no bounds, so they are taken from
+    /// `shapes`. The root of any other tree is reached, as the traversal tests its children.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH`]: struct.BVH.html
+    ///
+    pub(crate) fn root_passes<Shape: Bounded>(
+        &self,
+        test: &impl IntersectionAABB,
+        shapes: &[Shape],
+    ) -> bool {
+        match self.nodes.first() {
+            Some(BVHNode::Node { .. }) => true,
+            Some(BVHNode::Leaf { shape_index, .. }) => {
+                test.intersects_aabb(&shapes[*shape_index].aabb())
+            }
+            None => false,
+        }
     }
 
     /// Traverses the [`BVH`].
@@ -648,7 +822,9 @@ impl BVH {
         shapes: &'a [Shape],
     ) -> Vec<&Shape> {
         let mut indices = Vec::new();
-        BVHNode::traverse_recursive(&self.nodes, 0, ray, &mut indices);
+        if self.root_passes(ray, shapes) {
+            BVHNode::traverse_recursive(&self.nodes, 0, ray, &mut indices);
+        }
         indices
             .iter()
             .map(|index| &shapes[*index])
@@ -669,12 +845,155 @@ impl BVH {
         BVHTraverseIterator::new(self, test, shapes)
     }
 
+    /// Traverses the [`BVH`] and returns mutable references to all shapes whose [`AABB`]s
+    /// were hit by `test`. The shapes are returned in the order of their indices.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, selected: bool, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..10)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), selected: false, node_index: 0 })
+    ///     .collect();
+    /// let bvh = BVH::build(&mut cubes);
+    ///
+    /// let ray = Ray::new(Point3::new(6.0, -10.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+    /// for cube in bvh.traverse_mut(&ray, &mut cubes) {
+    ///     cube.selected = true;
+    /// }
+    /// assert!(cubes[3].selected);
+    /// assert_eq!(cubes.iter().filter(|cube| cube.selected).count(), 1);
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub fn traverse_mut<'a, Shape: Bounded>(
+        &self,
+        test: &impl IntersectionAABB,
+        shapes: &'a mut [Shape],
+    ) -> Vec<&'a mut Shape> {
+        if !self.root_passes(test, shapes) {
+            return Vec::new();
+        }
+        let mut indices = self.traverse_indices(test);
+        indices.sort_unstable();
+        indices.dedup();
+
+        // Walk the sorted indices and split each hit off the front of the remaining slice.
+        let mut hits = Vec::with_capacity(indices.len());
+        let mut rest = shapes;
+        let mut offset = 0;
+        for index in indices {
+            let (_, tail) = std::mem::take(&mut rest).split_at_mut(index - offset);
+            let (shape, tail) = tail.split_first_mut().unwrap();
+            hits.push(shape);
+            rest = tail;
+            offset = index + 1;
+        }
+        hits
+    }
+
+    /// Traverses the [`BVH`] and calls `f` with the index of and a mutable reference to
+    /// every shape whose [`AABB`] was hit by `test`.
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub fn traverse_mut_with<Shape: Bounded>(
+        &self,
+        test: &impl IntersectionAABB,
+        shapes: &mut [Shape],
+        mut f: impl FnMut(usize, &mut Shape),
+    ) {
+        if !self.root_passes(test, shapes) {
+            return;
+        }
+        for index in self.traverse_indices_iterator(test) {
+            f(index, &mut shapes[index]);
+        }
+    }
+
+    /// Traverses the [`BVH`] without looking at the shapes.
+    /// Returns the indices of all shapes whose [`AABB`]s were hit by `test`. The root of a
+    /// [`BVH`] with a single shape stores no bounds, so its shape is always returned, see
+    /// [`BVH::build`].
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..10)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let bvh = BVH::build(&mut cubes);
+    ///
+    /// let ray = Ray::new(Point3::new(6.0, -10.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+    /// assert_eq!(bvh.traverse_indices(&ray), vec![3]);
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::build`]: struct.BVH.html#method.build
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub fn traverse_indices(&self, test: &impl IntersectionAABB) -> Vec<usize> {
+        let mut indices = Vec::new();
+        if !self.nodes.is_empty() {
+            BVHNode::traverse_recursive(&self.nodes, 0, test, &mut indices);
+        }
+        indices
+    }
+
+    /// Creates a [`BVHIndexIterator`] to traverse the [`BVH`] without looking at the shapes.
+    /// Yields the indices of all shapes whose [`AABB`]s were hit by `test`.
+    ///
+    /// [`BVHIndexIterator`]: struct.BVHIndexIterator.html
+    /// [`BVH`]: struct.BVH.html
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub fn traverse_indices_iterator<'bvh, 'test, Test: IntersectionAABB>(
+        &'bvh self,
+        test: &'test Test,
+    ) -> BVHIndexIterator<'bvh, 'test, Test> {
+        BVHIndexIterator::new(self, test)
+    }
+
     /// Prints the [`BVH`] in a tree-like visualization.
     ///
     /// [`BVH`]: struct.BVH.html
     ///
     pub fn pretty_print(&self) {
-        self.print_node(0);
+        if !self.nodes.is_empty() {
+            self.print_node(0);
+        }
     }
 
     fn print_node(&self, node_index: usize) {
@@ -783,8 +1102,16 @@ impl BVH {
     }
 
     /// Checks if all children of a node have the correct parent index, and that there is no
-    /// detached subtree. Also checks if the `AABB` hierarchy is consistent.
+    /// detached subtree. Also checks if the `AABB` hierarchy is consistent. Use
+    /// [`BVH::validate`] to find out which invariant is violated.
+    ///
+    /// [`BVH::validate`]: struct.BVH.html#method.validate
+    ///
     pub fn is_consistent<Shape: BHShape>(&self, shapes: &[Shape]) -> bool {
+        if self.nodes.is_empty() {
+            return true;
+        }
+
         // The root node of the bvh is not bounded by anything.
         let space = AABB {
             min: Point3::new(Real::NEG_INFINITY, Real::NEG_INFINITY, Real::NEG_INFINITY),
@@ -990,11 +1317,11 @@ impl BVH {
     pub fn assert_tight<Shape: BHShape>(&self, shapes: &[Shape]) {
         // When starting to check whether the `BVH` is tight, we cannot provide a minimum
         // outer `AABB`, therefore we compute the correct one in this instance.
-        if let BVHNode::Node {
+        if let Some(&BVHNode::Node {
             child_l_aabb,
             child_r_aabb,
             ..
-        } = self.nodes[0]
+        }) = self.nodes.first()
         {
             let joint_aabb = child_l_aabb.join(&child_r_aabb);
             self.assert_tight_subtree(0, &joint_aabb, shapes);
@@ -1029,6 +1356,31 @@ impl BoundingHierarchy for BVH {
         self.traverse(ray, shapes)
     }
 
+    fn traverse_with<'a, Shape: Bounded>(
+        &'a self,
+        test: &impl IntersectionAABB,
+        shapes: &'a [Shape],
+        mut visit: impl FnMut(&'a Shape),
+    ) {
+        if !self.root_passes(test, shapes) {
+            return;
+        }
+        for index in self.traverse_indices_iterator(test) {
+            visit(&shapes[index]);
+        }
+    }
+
+    fn traverse_n_nearest<'a, Shape: BHShape + IntersectionRay>(
+        &'a self,
+        ray: &Ray,
+        n: usize,
+        t_min: Real,
+        t_max: Real,
+        shapes: &'a [Shape],
+    ) -> Vec<(&'a Shape, Intersection)> {
+        self.traverse_n_nearest(ray, n, t_min, t_max, shapes)
+    }
+
     fn pretty_print(&self) {
         self.pretty_print();
     }
@@ -1036,11 +1388,15 @@ impl BoundingHierarchy for BVH {
 
 #[cfg(test)]
 mod tests {
-    use crate::aabb::AABB;
-    use crate::bounding_hierarchy::BHShape;
-    use crate::bvh::{BVHNode, BVH};
+    use super::NUM_BUCKETS;
+    use crate::aabb::{Bounded, AABB};
+    use crate::bounding_hierarchy::{BHShape, BoundingHierarchy, IntersectionAABB};
+    use crate::bvh::{BVHNode, TraversalScratch, BVH};
+    use crate::float::relative_eq;
     use crate::ray::Ray;
-    use crate::testbase::{build_some_bh, traverse_some_bh, UnitBox};
+    use crate::testbase::{
+        build_some_bh, next_point3, query_some_bh, traverse_some_bh, Triangle, UnitBox,
+    };
     use crate::{Point3, Real, Vector3};
     use itertools::Itertools;
 
@@ -1058,6 +1414,361 @@ mod tests {
         traverse_some_bh::<BVH>();
     }
 
+    #[test]
+    /// Runs the nearest hit and nearest neighbor queries of `BoundingHierarchy` on a `BVH`.
+    fn test_query_bvh() {
+        query_some_bh::<BVH>();
+    }
+
+    #[test]
+    /// Checks that `BVH`s can be built over boxed trait objects and mutable references, and
+    /// find the same shapes as a `BVH` over the shapes themselves.
+    fn test_build_bvh_over_wrappers() {
+        let mut shapes: Vec<UnitBox> = (-10..10)
+            .map(|x| UnitBox::new(x, Point3::new(x as Real, 0.0, 0.0)))
+            .collect();
+        let test = AABB::with_bounds(Point3::new(-3.2, -1.0, -1.0), Point3::new(2.2, 1.0, 1.0));
+        let bvh = BVH::build(&mut shapes);
+        let expected: Vec<AABB> = bvh
+            .traverse(&test, &shapes)
+            .iter()
+            .map(|shape| shape.aabb())
+            .collect();
+
+        let mut boxed: Vec<Box<dyn BHShape>> = shapes
+            .iter()
+            .map(|shape| Box::new(*shape) as Box<dyn BHShape>)
+            .collect();
+        let bvh = BVH::build(&mut boxed);
+        bvh.assert_consistent(&boxed);
+        let found: Vec<AABB> = bvh
+            .traverse(&test, &boxed)
+            .iter()
+            .map(|shape| shape.aabb())
+            .collect();
+        assert_eq!(sorted_by_min(found), sorted_by_min(expected.clone()));
+
+        let mut references: Vec<&mut UnitBox> = shapes.iter_mut().collect();
+        let bvh = BVH::build(&mut references);
+        bvh.assert_consistent(&references);
+        let found: Vec<AABB> = bvh
+            .traverse(&test, &references)
+            .iter()
+            .map(|shape| shape.aabb())
+            .collect();
+        assert_eq!(sorted_by_min(found), sorted_by_min(expected));
+    }
+
+    /// Sorts `AABB`s by the `x` coordinate of their minimum bound.
+    fn sorted_by_min(mut aabbs: Vec<AABB>) -> Vec<AABB> {
+        aabbs.sort_by(|a, b| a.min.x.partial_cmp(&b.min.x).unwrap());
+        aabbs
+    }
+
+    #[cfg(feature = "rkyv")]
+    #[test]
+    /// Tests that a `BVH` survives a round trip through rkyv, and that its archived nodes can
+    /// be read in place.
+    fn test_bvh_rkyv_round_trip() {
+        use crate::bvh::ArchivedBVHNode;
+        use rkyv::Deserialize;
+
+        let (shapes, bvh) = build_some_bh::<BVH>();
+        let bytes = rkyv::to_bytes::<_, 1024>(&bvh).unwrap();
+        let archived = unsafe { rkyv::archived_root::<BVH>(&bytes) };
+        assert_eq!(archived.nodes.len(), bvh.nodes.len());
+        match (&archived.nodes[0], &bvh.nodes[0]) {
+            (
+                ArchivedBVHNode::Node { child_l_aabb, .. },
+                BVHNode::Node {
+                    child_l_aabb: expected,
+                    ..
+                },
+            ) => assert_eq!(child_l_aabb, expected),
+            _ => panic!("the root of the BVH should be an inner node"),
+        }
+
+        let deserialized: BVH = archived.deserialize(&mut rkyv::Infallible).unwrap();
+        assert_eq!(
+            format!("{:?}", deserialized.nodes),
+            format!("{:?}", bvh.nodes)
+        );
+        deserialized.is_consistent(shapes.as_slice());
+    }
+
+    #[test]
+    /// Checks that `traverse_mut` and `traverse_mut_with` reach exactly the shapes `traverse` finds.
+    fn test_traverse_mut_matches_traverse() {
+        let mut shapes: Vec<UnitBox> = (-10..10)
+            .map(|x| UnitBox::new(x, Point3::new(x as Real, 0.0, 0.0)))
+            .collect();
+        let bvh = BVH::build(&mut shapes);
+        let test = AABB::with_bounds(Point3::new(-3.2, -1.0, -1.0), Point3::new(2.2, 1.0, 1.0));
+
+        let expected: Vec<i32> = bvh
+            .traverse(&test, &shapes)
+            .iter()
+            .map(|shape| shape.id)
+            .sorted()
+            .collect();
+
+        for shape in bvh.traverse_mut(&test, &mut shapes) {
+            shape.id += 100;
+        }
+        let mut visited = Vec::new();
+        bvh.traverse_mut_with(&test, &mut shapes, |index, shape| {
+            visited.push(index);
+            shape.id -= 100;
+        });
+
+        let ids: Vec<i32> = visited
+            .iter()
+            .map(|index| shapes[*index].id)
+            .sorted()
+            .collect();
+        assert_eq!(ids, expected);
+        assert_eq!(ids, (-3..=2).collect::<Vec<_>>());
+        assert!(shapes.iter().all(|shape| shape.id < 100));
+    }
+
+    #[test]
+    /// Tests that trees of zero, one and two shapes are valid and can be queried and flattened.
+    fn test_tiny_builds() {
+        fn assert_missed(bvh: &BVH, test: &impl IntersectionAABB, shapes: &mut [UnitBox]) {
+            let mut scratch = TraversalScratch::new();
+            assert!(bvh.traverse(test, shapes).is_empty());
+            assert_eq!(bvh.traverse_iterator(test, shapes).count(), 0);
+            assert_eq!(
+                bvh.traverse_with_scratch(test, shapes, &mut scratch)
+                    .count(),
+                0
+            );
+            assert!(bvh.traverse_mut(test, shapes).is_empty());
+        }
+
+        let ray = Ray::new(Point3::new(0.0, 0.0, -5.0), Vector3::new(0.0, 0.0, 1.0));
+        let everything = AABB::with_bounds(Point3::splat(-10.0), Point3::splat(10.0));
+        let nothing = AABB::with_bounds(Point3::splat(20.0), Point3::splat(21.0));
+        for count in 0..3_usize {
+            let mut shapes: Vec<UnitBox> = (0..count)
+                .map(|x| UnitBox::new(x as i32, Point3::new(x as Real * 0.5, 0.0, 0.0)))
+                .collect();
+            let mut bvh = BVH::build(&mut shapes);
+            assert_eq!(bvh.nodes.len(), (2 * count).saturating_sub(1));
+            assert!(bvh.is_consistent(&shapes));
+            bvh.assert_consistent(&shapes);
+            bvh.assert_tight(&shapes);
+            bvh.pretty_print();
+
+            assert_eq!(bvh.traverse(&ray, &shapes).len(), count);
+            assert_eq!(bvh.traverse(&everything, &shapes).len(), count);
+            assert_eq!(bvh.traverse_iterator(&ray, &shapes).count(), count);
+            assert_eq!(bvh.traverse_indices(&ray), (0..count).collect::<Vec<_>>());
+            // A leaf root stores no bounds, so the shape's own bounds are tested instead.
+            let missed = Ray::new(Point3::new(15.0, 15.0, -5.0), Vector3::new(0.0, 0.0, 1.0));
+            assert_missed(&bvh, &nothing, &mut shapes);
+            assert_missed(&bvh, &missed, &mut shapes);
+            let nearest = bvh.traverse_best_first(
+                0.0,
+                Real::INFINITY,
+                |_| Some(0.0),
+                |index| Some((1.0, index)),
+            );
+            assert_eq!(nearest.is_some(), count > 0);
+            // Hits on the bounds of the interval are accepted.
+            let touching =
+                bvh.traverse_best_first(1.0, 1.0, |_| Some(0.0), |index| Some((1.0, index)));
+            assert_eq!(touching.is_some(), count > 0);
+
+            let flat = bvh.flatten(&shapes);
+            assert_eq!(flat.traverse(&ray, &shapes).len(), count);
+            assert_eq!(
+                flat.traverse(&nothing, &shapes).len(),
+                bvh.traverse(&nothing, &shapes).len()
+            );
+            assert_eq!(BVH::from_flat(&flat).nodes.len(), bvh.nodes.len());
+            if count == 1 {
+                assert_eq!(flat.len(), 1);
+                assert_eq!(flat[0].entry_index, u32::MAX);
+                assert_eq!((flat[0].exit_index, flat[0].shape_index), (1, 0));
+            } else {
+                assert_eq!(flat.is_empty(), count == 0);
+            }
+
+            bvh.rebuild(&mut shapes);
+            assert!(bvh.is_consistent(&shapes));
+            assert_eq!(bvh.traverse(&ray, &shapes).len(), count);
+        }
+    }
+
+    #[test]
+    /// Tests that removing the only shape leaves an empty tree which can still be queried.
+    fn test_remove_last_shape() {
+        let mut shapes = vec![UnitBox::new(0, Point3::new(0.0, 0.0, 0.0))];
+        let mut bvh = BVH::build(&mut shapes);
+        bvh.remove_node(&mut shapes, 0, true);
+        shapes.pop();
+        assert!(bvh.nodes.is_empty());
+        let ray = Ray::new(Point3::new(0.0, 0.0, -5.0), Vector3::new(0.0, 0.0, 1.0));
+        assert!(bvh.traverse(&ray, &shapes).is_empty());
+
+        shapes.push(UnitBox::new(1, Point3::new(1.0, 0.0, 0.0)));
+        bvh.add_node(&mut shapes, 0);
+        assert!(bvh.traverse(&ray, &shapes).is_empty());
+        let through = Ray::new(Point3::new(1.0, 0.0, -5.0), Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(bvh.traverse(&through, &shapes).len(), 1);
+    }
+
+    #[test]
+    /// Tests that shapes with flat `AABB`s, points on a line and triangles in a plane, are
+    /// built into valid and shallow trees in which every shape can be found.
+    fn test_build_flat_shapes() {
+        let mut points: Vec<Triangle> = (0..100)
+            .map(|x| {
+                let point = Point3::new(x as Real, 0.0, 0.0);
+                Triangle::new(point, point, point)
+            })
+            .collect();
+        let bvh = BVH::build(&mut points);
+        bvh.validate(&points).unwrap();
+        bvh.assert_tight(&points);
+        let depth = bvh.nodes.iter().map(|node| node.depth(&bvh.nodes)).max();
+        assert!(depth.unwrap() < 16);
+        let along = Ray::new(Point3::new(-1.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(bvh.traverse(&along, &points).len(), points.len());
+        for point in points.iter() {
+            let around = AABB::with_bounds(point.a - 0.1, point.a + 0.1);
+            assert_eq!(bvh.traverse(&around, &points).len(), 1);
+        }
+
+        let bounds =
+            AABB::with_bounds(Point3::new(-10.0, -10.0, 0.0), Point3::new(10.0, 10.0, 0.0));
+        let mut seed = 0;
+        let mut triangles: Vec<Triangle> = (0..100)
+            .map(|_| {
+                let a = next_point3(&mut seed, &bounds);
+                Triangle::new(a, a + Vector3::X, a + Vector3::Y)
+            })
+            .collect();
+        let bvh = BVH::build(&mut triangles);
+        bvh.validate(&triangles).unwrap();
+        for triangle in triangles.iter() {
+            let center = (triangle.a + triangle.b + triangle.c) / 3.0;
+            let ray = Ray::new(center + Vector3::Z, -Vector3::Z);
+            assert!(bvh
+                .traverse(&ray, &triangles)
+                .iter()
+                .any(|hit| std::ptr::eq(*hit, triangle)));
+        }
+    }
+
+    #[test]
+    /// Tests that the root of a tree over points on a line is split at the bucket plane
+    /// with the lowest cost by the sum of side lengths, found by trying every plane.
+    fn test_build_flat_shapes_picks_best_split() {
+        let mut points: Vec<Triangle> = (0..100_u32)
+            .map(|i| {
+                let point = Point3::new((i * i % 97) as Real + (i % 3) as Real * 0.1, 1.0, 0.0);
+                Triangle::new(point, point, point)
+            })
+            .collect();
+        let bvh = BVH::build(&mut points);
+        let length = |aabb: &AABB| {
+            let size = aabb.size();
+            size.x + size.y + size.z
+        };
+        let (min, max) = points
+            .iter()
+            .fold((Real::INFINITY, Real::NEG_INFINITY), |(min, max), point| {
+                (min.min(point.a.x), max.max(point.a.x))
+            });
+        let bucket = |point: &Triangle| {
+            ((point.a.x - min) / (max - min) * (NUM_BUCKETS as Real - 0.01)) as usize
+        };
+        let best = (0..NUM_BUCKETS - 1)
+            .map(|plane| {
+                let (left, right): (Vec<&Triangle>, Vec<&Triangle>) =
+                    points.iter().partition(|point| bucket(point) <= plane);
+                let cost = |side: &[&Triangle]| {
+                    let aabb = side
+                        .iter()
+                        .fold(AABB::empty(), |aabb, point| aabb.grow(&point.a));
+                    side.len() as Real * length(&aabb)
+                };
+                cost(&left) + cost(&right)
+            })
+            .fold(Real::INFINITY, Real::min);
+
+        let leaves = |index: usize| {
+            let mut count = 0;
+            let mut stack = vec![index];
+            while let Some(index) = stack.pop() {
+                match bvh.nodes[index] {
+                    BVHNode::Node {
+                        child_l_index,
+                        child_r_index,
+                        ..
+                    } => stack.extend([child_l_index, child_r_index]),
+                    BVHNode::Leaf { .. } => count += 1,
+                }
+            }
+            count
+        };
+        if let BVHNode::Node {
+            child_l_index,
+            ref child_l_aabb,
+            child_r_index,
+            ref child_r_aabb,
+            ..
+        } = bvh.nodes[0]
+        {
+            assert_eq!(child_l_aabb.surface_area(), 0.0);
+            let cost = leaves(child_l_index) as Real * length(child_l_aabb)
+                + leaves(child_r_index) as Real * length(child_r_aabb);
+            assert!(relative_eq(cost, best, 1e-5));
+        } else {
+            panic!("The root of 100 shapes is a leaf.");
+        }
+    }
+
+    #[test]
+    /// Tests that many shapes at the same position are split into equal halves and yield a
+    /// balanced tree instead of a list.
+    fn test_build_coincident_shapes() {
+        let mut shapes: Vec<UnitBox> = (0..100_000)
+            .map(|id| UnitBox::new(id, Point3::new(3.0, -2.0, 1.0)))
+            .collect();
+        let bvh = BVH::build(&mut shapes);
+        bvh.assert_consistent(&shapes);
+        bvh.validate(&shapes).unwrap();
+        let depth = bvh.nodes.iter().map(|node| node.depth(&bvh.nodes)).max();
+        assert!(depth.unwrap() <= 17);
+    }
+
+    #[test]
+    /// Tests that shapes whose centroids differ, but which no bucket plane separates, are
+    /// split into equal halves. The centroids span more than the largest finite number, so
+    /// that every relative position is `0` or NaN and all shapes fall into the first bucket.
+    fn test_build_unseparable_centroids() {
+        let mut shapes: Vec<UnitBox> = (0..64)
+            .map(|id| {
+                UnitBox::new(
+                    id,
+                    Point3::new((id - 32) as Real * (Real::MAX / 40.0), 0.0, 0.0),
+                )
+            })
+            .collect();
+        let bvh = BVH::build(&mut shapes);
+        bvh.assert_consistent(&shapes);
+        let depth = bvh.nodes.iter().map(|node| node.depth(&bvh.nodes)).max();
+        assert!(depth.unwrap() < 16);
+        let ray = Ray::new(
+            Point3::new(-Real::MAX, 0.0, 0.0),
+            Vector3::new(1.0, 0.0, 0.0),
+        );
+        assert_eq!(bvh.traverse(&ray, &shapes).len(), shapes.len());
+    }
+
     #[test]
     fn test_add_bvh() {
         let mut shapes = Vec::new();
@@ -0,0 +1,418 @@
+//! Building a [`BVH`] from shapes whose [`AABB`]s are not trusted, e.g. shapes loaded
+//! from files or produced by a simulation which may have diverged, and building it with a
+//! depth limit and a cancellation flag. Every failure is returned as a [`BuildError`], so
+//! that it can be passed on to callers in other languages instead of a panic.
+//!
+//! [`AABB`]: ../aabb/struct.AABB.html
+//! [`BVH`]: struct.BVH.html
+//! [`BuildError`]: enum.BuildError.html
+//!
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::bounding_hierarchy::BHShape;
+use crate::bvh::BVH;
+
+/// The shapes which have an [`AABB`] which can not be placed into a [`BVH`], because a
+/// bound is `NaN` or infinite, or because the lower bound is greater than the upper bound.
+/// Returned by [`BVH::try_build`] as [`BuildError::InvalidBounds`].
+///
+/// [`AABB`]: ../aabb/struct.AABB.html
+/// [`BVH`]: struct.BVH.html
+/// [`BVH::try_build`]: struct.BVH.html#method.try_build
+/// [`BuildError::InvalidBounds`]: enum.BuildError.html#variant.InvalidBounds
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidBounds {
+    /// The indices of the offending shapes, in ascending order.
+    pub shapes: Vec<usize>,
+}
+
+impl fmt::Display for InvalidBounds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "the shapes {:?} have NaN, infinite or inverted bounds",
+            self.shapes
+        )
+    }
+}
+
+impl std::error::Error for InvalidBounds {}
+
+/// The reasons why [`BVH::try_build`] and [`BVH::try_build_with`] can not build a [`BVH`].
+///
+/// [`BVH`]: struct.BVH.html
+/// [`BVH::try_build`]: struct.BVH.html#method.try_build
+/// [`BVH::try_build_with`]: struct.BVH.html#method.try_build_with
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    /// Some shapes have `NaN`, infinite or inverted bounds.
+    InvalidBounds(InvalidBounds),
+    /// There are more shapes than [`BVH::MAX_SHAPES`].
+    ///
+    /// [`BVH::MAX_SHAPES`]: struct.BVH.html#associatedconstant.MAX_SHAPES
+    ///
+    TooManyShapes {
+        /// The number of shapes.
+        shape_count: usize,
+    },
+    /// A node would be deeper than the maximum depth.
+    DepthLimitExceeded {
+        /// The maximum depth which was passed to [`BVH::try_build_with`].
+        ///
+        /// [`BVH::try_build_with`]: struct.BVH.html#method.try_build_with
+        ///
+        max_depth: u32,
+    },
+    /// The cancellation flag was set during the build.
+    Cancelled,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::InvalidBounds(error) => write!(f, "{}", error),
+            BuildError::TooManyShapes { shape_count } => write!(
+                f,
+                "{} shapes exceed the maximum of {} shapes",
+                shape_count,
+                BVH::MAX_SHAPES
+            ),
+            BuildError::DepthLimitExceeded { max_depth } => {
+                write!(f, "the tree is deeper than the maximum depth {}", max_depth)
+            }
+            BuildError::Cancelled => write!(f, "the build was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BuildError::InvalidBounds(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<InvalidBounds> for BuildError {
+    fn from(error: InvalidBounds) -> BuildError {
+        BuildError::InvalidBounds(error)
+    }
+}
+
+/// The limits of a build, which are checked before every node is built.
+pub(crate) struct BuildLimits<'a> {
+    /// The largest depth of a node. The root has depth `0`.
+    pub(crate) max_depth: u32,
+    /// Stops the build once it is set, possibly by another thread.
+    pub(crate) cancel: Option<&'a AtomicBool>,
+}
+
+impl BuildLimits<'static> {
+    /// No limits, with which a build never fails.
+    pub(crate) const NONE: BuildLimits<'static> = BuildLimits {
+        max_depth: u32::MAX,
+        cancel: None,
+    };
+}
+
+impl BuildLimits<'_> {
+    /// Returns an error if a node at `depth` must not be built.
+    pub(crate) fn check(&self, depth: u32) -> Result<(), BuildError> {
+        if depth > self.max_depth {
+            Err(BuildError::DepthLimitExceeded {
+                max_depth: self.max_depth,
+            })
+        } else if self
+            .cancel
+            .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+        {
+            Err(BuildError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl BVH {
+    /// The largest number of shapes which [`BVH::try_build`] accepts. All nodes of the tree
+    /// can then be addressed by `u32` indices, like those of a [`FlatBVH`], and even
+    /// `2 * shapes.len() - 1` does not overflow a 32 bit `usize`.
+    ///
+    /// [`BVH::try_build`]: struct.BVH.html#method.try_build
+    /// [`FlatBVH`]: ../flat_bvh/type.FlatBVH.html
+    ///
+    pub const MAX_SHAPES: usize = (u32::MAX / 2) as usize;
+
+    /// Creates a new [`BVH`] from the `shapes` slice like [`BVH::build`], but first checks
+    /// the number of shapes and the [`AABB`] of every shape. A single `NaN` or inverted
+    /// [`AABB`] corrupts the bounds of every node above it, so instead of building a broken
+    /// tree all offending shapes are reported.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::{BuildError, InvalidBounds, BVH};
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = [0.0, f32::NAN, 2.0]
+    ///     .iter()
+    ///     .map(|&x| Cube { pos: Point3::new(x, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let error = BVH::try_build(&mut cubes).err().unwrap();
+    /// assert_eq!(error, BuildError::InvalidBounds(InvalidBounds { shapes: vec![1] }));
+    ///
+    /// cubes.remove(1);
+    /// let bvh = BVH::try_build(&mut cubes).unwrap();
+    /// assert_eq!(bvh.nodes.len(), 3);
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::build`]: struct.BVH.html#method.build
+    ///
+    pub fn try_build<Shape: BHShape>(shapes: &mut [Shape]) -> Result<BVH, BuildError> {
+        BVH::try_build_limited(shapes, &BuildLimits::NONE)
+    }
+
+    /// Creates a new [`BVH`] like [`BVH::try_build`], but stops with an error as soon as a
+    /// node would be deeper than `max_depth`, or once `cancel` is set. The root has depth
+    /// `0`, so `n` shapes need a `max_depth` of at least `log2(n)` rounded up. The depth
+    /// limit protects against degenerate inputs, and `cancel` may be set by another thread
+    /// to abort a long build, e.g. when loading a scene is aborted. After an error, the node
+    /// indices of the `shapes` are unspecified.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    ///
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::{BuildError, BVH};
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..16)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let cancel = AtomicBool::new(false);
+    /// let error = BVH::try_build_with(&mut cubes, 3, &cancel).err();
+    /// assert_eq!(error, Some(BuildError::DepthLimitExceeded { max_depth: 3 }));
+    /// assert!(BVH::try_build_with(&mut cubes, 20, &cancel).is_ok());
+    ///
+    /// cancel.store(true, Ordering::Relaxed);
+    /// let error = BVH::try_build_with(&mut cubes, 20, &cancel).err();
+    /// assert_eq!(error, Some(BuildError::Cancelled));
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::try_build`]: struct.BVH.html#method.try_build
+    ///
+    pub fn try_build_with<Shape: BHShape>(
+        shapes: &mut [Shape],
+        max_depth: u32,
+        cancel: &AtomicBool,
+    ) -> Result<BVH, BuildError> {
+        let limits = BuildLimits {
+            max_depth,
+            cancel: Some(cancel),
+        };
+        BVH::try_build_limited(shapes, &limits)
+    }
+
+    /// Checks the `shapes` and builds a [`BVH`] of them within the `limits`.
+    ///
+    /// [`BVH`]: struct.BVH.html
+    ///
+    fn try_build_limited<Shape: BHShape>(
+        shapes: &mut [Shape],
+        limits: &BuildLimits,
+    ) -> Result<BVH, BuildError> {
+        if shapes.len() > BVH::MAX_SHAPES {
+            return Err(BuildError::TooManyShapes {
+                shape_count: shapes.len(),
+            });
+        }
+        let invalid: Vec<usize> = shapes
+            .iter()
+            .enumerate()
+            .filter(|(_, shape)| {
+                let aabb = shape.aabb();
+                !aabb.is_finite() || aabb.is_empty()
+            })
+            .map(|(index, _)| index)
+            .collect();
+        if !invalid.is_empty() {
+            return Err(InvalidBounds { shapes: invalid }.into());
+        }
+        let mut bvh = BVH { nodes: Vec::new() };
+        bvh.rebuild_limited(shapes, limits)?;
+        Ok(bvh)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+
+    use crate::aabb::{Bounded, AABB};
+    use crate::bounding_hierarchy::BHShape;
+    use crate::bvh::{BuildError, InvalidBounds, BVH};
+    use crate::{Point3, Real};
+
+    /// A shape with fixed bounds, which may be invalid.
+    struct Bounds {
+        aabb: AABB,
+        node_index: usize,
+    }
+
+    impl Bounded for Bounds {
+        fn aabb(&self) -> AABB {
+            self.aabb
+        }
+    }
+
+    impl BHShape for Bounds {
+        fn set_bh_node_index(&mut self, index: usize) {
+            self.node_index = index;
+        }
+
+        fn bh_node_index(&self) -> usize {
+            self.node_index
+        }
+    }
+
+    /// Returns ten unit boxes along the x axis, with the bounds of some replaced.
+    fn shapes(invalid: &[(usize, AABB)]) -> Vec<Bounds> {
+        (0..10)
+            .map(|i| {
+                let min = Point3::new(i as Real * 2.0, 0.0, 0.0);
+                let aabb = invalid
+                    .iter()
+                    .find(|(index, _)| *index == i)
+                    .map_or(AABB::with_bounds(min, min + 1.0), |(_, aabb)| *aabb);
+                Bounds {
+                    aabb,
+                    node_index: 0,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    /// Tests that shapes with `NaN`, infinite and inverted bounds are all reported.
+    fn test_try_build_rejects_invalid_bounds() {
+        let nan = AABB::with_bounds(Point3::new(0.0, Real::NAN, 0.0), Point3::splat(1.0));
+        let infinite = AABB::with_bounds(Point3::ZERO, Point3::new(1.0, 1.0, Real::INFINITY));
+        let inverted = AABB::with_bounds(Point3::splat(1.0), Point3::new(2.0, 0.0, 2.0));
+        for aabb in [nan, infinite, inverted] {
+            let error = BVH::try_build(&mut shapes(&[(4, aabb)])).err();
+            let invalid = InvalidBounds { shapes: vec![4] };
+            assert_eq!(error, Some(BuildError::InvalidBounds(invalid)));
+        }
+
+        let mut all = shapes(&[(7, inverted), (2, nan), (9, infinite), (0, AABB::empty())]);
+        let error = BVH::try_build(&mut all).err().unwrap();
+        let invalid = InvalidBounds {
+            shapes: vec![0, 2, 7, 9],
+        };
+        assert_eq!(error, BuildError::InvalidBounds(invalid));
+        assert_eq!(
+            error.to_string(),
+            "the shapes [0, 2, 7, 9] have NaN, infinite or inverted bounds"
+        );
+    }
+
+    #[test]
+    /// Tests that valid shapes, including flat ones, are built like with `BVH::build`.
+    fn test_try_build_valid_bounds() {
+        let flat = AABB::with_bounds(Point3::splat(3.0), Point3::new(4.0, 3.0, 4.0));
+        let mut shapes = shapes(&[(3, flat)]);
+        let bvh = BVH::try_build(&mut shapes).unwrap();
+        assert!(bvh.is_consistent(&shapes));
+        let nodes = BVH::build(&mut shapes).nodes;
+        assert_eq!(format!("{:?}", bvh.nodes), format!("{:?}", nodes));
+        assert!(BVH::try_build::<Bounds>(&mut []).unwrap().nodes.is_empty());
+    }
+
+    /// A shape without size, of which huge slices take no memory.
+    #[derive(Clone, Copy)]
+    struct Empty;
+
+    impl Bounded for Empty {
+        fn aabb(&self) -> AABB {
+            AABB::with_bounds(Point3::ZERO, Point3::splat(1.0))
+        }
+    }
+
+    impl BHShape for Empty {
+        fn set_bh_node_index(&mut self, _: usize) {}
+
+        fn bh_node_index(&self) -> usize {
+            0
+        }
+    }
+
+    #[test]
+    /// Tests that more shapes than `BVH::MAX_SHAPES` are rejected before they are built.
+    fn test_try_build_too_many_shapes() {
+        let shape_count = BVH::MAX_SHAPES + 1;
+        let mut empties = [Empty; BVH::MAX_SHAPES + 1];
+        let error = BVH::try_build(&mut empties).err();
+        assert_eq!(error, Some(BuildError::TooManyShapes { shape_count }));
+        assert!(BVH::MAX_SHAPES * 2 - 1 < u32::MAX as usize);
+    }
+
+    #[test]
+    /// Tests that the depth limit is exceeded exactly when the tree is deeper than the
+    /// limit, and that a cancelled build fails and leaves the shapes buildable.
+    fn test_try_build_with_limits() {
+        let mut shapes = shapes(&[]);
+        let bvh = BVH::build(&mut shapes);
+        let depth = bvh.nodes.iter().map(|node| node.depth(&bvh.nodes)).max();
+        let depth = depth.unwrap();
+        let not_cancelled = AtomicBool::new(false);
+
+        let limited = BVH::try_build_with(&mut shapes, depth, &not_cancelled).unwrap();
+        assert_eq!(format!("{:?}", limited.nodes), format!("{:?}", bvh.nodes));
+        let error = BVH::try_build_with(&mut shapes, depth - 1, &not_cancelled).err();
+        let exceeded = BuildError::DepthLimitExceeded {
+            max_depth: depth - 1,
+        };
+        assert_eq!(error, Some(exceeded.clone()));
+        assert_eq!(
+            exceeded.to_string(),
+            format!("the tree is deeper than the maximum depth {}", depth - 1)
+        );
+
+        let cancelled = AtomicBool::new(true);
+        let error = BVH::try_build_with(&mut shapes, u32::MAX, &cancelled).err();
+        assert_eq!(error, Some(BuildError::Cancelled));
+        let bvh = BVH::try_build(&mut shapes).unwrap();
+        bvh.validate(&shapes).unwrap();
+    }
+}
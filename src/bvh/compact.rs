@@ -0,0 +1,265 @@
+//! Compaction of the node array of a [`BVH`] into depth-first order, and reordering of the
+//! shapes into the order of the leaves.
+//!
+//! [`BVH`]: struct.BVH.html
+//!
+
+use crate::bvh::{BVHNode, BVH};
+
+impl BVH {
+    /// Rewrites the nodes of the [`BVH`] in depth-first order, so that every left child
+    /// directly follows its parent. Dynamic updates such as [`BVH::insert`] and
+    /// [`BVH::remove`] scatter nodes across the array, which hurts the cache locality of
+    /// traversals; compacting restores the layout of a fresh build and drops nodes which are
+    /// no longer reachable from the root.
+    ///
+    /// Returns a map from old to new node indices. Nodes which were unreachable map to
+    /// `usize::MAX`. The node indices stored in the shapes have to be updated with it.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..10)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let mut bvh = BVH::build(&mut cubes[..5]);
+    /// for i in 5..10 {
+    ///     bvh.insert(&mut cubes, i);
+    /// }
+    ///
+    /// let remap = bvh.compact();
+    /// for cube in cubes.iter_mut() {
+    ///     cube.node_index = remap[cube.node_index];
+    /// }
+    /// bvh.assert_consistent(&cubes);
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::insert`]: struct.BVH.html#method.insert
+    /// [`BVH::remove`]: struct.BVH.html#method.remove
+    ///
+    pub fn compact(&mut self) -> Vec<usize> {
+        let mut remap = vec![usize::MAX; self.nodes.len()];
+        if self.nodes.is_empty() {
+            return remap;
+        }
+
+        // Assign new indices in depth-first order.
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut stack = vec![0];
+        while let Some(node_index) = stack.pop() {
+            remap[node_index] = order.len();
+            order.push(node_index);
+            if let BVHNode::Node {
+                child_l_index,
+                child_r_index,
+                ..
+            } = self.nodes[node_index]
+            {
+                stack.push(child_r_index);
+                stack.push(child_l_index);
+            }
+        }
+
+        let nodes = order
+            .iter()
+            .map(|&old_index| {
+                let mut node = self.nodes[old_index];
+                let parent_index = node.parent_mut();
+                *parent_index = remap[*parent_index];
+                if let BVHNode::Node {
+                    ref mut child_l_index,
+                    ref mut child_r_index,
+                    ..
+                } = node
+                {
+                    *child_l_index = remap[*child_l_index];
+                    *child_r_index = remap[*child_r_index];
+                }
+                node
+            })
+            .collect();
+        self.nodes = nodes;
+        remap
+    }
+
+    /// Reorders the `shapes` so that they are stored in the order in which a depth-first
+    /// traversal visits the leaves, and updates the shape indices of the leaves. Shapes
+    /// which are close in the tree are then close in memory, which avoids cache misses when
+    /// the hit shapes are tested, and the shapes below any node form a contiguous range.
+    /// Shapes which are not referenced by a leaf are moved behind all others.
+    ///
+    /// Returns the permutation, which maps the new index of each shape to its old index,
+    /// e.g. to reorder data which is stored alongside the shapes. The node indices stored in
+    /// the shapes stay valid.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = [7, 2, 9, 0, 4]
+    ///     .iter()
+    ///     .map(|&x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let positions: Vec<Point3> = cubes.iter().map(|cube| cube.pos).collect();
+    /// let mut bvh = BVH::build(&mut cubes);
+    ///
+    /// let permutation = bvh.reorder_shapes(&mut cubes);
+    /// for (cube, &old_index) in cubes.iter().zip(&permutation) {
+    ///     assert_eq!(cube.pos, positions[old_index]);
+    /// }
+    /// bvh.assert_consistent(&cubes);
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    ///
+    pub fn reorder_shapes<Shape>(&mut self, shapes: &mut Vec<Shape>) -> Vec<usize> {
+        let mut permutation = Vec::with_capacity(shapes.len());
+        if !self.nodes.is_empty() {
+            let mut stack = vec![0];
+            while let Some(node_index) = stack.pop() {
+                match self.nodes[node_index] {
+                    BVHNode::Node {
+                        child_l_index,
+                        child_r_index,
+                        ..
+                    } => {
+                        stack.push(child_r_index);
+                        stack.push(child_l_index);
+                    }
+                    BVHNode::Leaf {
+                        ref mut shape_index,
+                        ..
+                    } => {
+                        permutation.push(*shape_index);
+                        *shape_index = permutation.len() - 1;
+                    }
+                }
+            }
+        }
+
+        // Append the shapes without a leaf in their current order.
+        let mut referenced = vec![false; shapes.len()];
+        for &old_index in &permutation {
+            referenced[old_index] = true;
+        }
+        permutation.extend((0..shapes.len()).filter(|&old_index| !referenced[old_index]));
+
+        let mut old_shapes: Vec<Option<Shape>> = shapes.drain(..).map(Some).collect();
+        shapes.extend(
+            permutation
+                .iter()
+                .map(|&old_index| old_shapes[old_index].take().unwrap()),
+        );
+        permutation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bounding_hierarchy::BHShape;
+    use crate::bvh::{BVHNode, BVH};
+    use crate::testbase::generate_aligned_boxes;
+
+    #[test]
+    /// Builds a scattered `BVH` by insertions and removals, compacts it and checks the
+    /// depth-first layout and the remapped shapes.
+    fn test_compact_depth_first() {
+        let mut shapes = generate_aligned_boxes();
+        let mut bvh = BVH::build(&mut shapes[..4]);
+        for i in 4..shapes.len() {
+            bvh.insert(&mut shapes, i);
+        }
+        for i in [3, 11, 0, 7] {
+            bvh.remove(&mut shapes, i);
+        }
+
+        let remap = bvh.compact();
+        assert!(remap.iter().all(|&new_index| new_index < bvh.nodes.len()));
+        for shape in shapes.iter_mut() {
+            let node_index = remap[shape.bh_node_index()];
+            shape.set_bh_node_index(node_index);
+        }
+        bvh.assert_consistent(&shapes);
+        bvh.assert_tight(&shapes);
+
+        // In depth-first order the left child follows its parent directly, and the right
+        // child follows the whole left subtree.
+        let mut subtree_size = vec![1; bvh.nodes.len()];
+        for index in (0..bvh.nodes.len()).rev() {
+            if let BVHNode::Node {
+                child_l_index,
+                child_r_index,
+                ..
+            } = bvh.nodes[index]
+            {
+                assert_eq!(child_l_index, index + 1);
+                assert_eq!(child_r_index, index + 1 + subtree_size[child_l_index]);
+                subtree_size[index] += subtree_size[child_l_index] + subtree_size[child_r_index];
+            }
+        }
+    }
+
+    #[test]
+    /// Reorders the shapes of a `BVH` and checks that the leaves are visited in ascending
+    /// shape order and that the permutation describes the move of the shapes.
+    fn test_reorder_shapes_leaf_order() {
+        let mut shapes = generate_aligned_boxes();
+        shapes.reverse();
+        let mut bvh = BVH::build(&mut shapes);
+        let old_shapes = shapes.clone();
+
+        let permutation = bvh.reorder_shapes(&mut shapes);
+        bvh.assert_consistent(&shapes);
+        for (shape, &old_index) in shapes.iter().zip(&permutation) {
+            assert_eq!(shape.id, old_shapes[old_index].id);
+        }
+
+        let mut stack = vec![0];
+        let mut next_shape = 0;
+        while let Some(node_index) = stack.pop() {
+            match bvh.nodes[node_index] {
+                BVHNode::Node {
+                    child_l_index,
+                    child_r_index,
+                    ..
+                } => {
+                    stack.push(child_r_index);
+                    stack.push(child_l_index);
+                }
+                BVHNode::Leaf { shape_index, .. } => {
+                    assert_eq!(shape_index, next_shape);
+                    next_shape += 1;
+                }
+            }
+        }
+        assert_eq!(next_shape, shapes.len());
+    }
+}
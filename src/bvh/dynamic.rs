@@ -0,0 +1,532 @@
+//! Support for [`BVH`]s over moving shapes, whose leaves store enlarged bounds so that
+//! small movements do not require touching the tree at all, or bounds which are swept along
+//! the shapes' velocities.
+//!
+//! [`BVH`]: struct.BVH.html
+//!
+
+use crate::aabb::{Bounded, AABB};
+use crate::bounding_hierarchy::BHShape;
+use crate::bvh::{BVHNode, BVH};
+use crate::{Real, Vector3};
+
+/// Stands in for a shape during a build, but reports custom bounds for it.
+struct ProxyShape<'a, Shape> {
+    shape: &'a mut Shape,
+    aabb: AABB,
+}
+
+impl<'a, Shape> Bounded for ProxyShape<'a, Shape> {
+    fn aabb(&self) -> AABB {
+        self.aabb
+    }
+}
+
+impl<'a, Shape: BHShape> BHShape for ProxyShape<'a, Shape> {
+    fn set_bh_node_index(&mut self, index: usize) {
+        self.shape.set_bh_node_index(index);
+    }
+
+    fn bh_node_index(&self) -> usize {
+        self.shape.bh_node_index()
+    }
+}
+
+/// Returns `aabb` extended to also cover its position after moving by `displacement`.
+fn sweep(aabb: &AABB, displacement: Vector3) -> AABB {
+    aabb.join(&AABB::with_bounds(
+        aabb.min + displacement,
+        aabb.max + displacement,
+    ))
+}
+
+impl BVH {
+    /// Creates a new [`BVH`] from the `shapes` slice, where the bounds of every shape are
+    /// provided by `shape_aabb` instead of [`Bounded::aabb`].
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`Bounded::aabb`]: ../aabb/trait.Bounded.html#tymethod.aabb
+    ///
+    pub(crate) fn build_with<Shape: BHShape>(
+        shapes: &mut [Shape],
+        mut shape_aabb: impl FnMut(usize, &Shape) -> AABB,
+    ) -> BVH {
+        let mut proxies: Vec<ProxyShape<Shape>> = shapes
+            .iter_mut()
+            .enumerate()
+            .map(|(index, shape)| {
+                let aabb = shape_aabb(index, shape);
+                ProxyShape { shape, aabb }
+            })
+            .collect();
+        BVH::build(&mut proxies)
+    }
+
+    /// Creates a new [`BVH`] from the `shapes` slice, in which the bounds of every shape are
+    /// enlarged by `margin` in every direction. Shapes can then move by up to `margin`
+    /// without invalidating the [`BVH`], which avoids refitting jittering objects every
+    /// frame. Use [`BVH::needs_update`] to find the shapes which have left their bounds.
+    ///
+    /// The margin is not stored in the [`BVH`]. Keep it up with [`BVH::refit_with_margin`],
+    /// [`BVH::update_shapes_with_margin`], [`BVH::insert_with_margin`],
+    /// [`BVH::remove_with_margin`] and [`BVH::rebuild_with_margin`], since the plain versions
+    /// of these methods write tight bounds.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..10)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let mut bvh = BVH::build_with_margin(&mut cubes, 0.25);
+    ///
+    /// cubes[3].pos.y += 0.2;
+    /// assert!(!bvh.needs_update(3, &cubes[3]));
+    /// cubes[3].pos.y += 0.2;
+    /// assert!(bvh.needs_update(3, &cubes[3]));
+    ///
+    /// bvh.refit_with_margin(&cubes, 0.25);
+    /// assert!(!bvh.needs_update(3, &cubes[3]));
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::insert_with_margin`]: struct.BVH.html#method.insert_with_margin
+    /// [`BVH::needs_update`]: struct.BVH.html#method.needs_update
+    /// [`BVH::rebuild_with_margin`]: struct.BVH.html#method.rebuild_with_margin
+    /// [`BVH::refit_with_margin`]: struct.BVH.html#method.refit_with_margin
+    /// [`BVH::remove_with_margin`]: struct.BVH.html#method.remove_with_margin
+    /// [`BVH::update_shapes_with_margin`]: struct.BVH.html#method.update_shapes_with_margin
+    ///
+    pub fn build_with_margin<Shape: BHShape>(shapes: &mut [Shape], margin: Real) -> BVH {
+        BVH::build_with(shapes, |_, shape| shape.aabb().inflate(margin))
+    }
+
+    /// Like [`BVH::refit`], but enlarges the bounds of every shape by `margin` in every
+    /// direction, just like [`BVH::build_with_margin`].
+    ///
+    /// [`BVH::build_with_margin`]: struct.BVH.html#method.build_with_margin
+    /// [`BVH::refit`]: struct.BVH.html#method.refit
+    ///
+    pub fn refit_with_margin<Shape: Bounded>(&mut self, shapes: &[Shape], margin: Real) {
+        self.refit_with(|shape_index| shapes[shape_index].aabb().inflate(margin));
+    }
+
+    /// Like [`BVH::update_shapes`], but enlarges the bounds of the moved shapes by `margin`
+    /// in every direction, just like [`BVH::build_with_margin`]. Only the shapes for which
+    /// [`BVH::needs_update`] returns `true` have to be passed in `moved_indices`.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..10)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let mut bvh = BVH::build_with_margin(&mut cubes, 0.25);
+    ///
+    /// cubes[3].pos.y += 1.0;
+    /// assert!(bvh.needs_update(3, &cubes[3]));
+    /// bvh.update_shapes_with_margin(&[3], &cubes, 0.25);
+    ///
+    /// // The new bounds of the cube still leave room for small movements.
+    /// cubes[3].pos.y += 0.2;
+    /// assert!(!bvh.needs_update(3, &cubes[3]));
+    /// ```
+    ///
+    /// [`BVH::build_with_margin`]: struct.BVH.html#method.build_with_margin
+    /// [`BVH::needs_update`]: struct.BVH.html#method.needs_update
+    /// [`BVH::update_shapes`]: struct.BVH.html#method.update_shapes
+    ///
+    pub fn update_shapes_with_margin<Shape: BHShape>(
+        &mut self,
+        moved_indices: &[usize],
+        shapes: &[Shape],
+        margin: Real,
+    ) {
+        self.update_shapes_with(moved_indices, shapes, |shape| shape.aabb().inflate(margin));
+    }
+
+    /// Like [`BVH::insert`], but enlarges the bounds of the new shape by `margin` in every
+    /// direction, just like [`BVH::build_with_margin`].
+    ///
+    /// [`BVH::build_with_margin`]: struct.BVH.html#method.build_with_margin
+    /// [`BVH::insert`]: struct.BVH.html#method.insert
+    ///
+    pub fn insert_with_margin<Shape: BHShape>(
+        &mut self,
+        shapes: &mut [Shape],
+        new_shape_index: usize,
+        margin: Real,
+    ) {
+        self.insert_with(shapes, new_shape_index, |shape| {
+            shape.aabb().inflate(margin)
+        });
+    }
+
+    /// Like [`BVH::remove`], but keeps the bounds of the remaining shapes enlarged by
+    /// `margin` in every direction, just like [`BVH::build_with_margin`]. Removing a leaf
+    /// promotes its sibling and refits the ancestors, which would otherwise store tight
+    /// bounds again.
+    ///
+    /// [`BVH::build_with_margin`]: struct.BVH.html#method.build_with_margin
+    /// [`BVH::remove`]: struct.BVH.html#method.remove
+    ///
+    pub fn remove_with_margin<Shape: BHShape>(
+        &mut self,
+        shapes: &mut Vec<Shape>,
+        shape_index: usize,
+        margin: Real,
+    ) -> (Shape, Option<usize>) {
+        // The removal only refits the ancestors of the sibling, so the path from any leaf
+        // below the sibling to the root covers all bounds which lost their margin.
+        let node_index = shapes[shape_index].bh_node_index();
+        let mut below_sibling = None;
+        if node_index != 0 {
+            let parent = self.nodes[self.nodes[node_index].parent()];
+            let mut index = if parent.child_l() == node_index {
+                parent.child_r()
+            } else {
+                parent.child_l()
+            };
+            while let BVHNode::Node { child_l_index, .. } = self.nodes[index] {
+                index = child_l_index;
+            }
+            below_sibling = self.nodes[index].shape_index();
+        }
+
+        let (shape, moved_from) = self.remove(shapes, shape_index);
+        if let Some(mut leaf_shape_index) = below_sibling {
+            if moved_from == Some(leaf_shape_index) {
+                leaf_shape_index = shape_index;
+            }
+            let node_aabb = |node: BVHNode| match node {
+                BVHNode::Leaf { shape_index, .. } => shapes[shape_index].aabb().inflate(margin),
+                BVHNode::Node {
+                    child_l_aabb,
+                    child_r_aabb,
+                    ..
+                } => child_l_aabb.join(&child_r_aabb),
+            };
+            let mut node_index = shapes[leaf_shape_index].bh_node_index();
+            while node_index != 0 {
+                let parent_index = self.nodes[node_index].parent();
+                let parent = self.nodes[parent_index];
+                let child_l_aabb = node_aabb(self.nodes[parent.child_l()]);
+                let child_r_aabb = node_aabb(self.nodes[parent.child_r()]);
+                *self.nodes[parent_index].child_l_aabb_mut() = child_l_aabb;
+                *self.nodes[parent_index].child_r_aabb_mut() = child_r_aabb;
+                node_index = parent_index;
+            }
+        }
+        (shape, moved_from)
+    }
+
+    /// Like [`BVH::rebuild`], but enlarges the bounds of every shape by `margin` in every
+    /// direction, just like [`BVH::build_with_margin`].
+    ///
+    /// [`BVH::build_with_margin`]: struct.BVH.html#method.build_with_margin
+    /// [`BVH::rebuild`]: struct.BVH.html#method.rebuild
+    ///
+    pub fn rebuild_with_margin<Shape: BHShape>(&mut self, shapes: &mut [Shape], margin: Real) {
+        *self = BVH::build_with_margin(shapes, margin);
+    }
+
+    /// Creates a new [`BVH`] from the `shapes` slice, in which the bounds of every shape cover
+    /// its whole path over the next `time_step` when it moves with `velocities[i]`. Queries
+    /// against such a [`BVH`] find every shape which may be touched during the time step,
+    /// which keeps continuous collision detection from tunneling through the broad phase.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..10)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let mut velocities = vec![Vector3::ZERO; cubes.len()];
+    /// velocities[3] = Vector3::new(0.0, 10.0, 0.0);
+    /// let bvh = BVH::build_swept(&mut cubes, &velocities, 0.5);
+    ///
+    /// // Cube 3 passes this point during the time step.
+    /// let probe = AABB::with_bounds(Point3::new(6.0, 4.0, 0.0), Point3::new(6.0, 4.0, 0.0));
+    /// assert_eq!(bvh.traverse(&probe, &cubes).len(), 1);
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    ///
+    pub fn build_swept<Shape: BHShape>(
+        shapes: &mut [Shape],
+        velocities: &[Vector3],
+        time_step: Real,
+    ) -> BVH {
+        BVH::build_with(shapes, |index, shape| {
+            sweep(&shape.aabb(), velocities[index] * time_step)
+        })
+    }
+
+    /// Like [`BVH::refit`], but sweeps the bounds of every shape along its velocity over the
+    /// next `time_step`, just like [`BVH::build_swept`].
+    ///
+    /// [`BVH::build_swept`]: struct.BVH.html#method.build_swept
+    /// [`BVH::refit`]: struct.BVH.html#method.refit
+    ///
+    pub fn refit_swept<Shape: Bounded>(
+        &mut self,
+        shapes: &[Shape],
+        velocities: &[Vector3],
+        time_step: Real,
+    ) {
+        self.refit_with(|shape_index| {
+            sweep(
+                &shapes[shape_index].aabb(),
+                velocities[shape_index] * time_step,
+            )
+        });
+    }
+
+    /// Returns `true` if `shape`, which is stored at `shape_index`, is no longer contained in
+    /// the bounds stored for its leaf, so that the [`BVH`] has to be updated before it is
+    /// queried. The leaf is found through [`BHShape::bh_node_index`], so this only looks at
+    /// a single shape. A [`BVH`] which consists of a single leaf stores no bounds and never
+    /// needs an update.
+    ///
+    /// [`BHShape::bh_node_index`]: ../bounding_hierarchy/trait.BHShape.html#tymethod.bh_node_index
+    /// [`BVH`]: struct.BVH.html
+    ///
+    pub fn needs_update<Shape: BHShape>(&self, shape_index: usize, shape: &Shape) -> bool {
+        let node_index = shape.bh_node_index();
+        if node_index == 0 {
+            return false;
+        }
+        debug_assert_eq!(
+            self.nodes[node_index].shape_index(),
+            Some(shape_index),
+            "shape {} is not stored at node {}",
+            shape_index,
+            node_index
+        );
+        let stored_aabb = match self.nodes[self.nodes[node_index].parent()] {
+            BVHNode::Node {
+                child_l_index,
+                ref child_l_aabb,
+                ref child_r_aabb,
+                ..
+            } => {
+                if child_l_index == node_index {
+                    *child_l_aabb
+                } else {
+                    *child_r_aabb
+                }
+            }
+            BVHNode::Leaf { .. } => unreachable!(),
+        };
+        let aabb = shape.aabb();
+        !(stored_aabb.contains(&aabb.min) && stored_aabb.contains(&aabb.max))
+    }
+
+    /// Like [`BVH::needs_update`], but looks up the shape at `shape_index` in `shapes`.
+    ///
+    /// [`BVH::needs_update`]: struct.BVH.html#method.needs_update
+    ///
+    pub fn needs_update_in<Shape: BHShape>(&self, shapes: &[Shape], shape_index: usize) -> bool {
+        self.needs_update(shape_index, &shapes[shape_index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::{Bounded, AABB};
+    use crate::bvh::BVH;
+    use crate::testbase::{generate_aligned_boxes, UnitBox};
+    use crate::{Point3, Real, Vector3};
+
+    #[test]
+    /// Moves every shape by a different amount and checks which ones escaped their bounds.
+    fn test_needs_update_with_margin() {
+        let mut shapes = generate_aligned_boxes();
+        let mut bvh = BVH::build_with_margin(&mut shapes, 0.5);
+        assert!(bvh.is_consistent(&shapes));
+        for (i, shape) in shapes.iter().enumerate() {
+            assert!(!bvh.needs_update(i, shape));
+        }
+
+        for (i, shape) in shapes.iter_mut().enumerate() {
+            shape.pos += Vector3::new(0.0, 0.0, i as Real * 0.04);
+        }
+        for i in 0..shapes.len() {
+            assert_eq!(bvh.needs_update_in(&shapes, i), i >= 13, "shape {}", i);
+        }
+
+        bvh.refit_with_margin(&shapes, 0.5);
+        assert!(bvh.is_consistent(&shapes));
+        for (i, shape) in shapes.iter().enumerate() {
+            assert!(!bvh.needs_update(i, shape));
+        }
+    }
+
+    #[test]
+    /// Moves shapes out of their bounds, updates them with every margin aware method and
+    /// checks that a second move within the margin needs no update.
+    fn test_margin_survives_updates() {
+        let margin = 0.5;
+        let step = Vector3::new(0.0, 0.0, 0.4);
+        let mut shapes = generate_aligned_boxes();
+        let mut bvh = BVH::build_with_margin(&mut shapes, margin);
+
+        shapes[4].pos += step * 2.0;
+        shapes[9].pos += step * 2.0;
+        assert!(bvh.needs_update(4, &shapes[4]));
+        assert!(bvh.needs_update(9, &shapes[9]));
+        bvh.update_shapes_with_margin(&[4, 9], &shapes, margin);
+        assert!(bvh.is_consistent(&shapes));
+        shapes[4].pos += step;
+        shapes[9].pos += step;
+        assert!(!bvh.needs_update(4, &shapes[4]));
+        assert!(!bvh.needs_update(9, &shapes[9]));
+
+        // Into an empty tree, into a single leaf and into a complete tree.
+        let mut inserted: Vec<UnitBox> = Vec::new();
+        let mut small_bvh = BVH { nodes: Vec::new() };
+        for i in 0..3 {
+            inserted.push(UnitBox::new(
+                i as i32,
+                Point3::new(i as Real * 3.0, 0.0, 0.0),
+            ));
+            small_bvh.insert_with_margin(&mut inserted, i, margin);
+        }
+        shapes.push(UnitBox::new(
+            shapes.len() as i32,
+            Point3::new(0.0, 5.0, 0.0),
+        ));
+        let last = shapes.len() - 1;
+        bvh.insert_with_margin(&mut shapes, last, margin);
+        for shape in inserted.iter_mut() {
+            shape.pos += step;
+        }
+        for (i, shape) in inserted.iter().enumerate() {
+            assert!(!small_bvh.needs_update(i, shape), "shape {}", i);
+        }
+        shapes[last].pos += step;
+        assert!(!bvh.needs_update(last, &shapes[last]));
+
+        bvh.rebuild_with_margin(&mut shapes, margin);
+        assert!(bvh.is_consistent(&shapes));
+        for shape in shapes.iter_mut() {
+            shape.pos += step;
+        }
+        for (i, shape) in shapes.iter().enumerate() {
+            assert!(!bvh.needs_update(i, shape), "shape {}", i);
+        }
+    }
+
+    #[test]
+    /// Removes shapes from a tree with enlarged bounds and checks that the remaining shapes
+    /// can still move within the margin without an update.
+    fn test_remove_keeps_margin() {
+        let margin = 0.5;
+        let step = Vector3::new(0.0, 0.4, 0.0);
+        let mut shapes = generate_aligned_boxes();
+        let mut bvh = BVH::build_with_margin(&mut shapes, margin);
+
+        // Remove a shape in the middle, the last shape and shapes until only two are left.
+        assert_eq!(shapes.len(), 21);
+        for &index in &[10, 19, 0, 3, 5] {
+            bvh.remove_with_margin(&mut shapes, index, margin);
+            assert!(bvh.is_consistent(&shapes));
+            for shape in shapes.iter_mut() {
+                shape.pos += step;
+            }
+            for (i, shape) in shapes.iter().enumerate() {
+                assert!(!bvh.needs_update(i, shape), "shape {}", i);
+            }
+            for shape in shapes.iter_mut() {
+                shape.pos -= step;
+            }
+        }
+        while shapes.len() > 2 {
+            bvh.remove_with_margin(&mut shapes, 0, margin);
+        }
+        shapes[0].pos += step;
+        shapes[1].pos += step;
+        assert!(!bvh.needs_update(0, &shapes[0]));
+        assert!(!bvh.needs_update(1, &shapes[1]));
+    }
+
+    #[test]
+    /// Checks that swept bounds contain the shapes at the start and at the end of the time
+    /// step, and that refitting follows the shapes.
+    fn test_swept_bounds_cover_motion() {
+        let mut shapes = generate_aligned_boxes();
+        let velocities: Vec<Vector3> = (0..shapes.len())
+            .map(|i| Vector3::new(0.0, i as Real - 10.0, 2.0))
+            .collect();
+        let time_step = 0.25;
+        let mut bvh = BVH::build_swept(&mut shapes, &velocities, time_step);
+        assert!(bvh.is_consistent(&shapes));
+
+        for _ in 0..3 {
+            let end_positions: Vec<Point3> = shapes
+                .iter()
+                .zip(velocities.iter())
+                .map(|(shape, velocity)| shape.pos + *velocity * time_step)
+                .collect();
+            for (shape, end) in shapes.iter().zip(end_positions.iter()) {
+                let end_aabb =
+                    AABB::with_bounds(*end - Vector3::splat(0.5), *end + Vector3::splat(0.5));
+                let hits = bvh.traverse(&end_aabb, &shapes);
+                assert!(hits.iter().any(|hit| hit.id == shape.id));
+                assert!(bvh
+                    .traverse(&shape.aabb(), &shapes)
+                    .iter()
+                    .any(|hit| hit.id == shape.id));
+            }
+
+            // Advance the simulation and sweep the next time step.
+            for (shape, end) in shapes.iter_mut().zip(end_positions) {
+                shape.pos = end;
+            }
+            bvh.refit_swept(&shapes, &velocities, time_step);
+            assert!(bvh.is_consistent(&shapes));
+        }
+    }
+}
@@ -0,0 +1,508 @@
+//! Export of the node [`AABB`]s of a [`BVH`] as wireframe geometry, so that the quality
+//! of a tree can be inspected in tools like Blender or MeshLab, and of its structure as a
+//! Graphviz graph.
+//!
+//! [`AABB`]: ../aabb/struct.AABB.html
+//! [`BVH`]: struct.BVH.html
+//!
+
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::aabb::AABB;
+use crate::bvh::{BVHNode, BVH};
+use crate::Point3;
+
+/// The pairs of corners of an [`AABB`] which are connected by an edge. The bits of a corner
+/// index select the `max` instead of the `min` coordinate along `x`, `y` and `z`.
+///
+/// [`AABB`]: ../aabb/struct.AABB.html
+///
+const EDGES: [(usize, usize); 12] = [
+    (0, 1),
+    (2, 3),
+    (4, 5),
+    (6, 7),
+    (0, 2),
+    (1, 3),
+    (4, 6),
+    (5, 7),
+    (0, 4),
+    (1, 5),
+    (2, 6),
+    (3, 7),
+];
+
+/// The colors of the depths of the tree, repeated for deeper levels.
+const DEPTH_COLORS: [[u8; 3]; 8] = [
+    [230, 25, 75],
+    [245, 130, 48],
+    [255, 225, 25],
+    [60, 180, 75],
+    [70, 240, 240],
+    [0, 130, 200],
+    [145, 30, 180],
+    [240, 50, 230],
+];
+
+/// The file formats of a wireframe export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireframeFormat {
+    /// Wavefront OBJ, with one line element per edge and one group per depth.
+    Obj,
+    /// ASCII PLY, with edge elements and vertices which are colored by depth.
+    Ply,
+}
+
+/// Returns the corners of `aabb`, see [`EDGES`].
+///
+/// [`EDGES`]: constant.EDGES.html
+///
+fn corners(aabb: &AABB) -> impl Iterator<Item = Point3> + '_ {
+    (0..8).map(move |corner| {
+        Point3::new(
+            aabb[corner & 1].x,
+            aabb[(corner >> 1) & 1].y,
+            aabb[(corner >> 2) & 1].z,
+        )
+    })
+}
+
+impl BVH {
+    /// Returns the [`AABB`]s of the nodes up to `max_depth` with their depths, level by
+    /// level. The root has a depth of `0`. A [`BVH`] with a single leaf has no boxes, since
+    /// the bounds of a leaf are stored in its parent.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH`]: struct.BVH.html
+    ///
+    fn node_aabbs(&self, max_depth: u32) -> Vec<(AABB, u32)> {
+        let root = match self.nodes.first() {
+            Some(BVHNode::Node {
+                child_l_aabb,
+                child_r_aabb,
+                ..
+            }) => child_l_aabb.join(child_r_aabb),
+            _ => return Vec::new(),
+        };
+        let mut boxes = Vec::new();
+        let mut queue = VecDeque::from([(0, root, 0)]);
+        while let Some((node_index, aabb, depth)) = queue.pop_front() {
+            boxes.push((aabb, depth));
+            if depth == max_depth {
+                continue;
+            }
+            if let BVHNode::Node {
+                child_l_index,
+                child_l_aabb,
+                child_r_index,
+                child_r_aabb,
+                ..
+            } = self.nodes[node_index]
+            {
+                queue.push_back((child_l_index, child_l_aabb, depth + 1));
+                queue.push_back((child_r_index, child_r_aabb, depth + 1));
+            }
+        }
+        boxes
+    }
+
+    /// Writes the [`AABB`]s of the nodes up to `max_depth` as wireframe boxes to the file at
+    /// `path`, in the OBJ or PLY format depending on its extension. The root has a depth of
+    /// `0`, pass `u32::MAX` to export every node including the leaves.
+    ///
+    /// Returns an error of the kind [`io::ErrorKind::InvalidInput`] for other extensions.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..100)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let bvh = BVH::build(&mut cubes);
+    /// bvh.export_wireframe("bvh.obj", 6).unwrap();
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`io::ErrorKind::InvalidInput`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html
+    ///
+    pub fn export_wireframe(&self, path: impl AsRef<Path>, max_depth: u32) -> io::Result<()> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+        let format = match extension.as_deref() {
+            Some("obj") => WireframeFormat::Obj,
+            Some("ply") => WireframeFormat::Ply,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "wireframes can only be exported to .obj and .ply files",
+                ))
+            }
+        };
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_wireframe(&mut writer, format, max_depth)?;
+        writer.flush()
+    }
+
+    /// Writes the [`AABB`]s of the nodes up to `max_depth` as wireframe boxes in the given
+    /// `format`, see [`BVH::export_wireframe`].
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH::export_wireframe`]: struct.BVH.html#method.export_wireframe
+    ///
+    pub fn write_wireframe(
+        &self,
+        writer: &mut impl Write,
+        format: WireframeFormat,
+        max_depth: u32,
+    ) -> io::Result<()> {
+        let boxes = self.node_aabbs(max_depth);
+        match format {
+            WireframeFormat::Obj => {
+                writeln!(writer, "# BVH node bounds, one group per depth")?;
+                let mut group = None;
+                for (box_index, (aabb, depth)) in boxes.iter().enumerate() {
+                    if group != Some(*depth) {
+                        writeln!(writer, "g depth_{}", depth)?;
+                        group = Some(*depth);
+                    }
+                    for corner in corners(aabb) {
+                        writeln!(writer, "v {} {} {}", corner.x, corner.y, corner.z)?;
+                    }
+                    // OBJ indices start at one.
+                    let first = box_index * 8 + 1;
+                    for (a, b) in EDGES {
+                        writeln!(writer, "l {} {}", first + a, first + b)?;
+                    }
+                }
+            }
+            WireframeFormat::Ply => {
+                writeln!(writer, "ply")?;
+                writeln!(writer, "format ascii 1.0")?;
+                writeln!(writer, "comment BVH node bounds, colored by depth")?;
+                writeln!(writer, "element vertex {}", boxes.len() * 8)?;
+                writeln!(writer, "property float x")?;
+                writeln!(writer, "property float y")?;
+                writeln!(writer, "property float z")?;
+                writeln!(writer, "property uchar red")?;
+                writeln!(writer, "property uchar green")?;
+                writeln!(writer, "property uchar blue")?;
+                writeln!(writer, "element edge {}", boxes.len() * EDGES.len())?;
+                writeln!(writer, "property int vertex1")?;
+                writeln!(writer, "property int vertex2")?;
+                writeln!(writer, "end_header")?;
+                for (aabb, depth) in &boxes {
+                    let [r, g, b] = DEPTH_COLORS[*depth as usize % DEPTH_COLORS.len()];
+                    for corner in corners(aabb) {
+                        let (x, y, z) = (corner.x, corner.y, corner.z);
+                        writeln!(writer, "{} {} {} {} {} {}", x, y, z, r, g, b)?;
+                    }
+                }
+                for box_index in 0..boxes.len() {
+                    let first = box_index * 8;
+                    for (a, b) in EDGES {
+                        writeln!(writer, "{} {}", first + a, first + b)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the number of shapes below every node, indexed like the nodes. The counts are
+    /// accumulated in one iterative post-order pass, so that deep, degenerate trees neither
+    /// overflow the stack nor are walked once per node.
+    fn shape_counts(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.nodes.len()];
+        if self.nodes.is_empty() {
+            return counts;
+        }
+        let mut stack = vec![(0, false)];
+        while let Some((node_index, children_done)) = stack.pop() {
+            match self.nodes[node_index] {
+                BVHNode::Leaf { .. } => counts[node_index] = 1,
+                BVHNode::Node {
+                    child_l_index,
+                    child_r_index,
+                    ..
+                } => {
+                    if children_done {
+                        counts[node_index] = counts[child_l_index] + counts[child_r_index];
+                    } else {
+                        stack.push((node_index, true));
+                        stack.push((child_r_index, false));
+                        stack.push((child_l_index, false));
+                    }
+                }
+            }
+        }
+        counts
+    }
+
+    /// Returns the node hierarchy as a [Graphviz](https://graphviz.org) DOT graph. Every
+    /// node is labeled with its index, depth, number of shapes and the surface area of its
+    /// [`AABB`], leaves also with the index of their shape. Render it with
+    /// `dot -Tsvg bvh.dot -o bvh.svg`.
+    ///
+    /// The root of a [`BVH`] with a single shape has no surface area, since the bounds of a
+    /// leaf are stored in its parent.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..2)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let bvh = BVH::build(&mut cubes);
+    /// let dot = bvh.to_dot();
+    /// assert!(dot.starts_with("digraph BVH {"));
+    /// assert!(dot.contains("n0 -> n1;"));
+    /// assert!(dot.contains("n1 [label=\"leaf 1\\nshape 0\\ndepth 1\\nshapes 1\\narea 6\", shape=ellipse];"));
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH`]: struct.BVH.html
+    ///
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph BVH {\n    node [shape=box, fontname=monospace];\n");
+        let root = match self.nodes.first() {
+            Some(BVHNode::Node {
+                child_l_aabb,
+                child_r_aabb,
+                ..
+            }) => Some(child_l_aabb.join(child_r_aabb)),
+            Some(BVHNode::Leaf { .. }) => None,
+            None => {
+                dot.push_str("}\n");
+                return dot;
+            }
+        };
+        let shape_counts = self.shape_counts();
+        let mut stack = vec![(0, root, 0)];
+        while let Some((node_index, aabb, depth)) = stack.pop() {
+            let area = aabb.map_or_else(String::new, |aabb| {
+                format!("\\narea {}", aabb.surface_area())
+            });
+            let shapes = shape_counts[node_index];
+            // Writing to a `String` never fails.
+            match self.nodes[node_index] {
+                BVHNode::Leaf { shape_index, .. } => {
+                    let _ = writeln!(
+                        dot,
+                        "    n{} [label=\"leaf {}\\nshape {}\\ndepth {}\\nshapes {}{}\", shape=ellipse];",
+                        node_index, node_index, shape_index, depth, shapes, area
+                    );
+                }
+                BVHNode::Node {
+                    child_l_index,
+                    child_l_aabb,
+                    child_r_index,
+                    child_r_aabb,
+                    ..
+                } => {
+                    let _ = writeln!(
+                        dot,
+                        "    n{} [label=\"node {}\\ndepth {}\\nshapes {}{}\"];",
+                        node_index, node_index, depth, shapes, area
+                    );
+                    for child_index in [child_l_index, child_r_index] {
+                        let _ = writeln!(dot, "    n{} -> n{};", node_index, child_index);
+                    }
+                    stack.push((child_r_index, Some(child_r_aabb), depth + 1));
+                    stack.push((child_l_index, Some(child_l_aabb), depth + 1));
+                }
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::AABB;
+    use crate::bvh::{BVHNode, WireframeFormat, BVH};
+    use crate::testbase::{next_point3, UnitBox};
+    use crate::Point3;
+
+    /// Returns the lines of the wireframe of `bvh`.
+    fn wireframe(bvh: &BVH, format: WireframeFormat, max_depth: u32) -> Vec<String> {
+        let mut bytes = Vec::new();
+        bvh.write_wireframe(&mut bytes, format, max_depth).unwrap();
+        String::from_utf8(bytes)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    /// Tests that the OBJ wireframe has one box per node up to the depth, grouped by depth.
+    fn test_wireframe_obj() {
+        let bounds = AABB::with_bounds(Point3::splat(-20.0), Point3::splat(20.0));
+        let mut seed = 0;
+        let mut shapes: Vec<UnitBox> = (0..50)
+            .map(|id| UnitBox::new(id, next_point3(&mut seed, &bounds)))
+            .collect();
+        let bvh = BVH::build(&mut shapes);
+
+        let count = |lines: &[String], prefix: &str| {
+            lines.iter().filter(|line| line.starts_with(prefix)).count()
+        };
+        let lines = wireframe(&bvh, WireframeFormat::Obj, u32::MAX);
+        assert_eq!(count(&lines, "v "), bvh.nodes.len() * 8);
+        assert_eq!(count(&lines, "l "), bvh.nodes.len() * 12);
+        assert_eq!(
+            lines.last().unwrap(),
+            &format!("l {} {}", bvh.nodes.len() * 8 - 4, bvh.nodes.len() * 8)
+        );
+
+        let lines = wireframe(&bvh, WireframeFormat::Obj, 1);
+        assert_eq!(count(&lines, "v "), 3 * 8);
+        assert_eq!(count(&lines, "g "), 2);
+        assert_eq!(lines[1], "g depth_0");
+        // The root box is the joint box of all shapes.
+        let root = shapes.iter().fold(AABB::empty(), |aabb, shape| {
+            aabb.join(&crate::aabb::Bounded::aabb(shape))
+        });
+        assert_eq!(
+            lines[2],
+            format!("v {} {} {}", root.min.x, root.min.y, root.min.z)
+        );
+        assert_eq!(
+            lines[9],
+            format!("v {} {} {}", root.max.x, root.max.y, root.max.z)
+        );
+    }
+
+    #[test]
+    /// Tests the header and element counts of the PLY wireframe, and the export to files.
+    fn test_wireframe_ply_export() {
+        let bounds = AABB::with_bounds(Point3::splat(-20.0), Point3::splat(20.0));
+        let mut seed = 1;
+        let mut shapes: Vec<UnitBox> = (0..10)
+            .map(|id| UnitBox::new(id, next_point3(&mut seed, &bounds)))
+            .collect();
+        let bvh = BVH::build(&mut shapes);
+
+        let lines = wireframe(&bvh, WireframeFormat::Ply, 2);
+        let boxes = 1 + 2 + 4;
+        assert!(lines.contains(&format!("element vertex {}", boxes * 8)));
+        assert!(lines.contains(&format!("element edge {}", boxes * 12)));
+        let header = lines.iter().position(|line| line == "end_header").unwrap();
+        assert_eq!(lines.len(), header + 1 + boxes * 8 + boxes * 12);
+        assert!(lines[header + 1].ends_with(" 230 25 75"));
+
+        let path = std::env::temp_dir().join(format!("bvh_wireframe_{}.ply", std::process::id()));
+        bvh.export_wireframe(&path, 2).unwrap();
+        let exported = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(exported.lines().collect::<Vec<_>>(), lines);
+
+        let error = bvh.export_wireframe("bvh.stl", 2).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(BVH::build(&mut shapes[..1]).node_aabbs(u32::MAX).is_empty());
+    }
+
+    #[test]
+    /// Tests that the DOT graph has a vertex per node and an edge per child.
+    fn test_to_dot() {
+        let bounds = AABB::with_bounds(Point3::splat(-20.0), Point3::splat(20.0));
+        let mut seed = 2;
+        let mut shapes: Vec<UnitBox> = (0..20)
+            .map(|id| UnitBox::new(id, next_point3(&mut seed, &bounds)))
+            .collect();
+        let bvh = BVH::build(&mut shapes);
+
+        let dot = bvh.to_dot();
+        let lines: Vec<&str> = dot.lines().collect();
+        assert_eq!(lines[0], "digraph BVH {");
+        assert_eq!(lines.last(), Some(&"}"));
+        let labels = lines.iter().filter(|line| line.contains("[label=")).count();
+        let edges = lines.iter().filter(|line| line.contains(" -> ")).count();
+        assert_eq!(labels, bvh.nodes.len());
+        assert_eq!(edges, bvh.nodes.len() - 1);
+        let leaves = lines.iter().filter(|line| line.contains("shape=ellipse"));
+        assert_eq!(leaves.count(), shapes.len());
+        assert!(lines[2].starts_with("    n0 [label=\"node 0\\ndepth 0\\nshapes 20\\narea "));
+
+        let single = BVH::build(&mut shapes[..1]).to_dot();
+        assert!(
+            single.contains("n0 [label=\"leaf 0\\nshape 0\\ndepth 0\\nshapes 1\", shape=ellipse];")
+        );
+        assert!(!single.contains(" -> "));
+    }
+
+    #[test]
+    /// Tests the DOT graph of a tree which degenerated into a long chain, in which every
+    /// node has a leaf as its left child.
+    fn test_to_dot_chain() {
+        let count = 100_000;
+        let aabb = AABB::with_bounds(Point3::splat(0.0), Point3::splat(1.0));
+        let mut nodes = Vec::with_capacity(2 * count - 1);
+        for k in 0..count - 1 {
+            nodes.push(BVHNode::Node {
+                parent_index: k.saturating_sub(1) * 2,
+                child_l_index: 2 * k + 1,
+                child_l_aabb: aabb,
+                child_r_index: 2 * k + 2,
+                child_r_aabb: aabb,
+            });
+            nodes.push(BVHNode::Leaf {
+                parent_index: 2 * k,
+                shape_index: k,
+            });
+        }
+        nodes.push(BVHNode::Leaf {
+            parent_index: 2 * count - 4,
+            shape_index: count - 1,
+        });
+        let bvh = BVH { nodes };
+
+        let dot = bvh.to_dot();
+        assert!(dot.contains(&format!(
+            "n0 [label=\"node 0\\ndepth 0\\nshapes {}\\narea ",
+            count
+        )));
+        assert!(dot.contains(&format!(
+            "n{} [label=\"node {}\\ndepth {}\\nshapes 2\\narea ",
+            2 * count - 4,
+            2 * count - 4,
+            count - 2
+        )));
+    }
+}
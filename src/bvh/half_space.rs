@@ -0,0 +1,207 @@
+//! Classification of the shapes in a [`BVH`] against a [`Plane`].
+//!
+//! [`BVH`]: struct.BVH.html
+//! [`Plane`]: ../plane/struct.Plane.html
+//!
+
+use crate::aabb::Bounded;
+use crate::bvh::{BVHNode, BVH};
+use crate::plane::{Plane, PlaneSide};
+
+/// The result of [`BVH::classify_plane`]. Every shape of the hierarchy ends up
+/// in exactly one of the three lists.
+///
+/// [`BVH::classify_plane`]: struct.BVH.html#method.classify_plane
+///
+#[derive(Debug)]
+pub struct PlaneClassification<'a, Shape> {
+    /// Shapes whose `AABB` lies entirely in front of the plane.
+    pub front: Vec<&'a Shape>,
+    /// Shapes whose `AABB` lies entirely behind the plane.
+    pub back: Vec<&'a Shape>,
+    /// Shapes whose `AABB` crosses or touches the plane.
+    pub straddling: Vec<&'a Shape>,
+}
+
+impl<'a, Shape> PlaneClassification<'a, Shape> {
+    fn list_mut(&mut self, side: PlaneSide) -> &mut Vec<&'a Shape> {
+        match side {
+            PlaneSide::Front => &mut self.front,
+            PlaneSide::Back => &mut self.back,
+            PlaneSide::Straddling => &mut self.straddling,
+        }
+    }
+}
+
+impl BVH {
+    /// Classifies all shapes as being in front of, behind or straddling `plane`.
+    /// The classification is based on the shapes' [`AABB`]s. Whenever the [`AABB`]
+    /// of a whole subtree lies on one side of the plane, its shapes are assigned
+    /// without testing them individually.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::plane::Plane;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.25), self.pos + Vector3::splat(0.25))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (-5..5)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 + 0.5, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let bvh = BVH::build(&mut cubes);
+    ///
+    /// let plane = Plane::new(Vector3::new(1.0, 0.0, 0.0), 0.0);
+    /// let classification = bvh.classify_plane(&plane, &cubes);
+    /// assert_eq!(classification.front.len(), 5);
+    /// assert_eq!(classification.back.len(), 5);
+    /// assert!(classification.straddling.is_empty());
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub fn classify_plane<'a, Shape: Bounded>(
+        &self,
+        plane: &Plane,
+        shapes: &'a [Shape],
+    ) -> PlaneClassification<'a, Shape> {
+        let mut classification = PlaneClassification {
+            front: Vec::new(),
+            back: Vec::new(),
+            straddling: Vec::new(),
+        };
+        match self.nodes.first() {
+            Some(BVHNode::Node { .. }) => {
+                self.classify_children(0, plane, shapes, &mut classification)
+            }
+            Some(BVHNode::Leaf { shape_index, .. }) => {
+                let shape = &shapes[*shape_index];
+                let side = plane.classify_aabb(&shape.aabb());
+                classification.list_mut(side).push(shape);
+            }
+            None => {}
+        }
+        classification
+    }
+
+    /// Classifies both children of the inner node `node_index`.
+    fn classify_children<'a, Shape: Bounded>(
+        &self,
+        node_index: usize,
+        plane: &Plane,
+        shapes: &'a [Shape],
+        classification: &mut PlaneClassification<'a, Shape>,
+    ) {
+        if let BVHNode::Node {
+            child_l_index,
+            child_l_aabb,
+            child_r_index,
+            child_r_aabb,
+            ..
+        } = self.nodes[node_index]
+        {
+            for (child_index, child_aabb) in
+                [(child_l_index, child_l_aabb), (child_r_index, child_r_aabb)]
+            {
+                match plane.classify_aabb(&child_aabb) {
+                    PlaneSide::Straddling => match self.nodes[child_index] {
+                        BVHNode::Node { .. } => {
+                            self.classify_children(child_index, plane, shapes, classification)
+                        }
+                        BVHNode::Leaf { shape_index, .. } => {
+                            classification.straddling.push(&shapes[shape_index])
+                        }
+                    },
+                    side => {
+                        self.collect_subtree(child_index, shapes, classification.list_mut(side))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Pushes all shapes below `node_index` onto `result`.
+    fn collect_subtree<'a, Shape>(
+        &self,
+        node_index: usize,
+        shapes: &'a [Shape],
+        result: &mut Vec<&'a Shape>,
+    ) {
+        match self.nodes[node_index] {
+            BVHNode::Node {
+                child_l_index,
+                child_r_index,
+                ..
+            } => {
+                self.collect_subtree(child_l_index, shapes, result);
+                self.collect_subtree(child_r_index, shapes, result);
+            }
+            BVHNode::Leaf { shape_index, .. } => result.push(&shapes[shape_index]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::Bounded;
+    use crate::bvh::BVH;
+    use crate::plane::{Plane, PlaneSide};
+    use crate::testbase::{create_n_cubes, default_bounds, generate_aligned_boxes};
+    use crate::Vector3;
+
+    #[test]
+    /// Classifies the aligned boxes against a plane which cuts through the box at the origin.
+    fn test_classify_plane_aligned_boxes() {
+        let mut boxes = generate_aligned_boxes();
+        let bvh = BVH::build(&mut boxes);
+        let plane = Plane::new(Vector3::new(1.0, 0.0, 0.0), 0.2);
+
+        let classification = bvh.classify_plane(&plane, &boxes);
+        let mut front: Vec<i32> = classification.front.iter().map(|b| b.id).collect();
+        let mut back: Vec<i32> = classification.back.iter().map(|b| b.id).collect();
+        front.sort_unstable();
+        back.sort_unstable();
+
+        assert_eq!(front, (1..11).collect::<Vec<_>>());
+        assert_eq!(back, (-10..0).collect::<Vec<_>>());
+        assert_eq!(classification.straddling.len(), 1);
+        assert_eq!(classification.straddling[0].id, 0);
+    }
+
+    #[test]
+    /// Compares the hierarchical classification with classifying every shape on its own.
+    fn test_classify_plane_matches_brute_force() {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(100, &bounds);
+        let bvh = BVH::build(&mut triangles);
+        let plane = Plane::new(Vector3::new(1.0, -2.0, 0.5), 3.0);
+
+        let classification = bvh.classify_plane(&plane, &triangles);
+        assert_eq!(
+            classification.front.len()
+                + classification.back.len()
+                + classification.straddling.len(),
+            triangles.len()
+        );
+        for (list, side) in [
+            (&classification.front, PlaneSide::Front),
+            (&classification.back, PlaneSide::Back),
+            (&classification.straddling, PlaneSide::Straddling),
+        ] {
+            for triangle in list.iter() {
+                assert_eq!(plane.classify_aabb(&triangle.aabb()), side);
+            }
+        }
+    }
+}
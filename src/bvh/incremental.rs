@@ -0,0 +1,368 @@
+//! Incremental updates of a [`BVH`] which avoid a complete rebuild when shapes are added
+//! or removed.
+//!
+//! Insertion follows "Fast, Effective BVH Updates for Animated Scenes" by Bittner et al.:
+//! a branch and bound search finds the sibling for the new leaf which increases the total
+//! surface area of the tree the least.
+//!
+//! [`BVH`]: struct.BVH.html
+//!
+
+use crate::aabb::AABB;
+use crate::bounding_hierarchy::BHShape;
+use crate::bvh::{BVHNode, BVH};
+use crate::Real;
+
+impl BVH {
+    /// Inserts the shape at `new_shape_index` into the [`BVH`]. The sibling of the new leaf
+    /// is the node which minimizes the surface area added to the tree, including the growth
+    /// of all of its ancestors. Only the ancestors of the new leaf are updated, so inserting
+    /// a shape costs roughly `O(log n)` for a well balanced tree.
+    ///
+    /// Compared to [`BVH::add_node`] this produces trees of a quality much closer to a full
+    /// build, at the price of a slightly more expensive search.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..10)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let mut bvh = BVH::build(&mut cubes);
+    ///
+    /// cubes.push(Cube { pos: Point3::new(4.0, 10.0, 0.0), node_index: 0 });
+    /// bvh.insert(&mut cubes, 10);
+    ///
+    /// let ray = Ray::new(Point3::new(4.0, -10.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+    /// assert_eq!(bvh.traverse(&ray, &cubes).len(), 2);
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::add_node`]: struct.BVH.html#method.add_node
+    ///
+    pub fn insert<Shape: BHShape>(&mut self, shapes: &mut [Shape], new_shape_index: usize) {
+        self.insert_with(shapes, new_shape_index, |shape: &Shape| shape.aabb());
+    }
+
+    /// Like [`BVH::insert`], but the bounds of every shape are provided by `shape_aabb`
+    /// instead of [`Bounded::aabb`].
+    ///
+    /// [`BVH::insert`]: struct.BVH.html#method.insert
+    /// [`Bounded::aabb`]: ../aabb/trait.Bounded.html#tymethod.aabb
+    ///
+    pub(crate) fn insert_with<Shape: BHShape>(
+        &mut self,
+        shapes: &mut [Shape],
+        new_shape_index: usize,
+        shape_aabb: impl Fn(&Shape) -> AABB,
+    ) {
+        let leaf_aabb = shape_aabb(&shapes[new_shape_index]);
+        if self.nodes.is_empty() {
+            self.nodes.push(BVHNode::Leaf {
+                parent_index: 0,
+                shape_index: new_shape_index,
+            });
+            shapes[new_shape_index].set_bh_node_index(0);
+            return;
+        }
+
+        let root_aabb = match self.nodes[0] {
+            BVHNode::Node {
+                child_l_aabb,
+                child_r_aabb,
+                ..
+            } => child_l_aabb.join(&child_r_aabb),
+            BVHNode::Leaf { shape_index, .. } => shape_aabb(&shapes[shape_index]),
+        };
+        let (sibling_index, sibling_aabb) = self.find_best_sibling(&leaf_aabb, root_aabb);
+        let leaf_index = self.nodes.len();
+        let new_parent_index = leaf_index + 1;
+
+        if sibling_index == 0 {
+            // The root has to stay at index 0, so it is moved to the end of the nodes and
+            // the root slot is reused for the new parent.
+            let old_root = self.nodes[0];
+            self.nodes.push(BVHNode::Leaf {
+                parent_index: 0,
+                shape_index: new_shape_index,
+            });
+            self.nodes.push(old_root);
+            match old_root {
+                BVHNode::Node {
+                    child_l_index,
+                    child_r_index,
+                    ..
+                } => {
+                    *self.nodes[child_l_index].parent_mut() = new_parent_index;
+                    *self.nodes[child_r_index].parent_mut() = new_parent_index;
+                }
+                BVHNode::Leaf { shape_index, .. } => {
+                    shapes[shape_index].set_bh_node_index(new_parent_index);
+                }
+            }
+            self.nodes[0] = BVHNode::Node {
+                parent_index: 0,
+                child_l_index: new_parent_index,
+                child_l_aabb: sibling_aabb,
+                child_r_index: leaf_index,
+                child_r_aabb: leaf_aabb,
+            };
+            shapes[new_shape_index].set_bh_node_index(leaf_index);
+            return;
+        }
+
+        // Replace the sibling by a new node which has the sibling and the new leaf as children.
+        let old_parent_index = self.nodes[sibling_index].parent();
+        self.nodes.push(BVHNode::Leaf {
+            parent_index: new_parent_index,
+            shape_index: new_shape_index,
+        });
+        self.nodes.push(BVHNode::Node {
+            parent_index: old_parent_index,
+            child_l_index: sibling_index,
+            child_l_aabb: sibling_aabb,
+            child_r_index: leaf_index,
+            child_r_aabb: leaf_aabb,
+        });
+        shapes[new_shape_index].set_bh_node_index(leaf_index);
+        *self.nodes[sibling_index].parent_mut() = new_parent_index;
+        if self.nodes[old_parent_index].child_l() == sibling_index {
+            *self.nodes[old_parent_index].child_l_mut() = new_parent_index;
+        } else {
+            *self.nodes[old_parent_index].child_r_mut() = new_parent_index;
+        }
+
+        // Grow the stored bounds of all ancestors by the new leaf.
+        let mut child_index = new_parent_index;
+        let mut parent_index = old_parent_index;
+        loop {
+            let aabb = if self.nodes[parent_index].child_l() == child_index {
+                self.nodes[parent_index].child_l_aabb_mut()
+            } else {
+                self.nodes[parent_index].child_r_aabb_mut()
+            };
+            *aabb = aabb.join(&leaf_aabb);
+            if parent_index == 0 {
+                break;
+            }
+            child_index = parent_index;
+            parent_index = self.nodes[parent_index].parent();
+        }
+    }
+
+    /// Removes the shape at `shape_index` from the [`BVH`] and from `shapes`. The leaf of the
+    /// shape is deleted, its parent is replaced by its sibling and the bounds of all
+    /// ancestors are refitted.
+    ///
+    /// Like [`Vec::swap_remove`], the last shape is moved into the freed slot. Returns the
+    /// removed shape and, if a shape was moved, its previous index, so that external
+    /// references to shape indices can be remapped.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..10)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let mut bvh = BVH::build(&mut cubes);
+    ///
+    /// let (removed, moved_from) = bvh.remove(&mut cubes, 2);
+    /// assert_eq!(removed.pos, Point3::new(4.0, 0.0, 0.0));
+    /// // The last cube took the place of the removed one.
+    /// assert_eq!(moved_from, Some(9));
+    /// assert_eq!(cubes[2].pos, Point3::new(18.0, 0.0, 0.0));
+    ///
+    /// let ray = Ray::new(Point3::new(4.0, -10.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+    /// assert!(bvh.traverse(&ray, &cubes).is_empty());
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`Vec::swap_remove`]: https://doc.rust-lang.org/std/vec/struct.Vec.html#method.swap_remove
+    ///
+    pub fn remove<Shape: BHShape>(
+        &mut self,
+        shapes: &mut Vec<Shape>,
+        shape_index: usize,
+    ) -> (Shape, Option<usize>) {
+        let last_index = shapes.len() - 1;
+        // Removing the node with `swap_shape` already moves the shape to the end.
+        self.remove_node(shapes, shape_index, true);
+        let shape = shapes.pop().unwrap();
+        let moved_from = if shape_index < last_index {
+            Some(last_index)
+        } else {
+            None
+        };
+        (shape, moved_from)
+    }
+
+    /// Searches for the node which, when paired with a new leaf bounded by `leaf_aabb`,
+    /// increases the surface area of the tree the least. The root does not store its own
+    /// bounds, so they are passed as `root_aabb`. Returns the index and the `AABB` of that
+    /// node.
+    fn find_best_sibling(&self, leaf_aabb: &AABB, root_aabb: AABB) -> (usize, AABB) {
+        let leaf_area = leaf_aabb.surface_area();
+
+        let mut best = (0, root_aabb);
+        let mut best_cost = root_aabb.join(leaf_aabb).surface_area();
+
+        // Every entry carries the area its ancestors grow by when the leaf is placed below it.
+        let mut stack: Vec<(usize, AABB, Real)> = vec![(0, root_aabb, 0.0)];
+        while let Some((node_index, aabb, inherited_cost)) = stack.pop() {
+            let direct_cost = aabb.join(leaf_aabb).surface_area();
+            let cost = direct_cost + inherited_cost;
+            if cost < best_cost {
+                best = (node_index, aabb);
+                best_cost = cost;
+            }
+
+            if let BVHNode::Node {
+                child_l_index,
+                child_l_aabb,
+                child_r_index,
+                child_r_aabb,
+                ..
+            } = self.nodes[node_index]
+            {
+                // Any sibling below this node costs at least the leaf's own area plus the
+                // growth of this node and its ancestors.
+                let child_inherited_cost = inherited_cost + direct_cost - aabb.surface_area();
+                if child_inherited_cost + leaf_area < best_cost {
+                    stack.push((child_r_index, child_r_aabb, child_inherited_cost));
+                    stack.push((child_l_index, child_l_aabb, child_inherited_cost));
+                }
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::{Bounded, AABB};
+    use crate::bounding_hierarchy::{BHShape, IntersectionAABB};
+    use crate::bvh::BVH;
+    use crate::testbase::UnitBox;
+    use crate::testbase::{create_n_cubes, create_ray, generate_aligned_boxes};
+    use crate::{Point3, Real};
+
+    #[test]
+    /// Builds a `BVH` by inserting shapes one by one and checks it against a brute force test.
+    fn test_insert_into_empty_bvh() {
+        let bounds = AABB::with_bounds(Point3::splat(-20.0), Point3::splat(20.0));
+        let mut triangles = create_n_cubes(100, &bounds);
+        let mut bvh = BVH { nodes: Vec::new() };
+        for i in 0..triangles.len() {
+            bvh.insert(&mut triangles, i);
+        }
+        bvh.assert_consistent(&triangles);
+        bvh.assert_tight(&triangles);
+
+        let mut seed = 0;
+        for _ in 0..100 {
+            let ray = create_ray(&mut seed, &bounds);
+            let mut expected: Vec<*const _> = triangles
+                .iter()
+                .filter(|t| ray.intersects_aabb(&t.aabb()))
+                .map(|t| t as *const _)
+                .collect();
+            let mut actual: Vec<*const _> = bvh
+                .traverse(&ray, &triangles)
+                .into_iter()
+                .map(|t| t as *const _)
+                .collect();
+            expected.sort_unstable();
+            actual.sort_unstable();
+            assert_eq!(expected, actual);
+        }
+    }
+
+    #[test]
+    /// Inserts shapes into a built `BVH`, including one which encloses the whole scene and
+    /// therefore becomes a child of the root.
+    fn test_insert_into_built_bvh() {
+        let mut shapes = generate_aligned_boxes();
+        let count = shapes.len();
+        let mut bvh = BVH::build(&mut shapes[..count - 2]);
+
+        shapes[count - 2].pos = Point3::new(0.0, 100.0, 0.0);
+        bvh.insert(&mut shapes, count - 2);
+        bvh.assert_consistent(&shapes[..count - 1]);
+        bvh.assert_tight(&shapes[..count - 1]);
+        assert_eq!(bvh.nodes[0].child_r(), shapes[count - 2].bh_node_index());
+
+        shapes[count - 1].pos = Point3::new(3.0, 0.0, 0.5);
+        bvh.insert(&mut shapes, count - 1);
+        bvh.assert_consistent(&shapes);
+        bvh.assert_tight(&shapes);
+        bvh.assert_reachable(&shapes);
+    }
+
+    #[test]
+    /// Removes shapes in a scattered order, checks that the reported remapping matches the
+    /// moved shapes and that the `BVH` stays consistent and tight.
+    fn test_remove_reports_remap() {
+        let mut shapes: Vec<UnitBox> = (0..32)
+            .map(|x| UnitBox::new(x, Point3::new(x as Real, (x % 4) as Real, 0.0)))
+            .collect();
+        let mut bvh = BVH::build(&mut shapes);
+
+        // Tracks where the shape with a given id is stored.
+        let mut index_of_id: Vec<Option<usize>> = (0..32).map(Some).collect();
+        let mut seed = 7usize;
+        while !shapes.is_empty() {
+            seed = (seed * 31 + 11) % 1009;
+            let shape_index = seed % shapes.len();
+            let last_id = shapes[shapes.len() - 1].id as usize;
+
+            let (removed, moved_from) = bvh.remove(&mut shapes, shape_index);
+            index_of_id[removed.id as usize] = None;
+            if let Some(old_index) = moved_from {
+                assert_eq!(index_of_id[last_id], Some(old_index));
+                index_of_id[last_id] = Some(shape_index);
+            }
+            for (id, index) in index_of_id.iter().enumerate() {
+                if let Some(index) = index {
+                    assert_eq!(shapes[*index].id as usize, id);
+                }
+            }
+
+            if !shapes.is_empty() {
+                bvh.assert_consistent(&shapes);
+                bvh.assert_tight(&shapes);
+                bvh.assert_reachable(&shapes);
+            }
+        }
+        assert!(bvh.nodes.is_empty());
+    }
+}
@@ -4,15 +4,18 @@ use crate::aabb::Bounded;
 use crate::bounding_hierarchy::IntersectionAABB;
 use crate::bvh::{BVHNode, BVH};
 
-/// Iterator to traverse a [`BVH`] without memory allocations
+/// Iterator to traverse a [`BVH`] without memory allocations.
+/// Yields the indices of the shapes whose [`AABB`] passes the test.
+///
+/// [`AABB`]: ../aabb/struct.AABB.html
+/// [`BVH`]: struct.BVH.html
+///
 #[allow(clippy::upper_case_acronyms)]
-pub struct BVHTraverseIterator<'bvh, 'test, 'shapes, Shape: Bounded, Test: IntersectionAABB> {
+pub struct BVHIndexIterator<'bvh, 'test, Test: IntersectionAABB> {
     /// Reference to the BVH to traverse
     bvh: &'bvh BVH,
     /// Reference to the input ray
     test: &'test Test,
-    /// Reference to the input shapes array
-    shapes: &'shapes [Shape],
     /// Traversal stack. Allocates if exceeds depth of 64
     stack: SmallVec<[usize; 64]>,
     /// Position of the iterator in bvh.nodes
@@ -21,18 +24,15 @@ pub struct BVHTraverseIterator<'bvh, 'test, 'shapes, Shape: Bounded, Test: Inter
     has_node: bool,
 }
 
-impl<'bvh, 'test, 'shapes, Shape: Bounded, Test: IntersectionAABB>
-    BVHTraverseIterator<'bvh, 'test, 'shapes, Shape, Test>
-{
-    /// Creates a new `BVHTraverseIterator`
-    pub fn new(bvh: &'bvh BVH, test: &'test Test, shapes: &'shapes [Shape]) -> Self {
-        BVHTraverseIterator {
+impl<'bvh, 'test, Test: IntersectionAABB> BVHIndexIterator<'bvh, 'test, Test> {
+    /// Creates a new `BVHIndexIterator`
+    pub fn new(bvh: &'bvh BVH, test: &'test Test) -> Self {
+        BVHIndexIterator {
             bvh,
             test,
-            shapes,
             stack: SmallVec::new(),
             node_index: 0,
-            has_node: true,
+            has_node: !bvh.nodes.is_empty(),
         }
     }
 
@@ -104,12 +104,10 @@ impl<'bvh, 'test, 'shapes, Shape: Bounded, Test: IntersectionAABB>
     }
 }
 
-impl<'bvh, 'test, 'shapes, Shape: Bounded, Test: IntersectionAABB> Iterator
-    for BVHTraverseIterator<'bvh, 'test, 'shapes, Shape, Test>
-{
-    type Item = &'shapes Shape;
+impl<'bvh, 'test, Test: IntersectionAABB> Iterator for BVHIndexIterator<'bvh, 'test, Test> {
+    type Item = usize;
 
-    fn next(&mut self) -> Option<&'shapes Shape> {
+    fn next(&mut self) -> Option<usize> {
         loop {
             if self.is_stack_empty() && !self.has_node {
                 // Completed traversal.
@@ -131,7 +129,7 @@ impl<'bvh, 'test, 'shapes, Shape: Bounded, Test: IntersectionAABB> Iterator
                         // We previously pushed a leaf node. This is the "visit" of the in-order traverse.
                         // Next time we call `next()` we try to pop the stack again.
                         self.has_node = false;
-                        return Some(&self.shapes[shape_index]);
+                        return Some(shape_index);
                     }
                 }
             }
@@ -140,6 +138,40 @@ impl<'bvh, 'test, 'shapes, Shape: Bounded, Test: IntersectionAABB> Iterator
     }
 }
 
+/// Iterator to traverse a [`BVH`] without memory allocations
+///
+/// [`BVH`]: struct.BVH.html
+///
+#[allow(clippy::upper_case_acronyms)]
+pub struct BVHTraverseIterator<'bvh, 'test, 'shapes, Shape: Bounded, Test: IntersectionAABB> {
+    /// Iterator over the indices of the hit shapes
+    indices: BVHIndexIterator<'bvh, 'test, Test>,
+    /// Reference to the input shapes array
+    shapes: &'shapes [Shape],
+}
+
+impl<'bvh, 'test, 'shapes, Shape: Bounded, Test: IntersectionAABB>
+    BVHTraverseIterator<'bvh, 'test, 'shapes, Shape, Test>
+{
+    /// Creates a new `BVHTraverseIterator`
+    pub fn new(bvh: &'bvh BVH, test: &'test Test, shapes: &'shapes [Shape]) -> Self {
+        let mut indices = BVHIndexIterator::new(bvh, test);
+        indices.has_node = bvh.root_passes(test, shapes);
+        BVHTraverseIterator { indices, shapes }
+    }
+}
+
+impl<'bvh, 'test, 'shapes, Shape: Bounded, Test: IntersectionAABB> Iterator
+    for BVHTraverseIterator<'bvh, 'test, 'shapes, Shape, Test>
+{
+    type Item = &'shapes Shape;
+
+    fn next(&mut self) -> Option<&'shapes Shape> {
+        let shapes = self.shapes;
+        self.indices.next().map(|shape_index| &shapes[shape_index])
+    }
+}
+
 // Copy of part of the BH testing in testbase.
 // TODO: Once iterators are part of the BoundingHierarchy trait we can move all this to testbase.
 #[cfg(test)]
@@ -193,6 +225,28 @@ mod tests {
         assert_eq!(expected_shapes.len(), count);
     }
 
+    fn traverse_and_verify_indices(
+        ray_origin: Point3,
+        ray_direction: Vector3,
+        all_shapes: &[UnitBox],
+        bvh: &BVH,
+        expected_shapes: &HashSet<i32>,
+    ) {
+        let ray = Ray::new(ray_origin, ray_direction);
+        let indices = bvh.traverse_indices(&ray);
+        assert_eq!(expected_shapes.len(), indices.len());
+        for index in indices {
+            assert!(expected_shapes.contains(&all_shapes[index].id));
+        }
+
+        let mut count = 0;
+        for index in bvh.traverse_indices_iterator(&ray) {
+            assert!(expected_shapes.contains(&all_shapes[index].id));
+            count += 1;
+        }
+        assert_eq!(expected_shapes.len(), count);
+    }
+
     fn traverse_and_verify_base(
         ray_origin: Point3,
         ray_direction: Vector3,
@@ -202,6 +256,7 @@ mod tests {
     ) {
         traverse_and_verify_vec(ray_origin, ray_direction, all_shapes, bvh, expected_shapes);
         traverse_and_verify_iterator(ray_origin, ray_direction, all_shapes, bvh, expected_shapes);
+        traverse_and_verify_indices(ray_origin, ray_direction, all_shapes, bvh, expected_shapes);
     }
 
     /// Perform some fixed intersection tests on BH structures.
@@ -0,0 +1,228 @@
+//! Collision layer filtering during the traversal of a [`BVH`].
+//!
+//! [`BVH`]: struct.BVH.html
+//!
+
+use crate::bounding_hierarchy::IntersectionAABB;
+use crate::bvh::{BVHNode, BVH};
+
+/// The 32-bit layer masks of all nodes of a [`BVH`]. The mask of a leaf is the mask of its
+/// shape, and the mask of an inner node is the bitwise or of the masks of its children.
+/// Created by [`BVH::layer_masks`] and used by [`BVH::traverse_masked`] to skip whole
+/// subtrees which contain no shape on the queried layers.
+///
+/// The masks refer to node indices, so they have to be recomputed whenever the topology of
+/// the [`BVH`] changes, e.g. after [`BVH::rebuild`] or [`BVH::insert`].
+///
+/// [`BVH`]: struct.BVH.html
+/// [`BVH::insert`]: struct.BVH.html#method.insert
+/// [`BVH::layer_masks`]: struct.BVH.html#method.layer_masks
+/// [`BVH::rebuild`]: struct.BVH.html#method.rebuild
+/// [`BVH::traverse_masked`]: struct.BVH.html#method.traverse_masked
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct LayerMasks {
+    masks: Vec<u32>,
+}
+
+impl LayerMasks {
+    /// Returns the combined layer mask of the subtree below `node_index`.
+    pub fn mask(&self, node_index: usize) -> u32 {
+        self.masks[node_index]
+    }
+
+    /// Changes the layer mask of the leaf at `node_index` and updates the masks of its
+    /// ancestors.
+    pub fn set_leaf_mask(&mut self, bvh: &BVH, node_index: usize, mask: u32) {
+        self.masks[node_index] = mask;
+        let mut index = node_index;
+        while index != 0 {
+            index = bvh.nodes[index].parent();
+            let node_mask = match bvh.nodes[index] {
+                BVHNode::Node {
+                    child_l_index,
+                    child_r_index,
+                    ..
+                } => self.masks[child_l_index] | self.masks[child_r_index],
+                BVHNode::Leaf { .. } => unreachable!(),
+            };
+            if self.masks[index] == node_mask {
+                break;
+            }
+            self.masks[index] = node_mask;
+        }
+    }
+}
+
+impl BVH {
+    /// Computes the [`LayerMasks`] of the [`BVH`], where `shape_mask` returns the layer
+    /// mask of the shape with a given index.
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`LayerMasks`]: struct.LayerMasks.html
+    ///
+    pub fn layer_masks(&self, mut shape_mask: impl FnMut(usize) -> u32) -> LayerMasks {
+        let mut masks = vec![0; self.nodes.len()];
+        if self.nodes.is_empty() {
+            return LayerMasks { masks };
+        }
+
+        // Post-order traversal, so that the masks of both children are known when a node
+        // is visited the second time.
+        let mut stack = vec![(0, false)];
+        while let Some((node_index, children_done)) = stack.pop() {
+            match self.nodes[node_index] {
+                BVHNode::Leaf { shape_index, .. } => masks[node_index] = shape_mask(shape_index),
+                BVHNode::Node {
+                    child_l_index,
+                    child_r_index,
+                    ..
+                } => {
+                    if children_done {
+                        masks[node_index] = masks[child_l_index] | masks[child_r_index];
+                    } else {
+                        stack.push((node_index, true));
+                        stack.push((child_r_index, false));
+                        stack.push((child_l_index, false));
+                    }
+                }
+            }
+        }
+        LayerMasks { masks }
+    }
+
+    /// Like [`BVH::traverse_indices`], but only returns shapes whose layer mask shares a bit
+    /// with `query_mask`. Subtrees without any such shape are skipped without testing their
+    /// [`AABB`]s.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, layer: u32, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// const STATIC: u32 = 1;
+    /// const DYNAMIC: u32 = 2;
+    ///
+    /// let mut cubes: Vec<Cube> = (0..10)
+    ///     .map(|x| Cube {
+    ///         pos: Point3::new(x as f32 * 2.0, 0.0, 0.0),
+    ///         layer: if x < 5 { STATIC } else { DYNAMIC },
+    ///         node_index: 0,
+    ///     })
+    ///     .collect();
+    /// let bvh = BVH::build(&mut cubes);
+    /// let masks = bvh.layer_masks(|i| cubes[i].layer);
+    ///
+    /// let ray = Ray::new(Point3::new(-1.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+    /// let mut hits = bvh.traverse_masked(&ray, &masks, DYNAMIC);
+    /// hits.sort_unstable();
+    /// assert_eq!(hits, vec![5, 6, 7, 8, 9]);
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH::traverse_indices`]: struct.BVH.html#method.traverse_indices
+    ///
+    pub fn traverse_masked(
+        &self,
+        test: &impl IntersectionAABB,
+        masks: &LayerMasks,
+        query_mask: u32,
+    ) -> Vec<usize> {
+        let mut indices = Vec::new();
+        if self.nodes.is_empty() || masks.mask(0) & query_mask == 0 {
+            return indices;
+        }
+
+        let mut stack = vec![0];
+        while let Some(node_index) = stack.pop() {
+            match self.nodes[node_index] {
+                BVHNode::Node {
+                    ref child_l_aabb,
+                    child_l_index,
+                    ref child_r_aabb,
+                    child_r_index,
+                    ..
+                } => {
+                    if masks.mask(child_r_index) & query_mask != 0
+                        && test.intersects_aabb(child_r_aabb)
+                    {
+                        stack.push(child_r_index);
+                    }
+                    if masks.mask(child_l_index) & query_mask != 0
+                        && test.intersects_aabb(child_l_aabb)
+                    {
+                        stack.push(child_l_index);
+                    }
+                }
+                BVHNode::Leaf { shape_index, .. } => indices.push(shape_index),
+            }
+        }
+        indices
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::{Bounded, AABB};
+    use crate::bounding_hierarchy::{BHShape, IntersectionAABB};
+    use crate::bvh::BVH;
+    use crate::testbase::{create_ray, next_point3, UnitBox};
+    use crate::Point3;
+
+    /// Returns the layer mask used for `shape` in the tests.
+    fn layer_of(shape: &UnitBox) -> u32 {
+        1 << (shape.id % 4)
+    }
+
+    #[test]
+    /// Compares masked traversal against filtering the results of a full traversal, also
+    /// after the mask of a leaf has changed.
+    fn test_traverse_masked_matches_filtering() {
+        let bounds = AABB::with_bounds(Point3::splat(-20.0), Point3::splat(20.0));
+        let mut seed = 0;
+        let mut shapes: Vec<UnitBox> = (0..300)
+            .map(|id| UnitBox::new(id, next_point3(&mut seed, &bounds)))
+            .collect();
+        let bvh = BVH::build(&mut shapes);
+        let mut layers: Vec<u32> = shapes.iter().map(layer_of).collect();
+        let mut masks = bvh.layer_masks(|i| layers[i]);
+        assert_eq!(masks.mask(0), 0b1111);
+
+        // Move every shape on the last layer to the first one.
+        for (i, shape) in shapes.iter().enumerate() {
+            if layers[i] == 0b1000 {
+                layers[i] = 0b0001;
+                masks.set_leaf_mask(&bvh, shape.bh_node_index(), 0b0001);
+            }
+        }
+        assert_eq!(masks, bvh.layer_masks(|i| layers[i]));
+
+        for query_mask in [0b0001, 0b0110, 0b1000, 0b1111] {
+            for _ in 0..20 {
+                let ray = create_ray(&mut seed, &bounds);
+                let mut expected: Vec<usize> = (0..shapes.len())
+                    .filter(|&i| layers[i] & query_mask != 0)
+                    .filter(|&i| ray.intersects_aabb(&shapes[i].aabb()))
+                    .collect();
+                let mut actual = bvh.traverse_masked(&ray, &masks, query_mask);
+                expected.sort_unstable();
+                actual.sort_unstable();
+                assert_eq!(expected, actual);
+            }
+        }
+    }
+}
@@ -0,0 +1,341 @@
+//! Linear [`BVH`] construction from Morton codes, with an incremental update path for
+//! shapes which move a little every step, such as particles.
+//!
+//! [`BVH`]: struct.BVH.html
+//!
+
+use crate::aabb::AABB;
+use crate::bounding_hierarchy::BHShape;
+use crate::bvh::{BVHNode, BVH};
+use crate::{Point3, Real};
+
+/// Number of bits of a Morton code used for each axis.
+const BITS_PER_AXIS: u32 = 10;
+
+/// Spreads the lowest 10 bits of `x` so that there are two zero bits between each of them.
+fn expand_bits(x: u32) -> u32 {
+    let mut x = x & 0x3ff;
+    x = (x | (x << 16)) & 0x030000ff;
+    x = (x | (x << 8)) & 0x0300f00f;
+    x = (x | (x << 4)) & 0x030c30c3;
+    x = (x | (x << 2)) & 0x09249249;
+    x
+}
+
+/// Returns the 30-bit Morton code of `point` quantized inside of `bounds`. Points outside
+/// of `bounds` are clamped to its faces.
+fn morton_code(point: &Point3, bounds: &AABB) -> u32 {
+    let size = bounds.size();
+    let scale = ((1 << BITS_PER_AXIS) - 1) as Real;
+    let mut code = 0;
+    for axis in 0..3 {
+        let relative = if size[axis] > 0.0 {
+            ((point[axis] - bounds.min[axis]) / size[axis]).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        code |= expand_bits((relative * scale) as u32) << (2 - axis);
+    }
+    code
+}
+
+/// The sorted Morton codes and the node ranges of a [`BVH`] built by [`BVH::build_lbvh`],
+/// which allow [`BVH::update_lbvh`] to patch the [`BVH`] instead of rebuilding it.
+///
+/// [`BVH`]: struct.BVH.html
+/// [`BVH::build_lbvh`]: struct.BVH.html#method.build_lbvh
+/// [`BVH::update_lbvh`]: struct.BVH.html#method.update_lbvh
+///
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct LbvhState {
+    /// The bounds in which the centroids of the shapes are quantized.
+    bounds: AABB,
+    /// Pairs of Morton code and shape index, sorted by code.
+    order: Vec<(u32, usize)>,
+    /// The range of sorted positions covered by every inner node. Inner node `k` is stored
+    /// at node index `k`, and the leaf for position `p` at node index `n - 1 + p`.
+    ranges: Vec<(usize, usize)>,
+}
+
+impl LbvhState {
+    /// Returns the bounds in which the centroids of the shapes are quantized. They are fixed
+    /// at build time, so that small movements only change a few Morton codes.
+    pub fn bounds(&self) -> AABB {
+        self.bounds
+    }
+
+    /// Returns the key of the sorted position `position`. The position breaks ties between
+    /// equal Morton codes, so that all keys are unique.
+    fn key(&self, position: usize) -> u64 {
+        ((self.order[position].0 as u64) << 32) | position as u64
+    }
+
+    /// Returns the last position of the left half when splitting `first..=last` at the
+    /// highest bit in which the keys differ.
+    fn find_split(&self, first: usize, last: usize) -> usize {
+        let first_key = self.key(first);
+        let common_prefix = (first_key ^ self.key(last)).leading_zeros();
+        let mut split = first;
+        let mut step = last - first;
+        loop {
+            step = (step + 1) >> 1;
+            let candidate = split + step;
+            if candidate < last && (first_key ^ self.key(candidate)).leading_zeros() > common_prefix
+            {
+                split = candidate;
+            }
+            if step <= 1 {
+                break;
+            }
+        }
+        split
+    }
+}
+
+impl BVH {
+    /// Creates a new [`BVH`] from the `shapes` slice by sorting the centroids of the shapes
+    /// along a Morton curve. This is much faster than the SAH build of [`BVH::build`], but
+    /// produces trees of lower quality. The returned [`LbvhState`] is used by
+    /// [`BVH::update_lbvh`] to update the [`BVH`] after the shapes have moved.
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::build`]: struct.BVH.html#method.build
+    /// [`BVH::update_lbvh`]: struct.BVH.html#method.update_lbvh
+    /// [`LbvhState`]: struct.LbvhState.html
+    ///
+    pub fn build_lbvh<Shape: BHShape>(shapes: &mut [Shape]) -> (BVH, LbvhState) {
+        let mut bounds = AABB::empty();
+        for shape in shapes.iter() {
+            bounds.grow_mut(&shape.aabb().center());
+        }
+        let mut order: Vec<(u32, usize)> = shapes
+            .iter()
+            .enumerate()
+            .map(|(index, shape)| (morton_code(&shape.aabb().center(), &bounds), index))
+            .collect();
+        order.sort_unstable();
+        let mut state = LbvhState {
+            bounds,
+            ranges: vec![(usize::MAX, usize::MAX); order.len().saturating_sub(1)],
+            order,
+        };
+
+        let placeholder = BVHNode::Leaf {
+            parent_index: 0,
+            shape_index: 0,
+        };
+        let mut bvh = BVH {
+            nodes: vec![placeholder; (2 * shapes.len()).saturating_sub(1)],
+        };
+        let changed: Vec<usize> = (0..=shapes.len()).collect();
+        bvh.emit_lbvh(&mut state, &changed, shapes);
+        bvh.refit(shapes);
+        (bvh, state)
+    }
+
+    /// Updates a [`BVH`] created by [`BVH::build_lbvh`] after the shapes have moved. The
+    /// Morton codes are recomputed and re-sorted with an insertion sort, which is close to
+    /// linear for mostly sorted input. Only the subtrees whose range of shapes changed are
+    /// rewritten; all others keep their nodes and are merely refitted. Returns the number of
+    /// rewritten inner nodes.
+    ///
+    /// The number of shapes must not change, and centroids outside of
+    /// [`LbvhState::bounds`] are clamped to it, which degrades the tree. If the shapes
+    /// travel far, the [`BVH`] should be built again.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Particle { pos: Point3, node_index: usize }
+    /// # impl Bounded for Particle {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.1), self.pos + Vector3::splat(0.1))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Particle {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut particles: Vec<Particle> = (0..100)
+    ///     .map(|i| Particle {
+    ///         pos: Point3::new((i % 10) as f32, (i / 10) as f32, 0.0),
+    ///         node_index: 0,
+    ///     })
+    ///     .collect();
+    /// let (mut bvh, mut state) = BVH::build_lbvh(&mut particles);
+    ///
+    /// for step in 0..10 {
+    ///     particles[step].pos.z += 0.5;
+    ///     bvh.update_lbvh(&mut state, &mut particles);
+    ///     bvh.assert_consistent(&particles);
+    /// }
+    ///
+    /// let ray = Ray::new(Point3::new(-1.0, 0.0, 0.5), Vector3::new(1.0, 0.0, 0.0));
+    /// assert_eq!(bvh.traverse(&ray, &particles).len(), 10);
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::build_lbvh`]: struct.BVH.html#method.build_lbvh
+    /// [`LbvhState::bounds`]: struct.LbvhState.html#method.bounds
+    ///
+    pub fn update_lbvh<Shape: BHShape>(
+        &mut self,
+        state: &mut LbvhState,
+        shapes: &mut [Shape],
+    ) -> usize {
+        assert_eq!(
+            shapes.len(),
+            state.order.len(),
+            "The number of shapes changed since the LBVH was built."
+        );
+        let old_order = state.order.clone();
+        for entry in state.order.iter_mut() {
+            entry.0 = morton_code(&shapes[entry.1].aabb().center(), &state.bounds);
+        }
+
+        // Insertion sort, which only moves the few entries that are out of place.
+        for i in 1..state.order.len() {
+            let mut j = i;
+            while j > 0 && state.order[j - 1] > state.order[j] {
+                state.order.swap(j - 1, j);
+                j -= 1;
+            }
+        }
+
+        // Prefix sums over the positions whose entry changed.
+        let mut changed = Vec::with_capacity(shapes.len() + 1);
+        changed.push(0);
+        for (old, new) in old_order.iter().zip(state.order.iter()) {
+            let count = changed.last().unwrap() + (old != new) as usize;
+            changed.push(count);
+        }
+
+        let rewritten = self.emit_lbvh(state, &changed, shapes);
+        self.refit(shapes);
+        rewritten
+    }
+
+    /// Writes the hierarchy over the sorted Morton codes of `state` into the nodes, top-down.
+    /// Subtrees which are already stored at the right place and cover no changed position
+    /// according to the prefix sums in `changed` are kept. Returns the number of written
+    /// inner nodes.
+    fn emit_lbvh<Shape: BHShape>(
+        &mut self,
+        state: &mut LbvhState,
+        changed: &[usize],
+        shapes: &mut [Shape],
+    ) -> usize {
+        let count = state.order.len();
+        if count == 0 {
+            return 0;
+        }
+
+        let mut rewritten = 0;
+        let mut stack = vec![(0, count - 1, 0, 0)];
+        while let Some((first, last, node_index, parent_index)) = stack.pop() {
+            if first == last {
+                let shape_index = state.order[first].1;
+                self.nodes[node_index] = BVHNode::Leaf {
+                    parent_index,
+                    shape_index,
+                };
+                shapes[shape_index].set_bh_node_index(node_index);
+                continue;
+            }
+
+            if changed[last + 1] == changed[first] && state.ranges[node_index] == (first, last) {
+                *self.nodes[node_index].parent_mut() = parent_index;
+                continue;
+            }
+
+            let split = state.find_split(first, last);
+            let child_l_index = if split == first {
+                count - 1 + split
+            } else {
+                split
+            };
+            let child_r_index = if split + 1 == last {
+                count + split
+            } else {
+                split + 1
+            };
+            self.nodes[node_index] = BVHNode::Node {
+                parent_index,
+                child_l_index,
+                child_l_aabb: AABB::empty(),
+                child_r_index,
+                child_r_aabb: AABB::empty(),
+            };
+            state.ranges[node_index] = (first, last);
+            rewritten += 1;
+            stack.push((split + 1, last, child_r_index, node_index));
+            stack.push((first, split, child_l_index, node_index));
+        }
+        rewritten
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::{Bounded, AABB};
+    use crate::bounding_hierarchy::IntersectionAABB;
+    use crate::bvh::BVH;
+    use crate::testbase::{create_ray, next_point3, UnitBox};
+    use crate::{Point3, Real, Vector3};
+
+    #[test]
+    /// Builds an LBVH, moves a few shapes, updates it and compares the result with a
+    /// hierarchy written from scratch for the same order.
+    fn test_update_lbvh_matches_full_emit() {
+        let bounds = AABB::with_bounds(Point3::splat(-20.0), Point3::splat(20.0));
+        let mut seed = 0;
+        let mut shapes: Vec<UnitBox> = (0..500)
+            .map(|id| UnitBox::new(id, next_point3(&mut seed, &bounds)))
+            .collect();
+        let (mut bvh, mut state) = BVH::build_lbvh(&mut shapes);
+        bvh.assert_consistent(&shapes);
+        bvh.assert_tight(&shapes);
+        assert_eq!(bvh.update_lbvh(&mut state, &mut shapes), 0);
+
+        for (i, shape) in shapes.iter_mut().enumerate().step_by(25) {
+            shape.pos += Vector3::new(0.5, -0.25, i as Real * 0.001);
+        }
+        let rewritten = bvh.update_lbvh(&mut state, &mut shapes);
+        assert!(rewritten > 0 && rewritten < shapes.len() - 1);
+        bvh.assert_consistent(&shapes);
+        bvh.assert_tight(&shapes);
+
+        let mut fresh_state = state.clone();
+        for range in fresh_state.ranges.iter_mut() {
+            *range = (usize::MAX, usize::MAX);
+        }
+        let mut fresh = bvh.clone();
+        let changed: Vec<usize> = (0..=shapes.len()).collect();
+        fresh.emit_lbvh(&mut fresh_state, &changed, &mut shapes);
+        fresh.refit(&shapes);
+        assert!(fresh.nodes == bvh.nodes);
+
+        for _ in 0..100 {
+            let ray = create_ray(&mut seed, &bounds);
+            let mut expected: Vec<i32> = shapes
+                .iter()
+                .filter(|shape| ray.intersects_aabb(&shape.aabb()))
+                .map(|shape| shape.id)
+                .collect();
+            let mut actual: Vec<i32> = bvh
+                .traverse(&ray, &shapes)
+                .iter()
+                .map(|shape| shape.id)
+                .collect();
+            expected.sort_unstable();
+            actual.sort_unstable();
+            assert_eq!(expected, actual);
+        }
+    }
+}
@@ -5,10 +5,41 @@
 
 mod best_first;
 mod bvh_impl;
+mod checked;
+mod compact;
+mod dynamic;
+mod export;
+mod half_space;
+mod incremental;
 mod iter;
+mod layers;
+mod lbvh;
+mod nearest;
 mod optimization;
+mod penetration;
+mod quality;
+mod rebuild;
+mod refit;
+mod repair;
+mod scratch;
+mod validate;
+mod visibility;
+mod volumetric;
 
 pub use self::best_first::*;
 pub use self::bvh_impl::*;
+pub use self::checked::*;
+pub use self::export::*;
+pub use self::half_space::*;
 pub use self::iter::*;
+pub use self::layers::*;
+pub use self::lbvh::*;
 pub use self::optimization::*;
+pub use self::penetration::*;
+pub use self::quality::*;
+pub use self::rebuild::*;
+pub use self::refit::*;
+pub use self::scratch::*;
+pub use self::validate::*;
+pub use self::visibility::*;
+pub use self::volumetric::*;
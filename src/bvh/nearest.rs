@@ -0,0 +1,283 @@
+//! Closest-hit style ray queries on a [`BVH`].
+//!
+//! [`BVH`]: struct.BVH.html
+//!
+
+use smallvec::SmallVec;
+
+use crate::bvh::{BVHNode, BVH};
+use crate::ray::{Intersection, IntersectionRay, Ray};
+use crate::Real;
+
+impl BVH {
+    /// Finds the `n` closest intersections of `ray` with the shapes between `t_min` and
+    /// `t_max`. The hits are returned sorted by their distance. Nodes are visited front to
+    /// back and pruned as soon as they lie behind the `n`-th closest hit found so far.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::sphere::Sphere;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Ball { sphere: Sphere, node_index: usize }
+    /// # impl Bounded for Ball {
+    /// #     fn aabb(&self) -> AABB { self.sphere.aabb() }
+    /// # }
+    /// # impl BHShape for Ball {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// # impl bvh::ray::IntersectionRay for Ball {
+    /// #     fn intersects_ray(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<bvh::ray::Intersection> {
+    /// #         self.sphere.intersects_ray(ray, t_min, t_max)
+    /// #     }
+    /// # }
+    /// let mut balls: Vec<Ball> = (1..10)
+    ///     .map(|x| Ball { sphere: Sphere::new(Point3::new(x as f32 * 3.0, 0.0, 0.0), 1.0), node_index: 0 })
+    ///     .collect();
+    /// let bvh = BVH::build(&mut balls);
+    ///
+    /// let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+    /// let hits = bvh.traverse_n_nearest(&ray, 3, 0.0, f32::INFINITY, &balls);
+    /// let distances: Vec<f32> = hits.iter().map(|(_, hit)| hit.distance).collect();
+    /// assert_eq!(distances, vec![2.0, 5.0, 8.0]);
+    /// ```
+    ///
+    pub fn traverse_n_nearest<'shapes, Shape: IntersectionRay>(
+        &self,
+        ray: &Ray,
+        n: usize,
+        t_min: Real,
+        t_max: Real,
+        shapes: &'shapes [Shape],
+    ) -> Vec<(&'shapes Shape, Intersection)> {
+        let mut hits: SmallVec<[(usize, Intersection); 8]> = SmallVec::new();
+        if n == 0 || self.nodes.is_empty() {
+            return Vec::new();
+        }
+
+        // Everything beyond the current `n`-th hit can not contribute anymore.
+        let bound = |hits: &SmallVec<[(usize, Intersection); 8]>| {
+            if hits.len() == n {
+                hits[n - 1].1.distance
+            } else {
+                t_max
+            }
+        };
+
+        let mut stack: SmallVec<[(usize, Real); 64]> = SmallVec::new();
+        stack.push((0, t_min));
+        while let Some((node_index, entry)) = stack.pop() {
+            if entry > bound(&hits) {
+                continue;
+            }
+            match self.nodes[node_index] {
+                BVHNode::Node {
+                    child_l_index,
+                    ref child_l_aabb,
+                    child_r_index,
+                    ref child_r_aabb,
+                    ..
+                } => {
+                    let limit = bound(&hits);
+                    let test = |aabb| {
+                        ray.intersects_aabb_interval(aabb)
+                            .filter(|(entry, exit)| *entry <= limit && *exit >= t_min)
+                            .map(|(entry, _)| entry)
+                    };
+                    let l = test(child_l_aabb).map(|entry| (child_l_index, entry));
+                    let r = test(child_r_aabb).map(|entry| (child_r_index, entry));
+                    // Push the farther child first, so that the closer one is visited first.
+                    match (l, r) {
+                        (Some(l), Some(r)) if l.1 < r.1 => stack.extend([r, l]),
+                        (Some(l), Some(r)) => stack.extend([l, r]),
+                        (Some(child), None) | (None, Some(child)) => stack.push(child),
+                        (None, None) => {}
+                    }
+                }
+                BVHNode::Leaf { shape_index, .. } => {
+                    let limit = bound(&hits);
+                    if let Some(hit) = shapes[shape_index].intersects_ray(ray, t_min, limit) {
+                        let position = hits
+                            .iter()
+                            .position(|(_, other)| hit.distance < other.distance)
+                            .unwrap_or(hits.len());
+                        if position < n {
+                            hits.insert(position, (shape_index, hit));
+                            hits.truncate(n);
+                        }
+                    }
+                }
+            }
+        }
+
+        hits.into_iter()
+            .map(|(shape_index, hit)| (&shapes[shape_index], hit))
+            .collect()
+    }
+
+    /// Finds the closest shape hit by `ray`, where the exact intersection test is performed
+    /// by `test`. The closure returns the distance along `ray` at which it hits the shape,
+    /// or `None` on a miss. Nodes are visited front to back and every hit tightens the
+    /// bound beyond which nodes are skipped, so any primitive that can report a hit
+    /// distance gets an efficient closest-hit loop.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::{IntersectionRay, Ray};
+    /// use bvh::sphere::Sphere;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Ball { sphere: Sphere, node_index: usize }
+    /// # impl Bounded for Ball {
+    /// #     fn aabb(&self) -> AABB { self.sphere.aabb() }
+    /// # }
+    /// # impl BHShape for Ball {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut balls: Vec<Ball> = (1..10)
+    ///     .map(|x| Ball { sphere: Sphere::new(Point3::new(x as f32 * 3.0, 0.0, 0.0), 1.0), node_index: 0 })
+    ///     .collect();
+    /// let bvh = BVH::build(&mut balls);
+    ///
+    /// let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+    /// let (ball, distance) = bvh
+    ///     .traverse_nearest_with(&ray, &balls, |ball| {
+    ///         ball.sphere.intersects_ray(&ray, 0.0, f32::INFINITY).map(|hit| hit.distance)
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(ball.sphere.center, Point3::new(3.0, 0.0, 0.0));
+    /// assert_eq!(distance, 2.0);
+    /// ```
+    ///
+    pub fn traverse_nearest_with<'shapes, Shape>(
+        &self,
+        ray: &Ray,
+        shapes: &'shapes [Shape],
+        mut test: impl FnMut(&Shape) -> Option<Real>,
+    ) -> Option<(&'shapes Shape, Real)> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut nearest: Option<(usize, Real)> = None;
+        let mut stack: SmallVec<[(usize, Real); 64]> = SmallVec::new();
+        stack.push((0, 0.0));
+        while let Some((node_index, entry)) = stack.pop() {
+            let limit = nearest.map_or(Real::INFINITY, |(_, distance)| distance);
+            if entry > limit {
+                continue;
+            }
+            match self.nodes[node_index] {
+                BVHNode::Node {
+                    child_l_index,
+                    ref child_l_aabb,
+                    child_r_index,
+                    ref child_r_aabb,
+                    ..
+                } => {
+                    let test_aabb = |aabb| {
+                        ray.intersects_aabb_interval(aabb)
+                            .filter(|(entry, _)| *entry <= limit)
+                            .map(|(entry, _)| entry)
+                    };
+                    let l = test_aabb(child_l_aabb).map(|entry| (child_l_index, entry));
+                    let r = test_aabb(child_r_aabb).map(|entry| (child_r_index, entry));
+                    // Push the farther child first, so that the closer one is visited first.
+                    match (l, r) {
+                        (Some(l), Some(r)) if l.1 < r.1 => stack.extend([r, l]),
+                        (Some(l), Some(r)) => stack.extend([l, r]),
+                        (Some(child), None) | (None, Some(child)) => stack.push(child),
+                        (None, None) => {}
+                    }
+                }
+                BVHNode::Leaf { shape_index, .. } => {
+                    if let Some(distance) = test(&shapes[shape_index]) {
+                        if distance < limit {
+                            nearest = Some((shape_index, distance));
+                        }
+                    }
+                }
+            }
+        }
+
+        nearest.map(|(shape_index, distance)| (&shapes[shape_index], distance))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::AABB;
+    use crate::bvh::BVH;
+    use crate::ray::IntersectionRay;
+    use crate::testbase::{create_n_cubes, create_ray};
+    use crate::{Point3, Real};
+
+    #[test]
+    /// Compares the `n` nearest hits with sorting all hits of a brute force test.
+    fn test_traverse_n_nearest_matches_brute_force() {
+        let bounds = AABB::with_bounds(Point3::splat(-20.0), Point3::splat(20.0));
+        let mut triangles = create_n_cubes(1000, &bounds);
+        let bvh = BVH::build(&mut triangles);
+
+        let mut seed = 0;
+        let mut rays_with_hits = 0;
+        for _ in 0..200 {
+            let ray = create_ray(&mut seed, &bounds);
+            let mut expected: Vec<Real> = triangles
+                .iter()
+                .filter_map(|t| t.intersects_ray(&ray, 0.0, Real::INFINITY))
+                .map(|hit| hit.distance)
+                .collect();
+            expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            if !expected.is_empty() {
+                rays_with_hits += 1;
+            }
+
+            for n in [1, 3, 8] {
+                let hits = bvh.traverse_n_nearest(&ray, n, 0.0, Real::INFINITY, &triangles);
+                let distances: Vec<Real> = hits.iter().map(|(_, hit)| hit.distance).collect();
+                assert_eq!(&distances[..], &expected[..n.min(expected.len())]);
+            }
+        }
+        assert!(rays_with_hits > 20);
+    }
+
+    #[test]
+    /// Compares `traverse_nearest_with` with a brute force search for the closest hit and
+    /// checks that pruning skips most of the narrow phase tests.
+    fn test_traverse_nearest_with_matches_brute_force() {
+        let bounds = AABB::with_bounds(Point3::splat(-20.0), Point3::splat(20.0));
+        let mut triangles = create_n_cubes(1000, &bounds);
+        let bvh = BVH::build(&mut triangles);
+
+        let mut seed = 0;
+        let mut total_tests = 0;
+        for _ in 0..200 {
+            let ray = create_ray(&mut seed, &bounds);
+            let expected = triangles
+                .iter()
+                .filter_map(|t| t.intersects_ray(&ray, 0.0, Real::INFINITY))
+                .map(|hit| hit.distance)
+                .fold(None, |nearest: Option<Real>, d| {
+                    Some(nearest.map_or(d, |n| n.min(d)))
+                });
+
+            let nearest = bvh.traverse_nearest_with(&ray, &triangles, |t| {
+                total_tests += 1;
+                t.intersects_ray(&ray, 0.0, Real::INFINITY)
+                    .map(|hit| hit.distance)
+            });
+            assert_eq!(nearest.map(|(_, distance)| distance), expected);
+        }
+        assert!(total_tests < 200 * triangles.len() / 10);
+    }
+}
@@ -8,9 +8,75 @@
 
 use crate::bounding_hierarchy::BHShape;
 
-use crate::{bvh::*, EPSILON};
+use crate::{bvh::*, Real, EPSILON};
 
 use log::info;
+use std::collections::VecDeque;
+
+/// A tree rotation, which swaps a child of a node with a grandchild below the other child.
+#[derive(Debug, Clone, Copy)]
+struct Rotation {
+    /// The node whose grandchildren are rearranged.
+    node_index: usize,
+    /// Whether the left child of the node is swapped with a grandchild below the right child.
+    child_is_left: bool,
+    /// Whether the swapped grandchild is the left child of its parent.
+    grandchild_is_left: bool,
+    /// By how much the rotation reduces the surface area of the tree.
+    gain: Real,
+}
+
+/// The nodes of a [`BVH`] which [`BVH::optimize_with_budget`] still has to examine for
+/// rotations. It is kept between calls, so that every call only examines nodes whose
+/// neighbourhood changed. Created by [`BVH::rotation_queue`] and filled by
+/// [`BVH::queue_rotations`].
+///
+/// [`BVH`]: struct.BVH.html
+/// [`BVH::optimize_with_budget`]: struct.BVH.html#method.optimize_with_budget
+/// [`BVH::queue_rotations`]: struct.BVH.html#method.queue_rotations
+/// [`BVH::rotation_queue`]: struct.BVH.html#method.rotation_queue
+///
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct RotationQueue {
+    /// The queued node indices, in the order in which they are examined.
+    nodes: VecDeque<usize>,
+    /// Whether the node with a given index is in `nodes`.
+    queued: Vec<bool>,
+}
+
+impl RotationQueue {
+    /// Returns the number of queued nodes.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns `true` if no node is queued, so that no rotation improves the [`BVH`].
+    ///
+    /// [`BVH`]: struct.BVH.html
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Queues `node_index`, unless it is queued already.
+    fn push(&mut self, node_index: usize) {
+        if node_index >= self.queued.len() {
+            self.queued.resize(node_index + 1, false);
+        }
+        if !self.queued[node_index] {
+            self.queued[node_index] = true;
+            self.nodes.push_back(node_index);
+        }
+    }
+
+    /// Removes the next node from the queue.
+    fn pop(&mut self) -> Option<usize> {
+        let node_index = self.nodes.pop_front()?;
+        self.queued[node_index] = false;
+        Some(node_index)
+    }
+}
 
 impl BVH {
     /// Optimizes the `BVH` by batch-reorganizing updated nodes.
@@ -490,15 +556,230 @@ impl BVH {
         // Set child's parent.
         *self.nodes[child_index].parent_mut() = parent_index;
     }
+
+    /// Creates a [`RotationQueue`] for [`BVH::optimize_with_budget`] which contains every
+    /// inner node of the [`BVH`], as needed after a build or a complete refit.
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::optimize_with_budget`]: struct.BVH.html#method.optimize_with_budget
+    /// [`RotationQueue`]: struct.RotationQueue.html
+    ///
+    pub fn rotation_queue(&self) -> RotationQueue {
+        let mut queue = RotationQueue::default();
+        for node_index in 0..self.nodes.len() {
+            if let BVHNode::Node { .. } = self.nodes[node_index] {
+                queue.push(node_index);
+            }
+        }
+        queue
+    }
+
+    /// Adds the nodes whose rotations may have changed after the shapes in `moved_indices`
+    /// were updated to `queue`. These are the ancestors of their leaves, so this costs
+    /// `O(log n)` per moved shape for a well balanced tree. Call it after
+    /// [`BVH::update_shapes`] with the same indices.
+    ///
+    /// [`BVH::update_shapes`]: struct.BVH.html#method.update_shapes
+    ///
+    pub fn queue_rotations<Shape: BHShape>(
+        &self,
+        queue: &mut RotationQueue,
+        moved_indices: &[usize],
+        shapes: &[Shape],
+    ) {
+        for &shape_index in moved_indices {
+            let mut node_index = shapes[shape_index].bh_node_index();
+            while node_index != 0 {
+                node_index = self.nodes[node_index].parent();
+                queue.push(node_index);
+            }
+        }
+    }
+
+    /// Examines up to `budget` nodes from `queue` and performs the local tree rotation which
+    /// shrinks the surface area of the tree the most at each of them. A rotation swaps a
+    /// child of a node with one of its grandchildren on the other side, as described in the
+    /// paper referenced in the module documentation. The nodes whose rotations are affected
+    /// by a performed rotation are queued again. Returns the number of performed rotations.
+    ///
+    /// The work per call is bounded by `budget` rather than by the size of the tree, so this
+    /// can be called every frame after refitting to spread the maintenance of a dynamic
+    /// scene over time. Once `queue` is empty, no rotation improves the tree anymore.
+    /// Rotations never change the bounds of the rotated node, and node indices stay valid,
+    /// so the shapes do not have to be updated.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..16)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let mut bvh = BVH::build(&mut cubes);
+    ///
+    /// for (i, cube) in cubes.iter_mut().enumerate() {
+    ///     cube.pos.x = if i % 2 == 0 { i as f32 } else { 30.0 - i as f32 };
+    /// }
+    /// bvh.refit(&cubes);
+    ///
+    /// // Spread the work over several frames.
+    /// let mut queue = bvh.rotation_queue();
+    /// while !queue.is_empty() {
+    ///     bvh.optimize_with_budget(&mut queue, 4);
+    /// }
+    /// bvh.assert_consistent(&cubes);
+    ///
+    /// // Later, only the ancestors of moved shapes have to be examined.
+    /// cubes[5].pos.y += 3.0;
+    /// bvh.update_shapes(&[5], &cubes);
+    /// bvh.queue_rotations(&mut queue, &[5], &cubes);
+    /// bvh.optimize_with_budget(&mut queue, 4);
+    /// bvh.assert_consistent(&cubes);
+    /// ```
+    ///
+    pub fn optimize_with_budget(&mut self, queue: &mut RotationQueue, budget: usize) -> usize {
+        let mut performed = 0;
+        for _ in 0..budget {
+            let node_index = match queue.pop() {
+                Some(node_index) => node_index,
+                None => break,
+            };
+            // The queue may refer to nodes which were removed since.
+            if node_index >= self.nodes.len() {
+                continue;
+            }
+            if let Some(rotation) = self.best_rotation(node_index) {
+                self.rotate(&rotation);
+                performed += 1;
+                // The rotated node and the sibling got new children, and the bounds of the
+                // sibling changed below the parent. The bounds of the rotated node did not
+                // change, so nodes further up are not affected.
+                let node = self.nodes[node_index];
+                let sibling_index = if rotation.child_is_left {
+                    node.child_r()
+                } else {
+                    node.child_l()
+                };
+                queue.push(node_index);
+                queue.push(sibling_index);
+                if node_index != 0 {
+                    queue.push(node.parent());
+                }
+            }
+        }
+        performed
+    }
+
+    /// Finds the rotation at `node_index` which reduces the surface area of the tree the most.
+    fn best_rotation(&self, node_index: usize) -> Option<Rotation> {
+        let (child_l_index, child_l_aabb, child_r_index, child_r_aabb) =
+            match self.nodes[node_index] {
+                BVHNode::Node {
+                    child_l_index,
+                    child_l_aabb,
+                    child_r_index,
+                    child_r_aabb,
+                    ..
+                } => (child_l_index, child_l_aabb, child_r_index, child_r_aabb),
+                BVHNode::Leaf { .. } => return None,
+            };
+
+        let mut best: Option<Rotation> = None;
+        for (child_is_left, child_aabb, sibling_index, sibling_aabb) in [
+            (true, child_l_aabb, child_r_index, child_r_aabb),
+            (false, child_r_aabb, child_l_index, child_l_aabb),
+        ] {
+            if let BVHNode::Node {
+                child_l_aabb: grandchild_l_aabb,
+                child_r_aabb: grandchild_r_aabb,
+                ..
+            } = self.nodes[sibling_index]
+            {
+                // The grandchild which is not swapped ends up next to the child.
+                for (grandchild_is_left, remaining_aabb) in
+                    [(true, grandchild_r_aabb), (false, grandchild_l_aabb)]
+                {
+                    let gain = sibling_aabb.surface_area()
+                        - child_aabb.join(&remaining_aabb).surface_area();
+                    if gain > best.map_or(0.0, |rotation| rotation.gain) {
+                        best = Some(Rotation {
+                            node_index,
+                            child_is_left,
+                            grandchild_is_left,
+                            gain,
+                        });
+                    }
+                }
+            }
+        }
+        best
+    }
+
+    /// Swaps the child and the grandchild described by `rotation`.
+    fn rotate(&mut self, rotation: &Rotation) {
+        let node_index = rotation.node_index;
+        let node = self.nodes[node_index];
+        let (child_index, child_aabb, sibling_index) = if rotation.child_is_left {
+            (node.child_l(), node.child_l_aabb(), node.child_r())
+        } else {
+            (node.child_r(), node.child_r_aabb(), node.child_l())
+        };
+        let sibling = self.nodes[sibling_index];
+        let (grandchild_index, grandchild_aabb) = if rotation.grandchild_is_left {
+            (sibling.child_l(), sibling.child_l_aabb())
+        } else {
+            (sibling.child_r(), sibling.child_r_aabb())
+        };
+
+        if rotation.child_is_left {
+            *self.nodes[node_index].child_l_mut() = grandchild_index;
+            *self.nodes[node_index].child_l_aabb_mut() = grandchild_aabb;
+        } else {
+            *self.nodes[node_index].child_r_mut() = grandchild_index;
+            *self.nodes[node_index].child_r_aabb_mut() = grandchild_aabb;
+        }
+        if rotation.grandchild_is_left {
+            *self.nodes[sibling_index].child_l_mut() = child_index;
+            *self.nodes[sibling_index].child_l_aabb_mut() = child_aabb;
+        } else {
+            *self.nodes[sibling_index].child_r_mut() = child_index;
+            *self.nodes[sibling_index].child_r_aabb_mut() = child_aabb;
+        }
+        *self.nodes[child_index].parent_mut() = sibling_index;
+        *self.nodes[grandchild_index].parent_mut() = node_index;
+
+        let sibling_aabb = self.nodes[sibling_index]
+            .child_l_aabb()
+            .join(&self.nodes[sibling_index].child_r_aabb());
+        if rotation.child_is_left {
+            *self.nodes[node_index].child_r_aabb_mut() = sibling_aabb;
+        } else {
+            *self.nodes[node_index].child_l_aabb_mut() = sibling_aabb;
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::aabb::Bounded;
+    use crate::aabb::{Bounded, AABB};
     use crate::bounding_hierarchy::BHShape;
-    use crate::bvh::{BVHNode, BVH};
+    use crate::bvh::{BVHNode, RotationQueue, BVH};
     use crate::testbase::{
-        build_some_bh, create_n_cubes, default_bounds, randomly_transform_scene, UnitBox,
+        build_some_bh, create_n_cubes, default_bounds, next_point3, randomly_transform_scene,
+        UnitBox,
     };
     use crate::Point3;
     use crate::EPSILON;
@@ -810,6 +1091,91 @@ mod tests {
         bvh.assert_reachable(&triangles);
     }
 
+    #[test]
+    /// Scrambles a `BVH` and lets budgeted rotations improve it until no rotation helps.
+    fn test_optimize_with_budget() {
+        let bounds = AABB::with_bounds(Point3::splat(-20.0), Point3::splat(20.0));
+        let mut seed = 0;
+        let mut shapes: Vec<UnitBox> = (0..200)
+            .map(|id| UnitBox::new(id, next_point3(&mut seed, &bounds)))
+            .collect();
+        let mut bvh = BVH::build(&mut shapes);
+        let count = shapes.len();
+        for i in 0..count / 2 {
+            let pos = shapes[i].pos;
+            shapes[i].pos = shapes[count - 1 - i].pos;
+            shapes[count - 1 - i].pos = pos;
+        }
+        bvh.refit(&shapes);
+
+        let initial_cost = bvh.sah_cost();
+        let mut cost = initial_cost;
+        let mut queue = bvh.rotation_queue();
+        let mut frames = 0;
+        while !queue.is_empty() {
+            let performed = bvh.optimize_with_budget(&mut queue, 8);
+            assert!(performed <= 8);
+            bvh.assert_consistent(&shapes);
+            bvh.assert_tight(&shapes);
+            let new_cost = bvh.sah_cost();
+            assert!(new_cost <= cost);
+            cost = new_cost;
+            frames += 1;
+            assert!(frames < 10_000);
+        }
+        assert!(frames > 1);
+        assert!(cost < initial_cost * 0.8);
+
+        // An empty queue means that no node has a rotation left.
+        for node_index in 0..bvh.nodes.len() {
+            assert!(bvh.best_rotation(node_index).is_none());
+        }
+    }
+
+    #[test]
+    /// Checks that a call examines at most `budget` nodes, and that moving a shape only
+    /// queues its ancestors instead of the whole tree.
+    fn test_optimize_with_budget_bounds_work() {
+        let bounds = AABB::with_bounds(Point3::splat(-50.0), Point3::splat(50.0));
+        let mut seed = 0;
+        let mut shapes: Vec<UnitBox> = (0..1000)
+            .map(|id| UnitBox::new(id, next_point3(&mut seed, &bounds)))
+            .collect();
+        let mut bvh = BVH::build(&mut shapes);
+        let mut queue = bvh.rotation_queue();
+        while !queue.is_empty() {
+            bvh.optimize_with_budget(&mut queue, 64);
+        }
+
+        // Without improving rotations nothing is queued again, so a call pops exactly
+        // `budget` nodes.
+        let mut queue = bvh.rotation_queue();
+        let queued = queue.len();
+        assert_eq!(queued, shapes.len() - 1);
+        assert_eq!(bvh.optimize_with_budget(&mut queue, 5), 0);
+        assert_eq!(queue.len(), queued - 5);
+
+        // After moving a shape, only the ancestors of its leaf are examined.
+        let mut queue = RotationQueue::default();
+        shapes[17].pos = Point3::new(60.0, 60.0, 60.0);
+        bvh.update_shapes(&[17], &shapes);
+        bvh.queue_rotations(&mut queue, &[17], &shapes);
+        let leaf_index = shapes[17].bh_node_index();
+        let depth = bvh.nodes[leaf_index].depth(&bvh.nodes) as usize;
+        assert_eq!(queue.len(), depth);
+        let mut calls = 0;
+        while !queue.is_empty() {
+            let before = queue.len();
+            let performed = bvh.optimize_with_budget(&mut queue, 2);
+            // Every rotation queues at most three nodes.
+            assert!(queue.len() + 2 >= before);
+            assert!(queue.len() + before.min(2) <= before + 3 * performed);
+            bvh.assert_consistent(&shapes);
+            calls += 1;
+            assert!(calls < 1000);
+        }
+    }
+
     #[test]
     fn test_optimize_bvh_12_75p() {
         let bounds = default_bounds();
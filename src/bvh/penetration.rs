@@ -0,0 +1,218 @@
+//! Penetration queries on a [`BVH`], which move a shape out of the shapes of the hierarchy.
+//!
+//! [`BVH`]: struct.BVH.html
+//!
+
+use crate::aabb::Bounded;
+use crate::bounding_hierarchy::{IntersectionAABB, Penetration, PenetrationDepth};
+use crate::bvh::BVH;
+use crate::{Vector3, EPSILON};
+
+/// The maximal number of passes of [`BVH::depenetrate`] over the penetrations.
+///
+/// [`BVH::depenetrate`]: struct.BVH.html#method.depenetrate
+///
+pub const DEPENETRATION_PASSES: usize = 8;
+
+impl BVH {
+    /// Returns all shapes which are penetrated by `query`, together with the [`Penetration`]
+    /// which moves `query` out of each of them. The hierarchy is traversed with the bounds
+    /// of `query`, so only shapes close to it are tested.
+    ///
+    /// [`Penetration`]: ../bounding_hierarchy/struct.Penetration.html
+    ///
+    pub fn penetrations<'a, Query, Shape>(
+        &'a self,
+        query: &Query,
+        shapes: &'a [Shape],
+    ) -> Vec<(&'a Shape, Penetration)>
+    where
+        Query: IntersectionAABB,
+        Shape: Bounded + PenetrationDepth<Query>,
+    {
+        self.traverse(query, shapes)
+            .into_iter()
+            .filter_map(|shape| {
+                shape
+                    .penetration(query)
+                    .map(|penetration| (shape, penetration))
+            })
+            .collect()
+    }
+
+    /// Returns a translation which moves `query` out of all shapes it penetrates, or `None`
+    /// if it does not penetrate any, e.g. to resolve the collisions of a character
+    /// controller. The translation has to move `query` at least by the depth of every
+    /// penetration along its normal. Starting with the deepest penetration, each one which
+    /// is not yet resolved adds the missing part along its normal, for up to
+    /// [`DEPENETRATION_PASSES`] passes over all of them. This is exact for a single
+    /// penetration and for normals at right angles, like a floor and a wall. Other
+    /// arrangements may need more passes or leave a small overlap, so controllers usually
+    /// move the shape and repeat the query.
+    ///
+    /// [`DEPENETRATION_PASSES`]: constant.DEPENETRATION_PASSES.html
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::{BHShape, Penetration, PenetrationDepth};
+    /// use bvh::bvh::BVH;
+    /// use bvh::sphere::Sphere;
+    /// use bvh::triangle::Triangle;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Face { triangle: Triangle, node_index: usize }
+    /// # impl Bounded for Face {
+    /// #     fn aabb(&self) -> AABB { self.triangle.aabb() }
+    /// # }
+    /// # impl BHShape for Face {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// # impl PenetrationDepth<Sphere> for Face {
+    /// #     fn penetration(&self, sphere: &Sphere) -> Option<Penetration> {
+    /// #         self.triangle.penetration(sphere)
+    /// #     }
+    /// # }
+    /// let face = |a: Point3, b: Point3, c: Point3| Face { triangle: Triangle::new(a, b, c), node_index: 0 };
+    /// // A floor at y = 0 and a wall at x = 0.
+    /// let mut faces = vec![
+    ///     face(Point3::new(-10.0, 0.0, -10.0), Point3::new(-10.0, 0.0, 10.0), Point3::new(10.0, 0.0, 0.0)),
+    ///     face(Point3::new(0.0, -10.0, -10.0), Point3::new(0.0, 10.0, 0.0), Point3::new(0.0, -10.0, 10.0)),
+    /// ];
+    /// let bvh = BVH::build(&mut faces);
+    ///
+    /// // A ball stuck in the corner.
+    /// let ball = Sphere::new(Point3::new(0.75, 0.5, 0.0), 1.0);
+    /// let translation = bvh.depenetrate(&ball, &faces).unwrap();
+    /// assert!(translation.abs_diff_eq(Vector3::new(0.25, 0.5, 0.0), 0.0001));
+    ///
+    /// let free = Sphere::new(Point3::new(2.0, 2.0, 0.0), 1.0);
+    /// assert!(bvh.depenetrate(&free, &faces).is_none());
+    /// ```
+    pub fn depenetrate<Query, Shape>(&self, query: &Query, shapes: &[Shape]) -> Option<Vector3>
+    where
+        Query: IntersectionAABB,
+        Shape: Bounded + PenetrationDepth<Query>,
+    {
+        let mut penetrations: Vec<Penetration> = self
+            .penetrations(query, shapes)
+            .into_iter()
+            .map(|(_, penetration)| penetration)
+            .collect();
+        if penetrations.is_empty() {
+            return None;
+        }
+        penetrations.sort_by(|a, b| b.depth.partial_cmp(&a.depth).unwrap());
+
+        let mut translation = Vector3::ZERO;
+        for _ in 0..DEPENETRATION_PASSES {
+            let mut resolved = true;
+            for penetration in penetrations.iter() {
+                let remaining = penetration.depth - translation.dot(penetration.normal);
+                if remaining > EPSILON {
+                    translation += penetration.normal * remaining;
+                    resolved = false;
+                }
+            }
+            if resolved {
+                break;
+            }
+        }
+        Some(translation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::AABB;
+    use crate::bounding_hierarchy::PenetrationDepth;
+    use crate::bvh::BVH;
+    use crate::capsule::Capsule;
+    use crate::sphere::Sphere;
+    use crate::testbase::{create_n_cubes, next_point3, Triangle};
+    use crate::{Point3, Real, Vector3};
+
+    #[test]
+    /// Tests that the penetrations found with the hierarchy are the ones of all triangles.
+    fn test_penetrations_match_triangles() {
+        let bounds = AABB::with_bounds(Point3::splat(-10.0), Point3::splat(10.0));
+        let mut triangles = create_n_cubes(200, &bounds);
+        let bvh = BVH::build(&mut triangles);
+
+        let mut seed = 0;
+        let mut penetrations = 0;
+        for _ in 0..200 {
+            let sphere = Sphere::new(next_point3(&mut seed, &bounds), 1.0);
+            let capsule = Capsule::new(
+                next_point3(&mut seed, &bounds),
+                next_point3(&mut seed, &bounds),
+                0.25,
+            );
+            let expected = triangles
+                .iter()
+                .filter(|triangle| triangle.penetration(&sphere).is_some())
+                .count();
+            assert_eq!(bvh.penetrations(&sphere, &triangles).len(), expected);
+            let expected = triangles
+                .iter()
+                .filter(|triangle| triangle.penetration(&capsule).is_some())
+                .count();
+            assert_eq!(bvh.penetrations(&capsule, &triangles).len(), expected);
+            penetrations += expected;
+        }
+        assert!(penetrations > 0);
+    }
+
+    #[test]
+    /// Tests that a sphere pressed into the floor and the walls of a box is moved out of all
+    /// of them.
+    fn test_depenetrate_box() {
+        // A room from (-5, 0, -5) to (5, 10, 5), with walls facing inwards.
+        let corners = |y: Real| {
+            [
+                Point3::new(-5.0, y, -5.0),
+                Point3::new(5.0, y, -5.0),
+                Point3::new(5.0, y, 5.0),
+                Point3::new(-5.0, y, 5.0),
+            ]
+        };
+        let (bottom, top) = (corners(0.0), corners(10.0));
+        let mut triangles = vec![
+            Triangle::new(bottom[0], bottom[2], bottom[1]),
+            Triangle::new(bottom[0], bottom[3], bottom[2]),
+        ];
+        for i in 0..4 {
+            let j = (i + 1) % 4;
+            triangles.push(Triangle::new(bottom[i], bottom[j], top[j]));
+            triangles.push(Triangle::new(bottom[i], top[j], top[i]));
+        }
+        let bvh = BVH::build(&mut triangles);
+
+        let free = Sphere::new(Point3::new(0.0, 5.0, 0.0), 1.0);
+        assert!(bvh.depenetrate(&free, &triangles).is_none());
+
+        let sunk = Sphere::new(Point3::new(1.0, 0.25, 2.0), 1.0);
+        let translation = bvh.depenetrate(&sunk, &triangles).unwrap();
+        assert!(translation.abs_diff_eq(Vector3::new(0.0, 0.75, 0.0), 1e-4));
+
+        // In the corner, touching the floor and two walls.
+        let cornered = Sphere::new(Point3::new(4.5, 0.5, -4.75), 1.0);
+        // The diagonal edge of the other triangle of a wall pushes a little further.
+        let translation = bvh.depenetrate(&cornered, &triangles).unwrap();
+        assert!(translation.x < -0.5 + 1e-4);
+        assert!(translation.y > 0.5 - 1e-4);
+        assert!(translation.z > 0.75 - 1e-4);
+        assert!(translation.length() < 1.2);
+        let resolved = Sphere::new(cornered.center + translation * 1.001, 1.0);
+        assert!(bvh.penetrations(&resolved, &triangles).is_empty());
+
+        let lying = Capsule::new(
+            Point3::new(-2.0, 0.5, 0.0),
+            Point3::new(2.0, -0.5, 0.0),
+            0.5,
+        );
+        let translation = bvh.depenetrate(&lying, &triangles).unwrap();
+        assert!(translation.abs_diff_eq(Vector3::new(0.0, 1.0, 0.0), 1e-4));
+    }
+}
@@ -0,0 +1,386 @@
+//! Per-subtree quality metrics of a [`BVH`], which allow scheduling maintenance such as
+//! [`BVH::repair`] or a full rebuild only where it pays off.
+//!
+//! [`BVH`]: struct.BVH.html
+//! [`BVH::repair`]: struct.BVH.html#method.repair
+//!
+
+use crate::bounding_hierarchy::BHShape;
+use crate::bvh::repair::{build_detached, overlap_ratio};
+use crate::bvh::{BVHNode, BVH};
+use crate::Real;
+
+/// Cost of visiting an inner node relative to testing a leaf, as used by the SAH costs of
+/// [`SubtreeScore`].
+///
+/// [`SubtreeScore`]: struct.SubtreeScore.html
+///
+const TRAVERSAL_COST: Real = 1.0;
+
+/// Quality metrics of the subtree below a node of a [`BVH`].
+///
+/// [`BVH`]: struct.BVH.html
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct SubtreeScore {
+    /// Expected cost of a ray query which enters the subtree, according to the surface area
+    /// heuristic. A leaf costs `1`.
+    pub sah_cost: Real,
+    /// Surface area of the intersection of the children's `AABB`s relative to their union.
+    /// Always `0` for leaves.
+    pub overlap: Real,
+}
+
+impl SubtreeScore {
+    /// The score of a leaf.
+    const LEAF: SubtreeScore = SubtreeScore {
+        sah_cost: 1.0,
+        overlap: 0.0,
+    };
+
+    /// Returns how much more expensive the subtree is than it would be after a rebuild,
+    /// given the cost returned by [`BVH::rebuilt_cost`]. A value of `1` means that a rebuild
+    /// would not improve the subtree.
+    ///
+    /// [`BVH::rebuilt_cost`]: struct.BVH.html#method.rebuilt_cost
+    ///
+    pub fn degradation(&self, rebuilt_cost: Real) -> Real {
+        self.sah_cost / rebuilt_cost
+    }
+}
+
+/// Computes the score of `node` from the scores of its children.
+fn score_node(node: &BVHNode, scores: &[SubtreeScore]) -> SubtreeScore {
+    match *node {
+        BVHNode::Leaf { .. } => SubtreeScore::LEAF,
+        BVHNode::Node {
+            child_l_index,
+            ref child_l_aabb,
+            child_r_index,
+            ref child_r_aabb,
+            ..
+        } => {
+            let area = child_l_aabb.join(child_r_aabb).surface_area();
+            // A flat node is hit exactly when its children are hit.
+            let (weight_l, weight_r) = if area > 0.0 {
+                (
+                    child_l_aabb.surface_area() / area,
+                    child_r_aabb.surface_area() / area,
+                )
+            } else {
+                (1.0, 1.0)
+            };
+            SubtreeScore {
+                sah_cost: TRAVERSAL_COST
+                    + weight_l * scores[child_l_index].sah_cost
+                    + weight_r * scores[child_r_index].sah_cost,
+                overlap: overlap_ratio(child_l_aabb, child_r_aabb),
+            }
+        }
+    }
+}
+
+/// Scores all nodes of the subtree below `root`, children before their parents.
+fn score_subtree(nodes: &[BVHNode], root: usize, scores: &mut [SubtreeScore]) {
+    let mut stack = vec![(root, false)];
+    while let Some((node_index, children_done)) = stack.pop() {
+        match nodes[node_index] {
+            BVHNode::Node {
+                child_l_index,
+                child_r_index,
+                ..
+            } if !children_done => {
+                stack.push((node_index, true));
+                stack.push((child_r_index, false));
+                stack.push((child_l_index, false));
+            }
+            ref node => scores[node_index] = score_node(node, scores),
+        }
+    }
+}
+
+impl BVH {
+    /// Computes a [`SubtreeScore`] for every node of the [`BVH`]. The returned `Vec` is
+    /// indexed like [`BVH::nodes`].
+    ///
+    /// The scores only depend on the bounds stored in the [`BVH`]. After changing the bounds
+    /// along a single path, e.g. when refitting a moved shape, they can be kept up to date
+    /// with [`BVH::update_subtree_scores`] instead of recomputing all of them.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..16)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let mut bvh = BVH::build(&mut cubes);
+    ///
+    /// // Mirror the scene, so that neighbouring leaves end up far apart.
+    /// for (i, cube) in cubes.iter_mut().enumerate() {
+    ///     cube.pos.x = if i % 2 == 0 { i as f32 } else { 30.0 - i as f32 };
+    /// }
+    /// bvh.refit(&cubes);
+    ///
+    /// let scores = bvh.subtree_scores();
+    /// let rebuilt_cost = bvh.rebuilt_cost(&mut cubes, 0);
+    /// assert!(scores[0].degradation(rebuilt_cost) > 1.5);
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::nodes`]: struct.BVH.html#structfield.nodes
+    /// [`BVH::update_subtree_scores`]: struct.BVH.html#method.update_subtree_scores
+    /// [`SubtreeScore`]: struct.SubtreeScore.html
+    ///
+    pub fn subtree_scores(&self) -> Vec<SubtreeScore> {
+        let mut scores = vec![SubtreeScore::LEAF; self.nodes.len()];
+        if !self.nodes.is_empty() {
+            score_subtree(&self.nodes, 0, &mut scores);
+        }
+        scores
+    }
+
+    /// Returns the SAH cost of the whole tree, or `0` for an empty [`BVH`].
+    ///
+    /// [`BVH`]: struct.BVH.html
+    ///
+    pub(crate) fn sah_cost(&self) -> Real {
+        self.subtree_scores()
+            .first()
+            .map_or(0.0, |score| score.sah_cost)
+    }
+
+    /// Recomputes the scores of `node_index` and all of its ancestors, assuming that the
+    /// scores of all other nodes are still valid.
+    pub fn update_subtree_scores(&self, scores: &mut [SubtreeScore], node_index: usize) {
+        let mut index = node_index;
+        loop {
+            scores[index] = score_node(&self.nodes[index], scores);
+            if index == 0 {
+                break;
+            }
+            index = self.nodes[index].parent();
+        }
+    }
+
+    /// Returns the SAH cost the subtree below `node_index` would have after rebuilding it
+    /// from scratch. This performs a full build of the subtree, but leaves the [`BVH`] and
+    /// the node indices of the shapes unchanged.
+    ///
+    /// [`BVH`]: struct.BVH.html
+    ///
+    pub fn rebuilt_cost<Shape: BHShape>(&self, shapes: &mut [Shape], node_index: usize) -> Real {
+        let (slots, mut indices) = self.subtree_slots(node_index);
+        let nodes = build_detached(shapes, &mut indices);
+        let mut scores = vec![SubtreeScore::LEAF; nodes.len()];
+        score_subtree(&nodes, 0, &mut scores);
+
+        // The build assigned the shapes to the detached nodes, point them back to the tree.
+        for slot in slots {
+            if let BVHNode::Leaf { shape_index, .. } = self.nodes[slot] {
+                shapes[shape_index].set_bh_node_index(slot);
+            }
+        }
+        scores[0].sah_cost
+    }
+}
+
+/// Tracks how much the quality of a [`BVH`] has degraded through refitting since its last
+/// build, and decides when a full rebuild pays off.
+///
+/// The quality is measured by the SAH cost of the whole tree, which grows as refitted nodes
+/// inflate and overlap. Once the cost exceeds the cost right after the last build by the
+/// configured factor, a rebuild is recommended.
+///
+/// # Examples
+/// ```
+/// use bvh::aabb::{AABB, Bounded};
+/// use bvh::bounding_hierarchy::BHShape;
+/// use bvh::bvh::{RebuildAdvisor, BVH};
+/// use bvh::{Point3, Vector3};
+///
+/// # struct Cube { pos: Point3, node_index: usize }
+/// # impl Bounded for Cube {
+/// #     fn aabb(&self) -> AABB {
+/// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+/// #     }
+/// # }
+/// # impl BHShape for Cube {
+/// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+/// #     fn bh_node_index(&self) -> usize { self.node_index }
+/// # }
+/// let mut cubes: Vec<Cube> = (0..16)
+///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+///     .collect();
+/// let mut bvh = BVH::build(&mut cubes);
+/// let mut advisor = RebuildAdvisor::new(&bvh, 1.5);
+///
+/// // Small motions are handled by refitting.
+/// cubes[0].pos.y += 0.1;
+/// assert!(!advisor.refit(&mut bvh, &mut cubes));
+///
+/// // Mirroring the scene ruins the tree, so it is rebuilt.
+/// for (i, cube) in cubes.iter_mut().enumerate() {
+///     cube.pos.x = if i % 2 == 0 { i as f32 } else { 30.0 - i as f32 };
+/// }
+/// assert!(advisor.refit(&mut bvh, &mut cubes));
+/// assert_eq!(advisor.refits_since_build(), 0);
+/// ```
+///
+/// [`BVH`]: struct.BVH.html
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct RebuildAdvisor {
+    /// Maximum ratio of the current to the original SAH cost before a rebuild is advised.
+    pub threshold: Real,
+    /// SAH cost of the tree right after the last build.
+    build_cost: Real,
+    /// Number of refits performed through the advisor since the last build.
+    refits: usize,
+}
+
+impl RebuildAdvisor {
+    /// Creates an advisor for a freshly built `bvh`, which advises a rebuild once the SAH
+    /// cost of the tree grows by more than a factor of `threshold`.
+    pub fn new(bvh: &BVH, threshold: Real) -> RebuildAdvisor {
+        RebuildAdvisor {
+            threshold,
+            build_cost: bvh.sah_cost(),
+            refits: 0,
+        }
+    }
+
+    /// Records that `bvh` has just been rebuilt and resets the accumulated degradation.
+    pub fn reset(&mut self, bvh: &BVH) {
+        self.build_cost = bvh.sah_cost();
+        self.refits = 0;
+    }
+
+    /// Returns the ratio of the current SAH cost of `bvh` to its cost after the last build.
+    pub fn degradation(&self, bvh: &BVH) -> Real {
+        if self.build_cost > 0.0 {
+            bvh.sah_cost() / self.build_cost
+        } else {
+            1.0
+        }
+    }
+
+    /// Returns `true` if the degradation of `bvh` exceeds the threshold.
+    pub fn should_rebuild(&self, bvh: &BVH) -> bool {
+        self.degradation(bvh) > self.threshold
+    }
+
+    /// Returns the number of refits performed through [`RebuildAdvisor::refit`] since the
+    /// last build.
+    ///
+    /// [`RebuildAdvisor::refit`]: struct.RebuildAdvisor.html#method.refit
+    ///
+    pub fn refits_since_build(&self) -> usize {
+        self.refits
+    }
+
+    /// Refits `bvh` to the current bounds of `shapes` and rebuilds it instead if the refitted
+    /// tree has degraded beyond the threshold. Returns `true` if the tree was rebuilt.
+    pub fn refit<Shape: BHShape>(&mut self, bvh: &mut BVH, shapes: &mut [Shape]) -> bool {
+        bvh.refit(shapes);
+        self.refits += 1;
+        if self.should_rebuild(bvh) {
+            bvh.rebuild(shapes);
+            self.reset(bvh);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::AABB;
+    use crate::bounding_hierarchy::BHShape;
+    use crate::bvh::{RebuildAdvisor, BVH};
+    use crate::testbase::{next_point3, UnitBox};
+    use crate::Point3;
+
+    #[test]
+    /// Checks that a fresh build is scored as undegraded, that scrambling the shapes
+    /// raises the degradation and that incremental updates match a full recomputation.
+    fn test_subtree_scores() {
+        let bounds = AABB::with_bounds(Point3::splat(-20.0), Point3::splat(20.0));
+        let mut seed = 0;
+        let mut shapes: Vec<UnitBox> = (0..200)
+            .map(|id| UnitBox::new(id, next_point3(&mut seed, &bounds)))
+            .collect();
+        let mut bvh = BVH::build(&mut shapes);
+
+        let scores = bvh.subtree_scores();
+        let rebuilt_cost = bvh.rebuilt_cost(&mut shapes, 0);
+        assert!((scores[0].degradation(rebuilt_cost) - 1.0).abs() < 1e-3);
+        bvh.assert_consistent(&shapes);
+
+        let count = shapes.len();
+        for i in 0..count / 2 {
+            let pos = shapes[i].pos;
+            shapes[i].pos = shapes[count - 1 - i].pos;
+            shapes[count - 1 - i].pos = pos;
+        }
+        bvh.refit(&shapes);
+        let mut scores = bvh.subtree_scores();
+        let rebuilt_cost = bvh.rebuilt_cost(&mut shapes, 0);
+        assert!(scores[0].degradation(rebuilt_cost) > 1.5);
+        bvh.assert_consistent(&shapes);
+
+        // Move a single shape, refit and update only its path.
+        shapes[3].pos = Point3::new(30.0, 30.0, 30.0);
+        bvh.refit(&shapes);
+        let parent = bvh.nodes[shapes[3].bh_node_index()].parent();
+        bvh.update_subtree_scores(&mut scores, parent);
+        assert_eq!(scores, bvh.subtree_scores());
+    }
+
+    #[test]
+    /// Lets shapes drift apart over many frames and checks that the advisor triggers a
+    /// rebuild at some point, after which the tree is undegraded again.
+    fn test_rebuild_advisor_triggers() {
+        let bounds = AABB::with_bounds(Point3::splat(-20.0), Point3::splat(20.0));
+        let mut seed = 0;
+        let mut shapes: Vec<UnitBox> = (0..200)
+            .map(|id| UnitBox::new(id, next_point3(&mut seed, &bounds)))
+            .collect();
+        let velocities: Vec<Point3> = (0..shapes.len())
+            .map(|_| next_point3(&mut seed, &bounds) * 0.05)
+            .collect();
+        let mut bvh = BVH::build(&mut shapes);
+        let mut advisor = RebuildAdvisor::new(&bvh, 1.3);
+        assert_eq!(advisor.degradation(&bvh), 1.0);
+
+        let mut rebuilds = 0;
+        for _ in 0..50 {
+            for (shape, velocity) in shapes.iter_mut().zip(velocities.iter()) {
+                shape.pos += *velocity;
+            }
+            if advisor.refit(&mut bvh, &mut shapes) {
+                rebuilds += 1;
+                assert_eq!(advisor.refits_since_build(), 0);
+            }
+            assert!(advisor.degradation(&bvh) <= advisor.threshold);
+            bvh.assert_consistent(&shapes);
+        }
+        assert!(rebuilds > 0);
+    }
+}
@@ -0,0 +1,254 @@
+//! Double-buffered rebuilding of a [`BVH`] on a background thread.
+//!
+//! Targets without threads, like `wasm32-unknown-unknown` without the `atomics` target
+//! feature, build the new [`BVH`] right away on the calling thread instead.
+//!
+//! [`BVH`]: struct.BVH.html
+//!
+
+#[cfg(not(all(target_arch = "wasm32", not(target_feature = "atomics"))))]
+use std::thread::{self, JoinHandle};
+
+use crate::aabb::{Bounded, AABB};
+use crate::bounding_hierarchy::BHShape;
+use crate::bvh::BVH;
+
+/// The bounds of a shape at the time a background rebuild was started.
+struct SnapshotShape {
+    aabb: AABB,
+    node_index: usize,
+}
+
+impl Bounded for SnapshotShape {
+    fn aabb(&self) -> AABB {
+        self.aabb
+    }
+}
+
+impl BHShape for SnapshotShape {
+    fn set_bh_node_index(&mut self, index: usize) {
+        self.node_index = index;
+    }
+
+    fn bh_node_index(&self) -> usize {
+        self.node_index
+    }
+}
+
+/// The new [`BVH`] and the node indices of the shapes in it.
+///
+/// [`BVH`]: struct.BVH.html
+///
+type Rebuilt = (BVH, Vec<usize>);
+
+/// The state of a rebuild, see [`RebuildHandle`].
+///
+/// [`RebuildHandle`]: struct.RebuildHandle.html
+///
+enum Build {
+    /// The build is running on this thread.
+    #[cfg(not(all(target_arch = "wasm32", not(target_feature = "atomics"))))]
+    Thread(JoinHandle<Rebuilt>),
+    /// The build ran on the calling thread, because the target has no threads.
+    #[cfg(all(target_arch = "wasm32", not(target_feature = "atomics")))]
+    Done(Rebuilt),
+}
+
+/// A [`BVH`] which is being built on a background thread, created by
+/// [`BVH::rebuild_async`]. The old [`BVH`] can keep serving queries until the new one is
+/// swapped in with [`RebuildHandle::try_swap`] or [`RebuildHandle::swap`].
+///
+/// [`BVH`]: struct.BVH.html
+/// [`BVH::rebuild_async`]: struct.BVH.html#method.rebuild_async
+/// [`RebuildHandle::swap`]: struct.RebuildHandle.html#method.swap
+/// [`RebuildHandle::try_swap`]: struct.RebuildHandle.html#method.try_swap
+///
+pub struct RebuildHandle {
+    /// The build. `None` once its result has been swapped in.
+    build: Option<Build>,
+    /// The number of shapes in the snapshot.
+    shape_count: usize,
+}
+
+impl RebuildHandle {
+    /// Returns `true` if the new [`BVH`] is ready to be swapped in without blocking.
+    ///
+    /// [`BVH`]: struct.BVH.html
+    ///
+    pub fn is_ready(&self) -> bool {
+        match self.build {
+            #[cfg(not(all(target_arch = "wasm32", not(target_feature = "atomics"))))]
+            Some(Build::Thread(ref thread)) => thread.is_finished(),
+            #[cfg(all(target_arch = "wasm32", not(target_feature = "atomics")))]
+            Some(Build::Done(_)) => true,
+            None => false,
+        }
+    }
+
+    /// Replaces `bvh` by the new [`BVH`] if it is ready, and returns whether it was swapped.
+    /// Returns `false` if the build is still running or its result has already been used.
+    ///
+    /// The new [`BVH`] was built from the bounds of the shapes at the time the rebuild was
+    /// started, so it is refitted to the current bounds of `shapes` while swapping. The node
+    /// indices of `shapes` are updated to point into the new [`BVH`].
+    ///
+    /// # Panics
+    /// Panics if the number of shapes changed since the rebuild was started, or if the
+    /// build thread panicked.
+    ///
+    /// [`BVH`]: struct.BVH.html
+    ///
+    pub fn try_swap<Shape: BHShape>(&mut self, bvh: &mut BVH, shapes: &mut [Shape]) -> bool {
+        if !self.is_ready() {
+            return false;
+        }
+        self.finish(bvh, shapes);
+        true
+    }
+
+    /// Waits for the new [`BVH`] to be built and replaces `bvh` by it, like
+    /// [`RebuildHandle::try_swap`].
+    ///
+    /// # Panics
+    /// Panics if the number of shapes changed since the rebuild was started, or if the
+    /// build thread panicked.
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`RebuildHandle::try_swap`]: struct.RebuildHandle.html#method.try_swap
+    ///
+    pub fn swap<Shape: BHShape>(mut self, bvh: &mut BVH, shapes: &mut [Shape]) {
+        self.finish(bvh, shapes);
+    }
+
+    /// Joins the build thread, if there is one, and swaps its result into `bvh`.
+    fn finish<Shape: BHShape>(&mut self, bvh: &mut BVH, shapes: &mut [Shape]) {
+        let build = match self.build.take() {
+            Some(build) => build,
+            None => return,
+        };
+        assert_eq!(
+            shapes.len(),
+            self.shape_count,
+            "The number of shapes changed during the rebuild."
+        );
+        let (new_bvh, node_indices) = match build {
+            #[cfg(not(all(target_arch = "wasm32", not(target_feature = "atomics"))))]
+            Build::Thread(thread) => thread.join().expect("The rebuild thread panicked."),
+            #[cfg(all(target_arch = "wasm32", not(target_feature = "atomics")))]
+            Build::Done(rebuilt) => rebuilt,
+        };
+        for (shape, node_index) in shapes.iter_mut().zip(node_indices) {
+            shape.set_bh_node_index(node_index);
+        }
+        *bvh = new_bvh;
+        bvh.refit(shapes);
+    }
+}
+
+impl BVH {
+    /// Starts building a new [`BVH`] for `shapes` on a background thread. Only a snapshot
+    /// of the shapes' bounds is sent to the thread, so the shapes and the old [`BVH`] can
+    /// be used and modified while the build is running, as long as no shapes are added
+    /// or removed.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..10)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let mut bvh = BVH::build(&mut cubes);
+    ///
+    /// let mut handle = BVH::rebuild_async(&cubes);
+    /// let ray = Ray::new(Point3::new(-1.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+    /// while !handle.try_swap(&mut bvh, &mut cubes) {
+    ///     // The old tree keeps answering queries in the meantime.
+    ///     assert_eq!(bvh.traverse(&ray, &cubes).len(), 10);
+    /// }
+    /// bvh.assert_consistent(&cubes);
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    ///
+    pub fn rebuild_async<Shape: Bounded>(shapes: &[Shape]) -> RebuildHandle {
+        let mut snapshot: Vec<SnapshotShape> = shapes
+            .iter()
+            .map(|shape| SnapshotShape {
+                aabb: shape.aabb(),
+                node_index: 0,
+            })
+            .collect();
+        let shape_count = snapshot.len();
+        let rebuild = move || {
+            let bvh = BVH::build(&mut snapshot);
+            let node_indices = snapshot.iter().map(|shape| shape.node_index).collect();
+            (bvh, node_indices)
+        };
+        #[cfg(not(all(target_arch = "wasm32", not(target_feature = "atomics"))))]
+        let build = Build::Thread(thread::spawn(rebuild));
+        #[cfg(all(target_arch = "wasm32", not(target_feature = "atomics")))]
+        let build = Build::Done(rebuild());
+        RebuildHandle {
+            build: Some(build),
+            shape_count,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::AABB;
+    use crate::bvh::BVH;
+    use crate::testbase::{next_point3, UnitBox};
+    use crate::Point3;
+
+    #[test]
+    /// Moves shapes while a rebuild is running and checks that the swapped in `BVH` matches
+    /// the current shapes and improves on the refitted old tree.
+    fn test_rebuild_async_with_concurrent_updates() {
+        let bounds = AABB::with_bounds(Point3::splat(-20.0), Point3::splat(20.0));
+        let mut seed = 0;
+        let mut shapes: Vec<UnitBox> = (0..500)
+            .map(|id| UnitBox::new(id, next_point3(&mut seed, &bounds)))
+            .collect();
+        let mut bvh = BVH::build(&mut shapes);
+
+        // Scramble the scene, which makes the refitted tree much worse than a rebuild.
+        let count = shapes.len();
+        for i in 0..count / 2 {
+            let pos = shapes[i].pos;
+            shapes[i].pos = shapes[count - 1 - i].pos;
+            shapes[count - 1 - i].pos = pos;
+        }
+        bvh.refit(&shapes);
+        let refitted_cost = bvh.sah_cost();
+
+        let handle = BVH::rebuild_async(&shapes);
+        // Keep moving some shapes while the build runs.
+        for shape in shapes.iter_mut().step_by(10) {
+            shape.pos.y += 0.25;
+        }
+        bvh.refit(&shapes);
+        bvh.assert_consistent(&shapes);
+
+        handle.swap(&mut bvh, &mut shapes);
+        bvh.assert_consistent(&shapes);
+        bvh.assert_tight(&shapes);
+        assert!(bvh.sah_cost() < refitted_cost);
+    }
+}
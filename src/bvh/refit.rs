@@ -0,0 +1,472 @@
+//! Bottom-up refitting of the [`AABB`]s stored in a [`BVH`] after its shapes have moved.
+//!
+//! [`AABB`]: ../aabb/struct.AABB.html
+//! [`BVH`]: struct.BVH.html
+//!
+
+use crate::aabb::{Bounded, AABB};
+use crate::bounding_hierarchy::BHShape;
+use crate::bvh::{BVHNode, BVH};
+use crate::Real;
+
+/// Number of nodes which are merged per iteration by [`BVH::refit_scheduled`].
+///
+/// [`BVH::refit_scheduled`]: struct.BVH.html#method.refit_scheduled
+///
+const LANES: usize = 8;
+
+/// The inner nodes of a [`BVH`] grouped by their depth, as used by
+/// [`BVH::refit_scheduled`]. Created by [`BVH::refit_schedule`], it only depends on the
+/// topology of the [`BVH`] and can be reused for every refit until the next rebuild.
+///
+/// [`BVH`]: struct.BVH.html
+/// [`BVH::refit_schedule`]: struct.BVH.html#method.refit_schedule
+/// [`BVH::refit_scheduled`]: struct.BVH.html#method.refit_scheduled
+///
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct RefitSchedule {
+    /// The inner nodes of every level, starting with the root.
+    levels: Vec<Vec<usize>>,
+    /// Pairs of leaf node index and shape index.
+    leaves: Vec<(usize, usize)>,
+}
+
+/// The bounds of [`LANES`] nodes in structure of arrays form, so that merging them compiles
+/// to vectorized min and max instructions.
+///
+/// [`LANES`]: constant.LANES.html
+///
+struct AABBLanes {
+    min: [[Real; LANES]; 3],
+    max: [[Real; LANES]; 3],
+}
+
+impl AABBLanes {
+    /// Gathers the bounds of `nodes` from `aabbs`. Unused lanes are left empty.
+    fn gather(aabbs: &[AABB], nodes: impl Iterator<Item = usize>) -> AABBLanes {
+        let empty = AABB::empty();
+        let mut lanes = AABBLanes {
+            min: [[empty.min.x; LANES]; 3],
+            max: [[empty.max.x; LANES]; 3],
+        };
+        for (lane, node_index) in nodes.enumerate() {
+            let aabb = &aabbs[node_index];
+            for axis in 0..3 {
+                lanes.min[axis][lane] = aabb.min[axis];
+                lanes.max[axis][lane] = aabb.max[axis];
+            }
+        }
+        lanes
+    }
+
+    /// Merges `other` into `self`, lane by lane.
+    fn join_mut(&mut self, other: &AABBLanes) {
+        for axis in 0..3 {
+            for lane in 0..LANES {
+                self.min[axis][lane] = self.min[axis][lane].min(other.min[axis][lane]);
+                self.max[axis][lane] = self.max[axis][lane].max(other.max[axis][lane]);
+            }
+        }
+    }
+
+    /// Returns the bounds stored in `lane`.
+    fn get(&self, lane: usize) -> AABB {
+        let mut aabb = AABB::empty();
+        for axis in 0..3 {
+            aabb.min[axis] = self.min[axis][lane];
+            aabb.max[axis] = self.max[axis][lane];
+        }
+        aabb
+    }
+}
+
+impl BVH {
+    /// Recomputes all [`AABB`]s of the [`BVH`] from the current bounds of `shapes` without
+    /// changing its topology. The merged bounds are propagated from the leaves up to the root.
+    ///
+    /// This is much cheaper than [`BVH::rebuild`] for scenes in which shapes move a little
+    /// every frame, but the quality of the tree degrades if shapes travel far from their
+    /// original neighbours.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..10)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let mut bvh = BVH::build(&mut cubes);
+    ///
+    /// // Lift every cube up by 5 units.
+    /// for cube in cubes.iter_mut() {
+    ///     cube.pos.y += 5.0;
+    /// }
+    /// bvh.refit(&cubes);
+    ///
+    /// let ray = Ray::new(Point3::new(-1.0, 5.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+    /// assert_eq!(bvh.traverse(&ray, &cubes).len(), 10);
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::rebuild`]: struct.BVH.html#method.rebuild
+    ///
+    pub fn refit<Shape: Bounded>(&mut self, shapes: &[Shape]) {
+        self.refit_with(|shape_index| shapes[shape_index].aabb());
+    }
+
+    /// Like [`BVH::refit`], but the bounds of the shape with a given index are provided by
+    /// `shape_aabb`. This allows refitting with enlarged or swept bounds.
+    ///
+    /// [`BVH::refit`]: struct.BVH.html#method.refit
+    ///
+    pub fn refit_with(&mut self, mut shape_aabb: impl FnMut(usize) -> AABB) {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        // Iterative post-order traversal. `aabbs` holds the bounds of the finished subtrees,
+        // so once both children of a node are done their bounds are on top of it.
+        let mut stack = vec![(0, false)];
+        let mut aabbs: Vec<AABB> = Vec::new();
+        while let Some((node_index, children_done)) = stack.pop() {
+            match self.nodes[node_index] {
+                BVHNode::Leaf { shape_index, .. } => aabbs.push(shape_aabb(shape_index)),
+                BVHNode::Node {
+                    child_l_index,
+                    child_r_index,
+                    ..
+                } => {
+                    if children_done {
+                        let r_aabb = aabbs.pop().unwrap();
+                        let l_aabb = aabbs.pop().unwrap();
+                        *self.nodes[node_index].child_l_aabb_mut() = l_aabb;
+                        *self.nodes[node_index].child_r_aabb_mut() = r_aabb;
+                        aabbs.push(l_aabb.join(&r_aabb));
+                    } else {
+                        stack.push((node_index, true));
+                        stack.push((child_r_index, false));
+                        stack.push((child_l_index, false));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Refits only the leaves of the shapes in `moved_indices` and their ancestors, instead
+    /// of the whole tree like [`BVH::refit`]. Propagation towards the root stops as soon as
+    /// the bounds of a node did not change, so the cost depends on the number of moved
+    /// shapes rather than on the size of the tree.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..10)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let mut bvh = BVH::build(&mut cubes);
+    ///
+    /// cubes[2].pos.y += 5.0;
+    /// cubes[7].pos.y += 5.0;
+    /// bvh.update_shapes(&[2, 7], &cubes);
+    ///
+    /// let ray = Ray::new(Point3::new(-1.0, 5.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+    /// assert_eq!(bvh.traverse(&ray, &cubes).len(), 2);
+    /// ```
+    ///
+    /// [`BVH::refit`]: struct.BVH.html#method.refit
+    ///
+    pub fn update_shapes<Shape: BHShape>(&mut self, moved_indices: &[usize], shapes: &[Shape]) {
+        self.update_shapes_with(moved_indices, shapes, |shape: &Shape| shape.aabb());
+    }
+
+    /// Like [`BVH::update_shapes`], but the bounds of every moved shape are provided by
+    /// `shape_aabb` instead of [`Bounded::aabb`].
+    ///
+    /// [`BVH::update_shapes`]: struct.BVH.html#method.update_shapes
+    /// [`Bounded::aabb`]: ../aabb/trait.Bounded.html#tymethod.aabb
+    ///
+    pub(crate) fn update_shapes_with<Shape: BHShape>(
+        &mut self,
+        moved_indices: &[usize],
+        shapes: &[Shape],
+        shape_aabb: impl Fn(&Shape) -> AABB,
+    ) {
+        for &shape_index in moved_indices {
+            let mut node_index = shapes[shape_index].bh_node_index();
+            let mut aabb = shape_aabb(&shapes[shape_index]);
+            // The root does not store its own bounds.
+            while node_index != 0 {
+                let parent_index = self.nodes[node_index].parent();
+                let parent = &mut self.nodes[parent_index];
+                let stored_aabb = if parent.child_l() == node_index {
+                    parent.child_l_aabb_mut()
+                } else {
+                    parent.child_r_aabb_mut()
+                };
+                if *stored_aabb == aabb {
+                    break;
+                }
+                *stored_aabb = aabb;
+                aabb = parent.child_l_aabb().join(&parent.child_r_aabb());
+                node_index = parent_index;
+            }
+        }
+    }
+
+    /// Creates the [`RefitSchedule`] for [`BVH::refit_scheduled`] by grouping the nodes
+    /// of the [`BVH`] by their depth.
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::refit_scheduled`]: struct.BVH.html#method.refit_scheduled
+    /// [`RefitSchedule`]: struct.RefitSchedule.html
+    ///
+    pub fn refit_schedule(&self) -> RefitSchedule {
+        let mut schedule = RefitSchedule {
+            levels: Vec::new(),
+            leaves: Vec::new(),
+        };
+        if self.nodes.is_empty() {
+            return schedule;
+        }
+
+        let mut level = vec![0];
+        while !level.is_empty() {
+            let mut next_level = Vec::new();
+            let mut inner_nodes = Vec::new();
+            for node_index in level {
+                match self.nodes[node_index] {
+                    BVHNode::Leaf { shape_index, .. } => {
+                        schedule.leaves.push((node_index, shape_index))
+                    }
+                    BVHNode::Node {
+                        child_l_index,
+                        child_r_index,
+                        ..
+                    } => {
+                        inner_nodes.push(node_index);
+                        next_level.push(child_l_index);
+                        next_level.push(child_r_index);
+                    }
+                }
+            }
+            if !inner_nodes.is_empty() {
+                schedule.levels.push(inner_nodes);
+            }
+            level = next_level;
+        }
+        schedule
+    }
+
+    /// Does the same as [`BVH::refit`], but processes the [`BVH`] level by level, from the
+    /// deepest level up to the root, instead of in depth-first order. The bounds of several
+    /// nodes of a level are merged at once in vectorized form, which makes refitting very
+    /// large trees considerably faster. The `schedule` has to be created by
+    /// [`BVH::refit_schedule`] after the last change to the topology of the [`BVH`].
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..10)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let mut bvh = BVH::build(&mut cubes);
+    /// let schedule = bvh.refit_schedule();
+    ///
+    /// for frame in 1..=3 {
+    ///     for cube in cubes.iter_mut() {
+    ///         cube.pos.y += 5.0;
+    ///     }
+    ///     bvh.refit_scheduled(&schedule, &cubes);
+    ///
+    ///     let y = frame as f32 * 5.0;
+    ///     let ray = Ray::new(Point3::new(-1.0, y, 0.0), Vector3::new(1.0, 0.0, 0.0));
+    ///     assert_eq!(bvh.traverse(&ray, &cubes).len(), 10);
+    /// }
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::refit`]: struct.BVH.html#method.refit
+    /// [`BVH::refit_schedule`]: struct.BVH.html#method.refit_schedule
+    ///
+    pub fn refit_scheduled<Shape: Bounded>(&mut self, schedule: &RefitSchedule, shapes: &[Shape]) {
+        let mut aabbs = vec![AABB::empty(); self.nodes.len()];
+        for &(node_index, shape_index) in schedule.leaves.iter() {
+            aabbs[node_index] = shapes[shape_index].aabb();
+        }
+
+        for level in schedule.levels.iter().rev() {
+            for chunk in level.chunks(LANES) {
+                let child_l = |&node_index: &usize| self.nodes[node_index].child_l();
+                let child_r = |&node_index: &usize| self.nodes[node_index].child_r();
+                let l_lanes = AABBLanes::gather(&aabbs, chunk.iter().map(child_l));
+                let r_lanes = AABBLanes::gather(&aabbs, chunk.iter().map(child_r));
+                let mut joined = AABBLanes {
+                    min: l_lanes.min,
+                    max: l_lanes.max,
+                };
+                joined.join_mut(&r_lanes);
+
+                for (lane, &node_index) in chunk.iter().enumerate() {
+                    let node = &mut self.nodes[node_index];
+                    *node.child_l_aabb_mut() = l_lanes.get(lane);
+                    *node.child_r_aabb_mut() = r_lanes.get(lane);
+                    aabbs[node_index] = joined.get(lane);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::{Bounded, AABB};
+    use crate::bounding_hierarchy::{BHShape, IntersectionAABB};
+    use crate::bvh::{BVHNode, BVH};
+    use crate::testbase::{create_n_cubes, create_ray, generate_aligned_boxes, Triangle};
+    use crate::{Point3, Real, Vector3};
+
+    #[test]
+    /// Moves all shapes, refits the `BVH` and checks that it is tight and traverses correctly.
+    fn test_refit_after_moving_shapes() {
+        let mut shapes = generate_aligned_boxes();
+        let mut bvh = BVH::build(&mut shapes);
+        let nodes_before = bvh.nodes.clone();
+
+        for (i, shape) in shapes.iter_mut().enumerate() {
+            shape.pos += Vector3::new(0.0, (i % 3) as Real * 2.0, -(i as Real));
+        }
+        bvh.refit(&shapes);
+
+        // The topology must not change.
+        assert_eq!(bvh.nodes.len(), nodes_before.len());
+        for (before, after) in nodes_before.iter().zip(bvh.nodes.iter()) {
+            assert_eq!(before.parent(), after.parent());
+            assert_eq!(before.shape_index(), after.shape_index());
+        }
+        bvh.assert_consistent(&shapes);
+        bvh.assert_tight(&shapes);
+
+        let bounds = AABB::with_bounds(Point3::splat(-25.0), Point3::splat(25.0));
+        let mut seed = 0;
+        for _ in 0..100 {
+            let ray = create_ray(&mut seed, &bounds);
+            let mut expected: Vec<i32> = shapes
+                .iter()
+                .filter(|shape| ray.intersects_aabb(&shape.aabb()))
+                .map(|shape| shape.id)
+                .collect();
+            let mut actual: Vec<i32> = bvh
+                .traverse(&ray, &shapes)
+                .iter()
+                .map(|shape| shape.id)
+                .collect();
+            expected.sort_unstable();
+            actual.sort_unstable();
+            assert_eq!(expected, actual);
+        }
+    }
+
+    #[test]
+    /// Moves a subset of the shapes and checks that `update_shapes` produces exactly the
+    /// same nodes as a full refit.
+    fn test_update_shapes_matches_refit() {
+        let bounds = AABB::with_bounds(Point3::splat(-20.0), Point3::splat(20.0));
+        let mut triangles = create_n_cubes(100, &bounds);
+        let mut bvh = BVH::build(&mut triangles);
+
+        let moved: Vec<usize> = (0..triangles.len()).step_by(7).collect();
+        for &i in moved.iter() {
+            let offset = Vector3::new(i as Real * 0.01, -3.0, 1.0);
+            let t = triangles[i];
+            let node_index = t.bh_node_index();
+            triangles[i] = Triangle::new(t.a + offset, t.b + offset, t.c + offset);
+            triangles[i].set_bh_node_index(node_index);
+        }
+
+        let mut refitted = bvh.clone();
+        refitted.refit(&triangles);
+        bvh.update_shapes(&moved, &triangles);
+        for (node, expected) in bvh.nodes.iter().zip(refitted.nodes.iter()) {
+            if let BVHNode::Node { .. } = node {
+                assert_eq!(node.child_l_aabb(), expected.child_l_aabb());
+                assert_eq!(node.child_r_aabb(), expected.child_r_aabb());
+            }
+        }
+        bvh.assert_consistent(&triangles);
+        bvh.assert_tight(&triangles);
+    }
+
+    #[test]
+    /// Checks that the level ordered refit produces exactly the same nodes as `refit`.
+    fn test_refit_scheduled_matches_refit() {
+        let bounds = AABB::with_bounds(Point3::splat(-20.0), Point3::splat(20.0));
+        let mut triangles = create_n_cubes(100, &bounds);
+        let mut bvh = BVH::build(&mut triangles);
+        let schedule = bvh.refit_schedule();
+
+        for (i, t) in triangles.iter_mut().enumerate() {
+            let offset = Vector3::new(0.0, (i % 5) as Real, -(i as Real) * 0.1);
+            let node_index = t.bh_node_index();
+            *t = Triangle::new(t.a + offset, t.b + offset, t.c + offset);
+            t.set_bh_node_index(node_index);
+        }
+
+        let mut refitted = bvh.clone();
+        refitted.refit(&triangles);
+        bvh.refit_scheduled(&schedule, &triangles);
+        for (node, expected) in bvh.nodes.iter().zip(refitted.nodes.iter()) {
+            if let BVHNode::Node { .. } = node {
+                assert_eq!(node.child_l_aabb(), expected.child_l_aabb());
+                assert_eq!(node.child_r_aabb(), expected.child_r_aabb());
+            }
+        }
+        bvh.assert_consistent(&triangles);
+        bvh.assert_tight(&triangles);
+    }
+}
@@ -0,0 +1,247 @@
+//! Selective rebuilding of the subtrees of a [`BVH`] whose quality has degraded, e.g.
+//! after many calls to [`BVH::refit`].
+//!
+//! [`BVH`]: struct.BVH.html
+//! [`BVH::refit`]: struct.BVH.html#method.refit
+//!
+
+use std::mem::MaybeUninit;
+use std::slice;
+
+use crate::aabb::AABB;
+use crate::bounding_hierarchy::BHShape;
+use crate::bvh::{BVHNode, BVH};
+use crate::utils::joint_aabb_of_shapes;
+use crate::Real;
+
+/// Returns the surface area shared by `a` and `b` relative to the surface area of their
+/// union. A freshly built [`BVH`] rarely has children that overlap by much, while refitting
+/// after motion makes siblings grow into each other.
+///
+/// [`BVH`]: struct.BVH.html
+///
+pub(crate) fn overlap_ratio(a: &AABB, b: &AABB) -> Real {
+    let union_area = a.join(b).surface_area();
+    if union_area <= 0.0 {
+        return 0.0;
+    }
+    let min = a.min.max(b.min);
+    let max = a.max.min(b.max);
+    if max.x < min.x || max.y < min.y || max.z < min.z {
+        return 0.0;
+    }
+    AABB::with_bounds(min, max).surface_area() / union_area
+}
+
+/// Builds a separate tree over the shapes in `indices` with the regular SAH build. The root
+/// of the returned nodes is at index `0`. The node indices of the shapes are overwritten.
+pub(crate) fn build_detached<Shape: BHShape>(
+    shapes: &mut [Shape],
+    indices: &mut [usize],
+) -> Vec<BVHNode> {
+    let node_count = indices.len() * 2 - 1;
+    let mut nodes: Vec<BVHNode> = Vec::with_capacity(node_count);
+    let uninit_slice = unsafe {
+        slice::from_raw_parts_mut(nodes.as_mut_ptr() as *mut MaybeUninit<BVHNode>, node_count)
+    };
+    let (aabb, centroid) = joint_aabb_of_shapes(indices, shapes);
+    BVHNode::build(shapes, indices, uninit_slice, 0, 0, 0, aabb, centroid);
+    unsafe {
+        nodes.set_len(node_count);
+    }
+    nodes
+}
+
+impl BVH {
+    /// Rebuilds every subtree whose children overlap by more than `threshold`, measured as
+    /// the surface area of the intersection of the children's [`AABB`]s relative to the
+    /// surface area of their union. The search starts at the root, so a flagged subtree is
+    /// rebuilt as a whole and not inspected any further. Nodes with two leaves as children
+    /// are never rebuilt, as there is nothing to reorganize. Returns the number of rebuilt
+    /// subtrees.
+    ///
+    /// Rebuilt subtrees reuse the node slots they occupied before, so the indices of all
+    /// other nodes stay valid. The bounds stored in the [`BVH`] are expected to be up to date,
+    /// which means that [`BVH::refit`] should be called first if shapes have moved.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..16)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let mut bvh = BVH::build(&mut cubes);
+    /// assert_eq!(bvh.repair(&mut cubes, 0.5), 0);
+    ///
+    /// // Mirror the scene, so that every subtree now spans the whole row of cubes.
+    /// for (i, cube) in cubes.iter_mut().enumerate() {
+    ///     cube.pos.x = if i % 2 == 0 { i as f32 } else { 30.0 - i as f32 };
+    /// }
+    /// bvh.refit(&cubes);
+    /// assert!(bvh.repair(&mut cubes, 0.5) > 0);
+    /// bvh.assert_consistent(&cubes);
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::refit`]: struct.BVH.html#method.refit
+    ///
+    pub fn repair<Shape: BHShape>(&mut self, shapes: &mut [Shape], threshold: Real) -> usize {
+        if self.nodes.is_empty() {
+            return 0;
+        }
+
+        let mut rebuilt = 0;
+        let mut stack = vec![0];
+        while let Some(node_index) = stack.pop() {
+            if let BVHNode::Node {
+                child_l_index,
+                ref child_l_aabb,
+                child_r_index,
+                ref child_r_aabb,
+                ..
+            } = self.nodes[node_index]
+            {
+                let has_inner_child = matches!(self.nodes[child_l_index], BVHNode::Node { .. })
+                    || matches!(self.nodes[child_r_index], BVHNode::Node { .. });
+                if has_inner_child && overlap_ratio(child_l_aabb, child_r_aabb) > threshold {
+                    self.rebuild_subtree(shapes, node_index);
+                    rebuilt += 1;
+                } else {
+                    stack.push(child_r_index);
+                    stack.push(child_l_index);
+                }
+            }
+        }
+        rebuilt
+    }
+
+    /// Returns the indices of all nodes in the subtree below `node_index`, starting with
+    /// `node_index` itself, and the indices of the shapes referenced by its leaves.
+    pub(crate) fn subtree_slots(&self, node_index: usize) -> (Vec<usize>, Vec<usize>) {
+        let mut slots = Vec::new();
+        let mut indices = Vec::new();
+        let mut stack = vec![node_index];
+        while let Some(index) = stack.pop() {
+            slots.push(index);
+            match self.nodes[index] {
+                BVHNode::Node {
+                    child_l_index,
+                    child_r_index,
+                    ..
+                } => {
+                    stack.push(child_r_index);
+                    stack.push(child_l_index);
+                }
+                BVHNode::Leaf { shape_index, .. } => indices.push(shape_index),
+            }
+        }
+        (slots, indices)
+    }
+
+    /// Rebuilds the subtree below `node_index` with the regular SAH build. The new nodes are
+    /// written into the slots of the old subtree, and `node_index` remains its root.
+    pub(crate) fn rebuild_subtree<Shape: BHShape>(
+        &mut self,
+        shapes: &mut [Shape],
+        node_index: usize,
+    ) {
+        let (slots, mut indices) = self.subtree_slots(node_index);
+        let parent_index = self.nodes[node_index].parent();
+        let new_nodes = build_detached(shapes, &mut indices);
+
+        // Move the new nodes into the old slots, translating all node indices.
+        for (new_index, mut node) in new_nodes.into_iter().enumerate() {
+            let slot = slots[new_index];
+            match node {
+                BVHNode::Node {
+                    ref mut parent_index,
+                    ref mut child_l_index,
+                    ref mut child_r_index,
+                    ..
+                } => {
+                    *parent_index = slots[*parent_index];
+                    *child_l_index = slots[*child_l_index];
+                    *child_r_index = slots[*child_r_index];
+                }
+                BVHNode::Leaf {
+                    ref mut parent_index,
+                    shape_index,
+                } => {
+                    *parent_index = slots[*parent_index];
+                    shapes[shape_index].set_bh_node_index(slot);
+                }
+            }
+            self.nodes[slot] = node;
+        }
+        *self.nodes[node_index].parent_mut() = parent_index;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::{Bounded, AABB};
+    use crate::bounding_hierarchy::IntersectionAABB;
+    use crate::bvh::BVH;
+    use crate::testbase::{create_ray, next_point3, UnitBox};
+    use crate::Point3;
+
+    #[test]
+    /// Scrambles the shapes of a `BVH`, refits and repairs it, and checks that the repaired
+    /// tree is consistent and traverses correctly.
+    fn test_repair_after_scrambling() {
+        let bounds = AABB::with_bounds(Point3::splat(-20.0), Point3::splat(20.0));
+        let mut seed = 0;
+        let mut shapes: Vec<UnitBox> = (0..200)
+            .map(|id| UnitBox::new(id, next_point3(&mut seed, &bounds)))
+            .collect();
+        let mut bvh = BVH::build(&mut shapes);
+        assert_eq!(bvh.repair(&mut shapes, 0.5), 0);
+
+        // Swap the positions of shapes from opposite ends of the slice.
+        let count = shapes.len();
+        for i in 0..count / 2 {
+            let pos = shapes[i].pos;
+            shapes[i].pos = shapes[count - 1 - i].pos;
+            shapes[count - 1 - i].pos = pos;
+        }
+        bvh.refit(&shapes);
+        let node_count = bvh.nodes.len();
+
+        assert!(bvh.repair(&mut shapes, 0.5) > 0);
+        assert_eq!(bvh.nodes.len(), node_count);
+        bvh.assert_consistent(&shapes);
+        bvh.assert_tight(&shapes);
+
+        for _ in 0..100 {
+            let ray = create_ray(&mut seed, &bounds);
+            let mut expected: Vec<i32> = shapes
+                .iter()
+                .filter(|shape| ray.intersects_aabb(&shape.aabb()))
+                .map(|shape| shape.id)
+                .collect();
+            let mut actual: Vec<i32> = bvh
+                .traverse(&ray, &shapes)
+                .iter()
+                .map(|shape| shape.id)
+                .collect();
+            expected.sort_unstable();
+            actual.sort_unstable();
+            assert_eq!(expected, actual);
+        }
+    }
+}
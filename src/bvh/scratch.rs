@@ -0,0 +1,229 @@
+//! Reusable traversal state for allocation free queries on a [`BVH`].
+//!
+//! [`BVH`]: struct.BVH.html
+//!
+
+use std::slice;
+
+use crate::aabb::Bounded;
+use crate::bounding_hierarchy::IntersectionAABB;
+use crate::bvh::{BVHNode, BVH};
+
+/// Buffers used by [`BVH::traverse_with_scratch`]. Create it once and pass it into every
+/// query; after the first few queries the buffers have grown to their working size and
+/// traversal no longer allocates.
+///
+/// [`BVH::traverse_with_scratch`]: struct.BVH.html#method.traverse_with_scratch
+///
+#[derive(Debug, Default, Clone)]
+pub struct TraversalScratch {
+    /// Stack of node indices which still have to be visited.
+    pub(crate) stack: Vec<usize>,
+    /// Shape indices found by the last query.
+    pub(crate) indices: Vec<usize>,
+}
+
+impl TraversalScratch {
+    /// Creates empty scratch buffers.
+    pub fn new() -> TraversalScratch {
+        TraversalScratch::default()
+    }
+
+    /// Creates scratch buffers which can hold a traversal stack of `stack_capacity` nodes
+    /// and `result_capacity` hits without reallocating.
+    pub fn with_capacity(stack_capacity: usize, result_capacity: usize) -> TraversalScratch {
+        TraversalScratch {
+            stack: Vec::with_capacity(stack_capacity),
+            indices: Vec::with_capacity(result_capacity),
+        }
+    }
+}
+
+/// Iterator over the shapes found by [`BVH::traverse_with_scratch`].
+///
+/// [`BVH::traverse_with_scratch`]: struct.BVH.html#method.traverse_with_scratch
+///
+pub struct ScratchHits<'scratch, 'shapes, Shape> {
+    indices: slice::Iter<'scratch, usize>,
+    shapes: &'shapes [Shape],
+}
+
+impl<'scratch, 'shapes, Shape> Iterator for ScratchHits<'scratch, 'shapes, Shape> {
+    type Item = &'shapes Shape;
+
+    fn next(&mut self) -> Option<&'shapes Shape> {
+        self.indices.next().map(|index| &self.shapes[*index])
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.indices.size_hint()
+    }
+}
+
+impl<'scratch, 'shapes, Shape> ExactSizeIterator for ScratchHits<'scratch, 'shapes, Shape> {}
+
+impl BVH {
+    /// Collects the indices of all shapes whose [`AABB`] passes `test` into `scratch.indices`.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub(crate) fn fill_scratch(
+        &self,
+        test: &impl IntersectionAABB,
+        scratch: &mut TraversalScratch,
+    ) {
+        let TraversalScratch { stack, indices } = scratch;
+        stack.clear();
+        indices.clear();
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        stack.push(0);
+        while let Some(node_index) = stack.pop() {
+            match self.nodes[node_index] {
+                BVHNode::Node {
+                    child_l_index,
+                    ref child_l_aabb,
+                    child_r_index,
+                    ref child_r_aabb,
+                    ..
+                } => {
+                    // Push the right child first so that the left subtree is visited first.
+                    if test.intersects_aabb(child_r_aabb) {
+                        stack.push(child_r_index);
+                    }
+                    if test.intersects_aabb(child_l_aabb) {
+                        stack.push(child_l_index);
+                    }
+                }
+                BVHNode::Leaf { shape_index, .. } => {
+                    indices.push(shape_index);
+                }
+            }
+        }
+    }
+
+    /// Traverses the [`BVH`] like [`BVH::traverse`], but keeps the traversal stack and the
+    /// results in `scratch` instead of allocating them for every query.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::{TraversalScratch, BVH};
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..10)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let bvh = BVH::build(&mut cubes);
+    ///
+    /// let mut scratch = TraversalScratch::new();
+    /// for x in 0..10 {
+    ///     let ray = Ray::new(Point3::new(x as f32 * 2.0, -10.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+    ///     let hits: Vec<_> = bvh.traverse_with_scratch(&ray, &cubes, &mut scratch).collect();
+    ///     assert_eq!(hits.len(), 1);
+    /// }
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::traverse`]: struct.BVH.html#method.traverse
+    ///
+    pub fn traverse_with_scratch<'scratch, 'shapes, Shape: Bounded>(
+        &self,
+        test: &impl IntersectionAABB,
+        shapes: &'shapes [Shape],
+        scratch: &'scratch mut TraversalScratch,
+    ) -> ScratchHits<'scratch, 'shapes, Shape> {
+        self.fill_scratch(test, scratch);
+        if !self.root_passes(test, shapes) {
+            scratch.indices.clear();
+        }
+        ScratchHits {
+            indices: scratch.indices.iter(),
+            shapes,
+        }
+    }
+
+    /// Traverses the [`BVH`] like [`BVH::traverse_indices`], but keeps the traversal stack
+    /// and the results in `scratch`. The returned slice is overwritten by the next query.
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::traverse_indices`]: struct.BVH.html#method.traverse_indices
+    ///
+    pub fn traverse_indices_with_scratch<'scratch>(
+        &self,
+        test: &impl IntersectionAABB,
+        scratch: &'scratch mut TraversalScratch,
+    ) -> &'scratch [usize] {
+        self.fill_scratch(test, scratch);
+        &scratch.indices
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bvh::{TraversalScratch, BVH};
+    use crate::testbase::{create_n_cubes, create_ray, default_bounds};
+
+    #[test]
+    /// Compares the results of `traverse_with_scratch` with the results of `traverse` while
+    /// reusing the same scratch buffers for all rays.
+    fn test_traverse_with_scratch_matches_traverse() {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(500, &bounds);
+        let bvh = BVH::build(&mut triangles);
+
+        let mut scratch = TraversalScratch::new();
+        let mut seed = 0;
+        for _ in 0..100 {
+            let ray = create_ray(&mut seed, &bounds);
+            let mut expected: Vec<_> = bvh
+                .traverse(&ray, &triangles)
+                .iter()
+                .map(|t| *t as *const _)
+                .collect();
+            let mut actual: Vec<_> = bvh
+                .traverse_with_scratch(&ray, &triangles, &mut scratch)
+                .map(|t| t as *const _)
+                .collect();
+            expected.sort_unstable();
+            actual.sort_unstable();
+            assert_eq!(expected, actual);
+
+            let mut expected_indices = bvh.traverse_indices(&ray);
+            let mut actual_indices = bvh
+                .traverse_indices_with_scratch(&ray, &mut scratch)
+                .to_vec();
+            expected_indices.sort_unstable();
+            actual_indices.sort_unstable();
+            assert_eq!(expected_indices, actual_indices);
+        }
+    }
+
+    #[test]
+    /// Traversing an empty `BVH` yields nothing.
+    fn test_traverse_with_scratch_empty() {
+        let mut triangles = create_n_cubes(0, &default_bounds());
+        let bvh = BVH::build(&mut triangles);
+        let ray = create_ray(&mut 0, &default_bounds());
+        let mut scratch = TraversalScratch::new();
+        assert_eq!(
+            bvh.traverse_with_scratch(&ray, &triangles, &mut scratch)
+                .count(),
+            0
+        );
+    }
+}
@@ -0,0 +1,419 @@
+//! Checking the invariants of a [`BVH`] with detailed errors, e.g. after a sequence of
+//! incremental updates.
+//!
+//! [`BVH`]: struct.BVH.html
+//!
+
+use std::fmt;
+
+use crate::aabb::AABB;
+use crate::bounding_hierarchy::BHShape;
+use crate::bvh::{BVHNode, BVH};
+use crate::EPSILON;
+
+/// A violated invariant of a [`BVH`], as found by [`BVH::validate`].
+///
+/// [`BVH`]: struct.BVH.html
+/// [`BVH::validate`]: struct.BVH.html#method.validate
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BVHValidationError {
+    /// A child index of `node` points beyond the end of the nodes.
+    IndexOutOfRange {
+        /// The index of the node.
+        node: usize,
+    },
+    /// The parent index of `node` does not point to the node which references it.
+    WrongParent {
+        /// The index of the node.
+        node: usize,
+        /// The index of the node which references it.
+        parent: usize,
+    },
+    /// The [`AABB`] of `node` is not contained in the [`AABB`] of its parent.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    BoundsNotContained {
+        /// The index of the node.
+        node: usize,
+        /// The index of the parent node.
+        parent: usize,
+    },
+    /// The [`AABB`] of the shape is not contained in the bounds of the leaf `node`.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    ShapeNotContained {
+        /// The index of the leaf.
+        node: usize,
+        /// The index of the shape.
+        shape_index: usize,
+    },
+    /// The node is referenced by more than one node.
+    SharedNode {
+        /// The index of the node.
+        node: usize,
+    },
+    /// The node can not be reached from the root.
+    OrphanedNode {
+        /// The index of the node.
+        node: usize,
+    },
+    /// The leaf `node` references a shape which does not exist.
+    ShapeOutOfRange {
+        /// The index of the leaf.
+        node: usize,
+        /// The referenced shape index.
+        shape_index: usize,
+    },
+    /// The node index stored in the shape is not the index of the leaf `node` which
+    /// references it.
+    ShapeIndexMismatch {
+        /// The index of the leaf.
+        node: usize,
+        /// The index of the shape.
+        shape_index: usize,
+    },
+    /// The shape is referenced by more than one leaf.
+    DuplicateShape {
+        /// The index of the shape.
+        shape_index: usize,
+    },
+    /// The shape is not referenced by any leaf, so it can never be found.
+    MissingShape {
+        /// The index of the shape.
+        shape_index: usize,
+    },
+}
+
+impl fmt::Display for BVHValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BVHValidationError::IndexOutOfRange { node } => {
+                write!(f, "node {} points beyond the end of the nodes", node)
+            }
+            BVHValidationError::WrongParent { node, parent } => write!(
+                f,
+                "node {} does not point back to its parent {}",
+                node, parent
+            ),
+            BVHValidationError::BoundsNotContained { node, parent } => write!(
+                f,
+                "the bounds of node {} are not contained in its parent {}",
+                node, parent
+            ),
+            BVHValidationError::ShapeNotContained { node, shape_index } => write!(
+                f,
+                "the bounds of shape {} are not contained in its leaf {}",
+                shape_index, node
+            ),
+            BVHValidationError::SharedNode { node } => {
+                write!(f, "node {} is referenced more than once", node)
+            }
+            BVHValidationError::OrphanedNode { node } => {
+                write!(f, "node {} can not be reached from the root", node)
+            }
+            BVHValidationError::ShapeOutOfRange { node, shape_index } => write!(
+                f,
+                "node {} references the nonexistent shape {}",
+                node, shape_index
+            ),
+            BVHValidationError::ShapeIndexMismatch { node, shape_index } => write!(
+                f,
+                "shape {} does not store the index of its leaf {}",
+                shape_index, node
+            ),
+            BVHValidationError::DuplicateShape { shape_index } => {
+                write!(f, "shape {} is referenced more than once", shape_index)
+            }
+            BVHValidationError::MissingShape { shape_index } => {
+                write!(f, "shape {} is not referenced", shape_index)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BVHValidationError {}
+
+impl BVH {
+    /// Checks that every node points back to the node which references it, that the
+    /// bounds of every node contain the bounds of its children and shapes, that every node
+    /// is reachable from the root exactly once, and that each shape is referenced by
+    /// exactly one leaf whose index it stores. Returns the first violated invariant.
+    ///
+    /// Unlike [`BVH::is_consistent`] this tells which node is broken, and unlike
+    /// [`BVH::assert_consistent`] it does not panic, so it can be used to check a tree
+    /// after a sequence of [`BVH::add_node`] and [`BVH::remove_node`] calls.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::{BVHValidationError, BVH};
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..10)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let mut bvh = BVH::build(&mut cubes);
+    /// assert_eq!(bvh.validate(&cubes), Ok(()));
+    ///
+    /// // Moving a shape without refitting leaves it outside of its leaf.
+    /// cubes[3].pos.y += 5.0;
+    /// assert_eq!(
+    ///     bvh.validate(&cubes),
+    ///     Err(BVHValidationError::ShapeNotContained { node: cubes[3].node_index, shape_index: 3 })
+    /// );
+    /// bvh.refit(&cubes);
+    /// assert_eq!(bvh.validate(&cubes), Ok(()));
+    /// ```
+    ///
+    /// [`BVH::add_node`]: struct.BVH.html#method.add_node
+    /// [`BVH::assert_consistent`]: struct.BVH.html#method.assert_consistent
+    /// [`BVH::is_consistent`]: struct.BVH.html#method.is_consistent
+    /// [`BVH::remove_node`]: struct.BVH.html#method.remove_node
+    ///
+    pub fn validate<Shape: BHShape>(&self, shapes: &[Shape]) -> Result<(), BVHValidationError> {
+        let mut visited = vec![false; self.nodes.len()];
+        let mut referenced = vec![false; shapes.len()];
+        // The root is not referenced by any node, and its bounds are not stored.
+        let mut stack: Vec<(usize, usize, Option<AABB>)> = Vec::new();
+        if !self.nodes.is_empty() {
+            stack.push((0, 0, None));
+        }
+        while let Some((node, parent, aabb)) = stack.pop() {
+            if visited[node] {
+                return Err(BVHValidationError::SharedNode { node });
+            }
+            visited[node] = true;
+            if self.nodes[node].parent() != parent {
+                return Err(BVHValidationError::WrongParent { node, parent });
+            }
+            match self.nodes[node] {
+                BVHNode::Node {
+                    child_l_index,
+                    child_l_aabb,
+                    child_r_index,
+                    child_r_aabb,
+                    ..
+                } => {
+                    for (child, child_aabb) in
+                        [(child_r_index, child_r_aabb), (child_l_index, child_l_aabb)]
+                    {
+                        if child >= self.nodes.len() {
+                            return Err(BVHValidationError::IndexOutOfRange { node });
+                        }
+                        if let Some(aabb) = aabb {
+                            if !aabb.approx_contains_aabb_eps(&child_aabb, EPSILON) {
+                                return Err(BVHValidationError::BoundsNotContained {
+                                    node: child,
+                                    parent: node,
+                                });
+                            }
+                        }
+                        stack.push((child, node, Some(child_aabb)));
+                    }
+                }
+                BVHNode::Leaf { shape_index, .. } => {
+                    match referenced.get_mut(shape_index) {
+                        None => {
+                            return Err(BVHValidationError::ShapeOutOfRange { node, shape_index })
+                        }
+                        Some(true) => {
+                            return Err(BVHValidationError::DuplicateShape { shape_index })
+                        }
+                        Some(referenced) => *referenced = true,
+                    }
+                    let shape = &shapes[shape_index];
+                    if let Some(aabb) = aabb {
+                        if !aabb.approx_contains_aabb_eps(&shape.aabb(), EPSILON) {
+                            return Err(BVHValidationError::ShapeNotContained {
+                                node,
+                                shape_index,
+                            });
+                        }
+                    }
+                    if shape.bh_node_index() != node {
+                        return Err(BVHValidationError::ShapeIndexMismatch { node, shape_index });
+                    }
+                }
+            }
+        }
+
+        if let Some(node) = visited.iter().position(|&visited| !visited) {
+            return Err(BVHValidationError::OrphanedNode { node });
+        }
+        match referenced.iter().position(|&referenced| !referenced) {
+            Some(shape_index) => Err(BVHValidationError::MissingShape { shape_index }),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::AABB;
+    use crate::bounding_hierarchy::BHShape;
+    use crate::bvh::{BVHNode, BVHValidationError, BVH};
+    use crate::testbase::{next_point3, UnitBox};
+    use crate::{Point3, Real};
+
+    /// Returns a tree over 20 random unit boxes.
+    fn some_bvh() -> (Vec<UnitBox>, BVH) {
+        let bounds = AABB::with_bounds(Point3::splat(-20.0), Point3::splat(20.0));
+        let mut seed = 3;
+        let mut shapes: Vec<UnitBox> = (0..20)
+            .map(|id| UnitBox::new(id, next_point3(&mut seed, &bounds)))
+            .collect();
+        let bvh = BVH::build(&mut shapes);
+        (shapes, bvh)
+    }
+
+    /// Returns the index of the first inner node below the root.
+    fn inner_child(bvh: &BVH) -> usize {
+        (1..bvh.nodes.len())
+            .find(|&index| matches!(bvh.nodes[index], BVHNode::Node { .. }))
+            .unwrap()
+    }
+
+    /// Returns the index of the leaf which references `shape_index`.
+    fn find_leaf(bvh: &BVH, shape_index: usize) -> usize {
+        bvh.nodes
+            .iter()
+            .position(|node| node.shape_index() == Some(shape_index))
+            .unwrap()
+    }
+
+    #[test]
+    /// Tests that built and incrementally updated trees are valid.
+    fn test_validate_valid_trees() {
+        let (mut shapes, mut bvh) = some_bvh();
+        assert_eq!(bvh.validate(&shapes), Ok(()));
+        assert_eq!(
+            BVH::build::<UnitBox>(&mut []).validate::<UnitBox>(&[]),
+            Ok(())
+        );
+        let mut single = vec![shapes[0]];
+        assert_eq!(BVH::build(&mut single).validate(&single), Ok(()));
+
+        for x in 0..10 {
+            shapes.push(UnitBox::new(100 + x, Point3::new(x as Real, 30.0, 0.0)));
+            let index = shapes.len() - 1;
+            bvh.add_node(&mut shapes, index);
+            assert_eq!(bvh.validate(&shapes), Ok(()));
+        }
+        for index in [4, 0, 17] {
+            bvh.remove_node(&mut shapes, index, true);
+            shapes.pop();
+            assert_eq!(bvh.validate(&shapes), Ok(()));
+        }
+    }
+
+    #[test]
+    /// Tests that broken indices are reported with the offending node or shape.
+    fn test_validate_broken_indices() {
+        let (mut shapes, bvh) = some_bvh();
+        let node = inner_child(&bvh);
+
+        let mut broken = bvh.clone();
+        *broken.nodes[node].parent_mut() = node;
+        let parent = bvh.nodes[node].parent();
+        let error = BVHValidationError::WrongParent { node, parent };
+        assert_eq!(broken.validate(&shapes), Err(error));
+
+        let mut broken = bvh.clone();
+        *broken.nodes[node].child_l_mut() = bvh.nodes.len();
+        let error = BVHValidationError::IndexOutOfRange { node };
+        assert_eq!(broken.validate(&shapes), Err(error));
+
+        let mut broken = bvh.clone();
+        let child_r = bvh.nodes[node].child_r();
+        *broken.nodes[node].child_l_mut() = child_r;
+        *broken.nodes[node].child_l_aabb_mut() = bvh.nodes[node].child_r_aabb();
+        let error = BVHValidationError::SharedNode { node: child_r };
+        assert_eq!(broken.validate(&shapes), Err(error));
+
+        let mut broken = bvh.clone();
+        broken.nodes.push(BVHNode::Leaf {
+            parent_index: 0,
+            shape_index: 0,
+        });
+        let error = BVHValidationError::OrphanedNode {
+            node: bvh.nodes.len(),
+        };
+        assert_eq!(broken.validate(&shapes), Err(error));
+
+        let leaf = find_leaf(&bvh, 5);
+        shapes[5].set_bh_node_index(0);
+        let error = BVHValidationError::ShapeIndexMismatch {
+            node: leaf,
+            shape_index: 5,
+        };
+        assert_eq!(bvh.validate(&shapes), Err(error));
+        shapes[5].set_bh_node_index(leaf);
+
+        let mut broken = bvh.clone();
+        *broken.nodes[leaf].shape_index_mut().unwrap() = 20;
+        let error = BVHValidationError::ShapeOutOfRange {
+            node: leaf,
+            shape_index: 20,
+        };
+        assert_eq!(broken.validate(&shapes), Err(error));
+
+        shapes.push(UnitBox::new(20, Point3::ZERO));
+        let error = BVHValidationError::MissingShape { shape_index: 20 };
+        assert_eq!(bvh.validate(&shapes), Err(error));
+
+        // Two boxes at the same position, so that both leaves contain either shape.
+        let mut twins = vec![UnitBox::new(0, Point3::ZERO), UnitBox::new(1, Point3::ZERO)];
+        let mut broken = BVH::build(&mut twins);
+        let first = broken.nodes[0].child_l();
+        let shape_index = broken.nodes[first].shape_index().unwrap();
+        let second = broken.nodes[0].child_r();
+        *broken.nodes[second].shape_index_mut().unwrap() = shape_index;
+        let error = BVHValidationError::DuplicateShape { shape_index };
+        assert_eq!(broken.validate(&twins), Err(error));
+    }
+
+    #[test]
+    /// Tests that bounds which do not contain their children or shapes are reported.
+    fn test_validate_bounds() {
+        let (mut shapes, bvh) = some_bvh();
+        let node = inner_child(&bvh);
+        let child_l = bvh.nodes[node].child_l();
+
+        let mut broken = bvh.clone();
+        broken.nodes[node].child_l_aabb_mut().max.x += 100.0;
+        let error = BVHValidationError::BoundsNotContained {
+            node: child_l,
+            parent: node,
+        };
+        assert_eq!(broken.validate(&shapes), Err(error));
+
+        let leaf = find_leaf(&bvh, 8);
+        shapes[8].pos.z += 50.0;
+        let error = BVHValidationError::ShapeNotContained {
+            node: leaf,
+            shape_index: 8,
+        };
+        assert_eq!(bvh.validate(&shapes), Err(error));
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "the bounds of shape 8 are not contained in its leaf {}",
+                leaf
+            )
+        );
+    }
+}
@@ -0,0 +1,358 @@
+//! Occlusion queries and batched line-of-sight tests on a [`BVH`].
+//!
+//! [`BVH`]: struct.BVH.html
+//!
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::bvh::{BVHNode, TraversalScratch, BVH};
+use crate::ray::{IntersectionRay, Ray};
+use crate::{Point3, Real, EPSILON};
+
+/// Number of bits stored per word of a [`VisibilityMatrix`].
+///
+/// [`VisibilityMatrix`]: struct.VisibilityMatrix.html
+///
+const WORD_BITS: usize = u64::BITS as usize;
+
+/// A dense bit matrix storing whether target `j` is visible from source `i`.
+/// Created by [`BVH::visibility_matrix`] and [`BVH::par_visibility_matrix`].
+///
+/// [`BVH::visibility_matrix`]: struct.BVH.html#method.visibility_matrix
+/// [`BVH::par_visibility_matrix`]: struct.BVH.html#method.par_visibility_matrix
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VisibilityMatrix {
+    /// Number of sources (rows).
+    sources: usize,
+    /// Number of targets (columns).
+    targets: usize,
+    /// Number of `u64` words used for each row.
+    words_per_row: usize,
+    /// Row major bits, `1` means visible.
+    bits: Vec<u64>,
+}
+
+impl VisibilityMatrix {
+    /// Creates a matrix in which nothing is visible.
+    fn new(sources: usize, targets: usize) -> VisibilityMatrix {
+        let words_per_row = targets.div_ceil(WORD_BITS);
+        VisibilityMatrix {
+            sources,
+            targets,
+            words_per_row,
+            bits: vec![0; sources * words_per_row],
+        }
+    }
+
+    /// Returns the number of source points, which is the number of rows.
+    pub fn sources(&self) -> usize {
+        self.sources
+    }
+
+    /// Returns the number of target points, which is the number of columns.
+    pub fn targets(&self) -> usize {
+        self.targets
+    }
+
+    /// Returns whether `target` can be seen from `source`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `source` or `target` are out of bounds.
+    pub fn is_visible(&self, source: usize, target: usize) -> bool {
+        assert!(source < self.sources && target < self.targets);
+        let word = self.bits[source * self.words_per_row + target / WORD_BITS];
+        word & (1 << (target % WORD_BITS)) != 0
+    }
+
+    /// Returns the number of visible targets of `source`.
+    pub fn count_visible(&self, source: usize) -> usize {
+        let start = source * self.words_per_row;
+        self.bits[start..start + self.words_per_row]
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    /// Returns the indices of all targets which are visible from `source`.
+    pub fn visible_targets(&self, source: usize) -> impl Iterator<Item = usize> + '_ {
+        (0..self.targets).filter(move |target| self.is_visible(source, *target))
+    }
+}
+
+impl BVH {
+    /// Returns `true` if any shape intersects `ray` between `t_min` and `t_max`.
+    /// The traversal stops at the first hit, so this is cheaper than finding the
+    /// closest intersection. The traversal stack is kept in `scratch`.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::{TraversalScratch, BVH};
+    /// use bvh::ray::Ray;
+    /// use bvh::sphere::Sphere;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Ball { sphere: Sphere, node_index: usize }
+    /// # impl Bounded for Ball {
+    /// #     fn aabb(&self) -> AABB { self.sphere.aabb() }
+    /// # }
+    /// # impl BHShape for Ball {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// # impl bvh::ray::IntersectionRay for Ball {
+    /// #     fn intersects_ray(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<bvh::ray::Intersection> {
+    /// #         self.sphere.intersects_ray(ray, t_min, t_max)
+    /// #     }
+    /// # }
+    /// let mut balls = vec![Ball { sphere: Sphere::new(Point3::new(5.0, 0.0, 0.0), 1.0), node_index: 0 }];
+    /// let bvh = BVH::build(&mut balls);
+    /// let mut scratch = TraversalScratch::new();
+    ///
+    /// let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+    /// assert!(bvh.is_occluded(&ray, 0.0, 10.0, &balls, &mut scratch));
+    /// assert!(!bvh.is_occluded(&ray, 0.0, 3.0, &balls, &mut scratch));
+    /// ```
+    ///
+    pub fn is_occluded<Shape: IntersectionRay>(
+        &self,
+        ray: &Ray,
+        t_min: Real,
+        t_max: Real,
+        shapes: &[Shape],
+        scratch: &mut TraversalScratch,
+    ) -> bool {
+        let stack = &mut scratch.stack;
+        stack.clear();
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        let overlaps = |(entry, exit): (Real, Real)| entry <= t_max && exit >= t_min;
+        stack.push(0);
+        while let Some(node_index) = stack.pop() {
+            match self.nodes[node_index] {
+                BVHNode::Node {
+                    child_l_index,
+                    ref child_l_aabb,
+                    child_r_index,
+                    ref child_r_aabb,
+                    ..
+                } => {
+                    if ray
+                        .intersects_aabb_interval(child_r_aabb)
+                        .is_some_and(overlaps)
+                    {
+                        stack.push(child_r_index);
+                    }
+                    if ray
+                        .intersects_aabb_interval(child_l_aabb)
+                        .is_some_and(overlaps)
+                    {
+                        stack.push(child_l_index);
+                    }
+                }
+                BVHNode::Leaf { shape_index, .. } => {
+                    if shapes[shape_index]
+                        .intersects_ray(ray, t_min, t_max)
+                        .is_some()
+                    {
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    /// Tests whether the straight segment between `source` and `target` is free of shapes.
+    /// Hits closer than `EPSILON` to either end point are ignored, so points lying on the
+    /// surface of a shape can still see each other.
+    fn segment_is_clear<Shape: IntersectionRay>(
+        &self,
+        source: &Point3,
+        target: &Point3,
+        shapes: &[Shape],
+        scratch: &mut TraversalScratch,
+    ) -> bool {
+        let (ray, length) = Ray::from_points(*source, *target);
+        if length <= 2.0 * EPSILON {
+            return true;
+        }
+        !self.is_occluded(&ray, EPSILON, length - EPSILON, shapes, scratch)
+    }
+
+    /// Computes which of the `targets` are visible from each of the `sources`.
+    /// Every pair is tested with an occlusion traversal. All traversals share the
+    /// buffers in `scratch`. See [`BVH::par_visibility_matrix`] for a parallel version.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::{TraversalScratch, BVH};
+    /// use bvh::ray::Ray;
+    /// use bvh::sphere::Sphere;
+    /// use bvh::Point3;
+    ///
+    /// # struct Ball { sphere: Sphere, node_index: usize }
+    /// # impl Bounded for Ball {
+    /// #     fn aabb(&self) -> AABB { self.sphere.aabb() }
+    /// # }
+    /// # impl BHShape for Ball {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// # impl bvh::ray::IntersectionRay for Ball {
+    /// #     fn intersects_ray(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<bvh::ray::Intersection> {
+    /// #         self.sphere.intersects_ray(ray, t_min, t_max)
+    /// #     }
+    /// # }
+    /// // A wall of one ball between the origin and (10, 0, 0).
+    /// let mut balls = vec![Ball { sphere: Sphere::new(Point3::new(5.0, 0.0, 0.0), 1.0), node_index: 0 }];
+    /// let bvh = BVH::build(&mut balls);
+    ///
+    /// let sources = [Point3::new(0.0, 0.0, 0.0)];
+    /// let targets = [Point3::new(10.0, 0.0, 0.0), Point3::new(0.0, 10.0, 0.0)];
+    /// let mut scratch = TraversalScratch::new();
+    /// let visibility = bvh.visibility_matrix(&sources, &targets, &balls, &mut scratch);
+    ///
+    /// assert!(!visibility.is_visible(0, 0));
+    /// assert!(visibility.is_visible(0, 1));
+    /// ```
+    ///
+    /// [`BVH::par_visibility_matrix`]: struct.BVH.html#method.par_visibility_matrix
+    ///
+    pub fn visibility_matrix<Shape: IntersectionRay>(
+        &self,
+        sources: &[Point3],
+        targets: &[Point3],
+        shapes: &[Shape],
+        scratch: &mut TraversalScratch,
+    ) -> VisibilityMatrix {
+        let mut matrix = VisibilityMatrix::new(sources.len(), targets.len());
+        if matrix.words_per_row == 0 {
+            return matrix;
+        }
+        for (source, row) in sources
+            .iter()
+            .zip(matrix.bits.chunks_mut(matrix.words_per_row))
+        {
+            self.fill_visibility_row(source, targets, shapes, scratch, row);
+        }
+        matrix
+    }
+
+    /// Parallel version of [`BVH::visibility_matrix`]. The rows of the matrix are
+    /// distributed over the rayon thread pool, each worker thread reuses its own
+    /// [`TraversalScratch`]. Requires the `rayon` feature, which is enabled by default.
+    ///
+    /// [`BVH::visibility_matrix`]: struct.BVH.html#method.visibility_matrix
+    /// [`TraversalScratch`]: struct.TraversalScratch.html
+    ///
+    #[cfg(feature = "rayon")]
+    pub fn par_visibility_matrix<Shape: IntersectionRay + Sync>(
+        &self,
+        sources: &[Point3],
+        targets: &[Point3],
+        shapes: &[Shape],
+    ) -> VisibilityMatrix {
+        let mut matrix = VisibilityMatrix::new(sources.len(), targets.len());
+        if matrix.words_per_row == 0 {
+            return matrix;
+        }
+        sources
+            .par_iter()
+            .zip(matrix.bits.par_chunks_mut(matrix.words_per_row))
+            .for_each_init(TraversalScratch::new, |scratch, (source, row)| {
+                self.fill_visibility_row(source, targets, shapes, scratch, row)
+            });
+        matrix
+    }
+
+    /// Sets the bit of every target in `row` which is visible from `source`.
+    fn fill_visibility_row<Shape: IntersectionRay>(
+        &self,
+        source: &Point3,
+        targets: &[Point3],
+        shapes: &[Shape],
+        scratch: &mut TraversalScratch,
+        row: &mut [u64],
+    ) {
+        for (target_index, target) in targets.iter().enumerate() {
+            if self.segment_is_clear(source, target, shapes, scratch) {
+                row[target_index / WORD_BITS] |= 1 << (target_index % WORD_BITS);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::AABB;
+    use crate::bvh::{TraversalScratch, BVH};
+    use crate::ray::{IntersectionRay, Ray};
+    use crate::testbase::{create_n_cubes, next_point3};
+    use crate::{Point3, EPSILON};
+
+    #[test]
+    /// Compares the visibility matrix with a brute force test of every pair against every
+    /// triangle, both with the sequential and the parallel version.
+    fn test_visibility_matrix_matches_brute_force() {
+        let bounds = AABB::with_bounds(Point3::splat(-10.0), Point3::splat(10.0));
+        let mut triangles = create_n_cubes(30, &bounds);
+        let bvh = BVH::build(&mut triangles);
+
+        let mut seed = 7;
+        let sources: Vec<Point3> = (0..20).map(|_| next_point3(&mut seed, &bounds)).collect();
+        let targets: Vec<Point3> = (0..70).map(|_| next_point3(&mut seed, &bounds)).collect();
+
+        let mut scratch = TraversalScratch::new();
+        let matrix = bvh.visibility_matrix(&sources, &targets, &triangles, &mut scratch);
+        #[cfg(feature = "rayon")]
+        assert_eq!(
+            matrix,
+            bvh.par_visibility_matrix(&sources, &targets, &triangles)
+        );
+
+        let mut visible_pairs = 0;
+        for (i, source) in sources.iter().enumerate() {
+            for (j, target) in targets.iter().enumerate() {
+                let ray = Ray::new(*source, *target - *source);
+                let length = (*target - *source).length();
+                let expected = triangles
+                    .iter()
+                    .all(|t| t.intersects_ray(&ray, EPSILON, length - EPSILON).is_none());
+                assert_eq!(matrix.is_visible(i, j), expected);
+                if expected {
+                    visible_pairs += 1;
+                }
+            }
+            assert_eq!(matrix.count_visible(i), matrix.visible_targets(i).count());
+        }
+        // Make sure the scene is neither empty nor completely blocking.
+        assert!(visible_pairs > 0 && visible_pairs < sources.len() * targets.len());
+    }
+
+    #[test]
+    /// Empty source or target lists produce empty matrices.
+    fn test_visibility_matrix_empty() {
+        let bounds = AABB::with_bounds(Point3::splat(-10.0), Point3::splat(10.0));
+        let mut triangles = create_n_cubes(1, &bounds);
+        let bvh = BVH::build(&mut triangles);
+        let points = [Point3::new(0.0, 0.0, 0.0)];
+        let mut scratch = TraversalScratch::new();
+
+        let matrix = bvh.visibility_matrix(&points, &[], &triangles, &mut scratch);
+        assert_eq!((matrix.sources(), matrix.targets()), (1, 0));
+        #[cfg(feature = "rayon")]
+        {
+            let matrix = bvh.par_visibility_matrix(&[], &points, &triangles);
+            assert_eq!((matrix.sources(), matrix.targets()), (0, 1));
+        }
+    }
+}
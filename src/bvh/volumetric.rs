@@ -0,0 +1,237 @@
+//! Front-to-back traversal of the leaves of a [`BVH`] which reports the parametric
+//! interval a [`Ray`] spends inside every leaf.
+//!
+//! [`BVH`]: struct.BVH.html
+//! [`Ray`]: ../ray/struct.Ray.html
+//!
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::aabb::{Bounded, AABB};
+use crate::bvh::{BVHNode, BVH};
+use crate::ray::Ray;
+use crate::Real;
+
+/// A leaf which is passed by a [`Ray`], together with the `[t_enter, t_exit]` interval
+/// the [`Ray`] spends inside the leaf's [`AABB`].
+///
+/// [`AABB`]: ../aabb/struct.AABB.html
+/// [`Ray`]: ../ray/struct.Ray.html
+///
+#[derive(Debug, Clone, Copy)]
+pub struct LeafInterval<'shapes, Shape> {
+    /// Index of the shape in the shapes slice.
+    pub shape_index: usize,
+    /// The shape referenced by the leaf.
+    pub shape: &'shapes Shape,
+    /// Distance at which the ray enters the leaf. Zero if the ray starts inside of it.
+    pub t_enter: Real,
+    /// Distance at which the ray exits the leaf.
+    pub t_exit: Real,
+}
+
+/// A node waiting in the queue of a [`LeafIntervalIterator`], ordered by `t_enter`.
+///
+/// [`LeafIntervalIterator`]: struct.LeafIntervalIterator.html
+///
+#[derive(Debug, Clone, Copy)]
+struct QueuedNode {
+    t_enter: Real,
+    t_exit: Real,
+    node_index: usize,
+}
+
+impl Ord for QueuedNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed, so that the `BinaryHeap` pops the closest node first.
+        self.t_enter
+            .partial_cmp(&other.t_enter)
+            .unwrap_or(Ordering::Equal)
+            .reverse()
+    }
+}
+
+impl PartialOrd for QueuedNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for QueuedNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.node_index == other.node_index
+    }
+}
+
+impl Eq for QueuedNode {}
+
+/// Iterator over the leaves of a [`BVH`] which are passed by a [`Ray`], sorted by the
+/// distance at which the [`Ray`] enters them. Created by [`BVH::traverse_intervals`].
+///
+/// [`BVH`]: struct.BVH.html
+/// [`BVH::traverse_intervals`]: struct.BVH.html#method.traverse_intervals
+/// [`Ray`]: ../ray/struct.Ray.html
+///
+pub struct LeafIntervalIterator<'bvh, 'ray, 'shapes, Shape: Bounded> {
+    bvh: &'bvh BVH,
+    ray: &'ray Ray,
+    shapes: &'shapes [Shape],
+    queue: BinaryHeap<QueuedNode>,
+}
+
+impl<'bvh, 'ray, 'shapes, Shape: Bounded> LeafIntervalIterator<'bvh, 'ray, 'shapes, Shape> {
+    /// Queues `node_index` if the ray passes through `aabb`.
+    fn push(&mut self, node_index: usize, aabb: &AABB) {
+        if let Some((t_enter, t_exit)) = self.ray.intersects_aabb_interval(aabb) {
+            self.queue.push(QueuedNode {
+                t_enter: t_enter.max(0.0),
+                t_exit,
+                node_index,
+            });
+        }
+    }
+}
+
+impl<'bvh, 'ray, 'shapes, Shape: Bounded> Iterator
+    for LeafIntervalIterator<'bvh, 'ray, 'shapes, Shape>
+{
+    type Item = LeafInterval<'shapes, Shape>;
+
+    fn next(&mut self) -> Option<LeafInterval<'shapes, Shape>> {
+        // A child's interval always starts at or after its parent's, so popping the queue
+        // in order of `t_enter` yields the leaves front to back.
+        while let Some(queued) = self.queue.pop() {
+            match self.bvh.nodes[queued.node_index] {
+                BVHNode::Node {
+                    child_l_index,
+                    child_l_aabb,
+                    child_r_index,
+                    child_r_aabb,
+                    ..
+                } => {
+                    self.push(child_l_index, &child_l_aabb);
+                    self.push(child_r_index, &child_r_aabb);
+                }
+                BVHNode::Leaf { shape_index, .. } => {
+                    return Some(LeafInterval {
+                        shape_index,
+                        shape: &self.shapes[shape_index],
+                        t_enter: queued.t_enter,
+                        t_exit: queued.t_exit,
+                    });
+                }
+            }
+        }
+        None
+    }
+}
+
+impl BVH {
+    /// Returns an iterator over all leaves whose [`AABB`] is passed by `ray`, in
+    /// front-to-back order. Every item carries the `[t_enter, t_exit]` interval the
+    /// ray spends inside the leaf's [`AABB`], which is what volumetric integrators
+    /// need to march through participating media.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..4)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let bvh = BVH::build(&mut cubes);
+    ///
+    /// let ray = Ray::new(Point3::new(-2.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+    /// let intervals: Vec<(f32, f32)> = bvh
+    ///     .traverse_intervals(&ray, &cubes)
+    ///     .map(|leaf| (leaf.t_enter, leaf.t_exit))
+    ///     .collect();
+    /// assert_eq!(intervals, vec![(1.5, 2.5), (3.5, 4.5), (5.5, 6.5), (7.5, 8.5)]);
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub fn traverse_intervals<'bvh, 'ray, 'shapes, Shape: Bounded>(
+        &'bvh self,
+        ray: &'ray Ray,
+        shapes: &'shapes [Shape],
+    ) -> LeafIntervalIterator<'bvh, 'ray, 'shapes, Shape> {
+        let mut iterator = LeafIntervalIterator {
+            bvh: self,
+            ray,
+            shapes,
+            queue: BinaryHeap::new(),
+        };
+        match self.nodes.first() {
+            Some(BVHNode::Node { .. }) => iterator.queue.push(QueuedNode {
+                t_enter: 0.0,
+                t_exit: Real::INFINITY,
+                node_index: 0,
+            }),
+            // The root does not store its own `AABB`, so a single leaf is tested directly.
+            Some(BVHNode::Leaf { shape_index, .. }) => {
+                iterator.push(0, &shapes[*shape_index].aabb());
+            }
+            None => {}
+        }
+        iterator
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::{Bounded, AABB};
+    use crate::bvh::BVH;
+    use crate::testbase::{create_n_cubes, create_ray};
+    use crate::Point3;
+
+    #[test]
+    /// Checks that the intervals are sorted, match the shapes' `AABB`s and that no leaf is
+    /// missing compared to a plain traversal.
+    fn test_traverse_intervals_front_to_back() {
+        let bounds = AABB::with_bounds(Point3::splat(-20.0), Point3::splat(20.0));
+        let mut triangles = create_n_cubes(500, &bounds);
+        let bvh = BVH::build(&mut triangles);
+
+        let mut seed = 0;
+        for _ in 0..100 {
+            let ray = create_ray(&mut seed, &bounds);
+            let intervals: Vec<_> = bvh.traverse_intervals(&ray, &triangles).collect();
+
+            let mut expected: Vec<usize> = triangles
+                .iter()
+                .enumerate()
+                .filter(|(_, t)| ray.intersects_aabb_interval(&t.aabb()).is_some())
+                .map(|(i, _)| i)
+                .collect();
+            let mut actual: Vec<usize> = intervals.iter().map(|leaf| leaf.shape_index).collect();
+            expected.sort_unstable();
+            actual.sort_unstable();
+            assert_eq!(expected, actual);
+
+            for pair in intervals.windows(2) {
+                assert!(pair[0].t_enter <= pair[1].t_enter);
+            }
+            for leaf in intervals.iter() {
+                let (t_enter, t_exit) = ray.intersects_aabb_interval(&leaf.shape.aabb()).unwrap();
+                assert_eq!(leaf.t_enter, t_enter.max(0.0));
+                assert_eq!(leaf.t_exit, t_exit);
+            }
+        }
+    }
+}
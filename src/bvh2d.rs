@@ -0,0 +1,803 @@
+//! This module exports 2D counterparts of the [`AABB`], the [`Ray`] and the [`BVH`], for
+//! sprites, UI hit-testing and map data which have no use for a third axis.
+//!
+//! [`AABB`]: ../aabb/struct.AABB.html
+//! [`Ray`]: ../ray/struct.Ray.html
+//! [`BVH`]: ../bvh/struct.BVH.html
+//!
+
+use smallvec::SmallVec;
+
+use crate::{Point2, Real, Vector2};
+
+/// The number of buckets of the binned SAH build of a [`BVH2D`].
+///
+/// [`BVH2D`]: struct.BVH2D.html
+///
+const NUM_BUCKETS: usize = 6;
+
+/// A 2D axis-aligned bounding rectangle.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rect {
+    /// Minimum coordinates
+    pub min: Point2,
+
+    /// Maximum coordinates
+    pub max: Point2,
+}
+
+impl Rect {
+    /// Creates a new [`Rect`] with the given bounds.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::bvh2d::Rect;
+    /// use bvh::Point2;
+    ///
+    /// let rect = Rect::with_bounds(Point2::new(-1.0, -1.0), Point2::new(1.0, 1.0));
+    /// assert_eq!(rect.min.x, -1.0);
+    /// assert_eq!(rect.max.y, 1.0);
+    /// ```
+    ///
+    /// [`Rect`]: struct.Rect.html
+    ///
+    pub fn with_bounds(min: Point2, max: Point2) -> Rect {
+        Rect { min, max }
+    }
+
+    /// Creates a new empty [`Rect`], which grows to the first point or [`Rect`] joined
+    /// into it.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::bvh2d::Rect;
+    /// use bvh::Point2;
+    ///
+    /// let rect = Rect::empty();
+    /// assert!(rect.is_empty());
+    /// assert!(!rect.contains(&Point2::new(0.0, 0.0)));
+    /// ```
+    ///
+    /// [`Rect`]: struct.Rect.html
+    ///
+    pub fn empty() -> Rect {
+        Rect {
+            min: Point2::new(Real::INFINITY, Real::INFINITY),
+            max: Point2::new(Real::NEG_INFINITY, Real::NEG_INFINITY),
+        }
+    }
+
+    /// Returns true if the [`Rect`] contains no points.
+    ///
+    /// [`Rect`]: struct.Rect.html
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y
+    }
+
+    /// Returns true if the [`Point2`] is inside the [`Rect`], including its boundary.
+    ///
+    /// [`Point2`]: ../type.Point2.html
+    /// [`Rect`]: struct.Rect.html
+    ///
+    pub fn contains(&self, p: &Point2) -> bool {
+        p.cmpge(self.min).all() && p.cmple(self.max).all()
+    }
+
+    /// Returns true if both [`Rect`]s share at least one point.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::bvh2d::Rect;
+    /// use bvh::Point2;
+    ///
+    /// let a = Rect::with_bounds(Point2::new(0.0, 0.0), Point2::new(2.0, 2.0));
+    /// let b = Rect::with_bounds(Point2::new(2.0, 1.0), Point2::new(3.0, 3.0));
+    /// let c = Rect::with_bounds(Point2::new(0.0, 3.0), Point2::new(1.0, 4.0));
+    /// assert!(a.overlaps(&b));
+    /// assert!(!a.overlaps(&c));
+    /// ```
+    ///
+    /// [`Rect`]: struct.Rect.html
+    ///
+    pub fn overlaps(&self, other: &Rect) -> bool {
+        self.min.cmple(other.max).all() && other.min.cmple(self.max).all()
+    }
+
+    /// Returns the smallest [`Rect`] which contains both [`Rect`]s.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::bvh2d::Rect;
+    /// use bvh::Point2;
+    ///
+    /// let a = Rect::with_bounds(Point2::new(0.0, 0.0), Point2::new(1.0, 1.0));
+    /// let b = Rect::with_bounds(Point2::new(2.0, -1.0), Point2::new(3.0, 0.5));
+    /// let joint = a.join(&b);
+    /// assert_eq!(joint.min, Point2::new(0.0, -1.0));
+    /// assert_eq!(joint.max, Point2::new(3.0, 1.0));
+    /// ```
+    ///
+    /// [`Rect`]: struct.Rect.html
+    ///
+    #[must_use]
+    pub fn join(&self, other: &Rect) -> Rect {
+        Rect::with_bounds(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    /// Mutable version of [`Rect::join`].
+    ///
+    /// [`Rect::join`]: struct.Rect.html#method.join
+    ///
+    pub fn join_mut(&mut self, other: &Rect) {
+        *self = self.join(other);
+    }
+
+    /// Returns the smallest [`Rect`] which contains this [`Rect`] and the [`Point2`].
+    ///
+    /// [`Point2`]: ../type.Point2.html
+    /// [`Rect`]: struct.Rect.html
+    ///
+    #[must_use]
+    pub fn grow(&self, p: &Point2) -> Rect {
+        Rect::with_bounds(self.min.min(*p), self.max.max(*p))
+    }
+
+    /// Mutable version of [`Rect::grow`].
+    ///
+    /// [`Rect::grow`]: struct.Rect.html#method.grow
+    ///
+    pub fn grow_mut(&mut self, p: &Point2) {
+        *self = self.grow(p);
+    }
+
+    /// Returns the size of the [`Rect`] along both axes.
+    ///
+    /// [`Rect`]: struct.Rect.html
+    ///
+    pub fn size(&self) -> Vector2 {
+        self.max - self.min
+    }
+
+    /// Returns the center of the [`Rect`].
+    ///
+    /// [`Rect`]: struct.Rect.html
+    ///
+    pub fn center(&self) -> Point2 {
+        self.min + (self.size() / 2.0)
+    }
+
+    /// Returns the area of the [`Rect`], which is zero if it is empty.
+    ///
+    /// [`Rect`]: struct.Rect.html
+    ///
+    pub fn area(&self) -> Real {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let size = self.size();
+        size.x * size.y
+    }
+
+    /// Returns the perimeter of the [`Rect`], which is zero if it is empty. The perimeter
+    /// plays the role of the surface area in the SAH of a [`BVH2D`], since a random line
+    /// hits a convex shape with a probability proportional to its perimeter.
+    ///
+    /// [`Rect`]: struct.Rect.html
+    /// [`BVH2D`]: struct.BVH2D.html
+    ///
+    pub fn perimeter(&self) -> Real {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let size = self.size();
+        2.0 * (size.x + size.y)
+    }
+
+    /// Returns the index of the longest axis, `0` for x and `1` for y.
+    ///
+    /// [`Rect`]: struct.Rect.html
+    ///
+    pub fn largest_axis(&self) -> usize {
+        let size = self.size();
+        if size.x > size.y {
+            0
+        } else {
+            1
+        }
+    }
+}
+
+impl Default for Rect {
+    fn default() -> Rect {
+        Rect::empty()
+    }
+}
+
+/// A trait implemented by things which can be bounded by a [`Rect`].
+///
+/// [`Rect`]: struct.Rect.html
+///
+pub trait Bounded2 {
+    /// Returns the bounding [`Rect`] of the shape.
+    ///
+    /// [`Rect`]: struct.Rect.html
+    ///
+    fn rect(&self) -> Rect;
+}
+
+impl Bounded2 for Rect {
+    fn rect(&self) -> Rect {
+        *self
+    }
+}
+
+impl Bounded2 for Point2 {
+    fn rect(&self) -> Rect {
+        Rect::with_bounds(*self, *self)
+    }
+}
+
+impl<T: Bounded2> Bounded2 for &T {
+    fn rect(&self) -> Rect {
+        T::rect(self)
+    }
+}
+
+/// A trait for queries which can be tested against the [`Rect`]s of a [`BVH2D`].
+///
+/// [`Rect`]: struct.Rect.html
+/// [`BVH2D`]: struct.BVH2D.html
+///
+pub trait IntersectionRect {
+    /// Returns true if the query may touch something inside the [`Rect`].
+    ///
+    /// [`Rect`]: struct.Rect.html
+    ///
+    fn intersects_rect(&self, rect: &Rect) -> bool;
+}
+
+impl IntersectionRect for Rect {
+    fn intersects_rect(&self, rect: &Rect) -> bool {
+        self.overlaps(rect)
+    }
+}
+
+impl IntersectionRect for Point2 {
+    fn intersects_rect(&self, rect: &Rect) -> bool {
+        rect.contains(self)
+    }
+}
+
+impl IntersectionRect for Ray2 {
+    fn intersects_rect(&self, rect: &Rect) -> bool {
+        self.intersects_rect_interval(rect).is_some()
+    }
+}
+
+/// A 2D ray, which starts at `origin` and extends in `direction`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ray2 {
+    /// The ray origin.
+    pub origin: Point2,
+
+    /// The normalized ray direction.
+    pub direction: Vector2,
+
+    /// Inverse (1/x) ray direction. Cached for use in [`Rect`] intersections.
+    ///
+    /// [`Rect`]: struct.Rect.html
+    ///
+    inv_direction: Vector2,
+}
+
+impl Ray2 {
+    /// Creates a new [`Ray2`] from an `origin` and a `direction`, which is normalized.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::bvh2d::Ray2;
+    /// use bvh::{Point2, Vector2};
+    ///
+    /// let ray = Ray2::new(Point2::new(0.0, 0.0), Vector2::new(3.0, 4.0));
+    /// assert_eq!(ray.direction, Vector2::new(0.6, 0.8));
+    /// ```
+    ///
+    /// [`Ray2`]: struct.Ray2.html
+    ///
+    pub fn new(origin: Point2, direction: Vector2) -> Ray2 {
+        let direction = direction.normalize();
+        Ray2 {
+            origin,
+            direction,
+            inv_direction: direction.recip(),
+        }
+    }
+
+    /// Returns the position the front of the [`Ray2`] is after traveling `distance`.
+    ///
+    /// [`Ray2`]: struct.Ray2.html
+    ///
+    pub fn at(&self, distance: Real) -> Point2 {
+        self.origin + self.direction * distance
+    }
+
+    /// Returns the distances along the [`Ray2`] at which it enters and exits the [`Rect`],
+    /// or `None` if it misses the [`Rect`]. The entry distance is negative if the origin is
+    /// inside the [`Rect`].
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::bvh2d::{Ray2, Rect};
+    /// use bvh::{Point2, Vector2};
+    ///
+    /// let rect = Rect::with_bounds(Point2::new(2.0, -1.0), Point2::new(3.0, 1.0));
+    /// let ray = Ray2::new(Point2::new(0.0, 0.0), Vector2::new(1.0, 0.0));
+    /// assert_eq!(ray.intersects_rect_interval(&rect), Some((2.0, 3.0)));
+    ///
+    /// let behind = Ray2::new(Point2::new(4.0, 0.0), Vector2::new(1.0, 0.0));
+    /// assert_eq!(behind.intersects_rect_interval(&rect), None);
+    /// ```
+    ///
+    /// [`Ray2`]: struct.Ray2.html
+    /// [`Rect`]: struct.Rect.html
+    ///
+    pub fn intersects_rect_interval(&self, rect: &Rect) -> Option<(Real, Real)> {
+        let t0 = (rect.min - self.origin) * self.inv_direction;
+        let t1 = (rect.max - self.origin) * self.inv_direction;
+        let entry = t0.min(t1).max_element();
+        let exit = t0.max(t1).min_element();
+        if entry > exit || exit < 0.0 {
+            None
+        } else {
+            Some((entry, exit))
+        }
+    }
+}
+
+/// A node of a [`BVH2D`].
+///
+/// [`BVH2D`]: struct.BVH2D.html
+///
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub enum BVH2DNode {
+    /// Leaf node.
+    Leaf {
+        /// The index of the shape contained in this leaf.
+        shape_index: usize,
+    },
+    /// Inner node.
+    Node {
+        /// Index of the left subtree's root node.
+        child_l_index: usize,
+
+        /// The bounding rectangle of the left subtree.
+        child_l_rect: Rect,
+
+        /// Index of the right subtree's root node.
+        child_r_index: usize,
+
+        /// The bounding rectangle of the right subtree.
+        child_r_rect: Rect,
+    },
+}
+
+/// A 2D bounding volume hierarchy, which is built with the binned SAH like the [`BVH`],
+/// weighting the nodes by their perimeter. Shapes are referred to by their index in the
+/// slice the hierarchy was built from, so unlike the [`BVH`] it needs no [`BHShape`].
+///
+/// [`BVH`]: ../bvh/struct.BVH.html
+/// [`BHShape`]: ../bounding_hierarchy/trait.BHShape.html
+///
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct BVH2D {
+    /// The list of nodes of the [`BVH2D`], with the root at index `0`.
+    ///
+    /// [`BVH2D`]: struct.BVH2D.html
+    ///
+    pub nodes: Vec<BVH2DNode>,
+}
+
+impl BVH2D {
+    /// Builds a [`BVH2D`] over the `shapes`.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::bvh2d::{Rect, BVH2D};
+    /// use bvh::Point2;
+    ///
+    /// let sprites: Vec<Rect> = (0..100)
+    ///     .map(|i| {
+    ///         let min = Point2::new((i % 10) as f32 * 2.0, (i / 10) as f32 * 2.0);
+    ///         Rect::with_bounds(min, min + Point2::new(1.0, 1.0))
+    ///     })
+    ///     .collect();
+    /// let bvh = BVH2D::build(&sprites);
+    ///
+    /// let hits = bvh.traverse(&Point2::new(4.5, 6.5), &sprites);
+    /// assert_eq!(hits, vec![&sprites[32]]);
+    /// ```
+    ///
+    /// [`BVH2D`]: struct.BVH2D.html
+    ///
+    pub fn build<T: Bounded2>(shapes: &[T]) -> BVH2D {
+        let rects: Vec<Rect> = shapes.iter().map(Bounded2::rect).collect();
+        let mut indices: Vec<usize> = (0..shapes.len()).collect();
+        let mut nodes = Vec::with_capacity(2 * shapes.len());
+        if !indices.is_empty() {
+            BVH2D::build_node(&rects, &mut indices, &mut nodes);
+        }
+        BVH2D { nodes }
+    }
+
+    /// Builds the subtree over the shapes with the given `indices` and returns the index
+    /// of its root node.
+    fn build_node(rects: &[Rect], indices: &mut [usize], nodes: &mut Vec<BVH2DNode>) -> usize {
+        let node_index = nodes.len();
+        if let [shape_index] = *indices {
+            nodes.push(BVH2DNode::Leaf { shape_index });
+            return node_index;
+        }
+
+        let centroid_bounds = indices
+            .iter()
+            .fold(Rect::empty(), |bounds, &i| bounds.grow(&rects[i].center()));
+        let axis = centroid_bounds.largest_axis();
+        let (min, size) = (centroid_bounds.min[axis], centroid_bounds.size()[axis]);
+
+        let split = if size <= 0.0 {
+            // All centroids coincide, so any partition is as good as another.
+            indices.len() / 2
+        } else {
+            let bucket_of = |i: usize| {
+                let relative = (rects[i].center()[axis] - min) / size;
+                ((relative * NUM_BUCKETS as Real) as usize).min(NUM_BUCKETS - 1)
+            };
+            let mut buckets = [(0usize, Rect::empty()); NUM_BUCKETS];
+            for &i in indices.iter() {
+                let bucket = &mut buckets[bucket_of(i)];
+                bucket.0 += 1;
+                bucket.1.join_mut(&rects[i]);
+            }
+
+            let cost = |buckets: &[(usize, Rect)]| {
+                let (count, rect) = buckets
+                    .iter()
+                    .fold((0, Rect::empty()), |(count, rect), bucket| {
+                        (count + bucket.0, rect.join(&bucket.1))
+                    });
+                count as Real * rect.perimeter()
+            };
+            let best = (1..NUM_BUCKETS)
+                .map(|split| (split, cost(&buckets[..split]) + cost(&buckets[split..])))
+                .fold((1, Real::INFINITY), |best, candidate| {
+                    if candidate.1 < best.1 {
+                        candidate
+                    } else {
+                        best
+                    }
+                })
+                .0;
+
+            // Moves the shapes of the buckets below `best` to the front.
+            let mut split = 0;
+            for j in 0..indices.len() {
+                if bucket_of(indices[j]) < best {
+                    indices.swap(split, j);
+                    split += 1;
+                }
+            }
+            split
+        };
+
+        let (left, right) = indices.split_at_mut(split);
+        let bounds = |indices: &[usize]| {
+            indices
+                .iter()
+                .fold(Rect::empty(), |rect, &i| rect.join(&rects[i]))
+        };
+        let (child_l_rect, child_r_rect) = (bounds(left), bounds(right));
+
+        // Reserves the node, and fills it in once the children have their indices.
+        nodes.push(BVH2DNode::Leaf { shape_index: 0 });
+        let child_l_index = BVH2D::build_node(rects, left, nodes);
+        let child_r_index = BVH2D::build_node(rects, right, nodes);
+        nodes[node_index] = BVH2DNode::Node {
+            child_l_index,
+            child_l_rect,
+            child_r_index,
+            child_r_rect,
+        };
+        node_index
+    }
+
+    /// Returns the indices of the shapes whose [`Rect`]s are hit by the `query`.
+    ///
+    /// [`Rect`]: struct.Rect.html
+    ///
+    pub fn traverse_indices<T: Bounded2>(
+        &self,
+        query: &impl IntersectionRect,
+        shapes: &[T],
+    ) -> Vec<usize> {
+        let mut hits = Vec::new();
+        let mut stack: SmallVec<[usize; 64]> = SmallVec::new();
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(node_index) = stack.pop() {
+            match self.nodes[node_index] {
+                BVH2DNode::Node {
+                    child_l_index,
+                    ref child_l_rect,
+                    child_r_index,
+                    ref child_r_rect,
+                } => {
+                    if query.intersects_rect(child_r_rect) {
+                        stack.push(child_r_index);
+                    }
+                    if query.intersects_rect(child_l_rect) {
+                        stack.push(child_l_index);
+                    }
+                }
+                BVH2DNode::Leaf { shape_index } => {
+                    if query.intersects_rect(&shapes[shape_index].rect()) {
+                        hits.push(shape_index);
+                    }
+                }
+            }
+        }
+        hits
+    }
+
+    /// Returns the shapes whose [`Rect`]s are hit by the `query`, which may be a
+    /// [`Point2`], a [`Rect`] or a [`Ray2`].
+    ///
+    /// [`Point2`]: ../type.Point2.html
+    /// [`Rect`]: struct.Rect.html
+    /// [`Ray2`]: struct.Ray2.html
+    ///
+    pub fn traverse<'shapes, T: Bounded2>(
+        &self,
+        query: &impl IntersectionRect,
+        shapes: &'shapes [T],
+    ) -> Vec<&'shapes T> {
+        self.traverse_indices(query, shapes)
+            .into_iter()
+            .map(|i| &shapes[i])
+            .collect()
+    }
+
+    /// Returns the shape with the nearest hit along the `ray` and its distance, where
+    /// `test` returns the distance at which the [`Ray2`] hits a shape. Subtrees which are
+    /// farther away than the nearest hit so far are skipped.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::bvh2d::{Ray2, Rect, BVH2D};
+    /// use bvh::{Point2, Vector2};
+    ///
+    /// let walls: Vec<Rect> = (1..10)
+    ///     .map(|x| Rect::with_bounds(Point2::new(x as f32 * 3.0, -1.0), Point2::new(x as f32 * 3.0 + 1.0, 1.0)))
+    ///     .collect();
+    /// let bvh = BVH2D::build(&walls);
+    ///
+    /// let ray = Ray2::new(Point2::new(0.0, 0.0), Vector2::new(1.0, 0.0));
+    /// let (wall, distance) = bvh
+    ///     .traverse_nearest_with(&ray, &walls, |wall| {
+    ///         ray.intersects_rect_interval(wall).map(|(entry, _)| entry)
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(wall, &walls[0]);
+    /// assert_eq!(distance, 3.0);
+    /// ```
+    ///
+    /// [`Ray2`]: struct.Ray2.html
+    ///
+    pub fn traverse_nearest_with<'shapes, T>(
+        &self,
+        ray: &Ray2,
+        shapes: &'shapes [T],
+        mut test: impl FnMut(&T) -> Option<Real>,
+    ) -> Option<(&'shapes T, Real)> {
+        let mut nearest: Option<(usize, Real)> = None;
+        let mut stack: SmallVec<[(usize, Real); 64]> = SmallVec::new();
+        if !self.nodes.is_empty() {
+            stack.push((0, 0.0));
+        }
+        while let Some((node_index, entry)) = stack.pop() {
+            let limit = nearest.map_or(Real::INFINITY, |(_, distance)| distance);
+            if entry > limit {
+                continue;
+            }
+            match self.nodes[node_index] {
+                BVH2DNode::Node {
+                    child_l_index,
+                    ref child_l_rect,
+                    child_r_index,
+                    ref child_r_rect,
+                } => {
+                    let test_rect = |rect| {
+                        ray.intersects_rect_interval(rect)
+                            .filter(|(entry, _)| *entry <= limit)
+                            .map(|(entry, _)| entry)
+                    };
+                    let l = test_rect(child_l_rect).map(|entry| (child_l_index, entry));
+                    let r = test_rect(child_r_rect).map(|entry| (child_r_index, entry));
+                    // Push the farther child first, so that the closer one is visited first.
+                    match (l, r) {
+                        (Some(l), Some(r)) if l.1 < r.1 => stack.extend([r, l]),
+                        (Some(l), Some(r)) => stack.extend([l, r]),
+                        (Some(child), None) | (None, Some(child)) => stack.push(child),
+                        (None, None) => {}
+                    }
+                }
+                BVH2DNode::Leaf { shape_index } => {
+                    if let Some(distance) = test(&shapes[shape_index]) {
+                        if distance >= 0.0 && distance < limit {
+                            nearest = Some((shape_index, distance));
+                        }
+                    }
+                }
+            }
+        }
+        nearest.map(|(i, distance)| (&shapes[i], distance))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bvh2d::{BVH2DNode, Bounded2, IntersectionRect, Ray2, Rect, BVH2D};
+    use crate::{Point2, Real, Vector2};
+
+    /// Creates `n` pseudo random rectangles of varying sizes, which overlap each other.
+    fn create_rects(n: usize) -> Vec<Rect> {
+        let mut seed = 0u64;
+        let mut next = move || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 40) as Real / (1u64 << 24) as Real
+        };
+        (0..n)
+            .map(|_| {
+                let min = Point2::new(next() * 100.0 - 50.0, next() * 100.0 - 50.0);
+                let size = Vector2::new(next() * 5.0, next() * 5.0);
+                Rect::with_bounds(min, min + size)
+            })
+            .collect()
+    }
+
+    /// Returns the sorted indices of the shapes which are hit by a brute force test.
+    fn brute_force(query: &impl IntersectionRect, rects: &[Rect]) -> Vec<usize> {
+        (0..rects.len())
+            .filter(|&i| query.intersects_rect(&rects[i]))
+            .collect()
+    }
+
+    fn sorted(mut indices: Vec<usize>) -> Vec<usize> {
+        indices.sort_unstable();
+        indices
+    }
+
+    #[test]
+    /// Tests that the inner nodes of a `BVH2D` bound their subtrees, and that every shape
+    /// is in exactly one leaf.
+    fn test_build_bvh2d() {
+        let rects = create_rects(500);
+        let bvh = BVH2D::build(&rects);
+        assert_eq!(bvh.nodes.len(), 2 * rects.len() - 1);
+
+        let mut seen = vec![false; rects.len()];
+        let mut stack = vec![(0, Rect::empty())];
+        while let Some((node_index, parent_rect)) = stack.pop() {
+            match bvh.nodes[node_index] {
+                BVH2DNode::Node {
+                    child_l_index,
+                    child_l_rect,
+                    child_r_index,
+                    child_r_rect,
+                } => {
+                    stack.push((child_l_index, child_l_rect));
+                    stack.push((child_r_index, child_r_rect));
+                }
+                BVH2DNode::Leaf { shape_index } => {
+                    assert!(!seen[shape_index]);
+                    seen[shape_index] = true;
+                    if node_index != 0 {
+                        assert_eq!(rects[shape_index].join(&parent_rect), parent_rect);
+                    }
+                }
+            }
+        }
+        assert!(seen.iter().all(|&seen| seen));
+
+        assert!(BVH2D::build::<Rect>(&[]).nodes.is_empty());
+        let single = [Point2::new(1.0, 2.0)];
+        let bvh = BVH2D::build(&single);
+        assert_eq!(
+            bvh.traverse_indices(&Point2::new(1.0, 2.0), &single),
+            vec![0]
+        );
+    }
+
+    #[test]
+    /// Tests that point, rectangle and ray queries find the same shapes as brute force.
+    fn test_traverse_bvh2d_matches_brute_force() {
+        let rects = create_rects(500);
+        let bvh = BVH2D::build(&rects);
+
+        let queries = create_rects(50);
+        for query in queries.iter() {
+            let point = query.center();
+            assert_eq!(
+                sorted(bvh.traverse_indices(&point, &rects)),
+                brute_force(&point, &rects)
+            );
+            assert_eq!(
+                sorted(bvh.traverse_indices(query, &rects)),
+                brute_force(query, &rects)
+            );
+
+            let ray = Ray2::new(query.min, query.size() - Vector2::new(2.5, 2.5));
+            assert_eq!(
+                sorted(bvh.traverse_indices(&ray, &rects)),
+                brute_force(&ray, &rects)
+            );
+            assert_eq!(
+                bvh.traverse(&ray, &rects).len(),
+                brute_force(&ray, &rects).len()
+            );
+        }
+    }
+
+    #[test]
+    /// Tests that the nearest hit of a `Ray2` is the smallest entry distance of all hits.
+    fn test_traverse_nearest_bvh2d() {
+        let rects = create_rects(500);
+        let bvh = BVH2D::build(&rects);
+        let entry = |ray: &Ray2, rect: &Rect| {
+            ray.intersects_rect_interval(rect)
+                .map(|(entry, _)| entry.max(0.0))
+        };
+
+        for query in create_rects(50).iter() {
+            let ray = Ray2::new(query.min, query.size() - Vector2::new(2.5, 2.5));
+            let expected = rects
+                .iter()
+                .filter_map(|rect| entry(&ray, rect))
+                .fold(Real::INFINITY, Real::min);
+            let nearest = bvh.traverse_nearest_with(&ray, &rects, |rect| entry(&ray, rect));
+            match nearest {
+                Some((rect, distance)) => {
+                    assert_eq!(distance, expected);
+                    assert_eq!(entry(&ray, rect), Some(expected));
+                }
+                None => assert_eq!(expected, Real::INFINITY),
+            }
+        }
+    }
+
+    #[test]
+    /// Tests the measures of a `Rect`, and that empty `Rect`s have no area or perimeter.
+    fn test_rect_measures() {
+        let rect = Rect::with_bounds(Point2::new(-1.0, 2.0), Point2::new(3.0, 3.0));
+        assert_eq!(rect.size(), Vector2::new(4.0, 1.0));
+        assert_eq!(rect.center(), Point2::new(1.0, 2.5));
+        assert_eq!(rect.area(), 4.0);
+        assert_eq!(rect.perimeter(), 10.0);
+        assert_eq!(rect.largest_axis(), 0);
+        assert_eq!(rect.rect(), rect);
+
+        let empty = Rect::default();
+        assert!(empty.is_empty());
+        assert_eq!(empty.area(), 0.0);
+        assert_eq!(empty.perimeter(), 0.0);
+        assert_eq!(empty.join(&rect), rect);
+        assert!(!empty.overlaps(&rect));
+    }
+}
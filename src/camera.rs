@@ -0,0 +1,166 @@
+//! This module defines a [`Camera`], which creates primary and picking rays for points on
+//! the screen from the view and projection matrices of a renderer.
+//!
+//! [`Camera`]: struct.Camera.html
+//!
+
+use crate::frustum::Frustum;
+use crate::ray::Ray;
+use crate::{Mat4, Point3, Real};
+
+/// Creates the rays which pass through points of the screen, given the view and projection
+/// matrices of a camera. Normalized device coordinates (NDC) range from `-1` to `1`, with
+/// `y` pointing up, while pixel coordinates start at the top left corner of the viewport.
+/// Rays start on the near plane, so shapes between the camera and its near plane are not
+/// hit, like when rendering. Projections with a reversed depth range are not supported.
+///
+/// # Examples
+/// ```
+/// use bvh::camera::Camera;
+/// use bvh::{Mat4, Point3, Vector3};
+///
+/// // A camera at (0, 0, 5) looking at the origin.
+/// let view = Mat4::look_at_rh(Point3::new(0.0, 0.0, 5.0), Point3::ZERO, Vector3::Y);
+/// let projection = Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 2.0, 0.1, 100.0);
+/// let camera = Camera::new(&view, &projection);
+///
+/// let center = camera.ray_from_pixel(400.0, 200.0, 800.0, 400.0);
+/// assert!(center.direction.abs_diff_eq(Vector3::new(0.0, 0.0, -1.0), 0.0001));
+/// assert!(center.origin.abs_diff_eq(Point3::new(0.0, 0.0, 4.9), 0.0001));
+///
+/// // The right edge of the screen is 90 degrees wide, seen at twice the height.
+/// let right = camera.ray_from_ndc(1.0, 0.0);
+/// assert!(right.direction.abs_diff_eq(Vector3::new(2.0, 0.0, -1.0).normalize(), 0.0001));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    /// The product of the projection and the view matrix.
+    view_projection: Mat4,
+    /// Maps normalized device coordinates back to world space.
+    inverse_view_projection: Mat4,
+    /// Whether the depth of the clip space ranges from `-1` instead of `0` to `1`.
+    gl_depth: bool,
+}
+
+impl Camera {
+    /// Creates a camera from a `view` matrix, which maps world space to the space of the
+    /// camera, and a `projection` matrix with a depth range of `[0, 1]`, like the
+    /// projections of `glam` without a `_gl` suffix and the ones used with Direct3D, Metal,
+    /// Vulkan and `wgpu`.
+    pub fn new(view: &Mat4, projection: &Mat4) -> Camera {
+        Camera::from_view_projection(*projection * *view, false)
+    }
+
+    /// Creates a camera from a `view` matrix and a `projection` matrix with a depth range of
+    /// `[-1, 1]`, like the `_gl` projections of `glam` and the ones used with OpenGL.
+    pub fn new_gl(view: &Mat4, projection: &Mat4) -> Camera {
+        Camera::from_view_projection(*projection * *view, true)
+    }
+
+    fn from_view_projection(view_projection: Mat4, gl_depth: bool) -> Camera {
+        Camera {
+            view_projection,
+            inverse_view_projection: view_projection.inverse(),
+            gl_depth,
+        }
+    }
+
+    /// Returns the product of the projection and the view matrix.
+    pub fn view_projection(&self) -> &Mat4 {
+        &self.view_projection
+    }
+
+    /// Returns the [`Frustum`] of the camera, e.g. to cull the shapes it cannot see.
+    ///
+    /// [`Frustum`]: ../frustum/struct.Frustum.html
+    ///
+    pub fn frustum(&self) -> Frustum {
+        if self.gl_depth {
+            Frustum::from_matrix_gl(&self.view_projection)
+        } else {
+            Frustum::from_matrix(&self.view_projection)
+        }
+    }
+
+    /// Returns the ray through the point `(x, y)` in normalized device coordinates, where
+    /// `(-1, -1)` is the bottom left and `(1, 1)` the top right corner of the screen.
+    pub fn ray_from_ndc(&self, x: Real, y: Real) -> Ray {
+        // The second point lies halfway to the far plane, which is at infinity for infinite
+        // projections.
+        let near_depth = if self.gl_depth { -1.0 } else { 0.0 };
+        let near = self.unproject(x, y, near_depth);
+        let middle = self.unproject(x, y, (near_depth + 1.0) / 2.0);
+        Ray::new(near, middle - near)
+    }
+
+    /// Returns the ray through the pixel coordinates `(x, y)` of a viewport which is `width`
+    /// pixels wide and `height` pixels high. The center of the top left pixel is at
+    /// `(0.5, 0.5)`.
+    pub fn ray_from_pixel(&self, x: Real, y: Real, width: Real, height: Real) -> Ray {
+        self.ray_from_ndc(2.0 * x / width - 1.0, 1.0 - 2.0 * y / height)
+    }
+
+    /// Maps a point in normalized device coordinates to world space.
+    fn unproject(&self, x: Real, y: Real, depth: Real) -> Point3 {
+        self.inverse_view_projection
+            .project_point3(Point3::new(x, y, depth))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::AABB;
+    use crate::camera::Camera;
+    use crate::ray::Ray;
+    use crate::testbase::next_point3;
+    use crate::{Mat4, Point3, Real, Vector3, PI};
+
+    #[test]
+    /// Tests that rays through the projections of random points pass through the points, for
+    /// perspective and orthographic projections with both depth ranges.
+    fn test_camera_rays_hit_projected_points() {
+        let eye = Point3::new(3.0, 4.0, 10.0);
+        let view = Mat4::look_at_rh(eye, Point3::ZERO, Vector3::Y);
+        let cameras = [
+            Camera::new(&view, &Mat4::perspective_rh(PI / 3.0, 1.5, 0.5, 100.0)),
+            Camera::new_gl(&view, &Mat4::perspective_rh_gl(PI / 3.0, 1.5, 0.5, 100.0)),
+            Camera::new(&view, &Mat4::perspective_infinite_rh(PI / 3.0, 1.5, 0.5)),
+            Camera::new(
+                &view,
+                &Mat4::orthographic_rh(-4.0, 4.0, -3.0, 3.0, 0.5, 100.0),
+            ),
+            Camera::new_gl(
+                &view,
+                &Mat4::orthographic_rh_gl(-4.0, 4.0, -3.0, 3.0, 0.5, 100.0),
+            ),
+        ];
+        let bounds = AABB::with_bounds(Point3::splat(-2.0), Point3::splat(2.0));
+        let mut seed = 0;
+        for camera in cameras.iter() {
+            assert!(camera.frustum().contains(&Point3::ZERO));
+            for _ in 0..100 {
+                let point = next_point3(&mut seed, &bounds);
+                let ndc = camera.view_projection().project_point3(point);
+                let ray = camera.ray_from_ndc(ndc.x, ndc.y);
+                let offset = point - ray.origin;
+                let along = offset.dot(ray.direction);
+                assert!(along > 0.0);
+                assert!((offset - ray.direction * along).length() < 1e-3);
+
+                // The same ray through the pixel of an 800 by 600 viewport.
+                let pixel = ray_from_pixel_of(camera, ndc.x, ndc.y);
+                assert!(pixel.origin.abs_diff_eq(ray.origin, 1e-3));
+                assert!(pixel.direction.abs_diff_eq(ray.direction, 1e-3));
+            }
+        }
+    }
+
+    /// Converts normalized device coordinates to the pixels of an 800 by 600 viewport and
+    /// returns the ray through them.
+    fn ray_from_pixel_of(camera: &Camera, x: Real, y: Real) -> Ray {
+        let (width, height) = (800.0, 600.0);
+        let pixel_x = (x + 1.0) / 2.0 * width;
+        let pixel_y = (1.0 - y) / 2.0 * height;
+        camera.ray_from_pixel(pixel_x, pixel_y, width, height)
+    }
+}
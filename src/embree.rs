@@ -0,0 +1,434 @@
+//! This module exports a [`BVH`] in the node layout of Embree's `BVH4`, so that trees can
+//! be shared with code that uses Embree and traversal results can be cross-validated.
+//!
+//! [`BVH`]: ../bvh/struct.BVH.html
+//!
+
+use crate::aabb::{Bounded, AABB};
+use crate::bounding_hierarchy::{BHShape, BoundingHierarchy, IntersectionAABB};
+use crate::bvh::BVH;
+use crate::wide_bvh::{WideNode, BVH4};
+use crate::Real;
+use std::mem;
+
+/// The bits of a node reference which encode its type, see [`EmbreeChild`].
+///
+/// [`EmbreeChild`]: enum.EmbreeChild.html
+///
+pub const EMBREE_ALIGN_MASK: u64 = 15;
+
+/// The type bit of a node reference which marks a leaf, like `tyLeaf` in Embree.
+pub const EMBREE_TY_LEAF: u64 = 8;
+
+/// The node reference of an unused child slot, like `emptyNode` in Embree.
+pub const EMBREE_EMPTY_NODE: u64 = EMBREE_TY_LEAF;
+
+/// The maximum number of primitives which a leaf reference can encode.
+pub const EMBREE_MAX_LEAF_SIZE: usize = (EMBREE_ALIGN_MASK - EMBREE_TY_LEAF) as usize;
+
+/// A node with 4 children in the memory layout of Embree's `AABBNode` of a `BVH4`:
+///
+/// ```c
+/// struct AABBNode4 {
+///     size_t children[4];
+///     float lower_x[4], upper_x[4];
+///     float lower_y[4], upper_y[4];
+///     float lower_z[4], upper_z[4];
+/// };
+/// ```
+///
+/// The node is 128 bytes large and aligned to 16 bytes. The bounds are always stored as
+/// `f32`, even if the crate is built with `f64`, and unused slots have the empty bounds of
+/// [`AABB::empty`]. Embree stores pointers in `children`, whose lowest 4 bits encode the
+/// type of the child. Since pointers can not be serialized, the children are stored as
+/// offsets with the same type bits instead, see [`EmbreeChild`]. They are turned into
+/// Embree's pointers by adding the address of the node or primitive array. With the
+/// `bytemuck` feature, `EmbreeNode4` implements `bytemuck::Pod` and `bytemuck::Zeroable`.
+///
+/// [`AABB::empty`]: ../aabb/struct.AABB.html#method.empty
+/// [`EmbreeChild`]: enum.EmbreeChild.html
+///
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "bytemuck", derive(bytemuck::Pod, bytemuck::Zeroable))]
+pub struct EmbreeNode4 {
+    /// The encoded references to the children, see [`EmbreeChild`].
+    ///
+    /// [`EmbreeChild`]: enum.EmbreeChild.html
+    ///
+    pub children: [u64; 4],
+    /// The minimum x coordinates of the children's bounds.
+    pub lower_x: [f32; 4],
+    /// The maximum x coordinates of the children's bounds.
+    pub upper_x: [f32; 4],
+    /// The minimum y coordinates of the children's bounds.
+    pub lower_y: [f32; 4],
+    /// The maximum y coordinates of the children's bounds.
+    pub upper_y: [f32; 4],
+    /// The minimum z coordinates of the children's bounds.
+    pub lower_z: [f32; 4],
+    /// The maximum z coordinates of the children's bounds.
+    pub upper_z: [f32; 4],
+}
+
+impl EmbreeNode4 {
+    /// Converts a node of a [`BVH4`], whose children are encoded with `encode`.
+    ///
+    /// [`BVH4`]: ../wide_bvh/type.BVH4.html
+    ///
+    #[allow(clippy::unnecessary_cast)]
+    fn new(node: &WideNode<4>, mut encode: impl FnMut(u32) -> u64) -> EmbreeNode4 {
+        EmbreeNode4 {
+            children: [
+                encode(node.children[0]),
+                encode(node.children[1]),
+                encode(node.children[2]),
+                encode(node.children[3]),
+            ],
+            lower_x: node.min_x.map(|x| x as f32),
+            upper_x: node.max_x.map(|x| x as f32),
+            lower_y: node.min_y.map(|y| y as f32),
+            upper_y: node.max_y.map(|y| y as f32),
+            lower_z: node.min_z.map(|z| z as f32),
+            upper_z: node.max_z.map(|z| z as f32),
+        }
+    }
+
+    /// Returns the [`AABB`] of the child in `slot`.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    #[allow(clippy::unnecessary_cast)]
+    pub fn child_aabb(&self, slot: usize) -> AABB {
+        let mut aabb = AABB::empty();
+        aabb.min.x = self.lower_x[slot] as Real;
+        aabb.min.y = self.lower_y[slot] as Real;
+        aabb.min.z = self.lower_z[slot] as Real;
+        aabb.max.x = self.upper_x[slot] as Real;
+        aabb.max.y = self.upper_y[slot] as Real;
+        aabb.max.z = self.upper_z[slot] as Real;
+        aabb
+    }
+}
+
+/// A decoded child reference of an [`EmbreeNode4`].
+///
+/// [`EmbreeNode4`]: struct.EmbreeNode4.html
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmbreeChild {
+    /// An unused slot, encoded as [`EMBREE_EMPTY_NODE`].
+    ///
+    /// [`EMBREE_EMPTY_NODE`]: constant.EMBREE_EMPTY_NODE.html
+    ///
+    Empty,
+    /// An inner node, encoded as its byte offset in [`EmbreeBVH4::nodes`], whose type bits
+    /// are zero since the nodes are 128 bytes large.
+    ///
+    /// [`EmbreeBVH4::nodes`]: struct.EmbreeBVH4.html#structfield.nodes
+    ///
+    Node(usize),
+    /// A leaf, encoded as its first primitive in [`EmbreeBVH4::primitives`] shifted by 4
+    /// bits, with [`EMBREE_TY_LEAF`] plus the number of primitives as type bits.
+    ///
+    /// [`EMBREE_TY_LEAF`]: constant.EMBREE_TY_LEAF.html
+    /// [`EmbreeBVH4::primitives`]: struct.EmbreeBVH4.html#structfield.primitives
+    ///
+    Leaf {
+        /// The index of the first primitive of the leaf.
+        first_primitive: usize,
+        /// The number of primitives of the leaf, at most [`EMBREE_MAX_LEAF_SIZE`].
+        ///
+        /// [`EMBREE_MAX_LEAF_SIZE`]: constant.EMBREE_MAX_LEAF_SIZE.html
+        ///
+        primitive_count: usize,
+    },
+}
+
+impl EmbreeChild {
+    /// Encodes the child as a node reference.
+    ///
+    /// # Panics
+    /// Panics if a leaf has no primitives or more than [`EMBREE_MAX_LEAF_SIZE`].
+    ///
+    /// [`EMBREE_MAX_LEAF_SIZE`]: constant.EMBREE_MAX_LEAF_SIZE.html
+    ///
+    pub fn encode(&self) -> u64 {
+        match *self {
+            EmbreeChild::Empty => EMBREE_EMPTY_NODE,
+            EmbreeChild::Node(index) => (index * mem::size_of::<EmbreeNode4>()) as u64,
+            EmbreeChild::Leaf {
+                first_primitive,
+                primitive_count,
+            } => {
+                assert!(
+                    primitive_count > 0 && primitive_count <= EMBREE_MAX_LEAF_SIZE,
+                    "Invalid number of primitives in a leaf."
+                );
+                ((first_primitive as u64) << 4) | EMBREE_TY_LEAF | primitive_count as u64
+            }
+        }
+    }
+
+    /// Decodes a node reference.
+    pub fn decode(node_ref: u64) -> EmbreeChild {
+        if node_ref == EMBREE_EMPTY_NODE {
+            EmbreeChild::Empty
+        } else if node_ref & EMBREE_TY_LEAF != 0 {
+            EmbreeChild::Leaf {
+                first_primitive: (node_ref >> 4) as usize,
+                primitive_count: (node_ref & (EMBREE_ALIGN_MASK - EMBREE_TY_LEAF)) as usize,
+            }
+        } else {
+            EmbreeChild::Node(node_ref as usize / mem::size_of::<EmbreeNode4>())
+        }
+    }
+}
+
+/// A [`BVH`] exported in the layout of Embree's `BVH4`, see [`BVH::flatten_embree`].
+///
+/// [`BVH`]: ../bvh/struct.BVH.html
+/// [`BVH::flatten_embree`]: ../bvh/struct.BVH.html#method.flatten_embree
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmbreeBVH4 {
+    /// The reference to the root, which is either the first node or [`EMBREE_EMPTY_NODE`]
+    /// for an empty tree.
+    ///
+    /// [`EMBREE_EMPTY_NODE`]: constant.EMBREE_EMPTY_NODE.html
+    ///
+    pub root: u64,
+    /// The nodes, which are referenced by their byte offset in this array.
+    pub nodes: Vec<EmbreeNode4>,
+    /// The shape indices referenced by the leaves.
+    pub primitives: Vec<u32>,
+}
+
+impl BVH {
+    /// Exports the [`BVH`] in the node layout of Embree's `BVH4`. The tree is collapsed like
+    /// [`BVH::flatten_wide`], and every leaf references a single primitive.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::{BHShape, BoundingHierarchy};
+    /// use bvh::bvh::BVH;
+    /// use bvh::embree::EmbreeChild;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..16)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let bvh = BVH::build(&mut cubes);
+    /// let embree = bvh.flatten_embree(&cubes);
+    /// assert_eq!(EmbreeChild::decode(embree.root), EmbreeChild::Node(0));
+    /// assert_eq!(embree.nodes.len(), 5);
+    /// assert_eq!(embree.primitives.len(), 16);
+    ///
+    /// let ray = Ray::new(Point3::new(6.0, -10.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+    /// assert_eq!(embree.traverse(&ray, &cubes).len(), 1);
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::flatten_wide`]: struct.BVH.html#method.flatten_wide
+    ///
+    pub fn flatten_embree<Shape: BHShape>(&self, shapes: &[Shape]) -> EmbreeBVH4 {
+        let wide: BVH4 = self.flatten_wide(shapes);
+        let mut primitives = Vec::with_capacity(shapes.len());
+        let nodes = wide
+            .nodes
+            .iter()
+            .map(|node| {
+                EmbreeNode4::new(node, |child| {
+                    let child = if child == WideNode::<4>::EMPTY {
+                        EmbreeChild::Empty
+                    } else if child & WideNode::<4>::LEAF_FLAG != 0 {
+                        primitives.push(child & !WideNode::<4>::LEAF_FLAG);
+                        EmbreeChild::Leaf {
+                            first_primitive: primitives.len() - 1,
+                            primitive_count: 1,
+                        }
+                    } else {
+                        EmbreeChild::Node(child as usize)
+                    };
+                    child.encode()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let root = if nodes.is_empty() {
+            EmbreeChild::Empty
+        } else {
+            EmbreeChild::Node(0)
+        };
+        EmbreeBVH4 {
+            root: root.encode(),
+            nodes,
+            primitives,
+        }
+    }
+}
+
+impl BoundingHierarchy for EmbreeBVH4 {
+    /// An [`EmbreeBVH4`] is built from a regular [`BVH`] using the [`BVH::flatten_embree`]
+    /// method.
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`BVH::flatten_embree`]: ../bvh/struct.BVH.html#method.flatten_embree
+    /// [`EmbreeBVH4`]: struct.EmbreeBVH4.html
+    ///
+    fn build<T: BHShape>(shapes: &mut [T]) -> EmbreeBVH4 {
+        let bvh = BVH::build(shapes);
+        bvh.flatten_embree(shapes)
+    }
+
+    /// Traverses an [`EmbreeBVH4`] by decoding its node references like Embree does.
+    ///
+    /// [`EmbreeBVH4`]: struct.EmbreeBVH4.html
+    ///
+    fn traverse<'a, T: Bounded>(
+        &'a self,
+        ray: &impl IntersectionAABB,
+        shapes: &'a [T],
+    ) -> Vec<&'a T> {
+        let mut hit_shapes = Vec::new();
+        let mut stack = vec![self.root];
+        while let Some(node_ref) = stack.pop() {
+            match EmbreeChild::decode(node_ref) {
+                EmbreeChild::Empty => {}
+                EmbreeChild::Node(index) => {
+                    let node = &self.nodes[index];
+                    for slot in 0..4 {
+                        if node.children[slot] != EMBREE_EMPTY_NODE
+                            && ray.intersects_aabb(&node.child_aabb(slot))
+                        {
+                            stack.push(node.children[slot]);
+                        }
+                    }
+                }
+                EmbreeChild::Leaf {
+                    first_primitive,
+                    primitive_count,
+                } => {
+                    let leaf = &self.primitives[first_primitive..first_primitive + primitive_count];
+                    hit_shapes.extend(leaf.iter().map(|&shape| &shapes[shape as usize]));
+                }
+            }
+        }
+        hit_shapes
+    }
+
+    /// Prints a textual representation of an [`EmbreeBVH4`].
+    ///
+    /// [`EmbreeBVH4`]: struct.EmbreeBVH4.html
+    ///
+    fn pretty_print(&self) {
+        for (i, node) in self.nodes.iter().enumerate() {
+            let children: Vec<String> = node
+                .children
+                .iter()
+                .map(|&child| format!("{:?}", EmbreeChild::decode(child)))
+                .collect();
+            println!("{}\t{}", i, children.join(", "));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bounding_hierarchy::BoundingHierarchy;
+    use crate::bvh::BVH;
+    use crate::embree::{EmbreeBVH4, EmbreeChild, EmbreeNode4, EMBREE_EMPTY_NODE};
+    use crate::testbase::{
+        build_some_bh, create_n_cubes, create_ray, default_bounds, query_some_bh, traverse_some_bh,
+    };
+    use std::mem;
+
+    #[test]
+    /// Tests whether the building procedure succeeds in not failing.
+    fn test_build_embree_bvh() {
+        build_some_bh::<EmbreeBVH4>();
+    }
+
+    #[test]
+    /// Runs some primitive tests for intersections of a ray with a fixed scene given as an
+    /// `EmbreeBVH4`.
+    fn test_traverse_embree_bvh() {
+        traverse_some_bh::<EmbreeBVH4>();
+    }
+
+    #[test]
+    /// Runs the nearest hit and nearest neighbor queries on an `EmbreeBVH4`.
+    fn test_query_embree_bvh() {
+        query_some_bh::<EmbreeBVH4>();
+    }
+
+    #[test]
+    /// Tests the size of the nodes and the encoding of the node references.
+    fn test_embree_node_layout() {
+        assert_eq!(mem::size_of::<EmbreeNode4>(), 128);
+        assert_eq!(mem::align_of::<EmbreeNode4>(), 16);
+
+        assert_eq!(EmbreeChild::Empty.encode(), EMBREE_EMPTY_NODE);
+        assert_eq!(EmbreeChild::Node(3).encode(), 384);
+        let leaf = EmbreeChild::Leaf {
+            first_primitive: 5,
+            primitive_count: 1,
+        };
+        assert_eq!(leaf.encode(), 0x59);
+        for child in [EmbreeChild::Empty, EmbreeChild::Node(3), leaf] {
+            assert_eq!(EmbreeChild::decode(child.encode()), child);
+        }
+
+        let empty = BVH { nodes: Vec::new() }.flatten_embree::<crate::testbase::Triangle>(&[]);
+        assert_eq!(empty.root, EMBREE_EMPTY_NODE);
+        assert!(empty.nodes.is_empty());
+    }
+
+    #[test]
+    /// Checks that every shape is referenced exactly once and that the traversal matches
+    /// the binary `BVH`.
+    fn test_flatten_embree_matches_bvh() {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(200, &bounds);
+        let bvh = BVH::build(&mut triangles);
+        let embree = bvh.flatten_embree(&triangles);
+
+        let mut primitives = embree.primitives.clone();
+        primitives.sort_unstable();
+        assert!(primitives
+            .iter()
+            .map(|&shape| shape as usize)
+            .eq(0..triangles.len()));
+
+        let mut seed = 0;
+        for _ in 0..100 {
+            let ray = create_ray(&mut seed, &bounds);
+            let mut expected: Vec<usize> = bvh.traverse_indices(&ray);
+            let mut actual: Vec<usize> = embree
+                .traverse(&ray, &triangles)
+                .iter()
+                .map(|triangle| {
+                    triangles
+                        .iter()
+                        .position(|other| std::ptr::eq(other, *triangle))
+                        .unwrap()
+                })
+                .collect();
+            expected.sort_unstable();
+            actual.sort_unstable();
+            assert_eq!(expected, actual);
+        }
+    }
+}
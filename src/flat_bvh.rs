@@ -3,12 +3,35 @@
 use crate::aabb::{Bounded, AABB};
 use crate::bounding_hierarchy::{BHShape, BoundingHierarchy, IntersectionAABB};
 use crate::bvh::{BVHNode, BVH};
+use crate::utils::{f16_bits_rounded, f16_bits_to_f32};
+use crate::{Point3, Real, EPSILON};
+use std::borrow::Borrow;
+use std::convert::TryFrom;
+use std::fmt;
+use std::mem;
+use std::ops::Deref;
 
 /// A structure of a node of a flat [`BVH`]. The structure of the nodes allows for an
 /// iterative traversal approach without the necessity to maintain a stack or queue.
 ///
+/// The node is `repr(C)`, so that it can be serialized and loaded without conversion, see
+/// [`FlatBVHBytes`]. With the `rkyv` feature, the node is its own archived type, so an
+/// archived [`FlatBVH`] can be used as a slice of nodes in place. With the `serde_impls`
+/// feature, the node is serialized as a struct with the fields `aabb`, `entry_index`,
+/// `exit_index` and `shape_index`, which will not be renamed or reordered.
+///
 /// [`BVH`]: ../bvh/struct.BVH.html
+/// [`FlatBVH`]: type.FlatBVH.html
+/// [`FlatBVHBytes`]: trait.FlatBVHBytes.html
 ///
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize),
+    archive(as = "FlatNode")
+)]
 pub struct FlatNode {
     /// The [`AABB`] of the [`BVH`] node. Prior to testing the [`AABB`] bounds,
     /// the `entry_index` must be checked. In case the entry_index is [`u32::max_value()`],
@@ -240,7 +263,9 @@ impl BVH {
         F: Fn(&AABB, u32, u32, u32) -> FNodeType,
     {
         let mut vec = Vec::new();
-        self.nodes[0].flatten_custom(&self.nodes, &mut vec, shapes, 0, constructor);
+        if let Some(root) = self.nodes.first() {
+            root.flatten_custom(&self.nodes, &mut vec, shapes, 0, constructor);
+        }
         vec
     }
 
@@ -312,6 +337,446 @@ impl BVH {
             shape_index: shape,
         })
     }
+
+    /// Flattens the [`BVH`] into [`GpuNode`]s, which can be uploaded to the GPU as they are.
+    /// With the `bytemuck` feature the result can be turned into bytes with
+    /// `bytemuck::cast_slice`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::flat_bvh::GpuNode;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..10)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let bvh = BVH::build(&mut cubes);
+    /// let gpu_nodes = bvh.flatten_gpu(&cubes);
+    /// assert_eq!(gpu_nodes.len(), bvh.flatten(&cubes).len());
+    /// assert_eq!(std::mem::size_of_val(gpu_nodes.as_slice()), gpu_nodes.len() * 48);
+    /// ```
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`GpuNode`]: struct.GpuNode.html
+    ///
+    pub fn flatten_gpu<T: BHShape>(&self, shapes: &[T]) -> Vec<GpuNode> {
+        self.flatten_custom(shapes, &|aabb, entry, exit, shape| {
+            GpuNode::new(aabb, entry, exit, shape)
+        })
+    }
+}
+
+/// A node of a flat [`BVH`] with a fixed memory layout, which matches the following struct
+/// in a std430 (and std140) GLSL storage buffer:
+///
+/// ```glsl
+/// struct Node {
+///     vec3 aabb_min;
+///     uint entry_index;
+///     vec3 aabb_max;
+///     uint exit_index;
+///     uint shape_index;
+///     uint _padding[3];
+/// };
+/// ```
+///
+/// The node is 48 bytes large and aligned to 16 bytes. The bounds are always stored as
+/// `f32`, even if the crate is built with `f64`. The indices have the same meaning as in
+/// [`FlatNode`]. With the `bytemuck` feature, `GpuNode` implements `bytemuck::Pod` and
+/// `bytemuck::Zeroable`.
+///
+/// [`BVH`]: ../bvh/struct.BVH.html
+/// [`FlatNode`]: struct.FlatNode.html
+///
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "bytemuck", derive(bytemuck::Pod, bytemuck::Zeroable))]
+pub struct GpuNode {
+    /// The minimum corner of the [`AABB`] of the node.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub aabb_min: [f32; 3],
+
+    /// The index of the node to jump to, if the [`AABB`] test is positive, or
+    /// [`u32::MAX`] for leaf nodes.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`u32::MAX`]: https://doc.rust-lang.org/std/u32/constant.MAX.html
+    ///
+    pub entry_index: u32,
+
+    /// The maximum corner of the [`AABB`] of the node.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub aabb_max: [f32; 3],
+
+    /// The index of the node to jump to, if the [`AABB`] test is negative.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub exit_index: u32,
+
+    /// The index of the shape in the shapes array.
+    pub shape_index: u32,
+
+    /// Padding up to the 16 byte alignment of the node.
+    pub _padding: [u32; 3],
+}
+
+impl GpuNode {
+    /// Creates a new node. The bounds are converted to `f32`.
+    #[allow(clippy::unnecessary_cast)]
+    pub fn new(aabb: &AABB, entry_index: u32, exit_index: u32, shape_index: u32) -> GpuNode {
+        GpuNode {
+            aabb_min: [aabb.min.x as f32, aabb.min.y as f32, aabb.min.z as f32],
+            entry_index,
+            aabb_max: [aabb.max.x as f32, aabb.max.y as f32, aabb.max.z as f32],
+            exit_index,
+            shape_index,
+            _padding: [0; 3],
+        }
+    }
+}
+
+/// A node of a flat [`BVH`] whose leaves reference a range of primitives instead of a single
+/// shape. Created by [`BVH::flatten_with_ranges`].
+///
+/// [`BVH`]: ../bvh/struct.BVH.html
+/// [`BVH::flatten_with_ranges`]: ../bvh/struct.BVH.html#method.flatten_with_ranges
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct FlatRangeNode {
+    /// The [`AABB`] of the node, which encloses all primitives of a leaf.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub aabb: AABB,
+
+    /// The index of the node to jump to, if the [`AABB`] test is positive.
+    /// If this value is [`u32::MAX`] then the current node is a leaf node.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`u32::MAX`]: https://doc.rust-lang.org/std/u32/constant.MAX.html
+    ///
+    pub entry_index: u32,
+
+    /// The index of the node to jump to, if the [`AABB`] test is negative or after a leaf.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub exit_index: u32,
+
+    /// The position of the first primitive of a leaf in the primitive index array.
+    pub first_primitive: u32,
+
+    /// The number of primitives of a leaf. Zero for inner nodes.
+    pub primitive_count: u32,
+}
+
+impl BVH {
+    /// Returns the number of shapes below every node.
+    pub(crate) fn subtree_shape_counts(&self) -> Vec<u32> {
+        let mut counts = vec![0; self.nodes.len()];
+        let mut stack = vec![(0, false)];
+        while let Some((node_index, children_done)) = stack.pop() {
+            match self.nodes[node_index] {
+                BVHNode::Leaf { .. } => counts[node_index] = 1,
+                BVHNode::Node {
+                    child_l_index,
+                    child_r_index,
+                    ..
+                } => {
+                    if children_done {
+                        counts[node_index] = counts[child_l_index] + counts[child_r_index];
+                    } else {
+                        stack.push((node_index, true));
+                        stack.push((child_r_index, false));
+                        stack.push((child_l_index, false));
+                    }
+                }
+            }
+        }
+        counts
+    }
+
+    /// Appends the flat nodes of the subtree below `node_index` with the bounds `aabb`.
+    /// Subtrees with at most `max_leaf_size` shapes become a single leaf, whose shapes are
+    /// appended to `primitives`.
+    #[allow(clippy::too_many_arguments)]
+    fn flatten_range_subtree<F, FNodeType>(
+        &self,
+        node_index: usize,
+        aabb: &AABB,
+        counts: &[u32],
+        max_leaf_size: u32,
+        vec: &mut Vec<FNodeType>,
+        primitives: &mut Vec<u32>,
+        constructor: &F,
+    ) where
+        F: Fn(&AABB, u32, u32, u32, u32) -> FNodeType,
+    {
+        let index = vec.len();
+        if counts[node_index] <= max_leaf_size {
+            let first = primitives.len() as u32;
+            let mut stack = vec![node_index];
+            while let Some(index) = stack.pop() {
+                match self.nodes[index] {
+                    BVHNode::Leaf { shape_index, .. } => primitives.push(shape_index as u32),
+                    BVHNode::Node {
+                        child_l_index,
+                        child_r_index,
+                        ..
+                    } => {
+                        stack.push(child_r_index);
+                        stack.push(child_l_index);
+                    }
+                }
+            }
+            vec.push(constructor(
+                aabb,
+                u32::MAX,
+                index as u32 + 1,
+                first,
+                counts[node_index],
+            ));
+            return;
+        }
+
+        // Create a dummy node, which is replaced once the end of the subtree is known.
+        vec.push(constructor(&AABB::empty(), 0, 0, 0, 0));
+        self.flatten_range_children(
+            node_index,
+            counts,
+            max_leaf_size,
+            vec,
+            primitives,
+            constructor,
+        );
+        vec[index] = constructor(aabb, index as u32 + 1, vec.len() as u32, 0, 0);
+    }
+
+    /// Appends the flat nodes of both children of the inner node `node_index`.
+    fn flatten_range_children<F, FNodeType>(
+        &self,
+        node_index: usize,
+        counts: &[u32],
+        max_leaf_size: u32,
+        vec: &mut Vec<FNodeType>,
+        primitives: &mut Vec<u32>,
+        constructor: &F,
+    ) where
+        F: Fn(&AABB, u32, u32, u32, u32) -> FNodeType,
+    {
+        let node = &self.nodes[node_index];
+        for (child_index, child_aabb) in [
+            (node.child_l(), node.child_l_aabb()),
+            (node.child_r(), node.child_r_aabb()),
+        ] {
+            self.flatten_range_subtree(
+                child_index,
+                &child_aabb,
+                counts,
+                max_leaf_size,
+                vec,
+                primitives,
+                constructor,
+            );
+        }
+    }
+
+    /// Flattens the [`BVH`] like [`BVH::flatten_custom`], but every subtree with at most
+    /// `max_leaf_size` shapes is emitted as a single leaf. Returns the flat nodes and the
+    /// primitive index array, which lists the shape indices in the order of the leaves.
+    /// Every leaf references the range of the primitive index array with its shapes.
+    ///
+    /// The `constructor` is fed the following arguments in this order:
+    ///
+    /// 1 - &AABB: The enclosing `AABB`
+    /// 2 - u32: The index of the nested node, [`u32::MAX`] for leaves
+    /// 3 - u32: The exit index
+    /// 4 - u32: The position of the first primitive of a leaf in the primitive index array
+    /// 5 - u32: The number of primitives of a leaf, zero for inner nodes
+    ///
+    /// # Panics
+    /// Panics if `max_leaf_size` is zero.
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`BVH::flatten_custom`]: ../bvh/struct.BVH.html#method.flatten_custom
+    /// [`u32::MAX`]: https://doc.rust-lang.org/std/u32/constant.MAX.html
+    ///
+    pub fn flatten_custom_with_ranges<F, FNodeType, T: BHShape>(
+        &self,
+        shapes: &[T],
+        max_leaf_size: u32,
+        constructor: &F,
+    ) -> (Vec<FNodeType>, Vec<u32>)
+    where
+        F: Fn(&AABB, u32, u32, u32, u32) -> FNodeType,
+    {
+        assert!(max_leaf_size > 0, "Leaves need to hold at least one shape.");
+        let mut vec = Vec::new();
+        let mut primitives = Vec::with_capacity(shapes.len());
+        if self.nodes.is_empty() {
+            return (vec, primitives);
+        }
+
+        let counts = self.subtree_shape_counts();
+        if counts[0] <= max_leaf_size {
+            let aabb = self.nodes[0].get_node_aabb(shapes);
+            self.flatten_range_subtree(
+                0,
+                &aabb,
+                &counts,
+                max_leaf_size,
+                &mut vec,
+                &mut primitives,
+                constructor,
+            );
+        } else {
+            self.flatten_range_children(
+                0,
+                &counts,
+                max_leaf_size,
+                &mut vec,
+                &mut primitives,
+                constructor,
+            );
+        }
+        (vec, primitives)
+    }
+
+    /// Flattens the [`BVH`] into [`FlatRangeNode`]s with up to `max_leaf_size` primitives
+    /// per leaf, see [`BVH::flatten_custom_with_ranges`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::{BHShape, IntersectionAABB};
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..16)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let bvh = BVH::build(&mut cubes);
+    /// let (nodes, primitives) = bvh.flatten_with_ranges(&cubes, 4);
+    /// assert_eq!(primitives.len(), cubes.len());
+    ///
+    /// // Traverse the flat nodes without a stack and test the primitives of every leaf.
+    /// let ray = Ray::new(Point3::new(6.0, -10.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+    /// let mut hits = Vec::new();
+    /// let mut index = 0;
+    /// while index < nodes.len() {
+    ///     let node = &nodes[index];
+    ///     if !ray.intersects_aabb(&node.aabb) {
+    ///         index = node.exit_index as usize;
+    ///     } else if node.entry_index == u32::MAX {
+    ///         let first = node.first_primitive as usize;
+    ///         for &shape_index in &primitives[first..first + node.primitive_count as usize] {
+    ///             if ray.intersects_aabb(&cubes[shape_index as usize].aabb()) {
+    ///                 hits.push(shape_index);
+    ///             }
+    ///         }
+    ///         index = node.exit_index as usize;
+    ///     } else {
+    ///         index = node.entry_index as usize;
+    ///     }
+    /// }
+    /// assert_eq!(hits, vec![3]);
+    /// ```
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`BVH::flatten_custom_with_ranges`]: ../bvh/struct.BVH.html#method.flatten_custom_with_ranges
+    /// [`FlatRangeNode`]: struct.FlatRangeNode.html
+    ///
+    pub fn flatten_with_ranges<T: BHShape>(
+        &self,
+        shapes: &[T],
+        max_leaf_size: u32,
+    ) -> (Vec<FlatRangeNode>, Vec<u32>) {
+        self.flatten_custom_with_ranges(
+            shapes,
+            max_leaf_size,
+            &|aabb, entry, exit, first, count| FlatRangeNode {
+                aabb: *aabb,
+                entry_index: entry,
+                exit_index: exit,
+                first_primitive: first,
+                primitive_count: count,
+            },
+        )
+    }
+}
+
+/// Traverses flat nodes iteratively and calls `visit` with every hit shape, see
+/// [`FlatBVH::traverse`]. Generic over the node
+/// type, so that wrapped nodes like [`Aligned64`] can be traversed as well.
+///
+/// [`Aligned64`]: struct.Aligned64.html
+/// [`FlatBVH::traverse`]: type.FlatBVH.html
+///
+fn traverse_flat_nodes<'a, N: Borrow<FlatNode>, T: Bounded>(
+    nodes: &'a [N],
+    ray: &impl IntersectionAABB,
+    shapes: &'a [T],
+    mut visit: impl FnMut(&'a T),
+) {
+    let mut index = 0;
+
+    // The traversal loop should terminate when `max_length` is set as the next node index.
+    let max_length = nodes.len();
+
+    // Iterate while the node index is valid.
+    while index < max_length {
+        let node = nodes[index].borrow();
+
+        if node.entry_index == u32::max_value() {
+            // If the entry_index is MAX_UINT32, then it's a leaf node.
+            if ray.intersects_aabb(&node.aabb) {
+                visit(&shapes[node.shape_index as usize]);
+            }
+
+            // Exit the current node.
+            index = node.exit_index as usize;
+        } else if ray.intersects_aabb(&node.aabb) {
+            // If entry_index is not MAX_UINT32 and the AABB test passes, then
+            // proceed to the node in entry_index (which goes down the bvh branch).
+            index = node.entry_index as usize;
+        } else {
+            // If entry_index is not MAX_UINT32 and the AABB test fails, then
+            // proceed to the node in exit_index (which defines the next untested partition).
+            index = node.exit_index as usize;
+        }
+    }
 }
 
 impl BoundingHierarchy for FlatBVH {
@@ -391,38 +856,23 @@ impl BoundingHierarchy for FlatBVH {
     /// ```
     fn traverse<'a, T: Bounded>(&'a self, ray: &impl IntersectionAABB, shapes: &'a [T]) -> Vec<&T> {
         let mut hit_shapes = Vec::new();
-        let mut index = 0;
-
-        // The traversal loop should terminate when `max_length` is set as the next node index.
-        let max_length = self.len();
-
-        // Iterate while the node index is valid.
-        while index < max_length {
-            let node = &self[index];
-
-            if node.entry_index == u32::max_value() {
-                // If the entry_index is MAX_UINT32, then it's a leaf node.
-                if ray.intersects_aabb(&node.aabb) {
-                    let shape = &shapes[node.shape_index as usize];
-                    hit_shapes.push(shape);
-                }
-
-                // Exit the current node.
-                index = node.exit_index as usize;
-            } else if ray.intersects_aabb(&node.aabb) {
-                // If entry_index is not MAX_UINT32 and the AABB test passes, then
-                // proceed to the node in entry_index (which goes down the bvh branch).
-                index = node.entry_index as usize;
-            } else {
-                // If entry_index is not MAX_UINT32 and the AABB test fails, then
-                // proceed to the node in exit_index (which defines the next untested partition).
-                index = node.exit_index as usize;
-            }
-        }
-
+        traverse_flat_nodes(self, ray, shapes, |shape| hit_shapes.push(shape));
         hit_shapes
     }
 
+    /// Traverses a [`FlatBVH`] and calls `visit` during the traversal.
+    ///
+    /// [`FlatBVH`]: type.FlatBVH.html
+    ///
+    fn traverse_with<'a, T: Bounded>(
+        &'a self,
+        ray: &impl IntersectionAABB,
+        shapes: &'a [T],
+        visit: impl FnMut(&'a T),
+    ) {
+        traverse_flat_nodes(self, ray, shapes, visit)
+    }
+
     /// Prints a textual representation of a [`FlatBVH`].
     ///
     /// [`FlatBVH`]: struct.FlatBVH.html
@@ -437,55 +887,2825 @@ impl BoundingHierarchy for FlatBVH {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::flat_bvh::FlatBVH;
-    use crate::testbase::{build_some_bh, traverse_some_bh};
+/// A node of a [`SkipBVH`]. Every node of the [`BVH`] is stored exactly once, in depth-first
+/// order, so that the first child of an inner node directly follows it. If the [`AABB`] test
+/// of a node fails, or after a leaf, the traversal continues at the `skip_index`, which
+/// points behind the subtree of the node. No stack is needed, which makes this layout a good
+/// fit for traversal loops in GLSL or WGSL shaders:
+///
+/// ```glsl
+/// uint index = 0;
+/// while (index < node_count) {
+///     Node node = nodes[index];
+///     if (intersects(ray, node.aabb_min, node.aabb_max)) {
+///         if (node.shape_index != 0xFFFFFFFF) {
+///             intersect_shape(ray, node.shape_index);
+///         }
+///         index = node.shape_index == 0xFFFFFFFF ? index + 1 : node.skip_index;
+///     } else {
+///         index = node.skip_index;
+///     }
+/// }
+/// ```
+///
+/// [`AABB`]: ../aabb/struct.AABB.html
+/// [`BVH`]: ../bvh/struct.BVH.html
+/// [`SkipBVH`]: type.SkipBVH.html
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct SkipNode {
+    /// The [`AABB`] of the node.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub aabb: AABB,
 
-    #[test]
-    /// Tests whether the building procedure succeeds in not failing.
-    fn test_build_flat_bvh() {
-        build_some_bh::<FlatBVH>();
-    }
+    /// The index of the node to continue with, if the [`AABB`] test is negative or the node
+    /// is a leaf. Equal to the number of nodes for the last subtree.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub skip_index: u32,
 
-    #[test]
-    /// Runs some primitive tests for intersections of a ray with a fixed scene given
-    /// as a `FlatBVH`.
-    fn test_traverse_flat_bvh() {
-        traverse_some_bh::<FlatBVH>();
-    }
+    /// The index of the shape in the shapes array, or [`u32::MAX`] for inner nodes.
+    ///
+    /// [`u32::MAX`]: https://doc.rust-lang.org/std/u32/constant.MAX.html
+    ///
+    pub shape_index: u32,
 }
 
-#[cfg(all(feature = "bench", test))]
-mod bench {
-    use crate::bvh::BVH;
-    use crate::flat_bvh::FlatBVH;
-
-    use crate::testbase::{
-        build_1200_triangles_bh, build_120k_triangles_bh, build_12k_triangles_bh, create_n_cubes,
-        default_bounds, intersect_1200_triangles_bh, intersect_120k_triangles_bh,
-        intersect_12k_triangles_bh,
-    };
+/// A flat [`BVH`] with skip pointers, represented by a vector of [`SkipNode`]s. Unlike a
+/// [`FlatBVH`], it has no separate navigator nodes, so it only needs `2n - 1` nodes for `n`
+/// shapes.
+///
+/// [`BVH`]: ../bvh/struct.BVH.html
+/// [`FlatBVH`]: type.FlatBVH.html
+/// [`SkipNode`]: struct.SkipNode.html
+///
+#[allow(clippy::upper_case_acronyms)]
+pub type SkipBVH = Vec<SkipNode>;
 
-    #[bench]
-    /// Benchmark the flattening of a BVH with 120,000 triangles.
-    fn bench_flatten_120k_triangles_bvh(b: &mut ::test::Bencher) {
-        let bounds = default_bounds();
-        let mut triangles = create_n_cubes(10_000, &bounds);
-        let bvh = BVH::build(&mut triangles);
+impl BVH {
+    /// Flattens the [`BVH`] into a [`SkipBVH`], whose nodes store the index to continue
+    /// with when they are missed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::{BHShape, BoundingHierarchy};
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..10)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let bvh = BVH::build(&mut cubes);
+    /// let skip_bvh = bvh.flatten_skip(&cubes);
+    /// assert_eq!(skip_bvh.len(), bvh.nodes.len());
+    ///
+    /// let ray = Ray::new(Point3::new(6.0, -10.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+    /// assert_eq!(skip_bvh.traverse(&ray, &cubes).len(), 1);
+    /// ```
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`SkipBVH`]: type.SkipBVH.html
+    ///
+    pub fn flatten_skip<T: BHShape>(&self, shapes: &[T]) -> SkipBVH {
+        let mut skip_bvh = Vec::with_capacity(self.nodes.len());
+        if self.nodes.is_empty() {
+            return skip_bvh;
+        }
 
-        b.iter(|| {
-            bvh.flatten(&triangles);
-        });
+        let counts = self.subtree_shape_counts();
+        let root_aabb = self.nodes[0].get_node_aabb(shapes);
+        let mut stack = vec![(0, root_aabb)];
+        while let Some((node_index, aabb)) = stack.pop() {
+            // A subtree with `n` shapes consists of `2n - 1` nodes.
+            let skip_index = skip_bvh.len() as u32 + 2 * counts[node_index] - 1;
+            match self.nodes[node_index] {
+                BVHNode::Node {
+                    child_l_index,
+                    child_l_aabb,
+                    child_r_index,
+                    child_r_aabb,
+                    ..
+                } => {
+                    skip_bvh.push(SkipNode {
+                        aabb,
+                        skip_index,
+                        shape_index: u32::MAX,
+                    });
+                    stack.push((child_r_index, child_r_aabb));
+                    stack.push((child_l_index, child_l_aabb));
+                }
+                BVHNode::Leaf { shape_index, .. } => skip_bvh.push(SkipNode {
+                    aabb,
+                    skip_index,
+                    shape_index: shape_index as u32,
+                }),
+            }
+        }
+        skip_bvh
     }
-    #[bench]
-    /// Benchmark the construction of a `FlatBVH` with 1,200 triangles.
-    fn bench_build_1200_triangles_flat_bvh(b: &mut ::test::Bencher) {
-        build_1200_triangles_bh::<FlatBVH>(b);
+}
+
+/// Traverses skip nodes iteratively and calls `visit` with every hit shape, see
+/// [`SkipBVH::traverse`]. Generic over the node
+/// type, so that wrapped nodes like [`Aligned32`] can be traversed as well.
+///
+/// [`Aligned32`]: struct.Aligned32.html
+/// [`SkipBVH::traverse`]: type.SkipBVH.html
+///
+fn traverse_skip_nodes<'a, N: Borrow<SkipNode>, T: Bounded>(
+    nodes: &'a [N],
+    ray: &impl IntersectionAABB,
+    shapes: &'a [T],
+    mut visit: impl FnMut(&'a T),
+) {
+    let mut index = 0;
+    while index < nodes.len() {
+        let node = nodes[index].borrow();
+        if !ray.intersects_aabb(&node.aabb) {
+            index = node.skip_index as usize;
+        } else if node.shape_index == u32::MAX {
+            index += 1;
+        } else {
+            visit(&shapes[node.shape_index as usize]);
+            index = node.skip_index as usize;
+        }
     }
+}
 
-    #[bench]
-    /// Benchmark the construction of a `FlatBVH` with 12,000 triangles.
+impl BoundingHierarchy for SkipBVH {
+    /// A [`SkipBVH`] is built from a regular [`BVH`] using the [`BVH::flatten_skip`] method.
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`BVH::flatten_skip`]: ../bvh/struct.BVH.html#method.flatten_skip
+    /// [`SkipBVH`]: type.SkipBVH.html
+    ///
+    fn build<T: BHShape>(shapes: &mut [T]) -> SkipBVH {
+        let bvh = BVH::build(shapes);
+        bvh.flatten_skip(shapes)
+    }
+
+    /// Traverses a [`SkipBVH`] structure iteratively without a stack.
+    ///
+    /// [`SkipBVH`]: type.SkipBVH.html
+    ///
+    fn traverse<'a, T: Bounded>(
+        &'a self,
+        ray: &impl IntersectionAABB,
+        shapes: &'a [T],
+    ) -> Vec<&'a T> {
+        let mut hit_shapes = Vec::new();
+        traverse_skip_nodes(self, ray, shapes, |shape| hit_shapes.push(shape));
+        hit_shapes
+    }
+
+    /// Traverses a [`SkipBVH`] and calls `visit` during the traversal.
+    ///
+    /// [`SkipBVH`]: type.SkipBVH.html
+    ///
+    fn traverse_with<'a, T: Bounded>(
+        &'a self,
+        ray: &impl IntersectionAABB,
+        shapes: &'a [T],
+        visit: impl FnMut(&'a T),
+    ) {
+        traverse_skip_nodes(self, ray, shapes, visit)
+    }
+
+    /// Prints a textual representation of a [`SkipBVH`].
+    ///
+    /// [`SkipBVH`]: type.SkipBVH.html
+    ///
+    fn pretty_print(&self) {
+        for (i, node) in self.iter().enumerate() {
+            println!(
+                "{}\tskip {}\tshape {}",
+                i, node.skip_index, node.shape_index
+            );
+        }
+    }
+}
+
+/// A node of a [`SkipBVH`] with a fixed memory layout for GPU storage buffers, which matches
+/// the following struct in std430 (and std140) GLSL:
+///
+/// ```glsl
+/// struct Node {
+///     vec3 aabb_min;
+///     uint skip_index;
+///     vec3 aabb_max;
+///     uint shape_index;
+/// };
+/// ```
+///
+/// The node is 32 bytes large and aligned to 16 bytes. The bounds are always stored as
+/// `f32`. The indices have the same meaning as in [`SkipNode`]. With the `bytemuck`
+/// feature, `GpuSkipNode` implements `bytemuck::Pod` and `bytemuck::Zeroable`.
+///
+/// [`SkipBVH`]: type.SkipBVH.html
+/// [`SkipNode`]: struct.SkipNode.html
+///
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "bytemuck", derive(bytemuck::Pod, bytemuck::Zeroable))]
+pub struct GpuSkipNode {
+    /// The minimum corner of the [`AABB`] of the node.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub aabb_min: [f32; 3],
+
+    /// The index of the node to continue with, if the [`AABB`] test is negative or the node
+    /// is a leaf.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub skip_index: u32,
+
+    /// The maximum corner of the [`AABB`] of the node.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub aabb_max: [f32; 3],
+
+    /// The index of the shape in the shapes array, or [`u32::MAX`] for inner nodes.
+    ///
+    /// [`u32::MAX`]: https://doc.rust-lang.org/std/u32/constant.MAX.html
+    ///
+    pub shape_index: u32,
+}
+
+impl GpuSkipNode {
+    /// Creates a new node from a [`SkipNode`]. The bounds are converted to `f32`.
+    ///
+    /// [`SkipNode`]: struct.SkipNode.html
+    ///
+    #[allow(clippy::unnecessary_cast)]
+    pub fn new(node: &SkipNode) -> GpuSkipNode {
+        let aabb = &node.aabb;
+        GpuSkipNode {
+            aabb_min: [aabb.min.x as f32, aabb.min.y as f32, aabb.min.z as f32],
+            skip_index: node.skip_index,
+            aabb_max: [aabb.max.x as f32, aabb.max.y as f32, aabb.max.z as f32],
+            shape_index: node.shape_index,
+        }
+    }
+}
+
+impl BVH {
+    /// Flattens the [`BVH`] like [`BVH::flatten_skip`] into [`GpuSkipNode`]s, which can be
+    /// uploaded to a GPU storage buffer as is.
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`BVH::flatten_skip`]: ../bvh/struct.BVH.html#method.flatten_skip
+    /// [`GpuSkipNode`]: struct.GpuSkipNode.html
+    ///
+    pub fn flatten_skip_gpu<T: BHShape>(&self, shapes: &[T]) -> Vec<GpuSkipNode> {
+        self.flatten_skip(shapes)
+            .iter()
+            .map(GpuSkipNode::new)
+            .collect()
+    }
+}
+
+/// A node of a flat [`BVH`] which stores its bounds as half precision floats, so that it only
+/// needs 24 bytes instead of the 48 bytes of a [`GpuNode`]. This halves the memory bandwidth
+/// of a GPU traversal, at the cost of looser bounds.
+///
+/// The bounds are rounded conservatively, the minimum corner towards negative and the
+/// maximum corner towards positive infinity, so the stored box always encloses the original
+/// one and no hit is ever culled. Bounds outside of the half precision range become
+/// infinite. The indices have the same meaning as in [`FlatNode`].
+///
+/// The `bounds` hold the bits of the minimum and then the maximum corner, which matches the
+/// following struct in GLSL (or `array<u32, 3>` in WGSL), whose elements can be unpacked
+/// with `unpackHalf2x16` (or `unpack2x16float`):
+///
+/// ```glsl
+/// struct Node {
+///     uint bounds[3];
+///     uint entry_index;
+///     uint exit_index;
+///     uint shape_index;
+/// };
+/// ```
+///
+/// [`BVH`]: ../bvh/struct.BVH.html
+/// [`FlatNode`]: struct.FlatNode.html
+/// [`GpuNode`]: struct.GpuNode.html
+///
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "bytemuck", derive(bytemuck::Pod, bytemuck::Zeroable))]
+pub struct HalfNode {
+    /// The bits of the half precision `[min.x, min.y, min.z, max.x, max.y, max.z]` of the
+    /// [`AABB`] of the node.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub bounds: [u16; 6],
+
+    /// The index of the node to jump to, if the [`AABB`] test is positive, or
+    /// [`u32::MAX`] for leaf nodes.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`u32::MAX`]: https://doc.rust-lang.org/std/u32/constant.MAX.html
+    ///
+    pub entry_index: u32,
+
+    /// The index of the node to jump to, if the [`AABB`] test is negative.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub exit_index: u32,
+
+    /// The index of the shape in the shapes array.
+    pub shape_index: u32,
+}
+
+impl HalfNode {
+    /// Creates a new node. The bounds are rounded outwards to half precision.
+    #[allow(clippy::unnecessary_cast)]
+    pub fn new(aabb: &AABB, entry_index: u32, exit_index: u32, shape_index: u32) -> HalfNode {
+        let min = aabb.min.to_array();
+        let max = aabb.max.to_array();
+        let mut bounds = [0; 6];
+        for axis in 0..3 {
+            bounds[axis] = f16_bits_rounded(min[axis] as f64, false);
+            bounds[axis + 3] = f16_bits_rounded(max[axis] as f64, true);
+        }
+        HalfNode {
+            bounds,
+            entry_index,
+            exit_index,
+            shape_index,
+        }
+    }
+
+    /// Returns the stored [`AABB`], which encloses the [`AABB`] the node was created from.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub fn aabb(&self) -> AABB {
+        let bound = |i: usize| Real::from(f16_bits_to_f32(self.bounds[i]));
+        AABB::with_bounds(
+            Point3::new(bound(0), bound(1), bound(2)),
+            Point3::new(bound(3), bound(4), bound(5)),
+        )
+    }
+}
+
+impl BVH {
+    /// Flattens the [`BVH`] like [`BVH::flatten`] into [`HalfNode`]s with half precision
+    /// bounds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..10)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.1, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let bvh = BVH::build(&mut cubes);
+    /// let half_nodes = bvh.flatten_half(&cubes);
+    /// let flat_nodes = bvh.flatten(&cubes);
+    /// for (half, flat) in half_nodes.iter().zip(flat_nodes.iter()) {
+    ///     let aabb = half.aabb();
+    ///     assert!(aabb.contains(&flat.aabb.min) && aabb.contains(&flat.aabb.max));
+    /// }
+    /// ```
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`BVH::flatten`]: ../bvh/struct.BVH.html#method.flatten
+    /// [`HalfNode`]: struct.HalfNode.html
+    ///
+    pub fn flatten_half<T: BHShape>(&self, shapes: &[T]) -> Vec<HalfNode> {
+        self.flatten_custom(shapes, &HalfNode::new)
+    }
+}
+
+/// Wraps a node so that it is aligned to, and padded to a multiple of, 32 bytes. In an
+/// array of such nodes, no node of up to 32 bytes straddles a cache line. A [`SkipNode`] is
+/// exactly 32 bytes large if the crate is built without the `f64` feature, see
+/// [`SkipBVH32`].
+///
+/// [`SkipBVH32`]: type.SkipBVH32.html
+/// [`SkipNode`]: struct.SkipNode.html
+///
+#[repr(C, align(32))]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde_impls", serde(transparent))]
+pub struct Aligned32<N>(pub N);
+
+impl<N> Deref for Aligned32<N> {
+    type Target = N;
+
+    fn deref(&self) -> &N {
+        &self.0
+    }
+}
+
+impl<N> Borrow<N> for Aligned32<N> {
+    fn borrow(&self) -> &N {
+        &self.0
+    }
+}
+
+/// Wraps a node so that it is aligned to, and padded to a multiple of, 64 bytes, which is
+/// the size of a cache line on most CPUs. Every node is then loaded with a single cache
+/// line, at the cost of padding a 36 byte [`FlatNode`] to 64 bytes, see [`FlatBVH64`].
+///
+/// [`FlatBVH64`]: type.FlatBVH64.html
+/// [`FlatNode`]: struct.FlatNode.html
+///
+#[repr(C, align(64))]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde_impls", serde(transparent))]
+pub struct Aligned64<N>(pub N);
+
+impl<N> Deref for Aligned64<N> {
+    type Target = N;
+
+    fn deref(&self) -> &N {
+        &self.0
+    }
+}
+
+impl<N> Borrow<N> for Aligned64<N> {
+    fn borrow(&self) -> &N {
+        &self.0
+    }
+}
+
+/// A [`FlatBVH`] whose nodes are aligned to cache lines. It needs up to 78% more memory than
+/// a [`FlatBVH`], but no node access touches two cache lines, which speeds up incoherent
+/// traversals.
+///
+/// [`FlatBVH`]: type.FlatBVH.html
+///
+#[allow(clippy::upper_case_acronyms)]
+pub type FlatBVH64 = Vec<Aligned64<FlatNode>>;
+
+/// A [`SkipBVH`] whose nodes are aligned to 32 bytes, so that two nodes share a cache line
+/// and none of them straddles two.
+///
+/// [`SkipBVH`]: type.SkipBVH.html
+///
+#[allow(clippy::upper_case_acronyms)]
+pub type SkipBVH32 = Vec<Aligned32<SkipNode>>;
+
+impl BVH {
+    /// Flattens the [`BVH`] like [`BVH::flatten`] into a [`FlatBVH64`].
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`BVH::flatten`]: ../bvh/struct.BVH.html#method.flatten
+    /// [`FlatBVH64`]: type.FlatBVH64.html
+    ///
+    pub fn flatten_aligned<T: BHShape>(&self, shapes: &[T]) -> FlatBVH64 {
+        self.flatten_custom(shapes, &|aabb, entry, exit, shape| {
+            Aligned64(FlatNode {
+                aabb: *aabb,
+                entry_index: entry,
+                exit_index: exit,
+                shape_index: shape,
+            })
+        })
+    }
+
+    /// Flattens the [`BVH`] like [`BVH::flatten_skip`] into a [`SkipBVH32`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::{BHShape, BoundingHierarchy};
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..10)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let bvh = BVH::build(&mut cubes);
+    /// let skip_bvh = bvh.flatten_skip_aligned(&cubes);
+    /// assert_eq!(skip_bvh.as_ptr() as usize % 32, 0);
+    /// assert_eq!(std::mem::size_of_val(&skip_bvh[0]), 32);
+    ///
+    /// let ray = Ray::new(Point3::new(6.0, -10.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+    /// assert_eq!(skip_bvh.traverse(&ray, &cubes).len(), 1);
+    /// ```
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`BVH::flatten_skip`]: ../bvh/struct.BVH.html#method.flatten_skip
+    /// [`SkipBVH32`]: type.SkipBVH32.html
+    ///
+    pub fn flatten_skip_aligned<T: BHShape>(&self, shapes: &[T]) -> SkipBVH32 {
+        self.flatten_skip(shapes)
+            .into_iter()
+            .map(Aligned32)
+            .collect()
+    }
+}
+
+impl BoundingHierarchy for FlatBVH64 {
+    /// A [`FlatBVH64`] is built from a regular [`BVH`] using the [`BVH::flatten_aligned`]
+    /// method.
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`BVH::flatten_aligned`]: ../bvh/struct.BVH.html#method.flatten_aligned
+    /// [`FlatBVH64`]: type.FlatBVH64.html
+    ///
+    fn build<T: BHShape>(shapes: &mut [T]) -> FlatBVH64 {
+        let bvh = BVH::build(shapes);
+        bvh.flatten_aligned(shapes)
+    }
+
+    /// Traverses a [`FlatBVH64`] like a [`FlatBVH`].
+    ///
+    /// [`FlatBVH`]: type.FlatBVH.html
+    /// [`FlatBVH64`]: type.FlatBVH64.html
+    ///
+    fn traverse<'a, T: Bounded>(
+        &'a self,
+        ray: &impl IntersectionAABB,
+        shapes: &'a [T],
+    ) -> Vec<&'a T> {
+        let mut hit_shapes = Vec::new();
+        traverse_flat_nodes(self, ray, shapes, |shape| hit_shapes.push(shape));
+        hit_shapes
+    }
+
+    /// Traverses a [`FlatBVH64`] and calls `visit` during the traversal.
+    ///
+    /// [`FlatBVH64`]: type.FlatBVH64.html
+    ///
+    fn traverse_with<'a, T: Bounded>(
+        &'a self,
+        ray: &impl IntersectionAABB,
+        shapes: &'a [T],
+        visit: impl FnMut(&'a T),
+    ) {
+        traverse_flat_nodes(self, ray, shapes, visit)
+    }
+
+    /// Prints a textual representation of a [`FlatBVH64`].
+    ///
+    /// [`FlatBVH64`]: type.FlatBVH64.html
+    ///
+    fn pretty_print(&self) {
+        for (i, node) in self.iter().enumerate() {
+            println!(
+                "{}\tentry {}\texit {}\tshape {}",
+                i, node.entry_index, node.exit_index, node.shape_index
+            );
+        }
+    }
+}
+
+impl BoundingHierarchy for SkipBVH32 {
+    /// A [`SkipBVH32`] is built from a regular [`BVH`] using the
+    /// [`BVH::flatten_skip_aligned`] method.
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`BVH::flatten_skip_aligned`]: ../bvh/struct.BVH.html#method.flatten_skip_aligned
+    /// [`SkipBVH32`]: type.SkipBVH32.html
+    ///
+    fn build<T: BHShape>(shapes: &mut [T]) -> SkipBVH32 {
+        let bvh = BVH::build(shapes);
+        bvh.flatten_skip_aligned(shapes)
+    }
+
+    /// Traverses a [`SkipBVH32`] like a [`SkipBVH`].
+    ///
+    /// [`SkipBVH`]: type.SkipBVH.html
+    /// [`SkipBVH32`]: type.SkipBVH32.html
+    ///
+    fn traverse<'a, T: Bounded>(
+        &'a self,
+        ray: &impl IntersectionAABB,
+        shapes: &'a [T],
+    ) -> Vec<&'a T> {
+        let mut hit_shapes = Vec::new();
+        traverse_skip_nodes(self, ray, shapes, |shape| hit_shapes.push(shape));
+        hit_shapes
+    }
+
+    /// Traverses a [`SkipBVH32`] and calls `visit` during the traversal.
+    ///
+    /// [`SkipBVH32`]: type.SkipBVH32.html
+    ///
+    fn traverse_with<'a, T: Bounded>(
+        &'a self,
+        ray: &impl IntersectionAABB,
+        shapes: &'a [T],
+        visit: impl FnMut(&'a T),
+    ) {
+        traverse_skip_nodes(self, ray, shapes, visit)
+    }
+
+    /// Prints a textual representation of a [`SkipBVH32`].
+    ///
+    /// [`SkipBVH32`]: type.SkipBVH32.html
+    ///
+    fn pretty_print(&self) {
+        for (i, node) in self.iter().enumerate() {
+            println!(
+                "{}\tskip {}\tshape {}",
+                i, node.skip_index, node.shape_index
+            );
+        }
+    }
+}
+
+/// An unsigned integer type which can store the indices of a [`CompactFlatNode`]. Its
+/// maximum value is reserved for [`FlatIndex::INVALID`], so a tree with indices of type
+/// `I` holds fewer than `I::INVALID` nodes and shapes.
+///
+/// [`CompactFlatNode`]: struct.CompactFlatNode.html
+/// [`FlatIndex::INVALID`]: trait.FlatIndex.html#associatedconstant.INVALID
+///
+pub trait FlatIndex: Copy + PartialEq + fmt::Debug {
+    /// The marker for a missing index, like [`u32::MAX`] in a [`FlatNode`].
+    ///
+    /// [`FlatNode`]: struct.FlatNode.html
+    /// [`u32::MAX`]: https://doc.rust-lang.org/std/primitive.u32.html#associatedconstant.MAX
+    ///
+    const INVALID: Self;
+
+    /// Converts an index, returns `None` if it does not fit or equals [`FlatIndex::INVALID`].
+    ///
+    /// [`FlatIndex::INVALID`]: trait.FlatIndex.html#associatedconstant.INVALID
+    ///
+    fn from_usize(index: usize) -> Option<Self>;
+
+    /// Converts the index back to a `usize`.
+    fn to_usize(self) -> usize;
+}
+
+impl FlatIndex for u16 {
+    const INVALID: u16 = u16::MAX;
+
+    fn from_usize(index: usize) -> Option<u16> {
+        u16::try_from(index).ok().filter(|&index| index != u16::MAX)
+    }
+
+    fn to_usize(self) -> usize {
+        self as usize
+    }
+}
+
+impl FlatIndex for u32 {
+    const INVALID: u32 = u32::MAX;
+
+    fn from_usize(index: usize) -> Option<u32> {
+        u32::try_from(index).ok().filter(|&index| index != u32::MAX)
+    }
+
+    fn to_usize(self) -> usize {
+        self as usize
+    }
+}
+
+impl FlatIndex for usize {
+    const INVALID: usize = usize::MAX;
+
+    fn from_usize(index: usize) -> Option<usize> {
+        Some(index).filter(|&index| index != usize::MAX)
+    }
+
+    fn to_usize(self) -> usize {
+        self
+    }
+}
+
+/// A [`FlatNode`] whose indices are of the type `I`. With `u16` indices a node takes 32
+/// instead of 36 bytes if the crate is built without the `f64` feature, which suits trees
+/// of up to 21845 shapes.
+///
+/// [`FlatNode`]: struct.FlatNode.html
+///
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct CompactFlatNode<I> {
+    /// The [`AABB`] of the node, see [`FlatNode::aabb`].
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`FlatNode::aabb`]: struct.FlatNode.html#structfield.aabb
+    ///
+    pub aabb: AABB,
+
+    /// The index of the first child, or [`FlatIndex::INVALID`] for a leaf.
+    ///
+    /// [`FlatIndex::INVALID`]: trait.FlatIndex.html#associatedconstant.INVALID
+    ///
+    pub entry_index: I,
+
+    /// The index of the node to continue with after this node or its subtree.
+    pub exit_index: I,
+
+    /// The index of the shape of a leaf, or [`FlatIndex::INVALID`] for an inner node.
+    ///
+    /// [`FlatIndex::INVALID`]: trait.FlatIndex.html#associatedconstant.INVALID
+    ///
+    pub shape_index: I,
+}
+
+/// A [`FlatBVH`] with indices of the type `I`, see [`BVH::flatten_compact`].
+///
+/// [`BVH::flatten_compact`]: ../bvh/struct.BVH.html#method.flatten_compact
+/// [`FlatBVH`]: type.FlatBVH.html
+///
+#[allow(clippy::upper_case_acronyms)]
+pub type CompactFlatBVH<I> = Vec<CompactFlatNode<I>>;
+
+/// The error returned by [`BVH::flatten_compact`] if the indices of the tree do not fit
+/// into the chosen index type.
+///
+/// [`BVH::flatten_compact`]: ../bvh/struct.BVH.html#method.flatten_compact
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlatIndexOverflow {
+    /// The number of flat nodes of the tree.
+    pub node_count: usize,
+    /// The number of shapes of the tree.
+    pub shape_count: usize,
+}
+
+impl fmt::Display for FlatIndexOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} nodes and {} shapes exceed the range of the index type",
+            self.node_count, self.shape_count
+        )
+    }
+}
+
+impl std::error::Error for FlatIndexOverflow {}
+
+impl BVH {
+    /// Flattens the [`BVH`] like [`BVH::flatten`], but stores the indices as `I`. Returns an
+    /// error if the number of nodes or shapes does not fit into `I`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::{BHShape, BoundingHierarchy};
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..10)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let bvh = BVH::build(&mut cubes);
+    /// let compact_bvh = bvh.flatten_compact::<u16, _>(&cubes).unwrap();
+    /// assert_eq!(compact_bvh.len(), bvh.flatten(&cubes).len());
+    ///
+    /// let ray = Ray::new(Point3::new(6.0, -10.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+    /// assert_eq!(compact_bvh.traverse(&ray, &cubes).len(), 1);
+    /// ```
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`BVH::flatten`]: ../bvh/struct.BVH.html#method.flatten
+    ///
+    pub fn flatten_compact<I: FlatIndex, T: BHShape>(
+        &self,
+        shapes: &[T],
+    ) -> Result<CompactFlatBVH<I>, FlatIndexOverflow> {
+        let flat_bvh = self.flatten(shapes);
+        // Exit indices may point one past the last node.
+        let overflow = FlatIndexOverflow {
+            node_count: flat_bvh.len(),
+            shape_count: shapes.len(),
+        };
+        if I::from_usize(flat_bvh.len()).is_none() || I::from_usize(shapes.len()).is_none() {
+            return Err(overflow);
+        }
+
+        let convert = |index: u32| {
+            if index == u32::MAX {
+                I::INVALID
+            } else {
+                I::from_usize(index as usize).unwrap()
+            }
+        };
+        Ok(flat_bvh
+            .iter()
+            .map(|node| CompactFlatNode {
+                aabb: node.aabb,
+                entry_index: convert(node.entry_index),
+                exit_index: convert(node.exit_index),
+                shape_index: convert(node.shape_index),
+            })
+            .collect())
+    }
+}
+
+/// Traverses compact flat nodes like [`traverse_flat_nodes`] and calls `visit` with every
+/// hit shape.
+///
+/// [`traverse_flat_nodes`]: fn.traverse_flat_nodes.html
+///
+fn traverse_compact_nodes<'a, I: FlatIndex, T: Bounded>(
+    nodes: &'a [CompactFlatNode<I>],
+    ray: &impl IntersectionAABB,
+    shapes: &'a [T],
+    mut visit: impl FnMut(&'a T),
+) {
+    let mut index = 0;
+    while index < nodes.len() {
+        let node = &nodes[index];
+        if node.entry_index == I::INVALID {
+            if ray.intersects_aabb(&node.aabb) {
+                visit(&shapes[node.shape_index.to_usize()]);
+            }
+            index = node.exit_index.to_usize();
+        } else if ray.intersects_aabb(&node.aabb) {
+            index = node.entry_index.to_usize();
+        } else {
+            index = node.exit_index.to_usize();
+        }
+    }
+}
+
+impl<I: FlatIndex> BoundingHierarchy for CompactFlatBVH<I> {
+    /// A [`CompactFlatBVH`] is built from a regular [`BVH`] using the
+    /// [`BVH::flatten_compact`] method.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tree does not fit into the index type.
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`BVH::flatten_compact`]: ../bvh/struct.BVH.html#method.flatten_compact
+    /// [`CompactFlatBVH`]: type.CompactFlatBVH.html
+    ///
+    fn build<T: BHShape>(shapes: &mut [T]) -> CompactFlatBVH<I> {
+        let bvh = BVH::build(shapes);
+        bvh.flatten_compact(shapes)
+            .expect("too many shapes for the index type")
+    }
+
+    /// Traverses a [`CompactFlatBVH`] like a [`FlatBVH`].
+    ///
+    /// [`CompactFlatBVH`]: type.CompactFlatBVH.html
+    /// [`FlatBVH`]: type.FlatBVH.html
+    ///
+    fn traverse<'a, T: Bounded>(
+        &'a self,
+        ray: &impl IntersectionAABB,
+        shapes: &'a [T],
+    ) -> Vec<&'a T> {
+        let mut hit_shapes = Vec::new();
+        traverse_compact_nodes(self, ray, shapes, |shape| hit_shapes.push(shape));
+        hit_shapes
+    }
+
+    /// Traverses a [`CompactFlatBVH`] and calls `visit` during the traversal.
+    ///
+    /// [`CompactFlatBVH`]: type.CompactFlatBVH.html
+    ///
+    fn traverse_with<'a, T: Bounded>(
+        &'a self,
+        ray: &impl IntersectionAABB,
+        shapes: &'a [T],
+        visit: impl FnMut(&'a T),
+    ) {
+        traverse_compact_nodes(self, ray, shapes, visit)
+    }
+
+    /// Prints a textual representation of a [`CompactFlatBVH`].
+    ///
+    /// [`CompactFlatBVH`]: type.CompactFlatBVH.html
+    ///
+    fn pretty_print(&self) {
+        for (i, node) in self.iter().enumerate() {
+            println!(
+                "{}\tentry {:?}\texit {:?}\tshape {:?}",
+                i, node.entry_index, node.exit_index, node.shape_index
+            );
+        }
+    }
+}
+
+/// The memory order of the nodes of a flat [`BVH`], see [`BVH::flatten_ordered`]. The
+/// nodes reference each other by index, so every order can be traversed the same way, but
+/// the order decides which nodes share cache lines.
+///
+/// [`BVH`]: ../bvh/struct.BVH.html
+/// [`BVH::flatten_ordered`]: ../bvh/struct.BVH.html#method.flatten_ordered
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub enum FlattenOrder {
+    #[default]
+    /// Depth-first order, in which the first child of a node directly follows it. This is
+    /// the order of [`BVH::flatten`] and suits single rays on the CPU.
+    ///
+    /// [`BVH::flatten`]: ../bvh/struct.BVH.html#method.flatten
+    ///
+    DepthFirst,
+    /// Breadth-first order, which stores the nodes level by level. The upper levels, which
+    /// are visited by every ray, are packed together, which suits coherent GPU traversal.
+    BreadthFirst,
+    /// Clusters of up to the given number of nodes, each of which holds a subtree in
+    /// breadth-first order. Choosing the number of nodes which fit into a cache line or
+    /// page keeps most steps of a traversal within the same block.
+    Treelets(usize),
+}
+
+impl BVH {
+    /// Flattens the [`BVH`] like [`BVH::flatten_custom`], but lays out the flat nodes in the
+    /// given `order`. The traversal still starts at index `0` and ends once an index is
+    /// equal to the number of nodes.
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`BVH::flatten_custom`]: ../bvh/struct.BVH.html#method.flatten_custom
+    ///
+    pub fn flatten_custom_ordered<F, FNodeType, T: BHShape>(
+        &self,
+        shapes: &[T],
+        order: FlattenOrder,
+        constructor: &F,
+    ) -> Vec<FNodeType>
+    where
+        F: Fn(&AABB, u32, u32, u32) -> FNodeType,
+    {
+        let flat = self.flatten_custom(shapes, &|aabb, entry, exit, shape| {
+            (*aabb, entry, exit, shape)
+        });
+        if flat.is_empty() {
+            return Vec::new();
+        }
+
+        // In the depth-first layout, a navigator node is followed by the leaf node of its
+        // shape, or by the navigator nodes of both children of its inner node, the second
+        // of which comes after the subtree of the first.
+        let is_leaf = |index: usize| flat[index].1 == u32::MAX;
+        let children = |index: usize| -> Vec<usize> {
+            if is_leaf(index) {
+                Vec::new()
+            } else if is_leaf(index + 1) {
+                vec![index + 1]
+            } else {
+                vec![index + 1, flat[index + 1].2 as usize]
+            }
+        };
+        let mut roots = vec![0];
+        if (flat[0].2 as usize) < flat.len() {
+            roots.push(flat[0].2 as usize);
+        }
+
+        let new_order: Vec<usize> = match order {
+            FlattenOrder::DepthFirst => (0..flat.len()).collect(),
+            FlattenOrder::BreadthFirst => {
+                let mut new_order = roots;
+                let mut next = 0;
+                while next < new_order.len() {
+                    new_order.extend(children(new_order[next]));
+                    next += 1;
+                }
+                new_order
+            }
+            FlattenOrder::Treelets(size) => {
+                let size = size.max(1);
+                let mut new_order = Vec::with_capacity(flat.len());
+                let mut treelet_roots = roots;
+                treelet_roots.reverse();
+                while let Some(root) = treelet_roots.pop() {
+                    // Gather a breadth-first treelet and start new treelets at its frontier.
+                    let start = new_order.len();
+                    new_order.push(root);
+                    let mut next = start;
+                    let mut frontier = Vec::new();
+                    while next < new_order.len() {
+                        for child in children(new_order[next]) {
+                            if new_order.len() - start < size {
+                                new_order.push(child);
+                            } else {
+                                frontier.push(child);
+                            }
+                        }
+                        next += 1;
+                    }
+                    treelet_roots.extend(frontier.into_iter().rev());
+                }
+                new_order
+            }
+        };
+
+        let mut remap = vec![0; flat.len()];
+        for (new_index, &old_index) in new_order.iter().enumerate() {
+            remap[old_index] = new_index as u32;
+        }
+        let remap_index = |index: u32| {
+            if (index as usize) < flat.len() {
+                remap[index as usize]
+            } else {
+                index
+            }
+        };
+        new_order
+            .iter()
+            .map(|&old_index| {
+                let (aabb, entry, exit, shape) = flat[old_index];
+                constructor(&aabb, remap_index(entry), remap_index(exit), shape)
+            })
+            .collect()
+    }
+
+    /// Flattens the [`BVH`] into a [`FlatBVH`] whose nodes are laid out in the given
+    /// `order`. The result can be traversed like any other [`FlatBVH`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::{BHShape, BoundingHierarchy};
+    /// use bvh::bvh::BVH;
+    /// use bvh::flat_bvh::FlattenOrder;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..10)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let bvh = BVH::build(&mut cubes);
+    /// let flat_bvh = bvh.flatten_ordered(&cubes, FlattenOrder::BreadthFirst);
+    ///
+    /// let ray = Ray::new(Point3::new(6.0, -10.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+    /// assert_eq!(flat_bvh.traverse(&ray, &cubes).len(), 1);
+    /// ```
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`FlatBVH`]: type.FlatBVH.html
+    ///
+    pub fn flatten_ordered<T: BHShape>(&self, shapes: &[T], order: FlattenOrder) -> FlatBVH {
+        self.flatten_custom_ordered(shapes, order, &|aabb, entry, exit, shape| FlatNode {
+            aabb: *aabb,
+            entry_index: entry,
+            exit_index: exit,
+            shape_index: shape,
+        })
+    }
+}
+
+/// The magic bytes at the start of a serialized [`FlatBVH`].
+///
+/// [`FlatBVH`]: type.FlatBVH.html
+///
+const FLAT_BVH_MAGIC: [u8; 4] = *b"BVHF";
+
+/// The magic bytes at the start of the serialized topology of a [`FlatBVH`].
+///
+/// [`FlatBVH`]: type.FlatBVH.html
+///
+const FLAT_BVH_TOPOLOGY_MAGIC: [u8; 4] = *b"BVHT";
+
+/// The size of a node in the serialized topology of a [`FlatBVH`], which consists of the
+/// exit and the shape index.
+///
+/// [`FlatBVH`]: type.FlatBVH.html
+///
+const FLAT_BVH_TOPOLOGY_NODE_SIZE: usize = 8;
+
+/// The endianness marker of a serialized [`FlatBVH`], which is stored in the byte order of
+/// the machine that wrote it.
+///
+/// [`FlatBVH`]: type.FlatBVH.html
+///
+const FLAT_BVH_ENDIANNESS: u32 = 0x0102_0304;
+
+/// The version of the binary format written by [`FlatBVHBytes::to_bytes`]. Version 2 added
+/// a checksum of the nodes to the header.
+///
+/// [`FlatBVHBytes::to_bytes`]: trait.FlatBVHBytes.html#tymethod.to_bytes
+///
+pub const FLAT_BVH_VERSION: u32 = 2;
+
+/// The oldest version of the binary format which can still be loaded. Buffers of version 1
+/// have no checksum, so their nodes are loaded without verifying them.
+///
+pub const FLAT_BVH_MIN_VERSION: u32 = 1;
+
+/// The size of the header of a serialized [`FlatBVH`]. The nodes directly follow the header.
+///
+/// [`FlatBVH`]: type.FlatBVH.html
+///
+pub const FLAT_BVH_HEADER_SIZE: usize = 32;
+
+/// The alignment which a buffer holding a serialized [`FlatBVH`] must have, so that the
+/// nodes can be accessed in place with [`FlatNode::slice_from_bytes`]. Memory mapped files
+/// always satisfy it, since pages are much larger.
+///
+/// [`FlatBVH`]: type.FlatBVH.html
+/// [`FlatNode::slice_from_bytes`]: struct.FlatNode.html#method.slice_from_bytes
+///
+pub const FLAT_BVH_ALIGNMENT: usize = 16;
+
+/// The reasons why a serialized [`FlatBVH`] can not be loaded.
+///
+/// [`FlatBVH`]: type.FlatBVH.html
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlatBVHBytesError {
+    /// The buffer is smaller than the header or the nodes announced in it.
+    Truncated,
+    /// The buffer does not start with the magic bytes of a [`FlatBVH`].
+    ///
+    /// [`FlatBVH`]: type.FlatBVH.html
+    ///
+    InvalidMagic,
+    /// The buffer was written with a format version this crate can not read, i.e. one older
+    /// than [`FLAT_BVH_MIN_VERSION`] or newer than [`FLAT_BVH_VERSION`].
+    ///
+    /// [`FLAT_BVH_MIN_VERSION`]: constant.FLAT_BVH_MIN_VERSION.html
+    /// [`FLAT_BVH_VERSION`]: constant.FLAT_BVH_VERSION.html
+    ///
+    UnsupportedVersion(u32),
+    /// The nodes do not match the checksum in the header, e.g. because the file was damaged.
+    ChecksumMismatch,
+    /// The endianness marker is neither in little nor in big endian byte order.
+    InvalidEndianness,
+    /// The buffer was written on a machine with a different byte order. It can still be
+    /// loaded with [`FlatBVHBytes::from_bytes`], but not accessed in place.
+    ///
+    /// [`FlatBVHBytes::from_bytes`]: trait.FlatBVHBytes.html#tymethod.from_bytes
+    ///
+    ForeignEndianness,
+    /// The buffer was written by a build of the crate with a different [`Real`] type or
+    /// node size, e.g. with the `f64` feature.
+    ///
+    /// [`Real`]: ../type.Real.html
+    ///
+    LayoutMismatch,
+    /// The buffer is not aligned to [`FLAT_BVH_ALIGNMENT`].
+    ///
+    /// [`FLAT_BVH_ALIGNMENT`]: constant.FLAT_BVH_ALIGNMENT.html
+    ///
+    Misaligned,
+    /// The nodes of a topology loaded with [`FlatBVHTopology::from_topology_bytes`] do not
+    /// form a valid tree over the shapes.
+    ///
+    /// [`FlatBVHTopology::from_topology_bytes`]: trait.FlatBVHTopology.html#tymethod.from_topology_bytes
+    ///
+    InvalidTopology(FlatBVHValidationError),
+}
+
+impl fmt::Display for FlatBVHBytesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FlatBVHBytesError::Truncated => write!(f, "the buffer is truncated"),
+            FlatBVHBytesError::InvalidMagic => write!(f, "the buffer does not hold a FlatBVH"),
+            FlatBVHBytesError::UnsupportedVersion(version) => {
+                write!(f, "unsupported FlatBVH format version {}", version)
+            }
+            FlatBVHBytesError::ChecksumMismatch => {
+                write!(f, "the nodes do not match the checksum")
+            }
+            FlatBVHBytesError::InvalidEndianness => write!(f, "invalid endianness marker"),
+            FlatBVHBytesError::ForeignEndianness => {
+                write!(f, "the buffer was written with a different byte order")
+            }
+            FlatBVHBytesError::LayoutMismatch => {
+                write!(f, "the buffer was written with a different node layout")
+            }
+            FlatBVHBytesError::Misaligned => write!(f, "the buffer is not aligned"),
+            FlatBVHBytesError::InvalidTopology(error) => write!(f, "invalid topology: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for FlatBVHBytesError {}
+
+/// The validated header of a serialized [`FlatBVH`].
+///
+/// [`FlatBVH`]: type.FlatBVH.html
+///
+struct FlatBVHHeader {
+    /// Whether the buffer was written with the opposite byte order.
+    swapped: bool,
+    node_count: usize,
+}
+
+impl FlatBVHHeader {
+    /// Creates a buffer with a header for `node_count` nodes of `node_size` bytes, whose
+    /// fields depend on [`Real`] values of `real_size` bytes. The checksum is added by
+    /// [`FlatBVHHeader::write_checksum`] once the nodes are written.
+    ///
+    /// [`FlatBVHHeader::write_checksum`]: struct.FlatBVHHeader.html#method.write_checksum
+    /// [`Real`]: ../type.Real.html
+    ///
+    fn write(magic: [u8; 4], real_size: usize, node_size: usize, node_count: usize) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(FLAT_BVH_HEADER_SIZE + node_count * node_size);
+        bytes.extend_from_slice(&magic);
+        bytes.extend_from_slice(&FLAT_BVH_VERSION.to_ne_bytes());
+        bytes.extend_from_slice(&FLAT_BVH_ENDIANNESS.to_ne_bytes());
+        bytes.extend_from_slice(&(real_size as u32).to_ne_bytes());
+        bytes.extend_from_slice(&(node_size as u32).to_ne_bytes());
+        bytes.extend_from_slice(&(node_count as u32).to_ne_bytes());
+        bytes.resize(FLAT_BVH_HEADER_SIZE, 0);
+        bytes
+    }
+
+    /// Stores the checksum of the nodes which follow the header in the header.
+    fn write_checksum(bytes: &mut [u8]) {
+        let checksum = nodes_checksum(&bytes[FLAT_BVH_HEADER_SIZE..]);
+        bytes[24..28].copy_from_slice(&checksum.to_ne_bytes());
+    }
+
+    /// Reads and validates the header, and checks that `bytes` is large enough for all nodes.
+    /// The header must start with `magic` and announce the given sizes, see
+    /// [`FlatBVHHeader::write`].
+    ///
+    /// [`FlatBVHHeader::write`]: struct.FlatBVHHeader.html#method.write
+    ///
+    fn read(
+        bytes: &[u8],
+        magic: [u8; 4],
+        expected_real_size: usize,
+        expected_node_size: usize,
+    ) -> Result<FlatBVHHeader, FlatBVHBytesError> {
+        if bytes.len() < FLAT_BVH_HEADER_SIZE {
+            return Err(FlatBVHBytesError::Truncated);
+        }
+        if bytes[0..4] != magic {
+            return Err(FlatBVHBytesError::InvalidMagic);
+        }
+
+        let marker = read_u32(&bytes[8..], false);
+        let swapped = if marker == FLAT_BVH_ENDIANNESS {
+            false
+        } else if marker.swap_bytes() == FLAT_BVH_ENDIANNESS {
+            true
+        } else {
+            return Err(FlatBVHBytesError::InvalidEndianness);
+        };
+
+        let version = read_u32(&bytes[4..], swapped);
+        if !(FLAT_BVH_MIN_VERSION..=FLAT_BVH_VERSION).contains(&version) {
+            return Err(FlatBVHBytesError::UnsupportedVersion(version));
+        }
+        let real_size = read_u32(&bytes[12..], swapped) as usize;
+        let node_size = read_u32(&bytes[16..], swapped) as usize;
+        if real_size != expected_real_size || node_size != expected_node_size {
+            return Err(FlatBVHBytesError::LayoutMismatch);
+        }
+
+        let node_count = read_u32(&bytes[20..], swapped) as usize;
+        let end = FLAT_BVH_HEADER_SIZE.saturating_add(node_count.saturating_mul(node_size));
+        if bytes.len() < end {
+            return Err(FlatBVHBytesError::Truncated);
+        }
+        if version >= 2
+            && read_u32(&bytes[24..], swapped) != nodes_checksum(&bytes[FLAT_BVH_HEADER_SIZE..end])
+        {
+            return Err(FlatBVHBytesError::ChecksumMismatch);
+        }
+        Ok(FlatBVHHeader {
+            swapped,
+            node_count,
+        })
+    }
+}
+
+/// Computes the FNV-1a hash of the serialized nodes, which is stored in the header since
+/// version 2 of the format. It is computed over the bytes as written, so it does not depend
+/// on the byte order of the reading machine.
+fn nodes_checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash: u32, &byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// Reads a `u32` from the start of `bytes`, which is stored in native byte order, or in
+/// the opposite one if `swapped` is set.
+fn read_u32(bytes: &[u8], swapped: bool) -> u32 {
+    let mut buffer = [0; 4];
+    buffer.copy_from_slice(&bytes[..4]);
+    let value = u32::from_ne_bytes(buffer);
+    if swapped {
+        value.swap_bytes()
+    } else {
+        value
+    }
+}
+
+/// Reads a [`Real`] from the start of `bytes` like [`read_u32`].
+///
+/// [`Real`]: ../type.Real.html
+/// [`read_u32`]: fn.read_u32.html
+///
+fn read_real(bytes: &[u8], swapped: bool) -> Real {
+    let mut buffer = [0; mem::size_of::<Real>()];
+    buffer.copy_from_slice(&bytes[..mem::size_of::<Real>()]);
+    if swapped {
+        buffer.reverse();
+    }
+    Real::from_ne_bytes(buffer)
+}
+
+/// Binary serialization of a [`FlatBVH`], so that a baked tree can be stored in an asset
+/// file and loaded again without rebuilding it.
+///
+/// The format consists of a header of [`FLAT_BVH_HEADER_SIZE`] bytes, followed by the nodes
+/// in the in-memory layout of [`FlatNode`]. The header holds the format version, an
+/// endianness marker, the size of [`Real`] and of the nodes, the number of nodes and a
+/// checksum of the nodes. Buffers of all versions since [`FLAT_BVH_MIN_VERSION`] can be
+/// loaded, and newer ones are rejected with [`FlatBVHBytesError::UnsupportedVersion`]. The
+/// nodes are stored in the
+/// byte order of the machine that wrote them, so loading them on a machine with the same
+/// byte order is a single copy, and if the buffer is aligned to [`FLAT_BVH_ALIGNMENT`], e.g.
+/// because it is memory mapped, they can be used in place with
+/// [`FlatNode::slice_from_bytes`]. Buffers with the other byte order are converted node by
+/// node.
+///
+/// # Examples
+///
+/// ```
+/// use bvh::aabb::{AABB, Bounded};
+/// use bvh::bounding_hierarchy::BHShape;
+/// use bvh::bvh::BVH;
+/// use bvh::flat_bvh::{FlatBVH, FlatBVHBytes};
+/// use bvh::{Point3, Vector3};
+///
+/// # struct Cube { pos: Point3, node_index: usize }
+/// # impl Bounded for Cube {
+/// #     fn aabb(&self) -> AABB {
+/// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+/// #     }
+/// # }
+/// # impl BHShape for Cube {
+/// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+/// #     fn bh_node_index(&self) -> usize { self.node_index }
+/// # }
+/// let mut cubes: Vec<Cube> = (0..10)
+///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+///     .collect();
+/// let flat_bvh = BVH::build(&mut cubes).flatten(&cubes);
+///
+/// let bytes = flat_bvh.to_bytes();
+/// let loaded = FlatBVH::from_bytes(&bytes).unwrap();
+/// assert_eq!(loaded, flat_bvh);
+/// ```
+///
+/// [`FLAT_BVH_ALIGNMENT`]: constant.FLAT_BVH_ALIGNMENT.html
+/// [`FLAT_BVH_HEADER_SIZE`]: constant.FLAT_BVH_HEADER_SIZE.html
+/// [`FLAT_BVH_MIN_VERSION`]: constant.FLAT_BVH_MIN_VERSION.html
+/// [`FlatBVH`]: type.FlatBVH.html
+/// [`FlatBVHBytesError::UnsupportedVersion`]: enum.FlatBVHBytesError.html#variant.UnsupportedVersion
+/// [`FlatNode`]: struct.FlatNode.html
+/// [`FlatNode::slice_from_bytes`]: struct.FlatNode.html#method.slice_from_bytes
+/// [`Real`]: ../type.Real.html
+///
+pub trait FlatBVHBytes: Sized {
+    /// Serializes the nodes into a new buffer.
+    fn to_bytes(&self) -> Vec<u8>;
+
+    /// Loads nodes which were serialized with [`FlatBVHBytes::to_bytes`].
+    ///
+    /// [`FlatBVHBytes::to_bytes`]: trait.FlatBVHBytes.html#tymethod.to_bytes
+    ///
+    fn from_bytes(bytes: &[u8]) -> Result<Self, FlatBVHBytesError>;
+}
+
+impl FlatBVHBytes for FlatBVH {
+    fn to_bytes(&self) -> Vec<u8> {
+        let node_size = mem::size_of::<FlatNode>();
+        let mut bytes = FlatBVHHeader::write(
+            FLAT_BVH_MAGIC,
+            mem::size_of::<Real>(),
+            node_size,
+            self.len(),
+        );
+
+        // The nodes are written field by field, so that the padding of the node (if any) is
+        // zeroed instead of copying uninitialized memory.
+        for node in self {
+            let start = bytes.len();
+            for value in node
+                .aabb
+                .min
+                .to_array()
+                .iter()
+                .chain(&node.aabb.max.to_array())
+            {
+                bytes.extend_from_slice(&value.to_ne_bytes());
+            }
+            bytes.extend_from_slice(&node.entry_index.to_ne_bytes());
+            bytes.extend_from_slice(&node.exit_index.to_ne_bytes());
+            bytes.extend_from_slice(&node.shape_index.to_ne_bytes());
+            bytes.resize(start + node_size, 0);
+        }
+        FlatBVHHeader::write_checksum(&mut bytes);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<FlatBVH, FlatBVHBytesError> {
+        let header = FlatBVHHeader::read(
+            bytes,
+            FLAT_BVH_MAGIC,
+            mem::size_of::<Real>(),
+            mem::size_of::<FlatNode>(),
+        )?;
+        let node_bytes = &bytes[FLAT_BVH_HEADER_SIZE..];
+
+        if !header.swapped {
+            let mut nodes: FlatBVH = Vec::with_capacity(header.node_count);
+            // Safety: `FlatNode` is `repr(C)` and consists only of floats and integers, for
+            // which every bit pattern is valid, and the header check guarantees that
+            // `node_bytes` holds `node_count` nodes of the same layout. The destination is
+            // aligned, so the source alignment does not matter for a byte-wise copy.
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    node_bytes.as_ptr(),
+                    nodes.as_mut_ptr() as *mut u8,
+                    header.node_count * mem::size_of::<FlatNode>(),
+                );
+                nodes.set_len(header.node_count);
+            }
+            return Ok(nodes);
+        }
+
+        let real_size = mem::size_of::<Real>();
+        let nodes = node_bytes
+            .chunks_exact(mem::size_of::<FlatNode>())
+            .take(header.node_count)
+            .map(|node| {
+                let real = |i: usize| read_real(&node[i * real_size..], true);
+                let index = |i: usize| read_u32(&node[6 * real_size + i * 4..], true);
+                FlatNode {
+                    aabb: AABB::with_bounds(
+                        Point3::new(real(0), real(1), real(2)),
+                        Point3::new(real(3), real(4), real(5)),
+                    ),
+                    entry_index: index(0),
+                    exit_index: index(1),
+                    shape_index: index(2),
+                }
+            })
+            .collect();
+        Ok(nodes)
+    }
+}
+
+impl FlatNode {
+    /// Accesses the nodes of a buffer written by [`FlatBVHBytes::to_bytes`] in place,
+    /// without copying them. This requires the buffer to be aligned to
+    /// [`FLAT_BVH_ALIGNMENT`] and to be written on a machine with the same byte order, which
+    /// is always the case for a memory mapped file baked on the same platform. Otherwise,
+    /// [`FlatBVHBytes::from_bytes`] can be used.
+    ///
+    /// [`FLAT_BVH_ALIGNMENT`]: constant.FLAT_BVH_ALIGNMENT.html
+    /// [`FlatBVHBytes::from_bytes`]: trait.FlatBVHBytes.html#tymethod.from_bytes
+    /// [`FlatBVHBytes::to_bytes`]: trait.FlatBVHBytes.html#tymethod.to_bytes
+    ///
+    pub fn slice_from_bytes(bytes: &[u8]) -> Result<&[FlatNode], FlatBVHBytesError> {
+        let header = FlatBVHHeader::read(
+            bytes,
+            FLAT_BVH_MAGIC,
+            mem::size_of::<Real>(),
+            mem::size_of::<FlatNode>(),
+        )?;
+        if header.swapped {
+            return Err(FlatBVHBytesError::ForeignEndianness);
+        }
+        if bytes.as_ptr().align_offset(FLAT_BVH_ALIGNMENT) != 0 {
+            return Err(FlatBVHBytesError::Misaligned);
+        }
+
+        // Safety: The header is a multiple of the alignment of `FlatNode` long, so the
+        // nodes are aligned if the buffer is. See `from_bytes` for the validity of the
+        // nodes themselves.
+        let nodes = bytes[FLAT_BVH_HEADER_SIZE..].as_ptr() as *const FlatNode;
+        Ok(unsafe { std::slice::from_raw_parts(nodes, header.node_count) })
+    }
+}
+
+/// Serialization of only the topology of a [`FlatBVH`], i.e. the exit and shape indices of
+/// its nodes. The bounds of the nodes are recomputed from the shapes when loading, so an
+/// asset which stores the shapes anyway needs 8 instead of 36 bytes per node. The buffer has
+/// the header of [`FlatBVHBytes`] with a different magic, and the same format version. The
+/// topology does not depend on [`Real`], so it can be loaded with and without the `f64`
+/// feature.
+///
+/// # Examples
+///
+/// ```
+/// use bvh::aabb::{AABB, Bounded};
+/// use bvh::bounding_hierarchy::BHShape;
+/// use bvh::bvh::BVH;
+/// use bvh::flat_bvh::{FlatBVH, FlatBVHBytes, FlatBVHTopology};
+/// use bvh::{Point3, Vector3};
+///
+/// # struct Cube { pos: Point3, node_index: usize }
+/// # impl Bounded for Cube {
+/// #     fn aabb(&self) -> AABB {
+/// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+/// #     }
+/// # }
+/// # impl BHShape for Cube {
+/// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+/// #     fn bh_node_index(&self) -> usize { self.node_index }
+/// # }
+/// let mut cubes: Vec<Cube> = (0..10)
+///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+///     .collect();
+/// let flat_bvh = BVH::build(&mut cubes).flatten(&cubes);
+///
+/// let bytes = flat_bvh.to_topology_bytes();
+/// assert!(bytes.len() < flat_bvh.to_bytes().len() / 2);
+/// let loaded = FlatBVH::from_topology_bytes(&bytes, &cubes).unwrap();
+/// assert_eq!(loaded, flat_bvh);
+/// ```
+///
+/// [`FlatBVH`]: type.FlatBVH.html
+/// [`FlatBVHBytes`]: trait.FlatBVHBytes.html
+/// [`Real`]: ../type.Real.html
+///
+pub trait FlatBVHTopology: Sized {
+    /// Serializes the topology of the nodes into a new buffer.
+    fn to_topology_bytes(&self) -> Vec<u8>;
+
+    /// Loads a topology which was serialized with [`FlatBVHTopology::to_topology_bytes`]
+    /// and computes the bounds of the nodes from the `shapes`. The bounds are the same as
+    /// the ones of the serialized tree, if it was tight. Returns
+    /// [`FlatBVHBytesError::InvalidTopology`] if the nodes do not form a valid tree over
+    /// the `shapes`.
+    ///
+    /// [`FlatBVHBytesError::InvalidTopology`]: enum.FlatBVHBytesError.html#variant.InvalidTopology
+    /// [`FlatBVHTopology::to_topology_bytes`]: trait.FlatBVHTopology.html#tymethod.to_topology_bytes
+    ///
+    fn from_topology_bytes<T: Bounded>(
+        bytes: &[u8],
+        shapes: &[T],
+    ) -> Result<Self, FlatBVHBytesError>;
+}
+
+impl FlatBVHTopology for FlatBVH {
+    fn to_topology_bytes(&self) -> Vec<u8> {
+        let mut bytes = FlatBVHHeader::write(
+            FLAT_BVH_TOPOLOGY_MAGIC,
+            0,
+            FLAT_BVH_TOPOLOGY_NODE_SIZE,
+            self.len(),
+        );
+        for node in self {
+            bytes.extend_from_slice(&node.exit_index.to_ne_bytes());
+            bytes.extend_from_slice(&node.shape_index.to_ne_bytes());
+        }
+        FlatBVHHeader::write_checksum(&mut bytes);
+        bytes
+    }
+
+    fn from_topology_bytes<T: Bounded>(
+        bytes: &[u8],
+        shapes: &[T],
+    ) -> Result<FlatBVH, FlatBVHBytesError> {
+        let header = FlatBVHHeader::read(
+            bytes,
+            FLAT_BVH_TOPOLOGY_MAGIC,
+            0,
+            FLAT_BVH_TOPOLOGY_NODE_SIZE,
+        )?;
+        let mut nodes: FlatBVH = bytes[FLAT_BVH_HEADER_SIZE..]
+            .chunks_exact(FLAT_BVH_TOPOLOGY_NODE_SIZE)
+            .take(header.node_count)
+            .enumerate()
+            .map(|(index, node)| {
+                let shape_index = read_u32(&node[4..], header.swapped);
+                FlatNode {
+                    aabb: AABB::empty(),
+                    // The first child of an inner node directly follows it.
+                    entry_index: if shape_index == u32::MAX {
+                        index as u32 + 1
+                    } else {
+                        u32::MAX
+                    },
+                    exit_index: read_u32(node, header.swapped),
+                    shape_index,
+                }
+            })
+            .collect();
+
+        // Refit the nodes from back to front, so that the children of every inner node,
+        // which follow it, already have their bounds.
+        let invalid = FlatBVHBytesError::InvalidTopology;
+        for index in (0..nodes.len()).rev() {
+            let exit = nodes[index].exit_index as usize;
+            if exit > nodes.len() {
+                return Err(invalid(FlatBVHValidationError::IndexOutOfRange {
+                    node: index,
+                }));
+            }
+            if exit <= index {
+                return Err(invalid(FlatBVHValidationError::InvalidExit { node: index }));
+            }
+
+            let aabb = if nodes[index].entry_index == u32::MAX {
+                let shape_index = nodes[index].shape_index as usize;
+                match shapes.get(shape_index) {
+                    Some(shape) => shape.aabb(),
+                    None => {
+                        return Err(invalid(FlatBVHValidationError::ShapeOutOfRange {
+                            node: index,
+                            shape_index,
+                        }))
+                    }
+                }
+            } else {
+                let mut aabb = AABB::empty();
+                let mut child = index + 1;
+                while child < exit {
+                    aabb.join_mut(&nodes[child].aabb);
+                    child = nodes[child].exit_index as usize;
+                }
+                aabb
+            };
+            nodes[index].aabb = aabb;
+        }
+
+        nodes.validate(shapes.len()).map_err(invalid)?;
+        Ok(nodes)
+    }
+}
+
+impl BVH {
+    /// Reconstructs a [`BVH`] from a [`FlatBVH`], e.g. one loaded with
+    /// [`FlatBVHBytes::from_bytes`], so that it can be updated with [`BVH::refit`],
+    /// [`BVH::add_node`] or [`BVH::remove_node`] again. Works for every node order of
+    /// [`BVH::flatten_ordered`]. The node indices of the shapes are not known to the
+    /// [`FlatBVH`], so they have to be set with [`BVH::update_shape_node_indices`] before
+    /// the shapes are used with the [`BVH`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::flat_bvh::{FlatBVH, FlatBVHBytes};
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..10)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let bytes = BVH::build(&mut cubes).flatten(&cubes).to_bytes();
+    ///
+    /// let mut bvh = BVH::from_flat(&FlatBVH::from_bytes(&bytes).unwrap());
+    /// bvh.update_shape_node_indices(&mut cubes);
+    /// cubes[3].pos.y += 1.0;
+    /// bvh.refit(&cubes);
+    /// bvh.assert_consistent(&cubes);
+    /// ```
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`BVH::add_node`]: ../bvh/struct.BVH.html#method.add_node
+    /// [`BVH::flatten_ordered`]: ../bvh/struct.BVH.html#method.flatten_ordered
+    /// [`BVH::refit`]: ../bvh/struct.BVH.html#method.refit
+    /// [`BVH::remove_node`]: ../bvh/struct.BVH.html#method.remove_node
+    /// [`BVH::update_shape_node_indices`]: ../bvh/struct.BVH.html#method.update_shape_node_indices
+    /// [`FlatBVH`]: type.FlatBVH.html
+    /// [`FlatBVHBytes::from_bytes`]: trait.FlatBVHBytes.html#tymethod.from_bytes
+    ///
+    pub fn from_flat(flat: &FlatBVH) -> BVH {
+        if flat.is_empty() {
+            return BVH { nodes: Vec::new() };
+        }
+        if flat[0].entry_index == u32::MAX {
+            let shape_index = flat[0].shape_index as usize;
+            return BVH {
+                nodes: vec![BVHNode::Leaf {
+                    parent_index: 0,
+                    shape_index,
+                }],
+            };
+        }
+
+        // Every inner node of the `BVH` is represented by the flat nodes of its two
+        // children. The first one is the entry of the parent's flat node (or the first flat
+        // node for the root), and the second one is the exit of the first one. A child is a
+        // leaf if its flat node is a leaf, or if its flat node only leads to a leaf.
+        let placeholder = BVHNode::Leaf {
+            parent_index: 0,
+            shape_index: 0,
+        };
+        let mut nodes = vec![placeholder];
+        let mut stack = vec![(0, 0, 0)];
+        while let Some((node_index, parent_index, flat_child_l)) = stack.pop() {
+            let flat_child_r = flat[flat_child_l].exit_index as usize;
+            let mut child_indices = [0; 2];
+            for (child_index, &flat_index) in
+                child_indices.iter_mut().zip(&[flat_child_l, flat_child_r])
+            {
+                *child_index = nodes.len();
+                nodes.push(placeholder);
+
+                let mut leaf = &flat[flat_index];
+                if leaf.entry_index != u32::MAX {
+                    let entry = leaf.entry_index as usize;
+                    leaf = &flat[entry];
+                    if leaf.entry_index != u32::MAX {
+                        stack.push((*child_index, node_index, entry));
+                        continue;
+                    }
+                }
+                nodes[*child_index] = BVHNode::Leaf {
+                    parent_index: node_index,
+                    shape_index: leaf.shape_index as usize,
+                };
+            }
+
+            nodes[node_index] = BVHNode::Node {
+                parent_index,
+                child_l_index: child_indices[0],
+                child_l_aabb: flat[flat_child_l].aabb,
+                child_r_index: child_indices[1],
+                child_r_aabb: flat[flat_child_r].aabb,
+            };
+        }
+        BVH { nodes }
+    }
+
+    /// Sets the node index of every shape to the index of the leaf which references it.
+    /// This is needed after a [`BVH`] was created without the shapes, e.g. with
+    /// [`BVH::from_flat`].
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`BVH::from_flat`]: ../bvh/struct.BVH.html#method.from_flat
+    ///
+    pub fn update_shape_node_indices<Shape: BHShape>(&self, shapes: &mut [Shape]) {
+        for (node_index, node) in self.nodes.iter().enumerate() {
+            if let BVHNode::Leaf { shape_index, .. } = *node {
+                shapes[shape_index].set_bh_node_index(node_index);
+            }
+        }
+    }
+}
+
+/// A violated invariant of a flat [`BVH`], as found by [`FlatBVHValidate::validate`].
+///
+/// [`BVH`]: ../bvh/struct.BVH.html
+/// [`FlatBVHValidate::validate`]: trait.FlatBVHValidate.html#tymethod.validate
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlatBVHValidationError {
+    /// The entry, exit or skip index of `node` points beyond the end of the nodes.
+    IndexOutOfRange {
+        /// The index of the node.
+        node: usize,
+    },
+    /// The inner node `node` does not continue with the next node.
+    InvalidEntry {
+        /// The index of the node.
+        node: usize,
+    },
+    /// The exit or skip index of `node` does not point to the end of its subtree.
+    InvalidExit {
+        /// The index of the node.
+        node: usize,
+    },
+    /// The [`AABB`] of `node` is not contained in the [`AABB`] of its parent.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    BoundsNotContained {
+        /// The index of the node.
+        node: usize,
+        /// The index of the parent node.
+        parent: usize,
+    },
+    /// The leaf `node` references a shape which does not exist.
+    ShapeOutOfRange {
+        /// The index of the node.
+        node: usize,
+        /// The referenced shape index.
+        shape_index: usize,
+    },
+    /// The shape is referenced by more than one leaf.
+    DuplicateShape {
+        /// The index of the shape.
+        shape_index: usize,
+    },
+    /// The shape is not referenced by any leaf.
+    MissingShape {
+        /// The index of the shape.
+        shape_index: usize,
+    },
+}
+
+impl fmt::Display for FlatBVHValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FlatBVHValidationError::IndexOutOfRange { node } => {
+                write!(f, "node {} points beyond the end of the nodes", node)
+            }
+            FlatBVHValidationError::InvalidEntry { node } => {
+                write!(f, "node {} does not continue with the next node", node)
+            }
+            FlatBVHValidationError::InvalidExit { node } => {
+                write!(f, "node {} does not exit at the end of its subtree", node)
+            }
+            FlatBVHValidationError::BoundsNotContained { node, parent } => write!(
+                f,
+                "the bounds of node {} are not contained in its parent {}",
+                node, parent
+            ),
+            FlatBVHValidationError::ShapeOutOfRange { node, shape_index } => write!(
+                f,
+                "node {} references the nonexistent shape {}",
+                node, shape_index
+            ),
+            FlatBVHValidationError::DuplicateShape { shape_index } => {
+                write!(f, "shape {} is referenced more than once", shape_index)
+            }
+            FlatBVHValidationError::MissingShape { shape_index } => {
+                write!(f, "shape {} is not referenced", shape_index)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FlatBVHValidationError {}
+
+/// Checks the structure of a flat [`BVH`], e.g. after it was loaded from disk or created
+/// by an external tool.
+///
+/// [`BVH`]: ../bvh/struct.BVH.html
+///
+pub trait FlatBVHValidate {
+    /// Checks that all indices are in range, that the nodes are in depth-first order with
+    /// exit or skip indices which point to the end of their subtrees, that the bounds of
+    /// every inner node contain the bounds of its children, and that each of the
+    /// `shape_count` shapes is referenced by exactly one leaf. Returns the first violated
+    /// invariant.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::flat_bvh::{FlatBVHValidate, FlatBVHValidationError};
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..10)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let bvh = BVH::build(&mut cubes);
+    /// let mut flat_bvh = bvh.flatten(&cubes);
+    /// assert_eq!(flat_bvh.validate(cubes.len()), Ok(()));
+    ///
+    /// flat_bvh[0].exit_index = 1000;
+    /// assert_eq!(
+    ///     flat_bvh.validate(cubes.len()),
+    ///     Err(FlatBVHValidationError::IndexOutOfRange { node: 0 })
+    /// );
+    /// ```
+    ///
+    fn validate(&self, shape_count: usize) -> Result<(), FlatBVHValidationError>;
+}
+
+/// Tracks the shapes referenced by the leaves during a validation.
+struct ShapeReferences {
+    referenced: Vec<bool>,
+}
+
+impl ShapeReferences {
+    fn new(shape_count: usize) -> ShapeReferences {
+        ShapeReferences {
+            referenced: vec![false; shape_count],
+        }
+    }
+
+    /// Marks the shape of the leaf `node` as referenced.
+    fn reference(&mut self, node: usize, shape_index: u32) -> Result<(), FlatBVHValidationError> {
+        let shape_index = shape_index as usize;
+        match self.referenced.get_mut(shape_index) {
+            None => Err(FlatBVHValidationError::ShapeOutOfRange { node, shape_index }),
+            Some(true) => Err(FlatBVHValidationError::DuplicateShape { shape_index }),
+            Some(referenced) => {
+                *referenced = true;
+                Ok(())
+            }
+        }
+    }
+
+    /// Checks that every shape was referenced.
+    fn finish(&self) -> Result<(), FlatBVHValidationError> {
+        match self.referenced.iter().position(|&referenced| !referenced) {
+            Some(shape_index) => Err(FlatBVHValidationError::MissingShape { shape_index }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Checks that the `AABB` of `node` is contained in the one of `parent`.
+fn check_bounds(
+    child: &AABB,
+    node: usize,
+    parent: Option<(usize, &AABB)>,
+) -> Result<(), FlatBVHValidationError> {
+    match parent {
+        Some((parent, parent_aabb)) if !parent_aabb.approx_contains_aabb_eps(child, EPSILON) => {
+            Err(FlatBVHValidationError::BoundsNotContained { node, parent })
+        }
+        _ => Ok(()),
+    }
+}
+
+impl FlatBVHValidate for FlatBVH {
+    /// The bounds of leaves are undefined in a [`FlatBVH`] and are not checked.
+    ///
+    /// [`FlatBVH`]: type.FlatBVH.html
+    ///
+    fn validate(&self, shape_count: usize) -> Result<(), FlatBVHValidationError> {
+        let mut shapes = ShapeReferences::new(shape_count);
+        // Ranges of nodes which have to be tiled by consecutive subtrees, with their parent.
+        let mut ranges = vec![(0, self.len(), None)];
+        while let Some((start, end, parent)) = ranges.pop() {
+            let mut index = start;
+            while index < end {
+                let node = &self[index];
+                let exit = node.exit_index as usize;
+                if exit > self.len()
+                    || (node.entry_index != u32::MAX && node.entry_index as usize > self.len())
+                {
+                    return Err(FlatBVHValidationError::IndexOutOfRange { node: index });
+                }
+                if node.entry_index == u32::MAX {
+                    if exit != index + 1 {
+                        return Err(FlatBVHValidationError::InvalidExit { node: index });
+                    }
+                    shapes.reference(index, node.shape_index)?;
+                } else {
+                    if node.entry_index as usize != index + 1 {
+                        return Err(FlatBVHValidationError::InvalidEntry { node: index });
+                    }
+                    if exit <= index + 1 || exit > end {
+                        return Err(FlatBVHValidationError::InvalidExit { node: index });
+                    }
+                    let parent_aabb = parent.map(|parent: usize| (parent, &self[parent].aabb));
+                    check_bounds(&node.aabb, index, parent_aabb)?;
+                    ranges.push((index + 1, exit, Some(index)));
+                }
+                index = exit;
+            }
+        }
+        shapes.finish()
+    }
+}
+
+impl FlatBVHValidate for SkipBVH {
+    fn validate(&self, shape_count: usize) -> Result<(), FlatBVHValidationError> {
+        let mut shapes = ShapeReferences::new(shape_count);
+        // Ranges of nodes which have to be tiled by consecutive subtrees, with their parent.
+        let mut ranges = vec![(0, self.len(), None)];
+        while let Some((start, end, parent)) = ranges.pop() {
+            let mut index = start;
+            while index < end {
+                let node = &self[index];
+                let skip = node.skip_index as usize;
+                if skip > self.len() {
+                    return Err(FlatBVHValidationError::IndexOutOfRange { node: index });
+                }
+                let parent_aabb = parent.map(|parent: usize| (parent, &self[parent].aabb));
+                check_bounds(&node.aabb, index, parent_aabb)?;
+                if node.shape_index == u32::MAX {
+                    if skip <= index + 1 || skip > end {
+                        return Err(FlatBVHValidationError::InvalidExit { node: index });
+                    }
+                    ranges.push((index + 1, skip, Some(index)));
+                } else {
+                    if skip != index + 1 {
+                        return Err(FlatBVHValidationError::InvalidExit { node: index });
+                    }
+                    shapes.reference(index, node.shape_index)?;
+                }
+                index = skip;
+            }
+        }
+        shapes.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::Bounded;
+    use crate::bounding_hierarchy::{BoundingHierarchy, IntersectionAABB};
+    use crate::bvh::BVH;
+    use crate::flat_bvh::nodes_checksum;
+    use crate::flat_bvh::{
+        CompactFlatBVH, FlatBVH, FlatBVH64, FlatBVHBytes, FlatBVHBytesError, FlatBVHTopology,
+        FlatBVHValidate, FlatBVHValidationError, FlatIndexOverflow, FlatNode, FlattenOrder,
+        GpuNode, GpuSkipNode, HalfNode, SkipBVH, SkipBVH32, FLAT_BVH_ALIGNMENT,
+        FLAT_BVH_HEADER_SIZE, FLAT_BVH_MIN_VERSION, FLAT_BVH_VERSION,
+    };
+    use crate::testbase::{
+        build_some_bh, create_n_cubes, create_ray, default_bounds, query_some_bh, traverse_some_bh,
+    };
+    use crate::Real;
+    use std::mem;
+
+    #[test]
+    /// Tests whether the building procedure succeeds in not failing.
+    fn test_build_flat_bvh() {
+        build_some_bh::<FlatBVH>();
+    }
+
+    #[test]
+    /// Runs some primitive tests for intersections of a ray with a fixed scene given
+    /// as a `FlatBVH`.
+    fn test_traverse_flat_bvh() {
+        traverse_some_bh::<FlatBVH>();
+    }
+
+    #[test]
+    /// Runs some primitive tests for intersections of a ray with a fixed scene given
+    /// as a `SkipBVH`.
+    fn test_traverse_skip_bvh() {
+        traverse_some_bh::<SkipBVH>();
+    }
+
+    #[test]
+    /// Runs some primitive tests for intersections of a ray with a fixed scene given
+    /// as a `FlatBVH64`.
+    fn test_traverse_flat_bvh64() {
+        traverse_some_bh::<FlatBVH64>();
+    }
+
+    #[test]
+    /// Runs some primitive tests for intersections of a ray with a fixed scene given
+    /// as a `SkipBVH32`.
+    fn test_traverse_skip_bvh32() {
+        traverse_some_bh::<SkipBVH32>();
+    }
+
+    #[test]
+    /// Runs the nearest hit and nearest neighbor queries on all flat layouts.
+    fn test_query_flat_bvh() {
+        query_some_bh::<FlatBVH>();
+        query_some_bh::<SkipBVH>();
+        query_some_bh::<FlatBVH64>();
+        query_some_bh::<SkipBVH32>();
+    }
+
+    #[test]
+    /// Tests the alignment of the nodes of a `FlatBVH64` and a `SkipBVH32`.
+    fn test_aligned_node_layout() {
+        let (mut shapes, _) = build_some_bh::<FlatBVH>();
+        let bvh = BVH::build(&mut shapes);
+
+        let flat_bvh = bvh.flatten_aligned(&shapes);
+        assert_eq!(mem::size_of_val(&flat_bvh[0]), 64);
+        assert_eq!(flat_bvh.as_ptr().align_offset(64), 0);
+        let unaligned = bvh.flatten(&shapes);
+        assert!(flat_bvh.iter().map(|node| &node.0).eq(unaligned.iter()));
+
+        let skip_bvh = bvh.flatten_skip_aligned(&shapes);
+        assert_eq!(mem::size_of_val(&skip_bvh[0]) % 32, 0);
+        assert_eq!(skip_bvh.as_ptr().align_offset(32), 0);
+        #[cfg(not(feature = "f64"))]
+        assert_eq!(mem::size_of_val(&skip_bvh[0]), 32);
+    }
+
+    #[test]
+    /// Runs some primitive tests for intersections of a ray with a `CompactFlatBVH`.
+    fn test_traverse_compact_flat_bvh() {
+        traverse_some_bh::<CompactFlatBVH<u16>>();
+        traverse_some_bh::<CompactFlatBVH<usize>>();
+    }
+
+    #[test]
+    /// Runs the nearest hit and neighbor queries on a `CompactFlatBVH`.
+    fn test_query_compact_flat_bvh() {
+        query_some_bh::<CompactFlatBVH<u16>>();
+    }
+
+    #[test]
+    /// Tests that a `CompactFlatBVH` has the indices of the `FlatBVH` and that indices which
+    /// do not fit into the index type are rejected.
+    fn test_flatten_compact() {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(100, &bounds);
+        let bvh = BVH::build(&mut triangles);
+        let flat_bvh = bvh.flatten(&triangles);
+
+        let compact_bvh = bvh.flatten_compact::<u16, _>(&triangles).unwrap();
+        assert_eq!(compact_bvh.len(), flat_bvh.len());
+        for (compact, node) in compact_bvh.iter().zip(flat_bvh.iter()) {
+            let widen = |index: u16| {
+                if index == u16::MAX {
+                    u32::MAX
+                } else {
+                    index as u32
+                }
+            };
+            assert_eq!(compact.aabb, node.aabb);
+            assert_eq!(widen(compact.entry_index), node.entry_index);
+            assert_eq!(widen(compact.exit_index), node.exit_index);
+            assert_eq!(widen(compact.shape_index), node.shape_index);
+        }
+        if mem::size_of::<Real>() == 4 {
+            assert_eq!(mem::size_of_val(&compact_bvh[0]), 32);
+        }
+
+        // 1822 cubes have 21864 triangles and need more than `u16::MAX` nodes.
+        let mut triangles = create_n_cubes(1822, &bounds);
+        let bvh = BVH::build(&mut triangles);
+        assert_eq!(
+            bvh.flatten_compact::<u16, _>(&triangles),
+            Err(FlatIndexOverflow {
+                node_count: 3 * triangles.len() - 2,
+                shape_count: triangles.len(),
+            })
+        );
+        assert!(bvh.flatten_compact::<u32, _>(&triangles).is_ok());
+    }
+
+    #[test]
+    /// Compares the traversal of differently ordered `FlatBVH`s with the `BVH`.
+    fn test_flatten_ordered_matches_bvh() {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(100, &bounds);
+        let bvh = BVH::build(&mut triangles);
+        let depth_first = bvh.flatten(&triangles);
+
+        for order in [
+            FlattenOrder::DepthFirst,
+            FlattenOrder::BreadthFirst,
+            FlattenOrder::Treelets(1),
+            FlattenOrder::Treelets(7),
+        ] {
+            let flat_bvh = bvh.flatten_ordered(&triangles, order);
+            assert_eq!(flat_bvh.len(), depth_first.len());
+
+            let mut seed = 0;
+            for _ in 0..50 {
+                let ray = create_ray(&mut seed, &bounds);
+                let mut expected = bvh.traverse_indices(&ray);
+                let mut actual: Vec<usize> = flat_bvh
+                    .traverse(&ray, &triangles)
+                    .iter()
+                    .map(|&triangle| {
+                        triangles
+                            .iter()
+                            .position(|other| std::ptr::eq(other, triangle))
+                            .unwrap()
+                    })
+                    .collect();
+                expected.sort_unstable();
+                actual.sort_unstable();
+                assert_eq!(expected, actual, "{:?}", order);
+            }
+        }
+    }
+
+    #[test]
+    /// Checks the skip indices and compares `SkipBVH` traversal with the `BVH`.
+    fn test_skip_bvh_matches_bvh() {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(100, &bounds);
+        let bvh = BVH::build(&mut triangles);
+        let skip_bvh = bvh.flatten_skip(&triangles);
+        assert_eq!(skip_bvh.len(), bvh.nodes.len());
+        assert_eq!(skip_bvh[0].skip_index as usize, skip_bvh.len());
+        for (i, node) in skip_bvh.iter().enumerate() {
+            if node.shape_index != u32::MAX {
+                assert_eq!(node.skip_index as usize, i + 1);
+            }
+        }
+
+        let mut seed = 0;
+        for _ in 0..100 {
+            let ray = create_ray(&mut seed, &bounds);
+            let mut expected = bvh.traverse(&ray, &triangles);
+            let mut actual = skip_bvh.traverse(&ray, &triangles);
+            expected.sort_by_key(|triangle| *triangle as *const _);
+            actual.sort_by_key(|triangle| *triangle as *const _);
+            assert!(expected
+                .iter()
+                .zip(actual.iter())
+                .all(|(a, b)| std::ptr::eq(*a, *b)));
+            assert_eq!(expected.len(), actual.len());
+        }
+    }
+
+    #[test]
+    /// Checks the documented memory layout of `GpuNode` and that it matches the `FlatBVH`.
+    fn test_gpu_node_layout() {
+        use std::mem::{align_of, size_of};
+
+        assert_eq!(size_of::<GpuNode>(), 48);
+        assert_eq!(align_of::<GpuNode>(), 16);
+
+        let (mut shapes, _) = build_some_bh::<FlatBVH>();
+        let bvh = BVH::build(&mut shapes);
+        let flat = bvh.flatten(&shapes);
+        let gpu = bvh.flatten_gpu(&shapes);
+        assert_eq!(flat.len(), gpu.len());
+        for (flat_node, gpu_node) in flat.iter().zip(gpu.iter()) {
+            let expected = GpuNode::new(
+                &flat_node.aabb,
+                flat_node.entry_index,
+                flat_node.exit_index,
+                flat_node.shape_index,
+            );
+            assert_eq!(*gpu_node, expected);
+        }
+
+        #[cfg(feature = "bytemuck")]
+        {
+            let bytes: &[u8] = bytemuck::cast_slice(&gpu);
+            assert_eq!(bytes.len(), gpu.len() * 48);
+            assert_eq!(bytemuck::cast_slice::<u8, GpuNode>(bytes), gpu.as_slice());
+        }
+    }
+
+    #[test]
+    /// Tests that `HalfNode`s enclose the bounds of the `FlatNode`s, so that a traversal of
+    /// their bounds finds every shape which the exact traversal finds.
+    fn test_half_nodes_are_conservative() {
+        assert_eq!(mem::size_of::<HalfNode>(), 24);
+
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(100, &bounds);
+        let bvh = BVH::build(&mut triangles);
+        let flat_bvh = bvh.flatten(&triangles);
+        let half_nodes = bvh.flatten_half(&triangles);
+
+        let half_bvh: FlatBVH = half_nodes
+            .iter()
+            .zip(flat_bvh.iter())
+            .map(|(half, flat)| {
+                let aabb = half.aabb();
+                assert!(aabb.contains(&flat.aabb.min) && aabb.contains(&flat.aabb.max));
+                assert_eq!(half.entry_index, flat.entry_index);
+                assert_eq!(half.exit_index, flat.exit_index);
+                assert_eq!(half.shape_index, flat.shape_index);
+                FlatNode { aabb, ..*flat }
+            })
+            .collect();
+
+        let mut seed = 0;
+        for _ in 0..100 {
+            let ray = create_ray(&mut seed, &bounds);
+            let half_hits = half_bvh.traverse(&ray, &triangles);
+            for hit in flat_bvh.traverse(&ray, &triangles) {
+                assert!(half_hits
+                    .iter()
+                    .any(|half_hit| std::ptr::eq(*half_hit, hit)));
+            }
+        }
+    }
+
+    #[test]
+    /// Tests the size of a `GpuSkipNode` and that it matches the `SkipNode`s.
+    fn test_gpu_skip_node_layout() {
+        use std::mem::{align_of, size_of};
+
+        assert_eq!(size_of::<GpuSkipNode>(), 32);
+        assert_eq!(align_of::<GpuSkipNode>(), 16);
+
+        let (mut shapes, _) = build_some_bh::<FlatBVH>();
+        let bvh = BVH::build(&mut shapes);
+        let skip = bvh.flatten_skip(&shapes);
+        let gpu = bvh.flatten_skip_gpu(&shapes);
+        assert_eq!(skip.len(), gpu.len());
+        for (skip_node, gpu_node) in skip.iter().zip(gpu.iter()) {
+            assert_eq!(*gpu_node, GpuSkipNode::new(skip_node));
+            assert_eq!(gpu_node.skip_index, skip_node.skip_index);
+            assert_eq!(gpu_node.shape_index, skip_node.shape_index);
+        }
+    }
+
+    #[test]
+    /// Traverses flat nodes with leaf ranges for several leaf sizes and compares the hits
+    /// with the `BVH`.
+    fn test_flatten_with_ranges_matches_bvh() {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(100, &bounds);
+        let bvh = BVH::build(&mut triangles);
+
+        for max_leaf_size in [1, 4, 16, 10_000] {
+            let (nodes, primitives) = bvh.flatten_with_ranges(&triangles, max_leaf_size);
+            let mut sorted = primitives.clone();
+            sorted.sort_unstable();
+            assert_eq!(sorted, (0..triangles.len() as u32).collect::<Vec<_>>());
+            assert!(nodes
+                .iter()
+                .all(|node| node.primitive_count <= max_leaf_size));
+
+            let mut seed = 0;
+            for _ in 0..50 {
+                let ray = create_ray(&mut seed, &bounds);
+                let mut hits = Vec::new();
+                let mut index = 0;
+                while index < nodes.len() {
+                    let node = &nodes[index];
+                    if !ray.intersects_aabb(&node.aabb) {
+                        index = node.exit_index as usize;
+                    } else if node.entry_index == u32::MAX {
+                        let first = node.first_primitive as usize;
+                        let count = node.primitive_count as usize;
+                        for &shape_index in &primitives[first..first + count] {
+                            if ray.intersects_aabb(&triangles[shape_index as usize].aabb()) {
+                                hits.push(shape_index as usize);
+                            }
+                        }
+                        index = node.exit_index as usize;
+                    } else {
+                        index = node.entry_index as usize;
+                    }
+                }
+                let mut expected = bvh.traverse_indices(&ray);
+                expected.sort_unstable();
+                hits.sort_unstable();
+                assert_eq!(expected, hits);
+            }
+        }
+    }
+
+    #[test]
+    /// Tests that a `FlatBVH` survives a round trip through its binary format, both as a
+    /// copy and in place.
+    fn test_flat_bvh_bytes_round_trip() {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(100, &bounds);
+        let flat_bvh = BVH::build(&mut triangles).flatten(&triangles);
+        let bytes = flat_bvh.to_bytes();
+        assert_eq!(
+            bytes.len(),
+            FLAT_BVH_HEADER_SIZE + flat_bvh.len() * mem::size_of::<FlatNode>()
+        );
+        assert_eq!(FlatBVH::from_bytes(&bytes).unwrap(), flat_bvh);
+
+        // Place the bytes at an aligned offset, like in a memory mapped file.
+        let mut buffer = vec![0u8; bytes.len() + FLAT_BVH_ALIGNMENT];
+        let offset = buffer.as_ptr().align_offset(FLAT_BVH_ALIGNMENT);
+        buffer[offset..offset + bytes.len()].copy_from_slice(&bytes);
+        let aligned = &buffer[offset..offset + bytes.len()];
+        assert_eq!(FlatNode::slice_from_bytes(aligned).unwrap(), &flat_bvh[..]);
+        assert_eq!(
+            FlatNode::slice_from_bytes(&buffer[offset + 1..]),
+            Err(FlatBVHBytesError::InvalidMagic)
+        );
+    }
+
+    #[test]
+    /// Tests that a `FlatBVH` written with the opposite byte order is converted on load.
+    fn test_flat_bvh_bytes_foreign_endianness() {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(10, &bounds);
+        let flat_bvh = BVH::build(&mut triangles).flatten(&triangles);
+        let mut bytes = flat_bvh.to_bytes();
+
+        // Swap the byte order of every header field after the magic bytes and of every node
+        // field, which turns the buffer into one written by a foreign machine.
+        for field in bytes[4..24].chunks_exact_mut(4) {
+            field.reverse();
+        }
+        let real_size = mem::size_of::<Real>();
+        for node in bytes[FLAT_BVH_HEADER_SIZE..].chunks_exact_mut(mem::size_of::<FlatNode>()) {
+            let (reals, indices) = node.split_at_mut(6 * real_size);
+            reals
+                .chunks_exact_mut(real_size)
+                .for_each(|field| field.reverse());
+            indices[..12]
+                .chunks_exact_mut(4)
+                .for_each(|field| field.reverse());
+        }
+        // The foreign machine computed the checksum over its own bytes.
+        let checksum = nodes_checksum(&bytes[FLAT_BVH_HEADER_SIZE..]).swap_bytes();
+        bytes[24..28].copy_from_slice(&checksum.to_ne_bytes());
+
+        assert_eq!(FlatBVH::from_bytes(&bytes).unwrap(), flat_bvh);
+        assert_eq!(
+            FlatNode::slice_from_bytes(&bytes),
+            Err(FlatBVHBytesError::ForeignEndianness)
+        );
+    }
+
+    #[test]
+    /// Tests that invalid buffers are rejected with the matching error.
+    fn test_flat_bvh_bytes_errors() {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(10, &bounds);
+        let bytes = BVH::build(&mut triangles).flatten(&triangles).to_bytes();
+
+        assert_eq!(
+            FlatBVH::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(FlatBVHBytesError::Truncated)
+        );
+        assert_eq!(
+            FlatBVH::from_bytes(&bytes[..8]),
+            Err(FlatBVHBytesError::Truncated)
+        );
+
+        let mut invalid = bytes.clone();
+        invalid[0] = b'X';
+        assert_eq!(
+            FlatBVH::from_bytes(&invalid),
+            Err(FlatBVHBytesError::InvalidMagic)
+        );
+
+        let mut invalid = bytes.clone();
+        invalid[4..8].copy_from_slice(&7u32.to_ne_bytes());
+        assert_eq!(
+            FlatBVH::from_bytes(&invalid),
+            Err(FlatBVHBytesError::UnsupportedVersion(7))
+        );
+
+        let mut invalid = bytes.clone();
+        invalid[8..12].copy_from_slice(&0u32.to_ne_bytes());
+        assert_eq!(
+            FlatBVH::from_bytes(&invalid),
+            Err(FlatBVHBytesError::InvalidEndianness)
+        );
+
+        let mut invalid = bytes;
+        invalid[12..16].copy_from_slice(&2u32.to_ne_bytes());
+        assert_eq!(
+            FlatBVH::from_bytes(&invalid),
+            Err(FlatBVHBytesError::LayoutMismatch)
+        );
+    }
+
+    #[test]
+    /// Tests that buffers of the previous format version without a checksum are still
+    /// loaded, and that damaged nodes are detected in the current version.
+    fn test_flat_bvh_bytes_versions() {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(10, &bounds);
+        let flat_bvh = BVH::build(&mut triangles).flatten(&triangles);
+        let bytes = flat_bvh.to_bytes();
+        assert_eq!(FLAT_BVH_VERSION, 2);
+
+        let mut damaged = bytes.clone();
+        damaged[FLAT_BVH_HEADER_SIZE] ^= 1;
+        assert_eq!(
+            FlatBVH::from_bytes(&damaged),
+            Err(FlatBVHBytesError::ChecksumMismatch)
+        );
+
+        // A version 1 buffer is a version 2 buffer without the checksum.
+        let mut version_1 = bytes.clone();
+        version_1[4..8].copy_from_slice(&FLAT_BVH_MIN_VERSION.to_ne_bytes());
+        version_1[24..28].copy_from_slice(&0u32.to_ne_bytes());
+        assert_eq!(FlatBVH::from_bytes(&version_1).unwrap(), flat_bvh);
+        version_1[FLAT_BVH_HEADER_SIZE] ^= 1;
+        assert!(FlatBVH::from_bytes(&version_1).is_ok());
+
+        let mut future = bytes;
+        future[4..8].copy_from_slice(&(FLAT_BVH_VERSION + 1).to_ne_bytes());
+        assert_eq!(
+            FlatBVH::from_bytes(&future),
+            Err(FlatBVHBytesError::UnsupportedVersion(FLAT_BVH_VERSION + 1))
+        );
+    }
+
+    #[test]
+    /// Tests that a `FlatBVH` loaded from its topology has the bounds of the original one and
+    /// that invalid topologies are rejected.
+    fn test_flat_bvh_topology_round_trip() {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(100, &bounds);
+        let flat_bvh = BVH::build(&mut triangles).flatten(&triangles);
+        let bytes = flat_bvh.to_topology_bytes();
+        assert_eq!(bytes.len(), FLAT_BVH_HEADER_SIZE + flat_bvh.len() * 8);
+        assert_eq!(
+            FlatBVH::from_topology_bytes(&bytes, &triangles).unwrap(),
+            flat_bvh
+        );
+
+        assert_eq!(
+            FlatBVH::from_bytes(&bytes),
+            Err(FlatBVHBytesError::InvalidMagic)
+        );
+        assert_eq!(
+            FlatBVH::from_topology_bytes(&flat_bvh.to_bytes(), &triangles),
+            Err(FlatBVHBytesError::InvalidMagic)
+        );
+        assert_eq!(
+            FlatBVH::from_topology_bytes(&bytes, &triangles[1..]),
+            Err(FlatBVHBytesError::InvalidTopology(
+                FlatBVHValidationError::ShapeOutOfRange {
+                    node: flat_bvh
+                        .iter()
+                        .rposition(|node| node.shape_index as usize == triangles.len() - 1)
+                        .unwrap(),
+                    shape_index: triangles.len() - 1,
+                }
+            ))
+        );
+
+        let mut invalid = flat_bvh;
+        invalid[0].exit_index = 1;
+        assert_eq!(
+            FlatBVH::from_topology_bytes(&invalid.to_topology_bytes(), &triangles),
+            Err(FlatBVHBytesError::InvalidTopology(
+                FlatBVHValidationError::InvalidExit { node: 0 }
+            ))
+        );
+    }
+
+    #[cfg(feature = "serde_impls")]
+    #[test]
+    /// Tests that a `BVH` and its flat forms survive a round trip through serde, and that the
+    /// fields of a `FlatNode` keep their names.
+    fn test_flat_bvh_serde() {
+        use crate::aabb::AABB;
+        use crate::Point3;
+
+        let node = FlatNode {
+            aabb: AABB::with_bounds(Point3::new(0.0, 1.0, 2.0), Point3::new(3.0, 4.0, 5.0)),
+            entry_index: u32::MAX,
+            exit_index: 1,
+            shape_index: 2,
+        };
+        assert_eq!(
+            serde_json::to_string(&node).unwrap(),
+            "{\"aabb\":{\"min\":[0.0,1.0,2.0],\"max\":[3.0,4.0,5.0]},\
+             \"entry_index\":4294967295,\"exit_index\":1,\"shape_index\":2}"
+        );
+
+        // The boxes have integer bounds, which survive the decimal representation exactly.
+        let (boxes, bvh) = build_some_bh::<BVH>();
+        let json = serde_json::to_string(&bvh).unwrap();
+        let deserialized: BVH = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized.flatten(&boxes), bvh.flatten(&boxes));
+
+        let flat_bvh = bvh.flatten(&boxes);
+        let json = serde_json::to_string(&flat_bvh).unwrap();
+        assert_eq!(serde_json::from_str::<FlatBVH>(&json).unwrap(), flat_bvh);
+
+        let aligned = bvh.flatten_aligned(&boxes);
+        assert_eq!(serde_json::to_string(&aligned).unwrap(), json);
+        assert_eq!(serde_json::from_str::<FlatBVH64>(&json).unwrap(), aligned);
+    }
+
+    #[cfg(feature = "rkyv")]
+    #[test]
+    /// Tests that an archived `FlatBVH` is a slice of the original nodes, which can be used
+    /// without deserializing it.
+    fn test_flat_bvh_rkyv() {
+        use rkyv::Deserialize;
+
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(100, &bounds);
+        let flat_bvh = BVH::build(&mut triangles).flatten(&triangles);
+
+        let bytes = rkyv::to_bytes::<_, 1024>(&flat_bvh).unwrap();
+        let archived = unsafe { rkyv::archived_root::<FlatBVH>(&bytes) };
+        let nodes: &[FlatNode] = archived;
+        assert_eq!(nodes, flat_bvh.as_slice());
+
+        let deserialized: FlatBVH = archived.deserialize(&mut rkyv::Infallible).unwrap();
+        assert_eq!(deserialized, flat_bvh);
+    }
+
+    #[test]
+    /// Tests that a `BVH` reconstructed from a `FlatBVH` of any order flattens to the same
+    /// nodes as the original one and is consistent with the shapes.
+    fn test_bvh_from_flat() {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(100, &bounds);
+        let bvh = BVH::build(&mut triangles);
+        let flat_bvh = bvh.flatten(&triangles);
+
+        for order in [
+            FlattenOrder::DepthFirst,
+            FlattenOrder::BreadthFirst,
+            FlattenOrder::Treelets(7),
+        ] {
+            let mut triangles = triangles.clone();
+            let mut from_flat = BVH::from_flat(&bvh.flatten_ordered(&triangles, order));
+            assert_eq!(from_flat.nodes.len(), bvh.nodes.len());
+            from_flat.update_shape_node_indices(&mut triangles);
+            from_flat.assert_consistent(&triangles);
+            from_flat.assert_tight(&triangles);
+            assert_eq!(from_flat.flatten(&triangles), flat_bvh);
+
+            // The reconstructed `BVH` supports dynamic updates again.
+            from_flat.remove_node(&mut triangles, 0, true);
+            triangles.truncate(triangles.len() - 1);
+            from_flat.assert_consistent(&triangles);
+        }
+    }
+
+    #[test]
+    /// Tests the reconstruction of a `BVH` from an empty `FlatBVH` and one with one shape.
+    fn test_bvh_from_small_flat() {
+        assert!(BVH::from_flat(&FlatBVH::new()).nodes.is_empty());
+
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(1, &bounds);
+        triangles.truncate(1);
+        let bvh = BVH::build(&mut triangles);
+        let from_flat = BVH::from_flat(&bvh.flatten(&triangles));
+        assert_eq!(from_flat.nodes, bvh.nodes);
+    }
+
+    #[test]
+    /// Tests that flattened trees are valid and that corruptions are reported.
+    fn test_validate_flat_bvh() {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(10, &bounds);
+        let bvh = BVH::build(&mut triangles);
+        let count = triangles.len();
+
+        assert_eq!(FlatBVH::new().validate(0), Ok(()));
+        assert_eq!(
+            FlatBVH::new().validate(1),
+            Err(FlatBVHValidationError::MissingShape { shape_index: 0 })
+        );
+
+        let flat_bvh = bvh.flatten(&triangles);
+        assert_eq!(flat_bvh.validate(count), Ok(()));
+        assert_eq!(
+            flat_bvh.validate(count + 1),
+            Err(FlatBVHValidationError::MissingShape { shape_index: count })
+        );
+
+        let mut invalid = flat_bvh.clone();
+        invalid[0].entry_index = 2;
+        assert_eq!(
+            invalid.validate(count),
+            Err(FlatBVHValidationError::InvalidEntry { node: 0 })
+        );
+
+        let mut invalid = flat_bvh.clone();
+        invalid[0].exit_index = 1;
+        assert_eq!(
+            invalid.validate(count),
+            Err(FlatBVHValidationError::InvalidExit { node: 0 })
+        );
+
+        let inner = (1..flat_bvh.len())
+            .find(|&i| flat_bvh[i].entry_index != u32::MAX)
+            .unwrap();
+        let mut invalid = flat_bvh.clone();
+        invalid[inner].aabb.max.x = invalid[0].aabb.max.x + 1.0;
+        assert!(matches!(
+            invalid.validate(count),
+            Err(FlatBVHValidationError::BoundsNotContained { node, .. }) if node == inner
+        ));
+
+        let leaves: Vec<usize> = (0..flat_bvh.len())
+            .filter(|&i| flat_bvh[i].entry_index == u32::MAX)
+            .collect();
+        let mut invalid = flat_bvh.clone();
+        invalid[leaves[1]].shape_index = invalid[leaves[0]].shape_index;
+        assert!(matches!(
+            invalid.validate(count),
+            Err(FlatBVHValidationError::DuplicateShape { .. })
+        ));
+        let mut invalid = flat_bvh;
+        invalid[leaves[0]].shape_index = count as u32;
+        assert_eq!(
+            invalid.validate(count),
+            Err(FlatBVHValidationError::ShapeOutOfRange {
+                node: leaves[0],
+                shape_index: count
+            })
+        );
+
+        let skip_bvh = bvh.flatten_skip(&triangles);
+        assert_eq!(skip_bvh.validate(count), Ok(()));
+
+        let mut invalid = skip_bvh.clone();
+        invalid[0].skip_index = invalid.len() as u32 + 1;
+        assert_eq!(
+            invalid.validate(count),
+            Err(FlatBVHValidationError::IndexOutOfRange { node: 0 })
+        );
+
+        let leaf = (0..skip_bvh.len())
+            .find(|&i| skip_bvh[i].shape_index != u32::MAX)
+            .unwrap();
+        let mut invalid = skip_bvh;
+        invalid[leaf].aabb.min.y = invalid[0].aabb.min.y - 1.0;
+        assert!(matches!(
+            invalid.validate(count),
+            Err(FlatBVHValidationError::BoundsNotContained { node, .. }) if node == leaf
+        ));
+    }
+}
+
+#[cfg(all(feature = "bench", test))]
+mod bench {
+    use crate::bvh::BVH;
+    use crate::flat_bvh::{FlatBVH, FlatBVH64, FlattenOrder, SkipBVH, SkipBVH32};
+
+    use crate::testbase::{
+        build_1200_triangles_bh, build_120k_triangles_bh, build_12k_triangles_bh, create_n_cubes,
+        default_bounds, intersect_1200_triangles_bh, intersect_120k_triangles_bh,
+        intersect_12k_triangles_bh, intersect_bh,
+    };
+
+    #[bench]
+    /// Benchmark the flattening of a BVH with 120,000 triangles.
+    fn bench_flatten_120k_triangles_bvh(b: &mut ::test::Bencher) {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(10_000, &bounds);
+        let bvh = BVH::build(&mut triangles);
+
+        b.iter(|| {
+            bvh.flatten(&triangles);
+        });
+    }
+    #[bench]
+    /// Benchmark the construction of a `FlatBVH` with 1,200 triangles.
+    fn bench_build_1200_triangles_flat_bvh(b: &mut ::test::Bencher) {
+        build_1200_triangles_bh::<FlatBVH>(b);
+    }
+
+    #[bench]
+    /// Benchmark the construction of a `FlatBVH` with 12,000 triangles.
     fn bench_build_12k_triangles_flat_bvh(b: &mut ::test::Bencher) {
         build_12k_triangles_bh::<FlatBVH>(b);
     }
@@ -513,4 +3733,49 @@ mod bench {
     fn bench_intersect_120k_triangles_flat_bvh(b: &mut ::test::Bencher) {
         intersect_120k_triangles_bh::<FlatBVH>(b);
     }
+
+    #[bench]
+    /// Benchmark intersecting 120,000 triangles using a cache line aligned `FlatBVH64`.
+    fn bench_intersect_120k_triangles_flat_bvh64(b: &mut ::test::Bencher) {
+        intersect_120k_triangles_bh::<FlatBVH64>(b);
+    }
+
+    #[bench]
+    /// Benchmark intersecting 120,000 triangles using a `SkipBVH`.
+    fn bench_intersect_120k_triangles_skip_bvh(b: &mut ::test::Bencher) {
+        intersect_120k_triangles_bh::<SkipBVH>(b);
+    }
+
+    #[bench]
+    /// Benchmark intersecting 120,000 triangles using a 32 byte aligned `SkipBVH32`.
+    fn bench_intersect_120k_triangles_skip_bvh32(b: &mut ::test::Bencher) {
+        intersect_120k_triangles_bh::<SkipBVH32>(b);
+    }
+
+    /// Benchmark intersecting 120,000 triangles using a `FlatBVH` laid out in `order`.
+    fn intersect_120k_triangles_ordered(order: FlattenOrder, b: &mut ::test::Bencher) {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(10_000, &bounds);
+        let bvh = BVH::build(&mut triangles);
+        let flat_bvh = bvh.flatten_ordered(&triangles, order);
+        intersect_bh(&flat_bvh, &triangles, &bounds, b);
+    }
+
+    #[bench]
+    /// Benchmark intersecting 120,000 triangles using a depth-first `FlatBVH`.
+    fn bench_intersect_120k_triangles_flat_bvh_depth_first(b: &mut ::test::Bencher) {
+        intersect_120k_triangles_ordered(FlattenOrder::DepthFirst, b);
+    }
+
+    #[bench]
+    /// Benchmark intersecting 120,000 triangles using a breadth-first `FlatBVH`.
+    fn bench_intersect_120k_triangles_flat_bvh_breadth_first(b: &mut ::test::Bencher) {
+        intersect_120k_triangles_ordered(FlattenOrder::BreadthFirst, b);
+    }
+
+    #[bench]
+    /// Benchmark intersecting 120,000 triangles using a `FlatBVH` in treelets of 16 nodes.
+    fn bench_intersect_120k_triangles_flat_bvh_treelets(b: &mut ::test::Bencher) {
+        intersect_120k_triangles_ordered(FlattenOrder::Treelets(16), b);
+    }
 }
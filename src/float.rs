@@ -0,0 +1,296 @@
+//! Floating point comparisons which scale with the compared values, and bounds on the
+//! rounding errors of floating point arithmetic.
+//!
+//! An absolute tolerance like [`EPSILON`] is too loose for tiny scenes and too tight for
+//! huge ones, since the spacing of floating point numbers grows with their magnitude.
+//! [`ulps_eq`] and [`relative_eq`] compare values relative to that spacing instead, and
+//! [`gamma`] bounds the error of computations with a known number of operations, and
+//! [`Tolerances`] replace [`EPSILON`] in the intersection tests of a single query.
+//!
+//! [`EPSILON`]: ../constant.EPSILON.html
+//! [`ulps_eq`]: fn.ulps_eq.html
+//! [`relative_eq`]: fn.relative_eq.html
+//! [`gamma`]: fn.gamma.html
+//! [`Tolerances`]: struct.Tolerances.html
+//!
+
+use crate::{Point3, Real, Vector3, EPSILON};
+
+/// The largest relative error of rounding a real number to the nearest [`Real`], which is
+/// half of the distance from `1.0` to the next [`Real`].
+///
+/// [`Real`]: ../type.Real.html
+///
+pub const MACHINE_EPSILON: Real = Real::EPSILON * 0.5;
+
+/// Returns the bound `γ(n) = nε / (1 - nε)` on the relative error of a result which is
+/// computed with `n` rounded operations, where `ε` is the [`MACHINE_EPSILON`], following
+/// Physically Based Rendering, section 3.9. For example, the error of a sum of three
+/// values is at most `γ(2)` times the sum of their magnitudes.
+///
+/// # Examples
+/// ```
+/// use bvh::float::gamma;
+///
+/// let values = [1.0e8, 3.0, -1.0e8];
+/// let sum: f32 = values.iter().sum();
+/// let magnitude: f32 = values.iter().map(|value| value.abs()).sum();
+/// assert!((sum - 3.0).abs() <= gamma(2) * magnitude);
+/// ```
+///
+/// [`MACHINE_EPSILON`]: constant.MACHINE_EPSILON.html
+///
+pub fn gamma(n: u32) -> Real {
+    let n = n as Real * MACHINE_EPSILON;
+    n / (1.0 - n)
+}
+
+/// Maps the bits of a value to an integer which orders like the values themselves, so
+/// that neighboring values differ by one, and `-0.0` and `0.0` map to the same integer.
+fn ordered_bits(value: Real) -> i128 {
+    let magnitude = value.abs().to_bits() as i128;
+    if value.is_sign_negative() {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+/// Returns true if `a` and `b` are at most `max_ulps` representable values apart, so that
+/// the tolerance grows with their magnitude. Values of different signs are only equal if
+/// both are zero, and `NaN` is never equal to anything.
+///
+/// # Examples
+/// ```
+/// use bvh::float::ulps_eq;
+///
+/// assert!(ulps_eq(1.0e-30 + 1.0e-37, 1.0e-30, 4));
+/// assert!(ulps_eq(1.0e30 + 1.0e23, 1.0e30, 4));
+/// assert!(!ulps_eq(1.0, 1.001, 4));
+/// assert!(ulps_eq(0.0, -0.0, 0));
+/// ```
+///
+pub fn ulps_eq(a: Real, b: Real, max_ulps: u32) -> bool {
+    if a.is_nan() || b.is_nan() {
+        return false;
+    }
+    (ordered_bits(a) - ordered_bits(b)).abs() <= max_ulps as i128
+}
+
+/// Returns true if `a` and `b` differ by at most `max_relative` times the larger of their
+/// magnitudes. Values near zero need a relative tolerance close to `1.0`, so compare them
+/// with an absolute tolerance instead. Infinite values only equal themselves.
+///
+/// # Examples
+/// ```
+/// use bvh::float::relative_eq;
+///
+/// assert!(relative_eq(1.0e-9, 1.000001e-9, 1.0e-5));
+/// assert!(!relative_eq(1.0e-9, 2.0e-9, 1.0e-5));
+/// assert!(relative_eq(1.0e9, 1.000001e9, 1.0e-5));
+/// ```
+///
+pub fn relative_eq(a: Real, b: Real, max_relative: Real) -> bool {
+    if !a.is_finite() || !b.is_finite() {
+        return a == b;
+    }
+    (a - b).abs() <= max_relative * a.abs().max(b.abs())
+}
+
+/// Returns true if every component of `a` and `b` differs by at most `max_relative` times
+/// the largest component magnitude of both. Unlike comparing each component with
+/// [`relative_eq`], components near zero are compared with the scale of the whole vector,
+/// which is the scale of their rounding errors.
+///
+/// # Examples
+/// ```
+/// use bvh::float::relative_eq_vector;
+/// use bvh::Vector3;
+///
+/// let a = Vector3::new(1.0e6, 0.0, 2.0e6);
+/// let b = Vector3::new(1.0e6, 0.5, 2.0e6 + 1.0);
+/// assert!(relative_eq_vector(a, b, 1.0e-6));
+/// assert!(!relative_eq_vector(a, b * 1.01, 1.0e-6));
+/// ```
+///
+/// [`relative_eq`]: fn.relative_eq.html
+///
+pub fn relative_eq_vector(a: Vector3, b: Vector3, max_relative: Real) -> bool {
+    if !a.is_finite() || !b.is_finite() {
+        return a == b;
+    }
+    let scale = a.abs().max(b.abs()).max_element();
+    (a - b).abs().max_element() <= max_relative * scale
+}
+
+/// The tolerances of the ray/triangle intersection tests, which default to [`EPSILON`].
+/// That suits scenes whose triangles are about one unit large. Millimeter sized triangles
+/// in a CAD model are then mistaken for triangles seen edge-on, while in kilometer sized
+/// terrain rays starting on a surface hit it again due to rounding. Pass tolerances made
+/// for the scene with [`Tolerances::scaled`] to the `_eps` variants of the tests instead,
+/// e.g. [`Ray::intersects_triangle_eps`] or [`TriMesh::intersects_ray_face_eps`].
+///
+/// # Examples
+/// ```
+/// use bvh::float::Tolerances;
+/// use bvh::ray::Ray;
+/// use bvh::{Point3, Vector3};
+///
+/// // A triangle with sides of a tenth of a millimeter, in meters.
+/// let a = Point3::new(0.0, 0.0, 0.0);
+/// let b = Point3::new(1.0e-4, 0.0, 0.0);
+/// let c = Point3::new(0.0, 1.0e-4, 0.0);
+/// let ray = Ray::new(Point3::new(2.0e-5, 2.0e-5, 1.0), Vector3::new(0.0, 0.0, -1.0));
+///
+/// assert_eq!(ray.intersects_triangle(&a, &b, &c).distance, f32::INFINITY);
+/// let hit = ray.intersects_triangle_eps(&a, &b, &c, &Tolerances::scaled(1.0e-4));
+/// assert_eq!(hit.distance, 1.0);
+/// ```
+///
+/// [`EPSILON`]: ../constant.EPSILON.html
+/// [`Ray::intersects_triangle_eps`]: ../ray/struct.Ray.html#method.intersects_triangle_eps
+/// [`Tolerances::scaled`]: struct.Tolerances.html#method.scaled
+/// [`TriMesh::intersects_ray_face_eps`]: ../tri_mesh/struct.TriMesh.html#method.intersects_ray_face_eps
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tolerances {
+    /// Hits closer to the origin of the ray are ignored, so that rays which start on a
+    /// surface do not hit it again.
+    pub min_distance: Real,
+    /// Rays are treated as parallel to a triangle, and miss it, if the area of the
+    /// triangle as seen along the ray is smaller than half of this, in squared units.
+    pub parallel: Real,
+    /// Triangles whose height is smaller than this times their longest edge are degenerate
+    /// and never hit, see [`Tolerances::is_degenerate`]. As a ratio it does not depend on
+    /// the scale of the scene.
+    ///
+    /// [`Tolerances::is_degenerate`]: struct.Tolerances.html#method.is_degenerate
+    ///
+    pub sliver: Real,
+}
+
+impl Default for Tolerances {
+    fn default() -> Tolerances {
+        Tolerances {
+            min_distance: EPSILON,
+            parallel: EPSILON,
+            sliver: EPSILON,
+        }
+    }
+}
+
+impl Tolerances {
+    /// Returns the default tolerances adjusted to a scene whose triangles are about
+    /// `scale` units large, so `Tolerances::scaled(1.0)` equals the default.
+    pub fn scaled(scale: Real) -> Tolerances {
+        Tolerances {
+            min_distance: EPSILON * scale,
+            parallel: EPSILON * scale * scale,
+            sliver: EPSILON,
+        }
+    }
+
+    /// Returns true if the triangle `a`, `b`, `c` is degenerate, which means that its
+    /// height is at most `sliver` times its longest edge. This includes triangles without
+    /// area, whose points are equal or on a line, and slivers whose intersections would
+    /// divide by a determinant which is mostly rounding error. The intersection tests of
+    /// [`Ray`] never hit degenerate triangles.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::float::Tolerances;
+    /// use bvh::Point3;
+    ///
+    /// let tolerances = Tolerances::default();
+    /// let a = Point3::new(0.0, 0.0, 0.0);
+    /// let b = Point3::new(1000.0, 0.0, 0.0);
+    /// assert!(tolerances.is_degenerate(&a, &b, &Point3::new(500.0, 0.0, 0.0)));
+    /// assert!(tolerances.is_degenerate(&a, &b, &Point3::new(500.0, 1.0e-3, 0.0)));
+    /// assert!(!tolerances.is_degenerate(&a, &b, &Point3::new(500.0, 1.0, 0.0)));
+    /// ```
+    ///
+    /// [`Ray`]: ../ray/struct.Ray.html
+    ///
+    pub fn is_degenerate(&self, a: &Point3, b: &Point3, c: &Point3) -> bool {
+        let longest = a.distance(*b).max(a.distance(*c)).max(b.distance(*c));
+        // Points which coincide, or are not finite, span no triangle.
+        if !longest.is_normal() {
+            return true;
+        }
+        // The edges are scaled to a longest edge of one before their cross product, which
+        // would overflow for large coordinates, so its length is the relative height.
+        let a_to_b = (*b - *a) / longest;
+        let a_to_c = (*c - *a) / longest;
+        a_to_b.cross(a_to_c).length() <= self.sliver
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::float::{gamma, relative_eq, relative_eq_vector, ulps_eq, MACHINE_EPSILON};
+    use crate::{Real, Vector3, EPSILON};
+
+    #[test]
+    /// Tests that neighboring values are one ulp apart at every magnitude, across zero
+    /// and up to infinity, and that NaN never compares equal.
+    fn test_ulps_eq() {
+        for &value in [1.0e-30, 1.0, 3.5e12, Real::MAX / 4.0].iter() {
+            let next = Real::from_bits(value.to_bits() + 1);
+            let after = Real::from_bits(value.to_bits() + 2);
+            assert!(ulps_eq(value, next, 1) && ulps_eq(-next, -value, 1));
+            assert!(!ulps_eq(value, after, 1) && ulps_eq(value, after, 2));
+        }
+        let smallest = Real::from_bits(1);
+        assert!(ulps_eq(smallest, -smallest, 2) && !ulps_eq(smallest, -smallest, 1));
+        assert!(ulps_eq(Real::MAX, Real::INFINITY, 1));
+        assert!(!ulps_eq(Real::NAN, Real::NAN, u32::MAX));
+
+        // An absolute tolerance ignores tiny values and rejects neighbors of huge ones.
+        assert!((1.0e-7 - 2.0e-7 as Real).abs() < EPSILON && !ulps_eq(1.0e-7, 2.0e-7, 4));
+        let huge: Real = 1.0e20;
+        let next = Real::from_bits(huge.to_bits() + 1);
+        assert!((next - huge).abs() > EPSILON && ulps_eq(huge, next, 1));
+    }
+
+    #[test]
+    /// Tests that relative comparisons scale with the magnitude of the values.
+    fn test_relative_eq() {
+        for &scale in [1.0e-20, 1.0, 1.0e20].iter() {
+            let value: Real = 3.0 * scale;
+            assert!(relative_eq(value, value * (1.0 + 1.0e-6), 1.0e-5));
+            assert!(!relative_eq(value, value * (1.0 + 1.0e-4), 1.0e-5));
+            assert!(!relative_eq(value, -value, 1.0e-5));
+
+            let a = Vector3::new(value, 0.0, -value);
+            let b = Vector3::new(value, value * 1.0e-6, -value);
+            assert!(relative_eq_vector(a, b, 1.0e-5));
+            assert!(!relative_eq_vector(a, -b, 1.0e-5));
+        }
+        assert!(relative_eq(Real::INFINITY, Real::INFINITY, 0.0));
+        assert!(!relative_eq(Real::INFINITY, Real::MAX, 1.0));
+        assert!(!relative_eq(Real::NAN, Real::NAN, 1.0));
+    }
+
+    #[test]
+    /// Tests that `gamma` bounds the error of long sums, and grows with the number of
+    /// operations.
+    fn test_gamma() {
+        assert!(gamma(1) > MACHINE_EPSILON && gamma(1) < 2.0 * MACHINE_EPSILON);
+        assert!(gamma(2) > gamma(1));
+
+        // Summing the values one after another rounds `n - 1` times, while the error of the
+        // compensated sum is at most `2ε` times the magnitude.
+        let values: Vec<Real> = (1..=1000).map(|i| 1.0 / i as Real).collect();
+        let sum = values.iter().fold(0.0, |sum: Real, value| sum + value);
+        let (mut compensated, mut compensation): (Real, Real) = (0.0, 0.0);
+        for value in values.iter() {
+            let corrected = value - compensation;
+            let next = compensated + corrected;
+            compensation = (next - compensated) - corrected;
+            compensated = next;
+        }
+        assert!((sum - compensated).abs() <= gamma(1001) * sum);
+        assert!((sum - compensated).abs() > 0.0);
+    }
+}
@@ -0,0 +1,492 @@
+//! This module defines the [`SupportMap`] trait for convex shapes and the
+//! Gilbert-Johnson-Keerthi (GJK) algorithms, which test any two of them for overlap and
+//! intersect them with rays using nothing but their support functions.
+//!
+//! [`SupportMap`]: trait.SupportMap.html
+//!
+
+use crate::ray::{Intersection, Ray};
+use crate::{Point3, Real, Vector3, EPSILON};
+
+/// The maximal number of iterations of the GJK algorithms. They converge in a few
+/// iterations for polytopes, and to within [`EPSILON`] for smooth shapes.
+///
+/// [`EPSILON`]: ../constant.EPSILON.html
+///
+const MAX_ITERATIONS: usize = 64;
+
+/// A convex shape which is described by its support function.
+pub trait SupportMap {
+    /// Returns a point of the shape which lies farthest in `direction`, i.e. a point `p`
+    /// which maximizes `p.dot(direction)`. The direction does not need to be normalized and
+    /// may be zero, in which case any point of the shape may be returned.
+    fn support(&self, direction: Vector3) -> Point3;
+}
+
+/// A simplex of up to four points in the Minkowski difference of two shapes.
+struct Simplex {
+    points: [Point3; 4],
+    len: usize,
+}
+
+impl Simplex {
+    fn new() -> Simplex {
+        Simplex {
+            points: [Point3::ZERO; 4],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, point: Point3) {
+        self.points[self.len] = point;
+        self.len += 1;
+    }
+
+    /// Returns the point of the simplex closest to the origin, and reduces the simplex to
+    /// the smallest face containing it.
+    fn reduce(&mut self) -> Point3 {
+        let (closest, used) = closest_to_origin(&self.points[..self.len]);
+        let mut len = 0;
+        for (i, &is_used) in used.iter().enumerate().take(self.len) {
+            if is_used {
+                self.points[len] = self.points[i];
+                len += 1;
+            }
+        }
+        self.len = len;
+        closest
+    }
+
+    /// Returns the largest squared distance of a point of the simplex from the origin.
+    fn max_length_squared(&self) -> Real {
+        self.points[..self.len]
+            .iter()
+            .map(|point| point.length_squared())
+            .fold(0.0, Real::max)
+    }
+}
+
+/// Returns the point of the convex hull of `points` closest to the origin and which of the
+/// points are needed to describe it.
+fn closest_to_origin(points: &[Point3]) -> (Point3, [bool; 4]) {
+    match *points {
+        [a] => (a, [true, false, false, false]),
+        [a, b] => closest_on_segment(a, b),
+        [a, b, c] => closest_on_triangle(a, b, c),
+        [a, b, c, d] => closest_on_tetrahedron(a, b, c, d),
+        _ => unreachable!(),
+    }
+}
+
+fn closest_on_segment(a: Point3, b: Point3) -> (Point3, [bool; 4]) {
+    let edge = b - a;
+    let length_squared = edge.length_squared();
+    let t = if length_squared > 0.0 {
+        -a.dot(edge) / length_squared
+    } else {
+        0.0
+    };
+    if t <= 0.0 {
+        (a, [true, false, false, false])
+    } else if t >= 1.0 {
+        (b, [false, true, false, false])
+    } else {
+        (a + edge * t, [true, true, false, false])
+    }
+}
+
+/// Finds the closest point to the origin by the Voronoi regions of the triangle, following
+/// Ericson, Real-Time Collision Detection, 5.1.5.
+pub(crate) fn closest_on_triangle(a: Point3, b: Point3, c: Point3) -> (Point3, [bool; 4]) {
+    let ab = b - a;
+    let ac = c - a;
+    let d1 = -ab.dot(a);
+    let d2 = -ac.dot(a);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return (a, [true, false, false, false]);
+    }
+    let d3 = -ab.dot(b);
+    let d4 = -ac.dot(b);
+    if d3 >= 0.0 && d4 <= d3 {
+        return (b, [false, true, false, false]);
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return (a + ab * (d1 / (d1 - d3)), [true, true, false, false]);
+    }
+    let d5 = -ab.dot(c);
+    let d6 = -ac.dot(c);
+    if d6 >= 0.0 && d5 <= d6 {
+        return (c, [false, false, true, false]);
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return (a + ac * (d2 / (d2 - d6)), [true, false, true, false]);
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0 {
+        let t = (d4 - d3) / ((d4 - d3) + (d5 - d6));
+        return (b + (c - b) * t, [false, true, true, false]);
+    }
+    let sum = va + vb + vc;
+    if sum <= 0.0 {
+        // A degenerate triangle, whose closest point lies on one of its edges.
+        let points = [a, b, c];
+        return [(0, 1), (1, 2), (0, 2)]
+            .iter()
+            .map(|&(i, j)| {
+                let (point, segment_used) = closest_on_segment(points[i], points[j]);
+                let mut used = [false; 4];
+                used[i] = segment_used[0];
+                used[j] = segment_used[1];
+                (point, used)
+            })
+            .min_by(|x, y| {
+                x.0.length_squared()
+                    .partial_cmp(&y.0.length_squared())
+                    .unwrap()
+            })
+            .unwrap();
+    }
+    (
+        a + ab * (vb / sum) + ac * (vc / sum),
+        [true, true, true, false],
+    )
+}
+
+fn closest_on_tetrahedron(a: Point3, b: Point3, c: Point3, d: Point3) -> (Point3, [bool; 4]) {
+    // The faces with the vertex opposite to them.
+    let faces = [
+        ([a, b, c], d, [0, 1, 2]),
+        ([a, c, d], b, [0, 2, 3]),
+        ([a, d, b], c, [0, 3, 1]),
+        ([b, d, c], a, [1, 3, 2]),
+    ];
+    let mut nearest: Option<(Point3, [bool; 4])> = None;
+    for (face, opposite, indices) in faces.iter() {
+        let normal = (face[1] - face[0]).cross(face[2] - face[0]);
+        let origin_side = -normal.dot(face[0]);
+        let opposite_side = normal.dot(*opposite - face[0]);
+        // Only faces which do not have the origin on the side of the opposite vertex can
+        // be closest. All faces of a flat tetrahedron are candidates.
+        if origin_side * opposite_side <= 0.0 {
+            let (point, face_used) = closest_on_triangle(face[0], face[1], face[2]);
+            if nearest.is_none_or(|(nearest, _)| point.length_squared() < nearest.length_squared())
+            {
+                let mut used = [false; 4];
+                for (index, &is_used) in indices.iter().zip(face_used.iter()) {
+                    used[*index] = is_used;
+                }
+                nearest = Some((point, used));
+            }
+        }
+    }
+    // The origin lies inside of the tetrahedron if it is on the inner side of all faces.
+    nearest.unwrap_or((Point3::ZERO, [true; 4]))
+}
+
+/// Returns the support point of the Minkowski difference `a - b`.
+fn support_difference<A, B>(a: &A, b: &B, direction: Vector3) -> Point3
+where
+    A: SupportMap + ?Sized,
+    B: SupportMap + ?Sized,
+{
+    a.support(direction) - b.support(-direction)
+}
+
+/// Returns true if the convex shapes `a` and `b` overlap, including shapes which touch
+/// within a tolerance of [`EPSILON`] relative to their size.
+///
+/// # Examples
+/// ```
+/// use bvh::capsule::Capsule;
+/// use bvh::gjk::intersects;
+/// use bvh::obb::OBB;
+/// use bvh::{Point3, Quat, Vector3};
+///
+/// let obb = OBB {
+///     orientation: Quat::from_rotation_z(std::f32::consts::FRAC_PI_4),
+///     extents: Vector3::new(1.0, 1.0, 1.0),
+///     center: Vector3::new(0.0, 0.0, 0.0),
+/// };
+/// // The rotated box reaches sqrt(2) along the x axis.
+/// let capsule = |x: f32| Capsule::new(Point3::new(x, 0.0, -2.0), Point3::new(x, 0.0, 2.0), 0.5);
+/// assert!(intersects(&obb, &capsule(1.8)));
+/// assert!(!intersects(&obb, &capsule(2.0)));
+/// ```
+///
+/// [`EPSILON`]: ../constant.EPSILON.html
+///
+pub fn intersects<A, B>(a: &A, b: &B) -> bool
+where
+    A: SupportMap + ?Sized,
+    B: SupportMap + ?Sized,
+{
+    // `v` approaches the point of the Minkowski difference closest to the origin, the
+    // shapes overlap if it contains the origin.
+    let mut v = support_difference(a, b, Vector3::X);
+    let mut simplex = Simplex::new();
+    for _ in 0..MAX_ITERATIONS {
+        let w = support_difference(a, b, -v);
+        // The plane through `w` perpendicular to `v` separates the origin from the shapes.
+        if v.dot(w) > 0.0 {
+            return false;
+        }
+        simplex.push(w);
+        v = simplex.reduce();
+        let tolerance = EPSILON * EPSILON * simplex.max_length_squared();
+        if simplex.len == 4 || v.length_squared() <= tolerance {
+            return true;
+        }
+    }
+    true
+}
+
+/// Casts `ray` against the convex `shape` with the GJK ray cast of van den Bergen and returns
+/// the distance along the ray at which it first touches the shape at or after `t_min`,
+/// together with the unnormalized outward normal there. The normal is zero if the ray
+/// starts within the shape.
+fn cast_ray<S: SupportMap + ?Sized>(
+    shape: &S,
+    origin: Point3,
+    direction: Vector3,
+) -> Option<(Real, Vector3)> {
+    let mut distance = 0.0;
+    let mut position = origin;
+    let mut normal = Vector3::ZERO;
+    let mut v = position - shape.support(Vector3::X);
+    // The support points of the shape, whose differences to `position` span the simplex.
+    let mut supports = [Point3::ZERO; 4];
+    let mut len = 0;
+    for _ in 0..MAX_ITERATIONS {
+        let support = shape.support(v);
+        let w = position - support;
+        let v_dot_w = v.dot(w);
+        if v_dot_w > 0.0 {
+            let v_dot_direction = v.dot(direction);
+            if v_dot_direction >= 0.0 {
+                return None;
+            }
+            distance -= v_dot_w / v_dot_direction;
+            position = origin + direction * distance;
+            normal = v;
+        }
+
+        let mut simplex = Simplex::new();
+        for &point in supports[..len].iter().chain(std::iter::once(&support)) {
+            simplex.push(position - point);
+        }
+        v = simplex.reduce();
+        // Keep the support points which span the reduced simplex.
+        for (kept, point) in supports
+            .iter_mut()
+            .zip(simplex.points[..simplex.len].iter())
+        {
+            *kept = position - *point;
+        }
+        len = simplex.len;
+
+        let tolerance = EPSILON * EPSILON * simplex.max_length_squared();
+        if len == 4 || v.length_squared() <= tolerance {
+            return Some((distance, normal));
+        }
+    }
+    Some((distance, normal))
+}
+
+/// Intersects `ray` with the convex `shape`, as an implementation of [`IntersectionRay`] for
+/// shapes with a support function. If the ray starts within the shape, the point where it
+/// leaves the shape is returned as a back face. The `u` and `v` coordinates of the
+/// [`Intersection`] are zero.
+///
+/// # Examples
+/// ```
+/// use bvh::capsule::Capsule;
+/// use bvh::gjk::intersects_ray;
+/// use bvh::ray::Ray;
+/// use bvh::{Point3, Vector3};
+///
+/// let capsule = Capsule::new(Point3::new(0.0, -2.0, 0.0), Point3::new(0.0, 2.0, 0.0), 1.0);
+/// let ray = Ray::new(Point3::new(0.0, 10.0, 0.0), Vector3::new(0.0, -1.0, 0.0));
+/// let hit = intersects_ray(&capsule, &ray, 0.0, f32::INFINITY).unwrap();
+/// assert!((hit.distance - 7.0).abs() < 0.001);
+/// assert!((hit.norm - Vector3::new(0.0, 1.0, 0.0)).length() < 0.001);
+/// ```
+///
+/// [`Intersection`]: ../ray/struct.Intersection.html
+/// [`IntersectionRay`]: ../ray/trait.IntersectionRay.html
+///
+pub fn intersects_ray<S: SupportMap + ?Sized>(
+    shape: &S,
+    ray: &Ray,
+    t_min: Real,
+    t_max: Real,
+) -> Option<Intersection> {
+    let start = if t_min.is_finite() { t_min } else { 0.0 };
+    let (distance, normal) = cast_ray(shape, ray.at(start), ray.direction)?;
+    let (toi, out_norm) = if normal != Vector3::ZERO {
+        (start + distance, normal)
+    } else {
+        // The ray starts inside, so find where it leaves the shape by casting a ray back
+        // from beyond the farthest point of the shape along the ray.
+        let beyond = (shape.support(ray.direction) - ray.origin).dot(ray.direction) + 1.0;
+        let (distance, normal) = cast_ray(shape, ray.at(beyond), -ray.direction)?;
+        (beyond - distance, normal)
+    };
+    if toi < t_min || toi > t_max {
+        return None;
+    }
+    let (norm, back_face) = ray.face_normal(out_norm.normalize_or_zero());
+    Some(Intersection::new(toi, 0.0, 0.0, norm, back_face))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::AABB;
+    use crate::bounding_hierarchy::IntersectionShape;
+    use crate::capsule::Capsule;
+    use crate::convex_hull::ConvexHull;
+    use crate::gjk::{intersects, intersects_ray, SupportMap};
+    use crate::obb::OBB;
+    use crate::ray::IntersectionRay;
+    use crate::sphere::Sphere;
+    use crate::testbase::{create_ray, next_point3};
+    use crate::transformed::Transformed;
+    use crate::{Mat4, Point3, Quat, Real, Vector3};
+
+    /// Asserts that `intersects` agrees with `exact` for the shapes created by `create` with
+    /// a scale of 1, unless the shapes touch, so that scaling them slightly changes the result.
+    fn assert_agrees<A, B>(create: impl Fn(Real) -> (A, B), exact: impl Fn(&A, &B) -> bool) -> bool
+    where
+        A: SupportMap,
+        B: SupportMap,
+    {
+        let (small_a, small_b) = create(0.999);
+        let (large_a, large_b) = create(1.001);
+        let touching = exact(&small_a, &small_b) != exact(&large_a, &large_b);
+        let (a, b) = create(1.0);
+        if !touching {
+            assert_eq!(intersects(&a, &b), exact(&a, &b));
+            assert_eq!(intersects(&b, &a), exact(&a, &b));
+        }
+        exact(&a, &b)
+    }
+
+    #[test]
+    /// Tests the overlap of random pairs of shapes against their exact tests.
+    fn test_gjk_intersects() {
+        let bounds = AABB::with_bounds(Point3::splat(-3.0), Point3::splat(3.0));
+        let unit = AABB::with_bounds(Point3::splat(0.2), Point3::splat(1.5));
+        let mut seed = 0;
+        let mut overlaps = 0;
+        for _ in 0..500 {
+            let points: Vec<Point3> = (0..6).map(|_| next_point3(&mut seed, &bounds)).collect();
+            let sizes: Vec<Vector3> = (0..4).map(|_| next_point3(&mut seed, &unit)).collect();
+            let orientation = Quat::from_axis_angle(points[5].normalize(), sizes[3].x * 3.0);
+            let sphere = |scale: Real| Sphere::new(points[0], sizes[0].x * scale);
+            let capsule = |scale: Real| Capsule::new(points[1], points[2], sizes[0].y * scale);
+            let aabb = |scale: Real| {
+                AABB::with_bounds(points[3] - sizes[1] * scale, points[3] + sizes[1] * scale)
+            };
+            let obb = |scale: Real| OBB {
+                orientation,
+                extents: sizes[2] * scale,
+                center: points[4],
+            };
+
+            let other_obb = |scale: Real| OBB {
+                orientation: Quat::from_axis_angle(points[0].normalize(), sizes[3].y * 3.0),
+                extents: sizes[3] * scale,
+                center: points[2],
+            };
+
+            let exact_overlaps = [
+                assert_agrees(
+                    |s| (sphere(s), Sphere::new(points[4], s)),
+                    |a, b| a.intersects_shape(b),
+                ),
+                assert_agrees(|s| (sphere(s), aabb(s)), |a, b| a.intersects_shape(b)),
+                assert_agrees(|s| (sphere(s), obb(s)), |a, b| a.intersects_shape(b)),
+                assert_agrees(|s| (sphere(s), capsule(s)), |a, b| a.intersects_shape(b)),
+                assert_agrees(|s| (capsule(s), aabb(s)), |a, b| a.intersects_shape(b)),
+                assert_agrees(|s| (capsule(s), obb(s)), |a, b| a.intersects_shape(b)),
+                assert_agrees(|s| (aabb(s), obb(s)), |a, b| a.intersects_shape(b)),
+                assert_agrees(|s| (obb(s), other_obb(s)), |a, b| a.intersects_shape(b)),
+            ];
+            overlaps += exact_overlaps.iter().filter(|&&overlap| overlap).count();
+        }
+        // Both overlapping and separated pairs are tested.
+        assert!(overlaps > 0 && overlaps < 500 * 8);
+    }
+
+    #[test]
+    /// Tests the GJK ray cast against the exact intersections of spheres, ellipsoids and
+    /// convex hulls, from outside and inside of them.
+    fn test_gjk_intersects_ray() {
+        let sphere = Sphere::new(Point3::new(1.0, -2.0, 0.5), 3.0);
+        let transform = Mat4::from_scale_rotation_translation(
+            Vector3::new(1.0, 3.0, 0.5),
+            Quat::from_rotation_x(0.7),
+            Vector3::new(-1.0, 0.0, 2.0),
+        );
+        let ellipsoid = Transformed::new(Sphere::new(Point3::ZERO, 2.0), transform);
+        let corners: Vec<Point3> = (0..8)
+            .map(|i| {
+                let corner =
+                    Vector3::new((i & 1) as Real, ((i >> 1) & 1) as Real, (i >> 2) as Real);
+                transform.transform_point3(corner * 4.0 - Vector3::splat(2.0))
+            })
+            .collect();
+        let hull = ConvexHull::new(&corners).unwrap();
+
+        let bounds = AABB::with_bounds(Point3::splat(-8.0), Point3::splat(8.0));
+        let mut seed = 0;
+        let mut hits = 0;
+        for _ in 0..1000 {
+            let ray = create_ray(&mut seed, &bounds);
+            let pairs = [
+                (
+                    intersects_ray(&sphere, &ray, 0.0, Real::INFINITY),
+                    sphere.intersects_ray(&ray, 0.0, Real::INFINITY),
+                ),
+                (
+                    intersects_ray(&ellipsoid, &ray, 0.0, Real::INFINITY),
+                    ellipsoid.intersects_ray(&ray, 0.0, Real::INFINITY),
+                ),
+                (
+                    intersects_ray(&hull, &ray, 0.0, Real::INFINITY),
+                    hull.intersects_ray(&ray, 0.0, Real::INFINITY),
+                ),
+            ];
+            for (index, (actual, expected)) in pairs.iter().enumerate() {
+                match (actual, expected) {
+                    (Some(actual), Some(expected)) => {
+                        assert!((actual.distance - expected.distance).abs() < 1e-2);
+                        assert_eq!(actual.back_face, expected.back_face);
+                        // The normals of the hull are ambiguous at its edges.
+                        if index < 2 {
+                            assert!((actual.norm - expected.norm).length() < 1e-2);
+                        }
+                        hits += 1;
+                    }
+                    (None, None) => {}
+                    // Rays grazing the shapes may be decided either way.
+                    (Some(hit), None) | (None, Some(hit)) => {
+                        assert!(hit.norm.dot(ray.direction).abs() < 0.1);
+                    }
+                }
+            }
+        }
+        assert!(hits > 0);
+
+        // Respect the interval of the ray.
+        let ray = crate::ray::Ray::new(Point3::new(1.0, -2.0, -10.0), Vector3::Z);
+        let near = intersects_ray(&sphere, &ray, 0.0, Real::INFINITY).unwrap();
+        assert!((near.distance - 7.5).abs() < 1e-3);
+        let far = intersects_ray(&sphere, &ray, 8.0, Real::INFINITY).unwrap();
+        assert!((far.distance - 13.5).abs() < 1e-3);
+        assert!(far.back_face);
+        assert!(intersects_ray(&sphere, &ray, 0.0, 7.0).is_none());
+        assert!(intersects_ray(&sphere, &ray, 14.0, Real::INFINITY).is_none());
+    }
+}
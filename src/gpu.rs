@@ -0,0 +1,378 @@
+//! A reference implementation of a closest hit traversal with [`wgpu`] compute shaders.
+//!
+//! A [`BVH`] is uploaded as [`GpuSkipNode`]s together with the [`AABB`]s of its shapes.
+//! The built-in kernel uses the code of [`traversal_shader`] and reports the closest shape
+//! [`AABB`] hit by every ray, like [`BoundingHierarchy::traverse_nearest`] with shapes which
+//! are their own bounding boxes. This module is only available with the `wgpu` feature.
+//!
+//! [`AABB`]: ../aabb/struct.AABB.html
+//! [`BoundingHierarchy::traverse_nearest`]: ../bounding_hierarchy/trait.BoundingHierarchy.html#method.traverse_nearest
+//! [`BVH`]: ../bvh/struct.BVH.html
+//! [`GpuSkipNode`]: ../flat_bvh/struct.GpuSkipNode.html
+//! [`traversal_shader`]: ../shader/fn.traversal_shader.html
+//! [`wgpu`]: https://docs.rs/wgpu
+//!
+
+use crate::bounding_hierarchy::BHShape;
+use crate::bvh::BVH;
+use crate::flat_bvh::GpuSkipNode;
+use crate::ray::Ray;
+use crate::shader::{traversal_shader, ShaderLanguage, ShaderNodeLayout};
+use std::mem;
+use std::sync::mpsc;
+use wgpu::util::DeviceExt;
+
+/// The number of invocations per workgroup of the traversal kernel.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// The maximum number of workgroups per dimension of a dispatch.
+const MAX_WORKGROUPS: u32 = 65535;
+
+/// The kernel which is appended to the generated traversal code. The shape [`AABB`]s are
+/// stored as two `vec3<f32>` with a stride of 16 bytes.
+///
+/// [`AABB`]: ../aabb/struct.AABB.html
+///
+const KERNEL: &str = "
+struct BvhShapeAabb {
+    aabb_min: vec3<f32>,
+    aabb_max: vec3<f32>,
+}
+
+struct BvhRay {
+    origin: vec3<f32>,
+    t_min: f32,
+    direction: vec3<f32>,
+    t_max: f32,
+}
+
+struct BvhHit {
+    shape_index: u32,
+    distance: f32,
+}
+
+@group(0) @binding(1) var<storage, read> bvh_shape_aabbs: array<BvhShapeAabb>;
+@group(0) @binding(2) var<storage, read> bvh_rays: array<BvhRay>;
+@group(0) @binding(3) var<storage, read_write> bvh_hits: array<BvhHit>;
+
+var<private> bvh_t_min: f32;
+
+fn bvh_intersect_shape(shape_index: u32, origin: vec3<f32>, direction: vec3<f32>, t_max: f32) -> f32 {
+    let aabb = bvh_shape_aabbs[shape_index];
+    let t0 = (aabb.aabb_min - origin) / direction;
+    let t1 = (aabb.aabb_max - origin) / direction;
+    let t_near = min(t0, t1);
+    let t_far = max(t0, t1);
+    let t_enter = max(max(t_near.x, t_near.y), max(t_near.z, bvh_t_min));
+    let t_exit = min(min(t_far.x, t_far.y), min(t_far.z, t_max));
+    if (t_enter <= t_exit) {
+        return t_enter;
+    }
+    return t_max;
+}
+
+@compute @workgroup_size(64)
+fn bvh_main(@builtin(global_invocation_id) id: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>) {
+    let ray_index = id.x + id.y * groups.x * 64u;
+    if (ray_index >= arrayLength(&bvh_rays)) {
+        return;
+    }
+    let ray = bvh_rays[ray_index];
+    bvh_t_min = ray.t_min;
+    var t_max = ray.t_max;
+    let shape_index = bvh_traverse(ray.origin, ray.direction, &t_max);
+    bvh_hits[ray_index] = BvhHit(shape_index, t_max);
+}
+";
+
+/// A ray as it is uploaded to the GPU. It corresponds to the following WGSL structure:
+///
+/// ```wgsl
+/// struct BvhRay {
+///     origin: vec3<f32>,
+///     t_min: f32,
+///     direction: vec3<f32>,
+///     t_max: f32,
+/// }
+/// ```
+///
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy, PartialEq, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuRay {
+    /// The ray origin.
+    pub origin: [f32; 3],
+
+    /// The minimum distance of a hit.
+    pub t_min: f32,
+
+    /// The ray direction.
+    pub direction: [f32; 3],
+
+    /// The maximum distance of a hit.
+    pub t_max: f32,
+}
+
+impl GpuRay {
+    /// Creates a new GPU ray from a [`Ray`], which reports hits between `t_min` and `t_max`.
+    ///
+    /// [`Ray`]: ../ray/struct.Ray.html
+    ///
+    #[allow(clippy::unnecessary_cast)]
+    pub fn new(ray: &Ray, t_min: f32, t_max: f32) -> GpuRay {
+        GpuRay {
+            origin: [
+                ray.origin.x as f32,
+                ray.origin.y as f32,
+                ray.origin.z as f32,
+            ],
+            t_min,
+            direction: [
+                ray.direction.x as f32,
+                ray.direction.y as f32,
+                ray.direction.z as f32,
+            ],
+            t_max,
+        }
+    }
+}
+
+/// The closest hit of a [`GpuRay`], as it is read back from the GPU.
+///
+/// [`GpuRay`]: struct.GpuRay.html
+///
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuHit {
+    /// The index of the hit shape, or [`u32::MAX`] if the ray did not hit anything.
+    ///
+    /// [`u32::MAX`]: https://doc.rust-lang.org/std/u32/constant.MAX.html
+    ///
+    pub shape_index: u32,
+
+    /// The distance to the hit, or the `t_max` of the ray if it did not hit anything.
+    pub distance: f32,
+}
+
+impl GpuHit {
+    /// Returns `true` if the ray hit a shape.
+    pub fn is_hit(&self) -> bool {
+        self.shape_index != u32::MAX
+    }
+}
+
+/// A [`BVH`] and the [`AABB`]s of its shapes in GPU buffers.
+///
+/// [`AABB`]: ../aabb/struct.AABB.html
+/// [`BVH`]: ../bvh/struct.BVH.html
+///
+#[derive(Debug)]
+pub struct GpuBVH {
+    /// The buffer of [`GpuSkipNode`]s.
+    ///
+    /// [`GpuSkipNode`]: ../flat_bvh/struct.GpuSkipNode.html
+    ///
+    pub nodes: wgpu::Buffer,
+
+    /// The buffer of shape [`AABB`]s.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub shape_aabbs: wgpu::Buffer,
+}
+
+impl GpuBVH {
+    /// Flattens `bvh` and uploads it together with the [`AABB`]s of `shapes`.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    #[allow(clippy::unnecessary_cast)]
+    pub fn new<Shape: BHShape>(device: &wgpu::Device, bvh: &BVH, shapes: &[Shape]) -> GpuBVH {
+        // Empty storage buffers can not be bound, so an empty `BVH` is replaced by a single
+        // node which is never hit.
+        let mut nodes = bvh.flatten_skip_gpu(shapes);
+        if nodes.is_empty() {
+            nodes.push(GpuSkipNode {
+                aabb_min: [f32::INFINITY; 3],
+                skip_index: 1,
+                aabb_max: [f32::NEG_INFINITY; 3],
+                shape_index: u32::MAX,
+            });
+        }
+        let mut shape_aabbs: Vec<[f32; 8]> = shapes
+            .iter()
+            .map(|shape| {
+                let aabb = shape.aabb();
+                [
+                    aabb.min.x as f32,
+                    aabb.min.y as f32,
+                    aabb.min.z as f32,
+                    0.0,
+                    aabb.max.x as f32,
+                    aabb.max.y as f32,
+                    aabb.max.z as f32,
+                    0.0,
+                ]
+            })
+            .collect();
+        if shape_aabbs.is_empty() {
+            shape_aabbs.push([0.0; 8]);
+        }
+
+        GpuBVH {
+            nodes: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("bvh_nodes"),
+                contents: bytemuck::cast_slice(&nodes),
+                usage: wgpu::BufferUsages::STORAGE,
+            }),
+            shape_aabbs: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("bvh_shape_aabbs"),
+                contents: bytemuck::cast_slice(&shape_aabbs),
+                usage: wgpu::BufferUsages::STORAGE,
+            }),
+        }
+    }
+}
+
+/// The compute pipeline of the built-in traversal kernel.
+#[derive(Debug)]
+pub struct GpuTraversal {
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl GpuTraversal {
+    /// Compiles the traversal kernel for `device`.
+    pub fn new(device: &wgpu::Device) -> GpuTraversal {
+        let code = GpuTraversal::kernel_source();
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("bvh_traversal"),
+            source: wgpu::ShaderSource::Wgsl(code.into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("bvh_traversal"),
+            layout: None,
+            module: &module,
+            entry_point: "bvh_main",
+        });
+        GpuTraversal { pipeline }
+    }
+
+    /// Returns the WGSL code of the traversal kernel. The nodes are bound to binding 0,
+    /// the shape [`AABB`]s to binding 1, the rays to binding 2 and the hits to binding 3
+    /// of group 0. The entry point is `bvh_main`.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub fn kernel_source() -> String {
+        let mut code = traversal_shader(ShaderLanguage::Wgsl, ShaderNodeLayout::Skip, 0, 0);
+        code.push_str(KERNEL);
+        code
+    }
+
+    /// Traces `rays` against `bvh` and blocks until the hits are read back. The hits are
+    /// returned in the order of the rays.
+    pub fn traverse(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bvh: &GpuBVH,
+        rays: &[GpuRay],
+    ) -> Vec<GpuHit> {
+        if rays.is_empty() {
+            return Vec::new();
+        }
+
+        let ray_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("bvh_rays"),
+            contents: bytemuck::cast_slice(rays),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let hits_size = (mem::size_of::<GpuHit>() * rays.len()) as u64;
+        let hit_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("bvh_hits"),
+            size: hits_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let read_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("bvh_hits_read"),
+            size: hits_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bvh_traversal"),
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: bvh.nodes.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: bvh.shape_aabbs.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: ray_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: hit_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        // Large dispatches are split into rows of the maximum number of workgroups.
+        let workgroups = (rays.len() as u32).div_ceil(WORKGROUP_SIZE);
+        let groups_x = workgroups.min(MAX_WORKGROUPS);
+        let groups_y = workgroups.div_ceil(groups_x);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("bvh_traversal"),
+        });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("bvh_traversal"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(groups_x, groups_y, 1);
+        }
+        encoder.copy_buffer_to_buffer(&hit_buffer, 0, &read_buffer, 0, hits_size);
+        queue.submit(Some(encoder.finish()));
+
+        let slice = read_buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .expect("The hit buffer was never mapped.")
+            .expect("Could not map the hit buffer.");
+        let hits = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        read_buffer.unmap();
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gpu::{GpuHit, GpuRay, GpuTraversal};
+    use std::mem::{align_of, size_of};
+
+    #[test]
+    /// Checks the layout of the buffers and the kernel bindings.
+    fn test_gpu_buffer_layout() {
+        assert_eq!(size_of::<GpuRay>(), 32);
+        assert_eq!(align_of::<GpuRay>(), 16);
+        assert_eq!(size_of::<GpuHit>(), 8);
+
+        let code = GpuTraversal::kernel_source();
+        for binding in 0..4 {
+            assert!(code.contains(&format!("@group(0) @binding({})", binding)));
+        }
+        assert!(code.contains(&format!("@workgroup_size({})", super::WORKGROUP_SIZE)));
+    }
+}
@@ -65,9 +65,38 @@
 //! let hit_sphere_aabbs = bvh.traverse(&ray, &spheres);
 //! ```
 //!
+//! ## Precision
+//!
+//! The types of this crate are not generic over their scalar type. Instead, the crate is built
+//! twice from the same sources: `bvh` uses `f32`, and `bvh-f64` uses `f64` for every
+//! coordinate, through the [`Real`], [`Point3`] and [`Vector3`] aliases. Both packages have
+//! distinct names, so one binary can depend on both, for example to use a fast `f32` hierarchy
+//! for rendering next to an `f64` hierarchy for geodetic data. Their types are distinct, and
+//! convert through their glam points, e.g. with
+//! `bvh::aabb::AABB::with_bounds(aabb.min.as_vec3(), aabb.max.as_vec3())`.
+//!
+//! The `wasm` and `python` features are the exception. Both crates export their bindings
+//! under the same names, like the `Scene` class for JavaScript and the `BVH` class of the
+//! `bvh` Python module, so the symbols clash when both crates are linked into one binary.
+//! Enable each of these features on at most one of the two crates.
+//!
+//! [`Real`]: type.Real.html
+//! [`Point3`]: type.Point3.html
+//! [`Vector3`]: type.Vector3.html
+//!
 //! ## Features
 //!
+//! - `rayon` (default **enabled**) - builds large BVHs in parallel, and adds `BVH::par_visibility_matrix`. Disable it on targets without threads, like `wasm32-unknown-unknown`
+//! - `wasm` (default **disabled**) - adds the `wasm` module, a [wasm-bindgen](https://docs.rs/wasm-bindgen) wrapper for raycasting triangle scenes from JavaScript
+//! - `python` (default **disabled**) - adds the `python` module, [pyo3](https://docs.rs/pyo3) bindings which answer ray, nearest neighbour and region queries on numpy arrays
 //! - `serde_impls` (default **disabled**) - adds `Serialize` and `Deserialize` implementations for some types
+//! - `bytemuck` (default **disabled**) - implements `bytemuck::Pod` for the GPU node types
+//! - `wgpu` (default **disabled**) - adds the `gpu` module, which traverses flattened BVHs with wgpu compute shaders
+//! - `obj` (default **disabled**) - adds the `loader` module, which loads the triangles of OBJ files into a `TriMesh`
+//! - `gltf` (default **disabled**) - adds the `loader` module, which loads the triangles of glTF files into a `TriMesh`
+//! - `mint` (default **disabled**) - converts the math types and shapes from and to [mint](https://docs.rs/mint) types
+//! - `parry` (default **disabled**) - implements the traits of this crate for the ball, cuboid, capsule and triangle mesh shapes of [parry](https://docs.rs/parry3d)
+//! - `rkyv` (default **disabled**) - adds zero-copy [rkyv](https://docs.rs/rkyv) serialization for `AABB`, `BVH`, `BVHNode` and `FlatBVH`
 //!
 
 #![deny(missing_docs)]
@@ -92,6 +121,14 @@ pub type Mat4 = glam::DMat4;
 #[cfg(feature = "f64")]
 pub type Quat = glam::DQuat;
 
+/// 2D point math type used by this crate. Type alias for [`glam::DVec2`].
+#[cfg(feature = "f64")]
+pub type Point2 = glam::DVec2;
+
+/// 2D vector math type used by this crate. Type alias for [`glam::DVec2`].
+#[cfg(feature = "f64")]
+pub type Vector2 = glam::DVec2;
+
 #[cfg(feature = "f64")]
 /// Float type used by this crate
 pub type Real = f64;
@@ -112,12 +149,23 @@ pub type Mat4 = glam::Mat4;
 #[cfg(not(feature = "f64"))]
 pub type Quat = glam::Quat;
 
+/// 2D point math type used by this crate. Type alias for [`glam::Vec2`].
+#[cfg(not(feature = "f64"))]
+pub type Point2 = glam::Vec2;
+
+/// 2D vector math type used by this crate. Type alias for [`glam::Vec2`].
+#[cfg(not(feature = "f64"))]
+pub type Vector2 = glam::Vec2;
+
 #[cfg(not(feature = "f64"))]
 /// Float type used by this crate
 pub type Real = f32;
 
-/// A minimal floating value used as a lower bound.
-/// TODO: replace by/add ULPS/relative float comparison methods.
+/// A minimal floating value used as a lower bound. See the [`float`] module for
+/// comparisons which scale with the compared values.
+///
+/// [`float`]: float/index.html
+///
 pub const EPSILON: Real = 0.00001;
 /// Const for PI
 pub const PI: Real = std::f64::consts::PI as Real;
@@ -125,9 +173,31 @@ pub const PI: Real = std::f64::consts::PI as Real;
 pub mod axis;
 pub mod bounding_hierarchy;
 pub mod bvh;
+pub mod bvh2d;
+pub mod camera;
+pub mod embree;
 pub mod flat_bvh;
+pub mod float;
+pub mod gjk;
+#[cfg(feature = "wgpu")]
+pub mod gpu;
+#[cfg(any(feature = "obj", feature = "gltf"))]
+pub mod loader;
+#[cfg(feature = "mint")]
+mod mint_impls;
+pub mod paged_bvh;
+#[cfg(feature = "parry")]
+mod parry_impls;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod shader;
 mod shapes;
+pub mod split_bvh;
+pub mod two_level;
 mod utils;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod wide_bvh;
 
 #[cfg(test)]
 mod testbase;
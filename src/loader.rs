@@ -0,0 +1,414 @@
+//! This module exports loaders which read the triangles of OBJ and glTF files into a
+//! [`TriMesh`], which can be traversed directly or put into a scene level [`BVH`]. OBJ
+//! files are read with the `obj` feature, and glTF files with the `gltf` feature.
+//!
+//! All objects of a file are merged into one mesh. Polygons of OBJ files are triangulated,
+//! and the meshes of glTF files are placed with the transforms of their nodes. Materials,
+//! textures, normals and other attributes are ignored.
+//!
+//! [`TriMesh`]: ../tri_mesh/struct.TriMesh.html
+//! [`BVH`]: ../bvh/struct.BVH.html
+//!
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use crate::tri_mesh::TriMesh;
+#[cfg(feature = "gltf")]
+use crate::Mat4;
+use crate::{Point3, Real};
+
+/// The reasons why a mesh can not be loaded.
+#[derive(Debug)]
+pub enum LoadError {
+    /// The file could not be read.
+    Io(io::Error),
+    /// The OBJ file could not be parsed.
+    #[cfg(feature = "obj")]
+    Obj(tobj::LoadError),
+    /// The glTF file or one of its buffers could not be parsed.
+    #[cfg(feature = "gltf")]
+    Gltf(gltf::Error),
+    /// The file has a triangle whose vertex index is out of range.
+    InvalidIndex,
+    /// The extension of the file is not one of the formats enabled by the features.
+    UnknownFormat,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(error) => write!(f, "could not read the file: {}", error),
+            #[cfg(feature = "obj")]
+            LoadError::Obj(error) => write!(f, "invalid OBJ file: {}", error),
+            #[cfg(feature = "gltf")]
+            LoadError::Gltf(error) => write!(f, "invalid glTF file: {}", error),
+            LoadError::InvalidIndex => write!(f, "a vertex index is out of range"),
+            LoadError::UnknownFormat => write!(f, "unknown mesh file format"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io(error) => Some(error),
+            #[cfg(feature = "obj")]
+            LoadError::Obj(error) => Some(error),
+            #[cfg(feature = "gltf")]
+            LoadError::Gltf(error) => Some(error),
+            LoadError::InvalidIndex | LoadError::UnknownFormat => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(error: io::Error) -> LoadError {
+        LoadError::Io(error)
+    }
+}
+
+#[cfg(feature = "obj")]
+impl From<tobj::LoadError> for LoadError {
+    fn from(error: tobj::LoadError) -> LoadError {
+        LoadError::Obj(error)
+    }
+}
+
+#[cfg(feature = "gltf")]
+impl From<gltf::Error> for LoadError {
+    fn from(error: gltf::Error) -> LoadError {
+        LoadError::Gltf(error)
+    }
+}
+
+/// Collects the vertices and triangles of the parts of a file.
+#[derive(Default)]
+struct MeshBuilder {
+    vertices: Vec<Point3>,
+    indices: Vec<[u32; 3]>,
+}
+
+impl MeshBuilder {
+    /// Appends a part, whose `indices` refer to its own `vertices`.
+    fn append(
+        &mut self,
+        vertices: impl IntoIterator<Item = Point3>,
+        indices: impl IntoIterator<Item = [u32; 3]>,
+    ) -> Result<(), LoadError> {
+        let offset = self.vertices.len() as u32;
+        self.vertices.extend(vertices);
+        let count = self.vertices.len() as u32 - offset;
+        for triangle in indices {
+            if triangle.iter().any(|&index| index >= count) {
+                return Err(LoadError::InvalidIndex);
+            }
+            self.indices.push(triangle.map(|index| index + offset));
+        }
+        Ok(())
+    }
+
+    fn build(self) -> TriMesh {
+        TriMesh::new(self.vertices, self.indices)
+    }
+}
+
+/// Loads the mesh of an OBJ, glTF or binary glTF file, depending on whether the extension
+/// of the `path` is `obj`, `gltf` or `glb`.
+///
+/// [`LoadError::UnknownFormat`] is returned for other extensions, and for the formats whose
+/// feature is disabled.
+///
+/// [`LoadError::UnknownFormat`]: enum.LoadError.html#variant.UnknownFormat
+///
+pub fn load(path: impl AsRef<Path>) -> Result<TriMesh, LoadError> {
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+    match extension.as_deref() {
+        #[cfg(feature = "obj")]
+        Some("obj") => load_obj(path),
+        #[cfg(feature = "gltf")]
+        Some("gltf") | Some("glb") => load_gltf(path),
+        _ => Err(LoadError::UnknownFormat),
+    }
+}
+
+/// Loads the mesh of an OBJ file. Materials are not loaded, so a missing material library
+/// is not an error.
+#[cfg(feature = "obj")]
+pub fn load_obj(path: impl AsRef<Path>) -> Result<TriMesh, LoadError> {
+    let file = std::fs::File::open(path)?;
+    load_obj_from_reader(&mut io::BufReader::new(file))
+}
+
+/// Loads the mesh of an OBJ file from a reader.
+///
+/// # Examples
+/// ```
+/// use bvh::aabb::Bounded;
+/// use bvh::loader::load_obj_from_reader;
+/// use bvh::ray::Ray;
+/// use bvh::{Point3, Vector3};
+///
+/// let obj = "
+///     v 0 0 0
+///     v 1 0 0
+///     v 1 1 0
+///     v 0 1 0
+///     f 1 2 3 4
+/// ";
+/// let quad = load_obj_from_reader(&mut obj.as_bytes()).unwrap();
+/// assert_eq!(quad.len(), 2);
+/// assert_eq!(quad.aabb().max, Point3::new(1.0, 1.0, 0.0));
+///
+/// let ray = Ray::new(Point3::new(0.5, 0.5, 1.0), Vector3::new(0.0, 0.0, -1.0));
+/// assert!(quad.intersects_ray_face(&ray, 0.0, f32::INFINITY).is_some());
+/// ```
+#[cfg(feature = "obj")]
+#[allow(clippy::unnecessary_cast)]
+pub fn load_obj_from_reader(reader: &mut impl io::BufRead) -> Result<TriMesh, LoadError> {
+    let options = tobj::LoadOptions {
+        triangulate: true,
+        ignore_points: true,
+        ignore_lines: true,
+        ..tobj::LoadOptions::default()
+    };
+    let (models, _) =
+        tobj::load_obj_buf(reader, &options, |_| Err(tobj::LoadError::OpenFileFailed))?;
+
+    let mut mesh = MeshBuilder::default();
+    for model in models {
+        let positions = &model.mesh.positions;
+        mesh.append(
+            positions.chunks_exact(3).map(|position| {
+                Point3::new(
+                    position[0] as Real,
+                    position[1] as Real,
+                    position[2] as Real,
+                )
+            }),
+            model
+                .mesh
+                .indices
+                .chunks_exact(3)
+                .map(|triangle| [triangle[0], triangle[1], triangle[2]]),
+        )?;
+    }
+    Ok(mesh.build())
+}
+
+/// Loads the mesh of a glTF or binary glTF file. External buffers are read relative to the
+/// directory of the file.
+#[cfg(feature = "gltf")]
+pub fn load_gltf(path: impl AsRef<Path>) -> Result<TriMesh, LoadError> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path)?;
+    load_gltf_with_base(&bytes, path.parent())
+}
+
+/// Loads the mesh of a glTF or binary glTF file from its bytes. Buffers must be embedded,
+/// either in the binary chunk of a binary glTF file or as base64 data URIs.
+///
+/// The meshes of the nodes of the default scene are loaded, or of the first scene if there
+/// is no default one. Files without scenes have the meshes loaded as they are.
+#[cfg(feature = "gltf")]
+pub fn load_gltf_from_slice(bytes: &[u8]) -> Result<TriMesh, LoadError> {
+    load_gltf_with_base(bytes, None)
+}
+
+#[cfg(feature = "gltf")]
+fn load_gltf_with_base(bytes: &[u8], base: Option<&Path>) -> Result<TriMesh, LoadError> {
+    let gltf::Gltf { document, blob } = gltf::Gltf::from_slice(bytes)?;
+    let buffers = gltf::import_buffers(&document, base, blob)?;
+
+    let mut mesh = MeshBuilder::default();
+    match document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        Some(scene) => {
+            let mut stack: Vec<(gltf::Node, Mat4)> =
+                scene.nodes().map(|node| (node, Mat4::IDENTITY)).collect();
+            while let Some((node, parent)) = stack.pop() {
+                let transform = parent * gltf_matrix(node.transform().matrix());
+                if let Some(node_mesh) = node.mesh() {
+                    append_gltf_mesh(&mut mesh, &node_mesh, &buffers, &transform)?;
+                }
+                stack.extend(node.children().map(|child| (child, transform)));
+            }
+        }
+        None => {
+            for node_mesh in document.meshes() {
+                append_gltf_mesh(&mut mesh, &node_mesh, &buffers, &Mat4::IDENTITY)?;
+            }
+        }
+    }
+    Ok(mesh.build())
+}
+
+#[cfg(feature = "gltf")]
+#[allow(clippy::unnecessary_cast)]
+fn gltf_matrix(matrix: [[f32; 4]; 4]) -> Mat4 {
+    Mat4::from_cols_array_2d(&matrix.map(|column| column.map(|value| value as Real)))
+}
+
+/// Appends the triangles of the primitives of a glTF mesh, placed with `transform`. Strips
+/// and fans are converted to triangles, and points and lines are skipped.
+#[cfg(feature = "gltf")]
+#[allow(clippy::unnecessary_cast)]
+fn append_gltf_mesh(
+    mesh: &mut MeshBuilder,
+    gltf_mesh: &gltf::Mesh,
+    buffers: &[gltf::buffer::Data],
+    transform: &Mat4,
+) -> Result<(), LoadError> {
+    use gltf::mesh::Mode;
+
+    for primitive in gltf_mesh.primitives() {
+        let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data[..]));
+        let positions: Vec<Point3> = match reader.read_positions() {
+            Some(positions) => positions
+                .map(|[x, y, z]| {
+                    transform.transform_point3(Point3::new(x as Real, y as Real, z as Real))
+                })
+                .collect(),
+            None => continue,
+        };
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+        let triangles: Vec<[u32; 3]> = match primitive.mode() {
+            Mode::Triangles => indices
+                .chunks_exact(3)
+                .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+                .collect(),
+            // Every other triangle of a strip is flipped to keep the winding order.
+            Mode::TriangleStrip => indices
+                .windows(3)
+                .enumerate()
+                .map(|(i, strip)| match i % 2 {
+                    0 => [strip[0], strip[1], strip[2]],
+                    _ => [strip[1], strip[0], strip[2]],
+                })
+                .collect(),
+            Mode::TriangleFan => indices
+                .windows(2)
+                .skip(1)
+                .map(|fan| [indices[0], fan[0], fan[1]])
+                .collect(),
+            Mode::Points | Mode::Lines | Mode::LineLoop | Mode::LineStrip => continue,
+        };
+        mesh.append(positions, triangles)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::loader::{load, LoadError};
+    use crate::Point3;
+
+    #[test]
+    /// Tests that files with unknown extensions or which don't exist are rejected.
+    fn test_load_errors() {
+        assert!(matches!(load("mesh.stl"), Err(LoadError::UnknownFormat)));
+        assert!(matches!(load("mesh"), Err(LoadError::UnknownFormat)));
+        #[cfg(feature = "obj")]
+        assert!(matches!(
+            load("/nonexistent/mesh.OBJ"),
+            Err(LoadError::Io(_))
+        ));
+    }
+
+    #[cfg(feature = "obj")]
+    #[test]
+    /// Tests that the objects of an OBJ file are merged, and its polygons triangulated.
+    fn test_load_obj() {
+        use crate::aabb::Bounded;
+        use crate::loader::load_obj_from_reader;
+
+        let obj = "
+            mtllib missing.mtl
+            o quad
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0
+            f 1 2 3 4
+            o triangle
+            v 0 0 2
+            v 1 0 2
+            v 0 1 2
+            f 5 6 7
+            l 5 6
+        ";
+        let mesh = load_obj_from_reader(&mut obj.as_bytes()).unwrap();
+        assert_eq!(mesh.len(), 3);
+        assert_eq!(mesh.vertices().len(), 7);
+        assert_eq!(mesh.indices()[2], [4, 5, 6]);
+        assert_eq!(mesh.triangle(2).a, Point3::new(0.0, 0.0, 2.0));
+        assert_eq!(mesh.aabb().max, Point3::new(1.0, 1.0, 2.0));
+
+        let invalid = "v 0 0 0\nf 1 2 3\n";
+        assert!(matches!(
+            load_obj_from_reader(&mut invalid.as_bytes()),
+            Err(LoadError::Obj(_))
+        ));
+    }
+
+    #[cfg(feature = "gltf")]
+    #[test]
+    /// Tests that the meshes of a glTF file with an embedded buffer are placed with the
+    /// transforms of their nodes and parents.
+    fn test_load_gltf() {
+        use crate::loader::load_gltf_from_slice;
+
+        // A triangle with the corners (0, 0, 0), (1, 0, 0) and (0, 1, 0), which is scaled by
+        // two and then moved up by five.
+        let gltf = r#"{
+            "asset": { "version": "2.0" },
+            "scene": 0,
+            "scenes": [{ "nodes": [0] }],
+            "nodes": [
+                { "children": [1], "translation": [0, 0, 5] },
+                { "mesh": 0, "scale": [2, 2, 2] }
+            ],
+            "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 }, "indices": 1 }] }],
+            "buffers": [{
+                "byteLength": 44,
+                "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAABAAIAAAA="
+            }],
+            "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 36, "byteLength": 6 }
+            ],
+            "accessors": [
+                {
+                    "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                    "min": [0, 0, 0], "max": [1, 1, 0]
+                },
+                { "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }
+            ]
+        }"#;
+        let mesh = load_gltf_from_slice(gltf.as_bytes()).unwrap();
+        assert_eq!(mesh.len(), 1);
+        assert_eq!(
+            mesh.vertices(),
+            &[
+                Point3::new(0.0, 0.0, 5.0),
+                Point3::new(2.0, 0.0, 5.0),
+                Point3::new(0.0, 2.0, 5.0)
+            ]
+        );
+
+        assert!(matches!(
+            load_gltf_from_slice(b"{}"),
+            Err(LoadError::Gltf(_))
+        ));
+    }
+}
@@ -0,0 +1,205 @@
+//! Conversions of the shapes of this crate from and to [mint] types, so that they can be
+//! built from and read by any math library which speaks mint. The glam types behind
+//! [`Point3`], [`Vector3`] and [`Quat`] convert through the `mint` feature of glam.
+//!
+//! [mint]: https://docs.rs/mint
+//! [`Point3`]: ../type.Point3.html
+//! [`Vector3`]: ../type.Vector3.html
+//! [`Quat`]: ../type.Quat.html
+//!
+
+use crate::aabb::AABB;
+use crate::bvh2d::{Ray2, Rect};
+use crate::obb::OBB;
+use crate::plane::Plane;
+use crate::ray::Ray;
+use crate::sphere::Sphere;
+use crate::triangle::Triangle;
+use crate::Real;
+
+type MintPoint2 = mint::Point2<Real>;
+type MintVector2 = mint::Vector2<Real>;
+type MintPoint3 = mint::Point3<Real>;
+type MintVector3 = mint::Vector3<Real>;
+type MintQuat = mint::Quaternion<Real>;
+
+/// Converts the `(min, max)` corners into an [`AABB`].
+impl From<(MintPoint3, MintPoint3)> for AABB {
+    fn from((min, max): (MintPoint3, MintPoint3)) -> AABB {
+        AABB::with_bounds(min.into(), max.into())
+    }
+}
+
+/// Converts an [`AABB`] into its `(min, max)` corners.
+impl From<AABB> for (MintPoint3, MintPoint3) {
+    fn from(aabb: AABB) -> (MintPoint3, MintPoint3) {
+        (aabb.min.into(), aabb.max.into())
+    }
+}
+
+/// Converts the `(origin, direction)` of a ray into a [`Ray`], normalizing the direction.
+impl From<(MintPoint3, MintVector3)> for Ray {
+    fn from((origin, direction): (MintPoint3, MintVector3)) -> Ray {
+        Ray::new(origin.into(), direction.into())
+    }
+}
+
+/// Converts a [`Ray`] into its `(origin, direction)`.
+impl From<Ray> for (MintPoint3, MintVector3) {
+    fn from(ray: Ray) -> (MintPoint3, MintVector3) {
+        (ray.origin.into(), ray.direction.into())
+    }
+}
+
+/// Converts the `(center, extents, orientation)` of a box into an [`OBB`].
+impl From<(MintPoint3, MintVector3, MintQuat)> for OBB {
+    fn from((center, extents, orientation): (MintPoint3, MintVector3, MintQuat)) -> OBB {
+        OBB {
+            orientation: orientation.into(),
+            extents: extents.into(),
+            center: center.into(),
+        }
+    }
+}
+
+/// Converts an [`OBB`] into its `(center, extents, orientation)`.
+impl From<OBB> for (MintPoint3, MintVector3, MintQuat) {
+    fn from(obb: OBB) -> (MintPoint3, MintVector3, MintQuat) {
+        (
+            obb.center.into(),
+            obb.extents.into(),
+            obb.orientation.into(),
+        )
+    }
+}
+
+/// Converts the `(center, radius)` of a sphere into a [`Sphere`].
+impl From<(MintPoint3, Real)> for Sphere {
+    fn from((center, radius): (MintPoint3, Real)) -> Sphere {
+        Sphere::new(center.into(), radius)
+    }
+}
+
+/// Converts a [`Sphere`] into its `(center, radius)`.
+impl From<Sphere> for (MintPoint3, Real) {
+    fn from(sphere: Sphere) -> (MintPoint3, Real) {
+        (sphere.center.into(), sphere.radius)
+    }
+}
+
+/// Converts the corners of a triangle into a [`Triangle`].
+impl From<[MintPoint3; 3]> for Triangle {
+    fn from([a, b, c]: [MintPoint3; 3]) -> Triangle {
+        Triangle::new(a.into(), b.into(), c.into())
+    }
+}
+
+/// Converts a [`Triangle`] into its corners.
+impl From<Triangle> for [MintPoint3; 3] {
+    fn from(triangle: Triangle) -> [MintPoint3; 3] {
+        [triangle.a.into(), triangle.b.into(), triangle.c.into()]
+    }
+}
+
+/// Converts the `(normal, d)` of a plane into a [`Plane`].
+impl From<(MintVector3, Real)> for Plane {
+    fn from((normal, d): (MintVector3, Real)) -> Plane {
+        Plane::new(normal.into(), d)
+    }
+}
+
+/// Converts a [`Plane`] into its `(normal, d)`.
+impl From<Plane> for (MintVector3, Real) {
+    fn from(plane: Plane) -> (MintVector3, Real) {
+        (plane.normal.into(), plane.d)
+    }
+}
+
+/// Converts the `(min, max)` corners into a [`Rect`].
+impl From<(MintPoint2, MintPoint2)> for Rect {
+    fn from((min, max): (MintPoint2, MintPoint2)) -> Rect {
+        Rect::with_bounds(min.into(), max.into())
+    }
+}
+
+/// Converts a [`Rect`] into its `(min, max)` corners.
+impl From<Rect> for (MintPoint2, MintPoint2) {
+    fn from(rect: Rect) -> (MintPoint2, MintPoint2) {
+        (rect.min.into(), rect.max.into())
+    }
+}
+
+/// Converts the `(origin, direction)` of a 2D ray into a [`Ray2`], normalizing the
+/// direction.
+impl From<(MintPoint2, MintVector2)> for Ray2 {
+    fn from((origin, direction): (MintPoint2, MintVector2)) -> Ray2 {
+        Ray2::new(origin.into(), direction.into())
+    }
+}
+
+/// Converts a [`Ray2`] into its `(origin, direction)`.
+impl From<Ray2> for (MintPoint2, MintVector2) {
+    fn from(ray: Ray2) -> (MintPoint2, MintVector2) {
+        (ray.origin.into(), ray.direction.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::AABB;
+    use crate::bvh2d::{Ray2, Rect};
+    use crate::obb::OBB;
+    use crate::plane::Plane;
+    use crate::ray::Ray;
+    use crate::sphere::Sphere;
+    use crate::triangle::Triangle;
+    use crate::{Point2, Point3, Quat, Real, Vector2, Vector3};
+
+    /// Converts `value` into mint types and back.
+    fn round_trip<T: Into<M> + From<M>, M>(value: T) -> T {
+        T::from(value.into())
+    }
+
+    #[test]
+    /// Tests that the shapes are built from mint types, and survive a round trip.
+    fn test_mint_round_trip() {
+        let min = mint::Point3::from([-1.0, 0.0, 1.0]);
+        let max = mint::Point3::from([2.0, 3.0, 4.0]);
+        let aabb = AABB::from((min, max));
+        assert_eq!(aabb.min, Point3::new(-1.0, 0.0, 1.0));
+        assert_eq!(aabb.max, Point3::new(2.0, 3.0, 4.0));
+        assert_eq!(round_trip::<_, (mint::Point3<Real>, _)>(aabb), aabb);
+
+        let direction = mint::Vector3::from([0.0, 2.0, 0.0]);
+        let ray = Ray::from((min, direction));
+        assert_eq!(ray.origin, aabb.min);
+        assert_eq!(ray.direction, Vector3::Y);
+        let (origin, direction): (mint::Point3<Real>, mint::Vector3<Real>) = ray.into();
+        assert_eq!(Ray::from((origin, direction)).direction, ray.direction);
+
+        let obb = OBB {
+            orientation: Quat::from_rotation_z(0.5),
+            extents: Vector3::new(1.0, 2.0, 3.0),
+            center: Vector3::new(4.0, 5.0, 6.0),
+        };
+        assert_eq!(round_trip::<_, (mint::Point3<Real>, _, _)>(obb), obb);
+
+        let sphere = Sphere::new(Point3::new(1.0, 2.0, 3.0), 4.0);
+        assert_eq!(round_trip::<_, (mint::Point3<Real>, _)>(sphere), sphere);
+
+        let triangle = Triangle::from([min, max, mint::Point3::from([0.0, 0.0, 0.0])]);
+        assert_eq!(triangle.b, aabb.max);
+        assert_eq!(round_trip::<_, [mint::Point3<Real>; 3]>(triangle), triangle);
+
+        let plane = Plane::new(Vector3::Z, 2.0);
+        assert_eq!(round_trip::<_, (mint::Vector3<Real>, _)>(plane), plane);
+
+        let rect = Rect::with_bounds(Point2::new(-1.0, 0.0), Point2::new(2.0, 3.0));
+        assert_eq!(round_trip::<_, (mint::Point2<Real>, _)>(rect), rect);
+
+        let ray = Ray2::new(Point2::new(1.0, 1.0), Vector2::new(0.0, -3.0));
+        assert_eq!(ray.direction, Vector2::new(0.0, -1.0));
+        let (origin, direction): (mint::Point2<Real>, mint::Vector2<Real>) = ray.into();
+        assert_eq!(Ray2::from((origin, direction)), ray);
+    }
+}
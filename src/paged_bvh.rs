@@ -0,0 +1,516 @@
+//! This module exports a flat `BVH` layout for out-of-core scenes, which clusters subtrees
+//! into fixed-size pages, so that the nodes can be memory-mapped and loaded page by page.
+
+use crate::aabb::{Bounded, AABB};
+use crate::bounding_hierarchy::{BHShape, BoundingHierarchy, IntersectionAABB};
+use crate::bvh::{BVHNode, BVH};
+use std::collections::VecDeque;
+use std::mem;
+use std::ops::Range;
+
+/// A node of a [`PagedBVH`]. The nodes of a page are stored in the same order as in a
+/// [`SkipBVH`], and all indices are local to the page. Subtrees which do not fit into the
+/// page are replaced by a link to another page.
+///
+/// The node is `repr(C)`, so that a page can be read from a file or a memory map as is.
+///
+/// [`PagedBVH`]: struct.PagedBVH.html
+/// [`SkipBVH`]: ../flat_bvh/type.SkipBVH.html
+///
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct PagedNode {
+    /// The [`AABB`] of the node.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub aabb: AABB,
+
+    /// The index of the node in the same page to continue with, if the [`AABB`] test is
+    /// negative or the node is a leaf or a link.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub skip_index: u32,
+
+    /// The index of the shape in the shapes array, or [`PagedNode::NONE`] for inner nodes
+    /// and links.
+    ///
+    /// [`PagedNode::NONE`]: struct.PagedNode.html#associatedconstant.NONE
+    ///
+    pub shape_index: u32,
+
+    /// The index of the page which contains the subtree of this node, or
+    /// [`PagedNode::NONE`] if the subtree continues in the same page.
+    ///
+    /// [`PagedNode::NONE`]: struct.PagedNode.html#associatedconstant.NONE
+    ///
+    pub page_index: u32,
+}
+
+impl PagedNode {
+    /// Marks a missing shape or page index.
+    pub const NONE: u32 = u32::MAX;
+}
+
+/// Describes a page of a [`PagedBVH`], without the need to load the page itself.
+///
+/// [`PagedBVH`]: struct.PagedBVH.html
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct PageInfo {
+    /// The [`AABB`] of the subtree in the page.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub aabb: AABB,
+
+    /// The index of the page which links to this page, or [`PagedNode::NONE`] for the
+    /// root page.
+    ///
+    /// [`PagedNode::NONE`]: struct.PagedNode.html#associatedconstant.NONE
+    ///
+    pub parent_page: u32,
+}
+
+/// A flat [`BVH`] which is split into pages of `page_capacity` nodes. Page `i` occupies
+/// the nodes `i * page_capacity..(i + 1) * page_capacity`, the first node of a page is the
+/// root of its subtree and unused nodes at the end of a page are padding. The root page
+/// is page 0, and pages are only linked from pages with a smaller index.
+///
+/// A traversal only touches the pages of the subtrees it enters. The `pages` directory is
+/// small and can be kept in memory to find the pages a query needs in advance, see
+/// [`PagedBVH::pages_for`].
+///
+/// [`BVH`]: ../bvh/struct.BVH.html
+/// [`PagedBVH::pages_for`]: struct.PagedBVH.html#method.pages_for
+///
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct PagedBVH {
+    /// The number of nodes per page.
+    pub page_capacity: usize,
+
+    /// The nodes of all pages.
+    pub nodes: Vec<PagedNode>,
+
+    /// The directory of the pages.
+    pub pages: Vec<PageInfo>,
+}
+
+impl PagedBVH {
+    /// The default size of a page in bytes.
+    pub const DEFAULT_PAGE_SIZE: usize = 64 * 1024;
+
+    /// Returns the nodes of page `page_index`, including the padding.
+    pub fn page(&self, page_index: usize) -> &[PagedNode] {
+        &self.nodes[self.page_node_range(page_index)]
+    }
+
+    /// Returns the range of `page_index` in bytes from the start of the nodes, e.g. to
+    /// prefetch the page of a memory-mapped file.
+    pub fn page_byte_range(&self, page_index: usize) -> Range<usize> {
+        let range = self.page_node_range(page_index);
+        let node_size = mem::size_of::<PagedNode>();
+        range.start * node_size..range.end * node_size
+    }
+
+    /// Returns the indices of all pages which a traversal with `test` may touch, in
+    /// ascending order. Only the page directory is read, so the pages can be prefetched
+    /// before the traversal starts.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..1000)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let bvh = BVH::build(&mut cubes);
+    /// let paged = bvh.flatten_paged(&cubes, 4096);
+    ///
+    /// let query = AABB::with_bounds(Point3::new(0.0, -1.0, -1.0), Point3::new(10.0, 1.0, 1.0));
+    /// let pages = paged.pages_for(&query);
+    /// assert!(pages.len() < paged.pages.len());
+    /// for page in pages {
+    ///     let bytes = paged.page_byte_range(page);
+    ///     assert_eq!(bytes.len(), paged.page_capacity * std::mem::size_of_val(&paged.nodes[0]));
+    /// }
+    /// ```
+    ///
+    pub fn pages_for(&self, test: &impl IntersectionAABB) -> Vec<usize> {
+        // Parents come before their children, so a single pass suffices.
+        let mut needed = vec![false; self.pages.len()];
+        for (index, page) in self.pages.iter().enumerate() {
+            let parent_needed = page.parent_page == PagedNode::NONE
+                || needed.get(page.parent_page as usize) == Some(&true);
+            needed[index] = parent_needed && test.intersects_aabb(&page.aabb);
+        }
+        needed
+            .iter()
+            .enumerate()
+            .filter(|(_, &needed)| needed)
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Returns the range of `page_index` in the nodes.
+    fn page_node_range(&self, page_index: usize) -> Range<usize> {
+        let start = page_index * self.page_capacity;
+        start..start + self.page_capacity
+    }
+
+    /// Traverses the pages and calls `enter_page` before a page is read and `visit` with
+    /// every shape whose [`AABB`] was hit by `test`.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    fn traverse_pages<'a, T>(
+        &self,
+        test: &impl IntersectionAABB,
+        shapes: &'a [T],
+        mut enter_page: impl FnMut(usize),
+        mut visit: impl FnMut(&'a T),
+    ) {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let mut stack = vec![0];
+        while let Some(page_index) = stack.pop() {
+            enter_page(page_index);
+            let page = self.page(page_index);
+            // The root of the page skips over the whole subtree of the page.
+            let end = page[0].skip_index as usize;
+            let mut index = 0;
+            while index < end {
+                let node = &page[index];
+                if !test.intersects_aabb(&node.aabb) {
+                    index = node.skip_index as usize;
+                } else if node.page_index != PagedNode::NONE {
+                    stack.push(node.page_index as usize);
+                    index = node.skip_index as usize;
+                } else if node.shape_index == PagedNode::NONE {
+                    index += 1;
+                } else {
+                    visit(&shapes[node.shape_index as usize]);
+                    index = node.skip_index as usize;
+                }
+            }
+        }
+    }
+}
+
+impl BVH {
+    /// Writes the part of the subtree of `node_index` which belongs to the current page to
+    /// `nodes`, and queues the subtrees which are not `expanded` for new pages.
+    #[allow(clippy::too_many_arguments)]
+    fn flatten_page_subtree<T: BHShape>(
+        &self,
+        node_index: usize,
+        aabb: AABB,
+        page_index: usize,
+        page_start: usize,
+        expanded: &[bool],
+        nodes: &mut Vec<PagedNode>,
+        pages: &[PageInfo],
+        queue: &mut VecDeque<(usize, AABB, u32)>,
+        shapes: &[T],
+    ) {
+        let index = nodes.len();
+        nodes.push(PagedNode::default());
+        let local_index = (index - page_start) as u32;
+        let node = match self.nodes[node_index] {
+            BVHNode::Leaf { shape_index, .. } => PagedNode {
+                aabb: shapes[shape_index].aabb(),
+                skip_index: local_index + 1,
+                shape_index: shape_index as u32,
+                page_index: PagedNode::NONE,
+            },
+            BVHNode::Node {
+                child_l_index,
+                child_l_aabb,
+                child_r_index,
+                child_r_aabb,
+                ..
+            } if expanded[node_index] => {
+                for &(child_index, child_aabb) in
+                    &[(child_l_index, child_l_aabb), (child_r_index, child_r_aabb)]
+                {
+                    self.flatten_page_subtree(
+                        child_index,
+                        child_aabb,
+                        page_index,
+                        page_start,
+                        expanded,
+                        nodes,
+                        pages,
+                        queue,
+                        shapes,
+                    );
+                }
+                PagedNode {
+                    aabb,
+                    skip_index: (nodes.len() - page_start) as u32,
+                    shape_index: PagedNode::NONE,
+                    page_index: PagedNode::NONE,
+                }
+            }
+            BVHNode::Node { .. } => {
+                // Pages are created in the order of the queue.
+                let link = (pages.len() + queue.len()) as u32;
+                queue.push_back((node_index, aabb, page_index as u32));
+                PagedNode {
+                    aabb,
+                    skip_index: local_index + 1,
+                    shape_index: PagedNode::NONE,
+                    page_index: link,
+                }
+            }
+        };
+        nodes[index] = node;
+    }
+
+    /// Flattens the [`BVH`] into a [`PagedBVH`] with pages of `page_size` bytes. Every page
+    /// is filled breadth first with the top of a subtree, the remaining subtrees continue in
+    /// new pages.
+    ///
+    /// # Panics
+    /// Panics if a page can not hold at least three nodes.
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`PagedBVH`]: ../paged_bvh/struct.PagedBVH.html
+    ///
+    pub fn flatten_paged<T: BHShape>(&self, shapes: &[T], page_size: usize) -> PagedBVH {
+        let page_capacity = page_size / mem::size_of::<PagedNode>();
+        assert!(
+            page_capacity >= 3,
+            "A page has to hold a node and its two children."
+        );
+        let mut paged = PagedBVH {
+            page_capacity,
+            nodes: Vec::new(),
+            pages: Vec::new(),
+        };
+        if self.nodes.is_empty() {
+            return paged;
+        }
+
+        let mut expanded = vec![false; self.nodes.len()];
+        let mut queue = VecDeque::new();
+        queue.push_back((0, self.nodes[0].get_node_aabb(shapes), PagedNode::NONE));
+        while let Some((root_index, aabb, parent_page)) = queue.pop_front() {
+            let page_index = paged.pages.len();
+            paged.pages.push(PageInfo { aabb, parent_page });
+
+            // Expand the inner nodes breadth first while their children fit into the page.
+            let mut node_count = 1;
+            let mut frontier = VecDeque::new();
+            frontier.push_back(root_index);
+            while let Some(node_index) = frontier.pop_front() {
+                if let BVHNode::Node {
+                    child_l_index,
+                    child_r_index,
+                    ..
+                } = self.nodes[node_index]
+                {
+                    if node_count + 2 <= page_capacity {
+                        expanded[node_index] = true;
+                        node_count += 2;
+                        frontier.push_back(child_l_index);
+                        frontier.push_back(child_r_index);
+                    }
+                }
+            }
+
+            let page_start = paged.nodes.len();
+            self.flatten_page_subtree(
+                root_index,
+                aabb,
+                page_index,
+                page_start,
+                &expanded,
+                &mut paged.nodes,
+                &paged.pages,
+                &mut queue,
+                shapes,
+            );
+            paged
+                .nodes
+                .resize(page_start + page_capacity, PagedNode::default());
+        }
+        paged
+    }
+}
+
+impl BoundingHierarchy for PagedBVH {
+    /// A [`PagedBVH`] is built from a regular [`BVH`] using the [`BVH::flatten_paged`]
+    /// method with [`PagedBVH::DEFAULT_PAGE_SIZE`].
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`BVH::flatten_paged`]: ../bvh/struct.BVH.html#method.flatten_paged
+    /// [`PagedBVH`]: struct.PagedBVH.html
+    /// [`PagedBVH::DEFAULT_PAGE_SIZE`]: struct.PagedBVH.html#associatedconstant.DEFAULT_PAGE_SIZE
+    ///
+    fn build<T: BHShape>(shapes: &mut [T]) -> PagedBVH {
+        let bvh = BVH::build(shapes);
+        bvh.flatten_paged(shapes, PagedBVH::DEFAULT_PAGE_SIZE)
+    }
+
+    /// Traverses a [`PagedBVH`] page by page.
+    ///
+    /// [`PagedBVH`]: struct.PagedBVH.html
+    ///
+    fn traverse<'a, T: Bounded>(
+        &'a self,
+        test: &impl IntersectionAABB,
+        shapes: &'a [T],
+    ) -> Vec<&'a T> {
+        let mut hit_shapes = Vec::new();
+        self.traverse_pages(test, shapes, |_| {}, |shape| hit_shapes.push(shape));
+        hit_shapes
+    }
+
+    /// Traverses a [`PagedBVH`] and calls `visit` during the traversal.
+    ///
+    /// [`PagedBVH`]: struct.PagedBVH.html
+    ///
+    fn traverse_with<'a, T: Bounded>(
+        &'a self,
+        test: &impl IntersectionAABB,
+        shapes: &'a [T],
+        visit: impl FnMut(&'a T),
+    ) {
+        self.traverse_pages(test, shapes, |_| {}, visit)
+    }
+
+    /// Prints a textual representation of a [`PagedBVH`].
+    ///
+    /// [`PagedBVH`]: struct.PagedBVH.html
+    ///
+    fn pretty_print(&self) {
+        for (page_index, info) in self.pages.iter().enumerate() {
+            let page = self.page(page_index);
+            println!("page {}\tparent {}", page_index, info.parent_page);
+            for (i, node) in page[..page[0].skip_index as usize].iter().enumerate() {
+                println!(
+                    "  {}\tskip {}\tshape {}\tpage {}",
+                    i, node.skip_index, node.shape_index, node.page_index
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::{Bounded, AABB};
+    use crate::bounding_hierarchy::{BoundingHierarchy, IntersectionAABB};
+    use crate::bvh::BVH;
+    use crate::paged_bvh::{PagedBVH, PagedNode};
+    use crate::testbase::{
+        build_some_bh, create_n_cubes, create_ray, default_bounds, next_point3, query_some_bh,
+        traverse_some_bh,
+    };
+    use crate::Vector3;
+    use std::mem;
+
+    #[test]
+    /// Tests whether the building procedure succeeds in not failing.
+    fn test_build_paged_bvh() {
+        build_some_bh::<PagedBVH>();
+    }
+
+    #[test]
+    /// Runs some primitive tests for intersections of a ray with a fixed scene given
+    /// as a `PagedBVH`.
+    fn test_traverse_paged_bvh() {
+        traverse_some_bh::<PagedBVH>();
+        query_some_bh::<PagedBVH>();
+    }
+
+    #[test]
+    /// Splits a `BVH` into small pages, checks the page structure and compares the hits
+    /// and the touched pages with the prefetched pages.
+    fn test_flatten_paged_matches_bvh() {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(100, &bounds);
+        let bvh = BVH::build(&mut triangles);
+
+        for &page_capacity in &[3, 8, 64] {
+            let paged = bvh.flatten_paged(&triangles, page_capacity * mem::size_of::<PagedNode>());
+            assert_eq!(paged.page_capacity, page_capacity);
+            assert_eq!(paged.nodes.len(), paged.pages.len() * page_capacity);
+
+            // Every page except the root is linked exactly once, from an earlier page, and
+            // every shape is referenced exactly once.
+            let mut links = vec![0; paged.pages.len()];
+            let mut shapes = vec![0; triangles.len()];
+            for (page_index, info) in paged.pages.iter().enumerate() {
+                let page = paged.page(page_index);
+                let end = page[0].skip_index as usize;
+                assert!(end <= page_capacity);
+                assert_eq!(page[0].aabb, info.aabb);
+                for node in &page[..end] {
+                    assert!(node.skip_index as usize <= end);
+                    if node.page_index != PagedNode::NONE {
+                        assert!(node.page_index as usize > page_index);
+                        assert_eq!(
+                            paged.pages[node.page_index as usize].parent_page as usize,
+                            page_index
+                        );
+                        links[node.page_index as usize] += 1;
+                    } else if node.shape_index != PagedNode::NONE {
+                        shapes[node.shape_index as usize] += 1;
+                    }
+                }
+            }
+            assert!(links[1..].iter().all(|&count| count == 1));
+            assert!(shapes.iter().all(|&count| count == 1));
+
+            let mut seed = 0;
+            for _ in 0..100 {
+                let ray = create_ray(&mut seed, &bounds);
+                let mut expected: Vec<*const _> = bvh
+                    .traverse(&ray, &triangles)
+                    .into_iter()
+                    .filter(|triangle| ray.intersects_aabb(&triangle.aabb()))
+                    .map(|triangle| triangle as *const _)
+                    .collect();
+                let mut actual: Vec<*const _> = paged
+                    .traverse(&ray, &triangles)
+                    .into_iter()
+                    .map(|triangle| triangle as *const _)
+                    .collect();
+                expected.sort_unstable();
+                actual.sort_unstable();
+                assert_eq!(expected, actual);
+
+                let center = next_point3(&mut seed, &bounds);
+                let query = AABB::with_bounds(
+                    center - Vector3::splat(20_000.0),
+                    center + Vector3::splat(20_000.0),
+                );
+                let prefetched = paged.pages_for(&query);
+                let mut touched = Vec::new();
+                paged.traverse_pages(&query, &triangles, |page| touched.push(page), |_| {});
+                assert!(touched.iter().all(|page| prefetched.contains(page)));
+            }
+        }
+    }
+}
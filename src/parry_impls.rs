@@ -0,0 +1,285 @@
+//! Implementations of the traits of this crate for the shapes of [parry], so that parry
+//! colliders can be indexed by a [`BVH`] directly. The shapes are in their local space,
+//! and can be placed in a scene with a [`Transformed`].
+//!
+//! The shapes of parry have an inherent `aabb` method, which takes a position, so the
+//! [`Bounded`] bounds are computed with `Bounded::aabb(&shape)`.
+//!
+//! [parry]: https://docs.rs/parry3d
+//! [`Bounded`]: ../aabb/trait.Bounded.html
+//! [`BVH`]: ../bvh/struct.BVH.html
+//! [`Transformed`]: ../transformed/struct.Transformed.html
+//!
+
+use parry3d::bounding_volume::Aabb;
+use parry3d::math::{Isometry, Point, Vector};
+use parry3d::query::{self, PointQuery, RayCast};
+use parry3d::shape::{Ball, Capsule, Cuboid, Shape, TriMesh};
+
+use crate::aabb::{Bounded, AABB};
+use crate::bounding_hierarchy::IntersectionAABB;
+use crate::ray::{Intersection, IntersectionRay, Ray};
+use crate::{Point3, Real, Vector3};
+
+fn to_point3(p: &Point<Real>) -> Point3 {
+    Point3::new(p.x, p.y, p.z)
+}
+
+fn to_vector3(v: &Vector<Real>) -> Vector3 {
+    Vector3::new(v.x, v.y, v.z)
+}
+
+fn to_aabb(aabb: &Aabb) -> AABB {
+    AABB::with_bounds(to_point3(&aabb.mins), to_point3(&aabb.maxs))
+}
+
+/// Casts the part of the `ray` between `t_min` and `t_max` against the `shape`, and returns
+/// the distance along the `ray` and the parry result of the hit.
+fn cast_ray(
+    shape: &impl RayCast,
+    ray: &Ray,
+    t_min: Real,
+    t_max: Real,
+) -> Option<(Real, query::RayIntersection)> {
+    let start = ray.at(t_min);
+    let parry_ray = query::Ray::new(
+        Point::new(start.x, start.y, start.z),
+        Vector::new(ray.direction.x, ray.direction.y, ray.direction.z),
+    );
+    shape
+        .cast_local_ray_and_get_normal(&parry_ray, t_max - t_min, false)
+        .map(|hit| (t_min + hit.time_of_impact, hit))
+}
+
+/// Intersects the `ray` with a convex parry shape. A ray which starts inside the shape hits
+/// its back face on the way out.
+fn intersects_convex(
+    shape: &(impl RayCast + PointQuery),
+    ray: &Ray,
+    t_min: Real,
+    t_max: Real,
+) -> Option<Intersection> {
+    let (distance, hit) = cast_ray(shape, ray, t_min, t_max)?;
+    let start = ray.at(t_min);
+    let back_face = shape.contains_local_point(&Point::new(start.x, start.y, start.z));
+    Some(Intersection::new(
+        distance,
+        0.0,
+        0.0,
+        to_vector3(&hit.normal),
+        back_face,
+    ))
+}
+
+/// Returns true if the `shape` overlaps the `aabb`. Pairs of shapes which parry cannot test
+/// are conservatively treated as overlapping.
+fn intersects_aabb(shape: &dyn Shape, aabb: &AABB) -> bool {
+    if aabb.is_empty() {
+        return false;
+    }
+    let center = aabb.center();
+    let half_extents = aabb.size() * 0.5;
+    let cuboid = Cuboid::new(Vector::new(half_extents.x, half_extents.y, half_extents.z));
+    query::intersection_test(
+        &Isometry::identity(),
+        shape,
+        &Isometry::translation(center.x, center.y, center.z),
+        &cuboid,
+    )
+    .unwrap_or(true)
+}
+
+impl Bounded for Ball {
+    fn aabb(&self) -> AABB {
+        to_aabb(&self.local_aabb())
+    }
+}
+
+impl IntersectionRay for Ball {
+    fn intersects_ray(&self, ray: &Ray, t_min: Real, t_max: Real) -> Option<Intersection> {
+        intersects_convex(self, ray, t_min, t_max)
+    }
+}
+
+impl IntersectionAABB for Ball {
+    fn intersects_aabb(&self, aabb: &AABB) -> bool {
+        intersects_aabb(self, aabb)
+    }
+}
+
+impl Bounded for Cuboid {
+    fn aabb(&self) -> AABB {
+        to_aabb(&self.local_aabb())
+    }
+}
+
+impl IntersectionRay for Cuboid {
+    fn intersects_ray(&self, ray: &Ray, t_min: Real, t_max: Real) -> Option<Intersection> {
+        intersects_convex(self, ray, t_min, t_max)
+    }
+}
+
+impl IntersectionAABB for Cuboid {
+    fn intersects_aabb(&self, aabb: &AABB) -> bool {
+        intersects_aabb(self, aabb)
+    }
+}
+
+impl Bounded for Capsule {
+    fn aabb(&self) -> AABB {
+        to_aabb(&self.local_aabb())
+    }
+}
+
+impl IntersectionRay for Capsule {
+    fn intersects_ray(&self, ray: &Ray, t_min: Real, t_max: Real) -> Option<Intersection> {
+        intersects_convex(self, ray, t_min, t_max)
+    }
+}
+
+impl IntersectionAABB for Capsule {
+    fn intersects_aabb(&self, aabb: &AABB) -> bool {
+        intersects_aabb(self, aabb)
+    }
+}
+
+impl Bounded for TriMesh {
+    fn aabb(&self) -> AABB {
+        to_aabb(self.local_aabb())
+    }
+}
+
+/// The mesh is treated as a surface, so the nearest triangle is hit from either side.
+impl IntersectionRay for TriMesh {
+    fn intersects_ray(&self, ray: &Ray, t_min: Real, t_max: Real) -> Option<Intersection> {
+        let (distance, hit) = cast_ray(self, ray, t_min, t_max)?;
+        Some(Intersection::new(
+            distance,
+            0.0,
+            0.0,
+            to_vector3(&hit.normal),
+            self.is_backface(hit.feature),
+        ))
+    }
+}
+
+impl IntersectionAABB for TriMesh {
+    fn intersects_aabb(&self, aabb: &AABB) -> bool {
+        intersects_aabb(self, aabb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use parry3d::math::{Point, Vector};
+    use parry3d::shape::{Ball, Capsule, Cuboid, TriMesh};
+
+    use crate::aabb::{Bounded, AABB};
+    use crate::bounding_hierarchy::IntersectionAABB;
+    use crate::ray::{IntersectionRay, Ray};
+    use crate::sphere::Sphere;
+    use crate::transformed::Transformed;
+    use crate::{Mat4, Point3, Real, Vector3};
+
+    #[test]
+    /// Tests that parry balls match the spheres of this crate, from outside and inside.
+    fn test_parry_ball() {
+        let ball = Ball::new(2.0);
+        let sphere = Sphere::new(Point3::ZERO, 2.0);
+        assert_eq!(Bounded::aabb(&ball), sphere.aabb());
+
+        for origin in [Point3::new(-5.0, 0.5, 0.0), Point3::new(0.0, 0.5, 0.0)] {
+            let ray = Ray::new(origin, Vector3::X);
+            let hit = ball.intersects_ray(&ray, 0.0, Real::INFINITY).unwrap();
+            let expected = sphere.intersects_ray(&ray, 0.0, Real::INFINITY).unwrap();
+            assert!((hit.distance - expected.distance).abs() < 1e-4);
+            assert!((hit.norm - expected.norm).length() < 1e-4);
+            assert_eq!(hit.back_face, expected.back_face);
+        }
+        let ray = Ray::new(Point3::new(-5.0, 0.5, 0.0), Vector3::X);
+        assert!(ball.intersects_ray(&ray, 0.0, 2.0).is_none());
+        let far = ball.intersects_ray(&ray, 5.0, Real::INFINITY).unwrap();
+        assert!(far.back_face && (far.distance - (5.0 + (3.75 as Real).sqrt())).abs() < 1e-4);
+
+        let near = AABB::with_bounds(Point3::splat(1.0), Point3::splat(3.0));
+        assert!(ball.intersects_aabb(&near));
+        assert!(!ball.intersects_aabb(&AABB::with_bounds(Point3::splat(1.5), Point3::splat(3.0))));
+        assert!(!ball.intersects_aabb(&AABB::empty()));
+    }
+
+    #[test]
+    /// Tests the bounds and ray hits of parry cuboids and capsules, also when placed with a
+    /// transform.
+    fn test_parry_cuboid_capsule() {
+        let cuboid = Cuboid::new(Vector::new(1.0, 2.0, 3.0));
+        let bounds = AABB::with_bounds(Point3::new(-1.0, -2.0, -3.0), Point3::new(1.0, 2.0, 3.0));
+        assert_eq!(Bounded::aabb(&cuboid), bounds);
+        let ray = Ray::new(Point3::new(0.0, -10.0, 0.0), Vector3::Y);
+        let hit = cuboid.intersects_ray(&ray, 0.0, Real::INFINITY).unwrap();
+        assert_eq!(hit.distance, 8.0);
+        assert_eq!(hit.norm, -Vector3::Y);
+        assert!(!hit.back_face);
+        assert!(cuboid.intersects_aabb(&AABB::with_bounds(
+            Point3::new(0.5, 0.0, 0.0),
+            Point3::new(2.0, 1.0, 1.0)
+        )));
+        assert!(!cuboid.intersects_aabb(&AABB::with_bounds(
+            Point3::new(1.5, 0.0, 0.0),
+            Point3::new(2.0, 1.0, 1.0)
+        )));
+
+        let placed = Transformed::new(cuboid, Mat4::from_translation(Vector3::new(5.0, 0.0, 0.0)));
+        assert_eq!(placed.aabb().min, Point3::new(4.0, -2.0, -3.0));
+        let ray = Ray::new(Point3::new(5.0, -10.0, 0.0), Vector3::Y);
+        assert_eq!(
+            placed
+                .intersects_ray(&ray, 0.0, Real::INFINITY)
+                .unwrap()
+                .distance,
+            8.0
+        );
+
+        let capsule = Capsule::new_y(1.0, 0.5);
+        assert_eq!(
+            Bounded::aabb(&capsule),
+            AABB::with_bounds(Point3::new(-0.5, -1.5, -0.5), Point3::new(0.5, 1.5, 0.5))
+        );
+        let ray = Ray::new(Point3::new(0.0, 10.0, 0.0), -Vector3::Y);
+        let hit = capsule.intersects_ray(&ray, 0.0, Real::INFINITY).unwrap();
+        assert!((hit.distance - 8.5).abs() < 1e-4);
+        assert!((hit.norm - Vector3::Y).length() < 1e-4);
+    }
+
+    #[test]
+    /// Tests that parry triangle meshes are hit from both sides, and report back faces.
+    fn test_parry_trimesh() {
+        let vertices = vec![
+            Point::new(0.0, 0.0, 0.0),
+            Point::new(1.0, 0.0, 0.0),
+            Point::new(0.0, 1.0, 0.0),
+        ];
+        let mesh = TriMesh::new(vertices, vec![[0, 1, 2]]).unwrap();
+        assert_eq!(
+            Bounded::aabb(&mesh),
+            AABB::with_bounds(Point3::ZERO, Point3::new(1.0, 1.0, 0.0))
+        );
+
+        let front = Ray::new(Point3::new(0.25, 0.25, 1.0), -Vector3::Z);
+        let hit = mesh.intersects_ray(&front, 0.0, Real::INFINITY).unwrap();
+        assert_eq!(hit.distance, 1.0);
+        assert_eq!(hit.norm, Vector3::Z);
+        assert!(!hit.back_face);
+
+        let back = Ray::new(Point3::new(0.25, 0.25, -1.0), Vector3::Z);
+        let hit = mesh.intersects_ray(&back, 0.0, Real::INFINITY).unwrap();
+        assert_eq!(hit.norm, -Vector3::Z);
+        assert!(hit.back_face);
+
+        let above = AABB::with_bounds(Point3::new(0.0, 0.0, 0.5), Point3::splat(1.0));
+        assert!(!mesh.intersects_aabb(&above));
+        assert!(mesh.intersects_aabb(&AABB::with_bounds(
+            Point3::new(0.0, 0.0, -0.25),
+            Point3::splat(1.0)
+        )));
+    }
+}
@@ -0,0 +1,542 @@
+//! Python bindings with [pyo3], which take and return [numpy] arrays, so that point clouds
+//! and meshes can be queried from notebooks. [`register`] adds the classes `BVH` and
+//! `TriMesh` to a Python module, see the `bvh-python` crate of this repository for a
+//! module which can be built with [maturin]:
+//!
+//! ```python
+//! import numpy as np
+//! import bvh
+//!
+//! points = np.random.rand(10000, 3)
+//! tree = bvh.BVH.from_points(points)
+//! indices, distances = tree.nearest(np.random.rand(100, 3))
+//! inside = tree.query_sphere([0.5, 0.5, 0.5], 0.1)
+//!
+//! mesh = bvh.TriMesh(vertices, faces)
+//! faces, distances = mesh.raycast(origins, directions)
+//! ```
+//!
+//! Coordinates are converted to the [`Real`] type of the crate. Indices are returned as
+//! `int64` arrays, with `-1` and an infinite distance for queries which found nothing. The
+//! classes have the same names for the `bvh` and `bvh-f64` crates, so only one of them can
+//! enable this feature in a binary.
+//!
+//! [pyo3]: https://docs.rs/pyo3
+//! [numpy]: https://docs.rs/numpy
+//! [maturin]: https://www.maturin.rs
+//! [`register`]: fn.register.html
+//! [`Real`]: ../type.Real.html
+//!
+
+use numpy::ndarray::{ArrayView1, ArrayView2};
+use numpy::{AllowTypeChange, IntoPyArray, PyArray1, PyArrayLike1, PyArrayLike2};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::aabb::{Bounded, AABB};
+use crate::bounding_hierarchy::{BHShape, IntersectionAABB};
+use crate::bvh::BVH;
+use crate::ray::{IntersectionRay, Ray};
+use crate::sphere::Sphere;
+use crate::triangle::Triangle;
+use crate::{Point3, Real};
+
+/// The indices and distances returned by the batched queries.
+type Hits<'py> = (Bound<'py, PyArray1<i64>>, Bound<'py, PyArray1<Real>>);
+
+/// Adds the classes `BVH` and `TriMesh` to the Python `module`.
+pub fn register(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyBVH>()?;
+    module.add_class::<PyTriMesh>()?;
+    Ok(())
+}
+
+/// Reads an array of the shape `(n, 3)` into points.
+fn to_points(array: ArrayView2<Real>, name: &str) -> PyResult<Vec<Point3>> {
+    if array.ncols() != 3 {
+        return Err(PyValueError::new_err(format!(
+            "{} must have the shape (n, 3)",
+            name
+        )));
+    }
+    Ok(array
+        .rows()
+        .into_iter()
+        .map(|row| Point3::new(row[0], row[1], row[2]))
+        .collect())
+}
+
+/// Reads an array of the shape `(3,)` into a point.
+fn to_point(array: ArrayView1<Real>, name: &str) -> PyResult<Point3> {
+    match array.as_slice() {
+        Some(&[x, y, z]) => Ok(Point3::new(x, y, z)),
+        _ => Err(PyValueError::new_err(format!(
+            "{} must have three components",
+            name
+        ))),
+    }
+}
+
+/// Converts the results of a batched query into numpy arrays.
+fn to_hits(py: Python<'_>, hits: Vec<Option<(usize, Real)>>) -> Hits<'_> {
+    let indices: Vec<i64> = hits
+        .iter()
+        .map(|hit| hit.map_or(-1, |(index, _)| index as i64))
+        .collect();
+    let distances: Vec<Real> = hits
+        .iter()
+        .map(|hit| hit.map_or(Real::INFINITY, |(_, distance)| distance))
+        .collect();
+    (indices.into_pyarray(py), distances.into_pyarray(py))
+}
+
+/// The bounds of a box, point or triangle in the [`BVH`] of a Python object.
+///
+/// [`BVH`]: ../bvh/struct.BVH.html
+///
+struct Entry {
+    aabb: AABB,
+    index: usize,
+    node_index: usize,
+}
+
+impl Bounded for Entry {
+    fn aabb(&self) -> AABB {
+        self.aabb
+    }
+}
+
+impl BHShape for Entry {
+    fn set_bh_node_index(&mut self, index: usize) {
+        self.node_index = index;
+    }
+
+    fn bh_node_index(&self) -> usize {
+        self.node_index
+    }
+}
+
+/// A [`BVH`] with its entries, which answers the queries of both Python classes.
+///
+/// [`BVH`]: ../bvh/struct.BVH.html
+///
+struct Tree {
+    bvh: BVH,
+    entries: Vec<Entry>,
+}
+
+impl Tree {
+    fn new(aabbs: impl IntoIterator<Item = AABB>) -> Tree {
+        let mut entries: Vec<Entry> = aabbs
+            .into_iter()
+            .enumerate()
+            .map(|(index, aabb)| Entry {
+                aabb,
+                index,
+                node_index: 0,
+            })
+            .collect();
+        let bvh = BVH::build(&mut entries);
+        Tree { bvh, entries }
+    }
+
+    /// Returns the sorted indices of the entries which pass `test`, out of those whose
+    /// bounds overlap `region`.
+    fn query(&self, region: &impl IntersectionAABB, test: impl Fn(usize) -> bool) -> Vec<i64> {
+        let mut indices: Vec<i64> = self
+            .bvh
+            .traverse_indices(region)
+            .into_iter()
+            .filter(|&index| test(index))
+            .map(|index| index as i64)
+            .collect();
+        indices.sort_unstable();
+        indices
+    }
+
+    /// Returns the index of and distance to the entry nearest to `point`, given the
+    /// `distance` from `point` to the entry with an index.
+    fn nearest(&self, point: &Point3, distance: impl Fn(usize) -> Real) -> Option<(usize, Real)> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        self.bvh.traverse_best_first(
+            -1.0,
+            Real::INFINITY,
+            |aabb| Some(aabb.distance_squared(point)),
+            |index| {
+                let distance = distance(index);
+                Some((distance, (index, distance)))
+            },
+        )
+    }
+
+    /// Returns the index of and distance to the first entry hit by `ray`, given the
+    /// `distance` along `ray` to the entry with an index.
+    fn raycast(
+        &self,
+        ray: &Ray,
+        distance: impl Fn(usize) -> Option<Real>,
+    ) -> Option<(usize, Real)> {
+        self.bvh
+            .traverse_nearest_with(ray, &self.entries, |entry| distance(entry.index))
+            .map(|(entry, distance)| (entry.index, distance))
+    }
+}
+
+/// A BVH over axis aligned boxes or points, for region and nearest neighbour queries.
+#[pyclass(name = "BVH", module = "bvh", frozen)]
+pub struct PyBVH {
+    tree: Tree,
+}
+
+impl PyBVH {
+    fn from_aabbs(aabbs: Vec<AABB>) -> PyBVH {
+        PyBVH {
+            tree: Tree::new(aabbs),
+        }
+    }
+
+    fn aabb(&self, index: usize) -> &AABB {
+        &self.tree.entries[index].aabb
+    }
+}
+
+#[pymethods]
+impl PyBVH {
+    /// Builds a BVH over the boxes with the corners `mins` and `maxs`, both of the shape
+    /// `(n, 3)`.
+    #[new]
+    fn new(
+        mins: PyArrayLike2<Real, AllowTypeChange>,
+        maxs: PyArrayLike2<Real, AllowTypeChange>,
+    ) -> PyResult<PyBVH> {
+        let mins = to_points(mins.as_array(), "mins")?;
+        let maxs = to_points(maxs.as_array(), "maxs")?;
+        if mins.len() != maxs.len() {
+            return Err(PyValueError::new_err(
+                "mins and maxs must have the same length",
+            ));
+        }
+        Ok(PyBVH::from_aabbs(
+            mins.into_iter()
+                .zip(maxs)
+                .map(|(min, max)| AABB::with_bounds(min, max))
+                .collect(),
+        ))
+    }
+
+    /// Builds a BVH over `points` of the shape `(n, 3)`.
+    #[staticmethod]
+    fn from_points(points: PyArrayLike2<Real, AllowTypeChange>) -> PyResult<PyBVH> {
+        let points = to_points(points.as_array(), "points")?;
+        Ok(PyBVH::from_aabbs(
+            points
+                .into_iter()
+                .map(|point| AABB::with_bounds(point, point))
+                .collect(),
+        ))
+    }
+
+    fn __len__(&self) -> usize {
+        self.tree.entries.len()
+    }
+
+    /// Returns the sorted indices of the boxes which overlap the box from `min` to `max`.
+    fn query_box<'py>(
+        &self,
+        py: Python<'py>,
+        min: PyArrayLike1<Real, AllowTypeChange>,
+        max: PyArrayLike1<Real, AllowTypeChange>,
+    ) -> PyResult<Bound<'py, PyArray1<i64>>> {
+        let region = AABB::with_bounds(
+            to_point(min.as_array(), "min")?,
+            to_point(max.as_array(), "max")?,
+        );
+        Ok(self.tree.query(&region, |_| true).into_pyarray(py))
+    }
+
+    /// Returns the sorted indices of the boxes which overlap the sphere around `center`.
+    fn query_sphere<'py>(
+        &self,
+        py: Python<'py>,
+        center: PyArrayLike1<Real, AllowTypeChange>,
+        radius: Real,
+    ) -> PyResult<Bound<'py, PyArray1<i64>>> {
+        let region = Sphere::new(to_point(center.as_array(), "center")?, radius);
+        Ok(self.tree.query(&region, |_| true).into_pyarray(py))
+    }
+
+    /// Returns the indices of and distances to the boxes nearest to `points` of the shape
+    /// `(n, 3)`. Points inside a box have a distance of zero.
+    fn nearest<'py>(
+        &self,
+        py: Python<'py>,
+        points: PyArrayLike2<Real, AllowTypeChange>,
+    ) -> PyResult<Hits<'py>> {
+        let points = to_points(points.as_array(), "points")?;
+        let hits = py.detach(|| {
+            points
+                .iter()
+                .map(|point| {
+                    self.tree.nearest(point, |index| {
+                        self.aabb(index).distance_squared(point).sqrt()
+                    })
+                })
+                .collect()
+        });
+        Ok(to_hits(py, hits))
+    }
+
+    /// Returns the indices of and distances to the first boxes hit by the rays from
+    /// `origins` along `directions`, both of the shape `(n, 3)`, up to `max_distance`.
+    #[pyo3(signature = (origins, directions, max_distance = Real::INFINITY))]
+    fn raycast<'py>(
+        &self,
+        py: Python<'py>,
+        origins: PyArrayLike2<Real, AllowTypeChange>,
+        directions: PyArrayLike2<Real, AllowTypeChange>,
+        max_distance: Real,
+    ) -> PyResult<Hits<'py>> {
+        let rays = to_rays(origins.as_array(), directions.as_array())?;
+        let hits = py.detach(|| {
+            rays.iter()
+                .map(|ray| {
+                    self.tree.raycast(ray, |index| {
+                        ray.intersects_aabb_interval(self.aabb(index))
+                            .map(|(entry, _)| entry.max(0.0))
+                            .filter(|&distance| distance <= max_distance)
+                    })
+                })
+                .collect()
+        });
+        Ok(to_hits(py, hits))
+    }
+}
+
+/// Reads the `origins` and `directions` of rays, both of the shape `(n, 3)`.
+fn to_rays(origins: ArrayView2<Real>, directions: ArrayView2<Real>) -> PyResult<Vec<Ray>> {
+    let origins = to_points(origins, "origins")?;
+    let directions = to_points(directions, "directions")?;
+    if origins.len() != directions.len() {
+        return Err(PyValueError::new_err(
+            "origins and directions must have the same length",
+        ));
+    }
+    Ok(origins
+        .into_iter()
+        .zip(directions)
+        .map(|(origin, direction)| Ray::new(origin, direction))
+        .collect())
+}
+
+/// A triangle mesh, for ray, nearest point and region queries.
+#[pyclass(name = "TriMesh", module = "bvh", frozen)]
+pub struct PyTriMesh {
+    triangles: Vec<Triangle>,
+    tree: Tree,
+}
+
+impl PyTriMesh {
+    fn from_triangles(triangles: Vec<Triangle>) -> PyTriMesh {
+        let tree = Tree::new(triangles.iter().map(Triangle::aabb));
+        PyTriMesh { triangles, tree }
+    }
+
+    fn distance(&self, face: usize, point: &Point3) -> Real {
+        self.triangles[face].closest_point(point).distance(*point)
+    }
+}
+
+#[pymethods]
+impl PyTriMesh {
+    /// Builds a mesh from `vertices` of the shape `(n, 3)`, and `faces` of the shape
+    /// `(m, 3)` holding the vertex indices of the triangles.
+    #[new]
+    fn new(
+        vertices: PyArrayLike2<Real, AllowTypeChange>,
+        faces: PyArrayLike2<u32, AllowTypeChange>,
+    ) -> PyResult<PyTriMesh> {
+        let vertices = to_points(vertices.as_array(), "vertices")?;
+        let faces = faces.as_array();
+        if faces.ncols() != 3 {
+            return Err(PyValueError::new_err("faces must have the shape (m, 3)"));
+        }
+        if faces.iter().any(|&index| index as usize >= vertices.len()) {
+            return Err(PyValueError::new_err("a vertex index is out of range"));
+        }
+        Ok(PyTriMesh::from_triangles(
+            faces
+                .rows()
+                .into_iter()
+                .map(|face| {
+                    let [a, b, c] = [face[0], face[1], face[2]].map(|i| vertices[i as usize]);
+                    Triangle::new(a, b, c)
+                })
+                .collect(),
+        ))
+    }
+
+    fn __len__(&self) -> usize {
+        self.triangles.len()
+    }
+
+    /// Returns the sorted indices of the triangles which overlap the box from `min` to
+    /// `max`.
+    fn query_box<'py>(
+        &self,
+        py: Python<'py>,
+        min: PyArrayLike1<Real, AllowTypeChange>,
+        max: PyArrayLike1<Real, AllowTypeChange>,
+    ) -> PyResult<Bound<'py, PyArray1<i64>>> {
+        let region = AABB::with_bounds(
+            to_point(min.as_array(), "min")?,
+            to_point(max.as_array(), "max")?,
+        );
+        let faces = self.tree.query(&region, |face| {
+            self.triangles[face].intersects_aabb(&region)
+        });
+        Ok(faces.into_pyarray(py))
+    }
+
+    /// Returns the sorted indices of the triangles which overlap the sphere around
+    /// `center`.
+    fn query_sphere<'py>(
+        &self,
+        py: Python<'py>,
+        center: PyArrayLike1<Real, AllowTypeChange>,
+        radius: Real,
+    ) -> PyResult<Bound<'py, PyArray1<i64>>> {
+        let center = to_point(center.as_array(), "center")?;
+        let faces = self.tree.query(&Sphere::new(center, radius), |face| {
+            self.distance(face, &center) <= radius
+        });
+        Ok(faces.into_pyarray(py))
+    }
+
+    /// Returns the indices of and distances to the triangles nearest to `points` of the
+    /// shape `(n, 3)`.
+    fn nearest<'py>(
+        &self,
+        py: Python<'py>,
+        points: PyArrayLike2<Real, AllowTypeChange>,
+    ) -> PyResult<Hits<'py>> {
+        let points = to_points(points.as_array(), "points")?;
+        let hits = py.detach(|| {
+            points
+                .iter()
+                .map(|point| self.tree.nearest(point, |face| self.distance(face, point)))
+                .collect()
+        });
+        Ok(to_hits(py, hits))
+    }
+
+    /// Returns the indices of and distances to the first triangles hit by the rays from
+    /// `origins` along `directions`, both of the shape `(n, 3)`, up to `max_distance`.
+    #[pyo3(signature = (origins, directions, max_distance = Real::INFINITY))]
+    fn raycast<'py>(
+        &self,
+        py: Python<'py>,
+        origins: PyArrayLike2<Real, AllowTypeChange>,
+        directions: PyArrayLike2<Real, AllowTypeChange>,
+        max_distance: Real,
+    ) -> PyResult<Hits<'py>> {
+        let rays = to_rays(origins.as_array(), directions.as_array())?;
+        let hits = py.detach(|| {
+            rays.iter()
+                .map(|ray| {
+                    self.tree.raycast(ray, |face| {
+                        self.triangles[face]
+                            .intersects_ray(ray, 0.0, max_distance)
+                            .map(|hit| hit.distance)
+                            .filter(|distance| distance.is_finite())
+                    })
+                })
+                .collect()
+        });
+        Ok(to_hits(py, hits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::AABB;
+    use crate::python::{PyBVH, PyTriMesh};
+    use crate::ray::Ray;
+    use crate::sphere::Sphere;
+    use crate::testbase::next_point3;
+    use crate::triangle::Triangle;
+    use crate::{Point3, Real, Vector3};
+
+    #[test]
+    /// Tests the queries behind the Python `BVH` against brute force, without numpy.
+    fn test_python_bvh_queries() {
+        let bounds = AABB::with_bounds(Point3::splat(-10.0), Point3::splat(10.0));
+        let mut seed = 3;
+        let points: Vec<Point3> = (0..200).map(|_| next_point3(&mut seed, &bounds)).collect();
+        let bvh = PyBVH::from_aabbs(points.iter().map(|&p| AABB::with_bounds(p, p)).collect());
+
+        for _ in 0..20 {
+            let query = next_point3(&mut seed, &bounds);
+            let (index, distance) = bvh
+                .tree
+                .nearest(&query, |i| bvh.aabb(i).distance_squared(&query).sqrt())
+                .unwrap();
+            let expected = points
+                .iter()
+                .map(|point| point.distance(query))
+                .fold(Real::INFINITY, Real::min);
+            assert_eq!(points[index].distance(query), expected);
+            assert!((distance - expected).abs() < 1e-4);
+
+            let sphere = Sphere::new(query, 5.0);
+            let inside = bvh.tree.query(&sphere, |_| true);
+            let expected: Vec<i64> = (0..points.len())
+                .filter(|&i| points[i].distance_squared(query) <= 25.0)
+                .map(|i| i as i64)
+                .collect();
+            assert_eq!(inside, expected);
+        }
+
+        let boxes = PyBVH::from_aabbs(vec![
+            AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0)),
+            AABB::with_bounds(Point3::new(0.0, 0.0, 3.0), Point3::new(1.0, 1.0, 4.0)),
+            AABB::with_bounds(Point3::new(5.0, 0.0, 0.0), Point3::new(6.0, 1.0, 1.0)),
+        ]);
+        let ray = Ray::new(Point3::new(0.5, 0.5, 10.0), Vector3::new(0.0, 0.0, -1.0));
+        let hit = boxes.tree.raycast(&ray, |i| {
+            ray.intersects_aabb_interval(boxes.aabb(i))
+                .map(|(entry, _)| entry)
+        });
+        assert_eq!(hit, Some((1, 6.0)));
+
+        let empty = PyBVH::from_aabbs(Vec::new());
+        assert!(empty.tree.nearest(&Point3::ZERO, |_| 0.0).is_none());
+        assert!(empty.tree.query(&bounds, |_| true).is_empty());
+        assert!(empty.tree.raycast(&ray, |_| Some(0.0)).is_none());
+    }
+
+    #[test]
+    /// Tests the nearest triangle and region queries behind the Python `TriMesh`.
+    fn test_python_trimesh_queries() {
+        let mesh = PyTriMesh::from_triangles(vec![
+            Triangle::new(Point3::ZERO, Point3::X, Point3::Y),
+            Triangle::new(
+                Point3::new(0.0, 0.0, 4.0),
+                Point3::new(1.0, 0.0, 4.0),
+                Point3::new(0.0, 1.0, 4.0),
+            ),
+        ]);
+        let point = Point3::new(0.2, 0.2, 3.0);
+        let nearest = mesh
+            .tree
+            .nearest(&point, |face| mesh.distance(face, &point));
+        assert_eq!(nearest, Some((1, 1.0)));
+
+        let sphere = Sphere::new(Point3::new(0.2, 0.2, 0.5), 1.0);
+        let faces = mesh
+            .tree
+            .query(&sphere, |face| mesh.distance(face, &sphere.center) <= 1.0);
+        assert_eq!(faces, vec![0]);
+    }
+}
@@ -0,0 +1,468 @@
+//! Generation of WGSL and GLSL code for the traversal of flattened [`BVH`]s on the GPU.
+//!
+//! The generated code contains the node struct, its layout constants, the storage buffer
+//! declaration and a `bvh_traverse` function which finds the closest hit of a ray. The node
+//! struct and the constants are derived from the memory layout of [`GpuNode`] or
+//! [`GpuSkipNode`], so the shader always agrees with the nodes uploaded from Rust.
+//!
+//! The shape intersection is left to the shader which includes the code. It has to define
+//! the following function, which returns the distance to the hit, or a value which is not
+//! smaller than `t_max` if the shape is missed:
+//!
+//! ```glsl
+//! float bvh_intersect_shape(uint shape_index, vec3 origin, vec3 direction, float t_max);
+//! ```
+//!
+//! or in WGSL:
+//!
+//! ```wgsl
+//! fn bvh_intersect_shape(shape_index: u32, origin: vec3<f32>, direction: vec3<f32>, t_max: f32) -> f32
+//! ```
+//!
+//! [`BVH`]: ../bvh/struct.BVH.html
+//! [`GpuNode`]: ../flat_bvh/struct.GpuNode.html
+//! [`GpuSkipNode`]: ../flat_bvh/struct.GpuSkipNode.html
+//!
+
+use crate::flat_bvh::{GpuNode, GpuSkipNode};
+use std::fmt::{Display, Write};
+use std::mem;
+
+/// The shading language to generate code for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShaderLanguage {
+    /// WGSL, e.g. for wgpu.
+    Wgsl,
+    /// GLSL 4.30 or later, e.g. for OpenGL or Vulkan.
+    Glsl,
+}
+
+/// The flat node layout which the generated code traverses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShaderNodeLayout {
+    /// [`GpuNode`]s, as created by [`BVH::flatten_gpu`].
+    ///
+    /// [`BVH::flatten_gpu`]: ../bvh/struct.BVH.html#method.flatten_gpu
+    /// [`GpuNode`]: ../flat_bvh/struct.GpuNode.html
+    ///
+    Flat,
+    /// [`GpuSkipNode`]s, as created by [`BVH::flatten_skip_gpu`].
+    ///
+    /// [`BVH::flatten_skip_gpu`]: ../bvh/struct.BVH.html#method.flatten_skip_gpu
+    /// [`GpuSkipNode`]: ../flat_bvh/struct.GpuSkipNode.html
+    ///
+    Skip,
+}
+
+/// The type of a field of a GPU node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldType {
+    Vec3,
+    U32,
+}
+
+impl FieldType {
+    /// The size of the field in bytes.
+    fn size(self) -> usize {
+        match self {
+            FieldType::Vec3 => 12,
+            FieldType::U32 => 4,
+        }
+    }
+
+    /// The name of the type in the given language.
+    fn name(self, language: ShaderLanguage) -> &'static str {
+        match (self, language) {
+            (FieldType::Vec3, ShaderLanguage::Wgsl) => "vec3<f32>",
+            (FieldType::Vec3, ShaderLanguage::Glsl) => "vec3",
+            (FieldType::U32, ShaderLanguage::Wgsl) => "u32",
+            (FieldType::U32, ShaderLanguage::Glsl) => "uint",
+        }
+    }
+}
+
+/// A field of a GPU node with its byte offset.
+struct Field {
+    name: &'static str,
+    ty: FieldType,
+    offset: usize,
+}
+
+/// Returns the byte offset of `field` within `node`.
+fn offset_of<N, F>(node: &N, field: &F) -> usize {
+    field as *const F as usize - node as *const N as usize
+}
+
+impl ShaderNodeLayout {
+    /// The size of a node in bytes.
+    fn size(self) -> usize {
+        match self {
+            ShaderNodeLayout::Flat => mem::size_of::<GpuNode>(),
+            ShaderNodeLayout::Skip => mem::size_of::<GpuSkipNode>(),
+        }
+    }
+
+    /// The fields of a node, ordered by their offset. Padding is not included.
+    fn fields(self) -> Vec<Field> {
+        let field = |name, ty, offset| Field { name, ty, offset };
+        let mut fields = match self {
+            ShaderNodeLayout::Flat => {
+                let node = GpuNode::default();
+                vec![
+                    field(
+                        "aabb_min",
+                        FieldType::Vec3,
+                        offset_of(&node, &node.aabb_min),
+                    ),
+                    field(
+                        "entry_index",
+                        FieldType::U32,
+                        offset_of(&node, &node.entry_index),
+                    ),
+                    field(
+                        "aabb_max",
+                        FieldType::Vec3,
+                        offset_of(&node, &node.aabb_max),
+                    ),
+                    field(
+                        "exit_index",
+                        FieldType::U32,
+                        offset_of(&node, &node.exit_index),
+                    ),
+                    field(
+                        "shape_index",
+                        FieldType::U32,
+                        offset_of(&node, &node.shape_index),
+                    ),
+                ]
+            }
+            ShaderNodeLayout::Skip => {
+                let node = GpuSkipNode::default();
+                vec![
+                    field(
+                        "aabb_min",
+                        FieldType::Vec3,
+                        offset_of(&node, &node.aabb_min),
+                    ),
+                    field(
+                        "skip_index",
+                        FieldType::U32,
+                        offset_of(&node, &node.skip_index),
+                    ),
+                    field(
+                        "aabb_max",
+                        FieldType::Vec3,
+                        offset_of(&node, &node.aabb_max),
+                    ),
+                    field(
+                        "shape_index",
+                        FieldType::U32,
+                        offset_of(&node, &node.shape_index),
+                    ),
+                ]
+            }
+        };
+        fields.sort_by_key(|field| field.offset);
+        fields
+    }
+}
+
+/// Generates the code for the traversal of `layout` nodes in `language`. The nodes are
+/// read from a storage buffer named `bvh_nodes`, which is declared at the given `binding`.
+/// The `group` is only used for WGSL, since GLSL for OpenGL has no descriptor sets.
+///
+/// The generated `bvh_traverse` function takes the ray origin, direction and maximum
+/// distance, shortens the maximum distance to the closest hit and returns the index of the
+/// hit shape, or `BVH_INVALID_INDEX` if nothing was hit.
+///
+/// # Examples
+///
+/// ```
+/// use bvh::shader::{traversal_shader, ShaderLanguage, ShaderNodeLayout};
+///
+/// let wgsl = traversal_shader(ShaderLanguage::Wgsl, ShaderNodeLayout::Skip, 0, 1);
+/// assert!(wgsl.contains("const BVH_NODE_SIZE: u32 = 32u;"));
+/// assert!(wgsl.contains("@group(0) @binding(1) var<storage, read> bvh_nodes: array<BvhNode>;"));
+/// ```
+///
+pub fn traversal_shader(
+    language: ShaderLanguage,
+    layout: ShaderNodeLayout,
+    group: u32,
+    binding: u32,
+) -> String {
+    let mut code = String::new();
+    let fields = layout.fields();
+    let size = layout.size();
+
+    let _ = writeln!(
+        code,
+        "// Generated by bvh {}. Do not edit, the layout has to match the Rust nodes.",
+        env!("CARGO_PKG_VERSION")
+    );
+    write_constant(&mut code, language, "BVH_NODE_SIZE", size);
+    for field in &fields {
+        let name = format!("BVH_NODE_{}_OFFSET", field.name.to_uppercase());
+        write_constant(&mut code, language, &name, field.offset);
+    }
+    write_constant(&mut code, language, "BVH_INVALID_INDEX", "0xFFFFFFFF");
+    code.push('\n');
+
+    // The struct follows the Rust layout. Gaps between the fields and at the end are
+    // filled with explicit padding, so that the struct size matches in every layout rule.
+    code.push_str("struct BvhNode {\n");
+    let mut cursor = 0;
+    let mut padding = 0;
+    let mut write_padding = |code: &mut String, from: usize, to: usize| {
+        for _ in (from..to).step_by(4) {
+            let _ = match language {
+                ShaderLanguage::Wgsl => writeln!(code, "    _padding{}: u32,", padding),
+                ShaderLanguage::Glsl => writeln!(code, "    uint _padding{};", padding),
+            };
+            padding += 1;
+        }
+    };
+    for field in &fields {
+        debug_assert!(field.ty != FieldType::Vec3 || field.offset % 16 == 0);
+        write_padding(&mut code, cursor, field.offset);
+        let _ = match language {
+            ShaderLanguage::Wgsl => {
+                writeln!(code, "    {}: {},", field.name, field.ty.name(language))
+            }
+            ShaderLanguage::Glsl => {
+                writeln!(code, "    {} {};", field.ty.name(language), field.name)
+            }
+        };
+        cursor = field.offset + field.ty.size();
+    }
+    write_padding(&mut code, cursor, size);
+    code.push_str(match language {
+        ShaderLanguage::Wgsl => "}\n\n",
+        ShaderLanguage::Glsl => "};\n\n",
+    });
+
+    let _ = match language {
+        ShaderLanguage::Wgsl => writeln!(
+            code,
+            "@group({}) @binding({}) var<storage, read> bvh_nodes: array<BvhNode>;\n",
+            group, binding
+        ),
+        ShaderLanguage::Glsl => writeln!(
+            code,
+            "layout(std430, binding = {}) readonly buffer BvhNodes {{\n    BvhNode bvh_nodes[];\n}};\n",
+            binding
+        ),
+    };
+
+    let (prologue, body, epilogue) = match language {
+        ShaderLanguage::Wgsl => (WGSL_PROLOGUE, WGSL_HIT_SHAPE, WGSL_EPILOGUE),
+        ShaderLanguage::Glsl => (GLSL_PROLOGUE, GLSL_HIT_SHAPE, GLSL_EPILOGUE),
+    };
+    let step = match (language, layout) {
+        (ShaderLanguage::Wgsl, ShaderNodeLayout::Flat) => WGSL_FLAT_STEP,
+        (ShaderLanguage::Wgsl, ShaderNodeLayout::Skip) => WGSL_SKIP_STEP,
+        (ShaderLanguage::Glsl, ShaderNodeLayout::Flat) => GLSL_FLAT_STEP,
+        (ShaderLanguage::Glsl, ShaderNodeLayout::Skip) => GLSL_SKIP_STEP,
+    };
+    code.push_str(prologue);
+    for line in step.lines() {
+        if line.trim() == "HIT_SHAPE" {
+            let indent = &line[..line.len() - line.trim_start().len()];
+            for body_line in body.lines() {
+                let _ = writeln!(code, "{}{}", indent, body_line);
+            }
+        } else {
+            let _ = writeln!(code, "{}", line);
+        }
+    }
+    code.push_str(epilogue);
+    code
+}
+
+/// Writes the declaration of an unsigned integer constant.
+fn write_constant(code: &mut String, language: ShaderLanguage, name: &str, value: impl Display) {
+    let _ = match language {
+        ShaderLanguage::Wgsl => writeln!(code, "const {}: u32 = {}u;", name, value),
+        ShaderLanguage::Glsl => writeln!(code, "const uint {} = {}u;", name, value),
+    };
+}
+
+const GLSL_PROLOGUE: &str = "float bvh_intersect_shape(uint shape_index, vec3 origin, vec3 direction, float t_max);
+
+bool bvh_intersects_aabb(vec3 origin, vec3 inv_direction, float t_max, vec3 aabb_min, vec3 aabb_max) {
+    vec3 t0 = (aabb_min - origin) * inv_direction;
+    vec3 t1 = (aabb_max - origin) * inv_direction;
+    vec3 t_near = min(t0, t1);
+    vec3 t_far = max(t0, t1);
+    float t_enter = max(max(t_near.x, t_near.y), max(t_near.z, 0.0));
+    float t_exit = min(min(t_far.x, t_far.y), min(t_far.z, t_max));
+    return t_enter <= t_exit;
+}
+
+uint bvh_traverse(vec3 origin, vec3 direction, inout float t_max) {
+    vec3 inv_direction = 1.0 / direction;
+    uint hit_shape = BVH_INVALID_INDEX;
+    uint node_count = uint(bvh_nodes.length());
+    uint index = 0u;
+    while (index < node_count) {
+        BvhNode node = bvh_nodes[index];
+        bool hit = bvh_intersects_aabb(origin, inv_direction, t_max, node.aabb_min, node.aabb_max);
+";
+
+const GLSL_HIT_SHAPE: &str =
+    "float t = bvh_intersect_shape(node.shape_index, origin, direction, t_max);
+if (t < t_max) {
+    t_max = t;
+    hit_shape = node.shape_index;
+}";
+
+const GLSL_FLAT_STEP: &str = "        if (node.entry_index == BVH_INVALID_INDEX) {
+            if (hit) {
+                HIT_SHAPE
+            }
+            index = node.exit_index;
+        } else if (hit) {
+            index = node.entry_index;
+        } else {
+            index = node.exit_index;
+        }
+";
+
+const GLSL_SKIP_STEP: &str = "        if (!hit) {
+            index = node.skip_index;
+        } else if (node.shape_index == BVH_INVALID_INDEX) {
+            index += 1u;
+        } else {
+            HIT_SHAPE
+            index = node.skip_index;
+        }
+";
+
+const GLSL_EPILOGUE: &str = "    }
+    return hit_shape;
+}
+";
+
+const WGSL_PROLOGUE: &str = "fn bvh_intersects_aabb(origin: vec3<f32>, inv_direction: vec3<f32>, t_max: f32, aabb_min: vec3<f32>, aabb_max: vec3<f32>) -> bool {
+    let t0 = (aabb_min - origin) * inv_direction;
+    let t1 = (aabb_max - origin) * inv_direction;
+    let t_near = min(t0, t1);
+    let t_far = max(t0, t1);
+    let t_enter = max(max(t_near.x, t_near.y), max(t_near.z, 0.0));
+    let t_exit = min(min(t_far.x, t_far.y), min(t_far.z, t_max));
+    return t_enter <= t_exit;
+}
+
+fn bvh_traverse(origin: vec3<f32>, direction: vec3<f32>, t_max: ptr<function, f32>) -> u32 {
+    let inv_direction = 1.0 / direction;
+    var hit_shape = BVH_INVALID_INDEX;
+    let node_count = arrayLength(&bvh_nodes);
+    var index = 0u;
+    while (index < node_count) {
+        let node = bvh_nodes[index];
+        let hit = bvh_intersects_aabb(origin, inv_direction, *t_max, node.aabb_min, node.aabb_max);
+";
+
+const WGSL_HIT_SHAPE: &str =
+    "let t = bvh_intersect_shape(node.shape_index, origin, direction, *t_max);
+if (t < *t_max) {
+    *t_max = t;
+    hit_shape = node.shape_index;
+}";
+
+const WGSL_FLAT_STEP: &str = "        if (node.entry_index == BVH_INVALID_INDEX) {
+            if (hit) {
+                HIT_SHAPE
+            }
+            index = node.exit_index;
+        } else if (hit) {
+            index = node.entry_index;
+        } else {
+            index = node.exit_index;
+        }
+";
+
+const WGSL_SKIP_STEP: &str = "        if (!hit) {
+            index = node.skip_index;
+        } else if (node.shape_index == BVH_INVALID_INDEX) {
+            index += 1u;
+        } else {
+            HIT_SHAPE
+            index = node.skip_index;
+        }
+";
+
+const WGSL_EPILOGUE: &str = "    }
+    return hit_shape;
+}
+";
+
+#[cfg(test)]
+mod tests {
+    use crate::shader::{traversal_shader, ShaderLanguage, ShaderNodeLayout};
+
+    /// Computes the std430 offsets of the fields of the generated `BvhNode` struct and the
+    /// size of the struct.
+    fn std430_layout(code: &str, language: ShaderLanguage) -> (Vec<(String, usize)>, usize) {
+        let start = code.find("struct BvhNode {").unwrap();
+        let end = start + code[start..].find('}').unwrap();
+        let mut offsets = Vec::new();
+        let mut offset: usize = 0;
+        for line in code[start..end].lines().skip(1) {
+            let line = line.trim().trim_end_matches([',', ';']);
+            let (name, ty) = match language {
+                ShaderLanguage::Wgsl => {
+                    let mut parts = line.split(": ");
+                    (parts.next().unwrap(), parts.next().unwrap())
+                }
+                ShaderLanguage::Glsl => {
+                    let mut parts = line.split(' ');
+                    let ty = parts.next().unwrap();
+                    (parts.next().unwrap(), ty)
+                }
+            };
+            let (align, size) = match ty {
+                "vec3" | "vec3<f32>" => (16, 12),
+                "uint" | "u32" => (4, 4),
+                _ => panic!("Unexpected type {}", ty),
+            };
+            offset = offset.div_ceil(align) * align;
+            offsets.push((name.to_string(), offset));
+            offset += size;
+        }
+        (offsets, offset.div_ceil(16) * 16)
+    }
+
+    #[test]
+    /// Tests that the generated structs have the layout of the Rust nodes and that the
+    /// layout constants agree with them.
+    fn test_shader_layout_matches_nodes() {
+        for language in [ShaderLanguage::Wgsl, ShaderLanguage::Glsl] {
+            for (layout, size) in [(ShaderNodeLayout::Flat, 48), (ShaderNodeLayout::Skip, 32)] {
+                let code = traversal_shader(language, layout, 0, 0);
+                let (offsets, struct_size) = std430_layout(&code, language);
+                assert_eq!(struct_size, size);
+                assert!(code.contains(&format!(
+                    "BVH_NODE_SIZE{}{}u;",
+                    match language {
+                        ShaderLanguage::Wgsl => ": u32 = ",
+                        ShaderLanguage::Glsl => " = ",
+                    },
+                    size
+                )));
+                for (name, offset) in offsets {
+                    if name.starts_with("_padding") {
+                        continue;
+                    }
+                    let constant = format!("BVH_NODE_{}_OFFSET", name.to_uppercase());
+                    let line = code.lines().find(|line| line.contains(&constant)).unwrap();
+                    assert!(line.ends_with(&format!(" {}u;", offset)), "{}", line);
+                }
+
+                // The traversal loop is complete.
+                assert_eq!(code.matches('{').count(), code.matches('}').count());
+                assert!(code.contains("fn bvh_traverse") || code.contains("uint bvh_traverse"));
+            }
+        }
+    }
+}
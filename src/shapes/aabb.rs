@@ -1,16 +1,27 @@
 //! Axis Aligned Bounding Boxes.
 
-use crate::bounding_hierarchy::IntersectionAABB;
+use crate::bounding_hierarchy::{IntersectionAABB, IntersectionShape};
+use crate::gjk::SupportMap;
+use crate::ray::{Intersection, IntersectionRay, Ray};
+use std::cell::RefCell;
 use std::fmt;
+use std::iter::FromIterator;
 use std::ops::Index;
+use std::sync::Arc;
 
-use crate::{Point3, Real, Vector3};
+use crate::{Mat4, Point3, Real, Vector3};
 
 use crate::axis::Axis;
 
 /// AABB struct.
+#[repr(C)]
 #[derive(Debug, Copy, Clone, PartialEq)]
 #[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize),
+    archive(as = "AABB")
+)]
 #[allow(clippy::upper_case_acronyms)]
 pub struct AABB {
     /// minimum coordinates
@@ -69,7 +80,114 @@ where
     }
 }
 
+impl<T: ?Sized> Bounded for Arc<T>
+where
+    T: Bounded,
+{
+    fn aabb(&self) -> AABB {
+        (**self).aabb()
+    }
+}
+
+impl<T: ?Sized> Bounded for &mut T
+where
+    T: Bounded,
+{
+    fn aabb(&self) -> AABB {
+        (**self).aabb()
+    }
+}
+
+impl<T: ?Sized> Bounded for Box<T>
+where
+    T: Bounded,
+{
+    fn aabb(&self) -> AABB {
+        (**self).aabb()
+    }
+}
+
+/// Borrows the value to compute its bounds, which panics while it is mutably borrowed.
+impl<T: ?Sized> Bounded for RefCell<T>
+where
+    T: Bounded,
+{
+    fn aabb(&self) -> AABB {
+        self.borrow().aabb()
+    }
+}
+
+/// The bounds of a slice are the joined bounds of its elements, and [`AABB::empty`] for an
+/// empty slice.
+///
+/// # Examples
+/// ```
+/// use bvh::aabb::{AABB, Bounded};
+/// use bvh::Point3;
+///
+/// let boxes = vec![
+///     AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0)),
+///     AABB::with_bounds(Point3::new(2.0, -1.0, 0.0), Point3::new(3.0, 0.0, 1.0)),
+/// ];
+/// let aabb = boxes[..].aabb();
+/// assert_eq!(aabb.min, Point3::new(0.0, -1.0, 0.0));
+/// assert_eq!(aabb.max, Point3::new(3.0, 1.0, 1.0));
+/// ```
+///
+/// [`AABB::empty`]: struct.AABB.html#method.empty
+///
+impl<T: Bounded> Bounded for [T] {
+    fn aabb(&self) -> AABB {
+        self.iter()
+            .fold(AABB::empty(), |aabb, shape| aabb.join_bounded(shape))
+    }
+}
+
+impl<T: Bounded, const N: usize> Bounded for [T; N] {
+    fn aabb(&self) -> AABB {
+        self[..].aabb()
+    }
+}
+
 impl AABB {
+    /// The indices of the corners in [`vertices`] which are joined by the edges of an
+    /// [`AABB`]. The first four edges run along the `x` axis, the next four along `y` and
+    /// the last four along `z`, each from its lower to its higher corner.
+    ///
+    /// [`AABB`]: struct.AABB.html
+    /// [`vertices`]: struct.AABB.html#method.vertices
+    ///
+    pub const EDGES: [[usize; 2]; 12] = [
+        [0, 1],
+        [2, 3],
+        [4, 5],
+        [6, 7],
+        [0, 2],
+        [1, 3],
+        [4, 6],
+        [5, 7],
+        [0, 4],
+        [1, 5],
+        [2, 6],
+        [3, 7],
+    ];
+
+    /// The indices of the corners in [`vertices`] which span the faces of an [`AABB`], in
+    /// the order `-x`, `+x`, `-y`, `+y`, `-z` and `+z`. The corners of each face wind
+    /// counter-clockwise when seen from outside of the [`AABB`].
+    ///
+    /// [`AABB`]: struct.AABB.html
+    /// [`vertices`]: struct.AABB.html#method.vertices
+    ///
+    pub const FACES: [[usize; 4]; 6] = [
+        [0, 4, 6, 2],
+        [1, 3, 7, 5],
+        [0, 1, 5, 4],
+        [2, 6, 7, 3],
+        [0, 2, 3, 1],
+        [4, 5, 7, 6],
+    ];
+
     /// Creates a new [`AABB`] with the given bounds.
     ///
     /// # Examples
@@ -120,6 +238,32 @@ impl AABB {
         }
     }
 
+    /// Creates the smallest [`AABB`] which contains all `points`. Without points, the
+    /// [`AABB`] is [`empty()`].
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let points = [
+    ///     Point3::new(1.0, -2.0, 0.0),
+    ///     Point3::new(-1.0, 3.0, 2.0),
+    ///     Point3::new(0.0, 0.0, -4.0),
+    /// ];
+    /// let aabb = AABB::from_points(&points);
+    /// assert_eq!(aabb.min, Point3::new(-1.0, -2.0, -4.0));
+    /// assert_eq!(aabb.max, Point3::new(1.0, 3.0, 2.0));
+    /// assert!(AABB::from_points(&[]).is_empty());
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    /// [`empty()`]: #method.empty
+    ///
+    pub fn from_points(points: &[Point3]) -> AABB {
+        points.iter().collect()
+    }
+
     /// Returns true if the [`Point3`] is inside the [`AABB`].
     ///
     /// # Examples
@@ -199,6 +343,29 @@ impl AABB {
             && self.approx_contains_eps(&other.max, epsilon)
     }
 
+    /// Returns true if the `other` [`AABB`] lies inside this [`AABB`], including its
+    /// boundary. An empty [`AABB`] is contained in every [`AABB`].
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0));
+    /// let inner = AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0));
+    /// let crossing = AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(2.0, 1.0, 1.0));
+    ///
+    /// assert!(aabb.contains_aabb(&inner));
+    /// assert!(!aabb.contains_aabb(&crossing));
+    /// assert!(aabb.contains_aabb(&AABB::empty()));
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub fn contains_aabb(&self, other: &AABB) -> bool {
+        other.is_empty() || (self.contains(&other.min) && self.contains(&other.max))
+    }
+
     /// Returns true if the `other` [`AABB`] is approximately equal to this [`AABB`]
     /// with respect to some `epsilon`.
     ///
@@ -436,6 +603,157 @@ impl AABB {
         self.join(&other.aabb())
     }
 
+    /// Returns the overlap of this [`AABB`] and `other`, or `None` if they do not overlap.
+    /// [`AABB`]s which only touch overlap in a flat [`AABB`].
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let aabb1 = AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(2.0, 2.0, 2.0));
+    /// let aabb2 = AABB::with_bounds(Point3::new(1.0, -1.0, 1.0), Point3::new(3.0, 1.0, 3.0));
+    /// let overlap = aabb1.intersection(&aabb2).unwrap();
+    /// assert_eq!(overlap.min, Point3::new(1.0, 0.0, 1.0));
+    /// assert_eq!(overlap.max, Point3::new(2.0, 1.0, 2.0));
+    ///
+    /// let apart = AABB::with_bounds(Point3::new(3.0, 0.0, 0.0), Point3::new(4.0, 1.0, 1.0));
+    /// assert!(aabb1.intersection(&apart).is_none());
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    #[must_use]
+    pub fn intersection(&self, other: &AABB) -> Option<AABB> {
+        let overlap = AABB::with_bounds(self.min.max(other.min), self.max.min(other.max));
+        if overlap.min.cmple(overlap.max).all() {
+            Some(overlap)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the union of this [`AABB`] and `other`, clipped to `bounds`, or `None` if
+    /// the union lies outside of `bounds`. This keeps the bounds of regions which grow
+    /// inside of a fixed domain, such as the cells of a spatial partition, from growing
+    /// past it.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let domain = AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(10.0, 10.0, 10.0));
+    /// let aabb1 = AABB::with_bounds(Point3::new(1.0, 1.0, 1.0), Point3::new(2.0, 2.0, 2.0));
+    /// let aabb2 = AABB::with_bounds(Point3::new(8.0, 8.0, 8.0), Point3::new(12.0, 9.0, 9.0));
+    ///
+    /// let clipped = aabb1.join_clipped(&aabb2, &domain).unwrap();
+    /// assert_eq!(clipped.min, Point3::new(1.0, 1.0, 1.0));
+    /// assert_eq!(clipped.max, Point3::new(10.0, 9.0, 9.0));
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    #[must_use]
+    pub fn join_clipped(&self, other: &AABB, bounds: &AABB) -> Option<AABB> {
+        self.join(other).intersection(bounds)
+    }
+
+    /// Returns this [`AABB`] enlarged by `margin` in every direction, e.g. to give moving
+    /// shapes some room before their bounds have to be updated. A negative `margin` shrinks
+    /// the [`AABB`], which becomes empty once it is shrunk past its center.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(2.0, 2.0, 2.0));
+    /// let inflated = aabb.inflate(0.5);
+    /// assert_eq!(inflated.min, Point3::new(-0.5, -0.5, -0.5));
+    /// assert_eq!(inflated.max, Point3::new(2.5, 2.5, 2.5));
+    /// assert!(aabb.inflate(-1.5).is_empty());
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    #[must_use]
+    pub fn inflate(&self, margin: Real) -> AABB {
+        self.expand_by(Vector3::splat(margin))
+    }
+
+    /// mutable version of [`AABB::inflate`].
+    ///
+    /// [`AABB::inflate`]: struct.AABB.html#method.inflate
+    ///
+    pub fn inflate_mut(&mut self, margin: Real) {
+        *self = self.inflate(margin);
+    }
+
+    /// Returns this [`AABB`] enlarged by the components of `amount` along each axis, on
+    /// both sides.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(2.0, 2.0, 2.0));
+    /// let expanded = aabb.expand_by(Vector3::new(1.0, 0.0, 0.5));
+    /// assert_eq!(expanded.min, Point3::new(-1.0, 0.0, -0.5));
+    /// assert_eq!(expanded.max, Point3::new(3.0, 2.0, 2.5));
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    #[must_use]
+    pub fn expand_by(&self, amount: Vector3) -> AABB {
+        AABB::with_bounds(self.min - amount, self.max + amount)
+    }
+
+    /// mutable version of [`AABB::expand_by`].
+    ///
+    /// [`AABB::expand_by`]: struct.AABB.html#method.expand_by
+    ///
+    pub fn expand_by_mut(&mut self, amount: Vector3) {
+        *self = self.expand_by(amount);
+    }
+
+    /// Returns this [`AABB`] scaled by `factor` around its center. An empty [`AABB`] stays
+    /// empty.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(2.0, 4.0, 6.0));
+    /// let scaled = aabb.scale_about_center(1.5);
+    /// assert_eq!(scaled.min, Point3::new(-0.5, -1.0, -1.5));
+    /// assert_eq!(scaled.max, Point3::new(2.5, 5.0, 7.5));
+    /// assert_eq!(scaled.center(), aabb.center());
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    #[must_use]
+    pub fn scale_about_center(&self, factor: Real) -> AABB {
+        if self.is_empty() {
+            return *self;
+        }
+        let center = self.center();
+        let half_size = self.size() * (factor / 2.0);
+        AABB::with_bounds(center - half_size, center + half_size)
+    }
+
+    /// mutable version of [`AABB::scale_about_center`].
+    ///
+    /// [`AABB::scale_about_center`]: struct.AABB.html#method.scale_about_center
+    ///
+    pub fn scale_about_center_mut(&mut self, factor: Real) {
+        *self = self.scale_about_center(factor);
+    }
+
     /// Returns the size of this [`AABB`] in all three dimensions.
     ///
     /// # Examples
@@ -500,6 +818,27 @@ impl AABB {
         self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
     }
 
+    /// Returns true if all bounds of this [`AABB`] are neither infinite nor `NaN`.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let aabb = AABB::with_bounds(Point3::splat(-1.0), Point3::splat(1.0));
+    /// assert!(aabb.is_finite());
+    ///
+    /// let nan = AABB::with_bounds(Point3::new(f32::NAN, 0.0, 0.0), Point3::splat(1.0));
+    /// assert!(!nan.is_finite());
+    /// assert!(!AABB::empty().is_finite());
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub fn is_finite(&self) -> bool {
+        self.min.is_finite() && self.max.is_finite()
+    }
+
     /// Returns the total surface area of this [`AABB`].
     ///
     /// # Examples
@@ -573,13 +912,227 @@ impl AABB {
         }
     }
 
-    /// Returns the closest point inside the `AABB` to a target point
+    /// Splits the [`AABB`] with the plane perpendicular to `axis` at `position` into the
+    /// part below and the part above the plane. The `position` is clamped to the [`AABB`],
+    /// so one of the parts is flat if the plane does not cross it.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::axis::Axis;
+    /// use bvh::Point3;
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(4.0, 2.0, 2.0));
+    /// let (below, above) = aabb.split(Axis::X, 1.0);
+    /// assert_eq!(below.max, Point3::new(1.0, 2.0, 2.0));
+    /// assert_eq!(above.min, Point3::new(1.0, 0.0, 0.0));
+    /// assert_eq!(below.join(&above), aabb);
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub fn split(&self, axis: Axis, position: Real) -> (AABB, AABB) {
+        let position = position.max(self.min[axis]).min(self.max[axis]);
+        let mut below = *self;
+        let mut above = *self;
+        below.max[axis] = position;
+        above.min[axis] = position;
+        (below, above)
+    }
+
+    /// Splits the [`AABB`] into two halves of equal size along `axis`, see [`split`].
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::axis::Axis;
+    /// use bvh::Point3;
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(4.0, 2.0, 2.0));
+    /// let (below, above) = aabb.split_at_center(Axis::Y);
+    /// assert_eq!(below.max.y, 1.0);
+    /// assert_eq!(above.min.y, 1.0);
+    /// assert_eq!(below.volume(), above.volume());
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    /// [`split`]: struct.AABB.html#method.split
+    ///
+    pub fn split_at_center(&self, axis: Axis) -> (AABB, AABB) {
+        self.split(axis, self.center()[axis])
+    }
+
+    /// Returns the eight corners of the [`AABB`]. Bit 0 of the index of a corner selects
+    /// its `x`, bit 1 its `y` and bit 2 its `z` coordinate from [`max`] instead of [`min`],
+    /// so the first corner is [`min`] and the last one is [`max`].
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 2.0, 3.0));
+    /// let vertices = aabb.vertices();
+    /// assert_eq!(vertices[0], aabb.min);
+    /// assert_eq!(vertices[1], Point3::new(1.0, 0.0, 0.0));
+    /// assert_eq!(vertices[6], Point3::new(0.0, 2.0, 3.0));
+    /// assert_eq!(vertices[7], aabb.max);
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    /// [`min`]: struct.AABB.html#structfield.min
+    /// [`max`]: struct.AABB.html#structfield.max
+    ///
+    pub fn vertices(&self) -> [Point3; 8] {
+        [0, 1, 2, 3, 4, 5, 6, 7]
+            .map(|i: usize| Point3::new(self[i & 1].x, self[(i >> 1) & 1].y, self[i >> 2].z))
+    }
+
+    /// Returns the twelve edges of the [`AABB`] as pairs of corners, in the order of
+    /// [`AABB::EDGES`].
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 2.0, 3.0));
+    /// let length: f32 = aabb.edges().iter().map(|(start, end)| start.distance(*end)).sum();
+    /// assert_eq!(length, 24.0);
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    /// [`AABB::EDGES`]: struct.AABB.html#associatedconstant.EDGES
+    ///
+    pub fn edges(&self) -> [(Point3, Point3); 12] {
+        let vertices = self.vertices();
+        AABB::EDGES.map(|[start, end]| (vertices[start], vertices[end]))
+    }
+
+    /// Returns the six faces of the [`AABB`] as quads of corners, in the order of
+    /// [`AABB::FACES`]. The corners of each face wind counter-clockwise when seen from
+    /// outside of the [`AABB`].
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 2.0, 3.0));
+    /// let [a, b, c, _] = aabb.faces()[3];
+    /// assert!(a.y == 2.0 && b.y == 2.0 && c.y == 2.0);
+    /// assert_eq!((b - a).cross(c - b).normalize(), Vector3::Y);
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    /// [`AABB::FACES`]: struct.AABB.html#associatedconstant.FACES
+    ///
+    pub fn faces(&self) -> [[Point3; 4]; 6] {
+        let vertices = self.vertices();
+        AABB::FACES.map(|face| face.map(|i| vertices[i]))
+    }
+
+    /// Returns the tight [`AABB`] of this [`AABB`] transformed by the affine `transform`,
+    /// which is the [`AABB`] of its transformed corners. An empty [`AABB`] stays empty.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::{Mat4, Point3, Vector3};
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 2.0, 3.0));
+    /// let transform = Mat4::from_scale_rotation_translation(
+    ///     Vector3::splat(2.0),
+    ///     Default::default(),
+    ///     Vector3::new(10.0, 0.0, 0.0),
+    /// );
+    ///
+    /// let transformed = aabb.transformed(&transform);
+    /// assert_eq!(transformed.min, Point3::new(10.0, 0.0, 0.0));
+    /// assert_eq!(transformed.max, Point3::new(12.0, 4.0, 6.0));
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub fn transformed(&self, transform: &Mat4) -> AABB {
+        if self.is_empty() {
+            return *self;
+        }
+        // Following Arvo, the half size of the result is the half size of this box
+        // transformed with the absolute values of the linear part of `transform`, which
+        // picks the farthest corner along each axis.
+        let center = transform.transform_point3(self.center());
+        let half_size = self.size() / 2.0;
+        let extent = transform.x_axis.truncate().abs() * half_size.x
+            + transform.y_axis.truncate().abs() * half_size.y
+            + transform.z_axis.truncate().abs() * half_size.z;
+        AABB::with_bounds(center - extent, center + extent)
+    }
+
+    /// Returns the point inside the [`AABB`] which is closest to `point`. Points inside the
+    /// [`AABB`] are returned unchanged.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0));
+    /// let closest = aabb.closest_point(&Point3::new(3.0, 0.5, -2.0));
+    /// assert_eq!(closest, Point3::new(1.0, 0.5, -1.0));
+    /// ```
     ///
     /// [`AABB`]: struct.AABB.html
     ///
-    pub fn closest_point(&self, point: Point3) -> Point3 {
+    pub fn closest_point(&self, point: &Point3) -> Point3 {
         point.clamp(self.min, self.max)
     }
+
+    /// Returns the squared distance from `point` to the [`AABB`], which is zero for points
+    /// inside of it and infinite for an empty [`AABB`].
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0));
+    /// assert_eq!(aabb.distance_squared(&Point3::new(3.0, 0.5, -2.0)), 5.0);
+    /// assert_eq!(aabb.distance_squared(&Point3::new(0.0, 0.5, -1.0)), 0.0);
+    /// assert_eq!(AABB::empty().distance_squared(&Point3::ZERO), f32::INFINITY);
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub fn distance_squared(&self, point: &Point3) -> Real {
+        (self.min - *point)
+            .max(*point - self.max)
+            .max(Vector3::ZERO)
+            .length_squared()
+    }
+
+    /// Returns the distance between the closest points of this [`AABB`] and `other`, which
+    /// is zero if they overlap and infinite if either of them is empty.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let aabb1 = AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0));
+    /// let aabb2 = AABB::with_bounds(Point3::new(4.0, 5.0, 0.5), Point3::new(6.0, 6.0, 2.0));
+    /// assert_eq!(aabb1.distance_to_aabb(&aabb2), 5.0);
+    /// assert_eq!(aabb1.distance_to_aabb(&aabb1), 0.0);
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub fn distance_to_aabb(&self, other: &AABB) -> Real {
+        (self.min - other.max)
+            .max(other.min - self.max)
+            .max(Vector3::ZERO)
+            .length()
+    }
 }
 
 impl IntersectionAABB for AABB {
@@ -593,6 +1146,221 @@ impl IntersectionAABB for AABB {
     }
 }
 
+/// Implementation of [`IntersectionRay`] for [`AABB`] using the slab method. The hit is
+/// the entry into the box with the normal of the entered face. If the ray starts inside
+/// the box, the exit is returned as a back face instead. The `u` and `v` coordinates of
+/// the [`Intersection`] are the position of the hit on the face, scaled to `[0, 1]` along
+/// the lower and the higher of the two other axes.
+///
+/// # Examples
+/// ```
+/// use bvh::aabb::AABB;
+/// use bvh::ray::{IntersectionRay, Ray};
+/// use bvh::{Point3, Vector3};
+///
+/// let aabb = AABB::with_bounds(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0));
+/// let ray = Ray::new(Point3::new(0.0, 0.5, -5.0), Vector3::new(0.0, 0.0, 1.0));
+///
+/// let hit = aabb.intersects_ray(&ray, 0.0, f32::INFINITY).unwrap();
+/// assert_eq!(hit.distance, 4.0);
+/// assert_eq!(hit.norm, Vector3::new(0.0, 0.0, -1.0));
+/// assert_eq!((hit.u, hit.v), (0.5, 0.75));
+/// ```
+///
+/// [`AABB`]: struct.AABB.html
+/// [`Intersection`]: ../ray/struct.Intersection.html
+/// [`IntersectionRay`]: ../ray/trait.IntersectionRay.html
+///
+impl<T: IntersectionAABB> IntersectionShape<T> for AABB {
+    /// Every shape which can be tested against an [`AABB`] can be tested against another
+    /// [`AABB`] as a shape.
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    fn intersects_shape(&self, other: &T) -> bool {
+        other.intersects_aabb(self)
+    }
+}
+
+impl SupportMap for AABB {
+    fn support(&self, direction: Vector3) -> Point3 {
+        Point3::select(direction.cmpge(Vector3::ZERO), self.max, self.min)
+    }
+}
+
+impl IntersectionRay for AABB {
+    fn intersects_ray(&self, ray: &Ray, t_min: Real, t_max: Real) -> Option<Intersection> {
+        // The distances at which the ray enters and leaves the box, and their axes.
+        let mut entry = (Real::NEG_INFINITY, 0);
+        let mut exit = (Real::INFINITY, 0);
+        for axis in 0..3 {
+            let inv_direction = 1.0 / ray.direction[axis];
+            let near = (self.min[axis] - ray.origin[axis]) * inv_direction;
+            let far = (self.max[axis] - ray.origin[axis]) * inv_direction;
+            let (near, far) = if inv_direction < 0.0 {
+                (far, near)
+            } else {
+                (near, far)
+            };
+            if near > entry.0 {
+                entry = (near, axis);
+            }
+            if far < exit.0 {
+                exit = (far, axis);
+            }
+        }
+        if entry.0 > exit.0 {
+            return None;
+        }
+
+        let (toi, axis, sign) = if entry.0 >= t_min {
+            (entry.0, entry.1, -1.0)
+        } else {
+            (exit.0, exit.1, 1.0)
+        };
+        if toi < t_min || toi > t_max {
+            return None;
+        }
+
+        let mut out_norm = Vector3::ZERO;
+        out_norm[axis] = sign * ray.direction[axis].signum();
+        let (norm, back_face) = ray.face_normal(out_norm);
+
+        let size = self.size();
+        let offset = ray.at(toi) - self.min;
+        let scaled = |other: usize| {
+            if size[other] > 0.0 {
+                offset[other] / size[other]
+            } else {
+                0.0
+            }
+        };
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        let (u, v) = (u.min(v), u.max(v));
+        Some(Intersection::new(
+            toi,
+            scaled(u),
+            scaled(v),
+            norm,
+            back_face,
+        ))
+    }
+}
+
+/// Four [`AABB`]s stored as structure of arrays, so that a [`Ray`] can be tested against
+/// all of them at once with SIMD instructions, see [`Ray::intersects_aabb4`]. Missing
+/// [`AABB`]s are filled in as [`empty()`], which no [`Ray`] hits.
+///
+/// # Examples
+/// ```
+/// use bvh::aabb::{AABB, AABB4};
+/// use bvh::Point3;
+///
+/// let aabb = AABB::with_bounds(Point3::new(0.0, 1.0, 2.0), Point3::new(3.0, 4.0, 5.0));
+/// let aabbs = AABB4::new(&[aabb, aabb]);
+/// assert_eq!(aabbs.min_y, [1.0, 1.0, f32::INFINITY, f32::INFINITY]);
+/// assert_eq!(aabbs.aabb(1), aabb);
+/// assert!(aabbs.aabb(2).is_empty());
+/// ```
+///
+/// [`AABB`]: struct.AABB.html
+/// [`empty()`]: struct.AABB.html#method.empty
+/// [`Ray`]: ../ray/struct.Ray.html
+/// [`Ray::intersects_aabb4`]: ../ray/struct.Ray.html#method.intersects_aabb4
+///
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AABB4 {
+    /// The minimum x coordinates of the [`AABB`]s.
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub min_x: [Real; 4],
+    /// The minimum y coordinates of the [`AABB`]s.
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub min_y: [Real; 4],
+    /// The minimum z coordinates of the [`AABB`]s.
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub min_z: [Real; 4],
+    /// The maximum x coordinates of the [`AABB`]s.
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub max_x: [Real; 4],
+    /// The maximum y coordinates of the [`AABB`]s.
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub max_y: [Real; 4],
+    /// The maximum z coordinates of the [`AABB`]s.
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub max_z: [Real; 4],
+}
+
+impl AABB4 {
+    /// Stores up to four [`AABB`]s, and fills the remaining ones in as [`empty()`].
+    ///
+    /// # Panics
+    /// Panics if more than four [`AABB`]s are given.
+    ///
+    /// [`AABB`]: struct.AABB.html
+    /// [`empty()`]: struct.AABB.html#method.empty
+    ///
+    pub fn new(aabbs: &[AABB]) -> AABB4 {
+        assert!(aabbs.len() <= 4, "AABB4 stores at most four AABBs");
+        let mut result = AABB4::default();
+        for (lane, aabb) in aabbs.iter().enumerate() {
+            result.set(lane, aabb);
+        }
+        result
+    }
+
+    /// Returns the [`AABB`] in `lane`.
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub fn aabb(&self, lane: usize) -> AABB {
+        AABB::with_bounds(
+            Point3::new(self.min_x[lane], self.min_y[lane], self.min_z[lane]),
+            Point3::new(self.max_x[lane], self.max_y[lane], self.max_z[lane]),
+        )
+    }
+
+    /// Stores `aabb` in `lane`.
+    pub fn set(&mut self, lane: usize, aabb: &AABB) {
+        self.min_x[lane] = aabb.min.x;
+        self.min_y[lane] = aabb.min.y;
+        self.min_z[lane] = aabb.min.z;
+        self.max_x[lane] = aabb.max.x;
+        self.max_y[lane] = aabb.max.y;
+        self.max_z[lane] = aabb.max.z;
+    }
+}
+
+/// Returns four [`empty()`] [`AABB`]s.
+///
+/// [`AABB`]: struct.AABB.html
+/// [`empty()`]: struct.AABB.html#method.empty
+///
+impl Default for AABB4 {
+    fn default() -> AABB4 {
+        let empty = AABB::empty();
+        AABB4 {
+            min_x: [empty.min.x; 4],
+            min_y: [empty.min.y; 4],
+            min_z: [empty.min.z; 4],
+            max_x: [empty.max.x; 4],
+            max_y: [empty.max.y; 4],
+            max_z: [empty.max.z; 4],
+        }
+    }
+}
+
 /// Default instance for [`AABB`]s. Returns an [`AABB`] which is [`empty()`].
 ///
 /// [`AABB`]: struct.AABB.html
@@ -604,6 +1372,63 @@ impl Default for AABB {
     }
 }
 
+/// Collects points into the smallest [`AABB`] which contains them.
+///
+/// # Examples
+/// ```
+/// use bvh::aabb::AABB;
+/// use bvh::Point3;
+///
+/// let aabb: AABB = (0..4).map(|i| Point3::new(i as f32, 0.0, -(i as f32))).collect();
+/// assert_eq!(aabb.min, Point3::new(0.0, 0.0, -3.0));
+/// assert_eq!(aabb.max, Point3::new(3.0, 0.0, 0.0));
+/// ```
+///
+/// [`AABB`]: struct.AABB.html
+///
+impl FromIterator<Point3> for AABB {
+    fn from_iter<I: IntoIterator<Item = Point3>>(iter: I) -> AABB {
+        let mut aabb = AABB::empty();
+        aabb.extend(iter);
+        aabb
+    }
+}
+
+impl<'a> FromIterator<&'a Point3> for AABB {
+    fn from_iter<I: IntoIterator<Item = &'a Point3>>(iter: I) -> AABB {
+        iter.into_iter().copied().collect()
+    }
+}
+
+/// Grows an [`AABB`] to contain more points.
+///
+/// # Examples
+/// ```
+/// use bvh::aabb::AABB;
+/// use bvh::Point3;
+///
+/// let mut aabb = AABB::from_points(&[Point3::new(1.0, 1.0, 1.0)]);
+/// aabb.extend(vec![Point3::new(2.0, 0.0, 1.0), Point3::new(1.0, 3.0, 1.0)]);
+/// assert_eq!(aabb.min, Point3::new(1.0, 0.0, 1.0));
+/// assert_eq!(aabb.max, Point3::new(2.0, 3.0, 1.0));
+/// ```
+///
+/// [`AABB`]: struct.AABB.html
+///
+impl Extend<Point3> for AABB {
+    fn extend<I: IntoIterator<Item = Point3>>(&mut self, iter: I) {
+        for point in iter {
+            self.grow_mut(&point);
+        }
+    }
+}
+
+impl<'a> Extend<&'a Point3> for AABB {
+    fn extend<I: IntoIterator<Item = &'a Point3>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied());
+    }
+}
+
 /// make [`AABB`]s indexable. `aabb[0]` gives a reference to the minimum bound.
 /// All other indices return a reference to the maximum bound.
 ///
@@ -685,13 +1510,337 @@ impl Bounded for Point3 {
 #[cfg(test)]
 mod tests {
     use crate::aabb::{Bounded, AABB};
+    use crate::axis::Axis;
     use crate::bounding_hierarchy::IntersectionAABB;
-    use crate::testbase::{tuple_to_point, tuple_to_vector, tuplevec_large_strategy, TupleVec};
-    use crate::{Point3, Vector3};
+    use crate::float::{gamma, relative_eq, ulps_eq};
+    use crate::ray::{IntersectionRay, Ray};
+    use crate::testbase::{
+        next_point3, tuple_to_point, tuple_to_vector, tuplevec_large_strategy, TupleVec,
+    };
+    use crate::{Mat4, Point3, Vector3};
     use crate::{Real, EPSILON};
-
-    use float_eq::assert_float_eq;
     use proptest::prelude::*;
+    use std::cell::RefCell;
+    use std::sync::Arc;
+
+    #[test]
+    /// Tests the entry and exit of rays along every axis and direction, and misses.
+    fn test_aabb_intersects_ray() {
+        let aabb = AABB::with_bounds(Point3::new(-1.0, -2.0, -3.0), Point3::new(1.0, 2.0, 3.0));
+        for axis in 0..3 {
+            for sign in [-1.0, 1.0] {
+                let mut direction = Vector3::ZERO;
+                direction[axis] = sign;
+                // A quarter of the size away from the maximum along the other axes.
+                let mut center = Point3::new(0.5, 1.0, 1.5);
+                center[axis] = 0.0;
+                let origin = center - direction * 10.0;
+                let ray = Ray::new(origin, direction);
+
+                let hit = aabb.intersects_ray(&ray, 0.0, Real::INFINITY).unwrap();
+                assert!(ulps_eq(hit.distance, 10.0 - aabb.max[axis], 4));
+                assert_eq!(hit.norm, -direction);
+                assert!(!hit.back_face);
+                assert!(ulps_eq(hit.u, 0.75, 4) && ulps_eq(hit.v, 0.75, 4));
+
+                let inside = aabb.intersects_ray(&ray, 10.0, Real::INFINITY).unwrap();
+                assert!(ulps_eq(inside.distance, 10.0 + aabb.max[axis], 4));
+                assert_eq!(inside.norm, -direction);
+                assert!(inside.back_face);
+
+                assert!(aabb.intersects_ray(&ray, 0.0, 5.0).is_none());
+                assert!(aabb.intersects_ray(&ray, 20.0, Real::INFINITY).is_none());
+            }
+        }
+
+        let beside = Ray::new(Point3::new(0.0, 3.0, -10.0), Vector3::new(0.0, 0.0, 1.0));
+        assert!(aabb.intersects_ray(&beside, 0.0, Real::INFINITY).is_none());
+        let diagonal = Ray::new(Point3::new(-3.0, -3.0, 0.0), Vector3::new(1.0, 1.0, 0.0));
+        let corner = aabb.intersects_ray(&diagonal, 0.0, Real::INFINITY).unwrap();
+        assert_eq!(corner.norm, Vector3::new(-1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    /// Tests that the intersection of random AABBs contains exactly the points which lie in
+    /// both of them, and that it is contained in both.
+    fn test_aabb_intersection() {
+        let bounds = AABB::with_bounds(Point3::splat(-5.0), Point3::splat(5.0));
+        let mut seed = 0;
+        let mut overlaps = 0;
+        for _ in 0..1000 {
+            let aabb1 = AABB::empty()
+                .grow(&next_point3(&mut seed, &bounds))
+                .grow(&next_point3(&mut seed, &bounds));
+            let aabb2 = AABB::empty()
+                .grow(&next_point3(&mut seed, &bounds))
+                .grow(&next_point3(&mut seed, &bounds));
+            let point = next_point3(&mut seed, &bounds);
+            let in_both = aabb1.contains(&point) && aabb2.contains(&point);
+            match aabb1.intersection(&aabb2) {
+                Some(overlap) => {
+                    overlaps += 1;
+                    assert!(aabb1.contains_aabb(&overlap) && aabb2.contains_aabb(&overlap));
+                    assert_eq!(overlap.contains(&point), in_both);
+                    assert!(aabb1.intersects_aabb(&aabb2));
+                }
+                None => {
+                    assert!(!in_both);
+                    assert!(!aabb1.intersects_aabb(&aabb2));
+                }
+            }
+            assert_eq!(
+                aabb1.intersection(&aabb2).is_some(),
+                aabb2.intersection(&aabb1).is_some()
+            );
+            assert!(aabb1.join(&aabb2).contains_aabb(&aabb1));
+            if let Some(clipped) = aabb1.join_clipped(&aabb2, &aabb1) {
+                assert!(clipped.relative_eq(&aabb1, EPSILON));
+            }
+        }
+        assert!(overlaps > 100 && overlaps < 900);
+    }
+
+    #[test]
+    /// Tests that the distances to random AABBs match the closest points, and that AABBs
+    /// are at distance zero exactly when they intersect.
+    fn test_aabb_distances() {
+        let bounds = AABB::with_bounds(Point3::splat(-5.0), Point3::splat(5.0));
+        let mut seed = 0;
+        for _ in 0..1000 {
+            let aabb1 = AABB::empty()
+                .grow(&next_point3(&mut seed, &bounds))
+                .grow(&next_point3(&mut seed, &bounds));
+            let aabb2 = AABB::empty()
+                .grow(&next_point3(&mut seed, &bounds))
+                .grow(&next_point3(&mut seed, &bounds));
+            let point = next_point3(&mut seed, &bounds);
+
+            let closest = aabb1.closest_point(&point);
+            assert!(aabb1.contains(&closest));
+            let distance_squared = aabb1.distance_squared(&point);
+            assert!(relative_eq(
+                distance_squared,
+                closest.distance_squared(point),
+                gamma(4)
+            ));
+            assert_eq!(distance_squared == 0.0, aabb1.contains(&point));
+
+            let distance = aabb1.distance_to_aabb(&aabb2);
+            assert_eq!(distance, aabb2.distance_to_aabb(&aabb1));
+            assert_eq!(distance == 0.0, aabb1.intersects_aabb(&aabb2));
+            let point_aabb = AABB::with_bounds(point, point);
+            let distance_to_point = aabb1.distance_to_aabb(&point_aabb);
+            assert!(relative_eq(
+                distance_to_point,
+                distance_squared.sqrt(),
+                gamma(4)
+            ));
+            // No point of the second AABB is closer to the first one.
+            let closest = aabb2.closest_point(&point);
+            assert!(aabb1.distance_squared(&closest).sqrt() >= distance - EPSILON);
+        }
+    }
+
+    #[test]
+    /// Tests that inflated, expanded and scaled AABBs contain the original ones and keep
+    /// their centers, and that the mutable versions agree.
+    fn test_aabb_inflate_expand_scale() {
+        let bounds = AABB::with_bounds(Point3::splat(-5.0), Point3::splat(5.0));
+        let mut seed = 0;
+        for _ in 0..100 {
+            let aabb = AABB::empty()
+                .grow(&next_point3(&mut seed, &bounds))
+                .grow(&next_point3(&mut seed, &bounds));
+            let amount = next_point3(&mut seed, &bounds).abs();
+
+            let inflated = aabb.inflate(amount.x);
+            assert!(inflated.contains_aabb(&aabb));
+            assert!(inflated
+                .size()
+                .abs_diff_eq(aabb.size() + 2.0 * amount.x, EPSILON));
+            assert!(inflated.inflate(-amount.x).relative_eq(&aabb, EPSILON));
+
+            let expanded = aabb.expand_by(amount);
+            assert!(expanded.contains_aabb(&aabb));
+            assert!(expanded.center().abs_diff_eq(aabb.center(), EPSILON));
+            assert!(expanded
+                .size()
+                .abs_diff_eq(aabb.size() + 2.0 * amount, EPSILON));
+
+            let scaled = aabb.scale_about_center(1.0 + amount.y);
+            assert!(scaled.approx_contains_aabb_eps(&aabb, EPSILON));
+            assert!(scaled.center().abs_diff_eq(aabb.center(), EPSILON));
+            let shrunk = aabb.scale_about_center(0.5);
+            assert!(aabb.approx_contains_aabb_eps(&shrunk, EPSILON));
+            assert!((shrunk.volume() - aabb.volume() / 8.0).abs() < EPSILON);
+
+            let mut aabb_mut = aabb;
+            aabb_mut.inflate_mut(amount.x);
+            assert_eq!(aabb_mut, inflated);
+            let mut aabb_mut = aabb;
+            aabb_mut.expand_by_mut(amount);
+            assert_eq!(aabb_mut, expanded);
+            let mut aabb_mut = aabb;
+            aabb_mut.scale_about_center_mut(0.5);
+            assert_eq!(aabb_mut, shrunk);
+        }
+        assert!(AABB::empty().inflate(1.0).is_empty());
+        assert!(AABB::empty().scale_about_center(2.0).is_empty());
+    }
+
+    #[test]
+    /// Tests that collecting, extending and `from_points` give the same AABB as growing an
+    /// empty AABB by every point.
+    fn test_aabb_from_points() {
+        let bounds = AABB::with_bounds(Point3::splat(-5.0), Point3::splat(5.0));
+        let mut seed = 0;
+        let points: Vec<Point3> = (0..100).map(|_| next_point3(&mut seed, &bounds)).collect();
+        let expected = points
+            .iter()
+            .fold(AABB::empty(), |aabb, point| aabb.grow(point));
+
+        assert_eq!(AABB::from_points(&points), expected);
+        assert_eq!(points.iter().collect::<AABB>(), expected);
+        assert_eq!(points.iter().copied().collect::<AABB>(), expected);
+
+        let (first, second) = points.split_at(40);
+        let mut aabb = AABB::from_points(first);
+        aabb.extend(second);
+        assert_eq!(aabb, expected);
+        assert!(points.iter().all(|point| aabb.contains(point)));
+        assert_eq!(AABB::from_points(&[]), AABB::empty());
+    }
+
+    #[test]
+    /// Tests that split AABBs are covered by their parts, which meet at the clamped split
+    /// position and split the volume.
+    fn test_aabb_split() {
+        let bounds = AABB::with_bounds(Point3::splat(-5.0), Point3::splat(5.0));
+        let mut seed = 0;
+        for _ in 0..100 {
+            let aabb = AABB::empty()
+                .grow(&next_point3(&mut seed, &bounds))
+                .grow(&next_point3(&mut seed, &bounds));
+            let position = next_point3(&mut seed, &bounds);
+            for &axis in [Axis::X, Axis::Y, Axis::Z].iter() {
+                let (below, above) = aabb.split(axis, position[axis]);
+                assert_eq!(below.join(&above), aabb);
+                assert!(aabb.contains_aabb(&below) && aabb.contains_aabb(&above));
+                assert_eq!(below.max[axis], above.min[axis]);
+                let clamped = position[axis].max(aabb.min[axis]).min(aabb.max[axis]);
+                assert_eq!(below.max[axis], clamped);
+                assert!(relative_eq(
+                    below.volume() + above.volume(),
+                    aabb.volume(),
+                    gamma(8)
+                ));
+
+                // The rounding of the center scales with the coordinates, not with the size.
+                let (below, above) = aabb.split_at_center(axis);
+                let scale = aabb.min[axis].abs().max(aabb.max[axis].abs());
+                let difference = below.size()[axis] - above.size()[axis];
+                assert!(difference.abs() <= gamma(4) * scale);
+            }
+        }
+        let (below, above) = AABB::empty().split(Axis::X, 0.0);
+        assert!(below.is_empty() && above.is_empty());
+    }
+
+    #[test]
+    /// Tests that the corners, edges and faces of random AABBs lie on their boundary, that
+    /// edges are parallel to the axes and that faces wind outwards.
+    fn test_aabb_vertices_edges_faces() {
+        let bounds = AABB::with_bounds(Point3::splat(-5.0), Point3::splat(5.0));
+        let mut seed = 0;
+        for _ in 0..100 {
+            let aabb = AABB::empty()
+                .grow(&next_point3(&mut seed, &bounds))
+                .grow(&next_point3(&mut seed, &bounds));
+            let vertices = aabb.vertices();
+            assert_eq!(AABB::from_points(&vertices), aabb);
+
+            for (i, (start, end)) in aabb.edges().iter().enumerate() {
+                let axis = i / 4;
+                let offset = *end - *start;
+                assert_eq!(offset[axis], aabb.size()[axis]);
+                assert_eq!(offset.length_squared(), offset[axis] * offset[axis]);
+            }
+
+            for (i, face) in aabb.faces().iter().enumerate() {
+                let axis = i / 2;
+                let bound = aabb[i % 2][axis];
+                assert!(face.iter().all(|corner| corner[axis] == bound));
+                let center = face.iter().fold(Vector3::ZERO, |sum, &c| sum + c) / 4.0;
+                let normal = (face[1] - face[0]).cross(face[2] - face[1]);
+                assert!(normal.dot(center - aabb.center()) > 0.0);
+                assert!(
+                    (normal.length() - (face[1] - face[0]).length() * (face[2] - face[1]).length())
+                        .abs()
+                        < 1e-3
+                );
+            }
+        }
+    }
+
+    #[test]
+    /// Tests that references, smart pointers, cells, slices and arrays are bounded by the
+    /// bounds of the values they hold.
+    fn test_bounded_wrappers() {
+        let aabb1 = AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0));
+        let aabb2 = AABB::with_bounds(Point3::new(2.0, -1.0, 0.0), Point3::new(3.0, 0.0, 1.0));
+
+        // Takes the shape by value, so the implementation for the wrapper itself is used.
+        fn aabb_of<T: Bounded>(shape: T) -> AABB {
+            shape.aabb()
+        }
+
+        let mut value = aabb1;
+        let reference: &mut AABB = &mut value;
+        assert_eq!(aabb_of(reference), aabb1);
+        let boxed: Box<dyn Bounded> = Box::new(aabb1);
+        assert_eq!(aabb_of(boxed), aabb1);
+        assert_eq!(aabb_of(Arc::new(aabb1)), aabb1);
+        let cell = RefCell::new(aabb1);
+        assert_eq!(aabb_of(&cell), aabb1);
+        *cell.borrow_mut() = aabb2;
+        assert_eq!(aabb_of(&cell), aabb2);
+
+        let joined = aabb1.join(&aabb2);
+        assert_eq!(aabb_of([aabb1, aabb2]), joined);
+        assert_eq!(aabb_of(&vec![aabb1, aabb2][..]), joined);
+        let shapes: Vec<Box<dyn Bounded>> = vec![Box::new(aabb1), Box::new(aabb2.center())];
+        assert_eq!(aabb_of(&shapes[..]), aabb1.grow(&aabb2.center()));
+        let none: [AABB; 0] = [];
+        assert!(aabb_of(none).is_empty());
+    }
+
+    #[test]
+    /// Tests that transformed AABBs are the bounds of the transformed corners, for random
+    /// affine transforms with rotation, scale, shear and mirroring.
+    fn test_aabb_transformed() {
+        let bounds = AABB::with_bounds(Point3::splat(-5.0), Point3::splat(5.0));
+        let mut seed = 0;
+        for _ in 0..100 {
+            let aabb = AABB::empty()
+                .grow(&next_point3(&mut seed, &bounds))
+                .grow(&next_point3(&mut seed, &bounds));
+            let transform = Mat4::from_cols(
+                next_point3(&mut seed, &bounds).extend(0.0),
+                next_point3(&mut seed, &bounds).extend(0.0),
+                next_point3(&mut seed, &bounds).extend(0.0),
+                next_point3(&mut seed, &bounds).extend(1.0),
+            );
+            let corners: AABB = aabb
+                .vertices()
+                .iter()
+                .map(|&corner| transform.transform_point3(corner))
+                .collect();
+            let transformed = aabb.transformed(&transform);
+            assert!(transformed.min.abs_diff_eq(corners.min, 1e-3));
+            assert!(transformed.max.abs_diff_eq(corners.max, 1e-3));
+        }
+        assert!(AABB::empty().transformed(&Mat4::IDENTITY).is_empty());
+    }
 
     #[cfg(not(miri))]
     proptest! {
@@ -858,7 +2007,7 @@ mod tests {
                 // Check its surface area
                 let area_a = aabb.surface_area();
                 let area_b = 6.0 * size * size;
-                assert_float_eq!(area_a, area_b, rmax <= EPSILON);
+                assert!(relative_eq(area_a, area_b, EPSILON));
             }
         }
 
@@ -882,7 +2031,7 @@ mod tests {
             // Check its volume
             let volume_a = aabb.volume();
             let volume_b = (size.x * size.y * size.z).abs();
-            assert_float_eq!(volume_a, volume_b, rmax <= EPSILON);
+            assert!(relative_eq(volume_a, volume_b, EPSILON));
         }
 
         // Test whether generating an `AABB` from the min and max bounds yields the same `AABB`.
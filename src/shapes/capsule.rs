@@ -1,7 +1,13 @@
 //! This module defines Capsules and their intersection algorithms
 use crate::{
-    aabb::AABB, bounding_hierarchy::IntersectionAABB, utils::nearest_point_on_line, Point3, Real,
-    Vector3,
+    aabb::AABB,
+    bounding_hierarchy::{IntersectionAABB, IntersectionShape},
+    gjk::{intersects_ray, SupportMap},
+    obb::OBB,
+    ray::{Intersection, IntersectionRay, Ray},
+    sphere::Sphere,
+    utils::{nearest_point_on_line, nearest_points_on_lines},
+    Point3, Real, Vector3,
 };
 
 /// Representation of a capsule
@@ -32,49 +38,119 @@ impl Capsule {
             len,
         }
     }
+
+    /// Returns the end point of the center line.
+    pub fn end(&self) -> Point3 {
+        if self.len > 0.0 {
+            self.start + self.dir * self.len
+        } else {
+            self.start
+        }
+    }
 }
 
 impl IntersectionAABB for Capsule {
     fn intersects_aabb(&self, aabb: &AABB) -> bool {
-        /*
-        // Use Distance from closest point
-        let mut point: Vector3 = self.start;
-        let mut curr_d = 0.0;
-        let max_sq = (self.len + self.radius) * (self.len + self.radius) ;
-        let r_sq = self.radius * self.radius;
-
-        loop {
-            let x = aabb.closest_point(point);
-            let d_sq = x.distance_squared(point);
-            println!("{:?} closest={:?} d={:?}", point, x, d_sq.sqrt());
-            if d_sq <= r_sq
-            {
-                return true;
+        // The squared distance of the center line to the box is a piecewise quadratic
+        // function of the position along the line, which only changes where the line enters
+        // or leaves the slab of an axis. Its minimum is found on every piece.
+        let start = self.start.to_array();
+        let offset = (self.end() - self.start).to_array();
+        let (min, max) = (aabb.min.to_array(), aabb.max.to_array());
+        let mut breaks = vec![0.0, 1.0];
+        for ((&start, &offset), bounds) in start
+            .iter()
+            .zip(offset.iter())
+            .zip(min.iter().zip(max.iter()))
+        {
+            if offset != 0.0 {
+                for &bound in [bounds.0, bounds.1].iter() {
+                    let t = (bound - start) / offset;
+                    if t > 0.0 && t < 1.0 {
+                        breaks.push(t);
+                    }
+                }
             }
-            if d_sq > max_sq || curr_d >= self.len
-            {
-                return false;
-            }
-            curr_d = (curr_d + d_sq.sqrt()).min(self.len);
-            point = self.start + (curr_d * self.dir);
         }
-        */
-        let mut last = self.start;
-        loop {
-            let closest = &aabb.closest_point(last);
-            let center = nearest_point_on_line(&self.start, &self.dir, self.len, closest);
-            let sphere = crate::sphere::Sphere {
-                center,
-                radius: self.radius,
-            };
-            if sphere.intersects_aabb(aabb) {
-                return true;
+        breaks.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let radius_squared = self.radius * self.radius;
+        let line = self.end() - self.start;
+        breaks.windows(2).any(|piece| {
+            // The axes on which the middle of the piece lies outside of the box are clamped to
+            // the same bound on the whole piece.
+            let middle = self.start + line * ((piece[0] + piece[1]) / 2.0);
+            let clamped = aabb.closest_point(&middle).to_array();
+            let (mut numerator, mut denominator) = (0.0, 0.0);
+            for (i, &bound) in clamped.iter().enumerate() {
+                if bound != middle[i] {
+                    numerator += (bound - start[i]) * offset[i];
+                    denominator += offset[i] * offset[i];
+                }
             }
-            if last.distance_squared(center) < 0.0001 {
-                return false;
+            let t = if denominator > 0.0 {
+                (numerator / denominator).clamp(piece[0], piece[1])
             } else {
-                last = center;
-            }
-        }
+                piece[0]
+            };
+            let point = self.start + line * t;
+            aabb.distance_squared(&point) <= radius_squared
+        })
+    }
+}
+
+impl IntersectionShape<AABB> for Capsule {
+    fn intersects_shape(&self, other: &AABB) -> bool {
+        self.intersects_aabb(other)
+    }
+}
+
+impl IntersectionShape<Sphere> for Capsule {
+    fn intersects_shape(&self, other: &Sphere) -> bool {
+        let nearest = nearest_point_on_line(&self.start, &self.dir, self.len, &other.center);
+        let radius = self.radius + other.radius;
+        nearest.distance_squared(other.center) <= radius * radius
+    }
+}
+
+impl IntersectionShape for Capsule {
+    fn intersects_shape(&self, other: &Capsule) -> bool {
+        let (nearest, other_nearest) = nearest_points_on_lines(
+            &self.start,
+            &self.dir,
+            self.len,
+            &other.start,
+            &other.dir,
+            other.len,
+        );
+        let radius = self.radius + other.radius;
+        nearest.distance_squared(other_nearest) <= radius * radius
+    }
+}
+
+impl IntersectionShape<OBB> for Capsule {
+    fn intersects_shape(&self, other: &OBB) -> bool {
+        other.intersects_shape(self)
+    }
+}
+
+impl SupportMap for Capsule {
+    fn support(&self, direction: Vector3) -> Point3 {
+        let end = if direction.dot(self.dir) > 0.0 {
+            self.start + self.dir * self.len
+        } else {
+            self.start
+        };
+        end + direction.normalize_or_zero() * self.radius
+    }
+}
+
+impl IntersectionRay for Capsule {
+    /// Intersects the ray with the capsule with the GJK ray cast, see [`intersects_ray`].
+    ///
+    /// [`intersects_ray`]: ../gjk/fn.intersects_ray.html
+    ///
+    fn intersects_ray(&self, ray: &Ray, t_min: Real, t_max: Real) -> Option<Intersection> {
+        intersects_ray(self, ray, t_min, t_max)
     }
 }
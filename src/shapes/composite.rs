@@ -0,0 +1,239 @@
+//! This module defines a Composite shape, which is the union of a few shapes
+
+use std::iter::FromIterator;
+
+use crate::{
+    aabb::{Bounded, AABB},
+    bounding_hierarchy::{BHShape, IntersectionAABB},
+    ray::{Intersection, IntersectionRay, Ray},
+    Real,
+};
+
+/// The union of a small number of shapes, which is put into a hierarchy as one shape.
+/// Objects made of a few simple parts, such as a character approximated by some spheres,
+/// need only one leaf instead of one per part, and are hit at their nearest part. Parts of
+/// different types can be combined with an enum which implements the traits for all of
+/// them.
+///
+/// # Examples
+/// ```
+/// use bvh::aabb::Bounded;
+/// use bvh::composite::Composite;
+/// use bvh::ray::{IntersectionRay, Ray};
+/// use bvh::sphere::Sphere;
+/// use bvh::{Point3, Vector3};
+///
+/// // A snowman.
+/// let snowman: Composite<Sphere> = vec![
+///     Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0),
+///     Sphere::new(Point3::new(0.0, 2.5, 0.0), 0.75),
+///     Sphere::new(Point3::new(0.0, 3.6, 0.0), 0.5),
+/// ]
+/// .into_iter()
+/// .collect();
+/// assert_eq!(snowman.aabb().min, Point3::new(-1.0, 0.0, -1.0));
+/// assert_eq!(snowman.aabb().max, Point3::new(1.0, 4.1, 1.0));
+///
+/// let ray = Ray::new(Point3::new(0.0, 10.0, 0.0), Vector3::new(0.0, -1.0, 0.0));
+/// let (part, hit) = snowman.intersects_ray_shape(&ray, 0.0, f32::INFINITY).unwrap();
+/// assert_eq!(part, 2);
+/// assert!((hit.distance - 5.9).abs() < 0.0001);
+/// ```
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct Composite<T> {
+    shapes: Vec<T>,
+    aabb: AABB,
+    node_index: usize,
+}
+
+impl<T: Bounded> Composite<T> {
+    /// Creates the union of `shapes`.
+    pub fn new(shapes: Vec<T>) -> Composite<T> {
+        let aabb = shapes
+            .iter()
+            .fold(AABB::empty(), |aabb, shape| aabb.join_bounded(shape));
+        Composite {
+            shapes,
+            aabb,
+            node_index: 0,
+        }
+    }
+
+    /// Adds `shape` to the union. The hierarchy containing the composite has to be updated
+    /// with its new bounds.
+    pub fn push(&mut self, shape: T) {
+        self.aabb.join_mut(&shape.aabb());
+        self.shapes.push(shape);
+    }
+}
+
+impl<T> Composite<T> {
+    /// Returns the shapes of the union.
+    pub fn shapes(&self) -> &[T] {
+        &self.shapes
+    }
+
+    /// Returns the number of shapes.
+    pub fn len(&self) -> usize {
+        self.shapes.len()
+    }
+
+    /// Returns true if the union has no shapes.
+    pub fn is_empty(&self) -> bool {
+        self.shapes.is_empty()
+    }
+
+    /// Returns the shapes of the union.
+    pub fn into_shapes(self) -> Vec<T> {
+        self.shapes
+    }
+}
+
+impl<T: IntersectionRay> Composite<T> {
+    /// Returns the index of the nearest shape hit by `ray` within `[t_min, t_max]`, and its
+    /// [`Intersection`].
+    ///
+    /// [`Intersection`]: ../ray/struct.Intersection.html
+    ///
+    pub fn intersects_ray_shape(
+        &self,
+        ray: &Ray,
+        t_min: Real,
+        t_max: Real,
+    ) -> Option<(usize, Intersection)> {
+        let mut nearest = None;
+        let mut t_max = t_max;
+        for (index, shape) in self.shapes.iter().enumerate() {
+            if let Some(hit) = shape.intersects_ray(ray, t_min, t_max) {
+                t_max = hit.distance;
+                nearest = Some((index, hit));
+            }
+        }
+        nearest
+    }
+}
+
+impl<T: Bounded> FromIterator<T> for Composite<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Composite<T> {
+        Composite::new(iter.into_iter().collect())
+    }
+}
+
+impl<T: IntersectionRay> IntersectionRay for Composite<T> {
+    /// Returns the nearest [`Intersection`] of the shapes.
+    ///
+    /// [`Intersection`]: ../ray/struct.Intersection.html
+    ///
+    fn intersects_ray(&self, ray: &Ray, t_min: Real, t_max: Real) -> Option<Intersection> {
+        self.intersects_ray_shape(ray, t_min, t_max)
+            .map(|(_, hit)| hit)
+    }
+}
+
+impl<T: IntersectionAABB> IntersectionAABB for Composite<T> {
+    fn intersects_aabb(&self, aabb: &AABB) -> bool {
+        self.shapes.iter().any(|shape| shape.intersects_aabb(aabb))
+    }
+}
+
+impl<T> Bounded for Composite<T> {
+    fn aabb(&self) -> AABB {
+        self.aabb
+    }
+}
+
+impl<T: Send + Sync> BHShape for Composite<T> {
+    fn set_bh_node_index(&mut self, index: usize) {
+        self.node_index = index;
+    }
+
+    fn bh_node_index(&self) -> usize {
+        self.node_index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::{Bounded, AABB};
+    use crate::bounding_hierarchy::IntersectionAABB;
+    use crate::bvh::BVH;
+    use crate::composite::Composite;
+    use crate::ray::IntersectionRay;
+    use crate::sphere::Sphere;
+    use crate::testbase::{create_ray, next_point3};
+    use crate::{Point3, Real, Vector3};
+
+    /// Creates `n` composites of three spheres each.
+    fn create_composites(n: usize, bounds: &AABB) -> Vec<Composite<Sphere>> {
+        let mut seed = 0;
+        (0..n)
+            .map(|_| {
+                let center = next_point3(&mut seed, bounds);
+                (0..3)
+                    .map(|i| {
+                        let offset = Vector3::new(0.0, i as Real * 1.5, 0.0);
+                        Sphere::new(center + offset, 1.0 - i as Real * 0.25)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    /// Tests that a hierarchy of composites is hit like all of their parts.
+    fn test_composite_matches_parts() {
+        let bounds = AABB::with_bounds(Point3::splat(-50.0), Point3::splat(50.0));
+        let mut composites = create_composites(100, &bounds);
+        let parts: Vec<Sphere> = composites
+            .iter()
+            .flat_map(|composite| composite.shapes().iter().copied())
+            .collect();
+        for composite in composites.iter() {
+            for part in composite.shapes() {
+                assert!(composite.aabb().contains(&part.aabb().min));
+                assert!(composite.aabb().contains(&part.aabb().max));
+            }
+        }
+        let bvh = BVH::build(&mut composites);
+
+        let mut seed = 0;
+        let mut hits = 0;
+        for _ in 0..1000 {
+            let ray = create_ray(&mut seed, &bounds);
+            let expected = parts
+                .iter()
+                .filter_map(|part| part.intersects_ray(&ray, 0.0, Real::INFINITY))
+                .map(|hit| hit.distance)
+                .min_by(|a, b| a.partial_cmp(b).unwrap());
+            let actual = bvh
+                .traverse_n_nearest(&ray, 1, 0.0, Real::INFINITY, &composites)
+                .first()
+                .map(|(_, hit)| hit.distance);
+            assert_eq!(actual, expected);
+            if actual.is_some() {
+                hits += 1;
+            }
+        }
+        assert!(hits > 0);
+    }
+
+    #[test]
+    /// Tests adding parts and intersecting AABBs.
+    fn test_composite_push() {
+        let mut composite = Composite::new(vec![Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0)]);
+        let far = AABB::with_bounds(Point3::splat(4.5), Point3::splat(6.0));
+        assert!(!composite.intersects_aabb(&far));
+
+        composite.push(Sphere::new(Point3::splat(5.0), 1.0));
+        assert_eq!(composite.len(), 2);
+        assert!(composite.intersects_aabb(&far));
+        assert_eq!(composite.aabb().max, Point3::splat(6.0));
+
+        // The gap between the spheres is inside the bounds, but not part of the union.
+        let between = AABB::with_bounds(Point3::splat(2.0), Point3::splat(3.0));
+        assert!(composite.aabb().contains(&between.min));
+        assert!(composite.aabb().contains(&between.max));
+        assert!(!composite.intersects_aabb(&between));
+    }
+}
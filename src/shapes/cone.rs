@@ -0,0 +1,244 @@
+//! This module defines a Cone and its intersection algorithms
+
+use crate::{
+    aabb::{Bounded, AABB},
+    ray::{Intersection, IntersectionRay, Ray},
+    Point3, Real, Vector3, EPSILON, PI,
+};
+
+/// A representation of a solid, finite Cone, which is closed by a disk at its base
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cone {
+    /// Tip of the cone
+    pub apex: Point3,
+    /// Unit direction from the apex towards the center of the base
+    pub axis: Vector3,
+    /// Distance from the apex to the base
+    pub height: Real,
+    /// Radius of the base
+    pub radius: Real,
+}
+
+impl Cone {
+    /// Creates a cone from its apex, the direction towards its base, its height and the
+    /// radius of its base. The axis is normalized.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::Bounded;
+    /// use bvh::cone::Cone;
+    /// use bvh::ray::{IntersectionRay, Ray};
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// // A spotlight at the origin which shines down the y axis.
+    /// let cone = Cone::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, -2.0, 0.0), 4.0, 1.0);
+    /// assert_eq!(cone.base_center(), Point3::new(0.0, -4.0, 0.0));
+    /// assert_eq!(cone.aabb().min, Point3::new(-1.0, -4.0, -1.0));
+    /// assert_eq!(cone.aabb().max, Point3::new(1.0, 0.0, 1.0));
+    ///
+    /// let ray = Ray::new(Point3::new(0.0, -10.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+    /// let hit = cone.intersects_ray(&ray, 0.0, f32::INFINITY).unwrap();
+    /// assert_eq!(hit.distance, 6.0);
+    /// ```
+    pub fn new(apex: Point3, axis: Vector3, height: Real, radius: Real) -> Cone {
+        Cone {
+            apex,
+            axis: axis.normalize(),
+            height,
+            radius,
+        }
+    }
+
+    /// Returns the center of the base disk.
+    pub fn base_center(&self) -> Point3 {
+        self.apex + self.axis * self.height
+    }
+
+    /// Returns the angle of `point` around the axis, scaled to `[0, 1]`.
+    fn angle(&self, point: Point3) -> Real {
+        let (tangent, bitangent) = self.axis.any_orthonormal_pair();
+        let offset = point - self.apex;
+        (offset.dot(bitangent).atan2(offset.dot(tangent)) + PI) / (2.0 * PI)
+    }
+}
+
+/// Replaces `nearest` by `hit`, a tuple of distance, outward normal and `v` coordinate, if
+/// it lies within `[t_min, t_max]` and is closer.
+fn keep_nearest(
+    nearest: &mut Option<(Real, Vector3, Real)>,
+    hit: (Real, Vector3, Real),
+    t_min: Real,
+    t_max: Real,
+) {
+    let distance = hit.0;
+    if distance >= t_min
+        && distance <= t_max
+        && nearest.is_none_or(|(nearest, _, _)| distance < nearest)
+    {
+        *nearest = Some(hit);
+    }
+}
+
+impl IntersectionRay for Cone {
+    /// Intersects the ray with the lateral surface and the base disk of the cone. The `u`
+    /// coordinate of the [`Intersection`] is the angle around the axis, scaled to `[0, 1]`.
+    /// The `v` coordinate is the distance along the axis divided by the height on the
+    /// lateral surface, and the distance from the axis divided by the radius on the base.
+    ///
+    /// [`Intersection`]: ../ray/struct.Intersection.html
+    ///
+    fn intersects_ray(&self, ray: &Ray, t_min: Real, t_max: Real) -> Option<Intersection> {
+        // Points on the lateral surface satisfy `(x . axis)^2 = cos^2 * |x|^2` relative to
+        // the apex, where `cos` is the cosine of the half opening angle.
+        let cos_squared =
+            self.height * self.height / (self.height * self.height + self.radius * self.radius);
+        let origin = ray.origin - self.apex;
+        let direction_axis = ray.direction.dot(self.axis);
+        let origin_axis = origin.dot(self.axis);
+
+        let a = direction_axis * direction_axis - cos_squared * ray.direction.length_squared();
+        let half_b = direction_axis * origin_axis - cos_squared * ray.direction.dot(origin);
+        let c = origin_axis * origin_axis - cos_squared * origin.length_squared();
+
+        let roots = if a.abs() > EPSILON {
+            let discriminant = half_b * half_b - a * c;
+            if discriminant < 0.0 {
+                [None, None]
+            } else {
+                let d_sqrt = discriminant.sqrt();
+                [Some((-half_b - d_sqrt) / a), Some((-half_b + d_sqrt) / a)]
+            }
+        } else if half_b.abs() > EPSILON {
+            // The ray is parallel to the surface and crosses it only once.
+            [Some(-c / (2.0 * half_b)), None]
+        } else {
+            [None, None]
+        };
+
+        let mut nearest = None;
+        for toi in roots.iter().flatten() {
+            let offset = ray.at(*toi) - self.apex;
+            let along = offset.dot(self.axis);
+            // Discard the mirrored cone behind the apex and hits beyond the base.
+            if along >= 0.0 && along <= self.height {
+                let out_norm = (offset * cos_squared - self.axis * along).normalize_or_zero();
+                let v = along / self.height;
+                keep_nearest(&mut nearest, (*toi, out_norm, v), t_min, t_max);
+            }
+        }
+
+        if direction_axis.abs() > EPSILON {
+            let center = self.base_center();
+            let toi = (center - ray.origin).dot(self.axis) / direction_axis;
+            let distance_squared = ray.at(toi).distance_squared(center);
+            if distance_squared <= self.radius * self.radius {
+                let v = distance_squared.sqrt() / self.radius;
+                keep_nearest(&mut nearest, (toi, self.axis, v), t_min, t_max);
+            }
+        }
+
+        nearest.map(|(toi, out_norm, v)| {
+            let u = self.angle(ray.at(toi));
+            let (norm, back_face) = ray.face_normal(out_norm);
+            Intersection::new(toi, u, v, norm, back_face)
+        })
+    }
+}
+
+impl Bounded for Cone {
+    /// The bounds of the apex joined with the exact bounds of the base disk.
+    fn aabb(&self) -> AABB {
+        // A disk with unit normal `n` extends by `radius * sqrt(1 - n_i^2)` along axis `i`.
+        let extent = |n: Real| self.radius * (1.0 - n * n).max(0.0).sqrt();
+        let extent = Vector3::new(
+            extent(self.axis.x),
+            extent(self.axis.y),
+            extent(self.axis.z),
+        );
+        let center = self.base_center();
+        AABB::with_bounds(center - extent, center + extent).grow(&self.apex)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::Bounded;
+    use crate::cone::Cone;
+    use crate::ray::{IntersectionRay, Ray};
+    use crate::{Point3, Real, Vector3, EPSILON};
+
+    #[test]
+    /// Tests hits on the lateral surface and the base, and misses beside the cone and on
+    /// the mirrored cone behind the apex.
+    fn test_cone_intersects_ray() {
+        let cone = Cone::new(
+            Point3::new(0.0, 4.0, 0.0),
+            Vector3::new(0.0, -1.0, 0.0),
+            4.0,
+            2.0,
+        );
+        let intersect = |origin: Point3, direction: Vector3| {
+            cone.intersects_ray(&Ray::new(origin, direction), 0.0, Real::INFINITY)
+        };
+
+        // Close to the apex the radius is 0.5 at a distance of 1 from it.
+        let top = intersect(Point3::new(0.5, 10.0, 0.0), Vector3::new(0.0, -1.0, 0.0)).unwrap();
+        assert!((top.distance - 7.0).abs() < EPSILON);
+        assert!(!top.back_face);
+
+        let base = intersect(Point3::new(0.5, -3.0, 0.0), Vector3::new(0.0, 1.0, 0.0)).unwrap();
+        assert!((base.distance - 3.0).abs() < EPSILON);
+        assert_eq!(base.norm, Vector3::new(0.0, -1.0, 0.0));
+        assert!(!base.back_face);
+        assert!((base.v - 0.25).abs() < EPSILON);
+
+        // At half the height the radius is 1.
+        let side = intersect(Point3::new(-5.0, 2.0, 0.0), Vector3::new(1.0, 0.0, 0.0)).unwrap();
+        assert!((side.distance - 4.0).abs() < EPSILON);
+        assert!((side.v - 0.5).abs() < EPSILON);
+        let expected_norm = Vector3::new(-2.0, 1.0, 0.0).normalize();
+        assert!((side.norm - expected_norm).length() < EPSILON);
+
+        // From the inside, the far side is hit as a back face.
+        let inside = intersect(Point3::new(0.0, 2.0, 0.0), Vector3::new(1.0, 0.0, 0.0)).unwrap();
+        assert!((inside.distance - 1.0).abs() < EPSILON);
+        assert!(inside.back_face);
+
+        assert!(intersect(Point3::new(-5.0, 2.0, 3.0), Vector3::new(1.0, 0.0, 0.0)).is_none());
+        assert!(intersect(Point3::new(-5.0, 6.0, 0.0), Vector3::new(1.0, 0.0, 0.0)).is_none());
+        assert!(intersect(Point3::new(-5.0, -1.0, 0.0), Vector3::new(1.0, 0.0, 0.0)).is_none());
+
+        // Respect the interval of the ray.
+        let ray = Ray::new(Point3::new(-5.0, 2.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        let far = cone.intersects_ray(&ray, 5.0, Real::INFINITY).unwrap();
+        assert!((far.distance - 6.0).abs() < EPSILON);
+        assert!(cone.intersects_ray(&ray, 0.0, 3.0).is_none());
+    }
+
+    #[test]
+    /// Tests that the bounds of a tilted cone contain sample points of its surface and
+    /// are tight along the axes.
+    fn test_cone_aabb() {
+        let cone = Cone::new(
+            Point3::new(1.0, 2.0, 3.0),
+            Vector3::new(1.0, -2.0, 0.5),
+            3.0,
+            1.5,
+        );
+        let aabb = cone.aabb();
+        assert!(aabb.contains(&cone.apex));
+
+        let (tangent, bitangent) = cone.axis.any_orthonormal_pair();
+        let mut reached = Vector3::splat(Real::NEG_INFINITY);
+        for i in 0..360 {
+            let angle = (i as Real).to_radians();
+            let rim = cone.base_center()
+                + (tangent * angle.cos() + bitangent * angle.sin()) * cone.radius;
+            assert!(aabb.approx_contains_eps(&rim, EPSILON));
+            reached = reached.max(rim);
+        }
+        let max = reached.max(cone.apex);
+        assert!((max - aabb.max).abs().max_element() < 0.01);
+    }
+}
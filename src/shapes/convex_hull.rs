@@ -0,0 +1,380 @@
+//! This module defines a ConvexHull and its intersection algorithms
+
+use crate::{
+    aabb::{Bounded, AABB},
+    bounding_hierarchy::IntersectionAABB,
+    gjk::SupportMap,
+    plane::{Plane, PlaneSide},
+    ray::{Intersection, IntersectionRay, Ray},
+    Point3, Real, Vector3, EPSILON,
+};
+
+/// A representation of the convex hull of a point set, stored as the planes of its faces
+/// and its vertices. The normals of the planes point outwards.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConvexHull {
+    /// The planes of the faces, coplanar triangles of the hull are merged into one
+    pub planes: Vec<Plane>,
+    /// The points of the set which are corners of the hull
+    pub vertices: Vec<Point3>,
+}
+
+impl ConvexHull {
+    /// Computes the convex hull of `points` with an incremental algorithm. Points which
+    /// lie inside the hull or on its faces are dropped. Returns `None` if the points do not
+    /// span a volume, i.e. if there are fewer than 4 of them or all of them are coplanar.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::Bounded;
+    /// use bvh::convex_hull::ConvexHull;
+    /// use bvh::ray::{IntersectionRay, Ray};
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// // A unit cube with a point in its center.
+    /// let mut points: Vec<Point3> = (0..8)
+    ///     .map(|i| Point3::new((i & 1) as f32, ((i >> 1) & 1) as f32, ((i >> 2) & 1) as f32))
+    ///     .collect();
+    /// points.push(Point3::splat(0.5));
+    /// let hull = ConvexHull::new(&points).unwrap();
+    /// assert_eq!(hull.planes.len(), 6);
+    /// assert_eq!(hull.vertices.len(), 8);
+    /// assert_eq!(hull.aabb().max, Point3::splat(1.0));
+    ///
+    /// let ray = Ray::new(Point3::new(0.5, 0.5, -2.0), Vector3::new(0.0, 0.0, 1.0));
+    /// let hit = hull.intersects_ray(&ray, 0.0, f32::INFINITY).unwrap();
+    /// assert_eq!(hit.distance, 2.0);
+    /// assert_eq!(hit.norm, Vector3::new(0.0, 0.0, -1.0));
+    /// ```
+    pub fn new(points: &[Point3]) -> Option<ConvexHull> {
+        // Rounding errors grow with the magnitude of the coordinates.
+        let tolerance = points.iter().fold(1.0, |scale: Real, point| {
+            scale.max(point.abs().max_element())
+        }) * EPSILON;
+        let faces = hull_faces(points, tolerance)?;
+
+        let mut planes: Vec<Plane> = Vec::new();
+        let mut used = vec![false; points.len()];
+        for face in faces.iter() {
+            let [a, b, c] = face.map(|index| points[index]);
+            let plane = Plane::from_point_normal(a, (b - a).cross(c - a));
+            let duplicate = planes.iter().any(|other| {
+                other.normal.dot(plane.normal) > 1.0 - EPSILON
+                    && (other.d - plane.d).abs() < tolerance
+            });
+            if !duplicate {
+                planes.push(plane);
+            }
+            for &index in face.iter() {
+                used[index] = true;
+            }
+        }
+
+        // Vertices of merged faces, which lie on the border between two triangles of the
+        // same plane, are not corners.
+        let vertices = points
+            .iter()
+            .zip(used)
+            .filter(|&(point, used)| {
+                used && planes
+                    .iter()
+                    .filter(|plane| plane.signed_distance(point).abs() < tolerance)
+                    .count()
+                    >= 3
+            })
+            .map(|(point, _)| *point)
+            .collect::<Vec<_>>();
+        let mut unique: Vec<Point3> = Vec::with_capacity(vertices.len());
+        for vertex in vertices {
+            if !unique
+                .iter()
+                .any(|other| other.distance(vertex) < tolerance)
+            {
+                unique.push(vertex);
+            }
+        }
+
+        Some(ConvexHull {
+            planes,
+            vertices: unique,
+        })
+    }
+
+    /// Returns true if `point` lies inside the hull or on its surface.
+    pub fn contains(&self, point: &Point3) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(point) <= EPSILON)
+    }
+}
+
+/// Computes the triangles of the convex hull of `points`, as indices into `points` in
+/// counter-clockwise order seen from the outside. Points within `tolerance` of a face are
+/// considered to lie on it.
+fn hull_faces(points: &[Point3], tolerance: Real) -> Option<Vec<[usize; 3]>> {
+    // Start with a tetrahedron of points far apart from each other.
+    let first =
+        (0..points.len()).min_by(|&a, &b| points[a].x.partial_cmp(&points[b].x).unwrap())?;
+    let farthest = |distance: &dyn Fn(&Point3) -> Real| {
+        (0..points.len())
+            .max_by(|&a, &b| {
+                distance(&points[a])
+                    .partial_cmp(&distance(&points[b]))
+                    .unwrap()
+            })
+            .filter(|&index| distance(&points[index]) > tolerance)
+    };
+    let second = farthest(&|point| point.distance(points[first]))?;
+    let line = (points[second] - points[first]).normalize();
+    let third = farthest(&|point| {
+        let offset = *point - points[first];
+        (offset - line * offset.dot(line)).length()
+    })?;
+    let normal = (points[second] - points[first])
+        .cross(points[third] - points[first])
+        .normalize();
+    let fourth = farthest(&|point| (*point - points[first]).dot(normal).abs())?;
+
+    let mut faces = vec![
+        [first, second, third],
+        [first, third, fourth],
+        [first, fourth, second],
+        [second, fourth, third],
+    ];
+    if (points[fourth] - points[first]).dot(normal) > 0.0 {
+        // Orient the faces so that the fourth point lies behind the first one.
+        for face in faces.iter_mut() {
+            face.swap(1, 2);
+        }
+    }
+
+    let outside = |face: &[usize; 3], point: &Point3| {
+        let [a, b, c] = face.map(|index| points[index]);
+        let normal = (b - a).cross(c - a).normalize();
+        (*point - a).dot(normal) > tolerance
+    };
+    for (index, point) in points.iter().enumerate() {
+        let (visible, hidden): (Vec<[usize; 3]>, Vec<[usize; 3]>) =
+            faces.iter().partition(|face| outside(face, point));
+        if visible.is_empty() {
+            continue;
+        }
+
+        // The horizon consists of the edges of visible faces whose neighbors are hidden.
+        // Connecting them to the point keeps the orientation of the faces.
+        let edges =
+            |face: &[usize; 3]| [(face[0], face[1]), (face[1], face[2]), (face[2], face[0])];
+        let visible_edges: Vec<(usize, usize)> = visible.iter().flat_map(edges).collect();
+        faces = hidden;
+        for &(a, b) in visible_edges.iter() {
+            if !visible_edges.contains(&(b, a)) {
+                faces.push([a, b, index]);
+            }
+        }
+    }
+    Some(faces)
+}
+
+impl IntersectionRay for ConvexHull {
+    /// Intersects the ray with the hull by clipping it against all planes. If the ray
+    /// starts inside the hull, the exit is returned as a back face. The `u` and `v`
+    /// coordinates of the [`Intersection`] are always zero.
+    ///
+    /// [`Intersection`]: ../ray/struct.Intersection.html
+    ///
+    fn intersects_ray(&self, ray: &Ray, t_min: Real, t_max: Real) -> Option<Intersection> {
+        let mut entry = (t_min, None);
+        let mut exit = (t_max, None);
+        for plane in self.planes.iter() {
+            let denominator = plane.normal.dot(ray.direction);
+            let distance = plane.signed_distance(&ray.origin);
+            if denominator.abs() < EPSILON {
+                // Parallel to the plane, so entirely in front of or behind it.
+                if distance > 0.0 {
+                    return None;
+                }
+                continue;
+            }
+            let toi = -distance / denominator;
+            if denominator < 0.0 {
+                if toi > entry.0 {
+                    entry = (toi, Some(plane.normal));
+                }
+            } else if toi < exit.0 {
+                exit = (toi, Some(plane.normal));
+            }
+            if entry.0 > exit.0 {
+                return None;
+            }
+        }
+
+        let (toi, out_norm) = match (entry, exit) {
+            ((toi, Some(normal)), _) => (toi, normal),
+            (_, (toi, Some(normal))) => (toi, normal),
+            _ => return None,
+        };
+        let (norm, back_face) = ray.face_normal(out_norm);
+        Some(Intersection::new(toi, 0.0, 0.0, norm, back_face))
+    }
+}
+
+impl IntersectionAABB for ConvexHull {
+    /// Tests the [`AABB`] against the faces of the hull and of the box. This is exact
+    /// unless the only separating axis is the cross product of two edges, in which case
+    /// an intersection is reported conservatively.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    fn intersects_aabb(&self, aabb: &AABB) -> bool {
+        self.aabb().intersects_aabb(aabb)
+            && self
+                .planes
+                .iter()
+                .all(|plane| plane.classify_aabb(aabb) != PlaneSide::Front)
+    }
+}
+
+impl Bounded for ConvexHull {
+    fn aabb(&self) -> AABB {
+        AABB::from_points(&self.vertices)
+    }
+}
+
+impl SupportMap for ConvexHull {
+    fn support(&self, direction: Vector3) -> Point3 {
+        self.vertices
+            .iter()
+            .copied()
+            .max_by(|a, b| a.dot(direction).partial_cmp(&b.dot(direction)).unwrap())
+            .unwrap_or(Point3::ZERO)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::{Bounded, AABB};
+    use crate::bounding_hierarchy::IntersectionAABB;
+    use crate::convex_hull::ConvexHull;
+    use crate::ray::{IntersectionRay, Ray};
+    use crate::testbase::{default_bounds, next_point3};
+    use crate::{Point3, Real, Vector3, EPSILON};
+
+    /// Creates the corners of an octahedron with the given radius around the origin.
+    fn octahedron(radius: Real) -> Vec<Point3> {
+        vec![
+            Point3::new(radius, 0.0, 0.0),
+            Point3::new(-radius, 0.0, 0.0),
+            Point3::new(0.0, radius, 0.0),
+            Point3::new(0.0, -radius, 0.0),
+            Point3::new(0.0, 0.0, radius),
+            Point3::new(0.0, 0.0, -radius),
+        ]
+    }
+
+    #[test]
+    /// Tests that the hull of random points contains all of them, and that each plane
+    /// touches at least three vertices.
+    fn test_convex_hull_contains_points() {
+        let bounds = default_bounds();
+        let mut seed = 0;
+        let points: Vec<Point3> = (0..200).map(|_| next_point3(&mut seed, &bounds)).collect();
+        let hull = ConvexHull::new(&points).unwrap();
+        let tolerance = bounds.size().max_element() * EPSILON;
+
+        assert!(hull.vertices.len() >= 4 && hull.vertices.len() < points.len());
+        for point in points.iter() {
+            assert!(hull
+                .planes
+                .iter()
+                .all(|plane| plane.signed_distance(point) <= tolerance));
+        }
+        for plane in hull.planes.iter() {
+            let touching = hull
+                .vertices
+                .iter()
+                .filter(|vertex| plane.signed_distance(vertex).abs() <= tolerance)
+                .count();
+            assert!(touching >= 3);
+        }
+        assert_eq!(
+            hull.aabb(),
+            points
+                .iter()
+                .fold(AABB::empty(), |aabb, point| aabb.grow(point))
+        );
+    }
+
+    #[test]
+    /// Tests that point sets without a volume have no hull.
+    fn test_convex_hull_degenerate() {
+        assert!(ConvexHull::new(&[]).is_none());
+        assert!(ConvexHull::new(&octahedron(1.0)[..3]).is_none());
+        let square = [
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+            Point3::new(1.0, 1.0, 0.0),
+        ];
+        assert!(ConvexHull::new(&square).is_none());
+    }
+
+    #[test]
+    /// Tests hits from the outside and the inside, misses and the ray interval.
+    fn test_convex_hull_intersects_ray() {
+        let hull = ConvexHull::new(&octahedron(2.0)).unwrap();
+        assert_eq!(hull.planes.len(), 8);
+        assert_eq!(hull.vertices.len(), 6);
+        let intersect = |origin: Point3, direction: Vector3, t_min: Real| {
+            hull.intersects_ray(&Ray::new(origin, direction), t_min, Real::INFINITY)
+        };
+
+        let hit = intersect(
+            Point3::new(-5.0, 0.0, 0.0),
+            Vector3::new(1.0, 0.0, 0.0),
+            0.0,
+        )
+        .unwrap();
+        assert!((hit.distance - 3.0).abs() < EPSILON);
+        assert!(!hit.back_face);
+
+        let inside =
+            intersect(Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0), 0.0).unwrap();
+        assert!((inside.distance - 2.0).abs() < EPSILON);
+        assert!(inside.back_face);
+
+        let far = intersect(
+            Point3::new(-5.0, 0.0, 0.0),
+            Vector3::new(1.0, 0.0, 0.0),
+            4.0,
+        )
+        .unwrap();
+        assert!((far.distance - 7.0).abs() < EPSILON);
+        assert!(far.back_face);
+
+        assert!(intersect(
+            Point3::new(-5.0, 1.5, 1.5),
+            Vector3::new(1.0, 0.0, 0.0),
+            0.0
+        )
+        .is_none());
+        assert!(intersect(Point3::new(5.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0), 0.0).is_none());
+        assert!(hull.contains(&Point3::new(0.5, 0.5, 0.5)));
+        assert!(!hull.contains(&Point3::new(1.0, 1.0, 1.0)));
+    }
+
+    #[test]
+    /// Tests boxes inside, overlapping, beside the faces and beside the bounds of a hull.
+    fn test_convex_hull_intersects_aabb() {
+        let hull = ConvexHull::new(&octahedron(2.0)).unwrap();
+        let cube = |center: Point3, half: Real| {
+            AABB::with_bounds(center - Vector3::splat(half), center + Vector3::splat(half))
+        };
+        assert!(hull.intersects_aabb(&cube(Point3::splat(0.0), 0.5)));
+        assert!(hull.intersects_aabb(&cube(Point3::new(2.0, 0.0, 0.0), 0.5)));
+        assert!(hull.intersects_aabb(&cube(Point3::splat(0.0), 10.0)));
+        // Inside the bounds of the hull, but beyond the face facing (1, 1, 1).
+        assert!(!hull.intersects_aabb(&cube(Point3::splat(1.5), 0.2)));
+        assert!(!hull.intersects_aabb(&cube(Point3::new(3.0, 0.0, 0.0), 0.5)));
+    }
+}
@@ -0,0 +1,292 @@
+//! This module defines a Frustum and its classification of AABBs, e.g. for view frustum culling
+
+use crate::{
+    aabb::AABB,
+    bounding_hierarchy::IntersectionAABB,
+    plane::{Plane, PlaneSide},
+    Mat4, Point3, Real, Vector3,
+};
+
+/// Describes where a piece of geometry lies relative to a [`Frustum`].
+///
+/// [`Frustum`]: struct.Frustum.html
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub enum FrustumSide {
+    /// Entirely inside of the frustum.
+    Inside,
+    /// Entirely behind at least one of the planes of the frustum.
+    Outside,
+    /// Crosses or touches some of the planes of the frustum.
+    Intersecting,
+}
+
+/// A convex volume bounded by six planes, such as the volume seen by a camera. The normals
+/// of the planes point into the frustum.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct Frustum {
+    /// The left, right, bottom, top, near and far planes
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Creates a frustum from its left, right, bottom, top, near and far planes, whose
+    /// normals point into the frustum.
+    pub fn new(planes: [Plane; 6]) -> Frustum {
+        Frustum { planes }
+    }
+
+    /// Extracts the frustum from a view projection matrix which maps the visible volume to
+    /// clip space with a depth range of `[0, 1]`, like the projections of `glam` without a
+    /// `_gl` suffix and the ones used with Direct3D, Metal, Vulkan and `wgpu`.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bounding_hierarchy::IntersectionAABB;
+    /// use bvh::frustum::{Frustum, FrustumSide};
+    /// use bvh::{Mat4, Point3, Vector3};
+    ///
+    /// // A camera at the origin looking down the negative z axis.
+    /// let projection = Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0);
+    /// let frustum = Frustum::from_matrix(&projection);
+    ///
+    /// let ahead = AABB::with_bounds(Point3::new(-1.0, -1.0, -11.0), Point3::new(1.0, 1.0, -9.0));
+    /// assert_eq!(frustum.classify_aabb(&ahead), FrustumSide::Inside);
+    /// let behind = AABB::with_bounds(Point3::new(-1.0, -1.0, 9.0), Point3::new(1.0, 1.0, 11.0));
+    /// assert_eq!(frustum.classify_aabb(&behind), FrustumSide::Outside);
+    /// let beside = AABB::with_bounds(Point3::new(9.0, -1.0, -11.0), Point3::new(11.0, 1.0, -9.0));
+    /// assert_eq!(frustum.classify_aabb(&beside), FrustumSide::Intersecting);
+    /// assert!(frustum.intersects_aabb(&beside));
+    /// ```
+    pub fn from_matrix(view_projection: &Mat4) -> Frustum {
+        Frustum::from_rows(view_projection, false)
+    }
+
+    /// Extracts the frustum from a view projection matrix which maps the visible volume to
+    /// clip space with a depth range of `[-1, 1]`, like the `_gl` projections of `glam` and
+    /// the ones used with OpenGL.
+    pub fn from_matrix_gl(view_projection: &Mat4) -> Frustum {
+        Frustum::from_rows(view_projection, true)
+    }
+
+    /// Extracts the planes from the rows of the matrix, following Gribb and Hartmann. A point
+    /// is visible if its clip space coordinates satisfy `-w <= x <= w`, `-w <= y <= w` and
+    /// `0 <= z <= w`, or `-w <= z <= w` for the OpenGL depth range.
+    fn from_rows(view_projection: &Mat4, gl_depth: bool) -> Frustum {
+        let row = |i: usize| view_projection.row(i).to_array();
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        let combine = |a: [Real; 4], sign: Real, b: [Real; 4]| {
+            let coefficients = [0, 1, 2, 3].map(|i| a[i] + sign * b[i]);
+            plane_from_coefficients(coefficients)
+        };
+        let near = if gl_depth {
+            combine(w, 1.0, z)
+        } else {
+            plane_from_coefficients(z)
+        };
+        Frustum::new([
+            combine(w, 1.0, x),
+            combine(w, -1.0, x),
+            combine(w, 1.0, y),
+            combine(w, -1.0, y),
+            near,
+            combine(w, -1.0, z),
+        ])
+    }
+
+    /// Returns true if `point` lies inside of the frustum or on its boundary.
+    pub fn contains(&self, point: &Point3) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(point) >= 0.0)
+    }
+
+    /// Classifies an [`AABB`] against the frustum. An [`AABB`] is [`Outside`] if it lies
+    /// behind one of the planes, and [`Inside`] if it lies in front of all of them. All other
+    /// [`AABB`]s are [`Intersecting`], which includes some large [`AABB`]s near the edges of
+    /// the frustum which are not behind any single plane, but still do not overlap it.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`Inside`]: enum.FrustumSide.html#variant.Inside
+    /// [`Intersecting`]: enum.FrustumSide.html#variant.Intersecting
+    /// [`Outside`]: enum.FrustumSide.html#variant.Outside
+    ///
+    pub fn classify_aabb(&self, aabb: &AABB) -> FrustumSide {
+        let mut side = FrustumSide::Inside;
+        for plane in self.planes.iter() {
+            match plane.classify_aabb(aabb) {
+                PlaneSide::Back => return FrustumSide::Outside,
+                PlaneSide::Straddling => side = FrustumSide::Intersecting,
+                PlaneSide::Front => {}
+            }
+        }
+        side
+    }
+}
+
+/// Creates the plane of the points `p` with `a * p.x + b * p.y + c * p.z + w >= 0` in front
+/// of it from the coefficients `[a, b, c, w]`. Without a normal, the plane is in front of or
+/// behind all points, such as the far plane of an infinite projection.
+fn plane_from_coefficients([a, b, c, w]: [Real; 4]) -> Plane {
+    let normal = Vector3::new(a, b, c);
+    let length = normal.length();
+    if length > 0.0 {
+        Plane {
+            normal: normal / length,
+            d: -w / length,
+        }
+    } else {
+        Plane {
+            normal: Vector3::ZERO,
+            d: if w >= 0.0 {
+                Real::NEG_INFINITY
+            } else {
+                Real::INFINITY
+            },
+        }
+    }
+}
+
+impl IntersectionAABB for Frustum {
+    /// Returns true unless the [`AABB`] is [`Outside`] of the frustum, see [`classify_aabb`].
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`classify_aabb`]: struct.Frustum.html#method.classify_aabb
+    /// [`Outside`]: enum.FrustumSide.html#variant.Outside
+    ///
+    fn intersects_aabb(&self, aabb: &AABB) -> bool {
+        self.classify_aabb(aabb) != FrustumSide::Outside
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::{Bounded, AABB};
+    use crate::bounding_hierarchy::IntersectionAABB;
+    use crate::bvh::BVH;
+    use crate::frustum::{Frustum, FrustumSide};
+    use crate::testbase::{create_n_cubes, next_point3};
+    use crate::{Mat4, Point3, Real, Vector3, PI};
+
+    /// Returns a view projection matrix of a camera at `(5, 5, 5)` looking at the origin.
+    fn view_projection(gl: bool) -> Mat4 {
+        let view = Mat4::look_at_rh(Point3::splat(5.0), Point3::ZERO, Vector3::Y);
+        let projection = if gl {
+            Mat4::perspective_rh_gl(PI / 3.0, 1.5, 1.0, 50.0)
+        } else {
+            Mat4::perspective_rh(PI / 3.0, 1.5, 1.0, 50.0)
+        };
+        projection * view
+    }
+
+    #[test]
+    /// Tests that points are inside of the frustum if they are visible in clip space.
+    fn test_frustum_contains() {
+        let bounds = AABB::with_bounds(Point3::splat(-60.0), Point3::splat(60.0));
+        let mut seed = 0;
+        for &gl in [false, true].iter() {
+            let matrix = view_projection(gl);
+            let frustum = if gl {
+                Frustum::from_matrix_gl(&matrix)
+            } else {
+                Frustum::from_matrix(&matrix)
+            };
+            let near_depth = if gl { -1.0 } else { 0.0 };
+            let mut inside = 0;
+            for _ in 0..1000 {
+                let point = next_point3(&mut seed, &bounds);
+                let clip = matrix * point.extend(1.0);
+                let ndc = clip.truncate() / clip.w;
+                // Skip points close to the planes, which may be classified either way.
+                let margin = 1e-3;
+                let visible = clip.w > 0.0
+                    && ndc.x.abs() < 1.0 - margin
+                    && ndc.y.abs() < 1.0 - margin
+                    && ndc.z > near_depth + margin
+                    && ndc.z < 1.0 - margin;
+                let hidden = clip.w <= 0.0
+                    || ndc.x.abs() > 1.0 + margin
+                    || ndc.y.abs() > 1.0 + margin
+                    || ndc.z < near_depth - margin
+                    || ndc.z > 1.0 + margin;
+                if visible {
+                    assert!(frustum.contains(&point));
+                    inside += 1;
+                } else if hidden {
+                    assert!(!frustum.contains(&point));
+                }
+            }
+            assert!(inside > 0);
+        }
+    }
+
+    #[test]
+    /// Tests the classification of AABBs inside, outside and on the boundary, also for a
+    /// projection without a far plane.
+    fn test_frustum_classify_aabb() {
+        let frustum = Frustum::from_matrix(&view_projection(false));
+        let around = |center: Point3, size: Real| {
+            AABB::with_bounds(center - Vector3::splat(size), center + Vector3::splat(size))
+        };
+        assert_eq!(
+            frustum.classify_aabb(&around(Point3::ZERO, 1.0)),
+            FrustumSide::Inside
+        );
+        // The camera is inside of the box, but in front of the near plane.
+        assert_eq!(
+            frustum.classify_aabb(&around(Point3::splat(5.0), 1.0)),
+            FrustumSide::Intersecting
+        );
+        assert_eq!(
+            frustum.classify_aabb(&around(Point3::splat(8.0), 1.0)),
+            FrustumSide::Outside
+        );
+        assert_eq!(
+            frustum.classify_aabb(&around(Point3::splat(-30.0), 1.0)),
+            FrustumSide::Outside
+        );
+        assert_eq!(
+            frustum.classify_aabb(&around(Point3::splat(-24.0), 1.0)),
+            FrustumSide::Intersecting
+        );
+        assert_eq!(
+            frustum.classify_aabb(&around(Point3::new(20.0, -20.0, 0.0), 1.0)),
+            FrustumSide::Outside
+        );
+
+        let projection = Mat4::perspective_infinite_rh(PI / 2.0, 1.0, 0.5);
+        let infinite = Frustum::from_matrix(&projection);
+        let far = around(Point3::new(0.0, 0.0, -1.0e6), 1.0);
+        assert_eq!(infinite.classify_aabb(&far), FrustumSide::Inside);
+        assert!(!infinite.intersects_aabb(&around(Point3::new(0.0, 0.0, 1.0e6), 1.0)));
+    }
+
+    #[test]
+    /// Tests that traversing a BVH with a frustum returns all shapes which are not outside.
+    fn test_frustum_traverse() {
+        let bounds = AABB::with_bounds(Point3::splat(-50.0), Point3::splat(50.0));
+        let mut triangles = create_n_cubes(1000, &bounds);
+        let bvh = BVH::build(&mut triangles);
+        let frustum = Frustum::from_matrix(&view_projection(false));
+
+        let mut expected: Vec<*const _> = triangles
+            .iter()
+            .filter(|triangle| frustum.intersects_aabb(&triangle.aabb()))
+            .map(|triangle| triangle as *const _)
+            .collect();
+        let mut actual: Vec<*const _> = bvh
+            .traverse(&frustum, &triangles)
+            .into_iter()
+            .filter(|triangle| frustum.intersects_aabb(&triangle.aabb()))
+            .map(|triangle| triangle as *const _)
+            .collect();
+        expected.sort();
+        actual.sort();
+        assert!(!expected.is_empty());
+        assert!(expected.len() < triangles.len());
+        assert_eq!(actual, expected);
+    }
+}
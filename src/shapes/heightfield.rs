@@ -0,0 +1,319 @@
+//! This module defines a Heightfield and its intersection algorithms
+
+use crate::{
+    aabb::{Bounded, AABB},
+    ray::{Intersection, IntersectionRay, Ray},
+    Point3, Real, Vector3,
+};
+
+/// A representation of a Heightfield, a terrain given by a regular grid of heights over the
+/// xz plane. Each cell of the grid is split into two triangles, which face up.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct Heightfield {
+    origin: Point3,
+    columns: usize,
+    rows: usize,
+    cell_size: Real,
+    heights: Vec<Real>,
+    aabb: AABB,
+}
+
+impl Heightfield {
+    /// Creates a heightfield with `columns` samples along the x axis and `rows` samples along
+    /// the z axis, which are `cell_size` apart. The sample of `column` and `row` is stored in
+    /// `heights[row * columns + column]` and is offset along the y axis from `origin`, which
+    /// is the position of the first sample at height zero.
+    ///
+    /// # Panics
+    /// Panics if there are fewer than two columns or rows, or if the number of heights does
+    /// not match them.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::Bounded;
+    /// use bvh::heightfield::Heightfield;
+    /// use bvh::ray::{IntersectionRay, Ray};
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// // A ramp rising along the x axis over a 3x2 grid of cells.
+    /// let heights = vec![0.0, 1.0, 2.0, 3.0, 0.0, 1.0, 2.0, 3.0, 0.0, 1.0, 2.0, 3.0];
+    /// let ramp = Heightfield::new(Point3::new(0.0, 0.0, 0.0), 4, 3, 1.0, heights);
+    /// assert_eq!(ramp.aabb().max, Point3::new(3.0, 3.0, 2.0));
+    ///
+    /// let ray = Ray::new(Point3::new(2.5, 10.0, 0.5), Vector3::new(0.0, -1.0, 0.0));
+    /// let hit = ramp.intersects_ray(&ray, 0.0, f32::INFINITY).unwrap();
+    /// assert_eq!(hit.distance, 7.5);
+    /// ```
+    pub fn new(
+        origin: Point3,
+        columns: usize,
+        rows: usize,
+        cell_size: Real,
+        heights: Vec<Real>,
+    ) -> Heightfield {
+        assert!(columns >= 2 && rows >= 2);
+        assert_eq!(heights.len(), columns * rows);
+        let (min, max) = heights.iter().fold(
+            (Real::INFINITY, Real::NEG_INFINITY),
+            |(min, max), &height| (min.min(height), max.max(height)),
+        );
+        let extent = Vector3::new(
+            (columns - 1) as Real * cell_size,
+            0.0,
+            (rows - 1) as Real * cell_size,
+        );
+        let aabb = AABB::with_bounds(
+            origin + Vector3::new(0.0, min, 0.0),
+            origin + extent + Vector3::new(0.0, max, 0.0),
+        );
+        Heightfield {
+            origin,
+            columns,
+            rows,
+            cell_size,
+            heights,
+            aabb,
+        }
+    }
+
+    /// Returns the number of samples along the x axis.
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// Returns the number of samples along the z axis.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Returns the distance between two neighboring samples.
+    pub fn cell_size(&self) -> Real {
+        self.cell_size
+    }
+
+    /// Returns the height of the sample at `column` and `row`.
+    pub fn height(&self, column: usize, row: usize) -> Real {
+        self.heights[row * self.columns + column]
+    }
+
+    /// Returns the position of the sample at `column` and `row`.
+    pub fn point(&self, column: usize, row: usize) -> Point3 {
+        self.origin
+            + Vector3::new(
+                column as Real * self.cell_size,
+                self.height(column, row),
+                row as Real * self.cell_size,
+            )
+    }
+
+    /// Intersects the ray with both triangles of the cell at `column` and `row`, from either
+    /// side, and returns the closest hit with its outward normal.
+    fn intersects_cell(
+        &self,
+        ray: &Ray,
+        column: usize,
+        row: usize,
+        t_min: Real,
+        t_max: Real,
+    ) -> Option<(Real, Vector3)> {
+        let p00 = self.point(column, row);
+        let p10 = self.point(column + 1, row);
+        let p01 = self.point(column, row + 1);
+        let p11 = self.point(column + 1, row + 1);
+
+        let mut nearest: Option<(Real, Vector3)> = None;
+        for (a, b, c) in [(p00, p01, p10), (p10, p01, p11)] {
+            let distance = ray.intersects_triangle_double_sided(&a, &b, &c).distance;
+            if distance.is_finite()
+                && distance >= t_min
+                && distance <= t_max
+                && nearest.is_none_or(|(nearest, _)| distance < nearest)
+            {
+                nearest = Some((distance, (b - a).cross(c - a).normalize()));
+            }
+        }
+        nearest
+    }
+}
+
+impl IntersectionRay for Heightfield {
+    /// Intersects the ray with the terrain by walking the cells below it in order with a 2D
+    /// DDA, so only the cells crossed by the ray are tested. Hits from below are back faces.
+    /// The `u` and `v` coordinates of the [`Intersection`] are the position of the hit along
+    /// the x and z axis of the grid, scaled to `[0, 1]`.
+    ///
+    /// [`Intersection`]: ../ray/struct.Intersection.html
+    ///
+    fn intersects_ray(&self, ray: &Ray, t_min: Real, t_max: Real) -> Option<Intersection> {
+        let (entry, exit) = ray.intersects_aabb_interval(&self.aabb)?;
+        let mut t = entry.max(t_min);
+        let t_end = exit.min(t_max);
+        if t > t_end {
+            return None;
+        }
+
+        // The cell containing the entry point, with the last cells including their far edge.
+        let start = (ray.at(t) - self.origin) / self.cell_size;
+        let cell = |coordinate: Real, samples: usize| {
+            (coordinate.floor().max(0.0) as usize).min(samples - 2) as isize
+        };
+        let mut column = cell(start.x, self.columns);
+        let mut row = cell(start.z, self.rows);
+
+        // The distances at which the ray crosses the next cell border along each axis.
+        let axis = |direction: Real, cell: isize, origin: Real| {
+            if direction == 0.0 {
+                return (0, Real::INFINITY, Real::INFINITY);
+            }
+            let step = if direction > 0.0 { 1 } else { -1 };
+            let border = (cell + (step + 1) / 2) as Real * self.cell_size;
+            let next = (border - origin) / direction;
+            (step, next, self.cell_size / direction.abs())
+        };
+        let local = ray.origin - self.origin;
+        let (step_x, mut next_x, delta_x) = axis(ray.direction.x, column, local.x);
+        let (step_z, mut next_z, delta_z) = axis(ray.direction.z, row, local.z);
+
+        loop {
+            if let Some((toi, out_norm)) =
+                self.intersects_cell(ray, column as usize, row as usize, t_min, t_max)
+            {
+                let size = self.aabb.size();
+                let offset = ray.at(toi) - self.aabb.min;
+                let (norm, back_face) = ray.face_normal(out_norm);
+                return Some(Intersection::new(
+                    toi,
+                    offset.x / size.x,
+                    offset.z / size.z,
+                    norm,
+                    back_face,
+                ));
+            }
+
+            if next_x < next_z {
+                t = next_x;
+                next_x += delta_x;
+                column += step_x;
+            } else {
+                t = next_z;
+                next_z += delta_z;
+                row += step_z;
+            }
+            let outside = column < 0
+                || row < 0
+                || column as usize >= self.columns - 1
+                || row as usize >= self.rows - 1;
+            if outside || t > t_end {
+                return None;
+            }
+        }
+    }
+}
+
+impl Bounded for Heightfield {
+    fn aabb(&self) -> AABB {
+        self.aabb
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::Bounded;
+    use crate::heightfield::Heightfield;
+    use crate::ray::{IntersectionRay, Ray};
+    use crate::testbase::next_point3;
+    use crate::triangle::Triangle;
+    use crate::{Point3, Real, Vector3, EPSILON};
+
+    /// Creates a bumpy heightfield with 20x15 samples.
+    fn terrain() -> Heightfield {
+        let (columns, rows) = (20, 15);
+        let heights = (0..columns * rows)
+            .map(|index| {
+                let (x, z) = ((index % columns) as Real, (index / columns) as Real);
+                (x * 0.7).sin() * 2.0 + (z * 0.4).cos() * 3.0
+            })
+            .collect();
+        Heightfield::new(Point3::new(-5.0, 1.0, 3.0), columns, rows, 0.5, heights)
+    }
+
+    #[test]
+    /// Tests that the DDA finds the same closest hits as testing every triangle.
+    fn test_heightfield_matches_triangles() {
+        let terrain = terrain();
+        let mut triangles = Vec::new();
+        for row in 0..terrain.rows() - 1 {
+            for column in 0..terrain.columns() - 1 {
+                let p00 = terrain.point(column, row);
+                let p10 = terrain.point(column + 1, row);
+                let p01 = terrain.point(column, row + 1);
+                let p11 = terrain.point(column + 1, row + 1);
+                for (a, b, c) in [(p00, p01, p10), (p10, p01, p11)] {
+                    triangles.push(Triangle::new(a, b, c));
+                    triangles.push(Triangle::new(a, c, b));
+                }
+            }
+        }
+
+        let bounds = terrain.aabb();
+        let mut seed = 0;
+        let mut hits = 0;
+        for _ in 0..1000 {
+            let origin = next_point3(&mut seed, &bounds) + Vector3::new(0.0, 2.0, 0.0);
+            let target = next_point3(&mut seed, &bounds);
+            let ray = Ray::new(origin, target - origin);
+            let expected = triangles
+                .iter()
+                .filter_map(|triangle| triangle.intersects_ray(&ray, 0.0, Real::INFINITY))
+                .map(|hit| hit.distance)
+                .filter(|distance| distance.is_finite())
+                .min_by(|a, b| a.partial_cmp(b).unwrap());
+            let actual = terrain
+                .intersects_ray(&ray, 0.0, Real::INFINITY)
+                .map(|hit| hit.distance);
+            match (actual, expected) {
+                (Some(actual), Some(expected)) => {
+                    assert!((actual - expected).abs() < EPSILON * 100.0);
+                    hits += 1;
+                }
+                (actual, expected) => assert_eq!(actual, expected),
+            }
+        }
+        assert!(hits > 0);
+    }
+
+    #[test]
+    /// Tests the normal, the coordinates and hits from below, outside and within the ray
+    /// interval on a tilted plane.
+    fn test_heightfield_intersects_ray() {
+        let heights = vec![0.0, 1.0, 2.0, 0.0, 1.0, 2.0];
+        let ramp = Heightfield::new(Point3::new(0.0, 0.0, 0.0), 3, 2, 2.0, heights);
+        assert_eq!(ramp.aabb().min, Point3::new(0.0, 0.0, 0.0));
+        assert_eq!(ramp.aabb().max, Point3::new(4.0, 2.0, 2.0));
+
+        let down = Ray::new(Point3::new(1.0, 5.0, 1.5), Vector3::new(0.0, -1.0, 0.0));
+        let hit = ramp.intersects_ray(&down, 0.0, Real::INFINITY).unwrap();
+        assert!((hit.distance - 4.5).abs() < EPSILON);
+        assert!((hit.u - 0.25).abs() < EPSILON);
+        assert!((hit.v - 0.75).abs() < EPSILON);
+        assert!((hit.norm - Vector3::new(-1.0, 2.0, 0.0).normalize()).length() < EPSILON);
+        assert!(!hit.back_face);
+        assert!(ramp.intersects_ray(&down, 0.0, 4.0).is_none());
+
+        let up = Ray::new(Point3::new(3.0, -1.0, 0.5), Vector3::new(0.0, 1.0, 0.0));
+        let below = ramp.intersects_ray(&up, 0.0, Real::INFINITY).unwrap();
+        assert!((below.distance - 2.5).abs() < EPSILON);
+        assert!(below.back_face);
+
+        // A grazing ray along the z axis which crosses all rows of a single column.
+        let along = Ray::new(Point3::new(3.0, 1.4, -1.0), Vector3::new(0.0, 0.0, 1.0));
+        assert!(ramp.intersects_ray(&along, 0.0, Real::INFINITY).is_none());
+        let across = Ray::new(Point3::new(-1.0, 1.0, 1.0), Vector3::new(1.0, 0.0, 0.0));
+        let hit = ramp.intersects_ray(&across, 0.0, Real::INFINITY).unwrap();
+        assert!((hit.distance - 3.0).abs() < EPSILON);
+        assert!(!hit.back_face);
+        let beside = Ray::new(Point3::new(-1.0, 1.0, 3.0), Vector3::new(1.0, 0.0, 0.0));
+        assert!(ramp.intersects_ray(&beside, 0.0, Real::INFINITY).is_none());
+    }
+}
@@ -0,0 +1,205 @@
+//! This module defines an Instance, which places a shared shape with a transform
+
+use std::sync::Arc;
+
+use crate::{
+    aabb::{Bounded, AABB},
+    bounding_hierarchy::BHShape,
+    ray::{Intersection, IntersectionRay, Ray},
+    transformed::Transformed,
+    Mat4, Real,
+};
+
+/// An instance of a shape which is shared with other instances, such as a [`TriMesh`] with
+/// its [`BVH`], placed with a transform. Rays are transformed into the local space of the
+/// shape, so many instances only store one copy of its geometry. Unlike a [`Transformed`]
+/// shape, every instance has its own node index and can be put into a scene level
+/// hierarchy.
+///
+/// # Examples
+/// ```
+/// use std::sync::Arc;
+/// use bvh::bvh::BVH;
+/// use bvh::instance::Instance;
+/// use bvh::ray::Ray;
+/// use bvh::sphere::Sphere;
+/// use bvh::{Mat4, Point3, Vector3};
+///
+/// let ball = Arc::new(Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0));
+/// let mut balls: Vec<Instance<Sphere>> = (0..10)
+///     .map(|x| {
+///         let transform = Mat4::from_translation(Vector3::new(x as f32 * 3.0, 0.0, 0.0));
+///         Instance::new(ball.clone(), transform)
+///     })
+///     .collect();
+/// let bvh = BVH::build(&mut balls);
+///
+/// let ray = Ray::new(Point3::new(3.5, -5.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+/// let (hit_ball, hit) = bvh.traverse_n_nearest(&ray, 1, 0.0, f32::INFINITY, &balls)[0];
+/// assert_eq!(hit_ball.transform().w_axis.x, 3.0);
+/// assert!((hit.distance - (5.0 - 0.75f32.sqrt())).abs() < 0.0001);
+/// ```
+///
+/// [`BVH`]: ../bvh/struct.BVH.html
+/// [`Transformed`]: ../transformed/struct.Transformed.html
+/// [`TriMesh`]: ../tri_mesh/struct.TriMesh.html
+///
+#[derive(Debug, Clone)]
+pub struct Instance<T> {
+    placement: Transformed<Arc<T>>,
+    node_index: usize,
+}
+
+impl<T> Instance<T> {
+    /// Places the shared `shape` with `transform`, which must be invertible.
+    pub fn new(shape: Arc<T>, transform: Mat4) -> Instance<T> {
+        Instance {
+            placement: Transformed::new(shape, transform),
+            node_index: 0,
+        }
+    }
+
+    /// Returns the shared shape.
+    pub fn shape(&self) -> &Arc<T> {
+        &self.placement.shape
+    }
+
+    /// Returns the transform from the local space of the shape to world space.
+    pub fn transform(&self) -> &Mat4 {
+        self.placement.transform()
+    }
+
+    /// Replaces the transform. The hierarchy containing the instance has to be updated
+    /// with its new bounds.
+    pub fn set_transform(&mut self, transform: Mat4) {
+        self.placement.set_transform(transform);
+    }
+}
+
+impl<T: IntersectionRay> IntersectionRay for Instance<T> {
+    /// Intersects the ray with the shared shape in its local space, see [`Transformed`].
+    ///
+    /// [`Transformed`]: ../transformed/struct.Transformed.html
+    ///
+    fn intersects_ray(&self, ray: &Ray, t_min: Real, t_max: Real) -> Option<Intersection> {
+        self.placement.intersects_ray(ray, t_min, t_max)
+    }
+}
+
+impl<T: Bounded> Bounded for Instance<T> {
+    fn aabb(&self) -> AABB {
+        self.placement.aabb()
+    }
+}
+
+impl<T: Bounded + Send + Sync> BHShape for Instance<T> {
+    fn set_bh_node_index(&mut self, index: usize) {
+        self.node_index = index;
+    }
+
+    fn bh_node_index(&self) -> usize {
+        self.node_index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::aabb::AABB;
+    use crate::bvh::BVH;
+    use crate::instance::Instance;
+    use crate::ray::IntersectionRay;
+    use crate::testbase::create_ray;
+    use crate::tri_mesh::TriMesh;
+    use crate::triangle::Triangle;
+    use crate::{Mat4, Point3, Quat, Real, Vector3};
+
+    /// Creates a unit cube centered at the origin.
+    fn cube() -> TriMesh {
+        let vertices = (0..8)
+            .map(|i| {
+                let corner = Point3::new((i & 1) as Real, ((i >> 1) & 1) as Real, (i >> 2) as Real);
+                corner - Vector3::splat(0.5)
+            })
+            .collect();
+        let indices = vec![
+            [0, 4, 6],
+            [0, 6, 2],
+            [1, 3, 7],
+            [1, 7, 5],
+            [0, 1, 5],
+            [0, 5, 4],
+            [2, 6, 7],
+            [2, 7, 3],
+            [0, 2, 3],
+            [0, 3, 1],
+            [4, 5, 7],
+            [4, 7, 6],
+        ];
+        TriMesh::new(vertices, indices)
+    }
+
+    #[test]
+    /// Tests that a scene of instances of one mesh is hit like the mesh copied into world
+    /// space for every instance.
+    fn test_instances_match_copies() {
+        let mesh = Arc::new(cube());
+        let mut instances: Vec<Instance<TriMesh>> = (0..50)
+            .map(|i| {
+                let position = Vector3::new(
+                    (i % 5) as Real * 20.0 - 40.0,
+                    (i / 5 % 5) as Real * 20.0 - 40.0,
+                    (i / 25) as Real * 20.0 - 10.0,
+                );
+                let rotation =
+                    Quat::from_axis_angle(Vector3::new(1.0, 1.0, 0.0).normalize(), i as Real);
+                let scale = Vector3::splat(3.0 + (i % 3) as Real);
+                let transform = Mat4::from_scale_rotation_translation(scale, rotation, position);
+                Instance::new(mesh.clone(), transform)
+            })
+            .collect();
+        assert_eq!(Arc::strong_count(&mesh), 51);
+        let bvh = BVH::build(&mut instances);
+
+        let copies: Vec<Triangle> = instances
+            .iter()
+            .flat_map(|instance| {
+                (0..mesh.len()).map(move |face| {
+                    let triangle = instance.shape().triangle(face);
+                    let transform = instance.transform();
+                    Triangle::new(
+                        transform.transform_point3(triangle.a),
+                        transform.transform_point3(triangle.b),
+                        transform.transform_point3(triangle.c),
+                    )
+                })
+            })
+            .collect();
+
+        let bounds = AABB::with_bounds(Point3::splat(-60.0), Point3::splat(60.0));
+        let mut seed = 0;
+        let mut hits = 0;
+        for _ in 0..1000 {
+            let ray = create_ray(&mut seed, &bounds);
+            let expected = copies
+                .iter()
+                .filter_map(|triangle| triangle.intersects_ray(&ray, 0.0, Real::INFINITY))
+                .map(|hit| hit.distance)
+                .filter(|distance| distance.is_finite())
+                .min_by(|a, b| a.partial_cmp(b).unwrap());
+            let actual = bvh
+                .traverse_n_nearest(&ray, 1, 0.0, Real::INFINITY, &instances)
+                .first()
+                .map(|(_, hit)| hit.distance);
+            match (actual, expected) {
+                (Some(actual), Some(expected)) => {
+                    assert!((actual - expected).abs() < 1e-3);
+                    hits += 1;
+                }
+                (actual, expected) => assert_eq!(actual, expected),
+            }
+        }
+        assert!(hits > 0);
+    }
+}
@@ -1,16 +1,30 @@
 pub mod aabb;
 pub mod capsule;
+pub mod composite;
+pub mod cone;
+pub mod convex_hull;
+pub mod frustum;
+pub mod heightfield;
+pub mod instance;
+pub mod moving;
 pub mod obb;
+pub mod plane;
 pub mod ray;
+pub mod rounded_box;
+pub mod segment;
 pub mod sphere;
+pub mod transformed;
+pub mod tri_mesh;
 pub mod triangle;
 
 #[cfg(test)]
 mod tests {
     use crate::aabb::AABB;
-    use crate::bounding_hierarchy::IntersectionAABB;
+    use crate::bounding_hierarchy::{IntersectionAABB, IntersectionShape};
     use crate::capsule::Capsule;
     use crate::obb::OBB;
+    use crate::sphere::Sphere;
+    use crate::testbase::next_point3;
     use crate::{Point3, Quat, Real, Vector3, PI};
 
     #[test]
@@ -53,7 +67,7 @@ mod tests {
 
         let ori = Quat::from_axis_angle(Vector3::new(1.0, 0.0, 0.0), PI / 4.);
         let extents = Vector3::new(0.5, 0.5, 0.5);
-        let pos = Vector3::new(0.5, 2.2, 0.5);
+        let pos = Vector3::new(0.5, 1.6, 0.5);
 
         let obb = OBB {
             orientation: ori,
@@ -62,5 +76,129 @@ mod tests {
         };
 
         assert!(obb.intersects_aabb(&aabb));
+
+        // The rotated box reaches sqrt(0.5) below its center.
+        let above = OBB {
+            center: Vector3::new(0.5, 2.2, 0.5),
+            ..obb
+        };
+        assert!(!above.intersects_aabb(&aabb));
+    }
+
+    #[test]
+    /// Tests the overlap of pairs of shapes which touch or barely miss each other.
+    fn intersects_shape_cases() {
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0);
+        assert!(sphere.intersects_shape(&Sphere::new(Point3::new(2.0, 0.0, 0.0), 1.0)));
+        assert!(!sphere.intersects_shape(&Sphere::new(Point3::new(2.1, 0.0, 0.0), 1.0)));
+
+        // Skew capsules along x and y, one above the other.
+        let along_x = Capsule::new(Point3::new(-2.0, 0.0, 0.0), Point3::new(2.0, 0.0, 0.0), 0.8);
+        let along_y = |radius: Real| {
+            Capsule::new(
+                Point3::new(1.0, -2.0, 1.5),
+                Point3::new(1.0, 2.0, 1.5),
+                radius,
+            )
+        };
+        assert!(along_x.intersects_shape(&along_y(0.8)));
+        assert!(!along_x.intersects_shape(&along_y(0.6)));
+        let parallel = Capsule::new(Point3::new(3.0, 1.5, 0.0), Point3::new(5.0, 1.5, 0.0), 0.8);
+        assert!(!along_x.intersects_shape(&parallel));
+        let parallel = Capsule::new(Point3::new(1.0, 1.5, 0.0), Point3::new(5.0, 1.5, 0.0), 0.8);
+        assert!(along_x.intersects_shape(&parallel));
+
+        // Boxes turned by 45 degrees reach sqrt(2) along the x axis.
+        let diamond = |x: Real| OBB {
+            orientation: Quat::from_rotation_z(PI / 4.0),
+            extents: Vector3::splat(1.0),
+            center: Vector3::new(x, 0.0, 0.0),
+        };
+        assert!(diamond(0.0).intersects_shape(&Sphere::new(Point3::new(1.35, 0.0, 0.0), 0.1)));
+        assert!(!diamond(0.0).intersects_shape(&Sphere::new(Point3::new(1.6, 0.0, 0.0), 0.1)));
+        assert!(diamond(0.0).intersects_shape(&diamond(2.6)));
+        assert!(!diamond(0.0).intersects_shape(&diamond(2.9)));
+        let cube = AABB::with_bounds(Point3::splat(-1.0), Point3::splat(1.0));
+        assert!(diamond(2.3).intersects_shape(&cube));
+        assert!(!diamond(2.5).intersects_shape(&cube));
+        let vertical =
+            |x: Real| Capsule::new(Point3::new(x, 0.0, -3.0), Point3::new(x, 0.0, 3.0), 0.1);
+        assert!(diamond(0.0).intersects_shape(&vertical(1.45)));
+        assert!(!diamond(0.0).intersects_shape(&vertical(1.6)));
+    }
+
+    #[test]
+    /// Tests that the overlap of random shapes does not depend on the order of the pair,
+    /// and that boxes without rotation overlap like AABBs.
+    fn intersects_shape_symmetric() {
+        let bounds = AABB::with_bounds(Point3::splat(-3.0), Point3::splat(3.0));
+        let unit = AABB::with_bounds(Point3::splat(0.1), Point3::splat(1.0));
+        let mut seed = 0;
+        let mut overlaps = 0;
+        for _ in 0..1000 {
+            let center = next_point3(&mut seed, &bounds);
+            let extents = next_point3(&mut seed, &unit);
+            let aabb = AABB::with_bounds(center - extents, center + extents);
+            let axis_aligned = OBB {
+                orientation: Quat::IDENTITY,
+                extents,
+                center,
+            };
+            let axis = next_point3(&mut seed, &bounds).normalize();
+            let obb = OBB {
+                orientation: Quat::from_axis_angle(axis, next_point3(&mut seed, &bounds).x),
+                extents: next_point3(&mut seed, &unit),
+                center: next_point3(&mut seed, &bounds),
+            };
+            let sphere = Sphere::new(
+                next_point3(&mut seed, &bounds),
+                next_point3(&mut seed, &unit).x,
+            );
+            let capsule = Capsule::new(
+                next_point3(&mut seed, &bounds),
+                next_point3(&mut seed, &bounds),
+                next_point3(&mut seed, &unit).x,
+            );
+
+            assert_eq!(aabb.intersects_shape(&obb), obb.intersects_shape(&aabb));
+            assert_eq!(
+                aabb.intersects_shape(&sphere),
+                sphere.intersects_shape(&aabb)
+            );
+            assert_eq!(
+                aabb.intersects_shape(&capsule),
+                capsule.intersects_shape(&aabb)
+            );
+            assert_eq!(obb.intersects_shape(&sphere), sphere.intersects_shape(&obb));
+            assert_eq!(
+                obb.intersects_shape(&capsule),
+                capsule.intersects_shape(&obb)
+            );
+            assert_eq!(
+                sphere.intersects_shape(&capsule),
+                capsule.intersects_shape(&sphere)
+            );
+
+            assert_eq!(
+                axis_aligned.intersects_shape(&obb),
+                aabb.intersects_shape(&obb)
+            );
+            assert_eq!(
+                obb.intersects_shape(&axis_aligned),
+                obb.intersects_shape(&aabb)
+            );
+            assert_eq!(
+                axis_aligned.intersects_shape(&sphere),
+                aabb.intersects_shape(&sphere)
+            );
+            assert_eq!(
+                axis_aligned.intersects_shape(&capsule),
+                aabb.intersects_shape(&capsule)
+            );
+            if sphere.intersects_shape(&capsule) {
+                overlaps += 1;
+            }
+        }
+        assert!(overlaps > 0);
     }
 }
@@ -0,0 +1,232 @@
+//! This module defines a Moving shape, which moves a shape between two transforms for motion blur
+
+use crate::{
+    aabb::{Bounded, AABB},
+    bounding_hierarchy::BHShape,
+    ray::{Intersection, IntersectionRay, Ray},
+    transformed::intersects_ray_local,
+    Mat4, Real, Vector3,
+};
+
+/// A shape moving from a begin transform at time `0.0` to an end transform at time `1.0`.
+/// The transform at a time in between is the linear interpolation of the two matrices, so
+/// every point of the shape moves along a straight line. This is exact for translations and
+/// scales and approximates small rotations, which is how motion within the shutter interval
+/// of a camera is usually blurred.
+///
+/// Rays are intersected with the shape at their [`time`], and its bounds cover the whole
+/// motion, so a hierarchy built over moving shapes finds them at any time.
+///
+/// # Examples
+/// ```
+/// use bvh::aabb::{Bounded, AABB};
+/// use bvh::moving::Moving;
+/// use bvh::ray::{IntersectionRay, Ray};
+/// use bvh::sphere::Sphere;
+/// use bvh::{Mat4, Point3, Vector3};
+///
+/// // A unit sphere moving from the origin to x = 4.
+/// let sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0);
+/// let end = Mat4::from_translation(Vector3::new(4.0, 0.0, 0.0));
+/// let moving = Moving::new(sphere, Mat4::IDENTITY, end);
+/// assert_eq!(
+///     moving.aabb_at(0.5),
+///     AABB::with_bounds(Point3::new(1.0, -1.0, -1.0), Point3::new(3.0, 1.0, 1.0))
+/// );
+/// assert_eq!(moving.aabb().max, Point3::new(5.0, 1.0, 1.0));
+///
+/// let ray = Ray::new(Point3::new(4.0, -5.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+/// assert!(moving.intersects_ray(&ray, 0.0, f32::INFINITY).is_none());
+/// let hit = moving.intersects_ray(&ray.with_time(1.0), 0.0, f32::INFINITY).unwrap();
+/// assert_eq!(hit.distance, 4.0);
+/// ```
+///
+/// [`time`]: ../ray/struct.Ray.html#structfield.time
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct Moving<T> {
+    /// The shape in its local space
+    pub shape: T,
+    begin: Mat4,
+    end: Mat4,
+}
+
+impl<T> Moving<T> {
+    /// Moves `shape` from the `begin` transform to the `end` transform. The transforms at
+    /// all times in between must be invertible.
+    pub fn new(shape: T, begin: Mat4, end: Mat4) -> Moving<T> {
+        Moving { shape, begin, end }
+    }
+
+    /// Moves `shape` from `begin` to `end` without rotating or scaling it.
+    pub fn from_positions(shape: T, begin: Vector3, end: Vector3) -> Moving<T> {
+        Moving::new(
+            shape,
+            Mat4::from_translation(begin),
+            Mat4::from_translation(end),
+        )
+    }
+
+    /// Returns the transform from local space to world space at time `0.0`.
+    pub fn begin(&self) -> &Mat4 {
+        &self.begin
+    }
+
+    /// Returns the transform from local space to world space at time `1.0`.
+    pub fn end(&self) -> &Mat4 {
+        &self.end
+    }
+
+    /// Returns the transform from local space to world space at `time`.
+    pub fn transform_at(&self, time: Real) -> Mat4 {
+        self.begin * (1.0 - time) + self.end * time
+    }
+}
+
+impl<T: Bounded> Moving<T> {
+    /// Returns the bounds of the shape at `time`.
+    pub fn aabb_at(&self, time: Real) -> AABB {
+        self.shape.aabb().transformed(&self.transform_at(time))
+    }
+
+    /// Returns the bounds of the shape at all times between `begin` and `end`. Every point
+    /// moves along a straight line, so these are the joint bounds at both times.
+    pub fn aabb_over(&self, begin: Real, end: Real) -> AABB {
+        self.aabb_at(begin).join(&self.aabb_at(end))
+    }
+}
+
+impl<T: IntersectionRay> IntersectionRay for Moving<T> {
+    /// Intersects the ray with the shape placed at the [`time`] of the ray, or at its
+    /// beginning for rays without a time. Distances and normals are in world space, see
+    /// [`Transformed`].
+    ///
+    /// [`time`]: ../ray/struct.Ray.html#structfield.time
+    /// [`Transformed`]: ../transformed/struct.Transformed.html
+    ///
+    fn intersects_ray(&self, ray: &Ray, t_min: Real, t_max: Real) -> Option<Intersection> {
+        let inverse = self.transform_at(ray.time.unwrap_or(0.0)).inverse();
+        intersects_ray_local(&self.shape, &inverse, ray, t_min, t_max)
+    }
+}
+
+impl<T: Bounded> Bounded for Moving<T> {
+    /// Returns the bounds of the whole motion.
+    fn aabb(&self) -> AABB {
+        self.aabb_over(0.0, 1.0)
+    }
+}
+
+impl<T: BHShape> BHShape for Moving<T> {
+    fn set_bh_node_index(&mut self, index: usize) {
+        self.shape.set_bh_node_index(index);
+    }
+
+    fn bh_node_index(&self) -> usize {
+        self.shape.bh_node_index()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::{Bounded, AABB};
+    use crate::moving::Moving;
+    use crate::ray::{IntersectionRay, Ray};
+    use crate::testbase::{create_ray, next_point3};
+    use crate::transformed::Transformed;
+    use crate::triangle::Triangle;
+    use crate::{Mat4, Point3, Quat, Real, Vector3};
+
+    #[test]
+    /// Tests that a moving triangle is hit like the triangle placed at the time of the ray,
+    /// and that its bounds over an interval contain its bounds at all times within it.
+    fn test_moving_matches_transformed() {
+        let triangle = Triangle::new(
+            Point3::new(-1.0, -1.0, 0.0),
+            Point3::new(1.0, -1.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+        );
+        let begin = Mat4::from_scale_rotation_translation(
+            Vector3::splat(2.0),
+            Quat::from_rotation_y(0.3),
+            Vector3::new(-5.0, 0.0, 0.0),
+        );
+        let end = Mat4::from_scale_rotation_translation(
+            Vector3::splat(3.0),
+            Quat::from_rotation_y(0.5),
+            Vector3::new(5.0, 2.0, 1.0),
+        );
+        let moving = Moving::new(triangle, begin, end);
+        let interval = moving.aabb_over(0.25, 0.75);
+
+        let bounds = AABB::with_bounds(Point3::splat(-10.0), Point3::splat(10.0));
+        let mut seed = 0;
+        let mut hits = 0;
+        for i in 0..1000 {
+            let time = (i as Real + 0.5) / 1000.0;
+            let ray = create_ray(&mut seed, &bounds).with_time(time);
+            let placed = Transformed::new(triangle, moving.transform_at(time));
+            assert!(moving.aabb().approx_contains_aabb_eps(&placed.aabb(), 1e-4));
+            if (0.25..=0.75).contains(&time) {
+                assert!(interval.approx_contains_aabb_eps(&placed.aabb(), 1e-4));
+            }
+
+            // Missed triangles report an infinite distance.
+            let expected = placed
+                .intersects_ray(&ray, 0.0, Real::INFINITY)
+                .filter(|hit| hit.distance.is_finite());
+            let actual = moving
+                .intersects_ray(&ray, 0.0, Real::INFINITY)
+                .filter(|hit| hit.distance.is_finite());
+            assert_eq!(actual.is_some(), expected.is_some());
+            if let (Some(actual), Some(expected)) = (actual, expected) {
+                assert!((actual.distance - expected.distance).abs() < 1e-3);
+                hits += 1;
+            }
+        }
+        assert!(hits > 0);
+    }
+
+    #[test]
+    /// Tests that points of the shape stay within the bounds of a translating shape.
+    fn test_moving_from_positions() {
+        let triangle = Triangle::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+        );
+        let moving = Moving::from_positions(
+            triangle,
+            Vector3::new(0.0, 0.0, -3.0),
+            Vector3::new(10.0, 0.0, 3.0),
+        );
+        assert_eq!(
+            moving.aabb(),
+            AABB::with_bounds(Point3::new(0.0, 0.0, -3.0), Point3::new(11.0, 1.0, 3.0))
+        );
+        assert_eq!(
+            moving.aabb_at(0.5),
+            AABB::with_bounds(Point3::new(5.0, 0.0, 0.0), Point3::new(6.0, 1.0, 0.0))
+        );
+
+        let mut seed = 0;
+        for i in 0..=10 {
+            let time = i as Real / 10.0;
+            let point = next_point3(&mut seed, &triangle.aabb());
+            let moved = moving.transform_at(time).transform_point3(point);
+            assert!(moving.aabb().contains(&moved));
+        }
+
+        // A ray through the path of the triangle hits it only while it passes.
+        let ray = Ray::new(Point3::new(5.25, 0.25, 5.0), Vector3::new(0.0, 0.0, -1.0));
+        let hit = moving
+            .intersects_ray(&ray.with_time(0.5), 0.0, Real::INFINITY)
+            .filter(|hit| hit.distance.is_finite());
+        assert!((hit.unwrap().distance - 5.0).abs() < 1e-4);
+        let early = moving
+            .intersects_ray(&ray, 0.0, Real::INFINITY)
+            .filter(|hit| hit.distance.is_finite());
+        assert!(early.is_none());
+    }
+}
@@ -1,5 +1,13 @@
 //! This module defines an Oriented Bounding Box and its intersection properties
-use crate::{aabb::AABB, bounding_hierarchy::IntersectionAABB, Mat4, Quat, Vector3};
+use crate::{
+    aabb::AABB,
+    bounding_hierarchy::{IntersectionAABB, IntersectionShape},
+    capsule::Capsule,
+    gjk::{intersects_ray, SupportMap},
+    ray::{Intersection, IntersectionRay, Ray},
+    sphere::Sphere,
+    Point3, Quat, Real, Vector3, EPSILON,
+};
 
 /// Represents a box that can be rotated in any direction
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -13,161 +21,118 @@ pub struct OBB {
     pub center: Vector3,
 }
 
+impl OBB {
+    /// Returns the position of the world space `point` relative to the box, where the box
+    /// is its [`local_aabb`]. The orientation rotates world space offsets into this space.
+    ///
+    /// [`local_aabb`]: struct.OBB.html#method.local_aabb
+    ///
+    pub fn to_local(&self, point: Point3) -> Point3 {
+        self.orientation * (point - self.center)
+    }
+
+    /// Returns the box in its own space, centered at the origin.
+    pub fn local_aabb(&self) -> AABB {
+        AABB::with_bounds(-self.extents, self.extents)
+    }
+}
+
 impl IntersectionAABB for OBB {
+    /// Tests the 15 potential separating axes of the box and the `aabb`, which are the axes
+    /// of both boxes and the cross products of pairs of them.
     fn intersects_aabb(&self, aabb: &AABB) -> bool {
         let half_a = self.extents;
-        let half_b = (aabb.max - aabb.min) * 0.5;
-        let value = (aabb.max + aabb.min) * 0.5;
-        let translation = self.orientation * (value - self.center);
-        let mat = Mat4::from_rotation_translation(self.orientation, translation);
+        let half_b = aabb.size() * 0.5;
+        let translation = self.to_local(aabb.center());
+        // The axes of the `aabb` in the space of the box, as the columns of the rotation.
+        let rotation = [
+            self.orientation * Vector3::X,
+            self.orientation * Vector3::Y,
+            self.orientation * Vector3::Z,
+        ];
+        // Avoids false separations along the cross products of parallel axes.
+        let abs_rotation = rotation.map(|column| column.abs() + Vector3::splat(EPSILON));
 
-        let vec_1 = Vector3::new(
-            translation.x.abs(),
-            translation.y.abs(),
-            translation.z.abs(),
-        );
-        let right = right(mat);
-        let up = up(mat);
-        let backward = back(mat);
-        let vec_2 = right * half_b.x;
-        let vec_3 = up * half_b.y;
-        let vec_4 = backward * half_b.z;
-        let num = vec_2.x.abs() + vec_3.x.abs() + vec_4.x.abs();
-        let num2 = vec_2.y.abs() + vec_3.y.abs() + vec_4.y.abs();
-        let num3 = vec_2.z.abs() + vec_3.z.abs() + vec_4.z.abs();
-        if vec_1.x + num <= half_a.x && vec_1.y + num2 <= half_a.y && vec_1.z + num3 <= half_a.z {
-            // Contained
-            return true;
-        }
-        if vec_1.x > half_a.x + vec_2.x.abs() + vec_3.x.abs() + vec_4.x.abs() {
-            return false;
-        }
-        if vec_1.y > half_a.y + vec_2.y.abs() + vec_3.y.abs() + vec_4.y.abs() {
-            return false;
-        }
-        if vec_1.z > half_a.z + vec_2.z.abs() + vec_3.z.abs() + vec_4.z.abs() {
-            return false;
-        }
-        if translation.dot(right.abs())
-            > half_a.x * right.x.abs()
-                + half_a.y * right.y.abs()
-                + half_a.z * right.z.abs()
-                + half_b.x
-        {
-            return false;
-        }
-        if translation.dot(up.abs())
-            > half_a.x * up.x.abs() + half_a.y * up.y.abs() + half_a.z * up.z.abs() + half_b.y
-        {
-            return false;
-        }
-        if translation.dot(backward.abs())
-            > half_a.x * backward.x.abs()
-                + half_a.y * backward.y.abs()
-                + half_a.z * backward.z.abs()
-                + half_b.z
-        {
-            return false;
-        }
-        let mut vec_5 = Vector3::new(0.0, -right.z, right.y);
-        if translation.dot(vec_5.abs())
-            > half_a.y * vec_5.y.abs()
-                + half_a.z * vec_5.z.abs()
-                + vec_5.dot(vec_3.abs())
-                + vec_5.dot(vec_4.abs())
-        {
-            return false;
-        }
-        vec_5 = Vector3::new(0.0, -up.z, up.y);
-        if translation.dot(vec_5.abs())
-            > half_a.y * vec_5.y.abs()
-                + half_a.z * vec_5.z.abs()
-                + vec_5.dot(vec_4.abs())
-                + vec_5.dot(vec_2.abs())
-        {
-            return false;
-        }
-        vec_5 = Vector3::new(0.0, -backward.z, backward.y);
-        if translation.dot(vec_5.abs())
-            > half_a.y * vec_5.y.abs()
-                + half_a.z * vec_5.z.abs()
-                + vec_5.dot(vec_2.abs())
-                + vec_5.dot(vec_3.abs())
-        {
-            return false;
-        }
-        vec_5 = Vector3::new(right.z, 0.0, -right.x);
-        if translation.dot(vec_5.abs())
-            > half_a.z * vec_5.z.abs()
-                + half_a.x * vec_5.x.abs()
-                + vec_5.dot(vec_3.abs())
-                + vec_5.dot(vec_4.abs())
-        {
-            return false;
+        for i in 0..3 {
+            let radius_b = (0..3).map(|j| half_b[j] * abs_rotation[j][i]).sum::<Real>();
+            if translation[i].abs() > half_a[i] + radius_b {
+                return false;
+            }
+        }
+        for j in 0..3 {
+            let radius_a = half_a.dot(abs_rotation[j]);
+            if translation.dot(rotation[j]).abs() > radius_a + half_b[j] {
+                return false;
+            }
+        }
+        for i in 0..3 {
+            let (i1, i2) = ((i + 1) % 3, (i + 2) % 3);
+            for j in 0..3 {
+                let (j1, j2) = ((j + 1) % 3, (j + 2) % 3);
+                let radius_a = half_a[i1] * abs_rotation[j][i2] + half_a[i2] * abs_rotation[j][i1];
+                let radius_b = half_b[j1] * abs_rotation[j2][i] + half_b[j2] * abs_rotation[j1][i];
+                let distance =
+                    translation[i2] * rotation[j][i1] - translation[i1] * rotation[j][i2];
+                if distance.abs() > radius_a + radius_b {
+                    return false;
+                }
+            }
         }
-        vec_5 = Vector3::new(up.z, 0.0, -up.x);
-        if translation.dot(vec_5.abs())
-            > half_a.z * vec_5.z.abs()
-                + half_a.x * vec_5.x.abs()
-                + vec_5.dot(vec_4.abs())
-                + vec_5.dot(vec_2.abs())
-        {
-            return false;
-        }
-        vec_5 = Vector3::new(backward.z, 0.0, -backward.x);
-        if translation.dot(vec_5.abs())
-            > half_a.z * vec_5.z.abs()
-                + half_a.x * vec_5.x.abs()
-                + vec_5.dot(vec_2.abs())
-                + vec_5.dot(vec_3.abs())
-        {
-            return false;
-        }
-        vec_5 = Vector3::new(-right.y, right.x, 0.0);
-        if translation.dot(vec_5.abs())
-            > half_a.x * vec_5.x.abs()
-                + half_a.y * vec_5.y.abs()
-                + vec_5.dot(vec_3.abs())
-                + vec_5.dot(vec_4.abs())
-        {
-            return false;
-        }
-        vec_5 = Vector3::new(-up.y, up.x, 0.0);
-        if translation.dot(vec_5.abs())
-            > half_a.x * vec_5.x.abs()
-                + half_a.y * vec_5.y.abs()
-                + vec_5.dot(vec_4.abs())
-                + vec_5.dot(vec_2.abs())
-        {
-            return false;
-        }
-        vec_5 = Vector3::new(-backward.y, backward.x, 0.0);
-        if translation.dot(vec_5.abs())
-            > half_a.x * vec_5.x.abs()
-                + half_a.y * vec_5.y.abs()
-                + vec_5.dot(vec_2.abs())
-                + vec_5.dot(vec_3.abs())
-        {
-            return false;
-        }
-        // Intersection
         true
     }
 }
 
-fn right(matrix: Mat4) -> Vector3 {
-    matrix.row(0).truncate()
+impl IntersectionShape<AABB> for OBB {
+    fn intersects_shape(&self, other: &AABB) -> bool {
+        self.intersects_aabb(other)
+    }
 }
 
-fn up(matrix: Mat4) -> Vector3 {
-    matrix.row(1).truncate()
+impl IntersectionShape for OBB {
+    fn intersects_shape(&self, other: &OBB) -> bool {
+        // Tests `other`, placed in the space of this box, against its local bounds.
+        let relative = OBB {
+            orientation: other.orientation * self.orientation.inverse(),
+            extents: other.extents,
+            center: self.to_local(other.center),
+        };
+        relative.intersects_aabb(&self.local_aabb())
+    }
 }
 
-fn back(matrix: Mat4) -> Vector3 {
-    matrix.row(2).truncate()
+impl IntersectionShape<Sphere> for OBB {
+    fn intersects_shape(&self, other: &Sphere) -> bool {
+        Sphere::new(self.to_local(other.center), other.radius).intersects_aabb(&self.local_aabb())
+    }
 }
 
-#[allow(dead_code)]
-fn translation(matrix: Mat4) -> Vector3 {
-    matrix.row(3).truncate()
+impl IntersectionShape<Capsule> for OBB {
+    fn intersects_shape(&self, other: &Capsule) -> bool {
+        let local = Capsule {
+            start: self.to_local(other.start),
+            radius: other.radius,
+            dir: self.orientation * other.dir,
+            len: other.len,
+        };
+        local.intersects_aabb(&self.local_aabb())
+    }
+}
+
+impl SupportMap for OBB {
+    fn support(&self, direction: Vector3) -> Point3 {
+        // Rotations preserve dot products, so the support point of the rotated direction in
+        // the space of the box is rotated back.
+        let local = self.local_aabb().support(self.orientation * direction);
+        self.center + self.orientation.inverse() * local
+    }
+}
+
+impl IntersectionRay for OBB {
+    /// Intersects the ray with the box with the GJK ray cast, see [`intersects_ray`].
+    ///
+    /// [`intersects_ray`]: ../gjk/fn.intersects_ray.html
+    ///
+    fn intersects_ray(&self, ray: &Ray, t_min: Real, t_max: Real) -> Option<Intersection> {
+        intersects_ray(self, ray, t_min, t_max)
+    }
 }
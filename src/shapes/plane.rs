@@ -0,0 +1,379 @@
+//! This module defines a Plane, helpers for classifying geometry against it and its
+//! intersection with rays.
+
+use crate::aabb::{Bounded, AABB};
+use crate::bounding_hierarchy::IntersectionAABB;
+use crate::ray::{Intersection, IntersectionRay, Ray};
+use crate::{Point3, Real, Vector3, EPSILON};
+
+/// Describes on which side of a [`Plane`] a piece of geometry lies.
+///
+/// [`Plane`]: struct.Plane.html
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub enum PlaneSide {
+    /// Entirely in the half-space the normal points into.
+    Front,
+    /// Entirely in the half-space opposite to the normal.
+    Back,
+    /// Crosses or touches the plane.
+    Straddling,
+}
+
+/// A plane given by the set of points `p` for which `normal.dot(p) == d`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct Plane {
+    /// Unit normal of the plane, pointing into the front half-space
+    pub normal: Vector3,
+    /// Signed distance of the plane from the origin along `normal`
+    pub d: Real,
+}
+
+impl Plane {
+    /// Creates a plane from a normal and its distance from the origin.
+    /// The normal is normalized.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::plane::Plane;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let plane = Plane::new(Vector3::new(0.0, 2.0, 0.0), 1.0);
+    /// assert_eq!(plane.signed_distance(&Point3::new(5.0, 3.0, 5.0)), 2.0);
+    /// ```
+    pub fn new(normal: Vector3, d: Real) -> Plane {
+        Plane {
+            normal: normal.normalize(),
+            d,
+        }
+    }
+
+    /// Creates a plane which contains `point` and faces in the direction of `normal`.
+    pub fn from_point_normal(point: Point3, normal: Vector3) -> Plane {
+        let normal = normal.normalize();
+        Plane {
+            normal,
+            d: normal.dot(point),
+        }
+    }
+
+    /// Returns the signed distance of `point` to the plane.
+    /// Positive values lie in front of the plane.
+    pub fn signed_distance(&self, point: &Point3) -> Real {
+        self.normal.dot(*point) - self.d
+    }
+
+    /// Classifies a point against the plane. Points on the plane are [`Straddling`].
+    ///
+    /// [`Straddling`]: enum.PlaneSide.html#variant.Straddling
+    ///
+    pub fn classify_point(&self, point: &Point3) -> PlaneSide {
+        let distance = self.signed_distance(point);
+        if distance > 0.0 {
+            PlaneSide::Front
+        } else if distance < 0.0 {
+            PlaneSide::Back
+        } else {
+            PlaneSide::Straddling
+        }
+    }
+
+    /// Classifies an [`AABB`] against the plane by projecting its half extents
+    /// onto the plane normal.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::plane::{Plane, PlaneSide};
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let plane = Plane::new(Vector3::new(1.0, 0.0, 0.0), 0.0);
+    /// let aabb = AABB::with_bounds(Point3::new(1.0, -1.0, -1.0), Point3::new(2.0, 1.0, 1.0));
+    /// assert_eq!(plane.classify_aabb(&aabb), PlaneSide::Front);
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(-1.0, -1.0, -1.0), Point3::new(2.0, 1.0, 1.0));
+    /// assert_eq!(plane.classify_aabb(&aabb), PlaneSide::Straddling);
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub fn classify_aabb(&self, aabb: &AABB) -> PlaneSide {
+        let center = aabb.center();
+        let half_size = aabb.size() * 0.5;
+        let radius = half_size.dot(self.normal.abs());
+        let distance = self.signed_distance(&center);
+        if distance > radius {
+            PlaneSide::Front
+        } else if distance < -radius {
+            PlaneSide::Back
+        } else {
+            PlaneSide::Straddling
+        }
+    }
+
+    /// Clips the plane to `bounds`, e.g. the [`AABB`] of the scene, so that it can be stored
+    /// in a [`BVH`] together with bounded shapes. A plane is unbounded, so it does not
+    /// implement [`Bounded`] itself. Returns `None` if the plane does not cross `bounds`.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::plane::Plane;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let scene = AABB::with_bounds(Point3::new(-10.0, -10.0, -10.0), Point3::new(10.0, 10.0, 10.0));
+    /// let ground = Plane::new(Vector3::new(0.0, 1.0, 0.0), -2.0);
+    /// let clipped = ground.clip(&scene).unwrap();
+    /// assert_eq!(clipped.aabb().min, Point3::new(-10.0, -2.0, -10.0));
+    /// assert_eq!(clipped.aabb().max, Point3::new(10.0, -2.0, 10.0));
+    ///
+    /// let sky = Plane::new(Vector3::new(0.0, 1.0, 0.0), 20.0);
+    /// assert!(sky.clip(&scene).is_none());
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`Bounded`]: ../aabb/trait.Bounded.html
+    ///
+    pub fn clip(&self, bounds: &AABB) -> Option<ClippedPlane> {
+        if self.classify_aabb(bounds) != PlaneSide::Straddling {
+            return None;
+        }
+
+        // The polygon in which the plane cuts the box is spanned by the points where it
+        // crosses the edges of the box.
+        let mut aabb = AABB::empty();
+        for vertex in bounds.vertices().iter() {
+            if self.signed_distance(vertex) == 0.0 {
+                aabb.grow_mut(vertex);
+            }
+        }
+        for (start, end) in bounds.edges().iter() {
+            let start_distance = self.signed_distance(start);
+            let end_distance = self.signed_distance(end);
+            if (start_distance < 0.0) != (end_distance < 0.0)
+                && start_distance != 0.0
+                && end_distance != 0.0
+            {
+                let fraction = start_distance / (start_distance - end_distance);
+                aabb.grow_mut(&(*start + (*end - *start) * fraction));
+            }
+        }
+
+        if aabb.is_empty() {
+            None
+        } else {
+            Some(ClippedPlane { plane: *self, aabb })
+        }
+    }
+}
+
+impl IntersectionAABB for Plane {
+    /// Returns true if the plane crosses or touches the [`AABB`].
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    fn intersects_aabb(&self, aabb: &AABB) -> bool {
+        self.classify_aabb(aabb) == PlaneSide::Straddling
+    }
+}
+
+impl IntersectionRay for Plane {
+    /// Intersects the ray with the plane. The `u` and `v` coordinates of the
+    /// [`Intersection`] are the coordinates of the hit in an arbitrary orthonormal basis of
+    /// the plane. Rays parallel to the plane never hit it.
+    ///
+    /// [`Intersection`]: ../ray/struct.Intersection.html
+    ///
+    fn intersects_ray(&self, ray: &Ray, t_min: Real, t_max: Real) -> Option<Intersection> {
+        let denominator = self.normal.dot(ray.direction);
+        if denominator.abs() < EPSILON {
+            return None;
+        }
+        let toi = -self.signed_distance(&ray.origin) / denominator;
+        if toi < t_min || t_max < toi {
+            return None;
+        }
+
+        let hit = ray.at(toi);
+        let (tangent, bitangent) = self.normal.any_orthonormal_pair();
+        let (norm, back_face) = ray.face_normal(self.normal);
+        Some(Intersection::new(
+            toi,
+            hit.dot(tangent),
+            hit.dot(bitangent),
+            norm,
+            back_face,
+        ))
+    }
+}
+
+/// A [`Plane`] clipped to a box, which can be stored in a [`BVH`], see [`Plane::clip`].
+///
+/// [`BVH`]: ../bvh/struct.BVH.html
+/// [`Plane`]: struct.Plane.html
+/// [`Plane::clip`]: struct.Plane.html#method.clip
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClippedPlane {
+    /// The unbounded plane
+    pub plane: Plane,
+    /// The bounds of the part of the plane inside the clipping box
+    pub aabb: AABB,
+}
+
+impl Bounded for ClippedPlane {
+    fn aabb(&self) -> AABB {
+        self.aabb
+    }
+}
+
+impl IntersectionRay for ClippedPlane {
+    /// Intersects the ray with the plane, but only reports hits inside the clipping box.
+    fn intersects_ray(&self, ray: &Ray, t_min: Real, t_max: Real) -> Option<Intersection> {
+        self.plane.intersects_ray(ray, t_min, t_max).filter(|hit| {
+            self.aabb
+                .approx_contains_eps(&ray.at(hit.distance), EPSILON)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::{Bounded, AABB};
+    use crate::bounding_hierarchy::BHShape;
+    use crate::bvh::BVH;
+    use crate::plane::{ClippedPlane, Plane, PlaneSide};
+    use crate::ray::{IntersectionRay, Ray};
+    use crate::{Point3, Real, Vector3, EPSILON};
+
+    #[test]
+    /// Tests whether points are classified by the sign of their distance.
+    fn test_classify_point() {
+        let plane =
+            Plane::from_point_normal(Point3::new(0.0, 0.0, 3.0), Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(
+            plane.classify_point(&Point3::new(1.0, 1.0, 4.0)),
+            PlaneSide::Front
+        );
+        assert_eq!(
+            plane.classify_point(&Point3::new(1.0, 1.0, 2.0)),
+            PlaneSide::Back
+        );
+        assert_eq!(
+            plane.classify_point(&Point3::new(1.0, 1.0, 3.0)),
+            PlaneSide::Straddling
+        );
+    }
+
+    #[test]
+    /// Tests the classification of `AABB`s against a tilted plane.
+    fn test_classify_aabb_tilted() {
+        let plane = Plane::new(Vector3::new(1.0, 1.0, 0.0), 0.0);
+        let unit = Vector3::new(0.5, 0.5, 0.5);
+
+        let front = AABB::with_bounds(Point3::splat(2.0) - unit, Point3::splat(2.0) + unit);
+        let back = AABB::with_bounds(Point3::splat(-2.0) - unit, Point3::splat(-2.0) + unit);
+        let straddling = AABB::with_bounds(
+            Point3::new(0.1, -0.1, 0.0) - unit,
+            Point3::new(0.1, -0.1, 0.0) + unit,
+        );
+
+        assert_eq!(plane.classify_aabb(&front), PlaneSide::Front);
+        assert_eq!(plane.classify_aabb(&back), PlaneSide::Back);
+        assert_eq!(plane.classify_aabb(&straddling), PlaneSide::Straddling);
+    }
+
+    #[test]
+    /// Tests hits from both sides, rays parallel to the plane and the ray interval.
+    fn test_plane_intersects_ray() {
+        let plane = Plane::new(Vector3::new(0.0, 0.0, 2.0), 3.0);
+        let ray = Ray::new(Point3::new(1.0, 2.0, 0.0), Vector3::new(0.0, 0.0, 1.0));
+        let hit = plane.intersects_ray(&ray, 0.0, Real::INFINITY).unwrap();
+        assert!((hit.distance - 3.0).abs() < EPSILON);
+        assert!(hit.back_face);
+        assert_eq!(hit.norm, Vector3::new(0.0, 0.0, -1.0));
+        assert!(plane.intersects_ray(&ray, 0.0, 2.0).is_none());
+
+        let ray = Ray::new(Point3::new(1.0, 2.0, 5.0), Vector3::new(0.0, 1.0, -1.0));
+        let hit = plane.intersects_ray(&ray, 0.0, Real::INFINITY).unwrap();
+        assert!((ray.at(hit.distance).z - 3.0).abs() < EPSILON);
+        assert!(!hit.back_face);
+
+        let parallel = Ray::new(Point3::new(1.0, 2.0, 0.0), Vector3::new(1.0, 1.0, 0.0));
+        assert!(plane
+            .intersects_ray(&parallel, 0.0, Real::INFINITY)
+            .is_none());
+    }
+
+    #[test]
+    /// Tests that a tilted plane clipped to a box is bounded by the polygon in which it cuts
+    /// the box, and that it can be stored in a `BVH` with hits restricted to the box.
+    fn test_clip_plane() {
+        let bounds = AABB::with_bounds(Point3::splat(-2.0), Point3::splat(2.0));
+        let plane = Plane::new(Vector3::new(1.0, 1.0, 0.0), 0.0);
+        let clipped = plane.clip(&bounds).unwrap();
+        assert!((clipped.aabb().min - Point3::new(-2.0, -2.0, -2.0)).length() < EPSILON);
+        assert!((clipped.aabb().max - Point3::new(2.0, 2.0, 2.0)).length() < EPSILON);
+
+        let plane = Plane::new(Vector3::new(1.0, 1.0, 1.0), Real::sqrt(3.0) * 1.5);
+        let clipped = plane.clip(&bounds).unwrap();
+        // The plane `x + y + z = 4.5` cuts the corner at (2, 2, 2) off the box.
+        assert!((clipped.aabb().min - Point3::splat(0.5)).length() < EPSILON);
+        assert!((clipped.aabb().max - Point3::splat(2.0)).length() < EPSILON);
+
+        let touching = Plane::new(Vector3::new(0.0, 1.0, 0.0), 2.0);
+        let clipped = touching.clip(&bounds).unwrap();
+        assert_eq!(clipped.aabb().min.y, 2.0);
+        assert_eq!(clipped.aabb().max.y, 2.0);
+        assert!(Plane::new(Vector3::new(0.0, 1.0, 0.0), 2.5)
+            .clip(&bounds)
+            .is_none());
+
+        struct PlaneShape {
+            plane: ClippedPlane,
+            node_index: usize,
+        }
+        impl Bounded for PlaneShape {
+            fn aabb(&self) -> AABB {
+                self.plane.aabb()
+            }
+        }
+        impl BHShape for PlaneShape {
+            fn set_bh_node_index(&mut self, index: usize) {
+                self.node_index = index;
+            }
+            fn bh_node_index(&self) -> usize {
+                self.node_index
+            }
+        }
+
+        let mut shapes: Vec<PlaneShape> = [-1.0, 0.0, 1.0]
+            .iter()
+            .map(|&d| PlaneShape {
+                plane: Plane::new(Vector3::new(0.0, 0.0, 1.0), d)
+                    .clip(&bounds)
+                    .unwrap(),
+                node_index: 0,
+            })
+            .collect();
+        let bvh = BVH::build(&mut shapes);
+        let ray = Ray::new(Point3::new(0.0, 0.0, -5.0), Vector3::new(0.0, 0.0, 1.0));
+        let hits = bvh.traverse(&ray, &shapes);
+        assert_eq!(hits.len(), 3);
+        assert!(hits.iter().all(|shape| shape
+            .plane
+            .intersects_ray(&ray, 0.0, Real::INFINITY)
+            .is_some()));
+
+        let outside = Ray::new(Point3::new(3.0, 0.0, -5.0), Vector3::new(0.0, 0.0, 1.0));
+        assert!(bvh.traverse(&outside, &shapes).is_empty());
+        assert!(shapes.iter().all(|shape| shape
+            .plane
+            .intersects_ray(&outside, 0.0, Real::INFINITY)
+            .is_none()));
+    }
+}
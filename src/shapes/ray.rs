@@ -1,10 +1,13 @@
 //! This module defines a Ray structure and intersection algorithms
 //! for axis aligned bounding boxes and triangles.
 
-use crate::aabb::AABB;
+use crate::aabb::{AABB, AABB4};
 use crate::bounding_hierarchy::IntersectionAABB;
-use crate::{Point3, Vector3};
-use crate::{Real, EPSILON};
+use crate::float::Tolerances;
+use crate::Real;
+use crate::{Mat4, Point3, Vector3};
+use std::cell::RefCell;
+use std::sync::Arc;
 
 /// A struct which defines a ray and some of its cached values.
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -22,26 +25,36 @@ pub struct Ray {
     ///
     inv_direction: Vector3,
 
-    /// Sign of the X direction. 0 means positive, 1 means negative.
+    /// Sign of the X direction. 0 means positive, 1 means negative, including `-0.0`
+    /// whose inverse is negative infinity.
     /// Cached for use in [`AABB`] intersections.
     ///
     /// [`AABB`]: struct.AABB.html
     ///
     sign_x: usize,
 
-    /// Sign of the Y direction. 0 means positive, 1 means negative.
+    /// Sign of the Y direction. 0 means positive, 1 means negative, including `-0.0`
+    /// whose inverse is negative infinity.
     /// Cached for use in [`AABB`] intersections.
     ///
     /// [`AABB`]: struct.AABB.html
     ///
     sign_y: usize,
 
-    /// Sign of the Z direction. 0 means positive, 1 means negative.
+    /// Sign of the Z direction. 0 means positive, 1 means negative, including `-0.0`
+    /// whose inverse is negative infinity.
     /// Cached for use in [`AABB`] intersections.
     ///
     /// [`AABB`]: struct.AABB.html
     ///
     sign_z: usize,
+
+    /// The time at which the ray samples moving shapes, such as [`Moving`] shapes.
+    /// `None` samples them at their beginning.
+    ///
+    /// [`Moving`]: ../moving/struct.Moving.html
+    ///
+    pub time: Option<Real>,
 }
 
 /// A struct which is returned by the `intersects_triangle` method.
@@ -75,8 +88,127 @@ impl Intersection {
             back_face,
         }
     }
+
+    /// Returns the point where `ray` hits the shape. The `ray` has to be the one which
+    /// produced the intersection.
+    pub fn point(&self, ray: &Ray) -> Point3 {
+        ray.at(self.distance)
+    }
+
+    /// Returns the barycentric coordinates of a triangle hit, which are the weights of
+    /// the first, second and third point of the triangle.
+    pub fn barycentric(&self) -> [Real; 3] {
+        [1.0 - self.u - self.v, self.u, self.v]
+    }
+
+    /// Interpolates per vertex `values` of a hit triangle, such as normals, texture
+    /// coordinates or colors, with the [`barycentric`] coordinates of the hit.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    /// use glam::{Vec2, Vec4};
+    ///
+    /// let a = Point3::new(0.0, 0.0, 0.0);
+    /// let b = Point3::new(1.0, 0.0, 0.0);
+    /// let c = Point3::new(0.0, 1.0, 0.0);
+    /// let ray = Ray::new(Point3::new(0.25, 0.5, 2.0), Vector3::new(0.0, 0.0, -1.0));
+    /// let hit = ray.intersects_triangle(&a, &b, &c);
+    ///
+    /// assert!(hit.interpolate(&[a, b, c]).abs_diff_eq(hit.point(&ray), 0.0001));
+    /// let uvs = [Vec2::new(0.0, 0.0), Vec2::new(2.0, 0.0), Vec2::new(0.0, 2.0)];
+    /// assert!(hit.interpolate(&uvs).abs_diff_eq(Vec2::new(0.5, 1.0), 0.0001));
+    /// let colors = [Vec4::new(1.0, 0.0, 0.0, 1.0), Vec4::new(0.0, 1.0, 0.0, 1.0), Vec4::new(0.0, 0.0, 1.0, 1.0)];
+    /// let color = hit.interpolate(&colors);
+    /// assert!(color.abs_diff_eq(Vec4::new(0.25, 0.25, 0.5, 1.0), 0.0001));
+    /// ```
+    ///
+    /// [`barycentric`]: struct.Intersection.html#method.barycentric
+    ///
+    pub fn interpolate<A: Lerp>(&self, values: &[A; 3]) -> A {
+        // Blends the second and third value first, whose combined weight is `u + v`.
+        let weight = self.u + self.v;
+        if weight == 0.0 {
+            return values[0];
+        }
+        let edge = values[1].lerp(values[2], self.v / weight);
+        values[0].lerp(edge, weight)
+    }
 }
 
+/// Values which can be interpolated linearly, such as the attributes of the vertices of a
+/// triangle. Used by [`Intersection::interpolate`].
+///
+/// [`Intersection::interpolate`]: struct.Intersection.html#method.interpolate
+///
+pub trait Lerp: Copy {
+    /// Returns the value at `t` on the line from `self` at `0` to `other` at `1`.
+    fn lerp(self, other: Self, t: Real) -> Self;
+}
+
+impl Lerp for Real {
+    fn lerp(self, other: Real, t: Real) -> Real {
+        self + (other - self) * t
+    }
+}
+
+/// Four [`Real`]s, which are processed with SIMD instructions where `glam` supports them.
+///
+/// [`Real`]: ../type.Real.html
+///
+#[cfg(not(feature = "f64"))]
+type Lanes = glam::Vec4;
+
+#[cfg(feature = "f64")]
+type Lanes = glam::DVec4;
+
+/// Returns the distances at which a ray enters and exits the slab between two planes along
+/// one axis, where `near` is the plane which the ray reaches first. A ray which is parallel
+/// to the slab and starts on one of its planes computes `0 * inf = NaN`. It runs inside the
+/// closed slab, so the slab does not constrain it.
+#[inline(always)]
+fn slab(near: Real, far: Real, origin: Real, inv_direction: Real) -> (Real, Real) {
+    // `max` and `min` return the other argument if one is `NaN`.
+    (
+        ((near - origin) * inv_direction).max(Real::NEG_INFINITY),
+        ((far - origin) * inv_direction).min(Real::INFINITY),
+    )
+}
+
+/// Orders the distances `t1` and `t2` at which a ray crosses the two planes of a slab into
+/// entry and exit, for slab tests which do not know which plane is reached first. If one is
+/// `NaN`, the ray is parallel to the slab and starts on one of its planes, see [`slab`].
+///
+/// [`slab`]: fn.slab.html
+///
+#[inline(always)]
+pub(crate) fn unordered_slab(t1: Real, t2: Real) -> (Real, Real) {
+    if t1.is_nan() || t2.is_nan() {
+        (Real::NEG_INFINITY, Real::INFINITY)
+    } else {
+        (t1.min(t2), t1.max(t2))
+    }
+}
+
+macro_rules! impl_lerp {
+    ($($vector:ty),*) => {
+        $(
+            impl Lerp for $vector {
+                fn lerp(self, other: $vector, t: Real) -> $vector {
+                    self + (other - self) * t
+                }
+            }
+        )*
+    };
+}
+
+#[cfg(not(feature = "f64"))]
+impl_lerp!(glam::Vec2, glam::Vec3, glam::Vec3A, glam::Vec4);
+
+#[cfg(feature = "f64")]
+impl_lerp!(glam::DVec2, glam::DVec3, glam::DVec4);
+
 /// This trait can be implemented on anything that can intersect with a `Ray`
 pub trait IntersectionRay {
     /// Returns true if there is an intersection with the given `Ray`
@@ -92,6 +224,43 @@ where
     }
 }
 
+impl<T: ?Sized> IntersectionRay for Arc<T>
+where
+    T: IntersectionRay,
+{
+    fn intersects_ray(&self, ray: &Ray, t_min: Real, t_max: Real) -> Option<Intersection> {
+        (**self).intersects_ray(ray, t_min, t_max)
+    }
+}
+
+impl<T: ?Sized> IntersectionRay for &mut T
+where
+    T: IntersectionRay,
+{
+    fn intersects_ray(&self, ray: &Ray, t_min: Real, t_max: Real) -> Option<Intersection> {
+        (**self).intersects_ray(ray, t_min, t_max)
+    }
+}
+
+impl<T: ?Sized> IntersectionRay for Box<T>
+where
+    T: IntersectionRay,
+{
+    fn intersects_ray(&self, ray: &Ray, t_min: Real, t_max: Real) -> Option<Intersection> {
+        (**self).intersects_ray(ray, t_min, t_max)
+    }
+}
+
+/// Borrows the value to intersect it, which panics while it is mutably borrowed.
+impl<T: ?Sized> IntersectionRay for RefCell<T>
+where
+    T: IntersectionRay,
+{
+    fn intersects_ray(&self, ray: &Ray, t_min: Real, t_max: Real) -> Option<Intersection> {
+        self.borrow().intersects_ray(ray, t_min, t_max)
+    }
+}
+
 impl IntersectionAABB for Ray {
     /// Tests the intersection of a [`Ray`] with an [`AABB`] using the optimized algorithm
     /// from [this paper](http://www.cs.utah.edu/~awilliam/box/box.pdf).
@@ -118,11 +287,19 @@ impl IntersectionAABB for Ray {
     /// [`AABB`]: struct.AABB.html
     ///
     fn intersects_aabb(&self, aabb: &AABB) -> bool {
-        let mut ray_min = (aabb[self.sign_x].x - self.origin.x) * self.inv_direction.x;
-        let mut ray_max = (aabb[1 - self.sign_x].x - self.origin.x) * self.inv_direction.x;
-
-        let y_min = (aabb[self.sign_y].y - self.origin.y) * self.inv_direction.y;
-        let y_max = (aabb[1 - self.sign_y].y - self.origin.y) * self.inv_direction.y;
+        let (mut ray_min, mut ray_max) = slab(
+            aabb[self.sign_x].x,
+            aabb[1 - self.sign_x].x,
+            self.origin.x,
+            self.inv_direction.x,
+        );
+
+        let (y_min, y_max) = slab(
+            aabb[self.sign_y].y,
+            aabb[1 - self.sign_y].y,
+            self.origin.y,
+            self.inv_direction.y,
+        );
 
         if (ray_min > y_max) || (y_min > ray_max) {
             return false;
@@ -140,8 +317,12 @@ impl IntersectionAABB for Ray {
         // Using the following solution significantly decreases the performance
         // ray_max = ray_max.min(y_max);
 
-        let z_min = (aabb[self.sign_z].z - self.origin.z) * self.inv_direction.z;
-        let z_max = (aabb[1 - self.sign_z].z - self.origin.z) * self.inv_direction.z;
+        let (z_min, z_max) = slab(
+            aabb[self.sign_z].z,
+            aabb[1 - self.sign_z].z,
+            self.origin.z,
+            self.inv_direction.z,
+        );
 
         if (ray_min > z_max) || (z_min > ray_max) {
             return false;
@@ -182,17 +363,115 @@ impl Ray {
     /// [`Ray`]: struct.Ray.html
     ///
     pub fn new(origin: Point3, direction: Vector3) -> Ray {
-        let direction = direction.normalize();
+        Ray::new_unchecked(origin, direction.normalize())
+    }
+
+    /// Creates a new [`Ray`] from an `origin` and a `direction` which is already normalized,
+    /// skipping the normalization of [`Ray::new`]. The distances of intersections are only
+    /// measured correctly if the `direction` has a length of one.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3,Vector3};
+    ///
+    /// let origin = Point3::new(0.0,0.0,0.0);
+    /// let direction = Vector3::new(0.0,0.6,0.8);
+    /// assert_eq!(Ray::new_unchecked(origin, direction), Ray::new(origin, direction));
+    /// ```
+    ///
+    /// [`Ray`]: struct.Ray.html
+    /// [`Ray::new`]: struct.Ray.html#method.new
+    ///
+    pub fn new_unchecked(origin: Point3, direction: Vector3) -> Ray {
         Ray {
             origin,
             direction,
             inv_direction: Vector3::new(1.0 / direction.x, 1.0 / direction.y, 1.0 / direction.z),
-            sign_x: (direction.x < 0.0) as usize,
-            sign_y: (direction.y < 0.0) as usize,
-            sign_z: (direction.z < 0.0) as usize,
+            sign_x: direction.x.is_sign_negative() as usize,
+            sign_y: direction.y.is_sign_negative() as usize,
+            sign_z: direction.z.is_sign_negative() as usize,
+            time: None,
         }
     }
 
+    /// Creates a [`Ray`] from `origin` towards `target`, and returns it with the distance
+    /// between the points, which is the `t_max` of a test for shapes between them.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3,Vector3};
+    ///
+    /// let (ray, distance) = Ray::from_points(Point3::new(1.0,0.0,0.0), Point3::new(1.0,3.0,4.0));
+    /// assert_eq!(ray.direction, Vector3::new(0.0,0.6,0.8));
+    /// assert_eq!(distance, 5.0);
+    /// ```
+    ///
+    /// [`Ray`]: struct.Ray.html
+    ///
+    pub fn from_points(origin: Point3, target: Point3) -> (Ray, Real) {
+        let offset = target - origin;
+        let distance = offset.length();
+        (Ray::new_unchecked(origin, offset / distance), distance)
+    }
+
+    /// Returns the ray with its `time` set, for example a time within the shutter interval
+    /// of a camera rendering with motion blur.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3,Vector3};
+    ///
+    /// let ray = Ray::new(Point3::new(0.0,0.0,0.0), Vector3::new(1.0,0.0,0.0));
+    /// assert_eq!(ray.time, None);
+    /// assert_eq!(ray.with_time(0.5).time, Some(0.5));
+    /// ```
+    pub fn with_time(mut self, time: Real) -> Ray {
+        self.time = Some(time);
+        self
+    }
+
+    /// Transforms the ray with `transform`, for example from world space into the space of
+    /// an instance, and recomputes its cached values. The `time` is kept. As the direction
+    /// is normalized again, distances along the rays differ by the returned scale: a distance
+    /// `t` along this ray is the distance `t * scale` along the transformed ray.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::ray::Ray;
+    /// use bvh::sphere::Sphere;
+    /// use bvh::ray::IntersectionRay;
+    /// use bvh::{Mat4, Point3, Vector3};
+    ///
+    /// // A unit sphere scaled to a radius of 2 and moved to (10, 0, 0).
+    /// let transform = Mat4::from_translation(Vector3::new(10.0, 0.0, 0.0)) * Mat4::from_scale(Vector3::splat(2.0));
+    /// let sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0);
+    ///
+    /// let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+    /// let (local_ray, scale) = ray.transformed(&transform.inverse());
+    /// assert_eq!(scale, 0.5);
+    /// let hit = sphere.intersects_ray(&local_ray, 0.0, f32::INFINITY).unwrap();
+    /// assert_eq!(hit.distance / scale, 8.0);
+    /// ```
+    pub fn transformed(&self, transform: &Mat4) -> (Ray, Real) {
+        let direction = transform.transform_vector3(self.direction);
+        let scale = direction.length();
+        let mut ray = Ray::new(transform.transform_point3(self.origin), direction);
+        ray.time = self.time;
+        (ray, scale)
+    }
+
+    /// Transforms the ray with the inverse of `transform`, for example from world space into
+    /// the local space of a shape placed with `transform`. See [`transformed`].
+    ///
+    /// [`transformed`]: struct.Ray.html#method.transformed
+    ///
+    pub fn inverse_transformed(&self, transform: &Mat4) -> (Ray, Real) {
+        self.transformed(&transform.inverse())
+    }
+
     /// Naive implementation of a [`Ray`]/[`AABB`] intersection algorithm.
     ///
     /// # Examples
@@ -225,17 +504,14 @@ impl Ray {
         let hit_min_z = (aabb.min.z - self.origin.z) * self.inv_direction.z;
         let hit_max_z = (aabb.max.z - self.origin.z) * self.inv_direction.z;
 
-        let x_entry = hit_min_x.min(hit_max_x);
-        let y_entry = hit_min_y.min(hit_max_y);
-        let z_entry = hit_min_z.min(hit_max_z);
-        let x_exit = hit_min_x.max(hit_max_x);
-        let y_exit = hit_min_y.max(hit_max_y);
-        let z_exit = hit_min_z.max(hit_max_z);
+        let (x_entry, x_exit) = unordered_slab(hit_min_x, hit_max_x);
+        let (y_entry, y_exit) = unordered_slab(hit_min_y, hit_max_y);
+        let (z_entry, z_exit) = unordered_slab(hit_min_z, hit_max_z);
 
         let latest_entry = x_entry.max(y_entry).max(z_entry);
         let earliest_exit = x_exit.min(y_exit).min(z_exit);
 
-        latest_entry < earliest_exit && earliest_exit > 0.0
+        latest_entry <= earliest_exit && earliest_exit > 0.0
     }
 
     /// Implementation of the algorithm described [here]
@@ -265,32 +541,341 @@ impl Ray {
         let tx1 = (aabb.min.x - self.origin.x) * self.inv_direction.x;
         let tx2 = (aabb.max.x - self.origin.x) * self.inv_direction.x;
 
-        let mut tmin = tx1.min(tx2);
-        let mut tmax = tx1.max(tx2);
+        let (mut tmin, mut tmax) = unordered_slab(tx1, tx2);
 
         let ty1 = (aabb.min.y - self.origin.y) * self.inv_direction.y;
         let ty2 = (aabb.max.y - self.origin.y) * self.inv_direction.y;
 
-        tmin = tmin.max(ty1.min(ty2));
-        tmax = tmax.min(ty1.max(ty2));
+        let (ty_min, ty_max) = unordered_slab(ty1, ty2);
+        tmin = tmin.max(ty_min);
+        tmax = tmax.min(ty_max);
 
         let tz1 = (aabb.min.z - self.origin.z) * self.inv_direction.z;
         let tz2 = (aabb.max.z - self.origin.z) * self.inv_direction.z;
 
-        tmin = tmin.max(tz1.min(tz2));
-        tmax = tmax.min(tz1.max(tz2));
+        let (tz_min, tz_max) = unordered_slab(tz1, tz2);
+        tmin = tmin.max(tz_min);
+        tmax = tmax.min(tz_max);
 
         tmax >= tmin && tmax >= 0.0
     }
 
+    /// Tests the [`Ray`] against the four [`AABB`]s of an [`AABB4`] at once, using SIMD
+    /// instructions where `glam` supports them. Returns a mask which has bit `i` set if the
+    /// [`AABB`] in lane `i` is hit within `[t_min, t_max]`, and the distances at which the
+    /// [`Ray`] enters the [`AABB`]s, clamped to `t_min`. The distances of missed lanes are
+    /// meaningless. This is the inner loop of traversing a [`WideBVH`] with four children
+    /// per node.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, AABB4};
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let cube = |x: f32| AABB::with_bounds(Point3::new(x, -1.0, -1.0), Point3::new(x + 1.0, 1.0, 1.0));
+    /// let aabbs = AABB4::new(&[cube(2.0), cube(-5.0), cube(8.0)]);
+    /// let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+    ///
+    /// let (mask, entries) = ray.intersects_aabb4(&aabbs, 0.0, 5.0);
+    /// assert_eq!(mask, 0b0001);
+    /// assert_eq!(entries[0], 2.0);
+    /// ```
+    ///
+    /// [`Ray`]: struct.Ray.html
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`AABB4`]: ../aabb/struct.AABB4.html
+    /// [`WideBVH`]: ../wide_bvh/struct.WideBVH.html
+    ///
+    pub fn intersects_aabb4(&self, aabbs: &AABB4, t_min: Real, t_max: Real) -> (u32, [Real; 4]) {
+        // Like `intersects_aabb`, the planes which are entered first are chosen by the sign
+        // of the direction, so that empty lanes enter at infinity and are never hit. `NaN`s
+        // are replaced explicitly like in `slab`, as SIMD `min` and `max` may keep them.
+        let slab = |min: &[Real; 4], max: &[Real; 4], origin: Real, inv_direction: Real, sign| {
+            let (near, far) = if sign == 0 { (min, max) } else { (max, min) };
+            let origin = Lanes::splat(origin);
+            let inv_direction = Lanes::splat(inv_direction);
+            let near = (Lanes::from(*near) - origin) * inv_direction;
+            let far = (Lanes::from(*far) - origin) * inv_direction;
+            (
+                Lanes::select(near.is_nan_mask(), Lanes::splat(Real::NEG_INFINITY), near),
+                Lanes::select(far.is_nan_mask(), Lanes::splat(Real::INFINITY), far),
+            )
+        };
+        let (near_x, far_x) = slab(
+            &aabbs.min_x,
+            &aabbs.max_x,
+            self.origin.x,
+            self.inv_direction.x,
+            self.sign_x,
+        );
+        let (near_y, far_y) = slab(
+            &aabbs.min_y,
+            &aabbs.max_y,
+            self.origin.y,
+            self.inv_direction.y,
+            self.sign_y,
+        );
+        let (near_z, far_z) = slab(
+            &aabbs.min_z,
+            &aabbs.max_z,
+            self.origin.z,
+            self.inv_direction.z,
+            self.sign_z,
+        );
+        let entry = near_x.max(near_y).max(near_z).max(Lanes::splat(t_min));
+        let exit = far_x.min(far_y).min(far_z).min(Lanes::splat(t_max));
+        (entry.cmple(exit).bitmask(), entry.to_array())
+    }
+
     /// Implementation of the [Möller-Trumbore triangle/ray intersection algorithm]
     /// (https://en.wikipedia.org/wiki/M%C3%B6ller%E2%80%93Trumbore_intersection_algorithm).
     /// Returns the distance to the intersection, as well as
     /// the u and v coordinates of the intersection.
     /// The distance is set to +INFINITY if the ray does not intersect the triangle, or hits
-    /// it from behind.
-    #[allow(clippy::many_single_char_names)]
+    /// it from behind. See [`intersects_triangle_double_sided`] for a variant which does
+    /// not cull back faces. Like all triangle tests, it never hits degenerate triangles
+    /// without area or thinner than [`Tolerances::sliver`], see
+    /// [`Tolerances::is_degenerate`]. The [`IntersectionRay`] implementation of [`Triangle`]
+    /// additionally restricts hits to the interval `t_min..=t_max`.
+    ///
+    /// [`IntersectionRay`]: trait.IntersectionRay.html
+    /// [`Triangle`]: ../triangle/struct.Triangle.html
+    /// [`intersects_triangle_double_sided`]: struct.Ray.html#method.intersects_triangle_double_sided
+    /// [`Tolerances::sliver`]: ../float/struct.Tolerances.html#structfield.sliver
+    /// [`Tolerances::is_degenerate`]: ../float/struct.Tolerances.html#method.is_degenerate
+    ///
     pub fn intersects_triangle(&self, a: &Point3, b: &Point3, c: &Point3) -> Intersection {
+        self.intersects_triangle_eps(a, b, c, &Tolerances::default())
+    }
+
+    /// Like [`intersects_triangle`], but with the given [`Tolerances`] instead of the
+    /// default ones.
+    ///
+    /// [`intersects_triangle`]: struct.Ray.html#method.intersects_triangle
+    /// [`Tolerances`]: ../float/struct.Tolerances.html
+    ///
+    pub fn intersects_triangle_eps(
+        &self,
+        a: &Point3,
+        b: &Point3,
+        c: &Point3,
+        tolerances: &Tolerances,
+    ) -> Intersection {
+        self.intersects_triangle_culling(
+            a,
+            b,
+            c,
+            true,
+            Real::NEG_INFINITY,
+            Real::INFINITY,
+            tolerances,
+        )
+    }
+
+    /// Like [`intersects_triangle`], but also hits the triangle from behind. Hits on the
+    /// back face, which is the side where the points appear clockwise, have `back_face`
+    /// set and a normal which is flipped to face the ray. This suits thin geometry, shadow
+    /// rays and views from inside closed meshes.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let a = Point3::new(0.0, 0.0, 0.0);
+    /// let b = Point3::new(1.0, 0.0, 0.0);
+    /// let c = Point3::new(0.0, 1.0, 0.0);
+    /// let from_below = Ray::new(Point3::new(0.25, 0.25, -2.0), Vector3::new(0.0, 0.0, 1.0));
+    ///
+    /// assert_eq!(from_below.intersects_triangle(&a, &b, &c).distance, f32::INFINITY);
+    /// let hit = from_below.intersects_triangle_double_sided(&a, &b, &c);
+    /// assert_eq!(hit.distance, 2.0);
+    /// assert!(hit.back_face);
+    /// assert_eq!(hit.norm, Vector3::new(0.0, 0.0, -1.0));
+    /// ```
+    ///
+    /// [`intersects_triangle`]: struct.Ray.html#method.intersects_triangle
+    ///
+    pub fn intersects_triangle_double_sided(
+        &self,
+        a: &Point3,
+        b: &Point3,
+        c: &Point3,
+    ) -> Intersection {
+        self.intersects_triangle_double_sided_eps(a, b, c, &Tolerances::default())
+    }
+
+    /// Like [`intersects_triangle_double_sided`], but with the given [`Tolerances`]
+    /// instead of the default ones.
+    ///
+    /// [`intersects_triangle_double_sided`]: struct.Ray.html#method.intersects_triangle_double_sided
+    /// [`Tolerances`]: ../float/struct.Tolerances.html
+    ///
+    pub fn intersects_triangle_double_sided_eps(
+        &self,
+        a: &Point3,
+        b: &Point3,
+        c: &Point3,
+        tolerances: &Tolerances,
+    ) -> Intersection {
+        self.intersects_triangle_culling(
+            a,
+            b,
+            c,
+            false,
+            Real::NEG_INFINITY,
+            Real::INFINITY,
+            tolerances,
+        )
+    }
+
+    /// Implementation of the [watertight ray/triangle intersection algorithm] by Woop, Benthin
+    /// and Wald. The triangle is transformed into a space where the ray starts at the origin
+    /// and points along an axis, so the edge tests of neighboring triangles use the same
+    /// values for their shared edges. Rays can therefore not slip through the edges of a
+    /// closed mesh, unlike with [`intersects_triangle`]. Both faces are hit, and the result
+    /// is reported like in [`intersects_triangle_double_sided`].
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let a = Point3::new(0.0, 0.0, 0.0);
+    /// let b = Point3::new(1.0, 0.0, 0.0);
+    /// let c = Point3::new(0.0, 1.0, 0.0);
+    /// let ray = Ray::new(Point3::new(0.25, 0.5, 2.0), Vector3::new(0.0, 0.0, -1.0));
+    ///
+    /// let hit = ray.intersects_triangle_watertight(&a, &b, &c);
+    /// assert_eq!(hit.distance, 2.0);
+    /// assert_eq!((hit.u, hit.v), (0.25, 0.5));
+    /// assert!(!hit.back_face);
+    /// ```
+    ///
+    /// [watertight ray/triangle intersection algorithm]: https://jcgt.org/published/0002/01/05/
+    /// [`intersects_triangle`]: struct.Ray.html#method.intersects_triangle
+    /// [`intersects_triangle_double_sided`]: struct.Ray.html#method.intersects_triangle_double_sided
+    ///
+    pub fn intersects_triangle_watertight(
+        &self,
+        a: &Point3,
+        b: &Point3,
+        c: &Point3,
+    ) -> Intersection {
+        self.intersects_triangle_watertight_eps(a, b, c, &Tolerances::default())
+    }
+
+    /// Like [`intersects_triangle_watertight`], but with the given [`Tolerances`] instead
+    /// of the default ones. Only the `min_distance` applies, as the watertight test
+    /// needs no tolerance for rays which are parallel to the triangle.
+    ///
+    /// [`intersects_triangle_watertight`]: struct.Ray.html#method.intersects_triangle_watertight
+    /// [`Tolerances`]: ../float/struct.Tolerances.html
+    ///
+    pub fn intersects_triangle_watertight_eps(
+        &self,
+        a: &Point3,
+        b: &Point3,
+        c: &Point3,
+        tolerances: &Tolerances,
+    ) -> Intersection {
+        self.intersects_triangle_watertight_interval(
+            a,
+            b,
+            c,
+            Real::NEG_INFINITY,
+            Real::INFINITY,
+            tolerances,
+        )
+    }
+
+    /// The watertight test, which misses if the distance lies outside of `t_min..=t_max`.
+    #[allow(clippy::many_single_char_names)]
+    pub(crate) fn intersects_triangle_watertight_interval(
+        &self,
+        a: &Point3,
+        b: &Point3,
+        c: &Point3,
+        t_min: Real,
+        t_max: Real,
+        tolerances: &Tolerances,
+    ) -> Intersection {
+        let miss = Intersection::new(Real::INFINITY, 0.0, 0.0, Vector3::ZERO, false);
+
+        // Make the largest component of the direction the z axis, and swap x and y to keep
+        // the winding of the triangle if it is negative.
+        let abs = self.direction.abs();
+        let kz = if abs.x > abs.y && abs.x > abs.z {
+            0
+        } else if abs.y > abs.z {
+            1
+        } else {
+            2
+        };
+        let (mut kx, mut ky) = ((kz + 1) % 3, (kz + 2) % 3);
+        if self.direction[kz] < 0.0 {
+            std::mem::swap(&mut kx, &mut ky);
+        }
+
+        // The shear which maps the direction onto the z axis.
+        let shear_x = self.direction[kx] / self.direction[kz];
+        let shear_y = self.direction[ky] / self.direction[kz];
+        let shear_z = 1.0 / self.direction[kz];
+
+        let a = *a - self.origin;
+        let b = *b - self.origin;
+        let c = *c - self.origin;
+        let (ax, ay) = (a[kx] - shear_x * a[kz], a[ky] - shear_y * a[kz]);
+        let (bx, by) = (b[kx] - shear_x * b[kz], b[ky] - shear_y * b[kz]);
+        let (cx, cy) = (c[kx] - shear_x * c[kz], c[ky] - shear_y * c[kz]);
+
+        // Scaled barycentric coordinates from the edge functions. Redo them in double
+        // precision if one of them is zero, as the ray then passes through an edge.
+        let mut weight_a = cx * by - cy * bx;
+        let mut weight_b = ax * cy - ay * cx;
+        let mut weight_c = bx * ay - by * ax;
+        if weight_a == 0.0 || weight_b == 0.0 || weight_c == 0.0 {
+            weight_a = difference_of_products(cx, by, cy, bx);
+            weight_b = difference_of_products(ax, cy, ay, cx);
+            weight_c = difference_of_products(bx, ay, by, ax);
+        }
+        let negative = weight_a < 0.0 || weight_b < 0.0 || weight_c < 0.0;
+        let positive = weight_a > 0.0 || weight_b > 0.0 || weight_c > 0.0;
+        if negative && positive {
+            return miss;
+        }
+        let det = weight_a + weight_b + weight_c;
+        if det == 0.0 {
+            return miss;
+        }
+
+        let scaled_distance = shear_z * (weight_a * a[kz] + weight_b * b[kz] + weight_c * c[kz]);
+        let dist = scaled_distance / det;
+        let outside = !(t_min..=t_max).contains(&dist);
+        if dist <= tolerances.min_distance || outside || tolerances.is_degenerate(&a, &b, &c) {
+            return miss;
+        }
+
+        let normal = (b - a).cross(c - a);
+        let back_face = normal.dot(self.direction) > 0.0;
+        let normal = if back_face { -normal } else { normal };
+        Intersection::new(dist, weight_b / det, weight_c / det, normal, back_face)
+    }
+
+    /// The Möller-Trumbore algorithm, which culls back faces if `cull` is set and misses if
+    /// the distance lies outside of `t_min..=t_max`.
+    #[allow(clippy::many_single_char_names, clippy::too_many_arguments)]
+    pub(crate) fn intersects_triangle_culling(
+        &self,
+        a: &Point3,
+        b: &Point3,
+        c: &Point3,
+        cull: bool,
+        t_min: Real,
+        t_max: Real,
+        tolerances: &Tolerances,
+    ) -> Intersection {
         let a_to_b = *b - *a;
         let a_to_c = *c - *a;
 
@@ -304,10 +889,14 @@ impl Ray {
         // det = 0 => [dir, a_to_b, a_to_c] not linearly independant
         let det = a_to_b.dot(u_vec);
 
-        // Only testing positive bound, thus enabling backface culling
-        // If backface culling is not desired write:
-        // det < EPSILON && det > -EPSILON
-        if det < EPSILON {
+        // When culling, only test the positive bound, as a negative determinant means that
+        // the ray sees the back face.
+        let parallel = if cull {
+            det < tolerances.parallel
+        } else {
+            det.abs() < tolerances.parallel
+        };
+        if parallel {
             return Intersection::new(Real::INFINITY, 0.0, 0.0, Vector3::ZERO, false);
         }
 
@@ -336,12 +925,17 @@ impl Ray {
 
         let dist = a_to_c.dot(v_vec) * inv_det;
 
-        if dist > EPSILON {
+        let inside = (t_min..=t_max).contains(&dist);
+        if dist > tolerances.min_distance && inside && !tolerances.is_degenerate(a, b, c) {
             let mut normal = Vector3::ZERO;
             normal.x = (a_to_b.y * a_to_c.z) - (a_to_b.z * a_to_c.y);
             normal.y = (a_to_b.z * a_to_c.x) - (a_to_b.x * a_to_c.z);
             normal.z = (a_to_b.x * a_to_c.y) - (a_to_b.y * a_to_c.x);
-            Intersection::new(dist, u, v, normal, false)
+            let back_face = det < 0.0;
+            if back_face {
+                normal = -normal;
+            }
+            Intersection::new(dist, u, v, normal, back_face)
         } else {
             Intersection::new(Real::INFINITY, u, v, Vector3::ZERO, false)
         }
@@ -349,13 +943,21 @@ impl Ray {
 
     /// Returns the t_min of the aabb intersection
     pub fn intersects_aabb_dist(&self, aabb: &AABB) -> Option<Real> {
-        let x_min = (aabb[self.sign_x].x - self.origin.x) * self.inv_direction.x;
-        let x_max = (aabb[1 - self.sign_x].x - self.origin.x) * self.inv_direction.x;
+        let (x_min, x_max) = slab(
+            aabb[self.sign_x].x,
+            aabb[1 - self.sign_x].x,
+            self.origin.x,
+            self.inv_direction.x,
+        );
         let mut ray_min = x_min;
         let mut ray_max = x_max;
 
-        let y_min = (aabb[self.sign_y].y - self.origin.y) * self.inv_direction.y;
-        let y_max = (aabb[1 - self.sign_y].y - self.origin.y) * self.inv_direction.y;
+        let (y_min, y_max) = slab(
+            aabb[self.sign_y].y,
+            aabb[1 - self.sign_y].y,
+            self.origin.y,
+            self.inv_direction.y,
+        );
 
         if (ray_min > y_max) || (y_min > ray_max) {
             return None;
@@ -373,8 +975,12 @@ impl Ray {
         // Using the following solution significantly decreases the performance
         // ray_max = ray_max.min(y_max);
 
-        let z_min = (aabb[self.sign_z].z - self.origin.z) * self.inv_direction.z;
-        let z_max = (aabb[1 - self.sign_z].z - self.origin.z) * self.inv_direction.z;
+        let (z_min, z_max) = slab(
+            aabb[self.sign_z].z,
+            aabb[1 - self.sign_z].z,
+            self.origin.z,
+            self.inv_direction.z,
+        );
 
         if (ray_min > z_max) || (z_min > ray_max) {
             return None;
@@ -399,6 +1005,73 @@ impl Ray {
         }
     }
 
+    /// Returns the distances along the [`Ray`] at which it enters and exits `aabb`,
+    /// or `None` if the [`Ray`] misses `aabb` or `aabb` lies behind the origin.
+    /// The entry distance is negative if the origin lies inside `aabb`.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3,Vector3};
+    ///
+    /// let ray = Ray::new(Point3::new(0.0,0.0,0.0), Vector3::new(1.0,0.0,0.0));
+    /// let aabb = AABB::with_bounds(Point3::new(2.0,-1.0,-1.0), Point3::new(3.0,1.0,1.0));
+    ///
+    /// assert_eq!(ray.intersects_aabb_interval(&aabb), Some((2.0, 3.0)));
+    /// ```
+    ///
+    /// [`Ray`]: struct.Ray.html
+    ///
+    pub fn intersects_aabb_interval(&self, aabb: &AABB) -> Option<(Real, Real)> {
+        let (mut ray_min, mut ray_max) = slab(
+            aabb[self.sign_x].x,
+            aabb[1 - self.sign_x].x,
+            self.origin.x,
+            self.inv_direction.x,
+        );
+
+        let (y_min, y_max) = slab(
+            aabb[self.sign_y].y,
+            aabb[1 - self.sign_y].y,
+            self.origin.y,
+            self.inv_direction.y,
+        );
+
+        if (ray_min > y_max) || (y_min > ray_max) {
+            return None;
+        }
+        if y_min > ray_min {
+            ray_min = y_min;
+        }
+        if y_max < ray_max {
+            ray_max = y_max;
+        }
+
+        let (z_min, z_max) = slab(
+            aabb[self.sign_z].z,
+            aabb[1 - self.sign_z].z,
+            self.origin.z,
+            self.inv_direction.z,
+        );
+
+        if (ray_min > z_max) || (z_min > ray_max) {
+            return None;
+        }
+        if z_min > ray_min {
+            ray_min = z_min;
+        }
+        if z_max < ray_max {
+            ray_max = z_max;
+        }
+
+        if ray_max < 0.0 {
+            None
+        } else {
+            Some((ray_min, ray_max))
+        }
+    }
+
     /// Returns the position the front of the `Ray` is after traveling dist
     pub fn at(&self, dist: Real) -> Vector3 {
         self.origin + (self.direction * dist)
@@ -410,6 +1083,197 @@ impl Ray {
         let norm = if back_face { -out_norm } else { out_norm };
         (norm, back_face)
     }
+
+    /// Moves the origin of the ray by `epsilon` along `normal`, to the side of the surface
+    /// into which the ray points. A ray which starts on a surface, such as a shadow ray, can
+    /// then not hit that surface again due to rounding errors.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let normal = Vector3::new(0.0, 1.0, 0.0);
+    /// let up = Ray::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 1.0, 0.0));
+    /// assert_eq!(up.offset_along_normal(normal, 0.01).origin, Point3::new(0.0, 0.01, 0.0));
+    /// let down = Ray::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(1.0, -1.0, 0.0));
+    /// assert_eq!(down.offset_along_normal(normal, 0.01).origin, Point3::new(0.0, -0.01, 0.0));
+    /// ```
+    pub fn offset_along_normal(mut self, normal: Vector3, epsilon: Real) -> Ray {
+        let normal = normal.normalize_or_zero();
+        let side = if self.direction.dot(normal) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        self.origin += normal * (side * epsilon);
+        self
+    }
+
+    /// Moves the origin of the ray off a surface with the geometric `normal`, to the side
+    /// into which the ray points, so that a secondary ray spawned at a hit does not hit the
+    /// same surface again. Unlike [`offset_along_normal`], the offset scales with the
+    /// precision of the coordinates: each coordinate is moved by a fixed number of units in
+    /// the last place, and by a small fixed distance close to the origin, following
+    /// [A Fast and Robust Method for Avoiding Self-Intersection] from Ray Tracing Gems.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let normal = Vector3::new(0.0, 1.0, 0.0);
+    /// let near = Ray::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 1.0, 0.0));
+    /// let near_offset = near.offset_origin(normal).origin.y;
+    /// let far = Ray::new(Point3::new(0.0, 1000.0, 0.0), Vector3::new(1.0, 1.0, 0.0));
+    /// let far_offset = far.offset_origin(normal).origin.y - 1000.0;
+    /// assert!(0.0 < near_offset && near_offset < 0.0001);
+    /// assert!(near_offset < far_offset && far_offset < 0.1);
+    /// ```
+    ///
+    /// [`offset_along_normal`]: struct.Ray.html#method.offset_along_normal
+    /// [A Fast and Robust Method for Avoiding Self-Intersection]: https://link.springer.com/chapter/10.1007/978-1-4842-4427-2_6
+    ///
+    pub fn offset_origin(mut self, normal: Vector3) -> Ray {
+        let mut normal = normal.normalize_or_zero();
+        if self.direction.dot(normal) < 0.0 {
+            normal = -normal;
+        }
+        self.origin = offset_point(self.origin, normal);
+        self
+    }
+
+    /// Returns the ray reflected at the point where it hits the surface of `hit`, which has
+    /// to be an intersection of this ray. The origin is offset from the surface, see
+    /// [`offset_origin`], and the `time` is kept.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::ray::{IntersectionRay, Ray};
+    /// use bvh::sphere::Sphere;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0);
+    /// let ray = Ray::new(Point3::new(-5.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+    /// let hit = sphere.intersects_ray(&ray, 0.0, f32::INFINITY).unwrap();
+    ///
+    /// let reflected = ray.reflect(&hit);
+    /// assert_eq!(reflected.direction, Vector3::new(-1.0, 0.0, 0.0));
+    /// assert!(sphere.intersects_ray(&reflected, 0.0, f32::INFINITY).is_none());
+    /// ```
+    ///
+    /// [`offset_origin`]: struct.Ray.html#method.offset_origin
+    ///
+    pub fn reflect(&self, hit: &Intersection) -> Ray {
+        let normal = hit.norm.normalize_or_zero();
+        let direction = self.direction - normal * (2.0 * self.direction.dot(normal));
+        self.secondary(hit, direction)
+    }
+
+    /// Returns the ray refracted at the point where it hits the surface of `hit`, which has
+    /// to be an intersection of this ray, following Snell's law. `eta` is the refractive
+    /// index of the medium the ray comes from divided by the one it enters, e.g. `1.0 / 1.5`
+    /// from air into glass. Returns `None` on total internal reflection, in which case
+    /// [`reflect`] gives the continued ray. The origin is offset from the surface to the
+    /// side the ray enters, see [`offset_origin`], and the `time` is kept.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::ray::{IntersectionRay, Ray};
+    /// use bvh::sphere::Sphere;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0);
+    /// let ray = Ray::new(Point3::new(-5.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+    /// let hit = sphere.intersects_ray(&ray, 0.0, f32::INFINITY).unwrap();
+    ///
+    /// // Rays along the normal pass straight through.
+    /// let inside = ray.refract(&hit, 1.0 / 1.5).unwrap();
+    /// assert_eq!(inside.direction, Vector3::new(1.0, 0.0, 0.0));
+    /// assert!(inside.origin.x > -1.0);
+    /// ```
+    ///
+    /// [`offset_origin`]: struct.Ray.html#method.offset_origin
+    /// [`reflect`]: struct.Ray.html#method.reflect
+    ///
+    pub fn refract(&self, hit: &Intersection, eta: Real) -> Option<Ray> {
+        // The normal has to face the incoming ray.
+        let mut normal = hit.norm.normalize_or_zero();
+        if self.direction.dot(normal) > 0.0 {
+            normal = -normal;
+        }
+        let cos_incident = -self.direction.dot(normal);
+        let cos_squared = 1.0 - eta * eta * (1.0 - cos_incident * cos_incident);
+        if cos_squared < 0.0 {
+            return None;
+        }
+        let direction = self.direction * eta + normal * (eta * cos_incident - cos_squared.sqrt());
+        Some(self.secondary(hit, direction))
+    }
+
+    /// Creates a ray in `direction` from the point of `hit`, offset from the surface with
+    /// [`offset_origin`]. The point is computed along this ray, so its rounding errors grow
+    /// with the origin of the ray and the distance of the hit, and it is first offset by
+    /// a corresponding margin.
+    ///
+    /// [`offset_origin`]: struct.Ray.html#method.offset_origin
+    ///
+    fn secondary(&self, hit: &Intersection, direction: Vector3) -> Ray {
+        let extent = self.origin.abs().max_element() + hit.distance;
+        let mut ray = Ray::new(hit.point(self), direction)
+            .offset_along_normal(hit.norm, extent * Real::EPSILON * OFFSET_INT_SCALE)
+            .offset_origin(hit.norm);
+        ray.time = self.time;
+        ray
+    }
+}
+
+/// Coordinates closer to zero than this are offset by a fixed distance by [`offset_point`].
+///
+/// [`offset_point`]: fn.offset_point.html
+///
+const OFFSET_ORIGIN: Real = 1.0 / 32.0;
+/// The fixed distance by which [`offset_point`] moves coordinates close to zero, along the
+/// normal.
+///
+/// [`offset_point`]: fn.offset_point.html
+///
+const OFFSET_FLOAT_SCALE: Real = 1.0 / 65536.0;
+/// The number of units in the last place by which [`offset_point`] moves other coordinates,
+/// along the normal.
+///
+/// [`offset_point`]: fn.offset_point.html
+///
+const OFFSET_INT_SCALE: Real = 256.0;
+
+/// Moves `point` along the unit `normal` by a number of units in the last place of each
+/// coordinate, or by a fixed distance for coordinates close to zero.
+fn offset_point(point: Point3, normal: Vector3) -> Point3 {
+    let offset = |coordinate: Real, normal: Real| {
+        if coordinate.abs() < OFFSET_ORIGIN {
+            return coordinate + normal * OFFSET_FLOAT_SCALE;
+        }
+        // The magnitude of a float grows with its bits, so moving away from zero adds to
+        // them and moving towards zero subtracts from them.
+        let steps = (normal.abs() * OFFSET_INT_SCALE) as u16;
+        let bits = coordinate.to_bits();
+        if (coordinate > 0.0) == (normal > 0.0) {
+            Real::from_bits(bits.wrapping_add(steps.into()))
+        } else {
+            Real::from_bits(bits.wrapping_sub(steps.into()))
+        }
+    };
+    Point3::new(
+        offset(point.x, normal.x),
+        offset(point.y, normal.y),
+        offset(point.z, normal.z),
+    )
+}
+
+/// Computes `a * b - c * d` in double precision.
+#[allow(clippy::unnecessary_cast, clippy::useless_conversion)]
+fn difference_of_products(a: Real, b: Real, c: Real, d: Real) -> Real {
+    (f64::from(a) * f64::from(b) - f64::from(c) * f64::from(d)) as Real
 }
 
 #[cfg(test)]
@@ -417,11 +1281,13 @@ mod tests {
     use crate::Real;
     use std::cmp;
 
-    use crate::aabb::AABB;
+    use crate::aabb::{AABB, AABB4};
     use crate::bounding_hierarchy::IntersectionAABB;
-    use crate::ray::Ray;
-    use crate::testbase::{tuple_to_point, tuplevec_small_strategy, TupleVec};
-    use crate::EPSILON;
+    use crate::float::Tolerances;
+    use crate::ray::{Intersection, IntersectionRay, Ray};
+    use crate::sphere::Sphere;
+    use crate::testbase::{next_point3, tuple_to_point, tuplevec_small_strategy, TupleVec};
+    use crate::{Mat4, Point3, Quat, Vector3, EPSILON};
     use proptest::prelude::*;
 
     /// Generates a random `Ray` which points at at a random `AABB`.
@@ -440,6 +1306,322 @@ mod tests {
         (ray, aabb)
     }
 
+    #[test]
+    /// Tests that rays aimed at the shared edges and vertices of a jittered grid of triangles
+    /// always hit one of them with the watertight test, and that the centers of the
+    /// triangles are hit like with the Möller-Trumbore test.
+    fn test_ray_hits_triangle_watertight() {
+        let size = 8;
+        let mut seed = 0;
+        let jitter = AABB::with_bounds(Point3::splat(-0.3), Point3::splat(0.3));
+        let vertices: Vec<Point3> = (0..(size + 1) * (size + 1))
+            .map(|i| {
+                let (x, y) = ((i % (size + 1)) as Real, (i / (size + 1)) as Real);
+                Point3::new(x, y, 0.0) + next_point3(&mut seed, &jitter)
+            })
+            .collect();
+        let vertex = |x: usize, y: usize| vertices[y * (size + 1) + x];
+        let mut triangles = Vec::new();
+        for y in 0..size {
+            for x in 0..size {
+                let (v00, v10) = (vertex(x, y), vertex(x + 1, y));
+                let (v01, v11) = (vertex(x, y + 1), vertex(x + 1, y + 1));
+                triangles.push((v00, v10, v11));
+                triangles.push((v00, v11, v01));
+            }
+        }
+
+        // The diagonals of all cells, and the interior edges and vertices.
+        let mut targets = Vec::new();
+        for y in 0..size {
+            for x in 0..size {
+                targets.push((vertex(x, y) + vertex(x + 1, y + 1)) * 0.5);
+                if x > 0 {
+                    targets.push((vertex(x, y) + vertex(x, y + 1)) * 0.5);
+                }
+                if y > 0 {
+                    targets.push((vertex(x, y) + vertex(x + 1, y)) * 0.5);
+                }
+                if x > 0 && y > 0 {
+                    targets.push(vertex(x, y));
+                }
+            }
+        }
+        for target in targets {
+            for side in [-1.0, 1.0] {
+                let offset = next_point3(&mut seed, &jitter);
+                let origin = target + Vector3::new(offset.x, offset.y, 5.0 * side);
+                let ray = Ray::new(origin, target - origin);
+                let hit = triangles.iter().any(|(a, b, c)| {
+                    ray.intersects_triangle_watertight(a, b, c).distance < Real::INFINITY
+                });
+                assert!(hit);
+            }
+        }
+
+        for (a, b, c) in triangles.iter() {
+            let center = (*a + *b + *c) / 3.0;
+            let ray = Ray::new(
+                center + Vector3::new(0.1, -0.2, 3.0),
+                Vector3::new(-0.1, 0.2, -3.0),
+            );
+            let watertight = ray.intersects_triangle_watertight(a, b, c);
+            let moller = ray.intersects_triangle(a, b, c);
+            assert!((watertight.distance - moller.distance).abs() < EPSILON * 10.0);
+            assert!((watertight.u - moller.u).abs() < EPSILON * 10.0);
+            assert!((watertight.v - moller.v).abs() < EPSILON * 10.0);
+            assert!(!watertight.back_face);
+
+            let below = Ray::new(center - Vector3::Z, Vector3::Z);
+            let back = below.intersects_triangle_watertight(a, b, c);
+            assert!((back.distance - 1.0).abs() < EPSILON * 10.0);
+            assert!(back.back_face);
+        }
+    }
+
+    #[test]
+    /// Tests that testing four AABBs at once agrees with the scalar slab test of each of
+    /// them, including empty lanes and limited intervals.
+    fn test_intersects_aabb4() {
+        let bounds = AABB::with_bounds(Point3::splat(-10.0), Point3::splat(10.0));
+        let mut seed = 0;
+        for i in 0..1000 {
+            let target = next_point3(&mut seed, &bounds);
+            let origin = next_point3(&mut seed, &bounds);
+            let ray = Ray::new(origin, target - origin);
+            let aabbs: Vec<AABB> = (0..i % 5)
+                .map(|_| {
+                    AABB::empty()
+                        .grow(&next_point3(&mut seed, &bounds))
+                        .grow(&next_point3(&mut seed, &bounds))
+                })
+                .collect();
+            let t_max = if i % 2 == 0 { Real::INFINITY } else { 0.5 };
+
+            let (mask, entries) = ray.intersects_aabb4(&AABB4::new(&aabbs), 0.0, t_max);
+            for (lane, &entry) in entries.iter().enumerate() {
+                let expected = aabbs
+                    .get(lane)
+                    .and_then(|aabb| ray.intersects_aabb_interval(aabb))
+                    .filter(|&(entry, _)| entry <= t_max);
+                assert_eq!(mask & (1 << lane) != 0, expected.is_some());
+                if let Some((expected_entry, _)) = expected {
+                    assert_eq!(entry, expected_entry.max(0.0));
+                }
+            }
+        }
+    }
+
+    #[test]
+    /// Tests that transformed rays match rays created from the transformed points, and that
+    /// distances are scaled between the spaces.
+    fn test_ray_transformed() {
+        let bounds = AABB::with_bounds(Point3::splat(-5.0), Point3::splat(5.0));
+        let scales = AABB::with_bounds(Point3::splat(0.25), Point3::splat(4.0));
+        let mut seed = 0;
+        for _ in 0..100 {
+            let axis = next_point3(&mut seed, &bounds).normalize();
+            let transform = Mat4::from_scale_rotation_translation(
+                next_point3(&mut seed, &scales),
+                Quat::from_axis_angle(axis, next_point3(&mut seed, &bounds).x),
+                next_point3(&mut seed, &bounds),
+            );
+            let origin = next_point3(&mut seed, &bounds);
+            let ray = Ray::new(origin, next_point3(&mut seed, &bounds)).with_time(0.5);
+
+            let (moved, scale) = ray.transformed(&transform);
+            let expected = Ray::new(
+                transform.transform_point3(ray.origin),
+                transform.transform_vector3(ray.direction),
+            );
+            assert_eq!(moved.origin, expected.origin);
+            assert_eq!(moved.direction, expected.direction);
+            assert_eq!(moved.time, Some(0.5));
+            for &t in [0.0, 1.0, 7.5].iter() {
+                let point = transform.transform_point3(ray.at(t));
+                assert!(moved
+                    .at(t * scale)
+                    .abs_diff_eq(point, EPSILON * 100.0 * (1.0 + point.length())));
+            }
+            let target = next_point3(&mut seed, &bounds);
+            let aabb =
+                AABB::with_bounds(target - Vector3::splat(1.0), target + Vector3::splat(1.0));
+            assert_eq!(
+                moved.intersects_aabb(&aabb),
+                expected.intersects_aabb(&aabb)
+            );
+
+            let (back, inverse_scale) = moved.inverse_transformed(&transform);
+            assert!(back.origin.abs_diff_eq(ray.origin, EPSILON * 100.0));
+            assert!(back.direction.abs_diff_eq(ray.direction, EPSILON * 100.0));
+            assert!((scale * inverse_scale - 1.0).abs() < EPSILON * 10.0);
+        }
+    }
+
+    #[test]
+    /// Tests reflected and refracted rays at random points of spheres against the laws of
+    /// reflection and refraction.
+    fn test_ray_reflect_refract() {
+        let bounds = AABB::with_bounds(Point3::splat(-5.0), Point3::splat(5.0));
+        let mut seed = 0;
+        let mut total_reflections = 0;
+        for _ in 0..1000 {
+            let sphere = Sphere::new(next_point3(&mut seed, &bounds), 2.0);
+            let target = sphere.center + next_point3(&mut seed, &bounds).normalize() * 1.9;
+            let origin = target + next_point3(&mut seed, &bounds).normalize() * 10.0;
+            let ray = Ray::new(origin, target - origin).with_time(0.25);
+            let hit = sphere.intersects_ray(&ray, 0.0, Real::INFINITY).unwrap();
+            let normal = hit.norm.normalize();
+
+            let reflected = ray.reflect(&hit);
+            assert_eq!(reflected.time, Some(0.25));
+            assert!((reflected.direction.dot(normal) + ray.direction.dot(normal)).abs() < 1e-4);
+            let tangent = ray.direction - normal * ray.direction.dot(normal);
+            let reflected_tangent = reflected.direction - normal * reflected.direction.dot(normal);
+            assert!(tangent.abs_diff_eq(reflected_tangent, 1e-4));
+            assert!(sphere
+                .intersects_ray(&reflected, 0.0, Real::INFINITY)
+                .is_none());
+
+            // Into the sphere, and out of it again from the inside.
+            let eta = 1.0 / 1.5;
+            let inside = ray.refract(&hit, eta).unwrap();
+            let sin = |direction: Vector3| direction.cross(normal).length();
+            assert!((sin(inside.direction) - eta * sin(ray.direction)).abs() < 1e-4);
+            assert!(inside.direction.dot(normal) < 0.0);
+            assert!(inside.origin.distance(sphere.center) < 2.0);
+            let exit = sphere.intersects_ray(&inside, 0.0, Real::INFINITY).unwrap();
+            assert!(exit.back_face);
+            match inside.refract(&exit, 1.5) {
+                Some(outside) => {
+                    assert!(outside.origin.distance(sphere.center) > 2.0);
+                    assert!(sphere
+                        .intersects_ray(&outside, 0.0, Real::INFINITY)
+                        .is_none());
+                }
+                None => total_reflections += 1,
+            }
+        }
+        // A ray entering a sphere always leaves it at the same angle.
+        assert_eq!(total_reflections, 0);
+
+        let normal = Intersection::new(1.0, 0.0, 0.0, Vector3::Y, false);
+        let grazing = Ray::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.5, 0.0));
+        assert!(grazing.refract(&normal, 1.5).is_none());
+    }
+
+    #[test]
+    /// Tests that rays spawned at hits on random triangles close to and far from the origin
+    /// do not hit the triangles again, and that the offset stays small close to the origin.
+    fn test_ray_offset_origin() {
+        let mut seed = 0;
+        for &(center, size) in [(0.0, 0.01), (1.0, 1.0), (1000.0, 10.0), (20000.0, 100.0)].iter() {
+            let bounds =
+                AABB::with_bounds(Point3::splat(center - size), Point3::splat(center + size));
+            let unit = AABB::with_bounds(Point3::splat(-1.0), Point3::splat(1.0));
+            let mut hits = 0;
+            for _ in 0..1000 {
+                let [a, b, c] = [0; 3].map(|_| next_point3(&mut seed, &bounds));
+                let target = (a + b + c) / 3.0;
+                let origin = target + next_point3(&mut seed, &unit) * size * 4.0;
+                let ray = Ray::new(origin, target - origin);
+                let hit = ray.intersects_triangle_double_sided(&a, &b, &c);
+                if !hit.distance.is_finite() {
+                    continue;
+                }
+                hits += 1;
+                let point = hit.point(&ray);
+                let normal = (b - a).cross(c - a);
+                let spawned = Ray::new(point, next_point3(&mut seed, &unit)).offset_origin(normal);
+                let again = spawned.intersects_triangle_double_sided(&a, &b, &c);
+                assert!(!again.distance.is_finite() || again.distance > size * 1e-3);
+                if center == 0.0 {
+                    assert!(spawned.origin.distance(point) < 1e-4);
+                }
+            }
+            assert!(hits > 100);
+        }
+    }
+
+    #[test]
+    /// Tests that interpolating the points of random triangles at their hits gives the hit
+    /// points, and that other attributes are blended with the same weights.
+    fn test_intersection_interpolate() {
+        let bounds = AABB::with_bounds(Point3::splat(-5.0), Point3::splat(5.0));
+        let mut seed = 0;
+        let mut hits = 0;
+        for _ in 0..1000 {
+            let points = [
+                next_point3(&mut seed, &bounds),
+                next_point3(&mut seed, &bounds),
+                next_point3(&mut seed, &bounds),
+            ];
+            let origin = next_point3(&mut seed, &bounds);
+            let target = (points[0] + points[1] + points[2]) / 3.0;
+            let ray = Ray::new(
+                origin,
+                target + next_point3(&mut seed, &bounds) * 0.1 - origin,
+            );
+            let hit = ray.intersects_triangle_double_sided(&points[0], &points[1], &points[2]);
+            if !hit.distance.is_finite() {
+                continue;
+            }
+            hits += 1;
+
+            let point = hit.point(&ray);
+            let tolerance = EPSILON * 100.0 * (1.0 + point.length());
+            assert!(hit.interpolate(&points).abs_diff_eq(point, tolerance));
+            let weights = hit.barycentric();
+            assert!((weights.iter().sum::<Real>() - 1.0).abs() < EPSILON);
+            let scalar = hit.interpolate(&[1.0, 2.0, 4.0]);
+            assert!((scalar - (weights[0] + 2.0 * weights[1] + 4.0 * weights[2])).abs() < EPSILON);
+        }
+        assert!(hits > 100);
+    }
+
+    #[test]
+    /// Tests that scaled tolerances hit tiny triangles and ignore hits of rays which start
+    /// just above huge triangles, for all triangle tests.
+    fn test_triangle_tolerances() {
+        assert_eq!(Tolerances::scaled(1.0), Tolerances::default());
+
+        // A triangle with sides of a tenth of a millimeter, in meters.
+        let (a, b, c) = (Point3::ZERO, Point3::X * 1.0e-4, Point3::Y * 1.0e-4);
+        let ray = Ray::new(Point3::new(2.0e-5, 2.0e-5, 1.0), -Vector3::Z * 2.0);
+        let tiny = Tolerances::scaled(1.0e-4);
+        assert!(ray.intersects_triangle(&a, &b, &c).distance.is_infinite());
+        assert_eq!(ray.intersects_triangle_eps(&a, &b, &c, &tiny).distance, 1.0);
+        let below = Ray::new(Point3::new(2.0e-5, 2.0e-5, -1.0), Vector3::Z);
+        assert!(below
+            .intersects_triangle_double_sided(&a, &b, &c)
+            .distance
+            .is_infinite());
+        let hit = below.intersects_triangle_double_sided_eps(&a, &b, &c, &tiny);
+        assert!(hit.back_face && hit.distance == 1.0);
+
+        // A triangle with sides of two kilometers, in meters, and a ray which starts a
+        // millimeter above it, like a ray which is cast from a rounded hit point.
+        let (a, b, c) = (Point3::ZERO, Point3::X * 2000.0, Point3::Y * 2000.0);
+        let ray = Ray::new(Point3::new(500.0, 500.0, 1.0e-3), -Vector3::Z * 2.0);
+        let huge = Tolerances::scaled(1000.0);
+        assert!(ray.intersects_triangle(&a, &b, &c).distance.is_finite());
+        assert!(ray
+            .intersects_triangle_watertight(&a, &b, &c)
+            .distance
+            .is_finite());
+        assert!(ray
+            .intersects_triangle_eps(&a, &b, &c, &huge)
+            .distance
+            .is_infinite());
+        assert!(ray
+            .intersects_triangle_watertight_eps(&a, &b, &c, &huge)
+            .distance
+            .is_infinite());
+        let far = Ray::new(Point3::new(500.0, 500.0, 100.0), -Vector3::Z);
+        let hit = far.intersects_triangle_watertight_eps(&a, &b, &c, &huge);
+        assert_eq!(hit.distance, 100.0);
+    }
+
     #[cfg(not(miri))]
     proptest! {
         // Test whether a `Ray` which points at the center of an `AABB` intersects it.
@@ -516,6 +1698,64 @@ mod tests {
             assert!(!ray.intersects_aabb_branchless(&aabb) || aabb.contains(&ray.origin));
         }
 
+        // Test whether a `Ray` which points at the center of a flat `AABB` intersects it,
+        // with all algorithms.
+        #[test]
+        fn test_ray_points_at_flat_aabb_center(data in (tuplevec_small_strategy(),
+                                                        tuplevec_small_strategy(),
+                                                        tuplevec_small_strategy()),
+                                               axis in 0usize..3) {
+            let (_, aabb) = gen_ray_to_aabb(data);
+            let mut flat = aabb;
+            flat.max[axis] = flat.min[axis];
+            let pos = tuple_to_point(&data.2);
+            let ray = Ray::new(pos, flat.center() - pos);
+            assert!(ray.intersects_aabb(&flat));
+            assert!(ray.intersects_aabb_naive(&flat));
+            assert!(ray.intersects_aabb_branchless(&flat));
+            assert!(ray.intersects_aabb_interval(&flat).is_some());
+        }
+
+        // Test all `AABB` tests with rays along the axes, whose other direction components
+        // are `0.0` or `-0.0`, against boxes which may be flat. The coordinates on a grid of
+        // halves put many origins exactly on the planes of the boxes, where `0 * inf` is
+        // `NaN`. The origins are never on a plane perpendicular to the ray, where a hit at
+        // distance 0 is a matter of convention.
+        #[test]
+        fn test_axis_aligned_ray_flat_aabb(min in (-4i8..4, -4i8..4, -4i8..4),
+                                           size in (0i8..3, 0i8..3, 0i8..3),
+                                           origin in (-12i8..12, -12i8..12, -12i8..12),
+                                           axis in 0usize..3,
+                                           negative: bool,
+                                           zero_signs in (any::<bool>(), any::<bool>(), any::<bool>())) {
+            let min = Point3::new(min.0 as Real, min.1 as Real, min.2 as Real);
+            let size = Vector3::new(size.0 as Real, size.1 as Real, size.2 as Real);
+            let aabb = AABB::with_bounds(min, min + size);
+            let mut origin = Point3::new(origin.0 as Real, origin.1 as Real, origin.2 as Real) * 0.5;
+            origin[axis] += 0.25;
+            let zero = |negative| if negative { -0.0 } else { 0.0 };
+            let mut direction = Vector3::new(zero(zero_signs.0), zero(zero_signs.1), zero(zero_signs.2));
+            direction[axis] = if negative { -1.0 } else { 1.0 };
+            let ray = Ray::new(origin, direction);
+
+            let inside_slab = |i: usize| aabb.min[i] <= origin[i] && origin[i] <= aabb.max[i];
+            let ahead = if negative {
+                aabb.min[axis] < origin[axis]
+            } else {
+                aabb.max[axis] > origin[axis]
+            };
+            let expected = ahead && (0..3).all(|i| i == axis || inside_slab(i));
+
+            assert_eq!(ray.intersects_aabb(&aabb), expected);
+            assert_eq!(ray.intersects_aabb_naive(&aabb), expected);
+            assert_eq!(ray.intersects_aabb_branchless(&aabb), expected);
+            assert_eq!(ray.intersects_aabb_interval(&aabb).is_some(), expected);
+            assert_eq!(ray.intersects_aabb_dist(&aabb).is_some(), expected);
+            let (mask, _) = ray.intersects_aabb4(&AABB4::new(&[aabb]), 0.0, Real::INFINITY);
+            assert_eq!(mask & 1 == 1, expected);
+            assert_eq!(aabb.intersects_ray(&ray, 0.0, Real::INFINITY).is_some(), expected);
+        }
+
         // Test whether a `Ray` which points at the center of a triangle
         // intersects it, unless it sees the back face, which is culled.
         #[test]
@@ -576,6 +1816,36 @@ mod tests {
                 assert!(intersection_inside || close_to_border);
             }
         }
+
+        // Test whether the double sided triangle test matches the culling test from the
+        // front, and the culling test of the flipped triangle from behind.
+        #[test]
+        fn test_ray_hits_triangle_double_sided(a in tuplevec_small_strategy(),
+                                               b in tuplevec_small_strategy(),
+                                               c in tuplevec_small_strategy(),
+                                               origin in tuplevec_small_strategy(),
+                                               target in tuplevec_small_strategy()) {
+            let (a, b, c) = (tuple_to_point(&a), tuple_to_point(&b), tuple_to_point(&c));
+            let origin = tuple_to_point(&origin);
+            let ray = Ray::new(origin, tuple_to_point(&target) - origin);
+
+            let front = ray.intersects_triangle(&a, &b, &c);
+            let back = ray.intersects_triangle(&a, &c, &b);
+            let both = ray.intersects_triangle_double_sided(&a, &b, &c);
+            if front.distance < Real::INFINITY {
+                assert_eq!((both.distance, both.u, both.v), (front.distance, front.u, front.v));
+                assert_eq!((both.norm, both.back_face), (front.norm, false));
+            } else if back.distance < Real::INFINITY {
+                // The rounding errors grow with the coordinates, not only with the distance.
+                let scale = [a, b, c, origin]
+                    .iter()
+                    .map(|point| point.abs().max_element())
+                    .fold(back.distance.max(1.0), Real::max);
+                assert!((both.distance - back.distance).abs() <= scale * EPSILON);
+                assert!(both.back_face);
+                assert!(both.norm.dot(ray.direction) <= 0.0);
+            }
+        }
     }
 }
 
@@ -585,8 +1855,9 @@ mod bench {
     use rand::rngs::StdRng;
     use rand::{Rng, SeedableRng};
 
-    use crate::aabb::AABB;
+    use crate::aabb::{AABB, AABB4};
     use crate::ray::Ray;
+    use crate::Real;
 
     use crate::testbase::{tuple_to_point, tuple_to_vector, TupleVec};
 
@@ -646,4 +1917,48 @@ mod bench {
             }
         });
     }
+
+    /// Generates a random deterministic `Ray` and four `AABB`s.
+    fn gen_random_ray_aabbs(rng: &mut StdRng) -> (Ray, [AABB; 4]) {
+        let (ray, first) = gen_random_ray_aabb(rng);
+        let mut aabbs = [first; 4];
+        for aabb in aabbs.iter_mut().skip(1) {
+            *aabb = gen_random_ray_aabb(rng).1;
+        }
+        (ray, aabbs)
+    }
+
+    /// Benchmark for testing four `AABB`s one after another.
+    #[bench]
+    fn bench_intersects_4_aabbs_scalar(b: &mut ::test::Bencher) {
+        let mut rng = StdRng::from_seed([0; 32]);
+        let pairs: Vec<(Ray, [AABB; 4])> =
+            (0..1000).map(|_| gen_random_ray_aabbs(&mut rng)).collect();
+
+        b.iter(|| {
+            for (ray, aabbs) in pairs.iter() {
+                for aabb in aabbs.iter() {
+                    ::test::black_box(ray.intersects_aabb_interval(aabb));
+                }
+            }
+        });
+    }
+
+    /// Benchmark for testing four `AABB`s at once with SIMD instructions.
+    #[bench]
+    fn bench_intersects_aabb4(b: &mut ::test::Bencher) {
+        let mut rng = StdRng::from_seed([0; 32]);
+        let pairs: Vec<(Ray, AABB4)> = (0..1000)
+            .map(|_| {
+                let (ray, aabbs) = gen_random_ray_aabbs(&mut rng);
+                (ray, AABB4::new(&aabbs))
+            })
+            .collect();
+
+        b.iter(|| {
+            for (ray, aabbs) in pairs.iter() {
+                ::test::black_box(ray.intersects_aabb4(aabbs, 0.0, Real::INFINITY));
+            }
+        });
+    }
 }
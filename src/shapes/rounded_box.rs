@@ -0,0 +1,325 @@
+//! This module defines a RoundedBox and its intersection algorithms
+
+use crate::{
+    aabb::{Bounded, AABB},
+    bounding_hierarchy::IntersectionAABB,
+    ray::{Intersection, IntersectionRay, Ray},
+    Point3, Real, Vector3,
+};
+
+/// An axis aligned box with rounded edges and corners, which is the set of points within
+/// `radius` of a smaller inner box. Use a [`Transformed`] rounded box to rotate it.
+///
+/// [`Transformed`]: ../transformed/struct.Transformed.html
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct RoundedBox {
+    /// Center of the box
+    pub center: Point3,
+    /// Half of the size of the box along each axis, including the rounding
+    pub half_extents: Vector3,
+    /// Radius of the rounded edges and corners
+    pub radius: Real,
+}
+
+impl RoundedBox {
+    /// Creates a rounded box from its center, its half extents including the rounding and
+    /// the radius of its edges and corners. The radius is clamped to the smallest half
+    /// extent, where the box becomes a capsule or a sphere.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::Bounded;
+    /// use bvh::ray::{IntersectionRay, Ray};
+    /// use bvh::rounded_box::RoundedBox;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let rounded = RoundedBox::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(2.0, 1.0, 1.0), 0.5);
+    /// assert_eq!(rounded.aabb().max, Point3::new(2.0, 1.0, 1.0));
+    ///
+    /// // The flat faces are hit like the faces of a box.
+    /// let ray = Ray::new(Point3::new(-5.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+    /// let hit = rounded.intersects_ray(&ray, 0.0, f32::INFINITY).unwrap();
+    /// assert_eq!(hit.distance, 3.0);
+    ///
+    /// // Rays through the cut off corners miss.
+    /// let corner = Ray::new(Point3::new(1.95, 0.95, -5.0), Vector3::new(0.0, 0.0, 1.0));
+    /// assert!(rounded.intersects_ray(&corner, 0.0, f32::INFINITY).is_none());
+    /// ```
+    pub fn new(center: Point3, half_extents: Vector3, radius: Real) -> RoundedBox {
+        RoundedBox {
+            center,
+            half_extents,
+            radius: radius.min(half_extents.min_element()).max(0.0),
+        }
+    }
+
+    /// Returns the half extents of the inner box, which is rounded by the radius.
+    pub fn inner_half_extents(&self) -> Vector3 {
+        (self.half_extents - Vector3::splat(self.radius)).max(Vector3::ZERO)
+    }
+}
+
+/// Returns the roots of `a * t^2 + 2 * half_b * t + c`.
+fn roots(a: Real, half_b: Real, c: Real) -> [Option<Real>; 2] {
+    let discriminant = half_b * half_b - a * c;
+    if a == 0.0 || discriminant < 0.0 {
+        return [None, None];
+    }
+    let d_sqrt = discriminant.sqrt();
+    [Some((-half_b - d_sqrt) / a), Some((-half_b + d_sqrt) / a)]
+}
+
+impl IntersectionRay for RoundedBox {
+    /// Intersects the ray with the flat faces, the cylinders around the edges and the
+    /// spheres around the corners of the inner box, each limited to the part which is on the
+    /// surface. The `u` and `v` coordinates of the [`Intersection`] are the position on the
+    /// side facing along the largest component of the normal, scaled to `[0, 1]` along the
+    /// lower and higher of the two other axes, as for an [`AABB`].
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`Intersection`]: ../ray/struct.Intersection.html
+    ///
+    fn intersects_ray(&self, ray: &Ray, t_min: Real, t_max: Real) -> Option<Intersection> {
+        ray.intersects_aabb_interval(&self.aabb())?;
+
+        let origin = ray.origin - self.center;
+        let direction = ray.direction;
+        let inner = self.inner_half_extents();
+        let signs = [-1.0, 1.0];
+
+        // The nearest distance within the interval and its outward normal.
+        let mut nearest: Option<(Real, Vector3)> = None;
+        let mut keep_nearest = |toi: Real, out_norm: Vector3| {
+            if toi >= t_min && toi <= t_max && nearest.is_none_or(|(nearest, _)| toi < nearest) {
+                nearest = Some((toi, out_norm));
+            }
+        };
+
+        for axis in 0..3 {
+            let (j, k) = ((axis + 1) % 3, (axis + 2) % 3);
+            for &sign in signs.iter() {
+                // The flat face perpendicular to `axis`.
+                if direction[axis] != 0.0 {
+                    let toi = (sign * self.half_extents[axis] - origin[axis]) / direction[axis];
+                    let point = origin + direction * toi;
+                    if point[j].abs() <= inner[j] && point[k].abs() <= inner[k] {
+                        let mut out_norm = Vector3::ZERO;
+                        out_norm[axis] = sign;
+                        keep_nearest(toi, out_norm);
+                    }
+                }
+
+                // The cylinders around the edges along `axis`, on the side of the face.
+                if self.radius <= 0.0 {
+                    continue;
+                }
+                for &other_sign in signs.iter() {
+                    let mut center = Vector3::ZERO;
+                    center[j] = sign * inner[j];
+                    center[k] = other_sign * inner[k];
+                    let offset = origin - center;
+                    let a = direction[j] * direction[j] + direction[k] * direction[k];
+                    let half_b = offset[j] * direction[j] + offset[k] * direction[k];
+                    let c =
+                        offset[j] * offset[j] + offset[k] * offset[k] - self.radius * self.radius;
+                    for toi in roots(a, half_b, c).iter().flatten() {
+                        let mut out_norm = origin + direction * *toi - center;
+                        if out_norm[axis].abs() <= inner[axis]
+                            && out_norm[j] * sign >= 0.0
+                            && out_norm[k] * other_sign >= 0.0
+                        {
+                            out_norm[axis] = 0.0;
+                            keep_nearest(*toi, out_norm / self.radius);
+                        }
+                    }
+                }
+            }
+        }
+
+        // The spheres around the corners, on the side of all three faces.
+        let corners = if self.radius > 0.0 { 0..8 } else { 0..0 };
+        for corner in corners {
+            let corner_signs = Vector3::new(
+                signs[corner & 1],
+                signs[(corner >> 1) & 1],
+                signs[corner >> 2],
+            );
+            let offset = origin - corner_signs * inner;
+            let a = direction.length_squared();
+            let half_b = offset.dot(direction);
+            let c = offset.length_squared() - self.radius * self.radius;
+            for toi in roots(a, half_b, c).iter().flatten() {
+                let out_norm = offset + direction * *toi;
+                if (out_norm * corner_signs).min_element() >= 0.0 {
+                    keep_nearest(*toi, out_norm / self.radius);
+                }
+            }
+        }
+
+        nearest.map(|(toi, out_norm)| {
+            let (norm, back_face) = ray.face_normal(out_norm);
+            let abs_norm = out_norm.abs();
+            let axis = if abs_norm.x >= abs_norm.y && abs_norm.x >= abs_norm.z {
+                0
+            } else if abs_norm.y >= abs_norm.z {
+                1
+            } else {
+                2
+            };
+            let scaled = |other: usize| {
+                if self.half_extents[other] > 0.0 {
+                    (origin[other] + direction[other] * toi + self.half_extents[other])
+                        / (2.0 * self.half_extents[other])
+                } else {
+                    0.0
+                }
+            };
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            let (u, v) = (u.min(v), u.max(v));
+            Intersection::new(toi, scaled(u), scaled(v), norm, back_face)
+        })
+    }
+}
+
+impl IntersectionAABB for RoundedBox {
+    /// Tests whether the distance between the `aabb` and the inner box is at most the
+    /// radius.
+    fn intersects_aabb(&self, aabb: &AABB) -> bool {
+        let inner = self.inner_half_extents();
+        let gap = (aabb.min - self.center - inner)
+            .max(self.center - inner - aabb.max)
+            .max(Vector3::ZERO);
+        gap.length_squared() <= self.radius * self.radius
+    }
+}
+
+impl Bounded for RoundedBox {
+    fn aabb(&self) -> AABB {
+        AABB::with_bounds(
+            self.center - self.half_extents,
+            self.center + self.half_extents,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::{Bounded, AABB};
+    use crate::bounding_hierarchy::IntersectionAABB;
+    use crate::ray::{IntersectionRay, Ray};
+    use crate::rounded_box::RoundedBox;
+    use crate::testbase::{create_ray, next_point3};
+    use crate::{Point3, Real, Vector3, EPSILON};
+
+    /// Returns the signed distance of `point` to the surface of `rounded`.
+    fn signed_distance(rounded: &RoundedBox, point: Point3) -> Real {
+        let q = (point - rounded.center).abs() - rounded.inner_half_extents();
+        q.max(Vector3::ZERO).length() + q.max_element().min(0.0) - rounded.radius
+    }
+
+    #[test]
+    /// Tests hits on the faces, edges and corners, from the outside and the inside.
+    fn test_rounded_box_intersects_ray() {
+        let rounded = RoundedBox::new(Point3::new(1.0, 2.0, 3.0), Vector3::new(2.0, 1.0, 1.0), 0.5);
+        let intersect = |origin: Point3, direction: Vector3| {
+            let origin = origin + Vector3::new(1.0, 2.0, 3.0);
+            rounded.intersects_ray(&Ray::new(origin, direction), 0.0, Real::INFINITY)
+        };
+
+        let face = intersect(Point3::new(0.5, 5.0, 0.0), Vector3::new(0.0, -1.0, 0.0)).unwrap();
+        assert!((face.distance - 4.0).abs() < EPSILON);
+        assert_eq!(face.norm, Vector3::new(0.0, 1.0, 0.0));
+        assert!((face.u - 0.625).abs() < EPSILON);
+        assert!((face.v - 0.5).abs() < EPSILON);
+
+        // The edge along z at x = 1.5, y = 0.5 is rounded by a quarter circle.
+        let direction = Vector3::new(-1.0, -1.0, 0.0);
+        let edge = intersect(Point3::new(5.5, 4.5, 0.0), direction).unwrap();
+        let expected = 4.0 * Real::sqrt(2.0) - 0.5;
+        assert!((edge.distance - expected).abs() < EPSILON);
+        assert!((edge.norm - -direction.normalize()).length() < EPSILON);
+
+        let corner = intersect(
+            Point3::splat(5.0) + Vector3::new(1.5, 0.5, 0.5),
+            -Vector3::ONE,
+        );
+        let expected = 5.0 * Real::sqrt(3.0) - 0.5;
+        assert!((corner.unwrap().distance - expected).abs() < EPSILON);
+
+        let inside = intersect(Point3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0)).unwrap();
+        assert!((inside.distance - 2.0).abs() < EPSILON);
+        assert!(inside.back_face);
+        assert_eq!(inside.norm, Vector3::new(-1.0, 0.0, 0.0));
+
+        // The corner of the bounds is outside of the rounding.
+        assert!(intersect(Point3::new(1.9, 0.9, -5.0), Vector3::new(0.0, 0.0, 1.0)).is_none());
+        assert!(intersect(Point3::new(-5.0, 3.0, 0.0), Vector3::new(1.0, 0.0, 0.0)).is_none());
+
+        // Without a radius, the edges and corners of the box are sharp.
+        let sharp = RoundedBox::new(Point3::new(0.0, 0.0, 0.0), Vector3::splat(1.0), 0.0);
+        let diagonal = Ray::new(Point3::splat(3.0), -Vector3::ONE);
+        let hit = sharp
+            .intersects_ray(&diagonal, 0.0, Real::INFINITY)
+            .unwrap();
+        assert!((hit.distance - 2.0 * Real::sqrt(3.0)).abs() < EPSILON);
+        assert!(hit.norm.is_finite());
+    }
+
+    #[test]
+    /// Tests that hits lie on the surface, and that rays between the nearest hit and the
+    /// outside stay outside of the rounded box.
+    fn test_rounded_box_random_rays() {
+        let rounded = RoundedBox::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(3.0, 2.0, 1.0), 0.8);
+        let bounds = AABB::with_bounds(Point3::splat(-6.0), Point3::splat(6.0));
+        let mut seed = 0;
+        let mut hits = 0;
+        for _ in 0..1000 {
+            let ray = create_ray(&mut seed, &bounds);
+            let outside = signed_distance(&rounded, ray.origin) > 0.0;
+            match rounded.intersects_ray(&ray, 0.0, Real::INFINITY) {
+                Some(hit) => {
+                    let point = ray.at(hit.distance);
+                    assert!(signed_distance(&rounded, point).abs() < 1e-4);
+                    assert_eq!(hit.back_face, !outside);
+                    let halfway = ray.at(hit.distance * 0.5);
+                    assert_eq!(signed_distance(&rounded, halfway) > 0.0, outside);
+                    hits += 1;
+                }
+                None => {
+                    assert!(outside);
+                    for i in 0..100 {
+                        let point = ray.at(i as Real * 0.2);
+                        assert!(signed_distance(&rounded, point) > -1e-4);
+                    }
+                }
+            }
+        }
+        assert!(hits > 0);
+    }
+
+    #[test]
+    /// Tests the AABB intersection against sampled points of the rounded box.
+    fn test_rounded_box_intersects_aabb() {
+        let rounded = RoundedBox::new(Point3::new(1.0, 0.0, 0.0), Vector3::new(1.0, 1.0, 1.0), 0.5);
+        let near_corner = AABB::with_bounds(Point3::splat(1.8), Point3::splat(3.0));
+        assert!(!rounded.intersects_aabb(&near_corner));
+        let at_corner = AABB::with_bounds(Point3::new(1.8, 0.5, 0.5), Point3::splat(3.0));
+        assert!(rounded.intersects_aabb(&at_corner));
+
+        let bounds = AABB::with_bounds(Point3::splat(-3.0), Point3::splat(3.0));
+        let mut seed = 0;
+        for _ in 0..1000 {
+            let point = next_point3(&mut seed, &bounds);
+            let aabb = AABB::with_bounds(point, point + Vector3::splat(0.1));
+            if signed_distance(&rounded, aabb.center()) < 0.0 {
+                assert!(rounded.intersects_aabb(&aabb));
+            }
+            if signed_distance(&rounded, aabb.center()) > 0.1 {
+                assert!(!rounded.intersects_aabb(&aabb));
+            }
+        }
+        assert_eq!(rounded.aabb().size(), Vector3::splat(2.0));
+    }
+}
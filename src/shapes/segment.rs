@@ -0,0 +1,205 @@
+//! This module defines a line Segment and its intersection algorithms
+
+use crate::{
+    aabb::{Bounded, AABB},
+    ray::{Intersection, IntersectionRay, Ray},
+    Point3, Real, Vector3, EPSILON,
+};
+
+/// A representation of a line Segment with an optional radius, which makes it a thin capsule
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct Segment {
+    /// Start point of the segment
+    pub start: Point3,
+    /// End point of the segment
+    pub end: Point3,
+    /// Distance from the segment within which rays hit it
+    pub radius: Real,
+}
+
+impl Segment {
+    /// Creates a segment between two points with a radius around it. A radius of zero is
+    /// widened to [`EPSILON`] when intersecting rays, so that they can hit the segment.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::Bounded;
+    /// use bvh::ray::{IntersectionRay, Ray};
+    /// use bvh::segment::Segment;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// // A wire along the x axis.
+    /// let wire = Segment::new(Point3::new(-1.0, 0.0, 0.0), Point3::new(1.0, 0.0, 0.0), 0.5);
+    /// assert_eq!(wire.aabb().min, Point3::new(-1.5, -0.5, -0.5));
+    ///
+    /// let ray = Ray::new(Point3::new(0.5, 0.25, -5.0), Vector3::new(0.0, 0.0, 1.0));
+    /// let hit = wire.intersects_ray(&ray, 0.0, f32::INFINITY).unwrap();
+    /// assert_eq!(hit.distance, 5.0);
+    /// assert_eq!(hit.u, 0.75);
+    /// ```
+    ///
+    /// [`EPSILON`]: ../constant.EPSILON.html
+    ///
+    pub fn new(start: Point3, end: Point3, radius: Real) -> Segment {
+        Segment { start, end, radius }
+    }
+
+    /// Returns the point of the segment at `s`, which is `start` for 0 and `end` for 1.
+    pub fn at(&self, s: Real) -> Point3 {
+        self.start + (self.end - self.start) * s
+    }
+
+    /// Returns the parameter `s` of the point of the segment which is closest to `point`,
+    /// see [`Segment::at`].
+    ///
+    /// [`Segment::at`]: struct.Segment.html#method.at
+    ///
+    pub fn closest_point(&self, point: &Point3) -> Real {
+        let direction = self.end - self.start;
+        let length_squared = direction.length_squared();
+        if length_squared <= EPSILON {
+            return 0.0;
+        }
+        ((*point - self.start).dot(direction) / length_squared).clamp(0.0, 1.0)
+    }
+
+    /// Returns the parameters `(t, s)` of the closest points of the part of the `ray`
+    /// within `[t_min, t_max]` and of the segment, such that `ray.at(t)` is closest to
+    /// `self.at(s)`. If the ray is parallel to the segment, the closest points with the
+    /// smallest `t` are returned.
+    pub fn closest_points_to_ray(&self, ray: &Ray, t_min: Real, t_max: Real) -> (Real, Real) {
+        let direction = self.end - self.start;
+        let offset = ray.origin - self.start;
+        let a = ray.direction.length_squared();
+        let b = ray.direction.dot(direction);
+        let c = ray.direction.dot(offset);
+        let e = direction.length_squared();
+        let f = direction.dot(offset);
+
+        // Find the closest point on the ray to the line through the segment, clamp it to the
+        // ray interval, project it onto the segment and project that point back on the ray.
+        let denominator = a * e - b * b;
+        let t = if denominator > EPSILON {
+            (b * f - c * e) / denominator
+        } else {
+            t_min
+        };
+        let t = t.clamp(t_min, t_max);
+        let s = if e > EPSILON {
+            ((b * t + f) / e).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let t = ((b * s - c) / a).clamp(t_min, t_max);
+        (t, s)
+    }
+}
+
+impl IntersectionRay for Segment {
+    /// Intersects the ray with the segment, which is hit if the ray passes within `radius`
+    /// of it. The distance of the [`Intersection`] is the one of the closest approach,
+    /// which approximates the entry into a thin capsule. The `u` coordinate is the
+    /// parameter of the closest point on the segment, see [`Segment::at`], and `v` is the
+    /// distance from the segment divided by the radius. The normal is perpendicular to the
+    /// segment and faces the ray, and the hit is never a back face.
+    ///
+    /// [`Intersection`]: ../ray/struct.Intersection.html
+    /// [`Segment::at`]: struct.Segment.html#method.at
+    ///
+    fn intersects_ray(&self, ray: &Ray, t_min: Real, t_max: Real) -> Option<Intersection> {
+        let radius = self.radius.max(EPSILON);
+        let (t, s) = self.closest_points_to_ray(ray, t_min, t_max);
+        let offset = ray.at(t) - self.at(s);
+        let distance = offset.length();
+        if distance > radius {
+            return None;
+        }
+
+        // The surface of a thin segment is not resolved, so it faces the ray like a ribbon.
+        let axis = (self.end - self.start).normalize_or_zero();
+        let facing = -ray.direction + axis * ray.direction.dot(axis);
+        let out_norm = if facing.length_squared() > EPSILON {
+            facing.normalize()
+        } else {
+            -ray.direction
+        };
+        Some(Intersection::new(t, s, distance / radius, out_norm, false))
+    }
+}
+
+impl Bounded for Segment {
+    fn aabb(&self) -> AABB {
+        let radius = Vector3::splat(self.radius);
+        AABB::with_bounds(
+            self.start.min(self.end) - radius,
+            self.start.max(self.end) + radius,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::Bounded;
+    use crate::ray::{IntersectionRay, Ray};
+    use crate::segment::Segment;
+    use crate::{Point3, Real, Vector3, EPSILON};
+
+    #[test]
+    /// Tests rays which cross the segment, pass beside or beyond it, and run parallel to it.
+    fn test_segment_intersects_ray() {
+        let segment = Segment::new(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 4.0, 0.0), 0.5);
+        let intersect = |origin: Point3, direction: Vector3| {
+            segment.intersects_ray(&Ray::new(origin, direction), 0.0, Real::INFINITY)
+        };
+
+        let hit = intersect(Point3::new(-3.0, 1.0, 0.3), Vector3::new(1.0, 0.0, 0.0)).unwrap();
+        assert!((hit.distance - 3.0).abs() < EPSILON);
+        assert!((hit.u - 0.25).abs() < EPSILON);
+        assert!((hit.v - 0.6).abs() < EPSILON);
+        assert!((hit.norm - Vector3::new(-1.0, 0.0, 0.0)).length() < EPSILON);
+        assert!(!hit.back_face);
+
+        // Beside the radius, beyond the end and behind the origin.
+        assert!(intersect(Point3::new(-3.0, 1.0, 0.6), Vector3::new(1.0, 0.0, 0.0)).is_none());
+        assert!(intersect(Point3::new(-3.0, 4.6, 0.0), Vector3::new(1.0, 0.0, 0.0)).is_none());
+        assert!(intersect(Point3::new(3.0, 1.0, 0.0), Vector3::new(1.0, 0.0, 0.0)).is_none());
+
+        // Close to the end the ray passes the rounded cap.
+        let cap = intersect(Point3::new(-3.0, 4.4, 0.0), Vector3::new(1.0, 0.0, 0.0)).unwrap();
+        assert!((cap.u - 1.0).abs() < EPSILON);
+
+        // Parallel rays hit the segment at its start.
+        let parallel = intersect(Point3::new(0.2, -2.0, 0.0), Vector3::new(0.0, 1.0, 0.0)).unwrap();
+        assert!((parallel.distance - 2.0).abs() < EPSILON);
+        assert!(parallel.u.abs() < EPSILON);
+        assert!(intersect(Point3::new(0.7, -2.0, 0.0), Vector3::new(0.0, 1.0, 0.0)).is_none());
+
+        // The closest approach is clamped to the ray interval.
+        let ray = Ray::new(Point3::new(-3.0, 1.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        assert!(segment.intersects_ray(&ray, 0.0, 2.0).is_none());
+        let clamped = segment.intersects_ray(&ray, 0.0, 2.6).unwrap();
+        assert!((clamped.distance - 2.6).abs() < EPSILON);
+    }
+
+    #[test]
+    /// Tests the bounds of a segment with and without radius.
+    fn test_segment_aabb() {
+        let segment = Segment::new(
+            Point3::new(1.0, -2.0, 3.0),
+            Point3::new(-1.0, 2.0, 0.0),
+            0.0,
+        );
+        assert_eq!(segment.aabb().min, Point3::new(-1.0, -2.0, 0.0));
+        assert_eq!(segment.aabb().max, Point3::new(1.0, 2.0, 3.0));
+
+        let thick = Segment {
+            radius: 1.0,
+            ..segment
+        };
+        assert_eq!(thick.aabb().min, Point3::new(-2.0, -3.0, -1.0));
+        assert_eq!(thick.aabb().max, Point3::new(2.0, 3.0, 4.0));
+        assert!(thick.aabb().contains(&thick.at(0.5)));
+        assert!((segment.closest_point(&Point3::new(-3.0, 6.0, -3.0)) - 1.0).abs() < EPSILON);
+    }
+}
@@ -2,7 +2,10 @@
 
 use crate::{
     aabb::{Bounded, AABB},
-    bounding_hierarchy::IntersectionAABB,
+    bounding_hierarchy::{IntersectionAABB, IntersectionShape, Penetration, PenetrationDepth},
+    capsule::Capsule,
+    gjk::SupportMap,
+    obb::OBB,
     ray::{Intersection, IntersectionRay, Ray},
     Point3, Real, Vector3, PI,
 };
@@ -26,8 +29,75 @@ impl Sphere {
 
 impl IntersectionAABB for Sphere {
     fn intersects_aabb(&self, aabb: &AABB) -> bool {
-        let closest = aabb.closest_point(self.center);
-        closest.distance_squared(self.center) <= self.radius * self.radius
+        aabb.distance_squared(&self.center) <= self.radius * self.radius
+    }
+}
+
+impl IntersectionShape<AABB> for Sphere {
+    fn intersects_shape(&self, other: &AABB) -> bool {
+        self.intersects_aabb(other)
+    }
+}
+
+impl IntersectionShape for Sphere {
+    fn intersects_shape(&self, other: &Sphere) -> bool {
+        let radius = self.radius + other.radius;
+        self.center.distance_squared(other.center) <= radius * radius
+    }
+}
+
+impl IntersectionShape<OBB> for Sphere {
+    fn intersects_shape(&self, other: &OBB) -> bool {
+        other.intersects_shape(self)
+    }
+}
+
+impl IntersectionShape<Capsule> for Sphere {
+    fn intersects_shape(&self, other: &Capsule) -> bool {
+        other.intersects_shape(self)
+    }
+}
+
+impl Sphere {
+    /// Computes the penetration of another sphere of `radius` around `center`, which is
+    /// moved away from this sphere. Concentric spheres are moved along the y axis.
+    fn sphere_penetration(&self, center: &Point3, radius: Real) -> Option<Penetration> {
+        let offset = *center - self.center;
+        let distance = offset.length();
+        let radius = self.radius + radius;
+        if distance >= radius {
+            return None;
+        }
+        let normal = if distance > 0.0 {
+            offset / distance
+        } else {
+            Vector3::Y
+        };
+        Some(Penetration {
+            normal,
+            depth: radius - distance,
+        })
+    }
+}
+
+impl PenetrationDepth for Sphere {
+    fn penetration(&self, other: &Sphere) -> Option<Penetration> {
+        self.sphere_penetration(&other.center, other.radius)
+    }
+}
+
+impl PenetrationDepth<Capsule> for Sphere {
+    /// Moves the capsule away from the sphere, starting at the point of its center line
+    /// which is closest to the center of the sphere.
+    fn penetration(&self, other: &Capsule) -> Option<Penetration> {
+        let axis = other.end() - other.start;
+        let length_squared = axis.length_squared();
+        let t = if length_squared > 0.0 {
+            ((self.center - other.start).dot(axis) / length_squared).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        self.sphere_penetration(&(other.start + axis * t), other.radius)
     }
 }
 
@@ -74,3 +144,9 @@ impl Bounded for Sphere {
         AABB::with_bounds(min, max)
     }
 }
+
+impl SupportMap for Sphere {
+    fn support(&self, direction: Vector3) -> Point3 {
+        self.center + direction.normalize_or_zero() * self.radius
+    }
+}
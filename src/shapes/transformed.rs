@@ -0,0 +1,220 @@
+//! This module defines a Transformed shape, which places any shape with a transform
+
+use crate::{
+    aabb::{Bounded, AABB},
+    bounding_hierarchy::BHShape,
+    gjk::SupportMap,
+    ray::{Intersection, IntersectionRay, Ray},
+    Mat4, Point3, Real, Vector3,
+};
+
+/// A shape placed with a transform from its local space to world space. Its bounds are the
+/// transformed bounds of the shape, and rays are intersected with the shape in local space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct Transformed<T> {
+    /// The shape in its local space
+    pub shape: T,
+    transform: Mat4,
+    inverse: Mat4,
+}
+
+impl<T> Transformed<T> {
+    /// Places `shape` with `transform`, which must be invertible.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::Bounded;
+    /// use bvh::ray::{IntersectionRay, Ray};
+    /// use bvh::sphere::Sphere;
+    /// use bvh::transformed::Transformed;
+    /// use bvh::{Mat4, Point3, Vector3};
+    ///
+    /// // A unit sphere, stretched along the x axis and moved up.
+    /// let sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0);
+    /// let transform = Mat4::from_translation(Vector3::new(0.0, 5.0, 0.0))
+    ///     * Mat4::from_scale(Vector3::new(4.0, 1.0, 1.0));
+    /// let ellipsoid = Transformed::new(sphere, transform);
+    /// assert_eq!(ellipsoid.aabb().max, Point3::new(4.0, 6.0, 1.0));
+    ///
+    /// let ray = Ray::new(Point3::new(-10.0, 5.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+    /// let hit = ellipsoid.intersects_ray(&ray, 0.0, f32::INFINITY).unwrap();
+    /// assert_eq!(hit.distance, 6.0);
+    /// assert_eq!(hit.norm, Vector3::new(-1.0, 0.0, 0.0));
+    /// ```
+    pub fn new(shape: T, transform: Mat4) -> Transformed<T> {
+        Transformed {
+            shape,
+            transform,
+            inverse: transform.inverse(),
+        }
+    }
+
+    /// Returns the transform from local space to world space.
+    pub fn transform(&self) -> &Mat4 {
+        &self.transform
+    }
+
+    /// Returns the transform from world space to local space.
+    pub fn inverse(&self) -> &Mat4 {
+        &self.inverse
+    }
+
+    /// Replaces the transform, for example to move the shape.
+    pub fn set_transform(&mut self, transform: Mat4) {
+        self.transform = transform;
+        self.inverse = transform.inverse();
+    }
+}
+
+impl<T: IntersectionRay> IntersectionRay for Transformed<T> {
+    /// Transforms the ray into local space and intersects it with the shape. Distances are
+    /// converted between the spaces, so `t_min`, `t_max` and the distance of the
+    /// [`Intersection`] are measured in world space. The normal is transformed back to world
+    /// space, `u`, `v` and the back face flag are the ones of the shape.
+    ///
+    /// [`Intersection`]: ../ray/struct.Intersection.html
+    ///
+    fn intersects_ray(&self, ray: &Ray, t_min: Real, t_max: Real) -> Option<Intersection> {
+        intersects_ray_local(&self.shape, &self.inverse, ray, t_min, t_max)
+    }
+}
+
+/// Intersects `ray` with `shape` after transforming it with `inverse` into the local space of
+/// the shape, see the [`IntersectionRay`] implementation of [`Transformed`].
+///
+/// [`IntersectionRay`]: ../ray/trait.IntersectionRay.html
+/// [`Transformed`]: struct.Transformed.html
+///
+pub(crate) fn intersects_ray_local<T: IntersectionRay + ?Sized>(
+    shape: &T,
+    inverse: &Mat4,
+    ray: &Ray,
+    t_min: Real,
+    t_max: Real,
+) -> Option<Intersection> {
+    let (local_ray, scale) = ray.transformed(inverse);
+    let mut hit = shape.intersects_ray(&local_ray, t_min * scale, t_max * scale)?;
+    hit.distance /= scale;
+    // Normals transform with the inverse transpose to stay perpendicular to the surface.
+    hit.norm = inverse
+        .transpose()
+        .transform_vector3(hit.norm)
+        .normalize_or_zero();
+    Some(hit)
+}
+
+impl<T: Bounded> Bounded for Transformed<T> {
+    fn aabb(&self) -> AABB {
+        self.shape.aabb().transformed(&self.transform)
+    }
+}
+
+impl<T: BHShape> BHShape for Transformed<T> {
+    fn set_bh_node_index(&mut self, index: usize) {
+        self.shape.set_bh_node_index(index);
+    }
+
+    fn bh_node_index(&self) -> usize {
+        self.shape.bh_node_index()
+    }
+}
+
+impl<T: SupportMap> SupportMap for Transformed<T> {
+    /// Transforms the support point of the shape for the direction transformed with the
+    /// transpose. A transformed sphere is an ellipsoid, for example.
+    fn support(&self, direction: Vector3) -> Point3 {
+        let local = self.transform.transpose().transform_vector3(direction);
+        self.transform.transform_point3(self.shape.support(local))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::{Bounded, AABB};
+    use crate::ray::{IntersectionRay, Ray};
+    use crate::sphere::Sphere;
+    use crate::testbase::{create_ray, default_bounds};
+    use crate::transformed::Transformed;
+    use crate::triangle::Triangle;
+    use crate::{Mat4, Point3, Quat, Real, Vector3, EPSILON, PI};
+
+    #[test]
+    /// Tests that a transformed triangle is hit like the triangle with transformed corners.
+    fn test_transformed_matches_world_space() {
+        let triangle = Triangle::new(
+            Point3::new(-1.0, -1.0, 0.0),
+            Point3::new(1.0, -1.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+        );
+        let transform = Mat4::from_scale_rotation_translation(
+            Vector3::new(30_000.0, 50_000.0, 1.0),
+            Quat::from_axis_angle(Vector3::new(1.0, 2.0, 3.0).normalize(), PI / 5.0),
+            Vector3::new(1000.0, -2000.0, 500.0),
+        );
+        let transformed = Transformed::new(triangle, transform);
+        let world = Triangle::new(
+            transform.transform_point3(triangle.a),
+            transform.transform_point3(triangle.b),
+            transform.transform_point3(triangle.c),
+        );
+        // The rotated bounds of the local bounds are conservative.
+        let tolerance = transformed.aabb().size().max_element() * EPSILON;
+        assert!(transformed
+            .aabb()
+            .approx_contains_aabb_eps(&world.aabb(), tolerance));
+
+        let bounds = default_bounds();
+        let mut seed = 0;
+        let mut hits = 0;
+        for _ in 0..1000 {
+            let ray = create_ray(&mut seed, &bounds);
+            // Missed triangles report an infinite distance.
+            let expected = world
+                .intersects_ray(&ray, 0.0, Real::INFINITY)
+                .filter(|hit| hit.distance.is_finite());
+            let actual = transformed
+                .intersects_ray(&ray, 0.0, Real::INFINITY)
+                .filter(|hit| hit.distance.is_finite());
+            assert_eq!(actual.is_some(), expected.is_some());
+            if let (Some(actual), Some(expected)) = (actual, expected) {
+                assert!((actual.distance - expected.distance).abs() < expected.distance * 1e-3);
+                assert!((actual.norm - expected.norm.normalize()).length() < 1e-3);
+                hits += 1;
+            }
+        }
+        assert!(hits > 0);
+    }
+
+    #[test]
+    /// Tests that the ray interval and the normal of a scaled sphere are in world space.
+    fn test_transformed_sphere() {
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0);
+        let mut ellipsoid = Transformed::new(sphere, Mat4::from_scale(Vector3::new(1.0, 2.0, 1.0)));
+        assert_eq!(
+            ellipsoid.aabb(),
+            AABB::with_bounds(Point3::new(-1.0, -2.0, -1.0), Point3::new(1.0, 2.0, 1.0))
+        );
+
+        let ray = Ray::new(Point3::new(0.0, -10.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+        let hit = ellipsoid.intersects_ray(&ray, 0.0, Real::INFINITY).unwrap();
+        assert!((hit.distance - 8.0).abs() < EPSILON);
+        assert!(ellipsoid.intersects_ray(&ray, 0.0, 7.0).is_none());
+        let far = ellipsoid.intersects_ray(&ray, 9.0, Real::INFINITY).unwrap();
+        assert!((far.distance - 12.0).abs() < EPSILON);
+
+        // The normal is perpendicular to the stretched surface, not the scaled local normal.
+        let point = Point3::new(-0.6, 1.6, 0.0);
+        let side = Ray::new(point - Vector3::X * 5.0, Vector3::X);
+        let hit = ellipsoid
+            .intersects_ray(&side, 0.0, Real::INFINITY)
+            .unwrap();
+        assert!((hit.distance - 5.0).abs() < EPSILON);
+        let expected = Vector3::new(-0.6, 1.6 / 4.0, 0.0).normalize();
+        assert!((hit.norm - expected).length() < EPSILON);
+
+        ellipsoid.set_transform(Mat4::from_translation(Vector3::new(0.0, 20.0, 0.0)));
+        let hit = ellipsoid.intersects_ray(&ray, 0.0, Real::INFINITY).unwrap();
+        assert!((hit.distance - 29.0).abs() < EPSILON);
+    }
+}
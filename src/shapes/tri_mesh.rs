@@ -0,0 +1,339 @@
+//! This module defines a TriMesh, a triangle mesh with its own [`BVH`], and its
+//! intersection algorithms.
+//!
+//! [`BVH`]: ../bvh/struct.BVH.html
+//!
+
+use crate::{
+    aabb::{Bounded, AABB},
+    bounding_hierarchy::BHShape,
+    bvh::BVH,
+    float::Tolerances,
+    ray::{Intersection, IntersectionRay, Ray},
+    triangle::Triangle,
+    Point3, Real, Vector3,
+};
+
+/// A triangle of a [`TriMesh`] as stored in its internal [`BVH`].
+///
+/// [`TriMesh`]: struct.TriMesh.html
+/// [`BVH`]: ../bvh/struct.BVH.html
+///
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+struct MeshFace {
+    index: usize,
+    aabb: AABB,
+    node_index: usize,
+}
+
+impl Bounded for MeshFace {
+    fn aabb(&self) -> AABB {
+        self.aabb
+    }
+}
+
+impl BHShape for MeshFace {
+    fn set_bh_node_index(&mut self, index: usize) {
+        self.node_index = index;
+    }
+
+    fn bh_node_index(&self) -> usize {
+        self.node_index
+    }
+}
+
+/// A triangle mesh which owns its vertices and indices and accelerates ray queries with
+/// a [`BVH`] over its triangles. This allows a whole mesh to be a single shape in a scene
+/// level hierarchy.
+///
+/// [`BVH`]: ../bvh/struct.BVH.html
+///
+#[derive(Clone)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct TriMesh {
+    vertices: Vec<Point3>,
+    indices: Vec<[u32; 3]>,
+    normals: Option<Vec<Vector3>>,
+    faces: Vec<MeshFace>,
+    bvh: BVH,
+    aabb: AABB,
+}
+
+impl TriMesh {
+    /// Creates a mesh from its vertices and the counter clockwise vertex indices of its
+    /// triangles, and builds the [`BVH`] over the triangles.
+    ///
+    /// # Panics
+    /// Panics if an index is out of range for `vertices`.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::Bounded;
+    /// use bvh::ray::{IntersectionRay, Ray};
+    /// use bvh::tri_mesh::TriMesh;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// // A unit quad in the xy plane, facing +z.
+    /// let vertices = vec![
+    ///     Point3::new(0.0, 0.0, 0.0),
+    ///     Point3::new(1.0, 0.0, 0.0),
+    ///     Point3::new(1.0, 1.0, 0.0),
+    ///     Point3::new(0.0, 1.0, 0.0),
+    /// ];
+    /// let quad = TriMesh::new(vertices, vec![[0, 1, 2], [0, 2, 3]]);
+    /// assert_eq!(quad.aabb().max, Point3::new(1.0, 1.0, 0.0));
+    ///
+    /// let ray = Ray::new(Point3::new(0.25, 0.75, 2.0), Vector3::new(0.0, 0.0, -1.0));
+    /// let (face, hit) = quad.intersects_ray_face(&ray, 0.0, f32::INFINITY).unwrap();
+    /// assert_eq!(face, 1);
+    /// assert_eq!(hit.distance, 2.0);
+    /// assert_eq!(hit.norm, Vector3::new(0.0, 0.0, 1.0));
+    /// ```
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    ///
+    pub fn new(vertices: Vec<Point3>, indices: Vec<[u32; 3]>) -> TriMesh {
+        let mut faces: Vec<MeshFace> = indices
+            .iter()
+            .enumerate()
+            .map(|(index, face)| MeshFace {
+                index,
+                aabb: face
+                    .iter()
+                    .map(|&vertex| vertices[vertex as usize])
+                    .collect(),
+                node_index: 0,
+            })
+            .collect();
+        let bvh = BVH::build(&mut faces);
+        let aabb = faces
+            .iter()
+            .fold(AABB::empty(), |aabb, face| aabb.join(&face.aabb));
+        TriMesh {
+            vertices,
+            indices,
+            normals: None,
+            faces,
+            bvh,
+            aabb,
+        }
+    }
+
+    /// Interpolates the given per vertex normals across the triangles when intersecting
+    /// rays, instead of using the flat normals of the triangles.
+    ///
+    /// # Panics
+    /// Panics if there is not exactly one normal per vertex.
+    pub fn with_vertex_normals(mut self, normals: Vec<Vector3>) -> TriMesh {
+        assert_eq!(normals.len(), self.vertices.len());
+        self.normals = Some(normals);
+        self
+    }
+
+    /// Computes per vertex normals by averaging the normals of the adjacent triangles,
+    /// weighted by their area, and interpolates them when intersecting rays.
+    pub fn with_smooth_normals(self) -> TriMesh {
+        let mut normals = vec![Vector3::ZERO; self.vertices.len()];
+        for face in 0..self.indices.len() {
+            let triangle = self.triangle(face);
+            // The length of the cross product is twice the area of the triangle.
+            let normal = (triangle.b - triangle.a).cross(triangle.c - triangle.a);
+            for &vertex in self.indices[face].iter() {
+                normals[vertex as usize] += normal;
+            }
+        }
+        let normals = normals
+            .into_iter()
+            .map(Vector3::normalize_or_zero)
+            .collect();
+        self.with_vertex_normals(normals)
+    }
+
+    /// Returns the vertices of the mesh.
+    pub fn vertices(&self) -> &[Point3] {
+        &self.vertices
+    }
+
+    /// Returns the vertex indices of the triangles of the mesh.
+    pub fn indices(&self) -> &[[u32; 3]] {
+        &self.indices
+    }
+
+    /// Returns the number of triangles in the mesh.
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// Returns true if the mesh has no triangles.
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Returns the triangle with index `face`.
+    pub fn triangle(&self, face: usize) -> Triangle {
+        let [a, b, c] = self.indices[face].map(|vertex| self.vertices[vertex as usize]);
+        Triangle::new(a, b, c)
+    }
+
+    /// Returns the index of the closest triangle hit by `ray` within `[t_min, t_max]`
+    /// together with the [`Intersection`]. Like [`Triangle`], back faces are culled. The
+    /// `u` and `v` coordinates are the barycentric coordinates of the second and third
+    /// vertex of the triangle, and the normal is normalized and interpolated from the
+    /// vertex normals, if the mesh has them.
+    ///
+    /// [`Intersection`]: ../ray/struct.Intersection.html
+    /// [`Triangle`]: ../triangle/struct.Triangle.html
+    ///
+    pub fn intersects_ray_face(
+        &self,
+        ray: &Ray,
+        t_min: Real,
+        t_max: Real,
+    ) -> Option<(usize, Intersection)> {
+        self.intersects_ray_face_eps(ray, t_min, t_max, &Tolerances::default())
+    }
+
+    /// Like [`TriMesh::intersects_ray_face`], but with the given [`Tolerances`] instead of
+    /// the default ones, e.g. for meshes which are much smaller or larger than one unit.
+    ///
+    /// [`Tolerances`]: ../float/struct.Tolerances.html
+    /// [`TriMesh::intersects_ray_face`]: struct.TriMesh.html#method.intersects_ray_face
+    ///
+    pub fn intersects_ray_face_eps(
+        &self,
+        ray: &Ray,
+        t_min: Real,
+        t_max: Real,
+        tolerances: &Tolerances,
+    ) -> Option<(usize, Intersection)> {
+        let intersect = |face: &MeshFace| {
+            self.triangle(face.index)
+                .intersects_ray_eps(ray, t_min, t_max, tolerances)
+        };
+        let (face, _) = self.bvh.traverse_nearest_with(ray, &self.faces, |face| {
+            intersect(face).map(|hit| hit.distance)
+        })?;
+        let mut hit = intersect(face)?;
+
+        hit.norm = match self.normals {
+            Some(ref normals) => {
+                let vertex_normals =
+                    self.indices[face.index].map(|vertex| normals[vertex as usize]);
+                hit.interpolate(&vertex_normals).normalize_or_zero()
+            }
+            None => hit.norm.normalize_or_zero(),
+        };
+        Some((face.index, hit))
+    }
+}
+
+impl IntersectionRay for TriMesh {
+    /// Returns the closest hit, see [`TriMesh::intersects_ray_face`].
+    ///
+    /// [`TriMesh::intersects_ray_face`]: struct.TriMesh.html#method.intersects_ray_face
+    ///
+    fn intersects_ray(&self, ray: &Ray, t_min: Real, t_max: Real) -> Option<Intersection> {
+        self.intersects_ray_face(ray, t_min, t_max)
+            .map(|(_, hit)| hit)
+    }
+}
+
+impl Bounded for TriMesh {
+    fn aabb(&self) -> AABB {
+        self.aabb
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::{Bounded, AABB};
+    use crate::ray::{IntersectionRay, Ray};
+    use crate::testbase::{create_n_cubes, default_bounds, next_point3};
+    use crate::tri_mesh::TriMesh;
+    use crate::{Point3, Real, Vector3, EPSILON};
+
+    #[test]
+    /// Tests that the closest hit of the mesh matches intersecting all triangles.
+    fn test_tri_mesh_matches_triangles() {
+        let bounds = default_bounds();
+        let triangles = create_n_cubes(100, &bounds);
+        let vertices: Vec<Point3> = triangles
+            .iter()
+            .flat_map(|triangle| [triangle.a, triangle.b, triangle.c])
+            .collect();
+        let indices = (0..triangles.len() as u32)
+            .map(|face| [face * 3, face * 3 + 1, face * 3 + 2])
+            .collect();
+        let mesh = TriMesh::new(vertices, indices);
+        assert_eq!(mesh.len(), triangles.len());
+        assert_eq!(
+            mesh.aabb(),
+            triangles
+                .iter()
+                .fold(AABB::empty(), |aabb, triangle| aabb.join(&triangle.aabb()))
+        );
+
+        let mut seed = 0;
+        let mut hits = 0;
+        for _ in 0..1000 {
+            let origin = next_point3(&mut seed, &bounds);
+            let target = next_point3(&mut seed, &bounds);
+            let ray = Ray::new(origin, target - origin);
+            let expected = triangles
+                .iter()
+                .enumerate()
+                .filter_map(|(face, triangle)| {
+                    triangle
+                        .intersects_ray(&ray, 0.0, Real::INFINITY)
+                        .filter(|hit| hit.distance.is_finite())
+                        .map(|hit| (face, hit.distance))
+                })
+                .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+            let actual = mesh
+                .intersects_ray_face(&ray, 0.0, Real::INFINITY)
+                .map(|(face, hit)| (face, hit.distance));
+            assert_eq!(actual, expected);
+            hits += actual.is_some() as usize;
+        }
+        assert!(hits > 0);
+    }
+
+    #[test]
+    /// Tests flat, given and smooth normals, and the ray interval.
+    fn test_tri_mesh_normals() {
+        // Two triangles folded along the y axis like a roof.
+        let vertices = vec![
+            Point3::new(-1.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, 1.0),
+            Point3::new(0.0, 1.0, 1.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(1.0, 1.0, 0.0),
+            Point3::new(-1.0, 1.0, 0.0),
+        ];
+        let roof = TriMesh::new(vertices, vec![[0, 1, 2], [1, 3, 4], [0, 2, 5], [1, 4, 2]]);
+        let ray = Ray::new(Point3::new(-0.5, 0.25, 5.0), Vector3::new(0.0, 0.0, -1.0));
+
+        let (face, flat) = roof.intersects_ray_face(&ray, 0.0, Real::INFINITY).unwrap();
+        assert_eq!(face, 0);
+        assert!((flat.distance - 4.5).abs() < EPSILON);
+        let left = Vector3::new(-1.0, 0.0, 1.0).normalize();
+        assert!((flat.norm - left).length() < EPSILON);
+        assert!(roof.intersects_ray(&ray, 0.0, 4.0).is_none());
+        let beside = Ray::new(Point3::new(5.0, 0.5, 5.0), Vector3::new(0.0, 0.0, -1.0));
+        assert!(roof.intersects_ray(&beside, 0.0, Real::INFINITY).is_none());
+
+        // The ridge vertices average one face of one slope with two of the other, and the
+        // hit has the barycentric coordinates (0.5, 0.25, 0.25).
+        let smooth = roof.clone().with_smooth_normals();
+        let hit = smooth.intersects_ray(&ray, 0.0, Real::INFINITY).unwrap();
+        let ridge_b = Vector3::new(1.0, 0.0, 3.0).normalize();
+        let ridge_c = Vector3::new(-1.0, 0.0, 3.0).normalize();
+        let blended = (left * 0.5 + ridge_b * 0.25 + ridge_c * 0.25).normalize();
+        assert!((hit.norm - blended).length() < EPSILON);
+
+        let given = roof.with_vertex_normals(vec![Vector3::X; 6]);
+        let hit = given.intersects_ray(&ray, 0.0, Real::INFINITY).unwrap();
+        assert_eq!(hit.norm, Vector3::X);
+    }
+}
@@ -1,9 +1,14 @@
 //! This module defines a Triangle and its intersection algorithms
 
 use crate::aabb::{Bounded, AABB};
-use crate::bounding_hierarchy::IntersectionAABB;
+use crate::bounding_hierarchy::{IntersectionAABB, Penetration, PenetrationDepth};
+use crate::capsule::Capsule;
+use crate::float::Tolerances;
+use crate::gjk::{closest_on_triangle, SupportMap};
 use crate::shapes::ray::{Intersection, IntersectionRay, Ray};
-use crate::{Point3, Real, Vector3};
+use crate::sphere::Sphere;
+use crate::utils::nearest_points_on_lines;
+use crate::{Point3, Real, Vector3, EPSILON};
 
 /// A triangle struct. Instance of a more complex `Bounded` primitive.
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -22,6 +27,163 @@ impl Triangle {
     pub fn new(a: Point3, b: Point3, c: Point3) -> Triangle {
         Triangle { a, b, c }
     }
+
+    /// Returns true if the triangle has no area or is a sliver, which no ray hits. See
+    /// [`Tolerances::is_degenerate`] with the default tolerances.
+    ///
+    /// [`Tolerances::is_degenerate`]: ../float/struct.Tolerances.html#method.is_degenerate
+    ///
+    pub fn is_degenerate(&self) -> bool {
+        Tolerances::default().is_degenerate(&self.a, &self.b, &self.c)
+    }
+
+    /// Like the [`IntersectionRay`] implementation, but also hits the back face of the
+    /// triangle, see [`Ray::intersects_triangle_double_sided`].
+    ///
+    /// [`IntersectionRay`]: ../ray/trait.IntersectionRay.html
+    /// [`Ray::intersects_triangle_double_sided`]: ../ray/struct.Ray.html#method.intersects_triangle_double_sided
+    ///
+    pub fn intersects_ray_double_sided(
+        &self,
+        ray: &Ray,
+        t_min: Real,
+        t_max: Real,
+    ) -> Option<Intersection> {
+        let tolerances = Tolerances::default();
+        let inter = ray.intersects_triangle_culling(
+            &self.a,
+            &self.b,
+            &self.c,
+            false,
+            t_min,
+            t_max,
+            &tolerances,
+        );
+        Some(inter).filter(|inter| inter.distance.is_finite())
+    }
+
+    /// Like the [`IntersectionRay`] implementation, but with the given [`Tolerances`]
+    /// instead of the default ones.
+    ///
+    /// [`IntersectionRay`]: ../ray/trait.IntersectionRay.html
+    /// [`Tolerances`]: ../float/struct.Tolerances.html
+    ///
+    pub fn intersects_ray_eps(
+        &self,
+        ray: &Ray,
+        t_min: Real,
+        t_max: Real,
+        tolerances: &Tolerances,
+    ) -> Option<Intersection> {
+        let inter = ray
+            .intersects_triangle_culling(&self.a, &self.b, &self.c, true, t_min, t_max, tolerances);
+        // Misses have an infinite distance, which an infinite `t_max` would accept.
+        Some(inter).filter(|inter| inter.distance.is_finite())
+    }
+
+    /// Returns the point of the triangle closest to `point`.
+    pub fn closest_point(&self, point: &Point3) -> Point3 {
+        let (closest, _) = closest_on_triangle(self.a - *point, self.b - *point, self.c - *point);
+        closest + *point
+    }
+
+    /// Returns the closest points of the segment from `start` to `end` and the triangle,
+    /// first the one on the segment. Both points are the same if the segment crosses the
+    /// triangle.
+    pub fn closest_points_to_segment(&self, start: &Point3, end: &Point3) -> (Point3, Point3) {
+        let offset = *end - *start;
+        let length = offset.length();
+        if length <= 0.0 {
+            return (*start, self.closest_point(start));
+        }
+
+        let normal = (self.b - self.a).cross(self.c - self.a);
+        let start_height = normal.dot(*start - self.a);
+        let end_height = normal.dot(*end - self.a);
+        if start_height * end_height <= 0.0 && start_height != end_height {
+            let crossing = *start + offset * (start_height / (start_height - end_height));
+            let inside = [(self.a, self.b), (self.b, self.c), (self.c, self.a)]
+                .iter()
+                .all(|&(p, q)| normal.dot((q - p).cross(crossing - p)) >= 0.0);
+            if inside {
+                return (crossing, crossing);
+            }
+        }
+
+        // Otherwise the points are closest at an end point of the segment or an edge of the
+        // triangle.
+        let direction = offset / length;
+        let mut candidates = vec![
+            (*start, self.closest_point(start)),
+            (*end, self.closest_point(end)),
+        ];
+        for &(p, q) in [(self.a, self.b), (self.b, self.c), (self.c, self.a)].iter() {
+            let edge_length = (q - p).length();
+            if edge_length > 0.0 {
+                let edge_direction = (q - p) / edge_length;
+                candidates.push(nearest_points_on_lines(
+                    start,
+                    &direction,
+                    length,
+                    &p,
+                    &edge_direction,
+                    edge_length,
+                ));
+            }
+        }
+        candidates
+            .into_iter()
+            .min_by(|x, y| {
+                x.0.distance_squared(x.1)
+                    .partial_cmp(&y.0.distance_squared(y.1))
+                    .unwrap()
+            })
+            .unwrap()
+    }
+
+    /// Computes the penetration of the shape swept by a sphere of `radius` along the
+    /// segment from `start` to `end`.
+    fn segment_penetration(
+        &self,
+        start: &Point3,
+        end: &Point3,
+        radius: Real,
+    ) -> Option<Penetration> {
+        let (on_segment, on_triangle) = self.closest_points_to_segment(start, end);
+        let offset = on_segment - on_triangle;
+        let distance = offset.length();
+        if distance >= radius {
+            return None;
+        }
+        if distance > EPSILON {
+            return Some(Penetration {
+                normal: offset / distance,
+                depth: radius - distance,
+            });
+        }
+
+        // The segment touches or crosses the triangle, so it is moved along the normal to
+        // the side which is closer.
+        let normal = (self.b - self.a).cross(self.c - self.a).normalize_or_zero();
+        if normal == Vector3::ZERO {
+            return None;
+        }
+        let start_height = normal.dot(*start - self.a);
+        let end_height = normal.dot(*end - self.a);
+        let above = radius - start_height.min(end_height);
+        let below = radius + start_height.max(end_height);
+        Some(if above <= below {
+            Penetration {
+                normal,
+                depth: above,
+            }
+        } else {
+            Penetration {
+                normal: -normal,
+                depth: below,
+            }
+        })
+    }
 }
 
 impl Bounded for Triangle {
@@ -32,12 +194,7 @@ impl Bounded for Triangle {
 
 impl IntersectionRay for Triangle {
     fn intersects_ray(&self, ray: &Ray, t_min: Real, t_max: Real) -> Option<Intersection> {
-        let inter = ray.intersects_triangle(&self.a, &self.b, &self.c);
-        if inter.distance <= t_max && inter.distance >= t_min {
-            Some(inter)
-        } else {
-            None
-        }
+        self.intersects_ray_eps(ray, t_min, t_max, &Tolerances::default())
     }
 }
 
@@ -80,6 +237,167 @@ impl IntersectionAABB for Triangle {
     }
 }
 
+impl SupportMap for Triangle {
+    fn support(&self, direction: Vector3) -> Point3 {
+        let (a, b, c) = (
+            self.a.dot(direction),
+            self.b.dot(direction),
+            self.c.dot(direction),
+        );
+        if a >= b && a >= c {
+            self.a
+        } else if b >= c {
+            self.b
+        } else {
+            self.c
+        }
+    }
+}
+
+impl PenetrationDepth<Sphere> for Triangle {
+    /// Returns the translation which moves the sphere away from the closest point of the
+    /// triangle. Both sides of the triangle are solid, so a sphere whose center lies on the
+    /// triangle is moved along its normal. Degenerate triangles are never penetrated.
+    fn penetration(&self, other: &Sphere) -> Option<Penetration> {
+        self.segment_penetration(&other.center, &other.center, other.radius)
+    }
+}
+
+impl PenetrationDepth<Capsule> for Triangle {
+    /// Returns the translation which moves the capsule away from the closest point of the
+    /// triangle. Capsules whose center line crosses the triangle are moved along its normal
+    /// to the side which needs the shorter translation.
+    fn penetration(&self, other: &Capsule) -> Option<Penetration> {
+        self.segment_penetration(&other.start, &other.end(), other.radius)
+    }
+}
+
+/// A [`Triangle`] with cached data for fast ray intersection tests in the leaves of a
+/// hierarchy. It stores the transformation into the space of the triangle, where the
+/// triangle edges are the unit axes and its normal is the third axis, so a ray test only
+/// transforms the ray and can reject it by its distance before computing the barycentric
+/// coordinates. The results match the [`IntersectionRay`] implementation of [`Triangle`],
+/// including the culling of back faces.
+///
+/// # Examples
+/// ```
+/// use bvh::ray::{IntersectionRay, Ray};
+/// use bvh::triangle::{PrecomputedTriangle, Triangle};
+/// use bvh::{Point3, Vector3};
+///
+/// let triangle = Triangle::new(
+///     Point3::new(0.0, 0.0, 0.0),
+///     Point3::new(1.0, 0.0, 0.0),
+///     Point3::new(0.0, 1.0, 0.0),
+/// );
+/// let precomputed = PrecomputedTriangle::from(triangle);
+///
+/// let ray = Ray::new(Point3::new(0.25, 0.5, 2.0), Vector3::new(0.0, 0.0, -1.0));
+/// let hit = precomputed.intersects_ray(&ray, 0.0, f32::INFINITY).unwrap();
+/// assert_eq!(hit.distance, 2.0);
+/// assert_eq!((hit.u, hit.v), (0.25, 0.5));
+/// assert!(precomputed.intersects_ray(&ray, 0.0, 1.0).is_none());
+/// ```
+///
+/// [`IntersectionRay`]: ../ray/trait.IntersectionRay.html
+/// [`Triangle`]: struct.Triangle.html
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct PrecomputedTriangle {
+    /// The rows of the inverse of the matrix with the edges and the normal as columns.
+    rows: [Vector3; 3],
+    /// The offsets of the rows, which move the first point to the origin.
+    offsets: Vector3,
+    /// The unnormalized normal, as returned by the triangle test.
+    normal: Vector3,
+    triangle: Triangle,
+}
+
+impl PrecomputedTriangle {
+    /// Precomputes the intersection data of `triangle`.
+    pub fn new(triangle: Triangle) -> PrecomputedTriangle {
+        let edge_b = triangle.b - triangle.a;
+        let edge_c = triangle.c - triangle.a;
+        let normal = edge_b.cross(edge_c);
+        let length_squared = normal.length_squared();
+
+        // The inverse of `[edge_b, edge_c, normal]` has closed form rows, as the normal is
+        // perpendicular to the edges. Degenerate triangles get zero rows, so every ray is
+        // parallel to them and misses, like in the triangle tests of `Ray`.
+        let rows = if !triangle.is_degenerate() {
+            [
+                edge_c.cross(normal) / length_squared,
+                normal.cross(edge_b) / length_squared,
+                normal / length_squared,
+            ]
+        } else {
+            [Vector3::ZERO; 3]
+        };
+        let offsets = -Vector3::new(
+            rows[0].dot(triangle.a),
+            rows[1].dot(triangle.a),
+            rows[2].dot(triangle.a),
+        );
+        PrecomputedTriangle {
+            rows,
+            offsets,
+            normal,
+            triangle,
+        }
+    }
+
+    /// Returns the triangle.
+    pub fn triangle(&self) -> &Triangle {
+        &self.triangle
+    }
+}
+
+impl From<Triangle> for PrecomputedTriangle {
+    fn from(triangle: Triangle) -> PrecomputedTriangle {
+        PrecomputedTriangle::new(triangle)
+    }
+}
+
+impl Bounded for PrecomputedTriangle {
+    fn aabb(&self) -> AABB {
+        self.triangle.aabb()
+    }
+}
+
+impl<T> PenetrationDepth<T> for PrecomputedTriangle
+where
+    Triangle: PenetrationDepth<T>,
+{
+    fn penetration(&self, other: &T) -> Option<Penetration> {
+        self.triangle.penetration(other)
+    }
+}
+
+impl IntersectionRay for PrecomputedTriangle {
+    fn intersects_ray(&self, ray: &Ray, t_min: Real, t_max: Real) -> Option<Intersection> {
+        // The height of the ray above the plane of the triangle, in units of the normal.
+        let direction_height = self.rows[2].dot(ray.direction);
+        // Rays parallel to the triangle or seeing its back face miss it.
+        if direction_height >= -EPSILON * self.rows[2].length() {
+            return None;
+        }
+        let origin_height = self.rows[2].dot(ray.origin) + self.offsets.z;
+        let distance = -origin_height / direction_height;
+        if distance <= EPSILON || distance < t_min || distance > t_max {
+            return None;
+        }
+
+        let point = ray.at(distance);
+        let u = self.rows[0].dot(point) + self.offsets.x;
+        let v = self.rows[1].dot(point) + self.offsets.y;
+        if u < 0.0 || v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        Some(Intersection::new(distance, u, v, self.normal, false))
+    }
+}
+
 fn separating_axis_test(
     verts: &[Vector3; 3],
     normals: &[Vector3; 3],
@@ -98,3 +416,336 @@ fn separating_axis_test(
     let min = projection.min_element();
     (-max).max(min) <= r
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::AABB;
+    use crate::bounding_hierarchy::PenetrationDepth;
+    use crate::capsule::Capsule;
+    use crate::float::Tolerances;
+    use crate::float::{gamma, relative_eq};
+    use crate::ray::{IntersectionRay, Ray};
+    use crate::sphere::Sphere;
+    use crate::testbase::{create_n_cubes, create_ray, next_point3};
+    use crate::triangle::{PrecomputedTriangle, Triangle};
+    use crate::{Point3, Real, Vector3};
+
+    #[test]
+    /// Tests that precomputed triangles are hit like the triangles they are created from.
+    fn test_precomputed_triangle_matches_triangle() {
+        let bounds = AABB::with_bounds(Point3::splat(-10.0), Point3::splat(10.0));
+        let triangles: Vec<Triangle> = create_n_cubes(50, &bounds)
+            .iter()
+            .map(|triangle| Triangle::new(triangle.a, triangle.b, triangle.c))
+            .collect();
+        let precomputed: Vec<PrecomputedTriangle> = triangles
+            .iter()
+            .copied()
+            .map(PrecomputedTriangle::from)
+            .collect();
+
+        let mut seed = 0;
+        let mut hits = 0;
+        for _ in 0..1000 {
+            let ray = create_ray(&mut seed, &bounds);
+            for (triangle, precomputed) in triangles.iter().zip(precomputed.iter()) {
+                let expected = triangle.intersects_ray(&ray, 0.0, Real::INFINITY);
+                let actual = precomputed.intersects_ray(&ray, 0.0, Real::INFINITY);
+                match (actual, expected) {
+                    (Some(actual), Some(expected)) => {
+                        assert!(relative_eq(actual.distance, expected.distance, 1e-4));
+                        assert!((actual.u - expected.u).abs() < 1e-3);
+                        assert!((actual.v - expected.v).abs() < 1e-3);
+                        assert_eq!(actual.norm, expected.norm);
+                        hits += 1;
+                    }
+                    (None, None) => {}
+                    // Rays through the edges may be decided differently by rounding.
+                    (Some(hit), None) | (None, Some(hit)) => {
+                        let edge = hit.u.min(hit.v).min(1.0 - hit.u - hit.v);
+                        assert!(edge.abs() < 1e-3);
+                    }
+                }
+            }
+        }
+        assert!(hits > 0);
+    }
+
+    #[test]
+    /// Tests back faces, the ray interval and degenerate triangles.
+    fn test_precomputed_triangle_misses() {
+        let triangle = PrecomputedTriangle::new(Triangle::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(2.0, 0.0, 0.0),
+            Point3::new(0.0, 2.0, 0.0),
+        ));
+        let down = Ray::new(Point3::new(0.5, 0.5, 3.0), Vector3::new(0.0, 0.0, -1.0));
+        let up = Ray::new(Point3::new(0.5, 0.5, -3.0), Vector3::new(0.0, 0.0, 1.0));
+        let beside = Ray::new(Point3::new(1.5, 1.5, 3.0), Vector3::new(0.0, 0.0, -1.0));
+        let hit = triangle.intersects_ray(&down, 0.0, Real::INFINITY).unwrap();
+        assert_eq!((hit.distance, hit.u, hit.v), (3.0, 0.25, 0.25));
+        assert!(triangle
+            .intersects_ray(&down, 3.5, Real::INFINITY)
+            .is_none());
+        assert!(triangle.intersects_ray(&up, 0.0, Real::INFINITY).is_none());
+        assert!(triangle
+            .intersects_ray(&beside, 0.0, Real::INFINITY)
+            .is_none());
+
+        let point = Point3::new(1.0, 1.0, 0.0);
+        let degenerate = PrecomputedTriangle::new(Triangle::new(point, point, point));
+        assert!(degenerate
+            .intersects_ray(&down, 0.0, Real::INFINITY)
+            .is_none());
+    }
+
+    #[test]
+    /// Tests that every triangle test only reports hits within the ray interval, including
+    /// its bounds, and never reports a miss as a hit at an infinite distance.
+    fn test_triangle_respects_interval() {
+        let triangle = Triangle::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(2.0, 0.0, 0.0),
+            Point3::new(0.0, 2.0, 0.0),
+        );
+        let precomputed = PrecomputedTriangle::new(triangle);
+        let down = Ray::new(Point3::new(0.5, 0.5, 2.0), Vector3::new(0.0, 0.0, -1.0));
+        let up = Ray::new(Point3::new(0.5, 0.5, -2.0), Vector3::new(0.0, 0.0, 1.0));
+        let beside = Ray::new(Point3::new(1.5, 1.5, 2.0), Vector3::new(0.0, 0.0, -1.0));
+        let (a, b, c) = (&triangle.a, &triangle.b, &triangle.c);
+        let tolerances = Tolerances::default();
+        let distances = |ray: &Ray, t_min: Real, t_max: Real| {
+            [
+                triangle.intersects_ray(ray, t_min, t_max),
+                triangle.intersects_ray_double_sided(ray, t_min, t_max),
+                precomputed.intersects_ray(ray, t_min, t_max),
+            ]
+            .iter()
+            .map(|hit| hit.map(|hit| hit.distance))
+            .chain(std::iter::once(
+                Some(ray.intersects_triangle_watertight_interval(
+                    a,
+                    b,
+                    c,
+                    t_min,
+                    t_max,
+                    &tolerances,
+                ))
+                .map(|hit| hit.distance)
+                .filter(|distance| distance.is_finite()),
+            ))
+            .collect::<Vec<_>>()
+        };
+
+        assert_eq!(distances(&down, 0.0, Real::INFINITY), [Some(2.0); 4]);
+        assert_eq!(distances(&down, 2.0, 2.0), [Some(2.0); 4]);
+        assert_eq!(distances(&down, 0.0, 1.5), [None; 4]);
+        assert_eq!(distances(&down, 2.5, Real::INFINITY), [None; 4]);
+        assert_eq!(distances(&beside, 0.0, Real::INFINITY), [None; 4]);
+        assert_eq!(
+            distances(&up, 0.0, Real::INFINITY),
+            [None, Some(2.0), None, Some(2.0)]
+        );
+        assert_eq!(distances(&up, 0.0, 1.0), [None; 4]);
+    }
+
+    #[test]
+    /// Tests that no triangle test hits triangles without area or slivers, even through
+    /// their points, while a slightly thicker triangle is hit.
+    fn test_degenerate_triangles_are_never_hit() {
+        let a = Point3::new(0.0, 0.0, 0.0);
+        let b = Point3::new(1000.0, 0.0, 0.0);
+        let degenerate = [
+            Triangle::new(a, a, a),
+            Triangle::new(a, b, b),
+            Triangle::new(a, b, Point3::new(500.0, 0.0, 0.0)),
+            Triangle::new(a, b, Point3::new(500.0, 1.0e-3, 0.0)),
+        ];
+        let sliver = degenerate[3];
+        let thin = Triangle::new(a, b, Point3::new(500.0, 1.0, 0.0));
+        let hits = |triangle: &Triangle, ray: &Ray| {
+            let (a, b, c) = (&triangle.a, &triangle.b, &triangle.c);
+            [
+                ray.intersects_triangle(a, b, c).distance,
+                ray.intersects_triangle_double_sided(a, b, c).distance,
+                ray.intersects_triangle_watertight(a, b, c).distance,
+            ]
+            .iter()
+            .filter(|distance| distance.is_finite())
+            .count()
+                + triangle.intersects_ray(ray, 0.0, 2.0).iter().count()
+                + PrecomputedTriangle::new(*triangle)
+                    .intersects_ray(ray, 0.0, 2.0)
+                    .iter()
+                    .count()
+        };
+
+        let down = |x: Real, y: Real| Ray::new(Point3::new(x, y, 1.0), -Vector3::Z);
+        for triangle in degenerate.iter() {
+            assert!(triangle.is_degenerate());
+            for ray in [down(0.0, 0.0), down(500.0, 0.0), down(500.0, 1.0e-4)].iter() {
+                assert_eq!(hits(triangle, ray), 0);
+            }
+        }
+        assert!(!thin.is_degenerate());
+        assert_eq!(hits(&thin, &down(500.0, 0.5)), 5);
+
+        // Without a sliver tolerance only triangles without area are degenerate.
+        let no_sliver = Tolerances {
+            sliver: 0.0,
+            ..Tolerances::default()
+        };
+        assert!(!no_sliver.is_degenerate(&sliver.a, &sliver.b, &sliver.c));
+        let ray = down(500.0, 1.0e-4);
+        assert!(sliver
+            .intersects_ray_eps(&ray, 0.0, 2.0, &no_sliver)
+            .is_some());
+        let collinear = degenerate[2];
+        assert!(no_sliver.is_degenerate(&collinear.a, &collinear.b, &collinear.c));
+        assert_eq!(
+            Tolerances::scaled(1.0e3).sliver,
+            Tolerances::default().sliver
+        );
+    }
+
+    #[test]
+    /// Tests that random spheres and capsules are moved just out of random triangles.
+    fn test_triangle_penetration() {
+        let bounds = AABB::with_bounds(Point3::splat(-2.0), Point3::splat(2.0));
+        let unit = AABB::with_bounds(Point3::splat(0.1), Point3::splat(1.0));
+        let mut seed = 0;
+        let mut penetrations = 0;
+        for _ in 0..1000 {
+            let triangle = Triangle::new(
+                next_point3(&mut seed, &bounds),
+                next_point3(&mut seed, &bounds),
+                next_point3(&mut seed, &bounds),
+            );
+            let radius = next_point3(&mut seed, &unit).x;
+            let sphere = Sphere::new(next_point3(&mut seed, &bounds), radius);
+            let capsule = Capsule::new(
+                next_point3(&mut seed, &bounds),
+                next_point3(&mut seed, &bounds),
+                radius,
+            );
+            let moved_sphere = |offset: Vector3| Sphere::new(sphere.center + offset, radius);
+            let moved_capsule = |offset: Vector3| {
+                Capsule::new(capsule.start + offset, capsule.end() + offset, radius)
+            };
+
+            let closest = triangle.closest_point(&sphere.center);
+            match triangle.penetration(&sphere) {
+                Some(penetration) => {
+                    penetrations += 1;
+                    assert!(closest.distance(sphere.center) < radius + 1e-4);
+                    assert!(relative_eq(penetration.normal.length(), 1.0, gamma(16)));
+                    let out = penetration.translation() + penetration.normal * 1e-3;
+                    assert!(triangle.penetration(&moved_sphere(out)).is_none());
+                    if penetration.depth > 1e-2 {
+                        let short = penetration.translation() - penetration.normal * 1e-2;
+                        assert!(triangle.penetration(&moved_sphere(short)).is_some());
+                    }
+                }
+                None => assert!(closest.distance(sphere.center) > radius - 1e-4),
+            }
+
+            let (on_segment, on_triangle) =
+                triangle.closest_points_to_segment(&capsule.start, &capsule.end());
+            match triangle.penetration(&capsule) {
+                Some(penetration) => {
+                    penetrations += 1;
+                    assert!(on_segment.distance(on_triangle) < radius + 1e-4);
+                    let out = penetration.translation() + penetration.normal * 1e-3;
+                    assert!(triangle.penetration(&moved_capsule(out)).is_none());
+                }
+                None => assert!(on_segment.distance(on_triangle) > radius - 1e-4),
+            }
+        }
+        assert!(penetrations > 100);
+    }
+
+    #[test]
+    /// Tests the closest points of segments which cross, pass and lie beside a triangle.
+    fn test_triangle_closest_points_to_segment() {
+        let triangle = Triangle::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(2.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, 2.0),
+        );
+        let crossing = triangle
+            .closest_points_to_segment(&Point3::new(0.5, 1.0, 0.5), &Point3::new(0.5, -1.0, 0.5));
+        assert_eq!(
+            crossing,
+            (Point3::new(0.5, 0.0, 0.5), Point3::new(0.5, 0.0, 0.5))
+        );
+
+        // A segment above the triangle and one passing its hypotenuse.
+        let above = triangle
+            .closest_points_to_segment(&Point3::new(0.5, 1.0, 0.5), &Point3::new(0.5, 3.0, 0.5));
+        assert_eq!(
+            above,
+            (Point3::new(0.5, 1.0, 0.5), Point3::new(0.5, 0.0, 0.5))
+        );
+        let (on_segment, on_triangle) = triangle
+            .closest_points_to_segment(&Point3::new(2.0, -1.0, 2.0), &Point3::new(2.0, 1.0, 2.0));
+        assert!(on_segment.abs_diff_eq(Point3::new(2.0, 0.0, 2.0), 1e-5));
+        assert!(on_triangle.abs_diff_eq(Point3::new(1.0, 0.0, 1.0), 1e-5));
+
+        // A capsule lying through the triangle is pushed out to the closer side.
+        let capsule = Capsule::new(
+            Point3::new(0.5, 0.5, 0.5),
+            Point3::new(1.0, -0.25, 0.5),
+            0.5,
+        );
+        let penetration = triangle.penetration(&capsule).unwrap();
+        assert!(penetration
+            .normal
+            .abs_diff_eq(Vector3::new(0.0, 1.0, 0.0), 1e-5));
+        assert!((penetration.depth - 0.75).abs() < 1e-5);
+    }
+}
+
+#[cfg(all(feature = "bench", test))]
+mod bench {
+    use crate::ray::IntersectionRay;
+    use crate::testbase::{create_n_cubes, create_ray, default_bounds};
+    use crate::triangle::{PrecomputedTriangle, Triangle};
+
+    /// Intersects 1,200 triangles with 100 rays, the way the leaves of a hierarchy do.
+    fn intersect_triangles<T: IntersectionRay>(triangles: &[T], b: &mut ::test::Bencher) {
+        let bounds = default_bounds();
+        let mut seed = 0;
+        let rays: Vec<_> = (0..100).map(|_| create_ray(&mut seed, &bounds)).collect();
+        b.iter(|| {
+            for ray in rays.iter() {
+                for triangle in triangles.iter() {
+                    ::test::black_box(triangle.intersects_ray(ray, 0.0, 100_000.0));
+                }
+            }
+        });
+    }
+
+    /// Creates 1,200 triangles.
+    fn create_triangles() -> Vec<Triangle> {
+        create_n_cubes(100, &default_bounds())
+            .iter()
+            .map(|triangle| Triangle::new(triangle.a, triangle.b, triangle.c))
+            .collect()
+    }
+
+    #[bench]
+    /// Benchmark intersecting 1,200 triangles with the Möller-Trumbore test.
+    fn bench_intersect_1200_triangles(b: &mut ::test::Bencher) {
+        intersect_triangles(&create_triangles(), b);
+    }
+
+    #[bench]
+    /// Benchmark intersecting 1,200 precomputed triangles.
+    fn bench_intersect_1200_precomputed_triangles(b: &mut ::test::Bencher) {
+        let triangles: Vec<PrecomputedTriangle> = create_triangles()
+            .into_iter()
+            .map(PrecomputedTriangle::from)
+            .collect();
+        intersect_triangles(&triangles, b);
+    }
+}
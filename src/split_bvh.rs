@@ -0,0 +1,404 @@
+//! This module exports a flat `BVH` layout with separate arrays for inner nodes and leaves,
+//! whose leaves reference ranges of primitives.
+
+use crate::aabb::{Bounded, AABB};
+use crate::bounding_hierarchy::{BHShape, BoundingHierarchy, IntersectionAABB};
+use crate::bvh::{BVHNode, BVH};
+
+/// An inner node of a [`SplitBVH`], which stores the [`AABB`]s of both of its children.
+///
+/// [`AABB`]: ../aabb/struct.AABB.html
+/// [`SplitBVH`]: struct.SplitBVH.html
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct SplitInnerNode {
+    /// The [`AABB`]s of the left and the right child.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub child_aabbs: [AABB; 2],
+
+    /// The left and the right child. A child is either the index of another
+    /// [`SplitInnerNode`], or the index of a [`SplitLeaf`] with
+    /// [`SplitInnerNode::LEAF_FLAG`] set.
+    ///
+    /// [`SplitInnerNode`]: struct.SplitInnerNode.html
+    /// [`SplitInnerNode::LEAF_FLAG`]: struct.SplitInnerNode.html#associatedconstant.LEAF_FLAG
+    /// [`SplitLeaf`]: struct.SplitLeaf.html
+    ///
+    pub children: [u32; 2],
+}
+
+impl SplitInnerNode {
+    /// Marks a child which refers to a leaf instead of another inner node.
+    pub const LEAF_FLAG: u32 = 1 << 31;
+}
+
+/// A leaf of a [`SplitBVH`], which references a range of its primitive index array.
+///
+/// [`SplitBVH`]: struct.SplitBVH.html
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct SplitLeaf {
+    /// The position of the first primitive of the leaf in the primitive index array.
+    pub first_primitive: u32,
+
+    /// The number of primitives of the leaf.
+    pub primitive_count: u32,
+}
+
+/// A flat [`BVH`] which stores inner nodes and leaves in separate arrays, like the layouts
+/// of Embree or OptiX. Leaves only hold a range of the primitive index array, so they need
+/// 8 bytes instead of a full node, and subtrees with few shapes are collapsed into a single
+/// leaf. It is created with [`BVH::flatten_split`].
+///
+/// The root is the first inner node. If the whole [`BVH`] fits into a single leaf, there
+/// are no inner nodes and the root is the first leaf.
+///
+/// [`BVH`]: ../bvh/struct.BVH.html
+/// [`BVH::flatten_split`]: ../bvh/struct.BVH.html#method.flatten_split
+///
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct SplitBVH {
+    /// The inner nodes.
+    pub inner_nodes: Vec<SplitInnerNode>,
+
+    /// The leaves.
+    pub leaves: Vec<SplitLeaf>,
+
+    /// The primitive index array, which lists the shape indices in the order of the leaves.
+    pub primitives: Vec<u32>,
+}
+
+impl SplitBVH {
+    /// The maximum number of shapes per leaf used by [`BoundingHierarchy::build`].
+    ///
+    /// [`BoundingHierarchy::build`]: ../bounding_hierarchy/trait.BoundingHierarchy.html#tymethod.build
+    ///
+    pub const DEFAULT_MAX_LEAF_SIZE: u32 = 4;
+
+    /// Returns the shape indices of `leaf`.
+    pub fn leaf_primitives(&self, leaf: &SplitLeaf) -> &[u32] {
+        let first = leaf.first_primitive as usize;
+        &self.primitives[first..first + leaf.primitive_count as usize]
+    }
+
+    /// Traverses the hierarchy and calls `visit` with every shape whose [`AABB`] was hit by
+    /// `ray`.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    fn visit_hits<'a, T: Bounded>(
+        &'a self,
+        ray: &impl IntersectionAABB,
+        shapes: &'a [T],
+        mut visit: impl FnMut(&'a T),
+    ) {
+        let mut visit_leaf = |leaf: &SplitLeaf| {
+            for &shape_index in self.leaf_primitives(leaf) {
+                let shape = &shapes[shape_index as usize];
+                if ray.intersects_aabb(&shape.aabb()) {
+                    visit(shape);
+                }
+            }
+        };
+        if self.inner_nodes.is_empty() {
+            if let Some(leaf) = self.leaves.first() {
+                visit_leaf(leaf);
+            }
+            return;
+        }
+
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.inner_nodes[index];
+            for slot in 0..2 {
+                if !ray.intersects_aabb(&node.child_aabbs[slot]) {
+                    continue;
+                }
+                let child = node.children[slot];
+                if child & SplitInnerNode::LEAF_FLAG != 0 {
+                    visit_leaf(&self.leaves[(child & !SplitInnerNode::LEAF_FLAG) as usize]);
+                } else {
+                    stack.push(child as usize);
+                }
+            }
+        }
+    }
+}
+
+impl BVH {
+    /// Appends the shapes below `node_index` to `primitives` in depth-first order.
+    fn collect_subtree_shapes(&self, node_index: usize, primitives: &mut Vec<u32>) {
+        let mut stack = vec![node_index];
+        while let Some(index) = stack.pop() {
+            match self.nodes[index] {
+                BVHNode::Leaf { shape_index, .. } => primitives.push(shape_index as u32),
+                BVHNode::Node {
+                    child_l_index,
+                    child_r_index,
+                    ..
+                } => {
+                    stack.push(child_r_index);
+                    stack.push(child_l_index);
+                }
+            }
+        }
+    }
+
+    /// Flattens the [`BVH`] into a [`SplitBVH`], in which every subtree with at most
+    /// `max_leaf_size` shapes becomes a single leaf.
+    ///
+    /// # Panics
+    /// Panics if `max_leaf_size` is zero.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::{BHShape, BoundingHierarchy};
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..16)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let bvh = BVH::build(&mut cubes);
+    /// let split = bvh.flatten_split(&cubes, 4);
+    /// assert!(split.leaves.len() < cubes.len());
+    /// assert_eq!(split.primitives.len(), cubes.len());
+    ///
+    /// let ray = Ray::new(Point3::new(6.0, -10.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+    /// assert_eq!(split.traverse(&ray, &cubes).len(), 1);
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`SplitBVH`]: ../split_bvh/struct.SplitBVH.html
+    ///
+    pub fn flatten_split<Shape: BHShape>(&self, shapes: &[Shape], max_leaf_size: u32) -> SplitBVH {
+        assert!(max_leaf_size > 0, "Leaves need to hold at least one shape.");
+        let mut split = SplitBVH {
+            inner_nodes: Vec::new(),
+            leaves: Vec::new(),
+            primitives: Vec::with_capacity(shapes.len()),
+        };
+        if self.nodes.is_empty() {
+            return split;
+        }
+
+        let counts = self.subtree_shape_counts();
+        if counts[0] <= max_leaf_size {
+            self.collect_subtree_shapes(0, &mut split.primitives);
+            split.leaves.push(SplitLeaf {
+                first_primitive: 0,
+                primitive_count: counts[0],
+            });
+            return split;
+        }
+
+        let placeholder = SplitInnerNode {
+            child_aabbs: [AABB::empty(); 2],
+            children: [0; 2],
+        };
+        split.inner_nodes.push(placeholder);
+
+        // Pairs of binary node and the inner node which is created for it.
+        let mut stack = vec![(0, 0)];
+        while let Some((node_index, inner_index)) = stack.pop() {
+            let node = &self.nodes[node_index];
+            let child_indices = [node.child_l(), node.child_r()];
+            let mut children = [0; 2];
+            let mut inner_children = Vec::with_capacity(2);
+            for (child, &child_index) in children.iter_mut().zip(&child_indices) {
+                if counts[child_index] <= max_leaf_size {
+                    let first_primitive = split.primitives.len() as u32;
+                    self.collect_subtree_shapes(child_index, &mut split.primitives);
+                    *child = split.leaves.len() as u32 | SplitInnerNode::LEAF_FLAG;
+                    split.leaves.push(SplitLeaf {
+                        first_primitive,
+                        primitive_count: counts[child_index],
+                    });
+                } else {
+                    *child = split.inner_nodes.len() as u32;
+                    split.inner_nodes.push(placeholder);
+                    inner_children.push((child_index, *child as usize));
+                }
+            }
+            split.inner_nodes[inner_index] = SplitInnerNode {
+                child_aabbs: [node.child_l_aabb(), node.child_r_aabb()],
+                children,
+            };
+            // Visit the left child first, so that the leaves are in depth-first order.
+            stack.extend(inner_children.into_iter().rev());
+        }
+        split
+    }
+}
+
+impl BoundingHierarchy for SplitBVH {
+    /// A [`SplitBVH`] is built from a regular [`BVH`] using the [`BVH::flatten_split`]
+    /// method with [`SplitBVH::DEFAULT_MAX_LEAF_SIZE`].
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`BVH::flatten_split`]: ../bvh/struct.BVH.html#method.flatten_split
+    /// [`SplitBVH`]: struct.SplitBVH.html
+    /// [`SplitBVH::DEFAULT_MAX_LEAF_SIZE`]: struct.SplitBVH.html#associatedconstant.DEFAULT_MAX_LEAF_SIZE
+    ///
+    fn build<T: BHShape>(shapes: &mut [T]) -> SplitBVH {
+        let bvh = BVH::build(shapes);
+        bvh.flatten_split(shapes, SplitBVH::DEFAULT_MAX_LEAF_SIZE)
+    }
+
+    /// Traverses a [`SplitBVH`] structure iteratively. The shapes of a hit leaf are tested
+    /// against their own [`AABB`]s.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`SplitBVH`]: struct.SplitBVH.html
+    ///
+    fn traverse<'a, T: Bounded>(
+        &'a self,
+        ray: &impl IntersectionAABB,
+        shapes: &'a [T],
+    ) -> Vec<&'a T> {
+        let mut hit_shapes = Vec::new();
+        self.visit_hits(ray, shapes, |shape| hit_shapes.push(shape));
+        hit_shapes
+    }
+
+    /// Traverses a [`SplitBVH`] and calls `visit` during the traversal.
+    ///
+    /// [`SplitBVH`]: struct.SplitBVH.html
+    ///
+    fn traverse_with<'a, T: Bounded>(
+        &'a self,
+        ray: &impl IntersectionAABB,
+        shapes: &'a [T],
+        visit: impl FnMut(&'a T),
+    ) {
+        self.visit_hits(ray, shapes, visit)
+    }
+
+    /// Prints a textual representation of a [`SplitBVH`].
+    ///
+    /// [`SplitBVH`]: struct.SplitBVH.html
+    ///
+    fn pretty_print(&self) {
+        let child_name = |child: u32| {
+            if child & SplitInnerNode::LEAF_FLAG != 0 {
+                format!("leaf {}", child & !SplitInnerNode::LEAF_FLAG)
+            } else {
+                format!("node {}", child)
+            }
+        };
+        for (i, node) in self.inner_nodes.iter().enumerate() {
+            println!(
+                "node {}\t{}, {}",
+                i,
+                child_name(node.children[0]),
+                child_name(node.children[1])
+            );
+        }
+        for (i, leaf) in self.leaves.iter().enumerate() {
+            println!("leaf {}\tshapes {:?}", i, self.leaf_primitives(leaf));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::Bounded;
+    use crate::bounding_hierarchy::{BoundingHierarchy, IntersectionAABB};
+    use crate::bvh::BVH;
+    use crate::split_bvh::{SplitBVH, SplitInnerNode};
+    use crate::testbase::{
+        build_some_bh, create_n_cubes, create_ray, default_bounds, query_some_bh, traverse_some_bh,
+    };
+
+    #[test]
+    /// Tests whether the building procedure succeeds in not failing.
+    fn test_build_split_bvh() {
+        build_some_bh::<SplitBVH>();
+    }
+
+    #[test]
+    /// Runs some primitive tests for intersections of a ray with a fixed scene given
+    /// as a `SplitBVH`.
+    fn test_traverse_split_bvh() {
+        traverse_some_bh::<SplitBVH>();
+    }
+
+    #[test]
+    /// Runs the nearest hit and nearest neighbor queries on a `SplitBVH`.
+    fn test_query_split_bvh() {
+        query_some_bh::<SplitBVH>();
+    }
+
+    #[test]
+    /// Checks the leaf sizes and the references to every shape for several leaf sizes, and
+    /// compares the traversal with the binary `BVH`.
+    fn test_flatten_split_matches_bvh() {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(100, &bounds);
+        let bvh = BVH::build(&mut triangles);
+
+        for &max_leaf_size in &[1, 2, 5, 2000] {
+            let split = bvh.flatten_split(&triangles, max_leaf_size);
+            let mut primitives = split.primitives.clone();
+            primitives.sort_unstable();
+            assert!(primitives.iter().enumerate().all(|(i, &p)| p as usize == i));
+            assert!(split
+                .leaves
+                .iter()
+                .all(|leaf| leaf.primitive_count > 0 && leaf.primitive_count <= max_leaf_size));
+
+            // Every leaf is referenced exactly once, and the leaves are in depth-first
+            // order, so their ranges follow each other.
+            let mut referenced = vec![0; split.leaves.len()];
+            for node in &split.inner_nodes {
+                for &child in &node.children {
+                    if child & SplitInnerNode::LEAF_FLAG != 0 {
+                        referenced[(child & !SplitInnerNode::LEAF_FLAG) as usize] += 1;
+                    }
+                }
+            }
+            assert!(split.inner_nodes.is_empty() || referenced.iter().all(|&count| count == 1));
+            let mut next_primitive = 0;
+            for leaf in &split.leaves {
+                assert_eq!(leaf.first_primitive, next_primitive);
+                next_primitive += leaf.primitive_count;
+            }
+
+            let mut seed = 0;
+            for _ in 0..100 {
+                let ray = create_ray(&mut seed, &bounds);
+                let mut expected: Vec<*const _> = bvh
+                    .traverse(&ray, &triangles)
+                    .into_iter()
+                    .filter(|triangle| ray.intersects_aabb(&triangle.aabb()))
+                    .map(|triangle| triangle as *const _)
+                    .collect();
+                let mut actual: Vec<*const _> = split
+                    .traverse(&ray, &triangles)
+                    .into_iter()
+                    .map(|triangle| triangle as *const _)
+                    .collect();
+                expected.sort_unstable();
+                actual.sort_unstable();
+                assert_eq!(expected, actual);
+            }
+        }
+    }
+}
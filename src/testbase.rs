@@ -13,8 +13,8 @@ use rand::seq::SliceRandom;
 use rand::SeedableRng;
 
 use crate::aabb::{Bounded, AABB};
-use crate::bounding_hierarchy::{BHShape, BoundingHierarchy};
-use crate::ray::Ray;
+use crate::bounding_hierarchy::{BHShape, BoundingHierarchy, Penetration, PenetrationDepth};
+use crate::ray::{Intersection, IntersectionRay, Ray};
 
 /// A vector represented as a tuple
 pub type TupleVec = (Real, Real, Real);
@@ -162,6 +162,50 @@ pub fn traverse_some_bh<BH: BoundingHierarchy>() {
     }
 }
 
+/// Compares the nearest hit and nearest neighbor queries of a `BoundingHierarchy` with a
+/// brute force search over some cubes.
+pub fn query_some_bh<BH: BoundingHierarchy>() {
+    let bounds = default_bounds();
+    let mut triangles = create_n_cubes(50, &bounds);
+    let bh = BH::build(&mut triangles);
+
+    let mut seed = 0;
+    for target in triangles.iter().step_by(5) {
+        // Aim at the center of a triangle, so that every ray hits something.
+        let origin = next_point3(&mut seed, &bounds);
+        let center = (target.a + target.b + target.c) / 3.0;
+        let ray = Ray::new(origin, center - origin);
+
+        let mut expected: Vec<Real> = triangles
+            .iter()
+            .filter_map(|triangle| triangle.intersects_ray(&ray, 0.0, Real::INFINITY))
+            .map(|hit| hit.distance)
+            .collect();
+        expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        expected.truncate(3);
+
+        let nearest = bh.traverse_n_nearest(&ray, 3, 0.0, Real::INFINITY, &triangles);
+        let distances: Vec<Real> = nearest.iter().map(|(_, hit)| hit.distance).collect();
+        assert_eq!(distances, expected);
+        let nearest = bh.traverse_nearest(&ray, 0.0, Real::INFINITY, &triangles);
+        assert_eq!(
+            nearest.map(|(_, hit)| hit.distance),
+            expected.first().copied()
+        );
+
+        let point = next_point3(&mut seed, &bounds);
+        let distance = |triangle: &Triangle| triangle.aabb().distance_squared(&point).sqrt();
+        let expected = triangles
+            .iter()
+            .map(distance)
+            .fold(Real::INFINITY, Real::min);
+        let (_, nearest) = bh
+            .nearest_neighbor(point, Real::INFINITY, &triangles, distance)
+            .unwrap();
+        assert_eq!(nearest, expected);
+    }
+}
+
 /// A triangle struct. Instance of a more complex `Bounded` primitive.
 #[derive(Copy, Clone, Debug)]
 #[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
@@ -201,6 +245,21 @@ impl BHShape for Triangle {
     }
 }
 
+impl IntersectionRay for Triangle {
+    fn intersects_ray(&self, ray: &Ray, t_min: Real, t_max: Real) -> Option<Intersection> {
+        crate::triangle::Triangle::new(self.a, self.b, self.c).intersects_ray(ray, t_min, t_max)
+    }
+}
+
+impl<T> PenetrationDepth<T> for Triangle
+where
+    crate::triangle::Triangle: PenetrationDepth<T>,
+{
+    fn penetration(&self, other: &T) -> Option<Penetration> {
+        crate::triangle::Triangle::new(self.a, self.b, self.c).penetration(other)
+    }
+}
+
 impl<I: FromPrimitive + Integer> FromRawVertex<I> for Triangle {
     fn process(
         vertices: Vec<(f32, f32, f32, f32)>,
@@ -447,7 +506,6 @@ pub fn randomly_transform_scene(
 /// Creates a `Ray` from the random `seed`. Mutates the `seed`.
 /// The Ray origin will be inside the `bounds` and point to some other point inside this
 /// `bounds`.
-#[cfg(feature = "bench")]
 pub fn create_ray(seed: &mut u64, bounds: &AABB) -> Ray {
     let origin = next_point3(seed, bounds);
     let direction = next_point3(seed, bounds);
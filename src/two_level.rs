@@ -0,0 +1,440 @@
+//! This module exports a two level layout for instanced scenes, which packs the flattened
+//! bottom level `BVH`s of all meshes and a flattened top level `BVH` over their instances
+//! into one set of GPU buffers.
+
+use crate::aabb::{Bounded, AABB};
+use crate::bounding_hierarchy::BHShape;
+use crate::bvh::BVH;
+use crate::flat_bvh::GpuSkipNode;
+use crate::Mat4;
+
+/// An instance of a bottom level [`BVH`] which is placed in the scene with a transform.
+///
+/// [`BVH`]: ../bvh/struct.BVH.html
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct Instance {
+    /// The index of the instanced bottom level [`BVH`].
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    ///
+    pub blas_index: usize,
+
+    /// The transform from the object space of the mesh to world space.
+    pub transform: Mat4,
+}
+
+/// The location of a bottom level [`BVH`] in the packed buffers of a [`TwoLevelBVH`].
+///
+/// [`BVH`]: ../bvh/struct.BVH.html
+/// [`TwoLevelBVH`]: struct.TwoLevelBVH.html
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlasRange {
+    /// The index of the first node in [`TwoLevelBVH::blas_nodes`].
+    ///
+    /// [`TwoLevelBVH::blas_nodes`]: struct.TwoLevelBVH.html#structfield.blas_nodes
+    ///
+    pub node_offset: u32,
+
+    /// The number of nodes.
+    pub node_count: u32,
+
+    /// The index of the first shape of the mesh, if the shapes of all meshes are
+    /// concatenated into one buffer in the order of the bottom level [`BVH`]s.
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    ///
+    pub shape_offset: u32,
+
+    /// The number of shapes.
+    pub shape_count: u32,
+}
+
+/// An [`Instance`] as it is uploaded to the GPU. It corresponds to the following GLSL
+/// structure, with `std430` layout:
+///
+/// ```glsl
+/// struct Instance {
+///     vec4 object_to_world[3];
+///     vec4 world_to_object[3];
+///     uint node_offset;
+///     uint node_count;
+///     uint shape_offset;
+///     uint blas_index;
+/// };
+/// ```
+///
+/// The transforms are stored as the first three rows of the row major matrix. The instance is
+/// 112 bytes large and aligned to 16 bytes. With the `bytemuck` feature, `GpuInstance`
+/// implements `bytemuck::Pod` and `bytemuck::Zeroable`.
+///
+/// [`Instance`]: struct.Instance.html
+///
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "bytemuck", derive(bytemuck::Pod, bytemuck::Zeroable))]
+pub struct GpuInstance {
+    /// The transform from object space to world space, used to transform hits back.
+    pub object_to_world: [[f32; 4]; 3],
+
+    /// The transform from world space to object space, used to transform rays before the
+    /// bottom level [`BVH`] is traversed.
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    ///
+    pub world_to_object: [[f32; 4]; 3],
+
+    /// The [`BlasRange::node_offset`] of the instanced bottom level [`BVH`].
+    ///
+    /// [`BlasRange::node_offset`]: struct.BlasRange.html#structfield.node_offset
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    ///
+    pub node_offset: u32,
+
+    /// The [`BlasRange::node_count`] of the instanced bottom level [`BVH`].
+    ///
+    /// [`BlasRange::node_count`]: struct.BlasRange.html#structfield.node_count
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    ///
+    pub node_count: u32,
+
+    /// The [`BlasRange::shape_offset`] of the instanced bottom level [`BVH`].
+    ///
+    /// [`BlasRange::shape_offset`]: struct.BlasRange.html#structfield.shape_offset
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    ///
+    pub shape_offset: u32,
+
+    /// The index of the instanced bottom level [`BVH`].
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    ///
+    pub blas_index: u32,
+}
+
+impl GpuInstance {
+    /// Creates a new GPU instance of the bottom level [`BVH`] at `range`. The transforms are
+    /// converted to `f32`.
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    ///
+    #[allow(clippy::unnecessary_cast)]
+    pub fn new(instance: &Instance, range: &BlasRange) -> GpuInstance {
+        let rows = |matrix: Mat4| {
+            let mut rows = [[0.0; 4]; 3];
+            for (i, row) in rows.iter_mut().enumerate() {
+                let source = matrix.row(i);
+                *row = [
+                    source.x as f32,
+                    source.y as f32,
+                    source.z as f32,
+                    source.w as f32,
+                ];
+            }
+            rows
+        };
+        GpuInstance {
+            object_to_world: rows(instance.transform),
+            world_to_object: rows(instance.transform.inverse()),
+            node_offset: range.node_offset,
+            node_count: range.node_count,
+            shape_offset: range.shape_offset,
+            blas_index: instance.blas_index as u32,
+        }
+    }
+}
+
+/// The flattened bottom and top level [`BVH`]s of an instanced scene.
+///
+/// Both levels use [`GpuSkipNode`]s. The nodes of all bottom level [`BVH`]s are concatenated
+/// into `blas_nodes`, and their skip indices point into this buffer, so that a bottom level
+/// [`BVH`] is traversed from `node_offset` to `node_offset + node_count` of its
+/// [`GpuInstance`]. Shape indices stay local to their mesh and are offset with
+/// `shape_offset`. The leaves of `tlas_nodes` reference the `instances`.
+///
+/// [`BVH`]: ../bvh/struct.BVH.html
+/// [`GpuInstance`]: struct.GpuInstance.html
+/// [`GpuSkipNode`]: ../flat_bvh/struct.GpuSkipNode.html
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct TwoLevelBVH {
+    /// The flattened top level [`BVH`] over the world space bounds of the instances.
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    ///
+    pub tlas_nodes: Vec<GpuSkipNode>,
+
+    /// The concatenated flattened bottom level [`BVH`]s.
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    ///
+    pub blas_nodes: Vec<GpuSkipNode>,
+
+    /// The instances, in the order in which they were passed.
+    pub instances: Vec<GpuInstance>,
+
+    /// The location of every bottom level [`BVH`] in the packed buffers, in the order in
+    /// which they were passed. These offsets only depend on the bottom level [`BVH`]s, so
+    /// they stay valid when the instances change.
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    ///
+    pub blas_ranges: Vec<BlasRange>,
+}
+
+/// The world space bounds of an instance, used to build the top level [`BVH`].
+///
+/// [`BVH`]: ../bvh/struct.BVH.html
+///
+struct InstanceBounds {
+    aabb: AABB,
+    node_index: usize,
+}
+
+impl Bounded for InstanceBounds {
+    fn aabb(&self) -> AABB {
+        self.aabb
+    }
+}
+
+impl BHShape for InstanceBounds {
+    fn set_bh_node_index(&mut self, index: usize) {
+        self.node_index = index;
+    }
+
+    fn bh_node_index(&self) -> usize {
+        self.node_index
+    }
+}
+
+impl TwoLevelBVH {
+    /// Flattens the bottom level [`BVH`]s in `blases`, each with the shapes of its mesh, and
+    /// builds and flattens a top level [`BVH`] over the `instances`.
+    ///
+    /// # Panics
+    /// Panics if an [`Instance`] references a bottom level [`BVH`] which is not in `blases`.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::two_level::{Instance, TwoLevelBVH};
+    /// use bvh::{Mat4, Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..4)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let mesh = BVH::build(&mut cubes);
+    ///
+    /// let instances: Vec<Instance> = (0..3)
+    ///     .map(|y| Instance {
+    ///         blas_index: 0,
+    ///         transform: Mat4::from_translation(Vector3::new(0.0, y as f32 * 10.0, 0.0)),
+    ///     })
+    ///     .collect();
+    /// let scene = TwoLevelBVH::new(&[(&mesh, &cubes[..])], &instances);
+    /// assert_eq!(scene.blas_nodes.len(), 7);
+    /// assert_eq!(scene.instances.len(), 3);
+    /// assert_eq!(scene.tlas_nodes.len(), 5);
+    /// ```
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`Instance`]: struct.Instance.html
+    ///
+    pub fn new<Shape: BHShape>(blases: &[(&BVH, &[Shape])], instances: &[Instance]) -> TwoLevelBVH {
+        let mut blas_nodes = Vec::new();
+        let mut blas_ranges = Vec::with_capacity(blases.len());
+        let mut blas_aabbs = Vec::with_capacity(blases.len());
+        let mut shape_offset = 0;
+        for (bvh, shapes) in blases {
+            let node_offset = blas_nodes.len() as u32;
+            blas_nodes.extend(bvh.flatten_skip_gpu(shapes).into_iter().map(|mut node| {
+                node.skip_index += node_offset;
+                node
+            }));
+            blas_ranges.push(BlasRange {
+                node_offset,
+                node_count: blas_nodes.len() as u32 - node_offset,
+                shape_offset,
+                shape_count: shapes.len() as u32,
+            });
+            blas_aabbs.push(if bvh.nodes.is_empty() {
+                AABB::empty()
+            } else {
+                bvh.nodes[0].get_node_aabb(shapes)
+            });
+            shape_offset += shapes.len() as u32;
+        }
+
+        let mut bounds: Vec<InstanceBounds> = instances
+            .iter()
+            .map(|instance| InstanceBounds {
+                aabb: blas_aabbs[instance.blas_index].transformed(&instance.transform),
+                node_index: 0,
+            })
+            .collect();
+        let tlas_nodes = if bounds.is_empty() {
+            Vec::new()
+        } else {
+            BVH::build(&mut bounds).flatten_skip_gpu(&bounds)
+        };
+
+        TwoLevelBVH {
+            tlas_nodes,
+            blas_nodes,
+            instances: instances
+                .iter()
+                .map(|instance| GpuInstance::new(instance, &blas_ranges[instance.blas_index]))
+                .collect(),
+            blas_ranges,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::{Bounded, AABB};
+    use crate::bounding_hierarchy::IntersectionAABB;
+    use crate::bvh::BVH;
+    use crate::ray::Ray;
+    use crate::testbase::{create_n_cubes, create_ray, default_bounds, Triangle};
+    use crate::two_level::{GpuInstance, Instance, TwoLevelBVH};
+    use crate::{Mat4, Point3, Real, Vector3};
+
+    /// Traverses the bottom level nodes of `instance` like a GPU would and returns the
+    /// global indices of the hit shapes.
+    #[allow(clippy::unnecessary_cast)]
+    fn traverse_blas(scene: &TwoLevelBVH, instance: &GpuInstance, ray: &Ray) -> Vec<usize> {
+        let row = |rows: &[[f32; 4]; 3], point: [Real; 4]| {
+            let mut result = [0.0; 3];
+            for (value, row) in result.iter_mut().zip(rows) {
+                *value = (0..4).map(|i| row[i] as Real * point[i]).sum();
+            }
+            Vector3::new(result[0], result[1], result[2])
+        };
+        let origin = ray.origin;
+        let direction = ray.direction;
+        let local_ray = Ray::new(
+            row(
+                &instance.world_to_object,
+                [origin.x, origin.y, origin.z, 1.0],
+            ),
+            row(
+                &instance.world_to_object,
+                [direction.x, direction.y, direction.z, 0.0],
+            ),
+        );
+
+        let mut hits = Vec::new();
+        let end = (instance.node_offset + instance.node_count) as usize;
+        let mut index = instance.node_offset as usize;
+        while index < end {
+            let node = &scene.blas_nodes[index];
+            let aabb = AABB::with_bounds(
+                Point3::new(
+                    node.aabb_min[0] as Real,
+                    node.aabb_min[1] as Real,
+                    node.aabb_min[2] as Real,
+                ),
+                Point3::new(
+                    node.aabb_max[0] as Real,
+                    node.aabb_max[1] as Real,
+                    node.aabb_max[2] as Real,
+                ),
+            );
+            if !local_ray.intersects_aabb(&aabb) {
+                index = node.skip_index as usize;
+            } else if node.shape_index == u32::MAX {
+                index += 1;
+            } else {
+                hits.push((instance.shape_offset + node.shape_index) as usize);
+                index = node.skip_index as usize;
+            }
+        }
+        hits
+    }
+
+    #[test]
+    /// Builds a scene with two meshes and several instances and checks the offsets, the
+    /// transforms and the hits of the packed bottom level nodes.
+    fn test_two_level_offsets_and_traversal() {
+        let bounds = default_bounds();
+        let mut mesh_a = create_n_cubes(5, &bounds);
+        let mut mesh_b = create_n_cubes(8, &bounds);
+        let bvh_a = BVH::build(&mut mesh_a);
+        let bvh_b = BVH::build(&mut mesh_b);
+
+        let instances = [
+            Instance {
+                blas_index: 1,
+                transform: Mat4::IDENTITY,
+            },
+            Instance {
+                blas_index: 0,
+                transform: Mat4::from_translation(Vector3::new(10.0, 0.0, 0.0)),
+            },
+            Instance {
+                blas_index: 1,
+                transform: Mat4::from_scale(Vector3::splat(2.0)),
+            },
+        ];
+        let blases: [(&BVH, &[Triangle]); 2] = [(&bvh_a, &mesh_a), (&bvh_b, &mesh_b)];
+        let scene = TwoLevelBVH::new(&blases, &instances);
+
+        let range_a = scene.blas_ranges[0];
+        let range_b = scene.blas_ranges[1];
+        assert_eq!(range_a.node_offset, 0);
+        assert_eq!(range_a.node_count as usize, 2 * mesh_a.len() - 1);
+        assert_eq!(range_b.node_offset, range_a.node_count);
+        assert_eq!(range_b.shape_offset as usize, mesh_a.len());
+        assert_eq!(
+            scene.blas_nodes.len(),
+            (range_b.node_offset + range_b.node_count) as usize
+        );
+        assert_eq!(scene.tlas_nodes.len(), 2 * instances.len() - 1);
+        assert_eq!(scene.instances[1].node_offset, range_a.node_offset);
+        assert_eq!(scene.instances[2].shape_offset, range_b.shape_offset);
+        assert_eq!(scene.instances[1].object_to_world[0][3], 10.0);
+        assert_eq!(scene.instances[1].world_to_object[0][3], -10.0);
+        assert_eq!(scene.instances[2].world_to_object[1][1], 0.5);
+
+        // Every bottom level traversal must find the same shapes as the `BVH` of the mesh
+        // with a ray transformed into object space.
+        let all_shapes: Vec<&Triangle> = mesh_a.iter().chain(mesh_b.iter()).collect();
+        let mut seed = 0;
+        for _ in 0..100 {
+            let ray = create_ray(&mut seed, &bounds);
+            for (instance, gpu_instance) in instances.iter().zip(&scene.instances) {
+                let (bvh, shapes) = blases[instance.blas_index];
+                let (local_ray, _) = ray.inverse_transformed(&instance.transform);
+                let mut expected: Vec<*const Triangle> = bvh
+                    .traverse(&local_ray, shapes)
+                    .into_iter()
+                    .filter(|shape| local_ray.intersects_aabb(&shape.aabb()))
+                    .map(|shape| shape as *const _)
+                    .collect();
+                let mut actual: Vec<*const Triangle> = traverse_blas(&scene, gpu_instance, &ray)
+                    .into_iter()
+                    .map(|index| all_shapes[index] as *const _)
+                    .collect();
+                expected.sort_unstable();
+                actual.sort_unstable();
+                assert_eq!(expected, actual);
+            }
+        }
+    }
+}
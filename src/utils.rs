@@ -2,7 +2,7 @@
 
 use crate::aabb::AABB;
 use crate::bounding_hierarchy::BHShape;
-use crate::{Point3, Real, Vector3};
+use crate::{Point3, Real, Vector3, EPSILON};
 
 /// Concatenates the list of vectors into a single vector.
 /// Drains the elements from the source `vectors`.
@@ -15,6 +15,18 @@ pub fn concatenate_vectors<T: Sized>(vectors: &mut [Vec<T>]) -> Vec<T> {
     result
 }
 
+/// Runs `a` and `b` in parallel on the rayon thread pool, or one after the other without the
+/// `rayon` feature.
+pub fn join<A: FnOnce() + Send, B: FnOnce() + Send>(a: A, b: B) {
+    #[cfg(feature = "rayon")]
+    rayon::join(a, b);
+    #[cfg(not(feature = "rayon"))]
+    {
+        a();
+        b();
+    }
+}
+
 /// Defines a Bucket utility object. Used to store the properties of shape-partitions
 /// in the BVH build procedure using SAH.
 #[derive(Copy, Clone)]
@@ -74,9 +86,100 @@ pub fn nearest_point_on_line(start: &Point3, dir: &Vector3, len: Real, target: &
     *start + (*dir * d.clamp(0.0, len))
 }
 
+/// Helper function that given two line segments, finds the closest points on both of them
+pub fn nearest_points_on_lines(
+    start_a: &Point3,
+    dir_a: &Vector3,
+    len_a: Real,
+    start_b: &Point3,
+    dir_b: &Vector3,
+    len_b: Real,
+) -> (Point3, Point3) {
+    // Minimizes the distance between `start_a + dir_a * s` and `start_b + dir_b * t` for
+    // unit directions, then clamps `t` to the second segment and `s` again if needed.
+    let offset = *start_a - *start_b;
+    let cos = dir_a.dot(*dir_b);
+    let along_a = dir_a.dot(offset);
+    let along_b = dir_b.dot(offset);
+    let denominator = 1.0 - cos * cos;
+    let s = if denominator > EPSILON {
+        ((cos * along_b - along_a) / denominator).clamp(0.0, len_a)
+    } else {
+        // Parallel segments have many closest points, any of them will do.
+        0.0
+    };
+    let t = cos * s + along_b;
+    let (s, t) = if t < 0.0 {
+        ((-along_a).clamp(0.0, len_a), 0.0)
+    } else if t > len_b {
+        ((cos * len_b - along_a).clamp(0.0, len_a), len_b)
+    } else {
+        (s, t)
+    };
+    (*start_a + *dir_a * s, *start_b + *dir_b * t)
+}
+
+/// Converts `value` to the bits of an IEEE 754 half precision float. Unlike rounding to the
+/// nearest value, the result is rounded towards positive infinity if `round_up` is set and
+/// towards negative infinity otherwise, so that it never lies on the wrong side of `value`.
+/// Values beyond the range of half precision floats become infinite in the rounding
+/// direction, or the largest finite half precision float in the other direction.
+pub fn f16_bits_rounded(value: f64, round_up: bool) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 63) as u16) << 15;
+    let exponent = ((bits >> 52) & 0x7FF) as i64;
+    let mantissa = bits & ((1 << 52) - 1);
+    if exponent == 0x7FF {
+        // Infinity keeps its sign, NaN stays NaN.
+        return if mantissa == 0 { sign | 0x7C00 } else { 0x7E00 };
+    }
+
+    // Round the magnitude towards zero first and remember whether that was exact.
+    let unbiased = exponent - 1023;
+    let (magnitude, inexact) = if exponent == 0 {
+        // Subnormal doubles are far below the smallest half precision float.
+        (0, mantissa != 0)
+    } else if unbiased > 15 {
+        (0x7BFF, true)
+    } else if unbiased >= -14 {
+        let magnitude = (((unbiased + 15) as u16) << 10) | (mantissa >> 42) as u16;
+        (magnitude, mantissa & ((1 << 42) - 1) != 0)
+    } else {
+        // Subnormal half precision floats are multiples of 2^-24.
+        let significand = mantissa | (1 << 52);
+        let shift = 28 - unbiased;
+        if shift >= 64 {
+            (0, true)
+        } else {
+            let magnitude = (significand >> shift) as u16;
+            (magnitude, significand & ((1 << shift) - 1) != 0)
+        }
+    };
+
+    // Moving the magnitude away from zero by one step rounds up positive and down negative
+    // values. This also steps from the largest finite value to infinity.
+    let away_from_zero = inexact && (round_up == (sign == 0));
+    sign | (magnitude + away_from_zero as u16)
+}
+
+/// Converts the bits of an IEEE 754 half precision float to an `f32`, which is exact.
+pub fn f16_bits_to_f32(bits: u16) -> f32 {
+    let sign = ((bits >> 15) as u32) << 31;
+    let exponent = ((bits >> 10) & 0x1F) as u32;
+    let mantissa = (bits & 0x3FF) as u32;
+    match exponent {
+        0 => {
+            let magnitude = mantissa as f32 * (1.0 / (1 << 24) as f32);
+            f32::from_bits(sign | magnitude.to_bits())
+        }
+        0x1F => f32::from_bits(sign | 0x7F80_0000 | (mantissa << 13)),
+        _ => f32::from_bits(sign | ((exponent + 127 - 15) << 23) | (mantissa << 13)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::utils::concatenate_vectors;
+    use crate::utils::{concatenate_vectors, f16_bits_rounded, f16_bits_to_f32};
 
     #[test]
     /// Test if concatenating no `Vec`s yields an empty `Vec`.
@@ -98,4 +201,43 @@ mod tests {
         let expected_vecs: Vec<Vec<i32>> = vec![vec![], vec![], vec![], vec![], vec![]];
         assert_eq!(vectors, expected_vecs);
     }
+
+    #[test]
+    /// Tests that values are rounded to half precision in the requested direction.
+    fn test_f16_bits_rounded() {
+        for &value in &[0.0, 1.0, -2.5, 65504.0, 0.5f64.powi(24), -0.5f64.powi(14)] {
+            // Exactly representable values are not changed.
+            assert_eq!(
+                f16_bits_to_f32(f16_bits_rounded(value, false)) as f64,
+                value
+            );
+            assert_eq!(f16_bits_to_f32(f16_bits_rounded(value, true)) as f64, value);
+        }
+
+        let mut seed = 0u64;
+        for _ in 0..10000 {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let scale = [1e-7, 1e-3, 1.0, 1e3, 1e5][(seed >> 61) as usize % 5];
+            let value = ((seed >> 11) as f64 / (1u64 << 53) as f64 - 0.5) * scale;
+            let down = f16_bits_to_f32(f16_bits_rounded(value, false)) as f64;
+            let up = f16_bits_to_f32(f16_bits_rounded(value, true)) as f64;
+            assert!(
+                down <= value && value <= up,
+                "{} <= {} <= {}",
+                down,
+                value,
+                up
+            );
+            assert!(up - down <= (value.abs() / 512.0).max(0.5f64.powi(24)));
+        }
+
+        assert_eq!(f16_bits_rounded(1e6, true), 0x7C00);
+        assert_eq!(f16_bits_rounded(1e6, false), 0x7BFF);
+        assert_eq!(f16_bits_rounded(-1e6, false), 0xFC00);
+        assert_eq!(f16_bits_rounded(1e-30, false), 0x0000);
+        assert_eq!(f16_bits_rounded(1e-30, true), 0x0001);
+        assert!(f16_bits_to_f32(f16_bits_rounded(f64::NAN, true)).is_nan());
+    }
 }
@@ -0,0 +1,190 @@
+//! A small [wasm-bindgen] wrapper, so that web based viewers can raycast triangle scenes
+//! with this crate. Build a `cdylib` crate which depends on this crate with the `wasm`
+//! feature, and re-exports this module with `pub use bvh::wasm::*;`. The scene is then
+//! available from JavaScript:
+//!
+//! ```js
+//! const scene = new Scene(new Float32Array(positions), new Uint32Array(indices));
+//! const hit = scene.raycast([0, 0, 10], [0, 0, -1], Infinity);
+//! if (hit) console.log(hit.distance, hit.triangle, hit.normal());
+//! ```
+//!
+//! Points and vectors are passed as arrays of three numbers, which are `Float64Array`s
+//! with the `bvh-f64` crate. The exported names are the same for both crates, so only one
+//! of them can enable this feature in a binary.
+//!
+//! [wasm-bindgen]: https://docs.rs/wasm-bindgen
+//!
+
+use wasm_bindgen::prelude::*;
+
+use crate::ray::Ray;
+use crate::tri_mesh::TriMesh;
+use crate::{Point3, Real, Vector3};
+
+/// Converts a JavaScript array of three numbers into a point.
+fn to_point3(values: &[Real], name: &str) -> Result<Point3, String> {
+    match *values {
+        [x, y, z] => Ok(Point3::new(x, y, z)),
+        _ => Err(format!("{} must have three components", name)),
+    }
+}
+
+/// The closest hit of a ray in a [`Scene`].
+///
+/// [`Scene`]: struct.Scene.html
+///
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy)]
+pub struct RayHit {
+    /// Distance from the ray origin to the hit.
+    pub distance: Real,
+    /// Index of the hit triangle.
+    pub triangle: usize,
+    /// Barycentric coordinate of the second vertex of the triangle.
+    pub u: Real,
+    /// Barycentric coordinate of the third vertex of the triangle.
+    pub v: Real,
+    /// Whether the back face of the triangle was hit.
+    pub back_face: bool,
+    norm: Vector3,
+}
+
+#[wasm_bindgen]
+impl RayHit {
+    /// Returns the normalized normal of the hit triangle as `[x, y, z]`.
+    pub fn normal(&self) -> Vec<Real> {
+        self.norm.to_array().to_vec()
+    }
+}
+
+/// A triangle mesh which can be raycasted from JavaScript.
+#[wasm_bindgen]
+pub struct Scene {
+    mesh: TriMesh,
+}
+
+#[wasm_bindgen]
+impl Scene {
+    /// Builds a scene from flat vertex `positions`, three per vertex, and flat triangle
+    /// `indices`, three per triangle.
+    #[wasm_bindgen(constructor)]
+    pub fn new(positions: &[Real], indices: &[u32]) -> Result<Scene, String> {
+        if !positions.len().is_multiple_of(3) || !indices.len().is_multiple_of(3) {
+            return Err("positions and indices must have three values per element".into());
+        }
+        let vertices: Vec<Point3> = positions.chunks_exact(3).map(Point3::from_slice).collect();
+        if indices
+            .iter()
+            .any(|&index| index as usize >= vertices.len())
+        {
+            return Err("a vertex index is out of range".into());
+        }
+        let indices = indices
+            .chunks_exact(3)
+            .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+            .collect();
+        Ok(Scene {
+            mesh: TriMesh::new(vertices, indices),
+        })
+    }
+
+    /// Builds a scene from the text of an OBJ file.
+    #[cfg(feature = "obj")]
+    #[wasm_bindgen(js_name = fromObj)]
+    pub fn from_obj(obj: &str) -> Result<Scene, String> {
+        let mesh = crate::loader::load_obj_from_reader(&mut obj.as_bytes())
+            .map_err(|error| error.to_string())?;
+        Ok(Scene { mesh })
+    }
+
+    /// Returns the number of triangles.
+    #[wasm_bindgen(getter)]
+    pub fn triangles(&self) -> usize {
+        self.mesh.len()
+    }
+
+    /// Returns the bounds of the scene as `[min_x, min_y, min_z, max_x, max_y, max_z]`.
+    pub fn bounds(&self) -> Vec<Real> {
+        let aabb = crate::aabb::Bounded::aabb(&self.mesh);
+        [aabb.min.to_array(), aabb.max.to_array()].concat()
+    }
+
+    /// Returns the closest hit of the ray from `origin` along `direction` which is at most
+    /// `max_distance` away, or `undefined` if nothing is hit.
+    pub fn raycast(
+        &self,
+        origin: &[Real],
+        direction: &[Real],
+        max_distance: Real,
+    ) -> Result<Option<RayHit>, String> {
+        let ray = Ray::new(
+            to_point3(origin, "origin")?,
+            to_point3(direction, "direction")?,
+        );
+        Ok(self
+            .mesh
+            .intersects_ray_face(&ray, 0.0, max_distance)
+            .map(|(triangle, hit)| RayHit {
+                distance: hit.distance,
+                triangle,
+                u: hit.u,
+                v: hit.v,
+                back_face: hit.back_face,
+                norm: hit.norm,
+            }))
+    }
+
+    /// Casts many rays at once, which avoids a call into WebAssembly per ray. `rays` holds
+    /// six values per ray, the origin followed by the direction. Returns the distance to the
+    /// closest hit of every ray, which is `Infinity` for rays which hit nothing within
+    /// `max_distance`.
+    #[wasm_bindgen(js_name = raycastMany)]
+    pub fn raycast_many(&self, rays: &[Real], max_distance: Real) -> Result<Vec<Real>, String> {
+        if !rays.len().is_multiple_of(6) {
+            return Err("rays must have six values per ray".into());
+        }
+        Ok(rays
+            .chunks_exact(6)
+            .map(|ray| {
+                let ray = Ray::new(Point3::from_slice(ray), Vector3::from_slice(&ray[3..]));
+                self.mesh
+                    .intersects_ray_face(&ray, 0.0, max_distance)
+                    .map_or(Real::INFINITY, |(_, hit)| hit.distance)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::wasm::Scene;
+    use crate::{Real, Vector3};
+
+    #[test]
+    /// Tests building and raycasting a scene with the flat arrays used by JavaScript.
+    fn test_wasm_scene() {
+        let positions = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0];
+        let scene = Scene::new(&positions, &[0, 1, 2, 0, 2, 3]).unwrap();
+        assert_eq!(scene.triangles(), 2);
+        assert_eq!(scene.bounds(), vec![0.0, 0.0, 0.0, 1.0, 1.0, 0.0]);
+
+        let hit = scene
+            .raycast(&[0.25, 0.75, 2.0], &[0.0, 0.0, -4.0], Real::INFINITY)
+            .unwrap()
+            .unwrap();
+        assert_eq!((hit.distance, hit.triangle), (2.0, 1));
+        assert_eq!(hit.normal(), Vector3::Z.to_array().to_vec());
+        let missed = scene.raycast(&[0.25, 0.75, 2.0], &[0.0, 0.0, -1.0], 1.0);
+        assert!(missed.unwrap().is_none());
+        assert!(scene.raycast(&[0.0, 0.0], &[0.0, 0.0, -1.0], 1.0).is_err());
+
+        let rays = [0.5, 0.1, 3.0, 0.0, 0.0, -1.0, 2.0, 2.0, 3.0, 0.0, 0.0, -1.0];
+        let distances = scene.raycast_many(&rays, Real::INFINITY).unwrap();
+        assert_eq!(distances, vec![3.0, Real::INFINITY]);
+        assert!(scene.raycast_many(&rays[1..], Real::INFINITY).is_err());
+
+        assert!(Scene::new(&positions, &[0, 1, 4]).is_err());
+        assert!(Scene::new(&positions[1..], &[0, 1, 2]).is_err());
+    }
+}
@@ -0,0 +1,776 @@
+//! This module exports methods to collapse a binary `BVH` into a wide `BVH` with 4 or 8
+//! children per node, and to traverse it with single rays or packets of rays.
+
+use crate::aabb::{Bounded, AABB, AABB4};
+use crate::bounding_hierarchy::{BHShape, BoundingHierarchy, IntersectionAABB};
+use crate::bvh::{BVHNode, BVH};
+use crate::ray::{unordered_slab, Ray};
+use crate::Real;
+
+/// A node of a [`WideBVH`] with up to `N` children. The bounds of the children are stored
+/// as structure of arrays, so that all of them can be tested at once with SIMD instructions
+/// or by the threads of a GPU.
+///
+/// [`WideBVH`]: struct.WideBVH.html
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WideNode<const N: usize> {
+    /// The minimum x coordinates of the children's [`AABB`]s.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub min_x: [Real; N],
+    /// The minimum y coordinates of the children's [`AABB`]s.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub min_y: [Real; N],
+    /// The minimum z coordinates of the children's [`AABB`]s.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub min_z: [Real; N],
+    /// The maximum x coordinates of the children's [`AABB`]s.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub max_x: [Real; N],
+    /// The maximum y coordinates of the children's [`AABB`]s.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub max_y: [Real; N],
+    /// The maximum z coordinates of the children's [`AABB`]s.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub max_z: [Real; N],
+    /// The children of the node. A child is either the index of another [`WideNode`], the
+    /// index of a shape with [`WideNode::LEAF_FLAG`] set, or [`WideNode::EMPTY`] for an
+    /// unused slot. Unused slots have empty bounds, which fail every intersection test.
+    ///
+    /// [`WideNode`]: struct.WideNode.html
+    /// [`WideNode::EMPTY`]: struct.WideNode.html#associatedconstant.EMPTY
+    /// [`WideNode::LEAF_FLAG`]: struct.WideNode.html#associatedconstant.LEAF_FLAG
+    ///
+    pub children: [u32; N],
+}
+
+impl<const N: usize> WideNode<N> {
+    /// Marks a child which refers to a shape instead of another node.
+    pub const LEAF_FLAG: u32 = 1 << 31;
+
+    /// Marks an unused child slot.
+    pub const EMPTY: u32 = u32::MAX;
+
+    /// Creates a node without children.
+    fn empty() -> WideNode<N> {
+        let empty = AABB::empty();
+        WideNode {
+            min_x: [empty.min.x; N],
+            min_y: [empty.min.y; N],
+            min_z: [empty.min.z; N],
+            max_x: [empty.max.x; N],
+            max_y: [empty.max.y; N],
+            max_z: [empty.max.z; N],
+            children: [Self::EMPTY; N],
+        }
+    }
+
+    /// Returns the [`AABB`] of the child in `slot`.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub fn child_aabb(&self, slot: usize) -> AABB {
+        let mut aabb = AABB::empty();
+        aabb.min.x = self.min_x[slot];
+        aabb.min.y = self.min_y[slot];
+        aabb.min.z = self.min_z[slot];
+        aabb.max.x = self.max_x[slot];
+        aabb.max.y = self.max_y[slot];
+        aabb.max.z = self.max_z[slot];
+        aabb
+    }
+
+    /// Stores `child` and its [`AABB`] in `slot`.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    fn set_child(&mut self, slot: usize, child: u32, aabb: &AABB) {
+        self.min_x[slot] = aabb.min.x;
+        self.min_y[slot] = aabb.min.y;
+        self.min_z[slot] = aabb.min.z;
+        self.max_x[slot] = aabb.max.x;
+        self.max_y[slot] = aabb.max.y;
+        self.max_z[slot] = aabb.max.z;
+        self.children[slot] = child;
+    }
+}
+
+impl WideNode<4> {
+    /// Returns the [`AABB`]s of the four children as an [`AABB4`], which can be tested
+    /// against a [`Ray`] at once with [`Ray::intersects_aabb4`].
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`AABB4`]: ../aabb/struct.AABB4.html
+    /// [`Ray`]: ../ray/struct.Ray.html
+    /// [`Ray::intersects_aabb4`]: ../ray/struct.Ray.html#method.intersects_aabb4
+    ///
+    pub fn child_aabbs(&self) -> AABB4 {
+        AABB4 {
+            min_x: self.min_x,
+            min_y: self.min_y,
+            min_z: self.min_z,
+            max_x: self.max_x,
+            max_y: self.max_y,
+            max_z: self.max_z,
+        }
+    }
+}
+
+/// A wide [`BVH`] whose nodes have up to `N` children. It is created by collapsing a binary
+/// [`BVH`] with [`BVH::flatten_wide`]. The root is the first node.
+///
+/// [`BVH`]: ../bvh/struct.BVH.html
+/// [`BVH::flatten_wide`]: ../bvh/struct.BVH.html#method.flatten_wide
+///
+#[derive(Debug, Clone)]
+pub struct WideBVH<const N: usize> {
+    /// The nodes of the wide [`BVH`].
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    ///
+    pub nodes: Vec<WideNode<N>>,
+}
+
+/// A [`WideBVH`] with 4 children per node.
+///
+/// [`WideBVH`]: struct.WideBVH.html
+///
+#[allow(clippy::upper_case_acronyms)]
+pub type BVH4 = WideBVH<4>;
+
+/// A [`WideBVH`] with 8 children per node.
+///
+/// [`WideBVH`]: struct.WideBVH.html
+///
+#[allow(clippy::upper_case_acronyms)]
+pub type BVH8 = WideBVH<8>;
+
+impl BVH {
+    /// Collapses the [`BVH`] into a [`WideBVH`] with up to `N` children per node. The
+    /// children of a wide node are gathered by repeatedly replacing the inner node with the
+    /// largest surface area by its two children, until `N` children are found.
+    ///
+    /// # Panics
+    /// Panics if `N` is smaller than 2.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::{BHShape, BoundingHierarchy};
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::wide_bvh::BVH4;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..16)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let bvh = BVH::build(&mut cubes);
+    /// let wide: BVH4 = bvh.flatten_wide(&cubes);
+    /// assert_eq!(wide.nodes.len(), 5);
+    ///
+    /// let ray = Ray::new(Point3::new(6.0, -10.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+    /// assert_eq!(wide.traverse(&ray, &cubes).len(), 1);
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`WideBVH`]: ../wide_bvh/struct.WideBVH.html
+    ///
+    pub fn flatten_wide<Shape: BHShape, const N: usize>(&self, shapes: &[Shape]) -> WideBVH<N> {
+        assert!(N >= 2, "A wide BVH needs at least two children per node.");
+        let mut wide = WideBVH { nodes: Vec::new() };
+        if self.nodes.is_empty() {
+            return wide;
+        }
+
+        let mut root = WideNode::empty();
+        if let BVHNode::Leaf { shape_index, .. } = self.nodes[0] {
+            let aabb = shapes[shape_index].aabb();
+            root.set_child(0, shape_index as u32 | WideNode::<N>::LEAF_FLAG, &aabb);
+            wide.nodes.push(root);
+            return wide;
+        }
+        wide.nodes.push(root);
+
+        // Pairs of binary node and the wide node which is created for it.
+        let mut stack = vec![(0, 0)];
+        while let Some((node_index, wide_index)) = stack.pop() {
+            let children = self.collect_wide_children(node_index, N);
+            for (slot, (child_index, aabb)) in children.into_iter().enumerate() {
+                let child = match self.nodes[child_index] {
+                    BVHNode::Leaf { shape_index, .. } => {
+                        shape_index as u32 | WideNode::<N>::LEAF_FLAG
+                    }
+                    BVHNode::Node { .. } => {
+                        let child_wide_index = wide.nodes.len();
+                        wide.nodes.push(WideNode::empty());
+                        stack.push((child_index, child_wide_index));
+                        child_wide_index as u32
+                    }
+                };
+                wide.nodes[wide_index].set_child(slot, child, &aabb);
+            }
+        }
+        wide
+    }
+
+    /// Returns up to `width` descendants of the inner node `node_index`, together with their
+    /// [`AABB`]s, which together cover all of its leaves.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    fn collect_wide_children(&self, node_index: usize, width: usize) -> Vec<(usize, AABB)> {
+        let node = &self.nodes[node_index];
+        let mut children = vec![
+            (node.child_l(), node.child_l_aabb()),
+            (node.child_r(), node.child_r_aabb()),
+        ];
+        while children.len() < width {
+            let largest = children
+                .iter()
+                .enumerate()
+                .filter(|(_, (index, _))| matches!(self.nodes[*index], BVHNode::Node { .. }))
+                .max_by(|(_, (_, a)), (_, (_, b))| {
+                    a.surface_area().partial_cmp(&b.surface_area()).unwrap()
+                })
+                .map(|(position, _)| position);
+            let position = match largest {
+                Some(position) => position,
+                None => break,
+            };
+            let (index, _) = children.swap_remove(position);
+            let inner = &self.nodes[index];
+            children.push((inner.child_l(), inner.child_l_aabb()));
+            children.push((inner.child_r(), inner.child_r_aabb()));
+        }
+        children
+    }
+}
+
+impl<const N: usize> BoundingHierarchy for WideBVH<N> {
+    /// A [`WideBVH`] is built from a regular [`BVH`] using the [`BVH::flatten_wide`] method.
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`BVH::flatten_wide`]: ../bvh/struct.BVH.html#method.flatten_wide
+    /// [`WideBVH`]: struct.WideBVH.html
+    ///
+    fn build<T: BHShape>(shapes: &mut [T]) -> WideBVH<N> {
+        let bvh = BVH::build(shapes);
+        bvh.flatten_wide(shapes)
+    }
+
+    /// Traverses a [`WideBVH`] structure iteratively.
+    ///
+    /// [`WideBVH`]: struct.WideBVH.html
+    ///
+    fn traverse<'a, T: Bounded>(
+        &'a self,
+        ray: &impl IntersectionAABB,
+        shapes: &'a [T],
+    ) -> Vec<&'a T> {
+        let mut hit_shapes = Vec::new();
+        if self.nodes.is_empty() {
+            return hit_shapes;
+        }
+
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            for slot in 0..N {
+                let child = node.children[slot];
+                if child == WideNode::<N>::EMPTY || !ray.intersects_aabb(&node.child_aabb(slot)) {
+                    continue;
+                }
+                if child & WideNode::<N>::LEAF_FLAG != 0 {
+                    hit_shapes.push(&shapes[(child & !WideNode::<N>::LEAF_FLAG) as usize]);
+                } else {
+                    stack.push(child as usize);
+                }
+            }
+        }
+        hit_shapes
+    }
+
+    /// Prints a textual representation of a [`WideBVH`].
+    ///
+    /// [`WideBVH`]: struct.WideBVH.html
+    ///
+    fn pretty_print(&self) {
+        for (i, node) in self.nodes.iter().enumerate() {
+            let children: Vec<String> = node
+                .children
+                .iter()
+                .filter(|&&child| child != WideNode::<N>::EMPTY)
+                .map(|&child| {
+                    if child & WideNode::<N>::LEAF_FLAG != 0 {
+                        format!("shape {}", child & !WideNode::<N>::LEAF_FLAG)
+                    } else {
+                        format!("node {}", child)
+                    }
+                })
+                .collect();
+            println!("{}\t{}", i, children.join(", "));
+        }
+    }
+}
+
+/// A packet of up to `R` rays in structure of arrays layout, which is traversed through a
+/// [`WideBVH`] at once with [`WideBVH::traverse_packet`]. Every child of a node is tested
+/// against all rays of the packet in a loop over the lanes, which the compiler turns into
+/// SIMD instructions for packets of 4 or 8 rays.
+///
+/// [`WideBVH`]: struct.WideBVH.html
+/// [`WideBVH::traverse_packet`]: struct.WideBVH.html#method.traverse_packet
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayPacket<const R: usize> {
+    origin_x: [Real; R],
+    origin_y: [Real; R],
+    origin_z: [Real; R],
+    inv_direction_x: [Real; R],
+    inv_direction_y: [Real; R],
+    inv_direction_z: [Real; R],
+    len: usize,
+}
+
+/// A [`RayPacket`] of 4 rays.
+///
+/// [`RayPacket`]: struct.RayPacket.html
+///
+pub type RayPacket4 = RayPacket<4>;
+
+/// A [`RayPacket`] of 8 rays.
+///
+/// [`RayPacket`]: struct.RayPacket.html
+///
+pub type RayPacket8 = RayPacket<8>;
+
+impl<const R: usize> RayPacket<R> {
+    /// Creates a packet of the given rays. If there are fewer than `R` rays, the remaining
+    /// lanes are unused.
+    ///
+    /// # Panics
+    /// Panics if there are more than `R` rays, or if `R` is larger than 32.
+    ///
+    pub fn new(rays: &[Ray]) -> RayPacket<R> {
+        assert!(R <= 32, "A ray packet holds at most 32 rays.");
+        assert!(rays.len() <= R, "Too many rays for the packet.");
+        let mut packet = RayPacket {
+            origin_x: [0.0; R],
+            origin_y: [0.0; R],
+            origin_z: [0.0; R],
+            inv_direction_x: [0.0; R],
+            inv_direction_y: [0.0; R],
+            inv_direction_z: [0.0; R],
+            len: rays.len(),
+        };
+        for (lane, ray) in rays.iter().enumerate() {
+            packet.origin_x[lane] = ray.origin.x;
+            packet.origin_y[lane] = ray.origin.y;
+            packet.origin_z[lane] = ray.origin.z;
+            packet.inv_direction_x[lane] = 1.0 / ray.direction.x;
+            packet.inv_direction_y[lane] = 1.0 / ray.direction.y;
+            packet.inv_direction_z[lane] = 1.0 / ray.direction.z;
+        }
+        packet
+    }
+
+    /// Returns the number of rays in the packet.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the packet holds no rays.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the mask of the lanes which hold a ray.
+    fn active_mask(&self) -> u32 {
+        if self.len == 32 {
+            u32::MAX
+        } else {
+            (1 << self.len) - 1
+        }
+    }
+
+    /// Tests the child in `slot` of `node` against all rays and returns the mask of the
+    /// lanes whose rays hit it. The test is branchless, so that the loop is vectorized.
+    fn intersect_child<const N: usize>(&self, node: &WideNode<N>, slot: usize) -> u32 {
+        let mut hits = 0;
+        for lane in 0..R {
+            let tx1 = (node.min_x[slot] - self.origin_x[lane]) * self.inv_direction_x[lane];
+            let tx2 = (node.max_x[slot] - self.origin_x[lane]) * self.inv_direction_x[lane];
+            let ty1 = (node.min_y[slot] - self.origin_y[lane]) * self.inv_direction_y[lane];
+            let ty2 = (node.max_y[slot] - self.origin_y[lane]) * self.inv_direction_y[lane];
+            let tz1 = (node.min_z[slot] - self.origin_z[lane]) * self.inv_direction_z[lane];
+            let tz2 = (node.max_z[slot] - self.origin_z[lane]) * self.inv_direction_z[lane];
+
+            let (tx_min, tx_max) = unordered_slab(tx1, tx2);
+            let (ty_min, ty_max) = unordered_slab(ty1, ty2);
+            let (tz_min, tz_max) = unordered_slab(tz1, tz2);
+            let t_min = tx_min.max(ty_min).max(tz_min);
+            let t_max = tx_max.min(ty_max).min(tz_max);
+            hits |= ((t_min <= t_max && t_max > 0.0) as u32) << lane;
+        }
+        hits
+    }
+}
+
+impl<const N: usize> WideBVH<N> {
+    /// Traverses the [`WideBVH`] with all rays of the `packet` at once. A node is visited
+    /// as long as one of the rays hits it, and its children are tested against all of
+    /// these rays together. Returns the candidate shapes of every ray of the packet, whose
+    /// [`AABB`]s are hit by the ray, in the order of the rays.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::{BHShape, BoundingHierarchy};
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::wide_bvh::{RayPacket4, BVH4};
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..16)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let wide = BVH4::build(&mut cubes);
+    ///
+    /// let rays: Vec<Ray> = (0..4)
+    ///     .map(|x| Ray::new(Point3::new(x as f32 * 4.0, -10.0, 0.0), Vector3::new(0.0, 1.0, 0.0)))
+    ///     .collect();
+    /// let hits = wide.traverse_packet(&RayPacket4::new(&rays), &cubes);
+    /// assert_eq!(hits.len(), 4);
+    /// for (x, hit) in hits.iter().enumerate() {
+    ///     assert_eq!(hit.len(), 1);
+    ///     assert_eq!(hit[0].pos.x, x as f32 * 4.0);
+    /// }
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`WideBVH`]: struct.WideBVH.html
+    ///
+    pub fn traverse_packet<'a, T: Bounded, const R: usize>(
+        &'a self,
+        packet: &RayPacket<R>,
+        shapes: &'a [T],
+    ) -> Vec<Vec<&'a T>> {
+        let mut hit_shapes = vec![Vec::new(); packet.len()];
+        if self.nodes.is_empty() || packet.is_empty() {
+            return hit_shapes;
+        }
+
+        // Pairs of node index and the mask of the rays which hit the node.
+        let mut stack = vec![(0, packet.active_mask())];
+        while let Some((index, mask)) = stack.pop() {
+            let node = &self.nodes[index];
+            for slot in 0..N {
+                let child = node.children[slot];
+                if child == WideNode::<N>::EMPTY {
+                    continue;
+                }
+                let hits = packet.intersect_child(node, slot) & mask;
+                if hits == 0 {
+                    continue;
+                }
+                if child & WideNode::<N>::LEAF_FLAG != 0 {
+                    let shape = &shapes[(child & !WideNode::<N>::LEAF_FLAG) as usize];
+                    for (lane, lane_hits) in hit_shapes.iter_mut().enumerate() {
+                        if hits & (1 << lane) != 0 {
+                            lane_hits.push(shape);
+                        }
+                    }
+                } else {
+                    stack.push((child as usize, hits));
+                }
+            }
+        }
+        hit_shapes
+    }
+}
+
+impl WideBVH<4> {
+    /// Traverses the [`WideBVH`] with a single `ray`, and tests the four children of every
+    /// node at once with [`Ray::intersects_aabb4`]. Returns the shapes whose [`AABB`]s are
+    /// hit within `[t_min, t_max]`.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::{BHShape, BoundingHierarchy};
+    /// use bvh::ray::Ray;
+    /// use bvh::wide_bvh::BVH4;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// # struct Cube { pos: Point3, node_index: usize }
+    /// # impl Bounded for Cube {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.pos - Vector3::splat(0.5), self.pos + Vector3::splat(0.5))
+    /// #     }
+    /// # }
+    /// # impl BHShape for Cube {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    /// let mut cubes: Vec<Cube> = (0..16)
+    ///     .map(|x| Cube { pos: Point3::new(x as f32 * 2.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect();
+    /// let wide = BVH4::build(&mut cubes);
+    ///
+    /// let ray = Ray::new(Point3::new(-10.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+    /// assert_eq!(wide.traverse_ray(&ray, 0.0, 15.0, &cubes).len(), 3);
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`Ray::intersects_aabb4`]: ../ray/struct.Ray.html#method.intersects_aabb4
+    /// [`WideBVH`]: struct.WideBVH.html
+    ///
+    pub fn traverse_ray<'a, T: Bounded>(
+        &'a self,
+        ray: &Ray,
+        t_min: Real,
+        t_max: Real,
+        shapes: &'a [T],
+    ) -> Vec<&'a T> {
+        let mut hit_shapes = Vec::new();
+        if self.nodes.is_empty() {
+            return hit_shapes;
+        }
+
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            let (mask, _) = ray.intersects_aabb4(&node.child_aabbs(), t_min, t_max);
+            for slot in 0..4 {
+                // Empty slots have empty bounds, so they are never hit.
+                if mask & (1 << slot) == 0 {
+                    continue;
+                }
+                let child = node.children[slot];
+                if child & WideNode::<4>::LEAF_FLAG != 0 {
+                    hit_shapes.push(&shapes[(child & !WideNode::<4>::LEAF_FLAG) as usize]);
+                } else {
+                    stack.push(child as usize);
+                }
+            }
+        }
+        hit_shapes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::Bounded;
+    use crate::bounding_hierarchy::{BoundingHierarchy, IntersectionAABB};
+    use crate::bvh::BVH;
+    use crate::ray::Ray;
+    use crate::testbase::{
+        build_some_bh, create_n_cubes, create_ray, default_bounds, query_some_bh, traverse_some_bh,
+        UnitBox,
+    };
+    use crate::wide_bvh::{RayPacket, RayPacket4, RayPacket8, WideNode, BVH4, BVH8};
+    use crate::{Point3, Real, Vector3};
+
+    #[test]
+    /// Tests whether the building procedure succeeds in not failing.
+    fn test_build_wide_bvh() {
+        build_some_bh::<BVH4>();
+        build_some_bh::<BVH8>();
+    }
+
+    #[test]
+    /// Runs some primitive tests for intersections of a ray with a fixed scene given
+    /// as a `BVH4` and a `BVH8`.
+    fn test_traverse_wide_bvh() {
+        traverse_some_bh::<BVH4>();
+        traverse_some_bh::<BVH8>();
+    }
+
+    #[test]
+    /// Runs the nearest hit and nearest neighbor queries on wide `BVH`s.
+    fn test_query_wide_bvh() {
+        query_some_bh::<BVH4>();
+        query_some_bh::<BVH8>();
+    }
+
+    #[test]
+    /// Checks that every shape is referenced exactly once and that wide traversal matches
+    /// the binary `BVH`.
+    fn test_flatten_wide_matches_bvh() {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(200, &bounds);
+        let bvh = BVH::build(&mut triangles);
+        let wide: BVH8 = bvh.flatten_wide(&triangles);
+
+        let mut referenced = vec![0; triangles.len()];
+        for node in wide.nodes.iter() {
+            for &child in node.children.iter() {
+                if child != WideNode::<8>::EMPTY && child & WideNode::<8>::LEAF_FLAG != 0 {
+                    referenced[(child & !WideNode::<8>::LEAF_FLAG) as usize] += 1;
+                }
+            }
+        }
+        assert!(referenced.iter().all(|&count| count == 1));
+        assert!(wide.nodes.len() < bvh.nodes.len() / 4);
+
+        let mut seed = 0;
+        for _ in 0..100 {
+            let ray = create_ray(&mut seed, &bounds);
+            let mut expected: Vec<usize> = bvh.traverse_indices(&ray);
+            let mut actual: Vec<usize> = wide
+                .traverse(&ray, &triangles)
+                .iter()
+                .map(|triangle| {
+                    triangles
+                        .iter()
+                        .position(|other| std::ptr::eq(other, *triangle))
+                        .unwrap()
+                })
+                .collect();
+            assert!(actual
+                .iter()
+                .all(|&i| ray.intersects_aabb(&triangles[i].aabb())));
+            expected.sort_unstable();
+            actual.sort_unstable();
+            assert_eq!(expected, actual);
+        }
+    }
+
+    #[test]
+    /// Compares the candidates of single rays tested against four children at once with
+    /// the traversal of the binary `BVH`.
+    fn test_traverse_ray_matches_bvh() {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(200, &bounds);
+        let bvh = BVH::build(&mut triangles);
+        let bvh4: BVH4 = bvh.flatten_wide(&triangles);
+
+        let mut seed = 0;
+        for _ in 0..100 {
+            let ray = create_ray(&mut seed, &bounds);
+            let mut expected = bvh.traverse_indices(&ray);
+            let mut actual: Vec<usize> = bvh4
+                .traverse_ray(&ray, 0.0, Real::INFINITY, &triangles)
+                .iter()
+                .map(|triangle| {
+                    triangles
+                        .iter()
+                        .position(|other| std::ptr::eq(other, *triangle))
+                        .unwrap()
+                })
+                .collect();
+            expected.sort_unstable();
+            actual.sort_unstable();
+            assert_eq!(expected, actual);
+        }
+    }
+
+    #[test]
+    /// Compares the candidates of packets of 4 and 8 rays, including a partial packet, with
+    /// the traversal of the binary `BVH`.
+    fn test_traverse_packet_matches_bvh() {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(200, &bounds);
+        let bvh = BVH::build(&mut triangles);
+        let bvh4: BVH4 = bvh.flatten_wide(&triangles);
+        let bvh8: BVH8 = bvh.flatten_wide(&triangles);
+
+        let mut seed = 0;
+        let rays: Vec<Ray> = (0..67).map(|_| create_ray(&mut seed, &bounds)).collect();
+        let index_of = |triangle: &&_| {
+            triangles
+                .iter()
+                .position(|other| std::ptr::eq(other, *triangle))
+                .unwrap()
+        };
+        let check = |rays: &[Ray], hits: Vec<Vec<&_>>| {
+            assert_eq!(hits.len(), rays.len());
+            for (ray, hits) in rays.iter().zip(hits) {
+                let mut expected = bvh.traverse_indices(ray);
+                let mut actual: Vec<usize> = hits.iter().map(index_of).collect();
+                expected.sort_unstable();
+                actual.sort_unstable();
+                assert_eq!(expected, actual);
+            }
+        };
+        for chunk in rays.chunks(4) {
+            check(
+                chunk,
+                bvh4.traverse_packet(&RayPacket4::new(chunk), &triangles),
+            );
+            check(
+                chunk,
+                bvh8.traverse_packet(&RayPacket4::new(chunk), &triangles),
+            );
+        }
+        for chunk in rays.chunks(8) {
+            check(
+                chunk,
+                bvh4.traverse_packet(&RayPacket8::new(chunk), &triangles),
+            );
+            check(
+                chunk,
+                bvh8.traverse_packet(&RayPacket8::new(chunk), &triangles),
+            );
+        }
+
+        let empty = RayPacket::<4>::new(&[]);
+        assert!(empty.is_empty());
+        assert!(bvh4.traverse_packet(&empty, &triangles).is_empty());
+    }
+
+    #[test]
+    /// Tests that rays of a packet which run along the faces of the boxes, with zero and
+    /// negative zero direction components, hit all of them.
+    fn test_traverse_packet_along_faces() {
+        let mut boxes: Vec<UnitBox> = (0..20)
+            .map(|i| UnitBox::new(i, Point3::new(i as Real * 2.0, 0.0, 0.0)))
+            .collect();
+        let bvh = BVH::build(&mut boxes);
+        let bvh4: BVH4 = bvh.flatten_wide(&boxes);
+
+        let rays = [
+            Ray::new(Point3::new(-5.0, 0.5, 0.0), Vector3::new(1.0, 0.0, 0.0)),
+            Ray::new(Point3::new(50.0, -0.5, 0.5), Vector3::new(-1.0, -0.0, -0.0)),
+        ];
+        for ray in &rays {
+            assert_eq!(bvh.traverse(ray, &boxes).len(), boxes.len());
+        }
+        for hits in bvh4.traverse_packet(&RayPacket4::new(&rays), &boxes) {
+            assert_eq!(hits.len(), boxes.len());
+        }
+    }
+}